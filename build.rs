@@ -1,4 +1,6 @@
-use std::path::PathBuf;
+use std::{fs, path::PathBuf};
+
+use syscalls::ramdisk::{BootParamsFooter, RamdiskTrailer};
 
 fn main() {
     // set by cargo, build scripts should use this directory for output files
@@ -11,18 +13,31 @@ fn main() {
     let init = PathBuf::from("target/x86_64-mti_fun_os-init/debug/init");
 
     println!("cargo:rerun-if-changed={}", init.display());
+    println!("cargo:rerun-if-env-changed=BOOT_PARAMS");
+
+    // Append boot parameters, read by init (eg: `BOOT_PARAMS="profile=test"`)
+    let ramdisk = out_dir.join("ramdisk");
+    let mut data = fs::read(&init).unwrap();
+    let cmdline = std::env::var("BOOT_PARAMS").unwrap_or_default();
+    data.extend_from_slice(cmdline.as_bytes());
+    data.extend_from_slice(&BootParamsFooter::to_bytes(cmdline.as_bytes()));
+
+    // Append integrity trailer, checked by the kernel at boot
+    let trailer = RamdiskTrailer::new(&data);
+    data.extend_from_slice(&trailer.to_bytes());
+    fs::write(&ramdisk, data).unwrap();
 
     // create an UEFI disk image (optional)
     let uefi_path = out_dir.join("uefi.img");
     bootloader::UefiBoot::new(&kernel)
-        .set_ramdisk(&init)
+        .set_ramdisk(&ramdisk)
         .create_disk_image(&uefi_path)
         .unwrap();
 
     // create a BIOS disk image
     let bios_path = out_dir.join("bios.img");
     bootloader::BiosBoot::new(&kernel)
-        .set_ramdisk(&init)
+        .set_ramdisk(&ramdisk)
         .create_disk_image(&bios_path)
         .unwrap();
 
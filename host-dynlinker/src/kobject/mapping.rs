@@ -1,6 +1,7 @@
 // same api than kobject
 
-use core::{fmt, ops::Range, ptr, slice};
+use core::{fmt, ops::Range, slice};
+use std::io;
 
 use bitflags::bitflags;
 
@@ -34,6 +35,12 @@ pub enum Error {
     ObjectNameDuplicate,
     ObjectClosed,
     ObjectNotReady,
+    Partial,
+    DeadlineExceeded,
+    QuotaExceeded,
+    AddressInUse,
+    AddressNotMapped,
+    AddressReserved,
 }
 
 impl fmt::Display for Error {
@@ -47,6 +54,12 @@ impl fmt::Display for Error {
             Error::ObjectNameDuplicate => write!(formatter, "ObjectNameDuplicate"),
             Error::ObjectClosed => write!(formatter, "ObjectClosed"),
             Error::ObjectNotReady => write!(formatter, "ObjectNotReady"),
+            Error::Partial => write!(formatter, "Partial"),
+            Error::DeadlineExceeded => write!(formatter, "DeadlineExceeded"),
+            Error::QuotaExceeded => write!(formatter, "QuotaExceeded"),
+            Error::AddressInUse => write!(formatter, "AddressInUse"),
+            Error::AddressNotMapped => write!(formatter, "AddressNotMapped"),
+            Error::AddressReserved => write!(formatter, "AddressReserved"),
         }
     }
 }
@@ -85,26 +98,41 @@ impl Process {
     }
 
     /// Reserve an area in the process VM, but no not back it with memory
+    ///
+    /// Like the kernel, reserving at a given address fails with `Error::AddressInUse` if the area is already used.
     pub fn map_reserve(&self, addr: Option<usize>, size: usize) -> Result<Mapping, Error> {
-        let caddr = addr.unwrap_or_default() as *mut _;
-
-        let mut flags = libc::MAP_PRIVATE | libc::MAP_ANONYMOUS;
-        if let Some(_) = addr {
-            flags |= libc::MAP_FIXED;
-        }
-
-        let caddr = unsafe { libc::mmap(caddr, size, libc::PROT_NONE, flags, -1, 0) };
-
-        if caddr == ptr::null_mut() {
-            return Err(Error::InvalidArgument);
-        }
+        let flags = match addr {
+            Some(_) => libc::MAP_FIXED_NOREPLACE,
+            None => 0,
+        };
 
-        let addr = caddr as usize;
+        let addr = mmap(addr, size, libc::PROT_NONE, flags)?;
 
         Ok(unsafe { Mapping::unleak(self, addr..(addr + size), Permissions::NONE) })
     }
 
+    /// Back a part of a reservation with memory
+    pub fn map_commit(
+        &self,
+        range: &Range<usize>,
+        perms: Permissions,
+        //mobj: &MemoryObject,
+        //offset: usize,
+    ) -> Result<Mapping, Error> {
+        // Note: on the host, the reservation is replaced by a fixed mapping
+        let addr = mmap(
+            Some(range.start),
+            range.len(),
+            cperms(perms),
+            libc::MAP_FIXED,
+        )?;
+
+        Ok(unsafe { Mapping::unleak(self, addr..(addr + range.len()), perms) })
+    }
+
     /// Map a memory object into the process VM
+    ///
+    /// Like the kernel, mapping at a given address fails with `Error::AddressInUse` if the area is already used.
     pub fn map_mem(
         &self,
         addr: Option<usize>,
@@ -113,21 +141,12 @@ impl Process {
         //mobj: &MemoryObject,
         //offset: usize,
     ) -> Result<Mapping, Error> {
-        let cperms = cperms(perms);
-        let caddr = addr.unwrap_or_default() as *mut _;
+        let flags = match addr {
+            Some(_) => libc::MAP_FIXED_NOREPLACE,
+            None => 0,
+        };
 
-        let mut flags = libc::MAP_PRIVATE | libc::MAP_ANONYMOUS;
-        if let Some(_) = addr {
-            flags |= libc::MAP_FIXED;
-        }
-
-        let caddr = unsafe { libc::mmap(caddr, size, cperms, flags, -1, 0) };
-
-        if caddr == ptr::null_mut() {
-            return Err(Error::InvalidArgument);
-        }
-
-        let addr = caddr as usize;
+        let addr = mmap(addr, size, cperms(perms), flags)?;
 
         Ok(unsafe { Mapping::unleak(self, addr..(addr + size), perms) })
     }
@@ -156,6 +175,29 @@ pub struct Mapping<'a> {
     perms: Permissions,
 }
 
+/// Map anonymous memory, `flags` selects how `addr` is used
+fn mmap(
+    addr: Option<usize>,
+    size: usize,
+    cperms: libc::c_int,
+    flags: libc::c_int,
+) -> Result<usize, Error> {
+    let caddr = addr.unwrap_or_default() as *mut _;
+    let flags = flags | libc::MAP_PRIVATE | libc::MAP_ANONYMOUS;
+
+    let caddr = unsafe { libc::mmap(caddr, size, cperms, flags, -1, 0) };
+
+    if caddr == libc::MAP_FAILED {
+        return Err(match io::Error::last_os_error().raw_os_error() {
+            Some(libc::EEXIST) => Error::AddressInUse,
+            Some(libc::ENOMEM) => Error::OutOfMemory,
+            _ => Error::InvalidArgument,
+        });
+    }
+
+    Ok(caddr as usize)
+}
+
 fn cperms(perms: Permissions) -> libc::c_int {
     let mut value = libc::PROT_NONE;
 
@@ -26,9 +26,10 @@ impl<'a> Segment<'a> {
             align_down(vm_rel_segment.start, PAGE_SIZE)..align_up(vm_rel_segment.end, PAGE_SIZE);
 
         // while copying data, always setup RW access
-        let mapping = process.map_mem(
-            Some(vm_rel_segment_aligned.start + addr_offset),
-            vm_rel_segment_aligned.len(),
+        // The segment is committed inside the reservation of the whole object
+        let mapping = process.map_commit(
+            &((vm_rel_segment_aligned.start + addr_offset)
+                ..(vm_rel_segment_aligned.end + addr_offset)),
             Permissions::READ | Permissions::WRITE,
         )?;
 
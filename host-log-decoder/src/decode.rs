@@ -0,0 +1,272 @@
+use std::{
+    borrow::Cow,
+    collections::HashMap,
+    io::{self, Write},
+};
+
+use syscalls::log_record::{self, level_name, Entry, RecordHeader};
+
+/// Format strings of the kernel log, by id
+#[derive(Debug, Default)]
+pub struct FormatTable {
+    formats: HashMap<u32, String>,
+}
+
+impl FormatTable {
+    /// Parse a table generated by `cargo xtask log-formats`, invalid lines are ignored
+    pub fn parse(table: &str) -> Self {
+        let formats = table
+            .lines()
+            .filter_map(|line| {
+                let (id, format) = line.split_once(' ')?;
+                Some((u32::from_str_radix(id, 16).ok()?, unescape(format)))
+            })
+            .collect();
+
+        Self { formats }
+    }
+
+    pub fn get(&self, id: u32) -> Option<&str> {
+        self.formats.get(&id).map(String::as_str)
+    }
+}
+
+/// Decoder of a log stream, remembers the targets defined in it
+pub struct Decoder<'a> {
+    formats: &'a FormatTable,
+    targets: HashMap<u32, String>,
+}
+
+impl<'a> Decoder<'a> {
+    pub fn new(formats: &'a FormatTable) -> Self {
+        Self {
+            formats,
+            targets: HashMap::new(),
+        }
+    }
+
+    /// Decode `data`, and print it as text in `out`
+    pub fn decode(&mut self, data: &[u8], out: &mut impl Write) -> io::Result<()> {
+        for entry in log_record::Decoder::new(data) {
+            match entry {
+                Entry::Text(text) => out.write_all(text)?,
+                Entry::Target { id, name } => {
+                    self.targets
+                        .insert(id, String::from_utf8_lossy(name).into_owned());
+                }
+                Entry::Record { header, message } => {
+                    self.write_record(&header, &String::from_utf8_lossy(message), out)?;
+                }
+                Entry::Formatted {
+                    header,
+                    format_id,
+                    args,
+                } => {
+                    let args: Vec<Cow<str>> = args.iter().map(String::from_utf8_lossy).collect();
+
+                    let message = match self.formats.get(format_id) {
+                        Some(format) => render(format, &args),
+                        None => format!("#{:08x}({})", format_id, args.join(", ")),
+                    };
+
+                    self.write_record(&header, &message, out)?;
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    fn write_record(
+        &self,
+        header: &RecordHeader,
+        message: &str,
+        out: &mut impl Write,
+    ) -> io::Result<()> {
+        let target = self
+            .targets
+            .get(&header.target_id)
+            .map_or_else(|| format!("#{:08x}", header.target_id), Clone::clone);
+
+        writeln!(
+            out,
+            "[{:>5}.{:03}] {} {} (pid={}, tid={}) - {}",
+            header.timestamp / 1_000_000_000,
+            header.timestamp % 1_000_000_000 / 1_000_000,
+            level_name(header.level),
+            target,
+            header.pid,
+            header.tid,
+            message
+        )
+    }
+}
+
+/// Replace the placeholders of a format string by the args, already formatted with their spec
+///
+/// Missing args are shown as `{?}`.
+fn render(format: &str, args: &[Cow<str>]) -> String {
+    let mut output = String::new();
+    let mut args = args.iter();
+    let mut chars = format.chars().peekable();
+
+    while let Some(char) = chars.next() {
+        match char {
+            '{' if chars.next_if_eq(&'{').is_some() => output.push('{'),
+            '}' if chars.next_if_eq(&'}').is_some() => output.push('}'),
+            '{' => {
+                // Skip the spec, it has been applied by the kernel
+                for char in chars.by_ref() {
+                    if char == '}' {
+                        break;
+                    }
+                }
+
+                output.push_str(args.next().map_or("{?}", |arg| arg));
+            }
+            char => output.push(char),
+        }
+    }
+
+    output
+}
+
+fn unescape(format: &str) -> String {
+    let mut output = String::new();
+    let mut chars = format.chars();
+
+    while let Some(char) = chars.next() {
+        if char != '\\' {
+            output.push(char);
+            continue;
+        }
+
+        match chars.next() {
+            Some('n') => output.push('\n'),
+            Some('r') => output.push('\r'),
+            Some('t') => output.push('\t'),
+            Some(char) => output.push(char),
+            None => output.push('\\'),
+        }
+    }
+
+    output
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TABLE: &str = "0000002a Syscall {:?} {:#x}\n\
+                         00000007 {{literal}} {}\\n\n\
+                         invalid line\n";
+
+    fn header() -> RecordHeader {
+        RecordHeader {
+            timestamp: 1_234_000_000,
+            pid: 3,
+            tid: 4,
+            level: 3,
+            // No magic byte in the encoded header, so that truncated records are kept whole as text
+            target_id: 0x10,
+        }
+    }
+
+    fn formatted(id: u32, args: &[&str]) -> Vec<u8> {
+        let mut data = Vec::new();
+        header().encode_formatted(id, args.len(), &mut |byte| data.push(byte));
+
+        for arg in args {
+            data.extend_from_slice(arg.as_bytes());
+            data.push(0);
+        }
+
+        data
+    }
+
+    fn decode(formats: &FormatTable, data: &[u8]) -> String {
+        let mut out = Vec::new();
+        Decoder::new(formats).decode(data, &mut out).unwrap();
+        String::from_utf8(out).unwrap()
+    }
+
+    #[test]
+    fn table() {
+        let formats = FormatTable::parse(TABLE);
+
+        assert_eq!(formats.get(0x2a), Some("Syscall {:?} {:#x}"));
+        assert_eq!(formats.get(7), Some("{{literal}} {}\n"));
+        assert_eq!(formats.get(1), None);
+    }
+
+    #[test]
+    fn render_args() {
+        let args = [Cow::from("Log"), Cow::from("0x10")];
+
+        assert_eq!(render("Syscall {:?} {:#x}", &args), "Syscall Log 0x10");
+        assert_eq!(render("{{}} {:>5}}}", &args[..1]), "{} Log}");
+        assert_eq!(render("{} {}", &args[..1]), "Log {?}");
+    }
+
+    #[test]
+    fn internable() {
+        assert!(log_record::is_internable("Syscall {:?} {:#x}", 2));
+        assert!(log_record::is_internable("{{}} {:>5}", 1));
+        assert!(!log_record::is_internable("{} {}", 1));
+        assert!(!log_record::is_internable("ret={result:?}", 0));
+        assert!(!log_record::is_internable("{0} {0}", 1));
+        assert!(!log_record::is_internable("{:>1$}", 2));
+    }
+
+    #[test]
+    fn formatted_record() {
+        let formats = FormatTable::parse(TABLE);
+
+        let mut data = Vec::new();
+        log_record::encode_target(header().target_id, "kernel::user", &mut |byte| {
+            data.push(byte)
+        });
+        data.extend(b"boot text\n");
+        data.extend(formatted(0x2a, &["Log", "0x10"]));
+
+        assert_eq!(
+            decode(&formats, &data),
+            "boot text\n[    1.234] INFO kernel::user (pid=3, tid=4) - Syscall Log 0x10\n"
+        );
+    }
+
+    #[test]
+    fn unknown_format() {
+        let data = formatted(0x99, &["a", "b"]);
+
+        assert_eq!(
+            decode(&FormatTable::default(), &data),
+            format!(
+                "[    1.234] INFO #{:08x} (pid=3, tid=4) - #00000099(a, b)\n",
+                header().target_id
+            )
+        );
+    }
+
+    #[test]
+    fn record() {
+        let mut data = Vec::new();
+        header().encode(&mut |byte| data.push(byte));
+        data.extend(b"text message\0");
+
+        assert!(decode(&FormatTable::default(), &data).ends_with(" - text message\n"));
+    }
+
+    #[test]
+    fn truncated_record() {
+        let data = formatted(0x2a, &["Log", "0x10"]);
+        let truncated = &data[..data.len() - 1];
+
+        // Not decodable: kept as text
+        let mut out = Vec::new();
+        Decoder::new(&FormatTable::default())
+            .decode(truncated, &mut out)
+            .unwrap();
+        assert_eq!(out, truncated);
+    }
+}
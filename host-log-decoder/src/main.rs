@@ -0,0 +1,53 @@
+//! Decode the serial log of a kernel built with the `binary-log` feature.
+//!
+//! Usage: `host-log-decoder [--formats log-formats] [serial.log]` (reads stdin if no file is given)
+//!
+//! Text is passed through, binary records are printed as text.
+//! Formatted records need the table of the format strings generated by `cargo xtask build`
+//! (`target/image/log-formats`, used by default if it exists), else their args are printed raw.
+
+mod decode;
+
+use std::{
+    env, fs,
+    io::{self, Read},
+    path::Path,
+};
+
+use decode::{Decoder, FormatTable};
+
+/// Table used if none is given
+const DEFAULT_FORMATS: &str = "target/image/log-formats";
+
+fn main() -> io::Result<()> {
+    let mut args = env::args().skip(1);
+    let mut formats_path = None;
+    let mut input_path = None;
+
+    while let Some(arg) = args.next() {
+        if arg == "--formats" {
+            formats_path = args.next();
+        } else {
+            input_path = Some(arg);
+        }
+    }
+
+    let formats = match formats_path {
+        Some(path) => FormatTable::parse(&fs::read_to_string(path)?),
+        None if Path::new(DEFAULT_FORMATS).exists() => {
+            FormatTable::parse(&fs::read_to_string(DEFAULT_FORMATS)?)
+        }
+        None => FormatTable::default(),
+    };
+
+    let data = match input_path {
+        Some(path) => fs::read(path)?,
+        None => {
+            let mut data = Vec::new();
+            io::stdin().read_to_end(&mut data)?;
+            data
+        }
+    };
+
+    Decoder::new(&formats).decode(&data, &mut io::stdout().lock())
+}
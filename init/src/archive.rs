@@ -22,7 +22,22 @@ macro_rules! include_elf_bytes {
 // TODO: make path less static
 pub static PROCESS_SERVER: &[u8] =
     include_elf_bytes!("../../target/x86_64-mti_fun_os/debug/process-server");
+// Signed by `cargo xtask sign`, before init is built
+pub static PROCESS_SERVER_SIGNATURE: &[u8] =
+    include_bytes!("../../target/image/signatures/process-server.sig");
 pub static LIBRUNTIME: &[u8] =
     include_elf_bytes!("../../target/x86_64-mti_fun_os/debug/libruntime.so");
+pub static LOG_SERVER: &[u8] =
+    include_elf_bytes!("../../target/x86_64-mti_fun_os/debug/log-server");
+pub static LOG_SERVER_SIGNATURE: &[u8] =
+    include_bytes!("../../target/image/signatures/log-server.sig");
+pub static MEMFS_SERVER: &[u8] =
+    include_elf_bytes!("../../target/x86_64-mti_fun_os/debug/memfs-server");
+pub static MEMFS_SERVER_SIGNATURE: &[u8] =
+    include_bytes!("../../target/image/signatures/memfs-server.sig");
 pub static VFS_SERVER: &[u8] =
     include_elf_bytes!("../../target/x86_64-mti_fun_os/debug/vfs-server");
+pub static VFS_SERVER_SIGNATURE: &[u8] =
+    include_bytes!("../../target/image/signatures/vfs-server.sig");
+pub static C_SMOKE: &[u8] = include_elf_bytes!("../../target/x86_64-mti_fun_os/debug/c-smoke");
+pub static C_SMOKE_SIGNATURE: &[u8] = include_bytes!("../../target/image/signatures/c-smoke.sig");
@@ -0,0 +1,57 @@
+// Boot parameters: command line packed in the ramdisk after the init binary (see `syscalls::ramdisk`)
+//
+// Format: space separated `key=value` pairs, the last value of a key wins.
+// Keys:
+// - `profile=minimal|full|test`: boot profile
+// - `signature=disabled|log|enforce`: policy of binary signatures verification (see `signature::SignaturePolicy`)
+
+use libruntime::{boot_profile::Profile, sync::OnceLock};
+use log::warn;
+use syscalls::ramdisk::BootParamsFooter;
+
+static CMDLINE: OnceLock<&'static str> = OnceLock::new();
+
+/// Read the boot parameters at the end of the ramdisk data, and get the init binary which precedes them
+pub fn init(data: &'static [u8]) -> &'static [u8] {
+    let Some((binary, cmdline)) = BootParamsFooter::split(data) else {
+        warn!("No boot parameters found, using defaults");
+        let _ = CMDLINE.set("");
+        return data;
+    };
+
+    let cmdline = core::str::from_utf8(cmdline).unwrap_or_else(|_| {
+        warn!("Boot parameters are not valid UTF-8, using defaults");
+        ""
+    });
+
+    let _ = CMDLINE.set(cmdline);
+    binary
+}
+
+/// Get the value of a parameter
+pub fn get(key: &str) -> Option<&'static str> {
+    let cmdline = CMDLINE.get().expect("Boot parameters not read");
+
+    cmdline
+        .split_ascii_whitespace()
+        .filter_map(|param| param.split_once('='))
+        .filter(|(name, _)| *name == key)
+        .map(|(_, value)| value)
+        .last()
+}
+
+/// Get the boot profile
+pub fn profile() -> Profile {
+    let Some(param) = get("profile") else {
+        return Profile::DEFAULT;
+    };
+
+    Profile::parse(param).unwrap_or_else(|| {
+        warn!(
+            "Unknown boot profile '{}', using '{}'",
+            param,
+            Profile::DEFAULT
+        );
+        Profile::DEFAULT
+    })
+}
@@ -1,6 +1,8 @@
 use core::{arch::asm, ops::Range, slice};
 
-use libruntime::kobject::{self, Error, KObject, Permissions, ThreadPriority, PAGE_SIZE};
+use libruntime::kobject::{
+    self, Error, IdleStats, KObject, Permissions, ThreadPriority, PAGE_SIZE,
+};
 
 use super::offsets;
 
@@ -19,7 +21,12 @@ pub fn create_idle_process() -> Result<(), Error> {
 
     idle_mapping.leak();
 
-    let entry_point = unsafe { core::mem::transmute(idle as unsafe extern "C" fn() -> _) };
+    let entry_point = unsafe {
+        core::mem::transmute::<unsafe extern "C" fn(usize) -> !, extern "C" fn(usize) -> !>(idle)
+    };
+
+    // The kernel chooses the idle method (hlt or mwait with a C-state hint) from the CPU features
+    let arg = kobject::Stats::idle()?.thread_arg();
 
     // Use raw API, no runtime management
     libsyscalls::thread::create(
@@ -29,7 +36,7 @@ pub fn create_idle_process() -> Result<(), Error> {
         ThreadPriority::Idle,
         entry_point, // same vaddr in idle process
         0,           // no stack
-        0,           // no argument
+        arg,         // idle method
         0,           // no TLS
     )?;
 
@@ -64,16 +71,31 @@ fn prepare_mobj() -> Result<(kobject::MemoryObject, Range<usize>), Error> {
 }
 
 // Will run as idle process
+//
+// arg: 0 to use `hlt`, else `IdleStats::MWAIT_ARG | hint` to use `mwait` with the hint
+// (interrupts are enabled, so they wake up `mwait` like `hlt`)
 #[naked]
 #[no_mangle]
 #[link_section = ".text_idle"]
-unsafe extern "C" fn idle() -> ! {
+unsafe extern "C" fn idle(_arg: usize) -> ! {
     asm!(
         "
+      bt rdi, {mwait_bit};
+      jc 3f;
   2:
       hlt;
       jmp 2b;
+  3:
+      lea rax, [rip + 2b];
+      xor ecx, ecx;
+      xor edx, edx;
+      monitor;
+      mov eax, edi;
+      xor ecx, ecx;
+      mwait;
+      jmp 3b;
   ",
+        mwait_bit = const IdleStats::MWAIT_ARG.trailing_zeros(),
         options(noreturn)
     );
 }
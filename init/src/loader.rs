@@ -1,4 +1,11 @@
-use core::{error::Error, fmt, mem::size_of};
+use core::{error::Error, fmt, mem::size_of, ops::Range};
+
+use alloc::{string::String, vec::Vec};
+use libruntime::{
+    kobject::{self, MeasurementLog, Process},
+    loader::{self as image_loader, Image, Library},
+    manifest::{self, ManifestEntry, ManifestError, SandboxFlags},
+};
 use log::debug;
 use xmas_elf::{
     header, program,
@@ -8,7 +15,84 @@ use xmas_elf::{
 };
 use zero::read;
 
-pub fn load(binary: &[u8]) -> Result<(), LoaderError> {
+use crate::signature::{self, SignatureError};
+
+/// Rights of the spawner, which bound the requirements of the loaded binary
+#[derive(Debug, Clone)]
+pub struct SpawnerRights<'a> {
+    pub sandbox: SandboxFlags,
+    /// Io-ports the spawner can grant
+    pub io_ports: Range<u16>,
+    /// Services running: the binary can only require those
+    pub services: &'a [&'a str],
+}
+
+impl<'a> SpawnerRights<'a> {
+    /// Rights of init, which can spawn anything: only the services must be running
+    pub const fn all(services: &'a [&'a str]) -> Self {
+        Self {
+            sandbox: SandboxFlags::ALL,
+            io_ports: 0..u16::MAX,
+            services,
+        }
+    }
+}
+
+/// Capabilities given to the binary, from its manifest
+#[derive(Debug, Clone)]
+pub struct Capabilities {
+    pub sandbox: SandboxFlags,
+    pub io_ports: Vec<Range<u16>>,
+}
+
+impl Capabilities {
+    /// Apply the capabilities to the process, before it starts
+    fn apply(&self, process: &Process) -> Result<(), kobject::Error> {
+        process.set_sandbox(self.sandbox)?;
+
+        for range in self.io_ports.iter() {
+            process.grant_io_ports(range.clone())?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Check the binary, then load it in a new process, configure it from its manifest and start it
+///
+/// The libraries are the ones the binary can depend on (eg: `libruntime.so`).
+pub fn spawn(
+    name: &str,
+    binary: &[u8],
+    signature: Option<&[u8]>,
+    rights: &SpawnerRights,
+    libraries: &[Library],
+) -> Result<Process, LoaderError> {
+    let capabilities = load(name, binary, signature, rights)?;
+
+    let image = Image::load(name, binary, libraries).map_err(LoaderError::Image)?;
+    capabilities
+        .apply(image.process())
+        .map_err(LoaderError::CapabilitiesFailed)?;
+
+    image.start().map_err(LoaderError::Image)
+}
+
+/// Check the binary: signature, format and manifest
+///
+/// Returns the capabilities the binary gets
+pub fn load(
+    name: &str,
+    binary: &[u8],
+    signature: Option<&[u8]>,
+    rights: &SpawnerRights,
+) -> Result<Capabilities, LoaderError> {
+    // With the `enforce` policy, unsigned binaries are never loaded
+    signature::check(binary, signature).map_err(LoaderError::BadSignature)?;
+
+    // Measure exactly what gets loaded, before parsing it
+    MeasurementLog::measure(name, binary).map_err(|_| LoaderError::MeasurementFailed)?;
+
     let elf_file = wrap_res(xmas_elf::ElfFile::new(binary))?;
 
     for program_header in elf_file.program_iter() {
@@ -40,9 +124,68 @@ pub fn load(binary: &[u8]) -> Result<(), LoaderError> {
         }
     }
 
+    let capabilities = check_manifest(&elf_file, rights)?;
+
     resolve_dependencies(&elf_file);
 
-    Ok(())
+    Ok(capabilities)
+}
+
+/// Check the binary manifest against the spawner's rights, and get the capabilities it asks
+fn check_manifest(elf_file: &ElfFile, rights: &SpawnerRights) -> Result<Capabilities, LoaderError> {
+    let mut capabilities = Capabilities {
+        sandbox: SandboxFlags::NONE,
+        io_ports: Vec::new(),
+    };
+
+    let section = {
+        if let Some(section) = elf_file.find_section_by_name(manifest::SECTION_NAME) {
+            section
+        } else {
+            // No manifest: no requirement
+            return Ok(capabilities);
+        }
+    };
+
+    for entry in manifest::parse(section.raw_data(elf_file)) {
+        match entry.map_err(LoaderError::BadManifest)? {
+            ManifestEntry::RequiredService(name) => {
+                debug!("MANIFEST: required service '{name}'");
+
+                if !rights.services.contains(&name) {
+                    return Err(LoaderError::ServiceNotRunning(String::from(name)));
+                }
+            }
+            ManifestEntry::IoPortRange(range) => {
+                debug!(
+                    "MANIFEST: io-port range 0x{:04X} -> 0x{:04X}",
+                    range.start, range.end
+                );
+
+                let allowed = range.start < range.end
+                    && rights.io_ports.start <= range.start
+                    && range.end <= rights.io_ports.end;
+                if !allowed {
+                    return Err(LoaderError::IoPortsDenied(range));
+                }
+
+                capabilities.io_ports.push(range);
+            }
+            ManifestEntry::Sandbox(flags) => {
+                debug!("MANIFEST: sandbox {:?}", flags);
+
+                if !rights.sandbox.contains(flags) {
+                    return Err(LoaderError::RequirementDenied(
+                        flags.difference(rights.sandbox),
+                    ));
+                }
+
+                capabilities.sandbox = capabilities.sandbox.union(flags);
+            }
+        }
+    }
+
+    Ok(capabilities)
 }
 
 fn resolve_dependencies(elf_file: &ElfFile) -> Result<bool, LoaderError> {
@@ -89,16 +232,24 @@ fn wrap_res<T>(res: Result<T, &'static str>) -> Result<T, LoaderError> {
     res.map_err(|str| LoaderError::ElfReaderError(str))
 }
 
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug)]
 pub enum LoaderError {
     ElfReaderError(&'static str),
     BadObjectType(&'static str),
     BadDynamicSection,
+    BadManifest(ManifestError),
+    RequirementDenied(SandboxFlags),
+    IoPortsDenied(Range<u16>),
+    ServiceNotRunning(String),
+    CapabilitiesFailed(kobject::Error),
+    BadSignature(SignatureError),
+    MeasurementFailed,
+    Image(image_loader::LoaderError),
 }
 
 impl fmt::Display for LoaderError {
     fn fmt(&self, formatter: &mut fmt::Formatter<'_>) -> fmt::Result {
-        match *self {
+        match self {
             LoaderError::ElfReaderError(str) => {
                 write!(formatter, "elf reader error: {}", str)
             }
@@ -108,6 +259,34 @@ impl fmt::Display for LoaderError {
             LoaderError::BadDynamicSection => {
                 write!(formatter, "bad dynamic section")
             }
+            LoaderError::BadManifest(err) => {
+                write!(formatter, "bad manifest: {}", err)
+            }
+            LoaderError::RequirementDenied(flags) => {
+                write!(formatter, "requirement denied: sandbox {:?}", flags)
+            }
+            LoaderError::IoPortsDenied(range) => {
+                write!(
+                    formatter,
+                    "requirement denied: io-ports 0x{:04X} -> 0x{:04X}",
+                    range.start, range.end
+                )
+            }
+            LoaderError::ServiceNotRunning(name) => {
+                write!(formatter, "required service not running: '{}'", name)
+            }
+            LoaderError::CapabilitiesFailed(err) => {
+                write!(formatter, "could not apply capabilities: {:?}", err)
+            }
+            LoaderError::BadSignature(err) => {
+                write!(formatter, "bad signature: {:?}", err)
+            }
+            LoaderError::MeasurementFailed => {
+                write!(formatter, "measurement failed")
+            }
+            LoaderError::Image(err) => {
+                write!(formatter, "could not load image: {}", err)
+            }
         }
     }
 }
@@ -7,13 +7,20 @@
 extern crate alloc;
 
 mod archive;
+mod boot_params;
 mod idle;
 mod loader;
 mod offsets;
+mod services;
+mod signature;
+mod tests;
 
 use core::{arch::asm, hint::unreachable_unchecked, ops::Range, slice};
 
 use alloc::sync::Arc;
+use libruntime::boot_profile::Profile;
+use libruntime::error::{ErrorChain, ResultExt};
+use libruntime::format::{Bytes, Nanos};
 use libruntime::kobject::{
     self, Exception, Permissions, ThreadContextRegister, ThreadEventType, ThreadListenerFilter,
     ThreadOptions, TlsAllocator, PAGE_SIZE,
@@ -42,7 +49,9 @@ pub unsafe extern "C" fn user_start() -> ! {
 extern "C" fn entry(binary_len: usize) -> ! {
     libruntime::init();
 
-    let binary = unsafe { slice::from_raw_parts(offsets::global().start as *const u8, binary_len) };
+    // The kernel passes the ramdisk data: the binary is followed by the boot parameters
+    let data = unsafe { slice::from_raw_parts(offsets::global().start as *const u8, binary_len) };
+    let binary = boot_params::init(data);
     libruntime::debug::init_memory_binary(binary);
 
     apply_memory_protections(binary_len);
@@ -63,12 +72,21 @@ fn main() {
     // listen_threads();
     // do_ipc();
     // kmem_stats();
+    // dump_devices();
+    // uptime();
+    // ready_latencies();
+    // dump_resources("display-server");
     // test_unwind();
 
-    debug!("flan");
-    loader::load(archive::PROCESS_SERVER).expect("Pan");
+    let profile = boot_params::profile();
+    services::serve_profile(profile);
+    services::start(profile);
 
-    libruntime::exit();
+    if profile == Profile::Test {
+        services::run_tests();
+    }
+
+    // Init keeps running to serve the boot profile
 }
 
 #[inline(never)]
@@ -110,16 +128,13 @@ fn apply_memory_protections(binary_len: usize) {
     setup_protection("unmapped", unmapped_range, Permissions::READ);
 
     fn setup_protection(name: &str, range: Range<usize>, perms: Permissions) {
-        // kernel has mapped one area with all permissions set
-        let initial_perms = Permissions::READ | Permissions::WRITE | Permissions::EXECUTE;
+        // kernel has mapped one area with all permissions set: restrict it
         let process = kobject::Process::current();
 
-        unsafe {
-            let mut mapping = kobject::Mapping::unleak(process, range.clone(), initial_perms);
-            let res = mapping.update_permissions(perms);
-            mapping.leak(); // be sure to not drop the mapping, even on error, else we we have troubes to show the panic
-            res.expect("Could not setup memory protection");
-        }
+        // Not owned by a `Mapping`: the area is never unmapped, even on error, else we we have troubes to show the panic
+        process
+            .protect(&range, perms)
+            .expect("Could not setup memory protection");
 
         debug!(
             "{}: 0x{:016X} -> 0x{:016X} (size=0x{:X})",
@@ -132,29 +147,40 @@ fn apply_memory_protections(binary_len: usize) {
 }
 
 fn dump_processes_threads() {
-    let pids = kobject::Process::list().expect("Could not list pids");
+    dump_processes().expect("Could not dump processes");
+    dump_threads().expect("Could not dump threads");
+}
+
+fn dump_processes() -> Result<(), ErrorChain<kobject::Error>> {
+    let pids = kobject::Process::list().context("list pids")?;
     info!("pids list = {:?}", pids);
 
     for &pid in pids.iter() {
-        let process = kobject::Process::open(pid).expect("Could not open pid");
+        let process = kobject::Process::open(pid).context_value("open process", pid)?;
         info!("  {:?}", process.info());
         info!(
             "  name={}",
-            process.name().expect("Could not get process name")
+            process.name().context_value("get process name", pid)?
         );
     }
 
-    let tids = kobject::Thread::list().expect("Could not list tids");
+    Ok(())
+}
+
+fn dump_threads() -> Result<(), ErrorChain<kobject::Error>> {
+    let tids = kobject::Thread::list().context("list tids")?;
     info!("tids list = {:?}", tids);
 
     for &tid in tids.iter() {
-        let thread = kobject::Thread::open(tid).expect("Could not open tid");
+        let thread = kobject::Thread::open(tid).context_value("open thread", tid)?;
         info!("  {:?}", thread.info());
         info!(
             "  name={}",
-            thread.name().expect("Could not get thread name")
+            thread.name().context_value("get thread name", tid)?
         );
     }
+
+    Ok(())
 }
 
 fn do_ipc() {
@@ -275,9 +301,10 @@ fn listen_threads() {
                         let page = kobject::MemoryObject::create(PAGE_SIZE)
                             .expect("Could not create page");
 
+                        // Note: fails if the address is already used (eg: access to a reservation)
                         let mapping = self_proc
                             .map_mem(
-                                Some(address),
+                                Some(address & !(PAGE_SIZE - 1)),
                                 PAGE_SIZE,
                                 Permissions::READ | Permissions::WRITE,
                                 &page,
@@ -304,32 +331,127 @@ fn listen_threads() {
 
 fn kmem_stats() {
     let stats = kobject::Memory::stats();
-    const MEGA: usize = 1 * 1024 * 1024;
+    let size = |value: usize| Bytes(value as u64);
     debug!("Kernel memory allocator stats:");
 
     debug!(
-        "phys: total={} ({}MB), free={} ({}MB)",
+        "phys: total={} ({}), free={} ({})",
         stats.phys.total,
-        stats.phys.total / MEGA,
+        size(stats.phys.total),
         stats.phys.free,
-        stats.phys.free / MEGA
+        size(stats.phys.free)
+    );
+    debug!(
+        "phys: zeroed={} ({}), zeroed hits={}, zeroed misses={}",
+        stats.phys.zeroed,
+        size(stats.phys.zeroed),
+        stats.phys.zeroed_hits,
+        stats.phys.zeroed_misses
     );
     debug!(
         "kvm: total={} ({:#X}), used={} ({:#X})",
         stats.kvm.total, stats.kvm.total, stats.kvm.used, stats.kvm.used
     );
     debug!(
-        "kalloc: slabs: user={} ({}MB), allocated={} ({}MB)",
+        "kalloc: slabs: user={} ({}), allocated={} ({})",
         stats.kalloc.slabs_user,
-        stats.kalloc.slabs_user / MEGA,
+        size(stats.kalloc.slabs_user),
         stats.kalloc.slabs_allocated,
-        stats.kalloc.slabs_allocated / MEGA
+        size(stats.kalloc.slabs_allocated)
     );
     debug!(
-        "kalloc: kvm: user={} ({}MB), allocated={} ({}MB)",
+        "kalloc: kvm: user={} ({}), allocated={} ({})",
         stats.kalloc.kvm_user,
-        stats.kalloc.kvm_user / MEGA,
+        size(stats.kalloc.kvm_user),
         stats.kalloc.kvm_allocated,
-        stats.kalloc.kvm_allocated / MEGA
+        size(stats.kalloc.kvm_allocated)
     );
 }
+
+fn dump_devices() {
+    let devices = kobject::Device::list().expect("Could not list devices");
+    info!("{} devices", devices.len());
+
+    for device in devices.iter() {
+        info!("  {:?}", device);
+    }
+}
+
+fn uptime() {
+    let info = kobject::Stats::system_info().expect("Could not get system info");
+
+    let load = info.load as f64 / kobject::SystemInfo::LOAD_SCALE as f64;
+
+    info!(
+        "up {:.0}, booted at {} UTC, {} processes, free memory {}, load average: {:.2}",
+        Nanos(info.uptime),
+        DateTime(info.boot_time / 1_000_000_000),
+        info.processes,
+        Bytes(info.memory_free as u64),
+        load
+    );
+}
+
+fn ready_latencies() {
+    let latencies = kobject::Stats::ready_latencies().expect("Could not get ready latencies");
+
+    debug!("Ready latencies (TSC ticks):");
+    for latency in latencies.iter() {
+        debug!(
+            "{:?}: count={}, mean={}, p50<={}, p99<={}",
+            latency.priority,
+            latency.count,
+            latency.mean(),
+            latency.percentile(50),
+            latency.percentile(99)
+        );
+    }
+}
+
+/// Display of a Unix timestamp (in seconds), as `YYYY-MM-DD hh:mm:ss`
+struct DateTime(u64);
+
+impl core::fmt::Display for DateTime {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        let days = self.0 / 86400;
+        let secs = self.0 % 86400;
+
+        // Civil from days, from http://howardhinnant.github.io/date_algorithms.html (years from March)
+        let days = days + 719468;
+        let era = days / 146097;
+        let day_of_era = days - era * 146097;
+        let year_of_era =
+            (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146096) / 365;
+        let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+        let month = (5 * day_of_year + 2) / 153;
+        let day = day_of_year - (153 * month + 2) / 5 + 1;
+        let month = if month < 10 { month + 3 } else { month - 9 };
+        let year = year_of_era + era * 400 + if month <= 2 { 1 } else { 0 };
+
+        write!(
+            f,
+            "{:04}-{:02}-{:02} {:02}:{:02}:{:02}",
+            year,
+            month,
+            day,
+            secs / 3600,
+            (secs / 60) % 60,
+            secs % 60
+        )
+    }
+}
+
+fn dump_resources(server_port: &str) {
+    let entries = libruntime::introspection::list_resources(server_port, 0)
+        .expect("Could not list server resources");
+    info!("{}: {} resources held", server_port, entries.len());
+
+    for entry in entries.iter() {
+        info!(
+            "  client pid={}: {} {}",
+            entry.client_pid,
+            entry.kind(),
+            entry.id
+        );
+    }
+}
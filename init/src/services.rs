@@ -0,0 +1,285 @@
+// Service manager: starts the services of the boot profile, and serves the profile to userland
+//
+// Once process-server is up, the programs and libraries embedded in init are registered in it,
+// so that processes can spawn them (eg: with `posix_spawn`).
+
+use core::mem;
+
+use alloc::{format, vec::Vec};
+
+use libruntime::{
+    boot_profile::{Profile, Reply, Request, RequestType, SERVER_PORT_NAME},
+    failure, fs,
+    kobject::{self, Error, Message, Port, PortReceiver, PortSender, ThreadOptions},
+    loader::Library,
+    log_server::LogServer,
+    manifest::SandboxFlags,
+    process_server::{self, ProcessServer, LIBRARY_DIR},
+    vfs::{self, MountOptions, Vfs},
+};
+use log::{error, info, warn};
+
+use crate::{archive, loader, tests};
+
+/// Service embedded in init, and the profiles which start it
+struct Service {
+    name: &'static str,
+    binary: &'static [u8],
+    signature: Option<&'static [u8]>,
+    profiles: &'static [Profile],
+}
+
+/// Services, in start order
+static SERVICES: &[Service] = &[
+    Service {
+        name: "process-server",
+        binary: archive::PROCESS_SERVER,
+        signature: Some(archive::PROCESS_SERVER_SIGNATURE),
+        profiles: &[Profile::Minimal, Profile::Full, Profile::Test],
+    },
+    // Processes started after it buffer their logs in a ring it drains
+    Service {
+        name: "log-server",
+        binary: archive::LOG_SERVER,
+        signature: Some(archive::LOG_SERVER_SIGNATURE),
+        profiles: &[Profile::Full, Profile::Test],
+    },
+    Service {
+        name: "memfs-server",
+        binary: archive::MEMFS_SERVER,
+        signature: Some(archive::MEMFS_SERVER_SIGNATURE),
+        profiles: &[Profile::Full, Profile::Test],
+    },
+    Service {
+        name: "vfs-server",
+        binary: archive::VFS_SERVER,
+        signature: Some(archive::VFS_SERVER_SIGNATURE),
+        profiles: &[Profile::Full, Profile::Test],
+    },
+];
+
+/// Program embedded in init, registered in process-server
+struct Program {
+    path: &'static str,
+    binary: &'static [u8],
+    signature: Option<&'static [u8]>,
+}
+
+static PROGRAMS: &[Program] = &[Program {
+    path: "/bin/c-smoke",
+    binary: archive::C_SMOKE,
+    signature: Some(archive::C_SMOKE_SIGNATURE),
+}];
+
+/// Shared libraries the services and programs can depend on
+static LIBRARIES: &[Library] = &[Library::new("libruntime.so", archive::LIBRUNTIME)];
+
+/// Start the services of the profile
+pub fn start(profile: Profile) {
+    info!("Boot profile: {}", profile);
+
+    // Services can only require the ones started before them
+    let mut running = Vec::new();
+
+    for service in SERVICES
+        .iter()
+        .filter(|service| service.profiles.contains(&profile))
+    {
+        if spawn(service, &running) {
+            running.push(service.name);
+        }
+    }
+
+    if running.contains(&process_server::SERVER_PORT_NAME) {
+        register_programs(&running);
+    } else {
+        warn!("process-server not started: programs cannot be spawned");
+    }
+
+    if running.contains(&vfs::SERVER_PORT_NAME) && running.contains(&"memfs-server") {
+        mount_root();
+    }
+}
+
+/// Mount a memfs instance as the root of the vfs
+///
+/// vfs-server waits for memfs-server to come up (with a backoff).
+fn mount_root() {
+    let result = Vfs::wait_connect()
+        .and_then(|vfs| vfs.mount("/", fs::MEMFS_PORT_NAME, MountOptions::default()));
+
+    match result {
+        Ok(()) => info!("Root filesystem mounted"),
+        Err(err) => error!("Could not mount root filesystem: {:?}", err),
+    }
+}
+
+/// Run the tests of the `test` profile, then power off the machine
+pub fn run_tests() -> ! {
+    let failed = tests::run();
+
+    info!(
+        "Test profile: {} tests run, {} passed, {} failed, powering off",
+        tests::count(),
+        tests::count() - failed,
+        failed
+    );
+
+    power_off();
+}
+
+/// Power off the machine
+///
+/// The log server drains the logs of the processes first, with a bounded wait.
+/// The kernel only accepts it from a privileged thread: run it in a dedicated one.
+fn power_off() -> ! {
+    drain_logs();
+
+    let (receiver, sender) = Port::create(None).expect("Could not create power off port");
+
+    let run = move || {
+        let err = kobject::Power::power_off().expect_err("power off returned");
+
+        let status = err as u64;
+        let mut message = unsafe { Message::new(&status, &mut []) };
+        sender
+            .send(&mut message)
+            .expect("Could not send power off error");
+    };
+
+    let mut options = ThreadOptions::default();
+    options.name("power-off");
+    unsafe { options.privileged(true) };
+    kobject::Thread::start(run, options).expect("Could not start power off thread");
+
+    let message = receiver
+        .blocking_receive()
+        .expect("Could not receive power off error");
+    let status = *unsafe { message.data::<u64>() };
+    // Note: safe since it comes from the syscall, which only returns error codes
+    let err = unsafe { mem::transmute::<usize, Error>(status as usize) };
+    panic!("Could not power off: {:?}", err);
+}
+
+/// Let the log server drain the buffered records of the processes before the machine goes off
+fn drain_logs() {
+    match LogServer::connect().and_then(|server| server.shutdown()) {
+        Ok(drained) => info!("Logs drained ({} records)", drained),
+        // Not started in this profile
+        Err(Error::ObjectNotFound) => {}
+        Err(err) => warn!("Log server did not drain the logs: {:?}", err),
+    }
+}
+
+/// Start a service, returns true on success
+fn spawn(service: &Service, running: &[&str]) -> bool {
+    info!("Starting '{}'", service.name);
+
+    match loader::spawn(
+        service.name,
+        service.binary,
+        service.signature,
+        &loader::SpawnerRights::all(running),
+        LIBRARIES,
+    ) {
+        Ok(process) => {
+            info!("'{}' started as process {}", service.name, process.pid());
+            true
+        }
+        Err(err) => {
+            error!("Could not start '{}': {}", service.name, err);
+            false
+        }
+    }
+}
+
+/// Register the libraries and the programs in process-server
+///
+/// Programs are checked like the services: process-server trusts what init registers.
+///
+/// Note: process-server does not apply the manifests yet, programs must not need capabilities
+fn register_programs(running: &[&str]) {
+    let server = match ProcessServer::wait_connect() {
+        Ok(server) => server,
+        Err(err) => {
+            error!("Could not connect to process-server: {:?}", err);
+            return;
+        }
+    };
+
+    for library in LIBRARIES {
+        let path = format!("{}{}", LIBRARY_DIR, library.name());
+        if let Err(err) = server.register_binary(&path, library.binary()) {
+            error!("Could not register '{}': {:?}", path, err);
+        }
+    }
+
+    for program in PROGRAMS {
+        let no_rights = loader::SpawnerRights {
+            sandbox: SandboxFlags::NONE,
+            io_ports: 0..0,
+            services: running,
+        };
+
+        if let Err(err) = loader::load(program.path, program.binary, program.signature, &no_rights)
+        {
+            error!("Could not register '{}': {}", program.path, err);
+            continue;
+        }
+
+        if let Err(err) = server.register_binary(program.path, program.binary) {
+            error!("Could not register '{}': {:?}", program.path, err);
+        }
+    }
+}
+
+/// Serve the boot profile on its port, in a dedicated thread
+pub fn serve_profile(profile: Profile) {
+    let (receiver, sender) =
+        Port::create(Some(SERVER_PORT_NAME)).expect("Could not create boot profile port");
+
+    let serve = move || {
+        // Keep the port alive
+        let _sender = sender;
+
+        loop {
+            process_message(&receiver, profile);
+        }
+    };
+
+    let mut options = ThreadOptions::default();
+    options.name("boot-profile");
+    kobject::Thread::start(serve, options).expect("Could not start boot profile thread");
+}
+
+fn process_message(receiver: &PortReceiver, profile: Profile) {
+    let mut message = match receiver.blocking_receive() {
+        Ok(message) => message,
+        Err(err) => {
+            warn!("Could not receive request: {:?}", err);
+            return;
+        }
+    };
+
+    let reply_port = match PortSender::from_handle(message.take_handle(0)) {
+        Ok(port) => port,
+        Err(_) => {
+            warn!("Dropping request without reply port");
+            return;
+        }
+    };
+
+    // If processing panics, the client gets a failure report instead of the reply
+    let _request = failure::begin_request(&reply_port, message.correlation());
+
+    let request = *unsafe { message.data::<Request>() };
+    let result = RequestType::try_from(request.r#type).map(|r#type| match r#type {
+        RequestType::Get => profile,
+    });
+
+    let reply = Reply::new(result);
+    let mut reply_message = unsafe { Message::new(&reply, &mut []) };
+    if let Err(err) = reply_port.send(&mut reply_message) {
+        warn!("Could not send reply: {:?}", err);
+    }
+}
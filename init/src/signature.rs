@@ -0,0 +1,94 @@
+use ed25519_compact::{PublicKey, Signature};
+use log::warn;
+
+use crate::boot_params;
+
+/// Public key used to verify binaries, provisioned at image build with their detached ed25519 signatures
+/// (see `xtask/src/signing.rs`)
+pub const PUBLIC_KEY: [u8; PublicKey::BYTES] =
+    *include_bytes!("../../target/image/keys/signing.pub");
+
+/// Policy to apply on signature verification failure
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SignaturePolicy {
+    /// No verification at all
+    Disabled,
+    /// Verify, but only log on failure
+    LogOnly,
+    /// Verify, and refuse to load on failure
+    Enforce,
+}
+
+impl SignaturePolicy {
+    /// Policy used when the boot parameters do not give one
+    pub const DEFAULT: Self = Self::LogOnly;
+
+    /// Parse a policy from its name in the boot parameters
+    pub fn parse(name: &str) -> Option<Self> {
+        match name {
+            "disabled" => Some(Self::Disabled),
+            "log" => Some(Self::LogOnly),
+            "enforce" => Some(Self::Enforce),
+            _ => None,
+        }
+    }
+}
+
+/// Get the policy, from the boot parameters
+pub fn policy() -> SignaturePolicy {
+    let Some(param) = boot_params::get("signature") else {
+        return SignaturePolicy::DEFAULT;
+    };
+
+    SignaturePolicy::parse(param).unwrap_or_else(|| {
+        warn!(
+            "Unknown signature policy '{}', using {:?}",
+            param,
+            SignaturePolicy::DEFAULT
+        );
+        SignaturePolicy::DEFAULT
+    })
+}
+
+#[derive(Debug, Clone, Copy)]
+pub enum SignatureError {
+    Missing,
+    Malformed,
+    Invalid,
+}
+
+/// Verify the detached signature of the binary, and apply the policy of the boot parameters
+pub fn check(binary: &[u8], signature: Option<&[u8]>) -> Result<(), SignatureError> {
+    check_with(policy(), binary, signature)
+}
+
+/// Verify the detached signature of the binary, and apply the policy
+///
+/// With `SignaturePolicy::Enforce`, unsigned binaries are rejected (`SignatureError::Missing`).
+pub fn check_with(
+    policy: SignaturePolicy,
+    binary: &[u8],
+    signature: Option<&[u8]>,
+) -> Result<(), SignatureError> {
+    if policy == SignaturePolicy::Disabled {
+        return Ok(());
+    }
+
+    match verify(binary, signature) {
+        Ok(()) => Ok(()),
+        Err(err) if policy == SignaturePolicy::LogOnly => {
+            warn!("Binary signature verification failed: {:?}", err);
+            Ok(())
+        }
+        Err(err) => Err(err),
+    }
+}
+
+fn verify(binary: &[u8], signature: Option<&[u8]>) -> Result<(), SignatureError> {
+    let signature = signature.ok_or(SignatureError::Missing)?;
+    let key = PublicKey::new(PUBLIC_KEY);
+    let signature = Signature::from_slice(signature).map_err(|_| SignatureError::Malformed)?;
+
+    key.verify(binary, &signature)
+        .map_err(|_| SignatureError::Invalid)
+}
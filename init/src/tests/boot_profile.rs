@@ -0,0 +1,10 @@
+use libruntime::boot_profile::{self, Profile};
+
+use super::{ensure_eq, Check, TestResult};
+
+/// The profile served by init is the one selected by the boot parameters
+pub fn current() -> TestResult {
+    let profile = boot_profile::current().check("get boot profile")?;
+    ensure_eq!(profile, Profile::Test);
+    Ok(())
+}
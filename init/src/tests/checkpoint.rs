@@ -0,0 +1,215 @@
+use core::time::Duration;
+
+use alloc::{string::String, vec::Vec};
+use libruntime::{
+    debug::{Checkpoint, MappingCheckpoint, ThreadCheckpoint},
+    kobject::{
+        Error, HandleInfo, HandleType, MappingInfo, Permissions, Process, Thread, ThreadContext,
+        ThreadPriority, ThreadState, PAGE_SIZE,
+    },
+    retry,
+};
+
+use super::{ensure, ensure_eq, ensure_err, Check, TestResult};
+
+/// Code of the fixture: `loop: inc rax; jmp loop`
+const LOOP_CODE: [u8; 5] = [0x48, 0xFF, 0xC0, 0xEB, 0xFB];
+
+const CODE_ADDRESS: usize = 0x4000_0000;
+const STACK_ADDRESS: usize = 0x4001_0000;
+
+/// Handle values in the fixture: its own process, and a timer (which cannot be restored)
+const PROCESS_HANDLE: u64 = 1;
+const TIMER_HANDLE: u64 = 2;
+
+/// Time given to the fixture to run
+const RUN_TIME: Duration = Duration::from_millis(20);
+
+/// A restored process has the memory, the threads and the handles of its checkpoint, also through a file
+pub fn capture_restore() -> TestResult {
+    // The fixture is built from a checkpoint itself
+    let handles = Vec::from([
+        HandleInfo {
+            handle: PROCESS_HANDLE,
+            r#type: HandleType::Process,
+            object_id: 0,
+        },
+        HandleInfo {
+            handle: TIMER_HANDLE,
+            r#type: HandleType::Timer,
+            object_id: 0,
+        },
+    ]);
+    let first = fixture(0, handles).restore().check("restore fixture")?;
+    let result = check_restore(&first.process);
+    kill(&first.process);
+
+    ensure_eq!(first.lost_handles.len(), 1);
+    ensure_eq!(first.lost_handles[0].handle, TIMER_HANDLE);
+
+    result
+}
+
+fn check_restore(fixture: &Process) -> TestResult {
+    let counter = run(fixture)?;
+    ensure!(counter > 0, "fixture did not run");
+
+    let checkpoint = Checkpoint::capture(fixture).check("capture")?;
+
+    let captured = checkpoint
+        .threads
+        .first()
+        .and_then(|thread| thread.context.as_ref())
+        .map(|context| context.rax)
+        .ok_or("no thread context in the checkpoint")?;
+
+    let data = checkpoint.to_bytes();
+    let parsed = Checkpoint::parse(&data).check("parse")?;
+    ensure!(
+        parsed.to_bytes() == data,
+        "checkpoint changed through its file"
+    );
+    ensure_err!(
+        Checkpoint::parse(&data[..data.len() - 1]),
+        Error::InvalidArgument
+    );
+
+    let restored = parsed.restore().check("restore")?;
+    let result = check_restored(&restored.process, captured);
+    kill(&restored.process);
+
+    ensure!(
+        restored.lost_handles.is_empty(),
+        "handles lost: {:?}",
+        restored.lost_handles
+    );
+
+    result
+}
+
+fn check_restored(process: &Process, captured: usize) -> TestResult {
+    let mut code = [0u8; LOOP_CODE.len()];
+    process
+        .read_memory(CODE_ADDRESS, &mut code)
+        .check("read code")?;
+    ensure_eq!(code, LOOP_CODE);
+
+    let mapping = process
+        .mappings_in(&(CODE_ADDRESS..CODE_ADDRESS + PAGE_SIZE))
+        .check("mappings")?;
+    ensure_eq!(mapping.first().and_then(MappingInfo::name), Some("code"));
+
+    // Its own process handle is the restored process
+    let handles = process.handles().check("handles")?;
+    ensure!(
+        handles.iter().any(|info| info.handle == PROCESS_HANDLE
+            && info.r#type == HandleType::Process
+            && info.object_id == process.pid()),
+        "process handle not restored: {:?}",
+        handles
+    );
+
+    // The thread goes on from the captured context
+    let counter = run(process)?;
+    ensure!(
+        counter > captured,
+        "restored thread did not go on: {} <= {}",
+        counter,
+        captured
+    );
+
+    Ok(())
+}
+
+/// Let the suspended process run, suspend it again and get the counter of its thread
+fn run(process: &Process) -> Result<usize, String> {
+    process.resume().check("resume")?;
+    let _ = retry::sleep(RUN_TIME);
+    process.suspend().check("suspend")?;
+
+    let tid = *process
+        .threads()
+        .check("threads")?
+        .first()
+        .ok_or("no thread")?;
+    let context = Thread::open(tid)
+        .check("open thread")?
+        .context()
+        .check("context")?;
+
+    Ok(context.rax)
+}
+
+pub(super) fn kill(process: &Process) {
+    for &tid in process.threads().unwrap_or_default().iter() {
+        if let Ok(thread) = Thread::open(tid) {
+            let _ = unsafe { thread.kill() };
+        }
+    }
+}
+
+/// Process counting in `rax`, with a code page, a stack page and the given handles
+///
+/// Its thread has the tid `pid + 1`.
+pub(super) fn fixture(pid: u64, handles: Vec<HandleInfo>) -> Checkpoint {
+    let context = ThreadContext {
+        rax: 0,
+        rcx: 0,
+        rdx: 0,
+        rbx: 0,
+        rsi: 0,
+        rdi: 0,
+        rsp: STACK_ADDRESS + PAGE_SIZE,
+        rbp: 0,
+        r8: 0,
+        r9: 0,
+        r10: 0,
+        r11: 0,
+        r12: 0,
+        r13: 0,
+        r14: 0,
+        r15: 0,
+        instruction_pointer: CODE_ADDRESS,
+        // Interrupts enabled, and reserved bit 1
+        cpu_flags: 0x202,
+        tls: STACK_ADDRESS,
+    };
+
+    Checkpoint {
+        pid,
+        name: String::from("test-checkpoint"),
+        mappings: Vec::from([
+            MappingCheckpoint {
+                info: MappingInfo::new(
+                    CODE_ADDRESS,
+                    PAGE_SIZE,
+                    Permissions::READ | Permissions::EXECUTE,
+                    true,
+                    false,
+                    Some("code"),
+                ),
+                data: Some(Vec::from(LOOP_CODE)),
+            },
+            MappingCheckpoint {
+                info: MappingInfo::new(
+                    STACK_ADDRESS,
+                    PAGE_SIZE,
+                    Permissions::READ | Permissions::WRITE,
+                    true,
+                    false,
+                    Some("stack"),
+                ),
+                data: None,
+            },
+        ]),
+        threads: Vec::from([ThreadCheckpoint {
+            tid: pid + 1,
+            name: Some(String::from("main")),
+            priority: ThreadPriority::Normal,
+            privileged: false,
+            state: ThreadState::Ready,
+            context: Some(context),
+        }]),
+        handles,
+    }
+}
@@ -0,0 +1,62 @@
+use core::{arch::asm, time::Duration};
+
+use alloc::boxed::Box;
+use libruntime::{
+    debug::{capture_core, CoreDump},
+    kobject::{Error, Exception, Process, Thread, ThreadOptions, ThreadState, ThreadSupervisor},
+    retry::Backoff,
+};
+
+use super::{ensure, ensure_eq, Check, TestResult};
+
+/// Value looked for in the registers and the memory of the dump
+const MARKER: u64 = 0x6475_6D70_636F_7265;
+
+/// A dump has the registers of a thread stopped on a breakpoint, and the memory of the mappings
+pub fn capture_parse() -> TestResult {
+    let marker = Box::new(MARKER);
+    let marker_address = &*marker as *const u64 as usize;
+
+    let entry = || unsafe {
+        asm!("int3", in("rax") MARKER, options(nostack));
+    };
+
+    let mut options = ThreadOptions::default();
+    options.name("test-coredump");
+    let thread = Thread::start(entry, options).check("start thread")?;
+
+    let backoff = Backoff::new(Duration::from_millis(1), Duration::from_millis(50))
+        .timeout(Duration::from_secs(1));
+    backoff
+        .retry(|| match thread.info().state {
+            ThreadState::Error => Ok(()),
+            _ => Err(Error::ObjectNotReady),
+        })
+        .check("wait breakpoint")?;
+
+    let core = capture_core(
+        Process::current(),
+        Some((thread.tid(), Exception::Breakpoint)),
+    );
+    ThreadSupervisor::new(&thread).resume().check("resume")?;
+    let core = core.check("capture")?;
+
+    let dump = CoreDump::parse(&core).check("parse")?;
+    ensure_eq!(dump.pid(), Process::current().pid());
+    ensure_eq!(dump.exception().map(|(tid, _)| tid), Some(thread.tid()));
+
+    // The crashed thread comes first
+    let dumped = dump.threads().first().ok_or("no thread in the dump")?;
+    ensure_eq!(dumped.tid, thread.tid());
+    ensure_eq!(dumped.context.rax, MARKER as usize);
+    ensure!(dumped.signal != 0, "crashed thread has no signal");
+    ensure!(
+        !dump.backtrace(dumped).is_empty(),
+        "crashed thread has no backtrace"
+    );
+
+    let expected = MARKER.to_le_bytes();
+    ensure_eq!(dump.read(marker_address, 8), Some(&expected[..]));
+
+    Ok(())
+}
@@ -0,0 +1,118 @@
+use core::{arch::asm, hint::black_box, time::Duration};
+
+use libruntime::{
+    kobject::{
+        Error, Exception, Thread, ThreadContextRegister, ThreadOptions, ThreadState,
+        ThreadSupervisor, WatchpointKind,
+    },
+    retry::Backoff,
+};
+
+use super::{ensure, ensure_eq, ensure_err, Check, TestResult};
+
+/// DR6 bit B0: watchpoint 0 triggered
+const WATCHPOINT_0_STATUS: usize = 1 << 0;
+
+/// DR6 bit BS: single-step
+const SINGLE_STEP_STATUS: usize = 1 << 14;
+
+/// RFLAGS trap flag: single-step
+const TRAP_FLAG: usize = 1 << 8;
+
+/// RFLAGS interrupt flag
+const INTERRUPT_FLAG: usize = 1 << 9;
+
+#[inline(never)]
+fn breakpoint_target() {
+    black_box(());
+}
+
+/// A thread stops before an instruction with an execute breakpoint, and after one instruction when single-stepping
+pub fn execute_breakpoint_step() -> TestResult {
+    // Stop first on int3, so that the breakpoint is set before the call
+    let entry = || {
+        unsafe { asm!("int3", options(nostack)) };
+        breakpoint_target();
+    };
+
+    let mut options = ThreadOptions::default();
+    options.name("test-debug");
+    let thread = Thread::start(entry, options).check("start thread")?;
+    let supervisor = ThreadSupervisor::new(&thread);
+    let target = breakpoint_target as usize;
+
+    wait_state(&thread, ThreadState::Error).check("wait int3")?;
+    let exception = supervisor.error_info().check("error_info")?;
+    ensure!(
+        matches!(exception, Exception::Breakpoint),
+        "expected breakpoint, got {:?}",
+        exception
+    );
+
+    supervisor
+        .set_watchpoint(0, target, 1, WatchpointKind::Execute)
+        .check("set_watchpoint")?;
+    supervisor.resume().check("resume after int3")?;
+
+    wait_state(&thread, ThreadState::Error).check("wait breakpoint")?;
+    let status = match supervisor.error_info() {
+        Ok(Exception::Debug(status)) => status,
+        other => return Err(alloc::format!("expected debug exception, got {:?}", other)),
+    };
+    ensure!(
+        status & WATCHPOINT_0_STATUS != 0,
+        "watchpoint 0 not triggered (status=0x{:X})",
+        status
+    );
+    let context = supervisor.context().check("context")?;
+    ensure_eq!(context.instruction_pointer, target);
+
+    // Only the flags that userland can change itself, and single-step
+    let flags = context.cpu_flags;
+    ensure_err!(
+        supervisor.update_context(&[(ThreadContextRegister::CpuFlags, flags ^ INTERRUPT_FLAG)]),
+        Error::InvalidArgument
+    );
+
+    // Step over the breakpoint
+    supervisor.clear_watchpoint(0).check("clear_watchpoint")?;
+    supervisor
+        .update_context(&[(ThreadContextRegister::CpuFlags, flags | TRAP_FLAG)])
+        .check("set trap flag")?;
+    supervisor.resume().check("resume for step")?;
+
+    wait_state(&thread, ThreadState::Error).check("wait step")?;
+    let status = match supervisor.error_info() {
+        Ok(Exception::Debug(status)) => status,
+        other => return Err(alloc::format!("expected debug exception, got {:?}", other)),
+    };
+    ensure!(
+        status & SINGLE_STEP_STATUS != 0,
+        "single-step not reported (status=0x{:X})",
+        status
+    );
+    let context = supervisor.context().check("context after step")?;
+    ensure!(context.instruction_pointer != target, "thread did not step");
+
+    supervisor
+        .update_context(&[(ThreadContextRegister::CpuFlags, flags)])
+        .check("clear trap flag")?;
+    supervisor.resume().check("resume after step")?;
+
+    wait_state(&thread, ThreadState::Terminated).check("wait termination")?;
+
+    Ok(())
+}
+
+fn wait_state(thread: &Thread, state: ThreadState) -> Result<(), Error> {
+    let backoff = Backoff::new(Duration::from_millis(1), Duration::from_millis(50))
+        .timeout(Duration::from_secs(1));
+
+    backoff.retry(|| {
+        if thread.info().state == state {
+            Ok(())
+        } else {
+            Err(Error::ObjectNotReady)
+        }
+    })
+}
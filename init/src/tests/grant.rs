@@ -0,0 +1,91 @@
+use alloc::string::String;
+use libruntime::kobject::{Error, Grant, Mapping, MemoryObject, Permissions, Process, PAGE_SIZE};
+
+use super::{ensure, ensure_eq, ensure_err, Check, TestResult};
+
+/// Map a fresh read/write memory object
+fn map_memory(size: usize) -> Result<Mapping<'static>, String> {
+    let mobj = MemoryObject::create(size).check("create memory object")?;
+    Process::current()
+        .map_mem(None, size, Permissions::READ | Permissions::WRITE, &mobj, 0)
+        .check("map memory object")
+}
+
+/// The granted region is shared until the revoke, which replaces the mappings created from it by reservations
+pub fn revoke() -> TestResult {
+    let process = Process::current();
+    let owner = map_memory(PAGE_SIZE)?;
+    unsafe { owner.as_buffer_mut() }.unwrap()[42] = 0x5A;
+
+    let grant = Grant::create(owner.range(), Permissions::READ).check("create grant")?;
+    let granted = grant
+        .map(process, None, Permissions::READ)
+        .check("map grant")?;
+    ensure_eq!(unsafe { granted.as_buffer() }.unwrap()[42], 0x5A);
+
+    grant.revoke().check("revoke grant")?;
+
+    let mappings = process
+        .mappings_in(granted.range())
+        .check("list mappings")?;
+    ensure!(
+        mappings.len() == 1 && !mappings[0].has_memory_object,
+        "granted region not replaced by a reservation: {:?}",
+        mappings
+    );
+    ensure_err!(
+        process.protect(granted.range(), Permissions::READ),
+        Error::AddressReserved
+    );
+    ensure_err!(
+        grant.map(process, None, Permissions::READ),
+        Error::ObjectClosed
+    );
+
+    // The owner keeps its memory
+    ensure_eq!(unsafe { owner.as_buffer() }.unwrap()[42], 0x5A);
+
+    Ok(())
+}
+
+/// The mappings created from a grant cannot get more permissions than the grant
+pub fn protect_above_grant_permissions() -> TestResult {
+    let process = Process::current();
+    let owner = map_memory(PAGE_SIZE)?;
+
+    let grant = Grant::create(owner.range(), Permissions::READ).check("create grant")?;
+    ensure_err!(
+        grant.map(process, None, Permissions::READ | Permissions::WRITE),
+        Error::MemoryAccessDenied
+    );
+
+    let granted = grant
+        .map(process, None, Permissions::READ)
+        .check("map grant")?;
+    ensure_err!(
+        process.protect(granted.range(), Permissions::READ | Permissions::WRITE),
+        Error::MemoryAccessDenied
+    );
+
+    grant.revoke().check("revoke grant")?;
+    Ok(())
+}
+
+/// A region mapped from a grant cannot be granted again: revoking the first grant would not reach the second one
+pub fn regrant() -> TestResult {
+    let process = Process::current();
+    let owner = map_memory(PAGE_SIZE)?;
+
+    let grant = Grant::create(owner.range(), Permissions::READ).check("create grant")?;
+    let granted = grant
+        .map(process, None, Permissions::READ)
+        .check("map grant")?;
+
+    ensure_err!(
+        Grant::create(granted.range(), Permissions::READ),
+        Error::NotSupported
+    );
+
+    grant.revoke().check("revoke grant")?;
+    Ok(())
+}
@@ -0,0 +1,92 @@
+use alloc::{format, string::String};
+use libruntime::{
+    kobject::{Mapping, MemoryObject, Permissions, Process},
+    log_server::{LogServer, Ring, MAX_MESSAGE_SIZE, RING_SIZE},
+};
+use log::Level;
+
+use super::{ensure, ensure_eq, Check, TestResult};
+
+/// Records come out of a ring in order, also once the positions wrapped around, and a full ring refuses records
+pub fn ring_wrap() -> TestResult {
+    let (object, mapping) = create_ring()?;
+    let ring = unsafe { Ring::from_address(mapping.address()) };
+    drop(object);
+
+    let mut buffer = [0u8; MAX_MESSAGE_SIZE];
+
+    // Several rounds, so that records cross the end of the ring
+    for round in 0..3 {
+        let mut written = 0;
+        while ring.push(round, Level::Info, &format!("record {}", written)) {
+            written += 1;
+        }
+        ensure!(written > 0, "no record fits in the ring");
+
+        for index in 0..written {
+            let record = ring.pop(&mut buffer).ok_or("missing record")?;
+            ensure_eq!(record.tid, round);
+            ensure_eq!(record.level, Level::Info);
+            let expected = format!("record {}", index);
+            ensure_eq!(record.message, expected.as_str());
+        }
+
+        ensure!(ring.pop(&mut buffer).is_none(), "ring not empty");
+
+        // Shift the positions, so that the next round does not start at the same offset
+        ensure!(
+            ring.push(round, Level::Warn, "shift"),
+            "empty ring refused a record"
+        );
+        ensure!(ring.pop(&mut buffer).is_some(), "shift record lost");
+    }
+
+    let too_large = [b'x'; MAX_MESSAGE_SIZE + 1];
+    ensure!(
+        !ring.push(0, Level::Info, core::str::from_utf8(&too_large).unwrap()),
+        "record larger than the maximum accepted"
+    );
+
+    Ok(())
+}
+
+/// The log server drains a registered ring on flush
+pub fn server_flush() -> TestResult {
+    let server = LogServer::connect().check("connect")?;
+
+    let (object, mapping) = create_ring()?;
+    let ring = unsafe { Ring::from_address(mapping.address()) };
+    server.register(object).check("register")?;
+
+    for index in 0..3 {
+        ensure!(
+            ring.push(0, Level::Debug, &format!("test-logging flush {}", index)),
+            "ring full"
+        );
+    }
+
+    ensure_eq!(server.flush().check("flush")?, 3);
+
+    let mut buffer = [0u8; MAX_MESSAGE_SIZE];
+    ensure!(ring.pop(&mut buffer).is_none(), "ring not drained");
+
+    // Nothing left
+    ensure_eq!(server.flush().check("second flush")?, 0);
+
+    Ok(())
+}
+
+fn create_ring() -> Result<(MemoryObject, Mapping<'static>), String> {
+    let object = MemoryObject::create(RING_SIZE).check("create ring")?;
+    let mapping = Process::current()
+        .map_mem(
+            None,
+            RING_SIZE,
+            Permissions::READ | Permissions::WRITE,
+            &object,
+            0,
+        )
+        .check("map ring")?;
+
+    Ok((object, mapping))
+}
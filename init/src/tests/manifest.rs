@@ -0,0 +1,62 @@
+use core::ops::Range;
+
+use libruntime::{
+    kobject::{Error, Process},
+    manifest::SandboxFlags,
+};
+
+use super::{ensure, ensure_eq, ensure_err, Check, TestResult};
+
+/// process-server asks `PROCESS_CREATE` in its manifest: the loader must have applied it
+pub fn sandbox_applied() -> TestResult {
+    let pids = Process::list().check("list processes")?;
+    let server = pids
+        .iter()
+        .filter_map(|&pid| Process::open(pid).ok())
+        .find(|process| process.name().is_ok_and(|name| name == "process-server"));
+
+    ensure!(server.is_some(), "process-server not found");
+    ensure_eq!(server.unwrap().info().sandbox, SandboxFlags::PROCESS_CREATE);
+
+    Ok(())
+}
+
+pub fn sandbox_bounds() -> TestResult {
+    let process = Process::create("manifest-test").check("create process")?;
+
+    ensure_eq!(process.info().sandbox, SandboxFlags::NONE);
+
+    process
+        .set_sandbox(SandboxFlags::DEBUG)
+        .check("set sandbox")?;
+    ensure_eq!(process.info().sandbox, SandboxFlags::DEBUG);
+
+    ensure_err!(
+        process.set_sandbox(SandboxFlags::from_bits(0x8000_0000)),
+        Error::InvalidArgument
+    );
+    // Nobody changes its own sandbox, not even init
+    ensure_err!(
+        Process::current().set_sandbox(SandboxFlags::NONE),
+        Error::InvalidArgument
+    );
+
+    Ok(())
+}
+
+pub fn grant_io_ports() -> TestResult {
+    let process = Process::create("manifest-test").check("create process")?;
+
+    process
+        .grant_io_ports(0x3F8..0x400)
+        .check("grant io-ports")?;
+
+    ensure_err!(process.grant_io_ports(0x400..0x400), Error::InvalidArgument);
+    let reversed = Range {
+        start: 0x400,
+        end: 0x3F8,
+    };
+    ensure_err!(process.grant_io_ports(reversed), Error::InvalidArgument);
+
+    Ok(())
+}
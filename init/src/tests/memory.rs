@@ -0,0 +1,184 @@
+use libruntime::kobject::{Error, Memory, MemoryObject, Permissions, Process, PAGE_SIZE};
+
+use super::{ensure, ensure_eq, ensure_err, Check, TestResult};
+
+/// Permissions can be lowered, then raised again up to the ones of the memory object handle
+pub fn protect_within_max_permissions() -> TestResult {
+    let process = Process::current();
+    let mobj = MemoryObject::create(PAGE_SIZE).check("create memory object")?;
+    let mapping = process
+        .map_mem(None, PAGE_SIZE, Permissions::READ, &mobj, 0)
+        .check("map memory object")?;
+
+    process
+        .protect(mapping.range(), Permissions::NONE)
+        .check("protect none")?;
+    process
+        .protect(mapping.range(), Permissions::READ | Permissions::WRITE)
+        .check("protect read/write")?;
+
+    Ok(())
+}
+
+/// Permissions cannot be raised above the ones of the memory object handle used to map it
+pub fn protect_above_max_permissions() -> TestResult {
+    let process = Process::current();
+    let mobj = MemoryObject::create(2 * PAGE_SIZE).check("create memory object")?;
+    let read_only = mobj.read_only().check("restrict memory object")?;
+    let mapping = process
+        .map_mem(None, 2 * PAGE_SIZE, Permissions::READ, &read_only, 0)
+        .check("map memory object")?;
+
+    ensure_err!(
+        process.protect(mapping.range(), Permissions::READ | Permissions::WRITE),
+        Error::MemoryAccessDenied
+    );
+
+    // Also on a part of the mapping, and after lowering the permissions
+    let first_page = mapping.address()..(mapping.address() + PAGE_SIZE);
+    process
+        .protect(&first_page, Permissions::NONE)
+        .check("protect none")?;
+    ensure_err!(
+        process.protect(&first_page, Permissions::READ | Permissions::EXECUTE),
+        Error::MemoryAccessDenied
+    );
+    process
+        .protect(mapping.range(), Permissions::READ)
+        .check("protect read")?;
+
+    Ok(())
+}
+
+/// Mapping at a fixed address never replaces what is already there
+pub fn map_fixed_in_use() -> TestResult {
+    let process = Process::current();
+    let mobj = MemoryObject::create(PAGE_SIZE).check("create memory object")?;
+    let mapping = process
+        .map_mem(
+            None,
+            PAGE_SIZE,
+            Permissions::READ | Permissions::WRITE,
+            &mobj,
+            0,
+        )
+        .check("map memory object")?;
+    unsafe { mapping.as_buffer_mut() }.unwrap()[0] = 0x5A;
+
+    let other = MemoryObject::create(PAGE_SIZE).check("create memory object")?;
+    ensure_err!(
+        process.map_mem(
+            Some(mapping.address()),
+            PAGE_SIZE,
+            Permissions::READ,
+            &other,
+            0
+        ),
+        Error::AddressInUse
+    );
+    ensure_err!(
+        process.map_reserve(Some(mapping.address()), PAGE_SIZE),
+        Error::AddressInUse
+    );
+
+    // The existing mapping is untouched
+    ensure_eq!(unsafe { mapping.as_buffer() }.unwrap()[0], 0x5A);
+
+    Ok(())
+}
+
+/// Operations on ranges report which part of the range is not usable
+pub fn range_errors() -> TestResult {
+    let process = Process::current();
+    let reservation = process.map_reserve(None, 2 * PAGE_SIZE).check("reserve")?;
+    let range = reservation.range().clone();
+    let first_page = range.start..(range.start + PAGE_SIZE);
+
+    let mobj = MemoryObject::create(PAGE_SIZE).check("create memory object")?;
+    let committed = process
+        .map_commit(&first_page, Permissions::READ, &mobj, 0)
+        .check("commit")?;
+
+    // Committing never replaces memory in use
+    ensure_err!(
+        process.map_commit(&range, Permissions::READ, &mobj, 0),
+        Error::InvalidArgument
+    );
+
+    // Half committed, half reserved
+    ensure_err!(
+        process.protect(&range, Permissions::READ),
+        Error::AddressReserved
+    );
+
+    committed.leak();
+    drop(reservation);
+
+    // Nothing mapped anymore
+    ensure_err!(
+        process.protect(&first_page, Permissions::READ),
+        Error::AddressNotMapped
+    );
+    ensure_err!(
+        process.name_mem(&range, Some("test")),
+        Error::AddressNotMapped
+    );
+
+    Ok(())
+}
+
+/// Memory objects with unspecified content may expose data of other processes: unprivileged threads cannot create them
+pub fn uninitialized_unprivileged() -> TestResult {
+    ensure_err!(
+        MemoryObject::create_uninitialized(PAGE_SIZE),
+        Error::NotSupported
+    );
+    Ok(())
+}
+
+/// Physical addresses are reserved to device drivers: they give access to the whole memory through DMA
+pub fn physical_address_unprivileged() -> TestResult {
+    let mobj = MemoryObject::create(PAGE_SIZE).check("create memory object")?;
+    ensure_err!(mobj.physical_address(0), Error::NotSupported);
+    Ok(())
+}
+
+/// Unmapping the middle of a mapping frees its frames once nothing else references the memory object,
+/// and the parts left on both sides keep their content
+pub fn partial_unmap() -> TestResult {
+    const PAGES: usize = 32;
+
+    let process = Process::current();
+    let mapping = {
+        let mobj = MemoryObject::create(PAGES * PAGE_SIZE).check("create memory object")?;
+        process
+            .map_mem(
+                None,
+                PAGES * PAGE_SIZE,
+                Permissions::READ | Permissions::WRITE,
+                &mobj,
+                0,
+            )
+            .check("map memory object")?
+        // The handle is closed here: only the mapping references the memory object
+    };
+
+    let buffer = unsafe { mapping.as_buffer_mut() }.unwrap();
+    for (index, page) in buffer.chunks_mut(PAGE_SIZE).enumerate() {
+        page[0] = index as u8;
+    }
+
+    let middle = (mapping.address() + 8 * PAGE_SIZE)..(mapping.address() + 24 * PAGE_SIZE);
+    let free = Memory::stats().phys.free;
+    process.unmap(&middle).check("unmap middle")?;
+    let released = Memory::stats().phys.free.saturating_sub(free) / PAGE_SIZE;
+
+    // The kernel may allocate some frames meanwhile (eg: heap growth)
+    ensure!(released >= 12, "{} frames released, expected 16", released);
+
+    for index in (0..8).chain(24..PAGES) {
+        ensure_eq!(buffer[index * PAGE_SIZE], index as u8);
+    }
+
+    Ok(())
+}
@@ -0,0 +1,276 @@
+// Tests run by the `test` boot profile
+//
+// The tests run inside init, and exercise the kernel and the servers through syscalls.
+// Programs registered by init in process-server can be spawned to test the userland runtimes (eg: minilibc).
+// A test returns the description of the first failed check: it must not panic, else the whole harness stops.
+
+mod boot_profile;
+mod checkpoint;
+mod coredump;
+mod debug;
+mod grant;
+mod logging;
+mod manifest;
+mod memory;
+mod server;
+mod signature;
+mod snapshot;
+mod spawn;
+mod vfs;
+mod wait_queue;
+
+use core::fmt::Debug;
+
+use alloc::{format, string::String};
+use log::{error, info};
+
+type TestResult = Result<(), String>;
+
+/// Fail the test on error, with a description of the failed operation
+trait Check<T> {
+    fn check(self, operation: &str) -> Result<T, String>;
+}
+
+impl<T, E: Debug> Check<T> for Result<T, E> {
+    fn check(self, operation: &str) -> Result<T, String> {
+        self.map_err(|err| format!("{} failed: {:?}", operation, err))
+    }
+}
+
+/// Fail the test with the message if the condition is false
+macro_rules! ensure {
+    ($cond:expr, $($arg:tt)+) => {
+        if !$cond {
+            return Err(alloc::format!($($arg)+));
+        }
+    };
+}
+
+/// Fail the test if the values are not equal
+macro_rules! ensure_eq {
+    ($left:expr, $right:expr) => {{
+        let (left, right) = (&$left, &$right);
+        $crate::tests::ensure!(
+            left == right,
+            "{} == {} failed: {:?} != {:?}",
+            stringify!($left),
+            stringify!($right),
+            left,
+            right
+        );
+    }};
+}
+
+/// Fail the test if the result is not an error matching the pattern
+macro_rules! ensure_err {
+    ($result:expr, $err:pat) => {{
+        let result = $result;
+        $crate::tests::ensure!(
+            matches!(result, Err($err)),
+            "{} failed: expected {}, got {:?}",
+            stringify!($result),
+            stringify!($err),
+            result
+        );
+    }};
+}
+
+use ensure;
+use ensure_eq;
+use ensure_err;
+
+struct Test {
+    name: &'static str,
+    run: fn() -> TestResult,
+}
+
+static TESTS: &[Test] = &[
+    Test {
+        name: "boot_profile::current",
+        run: boot_profile::current,
+    },
+    Test {
+        name: "checkpoint::capture_restore",
+        run: checkpoint::capture_restore,
+    },
+    Test {
+        name: "coredump::capture_parse",
+        run: coredump::capture_parse,
+    },
+    Test {
+        name: "snapshot::capture",
+        run: snapshot::capture,
+    },
+    Test {
+        name: "snapshot::restore",
+        run: snapshot::restore,
+    },
+    Test {
+        name: "debug::execute_breakpoint_step",
+        run: debug::execute_breakpoint_step,
+    },
+    Test {
+        name: "logging::ring_wrap",
+        run: logging::ring_wrap,
+    },
+    Test {
+        name: "logging::server_flush",
+        run: logging::server_flush,
+    },
+    Test {
+        name: "memory::protect_within_max_permissions",
+        run: memory::protect_within_max_permissions,
+    },
+    Test {
+        name: "memory::protect_above_max_permissions",
+        run: memory::protect_above_max_permissions,
+    },
+    Test {
+        name: "memory::map_fixed_in_use",
+        run: memory::map_fixed_in_use,
+    },
+    Test {
+        name: "memory::range_errors",
+        run: memory::range_errors,
+    },
+    Test {
+        name: "memory::uninitialized_unprivileged",
+        run: memory::uninitialized_unprivileged,
+    },
+    Test {
+        name: "memory::physical_address_unprivileged",
+        run: memory::physical_address_unprivileged,
+    },
+    Test {
+        name: "memory::partial_unmap",
+        run: memory::partial_unmap,
+    },
+    Test {
+        name: "grant::revoke",
+        run: grant::revoke,
+    },
+    Test {
+        name: "grant::protect_above_grant_permissions",
+        run: grant::protect_above_grant_permissions,
+    },
+    Test {
+        name: "grant::regrant",
+        run: grant::regrant,
+    },
+    Test {
+        name: "manifest::sandbox_applied",
+        run: manifest::sandbox_applied,
+    },
+    Test {
+        name: "manifest::sandbox_bounds",
+        run: manifest::sandbox_bounds,
+    },
+    Test {
+        name: "manifest::grant_io_ports",
+        run: manifest::grant_io_ports,
+    },
+    Test {
+        name: "server::debug_endpoint",
+        run: server::debug_endpoint,
+    },
+    Test {
+        name: "signature::enforce_rejects_unsigned",
+        run: signature::enforce_rejects_unsigned,
+    },
+    Test {
+        name: "signature::enforce_accepts_signed",
+        run: signature::enforce_accepts_signed,
+    },
+    Test {
+        name: "signature::enforce_rejects_tampered",
+        run: signature::enforce_rejects_tampered,
+    },
+    Test {
+        name: "spawn::missing_binary",
+        run: spawn::missing_binary,
+    },
+    Test {
+        name: "spawn::c_smoke",
+        run: spawn::c_smoke,
+    },
+    Test {
+        name: "vfs::read_write",
+        run: vfs::read_write,
+    },
+    Test {
+        name: "vfs::open_flags",
+        run: vfs::open_flags,
+    },
+    Test {
+        name: "vfs::directories",
+        run: vfs::directories,
+    },
+    Test {
+        name: "vfs::mounts",
+        run: vfs::mounts,
+    },
+    Test {
+        name: "vfs::copy_range",
+        run: vfs::copy_range,
+    },
+    Test {
+        name: "vfs::timestamps",
+        run: vfs::timestamps,
+    },
+    Test {
+        name: "vfs::read_ahead",
+        run: vfs::read_ahead,
+    },
+    Test {
+        name: "vfs::trash",
+        run: vfs::trash,
+    },
+    Test {
+        name: "vfs::batch",
+        run: vfs::batch,
+    },
+    Test {
+        name: "vfs::mount_missing_fs",
+        run: vfs::mount_missing_fs,
+    },
+    Test {
+        name: "vfs::reconnect",
+        run: vfs::reconnect,
+    },
+    Test {
+        name: "wait_queue::wake_empty_queue",
+        run: wait_queue::wake_empty_queue,
+    },
+    Test {
+        name: "wait_queue::requeued_waiter",
+        run: wait_queue::requeued_waiter,
+    },
+    Test {
+        name: "wait_queue::direct_handoff",
+        run: wait_queue::direct_handoff,
+    },
+];
+
+/// Run all the tests, and report their results
+///
+/// Returns the number of failed tests
+pub fn run() -> usize {
+    let mut failed = 0;
+
+    for test in TESTS {
+        match (test.run)() {
+            Ok(()) => info!("test {} ... ok", test.name),
+            Err(message) => {
+                error!("test {} ... FAILED: {}", test.name, message);
+                failed += 1;
+            }
+        }
+    }
+
+    failed
+}
+
+/// Number of tests
+pub fn count() -> usize {
+    TESTS.len()
+}
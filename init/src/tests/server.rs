@@ -0,0 +1,58 @@
+use alloc::string::String;
+use libruntime::{
+    kobject::{Message, Port, Process},
+    server::{self, ManagedServerBuilder},
+};
+
+use super::{ensure, ensure_eq, Check, TestResult};
+
+const SERVER_NAME: &str = "test-managed-server";
+
+/// The debug endpoint of a managed server answers while its request loop is idle or busy, with the recorded
+/// sessions and tasks
+pub fn debug_endpoint() -> TestResult {
+    let managed = ManagedServerBuilder::new(SERVER_NAME)
+        .build()
+        .check("build server")?;
+    let context = managed.context();
+    let pid = Process::current().pid();
+
+    let session = context.open_session(pid, String::from("test session"));
+    let task = context.begin_task(String::from("test task"));
+
+    let dump = server::dump(SERVER_NAME).check("dump idle")?;
+    ensure!(dump.threads >= 2, "server threads not dumped");
+    ensure_eq!(dump.tasks, 1);
+    ensure_eq!(dump.sessions, 1);
+
+    // The request in progress is a pending task
+    let server_port = Port::open(SERVER_NAME).check("open server port")?;
+    let (reply_receiver, reply_sender) = Port::create(None).check("create reply port")?;
+    let mut handles = [reply_sender.into_handle()];
+    let mut message = unsafe { Message::new(&42u64, &mut handles) };
+    server_port.send(&mut message).check("send request")?;
+
+    let mut busy = None;
+    managed
+        .process_one(&mut |request| {
+            busy = Some((request.sender_pid(), server::dump(SERVER_NAME)));
+
+            let mut reply = unsafe { Message::new(&0u64, &mut []) };
+            let _ = request.reply_port.send(&mut reply);
+        })
+        .check("process request")?;
+
+    let (sender_pid, dump) = busy.ok_or("request not processed")?;
+    ensure_eq!(sender_pid, pid);
+    ensure_eq!(dump.check("dump busy")?.tasks, 2);
+    reply_receiver.blocking_receive().check("receive reply")?;
+
+    drop(task);
+    context.close_session(session);
+
+    let dump = server::dump(SERVER_NAME).check("dump after close")?;
+    ensure_eq!(dump.tasks, 0);
+    ensure_eq!(dump.sessions, 0);
+
+    Ok(())
+}
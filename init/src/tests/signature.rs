@@ -0,0 +1,92 @@
+use alloc::vec::Vec;
+
+use crate::{
+    archive,
+    signature::{self, SignatureError, SignaturePolicy},
+};
+
+use super::{ensure_eq, ensure_err, Check, TestResult};
+
+/// Unsigned binaries are only loaded if the policy does not enforce signatures
+pub fn enforce_rejects_unsigned() -> TestResult {
+    let binary = b"\x7fELF";
+
+    ensure_err!(
+        signature::check_with(SignaturePolicy::Enforce, binary, None),
+        SignatureError::Missing
+    );
+    signature::check_with(SignaturePolicy::LogOnly, binary, None).check("check log only")?;
+    signature::check_with(SignaturePolicy::Disabled, binary, None).check("check disabled")?;
+
+    ensure_eq!(
+        SignaturePolicy::parse("enforce"),
+        Some(SignaturePolicy::Enforce)
+    );
+
+    Ok(())
+}
+
+/// The binaries signed at image build are loaded with the enforce policy
+pub fn enforce_accepts_signed() -> TestResult {
+    signature::check_with(
+        SignaturePolicy::Enforce,
+        archive::PROCESS_SERVER,
+        Some(archive::PROCESS_SERVER_SIGNATURE),
+    )
+    .check("check process-server")?;
+
+    signature::check_with(
+        SignaturePolicy::Enforce,
+        archive::VFS_SERVER,
+        Some(archive::VFS_SERVER_SIGNATURE),
+    )
+    .check("check vfs-server")?;
+
+    Ok(())
+}
+
+/// A tampered binary or signature, or the signature of another binary, is rejected
+pub fn enforce_rejects_tampered() -> TestResult {
+    let mut binary = Vec::from(archive::PROCESS_SERVER);
+    let last = binary.len() - 1;
+    binary[last] ^= 1;
+    ensure_err!(
+        signature::check_with(
+            SignaturePolicy::Enforce,
+            &binary,
+            Some(archive::PROCESS_SERVER_SIGNATURE)
+        ),
+        SignatureError::Invalid
+    );
+
+    let mut tampered = Vec::from(archive::PROCESS_SERVER_SIGNATURE);
+    tampered[0] ^= 1;
+    ensure_err!(
+        signature::check_with(
+            SignaturePolicy::Enforce,
+            archive::PROCESS_SERVER,
+            Some(&tampered)
+        ),
+        SignatureError::Invalid
+    );
+
+    ensure_err!(
+        signature::check_with(
+            SignaturePolicy::Enforce,
+            archive::PROCESS_SERVER,
+            Some(archive::VFS_SERVER_SIGNATURE)
+        ),
+        SignatureError::Invalid
+    );
+
+    ensure_err!(
+        signature::check_with(
+            SignaturePolicy::Enforce,
+            archive::PROCESS_SERVER,
+            Some(&archive::PROCESS_SERVER_SIGNATURE[..32])
+        ),
+        SignatureError::Malformed
+    );
+
+    Ok(())
+}
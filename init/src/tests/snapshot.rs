@@ -0,0 +1,134 @@
+use alloc::{string::String, vec::Vec};
+use libruntime::{
+    debug::Snapshot,
+    kobject::{HandleInfo, HandleType, Process},
+};
+
+use super::{
+    checkpoint::{fixture, kill},
+    ensure, ensure_eq, Check, TestResult,
+};
+
+/// Captured ids of the fixtures: their threads have the tid `pid + 1`
+const FIRST_PID: u64 = 10;
+const SECOND_PID: u64 = 20;
+
+/// Handle values in the fixtures, to the other fixture
+const PROCESS_HANDLE: u64 = 1;
+const THREAD_HANDLE: u64 = 2;
+
+/// All userland processes but the caller are captured, and run again afterwards
+pub fn capture() -> TestResult {
+    // Processes left suspended by other tests stay so
+    let suspended = suspended_pids()?;
+    let snapshot = Snapshot::capture().check("capture")?;
+    let current = Process::current().pid();
+
+    ensure!(!snapshot.processes.is_empty(), "no process captured");
+    ensure!(
+        snapshot
+            .processes
+            .iter()
+            .all(|process| process.pid != current),
+        "current process captured"
+    );
+
+    let left = suspended_pids()?;
+    ensure!(
+        left.iter().all(|pid| suspended.contains(pid)),
+        "processes left suspended: {:?} (before: {:?})",
+        left,
+        suspended
+    );
+
+    let mobj = snapshot.to_memory_object().check("to_memory_object")?;
+    let size = snapshot.to_bytes().len();
+    let loaded = Snapshot::from_memory_object(&mobj, size).check("from_memory_object")?;
+    ensure_eq!(loaded.processes.len(), snapshot.processes.len());
+    ensure!(
+        loaded.to_bytes() == snapshot.to_bytes(),
+        "snapshot changed through its memory object"
+    );
+
+    Ok(())
+}
+
+/// Handles from one restored process to another refer to the restored objects
+pub fn restore() -> TestResult {
+    let snapshot = Snapshot {
+        processes: Vec::from([
+            fixture(FIRST_PID, handles_to(SECOND_PID)),
+            fixture(SECOND_PID, handles_to(FIRST_PID)),
+        ]),
+    };
+
+    let restored = snapshot.restore().check("restore")?;
+    let result = check_restored(&restored[0].process, &restored[1].process);
+
+    for restored in restored.iter() {
+        kill(&restored.process);
+    }
+
+    for restored in restored.iter() {
+        ensure!(
+            restored.lost_handles.is_empty(),
+            "handles lost: {:?}",
+            restored.lost_handles
+        );
+    }
+
+    result
+}
+
+fn check_restored(first: &Process, second: &Process) -> TestResult {
+    let second_tid = *second
+        .threads()
+        .check("threads")?
+        .first()
+        .ok_or("no thread")?;
+
+    let handles = first.handles().check("handles")?;
+    let object_of = |value| {
+        handles
+            .iter()
+            .find(|info| info.handle == value)
+            .map(|info| (info.r#type, info.object_id))
+    };
+
+    ensure_eq!(
+        object_of(PROCESS_HANDLE),
+        Some((HandleType::Process, second.pid()))
+    );
+    ensure_eq!(
+        object_of(THREAD_HANDLE),
+        Some((HandleType::Thread, second_tid))
+    );
+
+    Ok(())
+}
+
+fn suspended_pids() -> Result<Vec<u64>, String> {
+    let pids = Process::list().check("list")?;
+
+    Ok(pids
+        .iter()
+        .copied()
+        .filter(|&pid| Process::open(pid).is_ok_and(|process| process.info().suspended))
+        .collect())
+}
+
+/// Handles of a fixture to the other one, and its thread
+fn handles_to(pid: u64) -> Vec<HandleInfo> {
+    Vec::from([
+        HandleInfo {
+            handle: PROCESS_HANDLE,
+            r#type: HandleType::Process,
+            object_id: pid,
+        },
+        HandleInfo {
+            handle: THREAD_HANDLE,
+            r#type: HandleType::Thread,
+            object_id: pid + 1,
+        },
+    ])
+}
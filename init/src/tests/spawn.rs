@@ -0,0 +1,47 @@
+use core::time::Duration;
+
+use libruntime::{
+    kobject::Error,
+    process_server::{ExitedProcess, ProcessServer},
+    retry::Backoff,
+};
+
+use super::{ensure_eq, ensure_err, Check, TestResult};
+
+pub fn missing_binary() -> TestResult {
+    let server = ProcessServer::wait_connect().check("connect")?;
+
+    ensure_err!(server.spawn("/bin/missing"), Error::ObjectNotFound);
+    ensure_err!(server.spawn(""), Error::InvalidArgument);
+
+    Ok(())
+}
+
+/// Spawn the C smoke program, which runs its checks on minilibc and reports through its exit status
+pub fn c_smoke() -> TestResult {
+    let server = ProcessServer::wait_connect().check("connect")?;
+
+    let pid = server.spawn("/bin/c-smoke").check("spawn")?;
+    let record = wait_exited(&server, pid)?;
+
+    ensure_eq!(record.name.as_str(), "c-smoke");
+    ensure_eq!(record.status, 0);
+
+    Ok(())
+}
+
+/// Wait until the process is reaped by process-server, and get its record
+fn wait_exited(server: &ProcessServer, pid: u64) -> Result<ExitedProcess, alloc::string::String> {
+    let backoff = Backoff::new(Duration::from_millis(10), Duration::from_millis(200))
+        .timeout(Duration::from_secs(5));
+
+    backoff
+        .retry(|| {
+            server
+                .list_exited()?
+                .into_iter()
+                .find(|record| record.pid == pid)
+                .ok_or(Error::ObjectNotReady)
+        })
+        .check("wait for process exit")
+}
@@ -0,0 +1,644 @@
+// Most tests mount a private memfs instance on their own path, and unmount it at the end
+
+use core::time::Duration;
+
+use alloc::{string::String, vec, vec::Vec};
+use libruntime::{
+    fs::MEMFS_PORT_NAME,
+    kobject::{Error, Message, Port, PortSender, Thread, ThreadOptions},
+    retry::{self, Backoff},
+    vfs::{
+        BatchOperation, DirEntry, MountOptions, NodeInfo, NodeKind, OpenFlags, Reply, TimeSpec,
+        Vfs, BLOCK_SIZE,
+    },
+};
+
+use super::{ensure, ensure_eq, ensure_err, Check, TestResult};
+
+/// Mount a memfs instance on `path`, run the test on it, then unmount it
+fn with_mount(
+    path: &str,
+    options: MountOptions,
+    test: impl FnOnce(&Vfs) -> TestResult,
+) -> TestResult {
+    let vfs = Vfs::wait_connect().check("connect")?;
+    vfs.mount(path, MEMFS_PORT_NAME, options).check("mount")?;
+
+    let result = test(&vfs);
+
+    vfs.unmount(path).check("unmount")?;
+    result
+}
+
+fn pattern(size: usize) -> Vec<u8> {
+    (0..size).map(|index| (index % 251) as u8).collect()
+}
+
+pub fn read_write() -> TestResult {
+    with_mount("/tests/read-write", MountOptions::default(), |vfs| {
+        let file = vfs
+            .open("/tests/read-write/file", OpenFlags::CREATE)
+            .check("open")?;
+        ensure_eq!(file.info().size, 0);
+
+        ensure_eq!(file.write_at(0, b"hello world").check("write")?, 11);
+        // Past the end: the gap is zero-filled
+        ensure_eq!(file.write_at(16, b"!").check("write past end")?, 1);
+        ensure_eq!(file.stat().check("stat")?.size, 17);
+
+        let mut buffer = [0xFF; 32];
+        ensure_eq!(file.read_at(0, &mut buffer).check("read")?, 17);
+        ensure_eq!(&buffer[..17], b"hello world\0\0\0\0\0!");
+        ensure_eq!(file.read_at(6, &mut buffer[..5]).check("read middle")?, 5);
+        ensure_eq!(&buffer[..5], b"world");
+        ensure_eq!(file.read_at(100, &mut buffer).check("read past end")?, 0);
+
+        file.truncate(5).check("truncate")?;
+        ensure_eq!(
+            vfs.stat("/tests/read-write/file").check("stat path")?.size,
+            5
+        );
+        ensure_eq!(file.read_at(0, &mut buffer).check("read truncated")?, 5);
+        ensure_eq!(&buffer[..5], b"hello");
+
+        Ok(())
+    })
+}
+
+pub fn open_flags() -> TestResult {
+    with_mount("/tests/open-flags", MountOptions::default(), |vfs| {
+        let path = "/tests/open-flags/file";
+
+        ensure_err!(vfs.open(path, OpenFlags::NONE), Error::ObjectNotFound);
+
+        let file = vfs
+            .open(path, OpenFlags::CREATE | OpenFlags::EXCLUSIVE)
+            .check("create")?;
+        file.write_at(0, b"data").check("write")?;
+        drop(file);
+
+        ensure_err!(
+            vfs.open(path, OpenFlags::CREATE | OpenFlags::EXCLUSIVE),
+            Error::ObjectNameDuplicate
+        );
+
+        // Existing file: kept as is
+        let file = vfs.open(path, OpenFlags::CREATE).check("open existing")?;
+        ensure_eq!(file.info().size, 4);
+        drop(file);
+
+        let file = vfs.open(path, OpenFlags::TRUNCATE).check("open truncate")?;
+        ensure_eq!(file.info().size, 0);
+        ensure_eq!(file.stat().check("stat")?.size, 0);
+        drop(file);
+
+        // Directories can be opened, not read or truncated
+        let root = vfs
+            .open("/tests/open-flags", OpenFlags::NONE)
+            .check("open root")?;
+        ensure!(root.info().is_directory(), "root is not a directory");
+        ensure_err!(root.read_at(0, &mut [0; 4]), Error::InvalidArgument);
+        drop(root);
+        ensure_err!(
+            vfs.open("/tests/open-flags", OpenFlags::TRUNCATE),
+            Error::InvalidArgument
+        );
+
+        Ok(())
+    })
+}
+
+pub fn directories() -> TestResult {
+    with_mount("/tests/directories", MountOptions::default(), |vfs| {
+        vfs.mkdir("/tests/directories/dir").check("mkdir")?;
+        ensure_err!(
+            vfs.mkdir("/tests/directories/dir"),
+            Error::ObjectNameDuplicate
+        );
+        ensure_err!(
+            vfs.mkdir("/tests/directories/missing/dir"),
+            Error::ObjectNotFound
+        );
+
+        vfs.open("/tests/directories/file", OpenFlags::CREATE)
+            .check("create")?;
+        vfs.rename("/tests/directories/file", "/tests/directories/dir/moved")
+            .check("rename")?;
+        ensure_err!(vfs.stat("/tests/directories/file"), Error::ObjectNotFound);
+
+        let entries = vfs.list("/tests/directories/dir").check("list")?;
+        ensure_eq!(
+            entries,
+            vec![DirEntry {
+                name: String::from("moved"),
+                kind: NodeKind::File,
+            }]
+        );
+
+        // A directory cannot move into itself
+        ensure_err!(
+            vfs.rename("/tests/directories/dir", "/tests/directories/dir/inner"),
+            Error::InvalidArgument
+        );
+        // Not across filesystems
+        ensure_err!(
+            vfs.rename("/tests/directories/dir/moved", "/tests/moved"),
+            Error::InvalidArgument
+        );
+        // Not the mount point
+        ensure_err!(vfs.remove("/tests/directories"), Error::InvalidArgument);
+
+        ensure_err!(vfs.remove("/tests/directories/dir"), Error::InvalidArgument);
+        vfs.remove("/tests/directories/dir/moved")
+            .check("remove file")?;
+        vfs.remove("/tests/directories/dir")
+            .check("remove directory")?;
+        ensure_eq!(vfs.list("/tests/directories").check("list root")?, vec![]);
+
+        ensure_err!(
+            vfs.open("relative", OpenFlags::NONE),
+            Error::InvalidArgument
+        );
+        ensure_err!(vfs.stat("/tests/directories/.."), Error::InvalidArgument);
+
+        Ok(())
+    })
+}
+
+pub fn mounts() -> TestResult {
+    let vfs = Vfs::wait_connect().check("connect")?;
+
+    ensure_err!(
+        vfs.mount("/tests/mounts", "missing-fs", MountOptions::default()),
+        Error::ObjectNotFound
+    );
+    ensure_err!(vfs.unmount("/tests/mounts"), Error::ObjectNotFound);
+
+    with_mount("/tests/mounts", MountOptions::default(), |vfs| {
+        ensure_err!(
+            vfs.mount("/tests/mounts", MEMFS_PORT_NAME, MountOptions::default()),
+            Error::ObjectNameDuplicate
+        );
+
+        // Each mount has its own instance
+        let file = vfs
+            .open("/tests/mounts/file", OpenFlags::CREATE)
+            .check("create")?;
+        ensure_err!(vfs.stat("/file"), Error::ObjectNotFound);
+
+        ensure_err!(vfs.unmount("/tests/mounts"), Error::ObjectNotReady);
+        drop(file);
+
+        Ok(())
+    })
+}
+
+pub fn copy_range() -> TestResult {
+    with_mount("/tests/copy-range", MountOptions::default(), |vfs| {
+        let source = vfs
+            .open("/tests/copy-range/source", OpenFlags::CREATE)
+            .check("create source")?;
+        source.write_at(0, b"0123456789").check("write")?;
+        let dest = vfs
+            .open("/tests/copy-range/dest", OpenFlags::CREATE)
+            .check("create dest")?;
+
+        // Same filesystem: copied by memfs, the destination is extended
+        ensure_eq!(source.copy_range(2, &dest, 4, 5).check("copy")?, 5);
+        let mut buffer = [0xFF; 16];
+        ensure_eq!(dest.read_at(0, &mut buffer).check("read dest")?, 9);
+        ensure_eq!(&buffer[..4], &[0; 4]);
+        ensure_eq!(&buffer[4..9], b"23456");
+
+        // Shortened at the end of the source, nothing past it
+        ensure_eq!(source.copy_range(8, &dest, 0, 100).check("copy end")?, 2);
+        ensure_eq!(
+            source
+                .copy_range(10, &dest, 100, 4)
+                .check("copy past end")?,
+            0
+        );
+        ensure_eq!(dest.stat().check("stat dest")?.size, 9);
+
+        // Overlapping ranges of the same file
+        ensure_eq!(
+            source.copy_range(0, &source, 2, 6).check("copy overlap")?,
+            6
+        );
+        ensure_eq!(source.read_at(0, &mut buffer).check("read source")?, 10);
+        ensure_eq!(&buffer[..10], b"0101234589");
+
+        // Across filesystems: through vfs-server
+        let other = vfs
+            .open("/copy-range-other", OpenFlags::CREATE)
+            .check("create on root")?;
+        ensure_eq!(source.copy_range(0, &other, 0, 4).check("copy across")?, 4);
+        ensure_eq!(other.read_at(0, &mut buffer).check("read other")?, 4);
+        ensure_eq!(&buffer[..4], b"0101");
+        drop(other);
+        vfs.remove("/copy-range-other").check("remove other")?;
+
+        let directory = vfs
+            .open("/tests/copy-range", OpenFlags::NONE)
+            .check("open directory")?;
+        ensure_err!(
+            source.copy_range(0, &directory, 0, 4),
+            Error::InvalidArgument
+        );
+
+        Ok(())
+    })
+}
+
+pub fn timestamps() -> TestResult {
+    // Without read-ahead, so that all the reads reach memfs
+    let options = MountOptions {
+        read_ahead: 0,
+        ..MountOptions::default()
+    };
+    with_mount("/tests/timestamps", options, |vfs| {
+        let file = vfs
+            .open("/tests/timestamps/file", OpenFlags::CREATE)
+            .check("create")?;
+        let created = *file.info();
+        ensure!(created.mtime > 0, "no creation time: {:?}", created);
+        ensure_eq!(created.atime, created.mtime);
+        ensure_eq!(created.ctime, created.mtime);
+
+        // The directory changed with its entries
+        let directory = vfs.stat("/tests/timestamps").check("stat directory")?;
+        ensure!(directory.mtime >= created.mtime, "directory not modified");
+
+        // Explicit times
+        let info = file
+            .set_times(
+                TimeSpec::At(Duration::from_secs(1)),
+                TimeSpec::At(Duration::from_secs(2)),
+            )
+            .check("set times")?;
+        ensure_eq!(info.atime, 1_000_000_000);
+        ensure_eq!(info.mtime, 2_000_000_000);
+        ensure!(info.ctime >= created.ctime, "change time not updated");
+
+        let info = file
+            .set_times(TimeSpec::Omit, TimeSpec::At(Duration::from_secs(3)))
+            .check("set mtime only")?;
+        ensure_eq!(info.atime, 1_000_000_000);
+        ensure_eq!(info.mtime, 3_000_000_000);
+
+        // Relatime: the access time is older than the modification time, so a read updates it
+        file.write_at(0, b"data").check("write")?;
+        let written = file.stat().check("stat written")?;
+        ensure!(written.mtime >= info.ctime, "modification time not updated");
+        ensure_eq!(written.atime, 1_000_000_000);
+
+        file.read_at(0, &mut [0; 4]).check("read")?;
+        let read = file.stat().check("stat read")?;
+        ensure!(read.atime >= written.mtime, "access time not updated");
+        ensure_eq!(read.mtime, written.mtime);
+
+        let info = file
+            .set_times(TimeSpec::Now, TimeSpec::Omit)
+            .check("set atime now")?;
+        ensure!(info.atime >= read.atime, "access time not set to now");
+        ensure_eq!(info.mtime, read.mtime);
+
+        // More recent than the changes: reads leave it
+        let recent = Duration::from_nanos(info.ctime) + Duration::from_secs(60);
+        let info = file
+            .set_times(TimeSpec::At(recent), TimeSpec::Omit)
+            .check("set recent atime")?;
+        file.read_at(0, &mut [0; 4]).check("read again")?;
+        ensure_eq!(file.stat().check("stat read again")?.atime, info.atime);
+
+        Ok(())
+    })
+}
+
+/// Sequential reads are served from the blocks prefetched by read-ahead
+pub fn read_ahead() -> TestResult {
+    const SIZE: usize = 16 * BLOCK_SIZE;
+    let data = pattern(SIZE);
+
+    let read_all = |vfs: &Vfs, path: &str| -> Result<Vec<u8>, String> {
+        let file = vfs.open(path, OpenFlags::CREATE).check("open")?;
+        file.write_at(0, &data).check("write")?;
+
+        let mut read = vec![0; SIZE];
+        for (index, chunk) in read.chunks_mut(BLOCK_SIZE).enumerate() {
+            let size = file
+                .read_at((index * BLOCK_SIZE) as u64, chunk)
+                .check("read")?;
+            ensure_eq!(size, BLOCK_SIZE);
+        }
+        Ok(read)
+    };
+
+    with_mount("/tests/read-ahead", MountOptions::default(), |vfs| {
+        let before = vfs.statistics().check("statistics")?;
+        let read = read_all(vfs, "/tests/read-ahead/file")?;
+        let after = vfs.statistics().check("statistics")?;
+
+        ensure!(read == data, "data read does not match");
+        ensure!(after.prefetched > before.prefetched, "no block prefetched");
+        ensure!(after.hits > before.hits, "no read served from the cache");
+
+        // Rewritten: the cache must not serve the previous data
+        let file = vfs
+            .open("/tests/read-ahead/file", OpenFlags::NONE)
+            .check("open")?;
+        file.write_at(BLOCK_SIZE as u64, b"changed")
+            .check("rewrite")?;
+        let mut buffer = [0; 7];
+        file.read_at(BLOCK_SIZE as u64, &mut buffer)
+            .check("read rewritten")?;
+        ensure_eq!(&buffer, b"changed");
+
+        Ok(())
+    })?;
+
+    with_mount(
+        "/tests/no-read-ahead",
+        MountOptions {
+            read_ahead: 0,
+            ..MountOptions::default()
+        },
+        |vfs| {
+            let before = vfs.statistics().check("statistics")?;
+            let read = read_all(vfs, "/tests/no-read-ahead/file")?;
+            let after = vfs.statistics().check("statistics")?;
+
+            ensure!(read == data, "data read does not match");
+            ensure_eq!(after.prefetched, before.prefetched);
+            ensure_eq!(after.hits, before.hits);
+
+            Ok(())
+        },
+    )
+}
+
+pub fn trash() -> TestResult {
+    let options = MountOptions {
+        trash_expiry: Some(Duration::from_secs(3600)),
+        ..MountOptions::default()
+    };
+    with_mount("/tests/trash", options, |vfs| {
+        let file = vfs
+            .open("/tests/trash/file", OpenFlags::CREATE)
+            .check("open")?;
+        file.write_at(0, b"keep me").check("write")?;
+
+        vfs.remove("/tests/trash/file").check("remove")?;
+        ensure_err!(vfs.stat("/tests/trash/file"), Error::ObjectNotFound);
+
+        // Hidden from the mount, but the open handle still works
+        ensure_eq!(
+            vfs.list("/tests/trash").check("list")?,
+            Vec::<DirEntry>::new()
+        );
+        ensure_err!(vfs.list("/tests/trash/.trash"), Error::InvalidArgument);
+        ensure_eq!(file.stat().check("stat handle")?.size, 7);
+
+        let entries = vfs.list_trash().check("list trash")?;
+        let entry = entries
+            .iter()
+            .find(|entry| entry.path == "/tests/trash/file")
+            .ok_or(Error::ObjectNotFound)
+            .check("trash entry")?;
+        ensure_eq!(entry.kind, NodeKind::File);
+        ensure!(entry.deleted > 0, "no deletion time");
+        let id = entry.id;
+        drop(file);
+
+        ensure_eq!(vfs.restore(id).check("restore")?.size, 7);
+        let mut buffer = [0; 7];
+        let file = vfs
+            .open("/tests/trash/file", OpenFlags::NONE)
+            .check("open restored")?;
+        file.read_at(0, &mut buffer).check("read restored")?;
+        ensure_eq!(&buffer, b"keep me");
+        drop(file);
+        ensure_err!(vfs.restore(id), Error::ObjectNotFound);
+
+        // The path is taken again
+        vfs.remove("/tests/trash/file").check("remove again")?;
+        vfs.open("/tests/trash/file", OpenFlags::CREATE)
+            .check("create again")?;
+        let id = trash_id(vfs, "/tests/trash/file")?;
+        ensure_err!(vfs.restore(id), Error::ObjectNameDuplicate);
+
+        vfs.purge(Some(id)).check("purge")?;
+        ensure!(
+            trash_id(vfs, "/tests/trash/file").is_err(),
+            "purged entry still listed"
+        );
+
+        // A non-empty directory cannot be removed
+        vfs.mkdir("/tests/trash/dir").check("mkdir")?;
+        vfs.open("/tests/trash/dir/file", OpenFlags::CREATE)
+            .check("create in dir")?;
+        ensure_err!(vfs.remove("/tests/trash/dir"), Error::InvalidArgument);
+        vfs.remove("/tests/trash/dir/file").check("remove in dir")?;
+        vfs.remove("/tests/trash/dir").check("remove dir")?;
+        vfs.remove("/tests/trash/file").check("remove file")?;
+
+        vfs.purge(None).check("purge all")?;
+        ensure!(
+            vfs.list_trash()
+                .check("list trash")?
+                .iter()
+                .all(|entry| !entry.path.starts_with("/tests/trash/")),
+            "entries left after purge"
+        );
+
+        Ok(())
+    })?;
+
+    let options = MountOptions {
+        trash_expiry: Some(Duration::from_millis(200)),
+        ..MountOptions::default()
+    };
+    with_mount("/tests/trash-expiry", options, |vfs| {
+        vfs.open("/tests/trash-expiry/file", OpenFlags::CREATE)
+            .check("create")?;
+        vfs.remove("/tests/trash-expiry/file").check("remove")?;
+        trash_id(vfs, "/tests/trash-expiry/file")?;
+
+        let backoff = Backoff::new(Duration::from_millis(50), Duration::from_millis(500))
+            .timeout(Duration::from_secs(5));
+        backoff
+            .retry(|| {
+                let entries = vfs.list_trash()?;
+                if entries
+                    .iter()
+                    .any(|entry| entry.path == "/tests/trash-expiry/file")
+                {
+                    Err(Error::ObjectNotReady)
+                } else {
+                    Ok(())
+                }
+            })
+            .check("wait for expiry")?;
+
+        Ok(())
+    })
+}
+
+pub fn batch() -> TestResult {
+    with_mount("/tests/batch", MountOptions::default(), |vfs| {
+        let config = vfs
+            .open("/tests/batch/config", OpenFlags::CREATE)
+            .check("create config")?;
+        config.write_at(0, b"old config").check("write config")?;
+
+        // Config update: written to a temporary file, then renamed over the old one
+        vfs.batch(&[
+            BatchOperation::Create {
+                path: String::from("/tests/batch/config.tmp"),
+                kind: NodeKind::File,
+            },
+            BatchOperation::Write {
+                path: String::from("/tests/batch/config.tmp"),
+                offset: 0,
+                data: Vec::from(b"new"),
+            },
+            BatchOperation::Rename {
+                from: String::from("/tests/batch/config.tmp"),
+                to: String::from("/tests/batch/config"),
+            },
+        ])
+        .check("batch")?;
+        ensure_eq!(read_file(vfs, "/tests/batch/config")?, b"new");
+        ensure_err!(vfs.stat("/tests/batch/config.tmp"), Error::ObjectNotFound);
+        // The replaced file is gone
+        ensure_err!(config.stat(), Error::ObjectNotFound);
+        drop(config);
+
+        // The last operation fails: the write, the creation and the replacement are undone
+        ensure_err!(
+            vfs.batch(&[
+                BatchOperation::Write {
+                    path: String::from("/tests/batch/config"),
+                    offset: 1,
+                    data: Vec::from(b"written past the end"),
+                },
+                BatchOperation::Create {
+                    path: String::from("/tests/batch/other"),
+                    kind: NodeKind::File,
+                },
+                BatchOperation::Rename {
+                    from: String::from("/tests/batch/other"),
+                    to: String::from("/tests/batch/config"),
+                },
+                BatchOperation::Rename {
+                    from: String::from("/tests/batch/missing"),
+                    to: String::from("/tests/batch/renamed"),
+                },
+            ]),
+            Error::ObjectNotFound
+        );
+        ensure_eq!(read_file(vfs, "/tests/batch/config")?, b"new");
+        ensure_err!(vfs.stat("/tests/batch/other"), Error::ObjectNotFound);
+        ensure_eq!(
+            vfs.list("/tests/batch").check("list")?,
+            vec![DirEntry {
+                name: String::from("config"),
+                kind: NodeKind::File,
+            }]
+        );
+
+        // All operations must be on the same mount
+        ensure_err!(
+            vfs.batch(&[
+                BatchOperation::Create {
+                    path: String::from("/tests/batch/file"),
+                    kind: NodeKind::File,
+                },
+                BatchOperation::Create {
+                    path: String::from("/batch-other-mount"),
+                    kind: NodeKind::File,
+                },
+            ]),
+            Error::InvalidArgument
+        );
+        ensure_err!(vfs.stat("/tests/batch/file"), Error::ObjectNotFound);
+
+        vfs.batch(&[]).check("empty batch")?;
+
+        Ok(())
+    })
+}
+
+/// vfs-server waits for the filesystem server of a mount with a backoff, and gives up if it does not come up
+pub fn mount_missing_fs() -> TestResult {
+    let vfs = Vfs::wait_connect().check("connect")?;
+
+    ensure_err!(
+        vfs.mount(
+            "/tests/missing-fs",
+            "tests-missing-fs",
+            MountOptions::default()
+        ),
+        Error::DeadlineExceeded
+    );
+    ensure_err!(vfs.unmount("/tests/missing-fs"), Error::ObjectNotFound);
+
+    Ok(())
+}
+
+/// The client reopens the port of a server which restarted, and sends its request again
+pub fn reconnect() -> TestResult {
+    const PORT_NAME: &str = "tests-vfs-reconnect";
+
+    let (receiver, _sender) = Port::create(Some(PORT_NAME)).check("create port")?;
+    let vfs = Vfs::connect_to(PORT_NAME).check("connect")?;
+
+    // The server goes away, and comes back a bit later to answer one request
+    drop(receiver);
+
+    let entry = || {
+        let _ = retry::sleep(Duration::from_millis(50));
+        let Ok((receiver, _sender)) = Port::create(Some(PORT_NAME)) else {
+            return;
+        };
+        let Ok(mut message) = receiver.blocking_receive() else {
+            return;
+        };
+        let Ok(reply_port) = PortSender::from_handle(message.take_handle(0)) else {
+            return;
+        };
+
+        let reply = Reply::new(Ok((0, NodeInfo::EMPTY)));
+        let mut reply_message = unsafe { Message::new(&reply, &mut []) };
+        reply_message.set_correlation(message.correlation());
+        let _ = reply_port.send(&mut reply_message);
+    };
+
+    let mut options = ThreadOptions::default();
+    options.name("test-vfs-server");
+    Thread::start(entry, options).check("start server")?;
+
+    vfs.purge(None).check("purge after restart")?;
+
+    Ok(())
+}
+
+/// Read a whole file
+fn read_file(vfs: &Vfs, path: &str) -> Result<Vec<u8>, String> {
+    let file = vfs.open(path, OpenFlags::NONE).check("open")?;
+    let mut data = vec![0; file.info().size as usize];
+    let size = file.read_at(0, &mut data).check("read")?;
+    data.truncate(size);
+    Ok(data)
+}
+
+/// Get the id of the trash entry of a path
+fn trash_id(vfs: &Vfs, path: &str) -> Result<u64, String> {
+    vfs.list_trash()
+        .check("list trash")?
+        .iter()
+        .find(|entry| entry.path == path)
+        .map(|entry| entry.id)
+        .ok_or(Error::ObjectNotFound)
+        .check("trash entry")
+}
@@ -0,0 +1,220 @@
+use core::time::Duration;
+
+use alloc::{string::String, sync::Arc, vec::Vec};
+use libruntime::{
+    kobject::{
+        Error, KWaitable, Message, Port, PortReceiver, PortSender, SchedEvent, SchedEventType,
+        Thread, ThreadOptions,
+    },
+    retry::Backoff,
+};
+
+use super::{ensure, ensure_eq, ensure_err, Check, TestResult};
+
+/// Scheduler trace, enabled while the test runs
+struct Trace {
+    events: Vec<SchedEvent>,
+}
+
+impl Trace {
+    fn start() -> Result<Self, String> {
+        Thread::sched_trace_enable(true).check("enable sched trace")?;
+
+        let mut trace = Self { events: Vec::new() };
+        trace.reset()?;
+        Ok(trace)
+    }
+
+    /// Drop the events received so far
+    fn reset(&mut self) -> TestResult {
+        self.collect()?;
+        self.events.clear();
+        Ok(())
+    }
+
+    fn collect(&mut self) -> TestResult {
+        let events = Thread::sched_trace_read().check("read sched trace")?;
+        self.events.extend(events);
+        Ok(())
+    }
+
+    fn count(&self, r#type: SchedEventType, tid: u64) -> usize {
+        self.events
+            .iter()
+            .filter(|event| event.r#type == r#type && event.tid == tid)
+            .count()
+    }
+
+    /// Wait until the thread goes to sleep
+    fn wait_sleep(&mut self, tid: u64) -> TestResult {
+        let backoff = Backoff::new(Duration::from_millis(1), Duration::from_millis(50))
+            .timeout(Duration::from_secs(1));
+
+        let result = backoff.retry(|| {
+            self.events.extend(Thread::sched_trace_read()?);
+
+            if self.count(SchedEventType::Sleep, tid) > 0 {
+                Ok(())
+            } else {
+                Err(Error::ObjectNotReady)
+            }
+        });
+
+        result.map_err(|err| alloc::format!("thread {} never went to sleep: {:?}", tid, err))
+    }
+}
+
+impl Drop for Trace {
+    fn drop(&mut self) {
+        let _ = Thread::sched_trace_enable(false);
+    }
+}
+
+/// How a receiver waits for its message
+#[derive(Debug, Clone, Copy)]
+enum Receive {
+    /// `blocking_receive`: messages of the same process are handed off directly
+    Blocking,
+    /// `wait` then `receive`: woken with the other receivers on each message
+    WaitThenReceive,
+}
+
+/// Start a thread which receives one message from the port, and reports it with its tid on `results`
+fn start_receiver(
+    receiver: &Arc<PortReceiver>,
+    results: &PortSender,
+    mode: Receive,
+) -> Result<u64, String> {
+    let receiver = receiver.clone();
+    let results = results.clone();
+
+    let entry = move || {
+        let message = match mode {
+            Receive::Blocking => receiver.blocking_receive(),
+            Receive::WaitThenReceive => loop {
+                if let Err(err) = receiver.wait() {
+                    break Err(err);
+                }
+
+                match receiver.receive() {
+                    Err(Error::ObjectNotReady) => {}
+                    result => break result,
+                }
+            },
+        };
+
+        let value = message
+            .map(|message| *unsafe { message.data::<u64>() })
+            .unwrap_or(0);
+
+        let mut message = unsafe { Message::new(&(Thread::current_tid(), value), &mut []) };
+        let _ = results.send(&mut message);
+    };
+
+    let mut options = ThreadOptions::default();
+    options.name("test-receiver");
+    let thread = Thread::start(entry, options).check("start receiver")?;
+    Ok(thread.tid())
+}
+
+fn send(sender: &PortSender, value: u64) -> TestResult {
+    let mut message = unsafe { Message::new(&value, &mut []) };
+    sender.send(&mut message).check("send")
+}
+
+/// Get the (tid, value) reported by a receiver
+fn result(results: &PortReceiver) -> Result<(u64, u64), String> {
+    let message = results.blocking_receive().check("receive result")?;
+    Ok(*unsafe { message.data::<(u64, u64)>() })
+}
+
+/// Sending to a port nobody waits on wakes nobody, and the message waits for the next receive
+pub fn wake_empty_queue() -> TestResult {
+    let (receiver, sender) = Port::create(None).check("create port")?;
+    let mut trace = Trace::start()?;
+
+    send(&sender, 42)?;
+
+    trace.collect()?;
+    let wakes = trace
+        .events
+        .iter()
+        .filter(|event| {
+            event.r#type == SchedEventType::Wake && event.other_tid == Thread::current_tid()
+        })
+        .count();
+    ensure_eq!(wakes, 0);
+
+    let message = receiver.receive().check("receive")?;
+    ensure_eq!(*unsafe { message.data::<u64>() }, 42);
+    ensure_err!(receiver.receive(), Error::ObjectNotReady);
+
+    Ok(())
+}
+
+/// All the waiting receivers are woken once per message: the one which lost the race waits again, and gets the next message
+pub fn requeued_waiter() -> TestResult {
+    let (receiver, sender) = Port::create(None).check("create port")?;
+    let receiver = Arc::new(receiver);
+    let (results, results_sender) = Port::create(None).check("create results port")?;
+    let mut trace = Trace::start()?;
+
+    let first = start_receiver(&receiver, &results_sender, Receive::WaitThenReceive)?;
+    let second = start_receiver(&receiver, &results_sender, Receive::WaitThenReceive)?;
+    trace.wait_sleep(first)?;
+    trace.wait_sleep(second)?;
+    trace.reset()?;
+
+    send(&sender, 1)?;
+    let (winner, value) = result(&results)?;
+    ensure_eq!(value, 1);
+    ensure!(
+        winner == first || winner == second,
+        "unexpected receiver {}",
+        winner
+    );
+    let loser = if winner == first { second } else { first };
+
+    // The loser is woken, then waits again
+    trace.wait_sleep(loser)?;
+    ensure_eq!(trace.count(SchedEventType::Wake, winner), 1);
+    ensure_eq!(trace.count(SchedEventType::Wake, loser), 1);
+    trace.reset()?;
+
+    send(&sender, 2)?;
+    ensure_eq!(result(&results)?, (loser, 2));
+    trace.collect()?;
+    ensure_eq!(trace.count(SchedEventType::Wake, loser), 1);
+    ensure_eq!(trace.count(SchedEventType::Wake, winner), 0);
+
+    Ok(())
+}
+
+/// A message sent by the same process goes directly to the oldest blocked receiver: the other one is not woken
+pub fn direct_handoff() -> TestResult {
+    let (receiver, sender) = Port::create(None).check("create port")?;
+    let receiver = Arc::new(receiver);
+    let (results, results_sender) = Port::create(None).check("create results port")?;
+    let mut trace = Trace::start()?;
+
+    let first = start_receiver(&receiver, &results_sender, Receive::Blocking)?;
+    trace.wait_sleep(first)?;
+    let second = start_receiver(&receiver, &results_sender, Receive::Blocking)?;
+    trace.wait_sleep(second)?;
+    trace.reset()?;
+
+    send(&sender, 1)?;
+    ensure_eq!(result(&results)?, (first, 1));
+    trace.collect()?;
+    ensure_eq!(trace.count(SchedEventType::Wake, first), 1);
+    ensure_eq!(trace.count(SchedEventType::Wake, second), 0);
+    trace.reset()?;
+
+    send(&sender, 2)?;
+    ensure_eq!(result(&results)?, (second, 2));
+
+    // The message never went through the queue
+    ensure_err!(receiver.receive(), Error::ObjectNotReady);
+
+    Ok(())
+}
@@ -0,0 +1,344 @@
+use core::{mem::size_of, ptr::read_unaligned, slice};
+
+use alloc::vec::Vec;
+
+use crate::logging::{debug, warn};
+use crate::memory::{
+    map_iomem, page_aligned_down, page_aligned_up, unmap_iomem, Permissions, PhysAddr, VirtAddr,
+    PAGE_SIZE,
+};
+
+/// Interrupt controllers description, from the ACPI MADT ("APIC" table)
+#[derive(Debug)]
+pub struct Madt {
+    pub io_apics: Vec<IoApicEntry>,
+    pub overrides: Vec<InterruptOverride>,
+
+    /// The legacy 8259 PICs are present (they must be disabled when using the IOAPIC)
+    pub has_legacy_pics: bool,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct IoApicEntry {
+    pub id: u8,
+    pub address: PhysAddr,
+
+    /// First global system interrupt handled by this IOAPIC
+    pub gsi_base: u32,
+}
+
+/// ISA interrupt which is not identity mapped to a global system interrupt, or which has non-default polarity/trigger
+#[derive(Debug, Clone, Copy)]
+pub struct InterruptOverride {
+    pub isa_irq: u8,
+    pub gsi: u32,
+    pub polarity: Polarity,
+    pub trigger: Trigger,
+}
+
+/// Polarity of an interrupt line ("bus default" is resolved: active high for ISA)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Polarity {
+    ActiveHigh,
+    ActiveLow,
+}
+
+/// Trigger mode of an interrupt line ("bus default" is resolved: edge for ISA)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Trigger {
+    Edge,
+    Level,
+}
+
+/// ACPI sleep support, from the FADT and the DSDT
+#[derive(Debug, Clone, Copy)]
+pub struct SleepInfo {
+    /// PM1a control block (io port)
+    pub pm1a_control: u16,
+
+    /// PM1b control block (io port, 0 if not present)
+    pub pm1b_control: u16,
+
+    /// `SLP_TYPa` and `SLP_TYPb` values of the S3 state (from the `\_S3_` package), if S3 is supported
+    pub s3: Option<(u8, u8)>,
+
+    /// `SLP_TYPa` and `SLP_TYPb` values of the S5 state (soft off, from the `\_S5_` package), if supported
+    pub s5: Option<(u8, u8)>,
+}
+
+const RSDP_SIGNATURE: &[u8; 8] = b"RSD PTR ";
+const MADT_SIGNATURE: &[u8; 4] = b"APIC";
+const FADT_SIGNATURE: &[u8; 4] = b"FACP";
+
+const FADT_DSDT_OFFSET: usize = 40;
+const FADT_PM1A_CONTROL_OFFSET: usize = 64;
+const FADT_PM1B_CONTROL_OFFSET: usize = 68;
+const FADT_X_DSDT_OFFSET: usize = 140;
+
+const SDT_HEADER_SIZE: usize = 36;
+const MADT_ENTRIES_OFFSET: usize = SDT_HEADER_SIZE + 8;
+const MADT_FLAG_PCAT_COMPAT: u32 = 1;
+
+const MADT_ENTRY_IO_APIC: u8 = 1;
+const MADT_ENTRY_INTERRUPT_OVERRIDE: u8 = 2;
+
+/// Find and parse the MADT, from the RSDP given by the bootloader
+pub fn find_madt(rsdp: PhysAddr) -> Option<Madt> {
+    let Some(table) = find_table(rsdp, MADT_SIGNATURE) else {
+        warn!("ACPI: no MADT found");
+        return None;
+    };
+
+    parse_madt(&table)
+}
+
+/// Find the sleep registers and the S3 and S5 sleep types, from the RSDP given by the bootloader
+pub fn find_sleep_info(rsdp: PhysAddr) -> Option<SleepInfo> {
+    let Some(fadt) = find_table(rsdp, FADT_SIGNATURE) else {
+        warn!("ACPI: no FADT found");
+        return None;
+    };
+
+    // ACPI 2.0+: prefer the 64 bits pointer
+    let mut dsdt = 0;
+    if fadt.len >= FADT_X_DSDT_OFFSET + size_of::<u64>() {
+        dsdt = fadt.read::<u64>(FADT_X_DSDT_OFFSET);
+    }
+    if dsdt == 0 {
+        dsdt = fadt.read::<u32>(FADT_DSDT_OFFSET) as u64;
+    }
+
+    let dsdt = map_table(PhysAddr::new(dsdt));
+    let s3 = dsdt
+        .as_ref()
+        .and_then(|dsdt| find_sleep_type(dsdt, b"_S3_"));
+    let s5 = dsdt
+        .as_ref()
+        .and_then(|dsdt| find_sleep_type(dsdt, b"_S5_"));
+
+    Some(SleepInfo {
+        pm1a_control: fadt.read::<u32>(FADT_PM1A_CONTROL_OFFSET) as u16,
+        pm1b_control: fadt.read::<u32>(FADT_PM1B_CONTROL_OFFSET) as u16,
+        s3,
+        s5,
+    })
+}
+
+/// Find a table from its signature
+fn find_table(rsdp: PhysAddr, signature: &[u8; 4]) -> Option<PhysView> {
+    let rsdp = PhysView::new(rsdp, 36);
+
+    if rsdp.bytes(0, 8) != RSDP_SIGNATURE {
+        warn!("ACPI: bad RSDP signature");
+        return None;
+    }
+
+    let revision = rsdp.read::<u8>(15);
+
+    // ACPI 2.0+: use the XSDT (64 bits pointers), else the RSDT (32 bits pointers)
+    let (root, entry_size) = if revision >= 2 {
+        (PhysAddr::new(rsdp.read::<u64>(24)), size_of::<u64>())
+    } else {
+        (PhysAddr::new(rsdp.read::<u32>(16) as u64), size_of::<u32>())
+    };
+
+    let root = map_table(root)?;
+    let count = (root.len - SDT_HEADER_SIZE) / entry_size;
+
+    for index in 0..count {
+        let offset = SDT_HEADER_SIZE + index * entry_size;
+        let table = if entry_size == size_of::<u64>() {
+            PhysAddr::new(root.read::<u64>(offset))
+        } else {
+            PhysAddr::new(root.read::<u32>(offset) as u64)
+        };
+
+        let header = PhysView::new(table, SDT_HEADER_SIZE);
+        if header.bytes(0, 4) == signature {
+            return map_table(table);
+        }
+    }
+
+    None
+}
+
+/// Find the sleep type values in the `\_Sx_` package of the DSDT
+///
+/// This is not a full AML interpreter: the package is expected to be declared with constant values,
+/// which is what firmwares do (eg: `Name (_S3_, Package (0x04) { 0x01, 0x01, Zero, Zero })`).
+fn find_sleep_type(dsdt: &PhysView, name: &[u8; 4]) -> Option<(u8, u8)> {
+    const NAME_OP: u8 = 0x08;
+    const PACKAGE_OP: u8 = 0x12;
+    const BYTE_PREFIX: u8 = 0x0A;
+    const ZERO_OP: u8 = 0x00;
+    const ONE_OP: u8 = 0x01;
+
+    let aml = dsdt.bytes(SDT_HEADER_SIZE, dsdt.len - SDT_HEADER_SIZE);
+
+    let position = aml.windows(4).position(|window| window == name)?;
+
+    // NameOp, optionally preceded by a root prefix ('\')
+    let declared = (position >= 1 && aml[position - 1] == NAME_OP)
+        || (position >= 2 && aml[position - 1] == b'\\' && aml[position - 2] == NAME_OP);
+    if !declared {
+        warn!(
+            "ACPI: {} is not a name declaration",
+            core::str::from_utf8(name).unwrap()
+        );
+        return None;
+    }
+
+    let mut bytes = aml[position + 4..].iter().copied();
+    if bytes.next()? != PACKAGE_OP {
+        return None;
+    }
+
+    // PkgLength: the 2 high bits of the lead byte give the count of following bytes
+    let lead = bytes.next()?;
+    for _ in 0..(lead >> 6) {
+        bytes.next()?;
+    }
+
+    let _element_count = bytes.next()?;
+
+    let mut read_value = || match bytes.next()? {
+        BYTE_PREFIX => bytes.next(),
+        ZERO_OP => Some(0),
+        ONE_OP => Some(1),
+        _ => None,
+    };
+
+    let slp_typ_a = read_value()?;
+    let slp_typ_b = read_value()?;
+
+    Some((slp_typ_a, slp_typ_b))
+}
+
+fn parse_madt(table: &PhysView) -> Option<Madt> {
+    let flags = table.read::<u32>(SDT_HEADER_SIZE + 4);
+
+    let mut madt = Madt {
+        io_apics: Vec::new(),
+        overrides: Vec::new(),
+        has_legacy_pics: flags & MADT_FLAG_PCAT_COMPAT != 0,
+    };
+
+    let mut offset = MADT_ENTRIES_OFFSET;
+    while offset + 2 <= table.len {
+        let r#type = table.read::<u8>(offset);
+        let len = table.read::<u8>(offset + 1) as usize;
+        if len < 2 || offset + len > table.len {
+            warn!("ACPI: MADT corrupted (entry at offset {offset})");
+            return None;
+        }
+
+        match r#type {
+            MADT_ENTRY_IO_APIC => {
+                let entry = IoApicEntry {
+                    id: table.read::<u8>(offset + 2),
+                    address: PhysAddr::new(table.read::<u32>(offset + 4) as u64),
+                    gsi_base: table.read::<u32>(offset + 8),
+                };
+
+                debug!("ACPI: {entry:?}");
+                madt.io_apics.push(entry);
+            }
+            MADT_ENTRY_INTERRUPT_OVERRIDE => {
+                let flags = table.read::<u16>(offset + 8);
+                let entry = InterruptOverride {
+                    isa_irq: table.read::<u8>(offset + 3),
+                    gsi: table.read::<u32>(offset + 4),
+                    // 0b00 and 0b01 (bus default for ISA, active high), 0b11 (active low)
+                    polarity: if flags & 0b11 == 0b11 {
+                        Polarity::ActiveLow
+                    } else {
+                        Polarity::ActiveHigh
+                    },
+                    // 0b00 and 0b01 (bus default for ISA, edge), 0b11 (level)
+                    trigger: if (flags >> 2) & 0b11 == 0b11 {
+                        Trigger::Level
+                    } else {
+                        Trigger::Edge
+                    },
+                };
+
+                debug!("ACPI: {entry:?}");
+                madt.overrides.push(entry);
+            }
+            _ => {}
+        }
+
+        offset += len;
+    }
+
+    Some(madt)
+}
+
+/// Map a whole table, using the length in its header
+fn map_table(addr: PhysAddr) -> Option<PhysView> {
+    let len = PhysView::new(addr, SDT_HEADER_SIZE).read::<u32>(4) as usize;
+    if len < SDT_HEADER_SIZE {
+        warn!("ACPI: bad table length at {addr:?}");
+        return None;
+    }
+
+    let table = PhysView::new(addr, len);
+
+    let checksum = table
+        .bytes(0, len)
+        .iter()
+        .fold(0u8, |acc, &byte| acc.wrapping_add(byte));
+    if checksum != 0 {
+        warn!("ACPI: bad table checksum at {addr:?}");
+        return None;
+    }
+
+    Some(table)
+}
+
+/// Read-only view of physical memory, mapped into kernel space until dropped
+struct PhysView {
+    mapping: VirtAddr,
+    page_count: usize,
+    data: VirtAddr,
+    len: usize,
+}
+
+impl PhysView {
+    fn new(addr: PhysAddr, len: usize) -> Self {
+        let start = page_aligned_down(addr.as_u64() as usize);
+        let end = page_aligned_up(addr.as_u64() as usize + len);
+        let page_count = (end - start) / PAGE_SIZE;
+
+        let mapping = unsafe {
+            map_iomem(
+                PhysAddr::new(start as u64)..PhysAddr::new(end as u64),
+                Permissions::READ,
+            )
+        }
+        .expect("could not map ACPI table into kernel space");
+
+        Self {
+            mapping,
+            page_count,
+            data: mapping + (addr.as_u64() as usize - start),
+            len,
+        }
+    }
+
+    fn read<T: Copy>(&self, offset: usize) -> T {
+        assert!(offset + size_of::<T>() <= self.len);
+        unsafe { read_unaligned((self.data + offset).as_ptr()) }
+    }
+
+    fn bytes(&self, offset: usize, len: usize) -> &[u8] {
+        assert!(offset + len <= self.len);
+        unsafe { slice::from_raw_parts((self.data + offset).as_ptr(), len) }
+    }
+}
+
+impl Drop for PhysView {
+    fn drop(&mut self) {
+        unmap_iomem(self.mapping, self.page_count);
+    }
+}
@@ -1,6 +1,20 @@
 use lazy_static::lazy_static;
 use raw_cpuid::{CpuId, CpuIdReaderNative};
 
+use crate::interrupts;
+
+use super::local_apic;
+
 lazy_static! {
     pub static ref CPUID: CpuId<CpuIdReaderNative> = CpuId::new();
 }
+
+/// Tear down the state of the current CPU, before handing it off to a new kernel image (see `kexec`)
+///
+/// The devices must have been quiesced first (see `devices::quiesce`).
+///
+/// Note: only the bootstrap processor runs (no multi-core yet), there is no other CPU to stop.
+pub fn teardown() {
+    local_apic::disable();
+    interrupts::teardown();
+}
@@ -0,0 +1,57 @@
+use spin::Once;
+use syscalls::IdleMethod;
+
+use crate::logging::info;
+
+use super::cpu::CPUID;
+
+/// Idle policy, chosen at boot from the CPU features
+static POLICY: Once<(IdleMethod, u32)> = Once::new();
+
+/// Choose how the idle thread waits for interrupts
+///
+/// `mwait` is used with a hint for the deepest C-state enumerated by CPUID, if the CPU supports it.
+/// Else (eg: most VMs, which do not expose `mwait`), `hlt` is used.
+pub fn init() {
+    let (method, hint) = POLICY.call_once(choose);
+
+    info!("cpuidle: method={method:?}, mwait hint={hint:#X}");
+}
+
+/// Get the idle method, and the `mwait` hint
+pub fn policy() -> (IdleMethod, u32) {
+    *POLICY.get().expect("cpuidle not initialized")
+}
+
+fn choose() -> (IdleMethod, u32) {
+    let has_mwait = CPUID
+        .get_feature_info()
+        .map_or(false, |features| features.has_monitor_mwait());
+
+    let Some(info) = CPUID.get_monitor_mwait_info() else {
+        return (IdleMethod::Hlt, 0);
+    };
+
+    if !has_mwait || !info.extensions_supported() {
+        return (IdleMethod::Hlt, 0);
+    }
+
+    // Sub-states count of C1 to C7: the hint selects C-state (n - 1) in bits 4..8, and the sub-state in bits 0..4
+    let substates = [
+        info.supported_c1_states(),
+        info.supported_c2_states(),
+        info.supported_c3_states(),
+        info.supported_c4_states(),
+        info.supported_c5_states(),
+        info.supported_c6_states(),
+        info.supported_c7_states(),
+    ];
+
+    match substates.iter().rposition(|&count| count > 0) {
+        Some(index) => (
+            IdleMethod::Mwait,
+            ((index as u32) << 4) | (substates[index] as u32 - 1),
+        ),
+        None => (IdleMethod::Hlt, 0),
+    }
+}
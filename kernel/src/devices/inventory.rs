@@ -0,0 +1,160 @@
+use alloc::vec::Vec;
+use spin::RwLock;
+use syscalls::{DeviceBus, DeviceInfo, DeviceResource, DeviceResourceType};
+
+use crate::logging::{debug, info};
+
+use super::pci::{self, Bar};
+
+/// Hardware inventory, built at boot time from discovery
+static DEVICES: RwLock<Vec<DeviceInfo>> = RwLock::new(Vec::new());
+
+/// Resource of a platform device: type, start, length
+type PlatformResource = (DeviceResourceType, u64, u64);
+
+/// Legacy devices driven by the kernel
+const PLATFORM_DEVICES: &[(&[PlatformResource], &str)] = &[
+    (
+        &[
+            (DeviceResourceType::IoPort, 0x20, 2),
+            (DeviceResourceType::IoPort, 0xA0, 2),
+        ],
+        "pic8259",
+    ),
+    (
+        &[
+            (DeviceResourceType::IoPort, 0x40, 4),
+            (DeviceResourceType::Irq, 0, 1),
+        ],
+        "pit",
+    ),
+    (
+        &[
+            (DeviceResourceType::IoPort, 0x3F8, 8),
+            (DeviceResourceType::Irq, 4, 1),
+        ],
+        "serial",
+    ),
+];
+
+pub fn init() {
+    let mut devices = Vec::new();
+
+    for (index, &(resources, name)) in PLATFORM_DEVICES.iter().enumerate() {
+        let mut device = DeviceInfo {
+            bus: DeviceBus::Platform,
+            address: index as u64,
+            claimed_by: DeviceInfo::KERNEL_OWNER,
+            ..Default::default()
+        };
+
+        for (slot, &(r#type, start, len)) in resources.iter().enumerate() {
+            device.resources[slot] = DeviceResource { r#type, start, len };
+        }
+
+        debug!("Platform device: {}", name);
+        devices.push(device);
+    }
+
+    for function in pci::enumerate() {
+        let mut device = DeviceInfo {
+            bus: DeviceBus::Pci,
+            address: function.address.as_u64(),
+            vendor_id: function.vendor_id,
+            device_id: function.device_id,
+            class: function.class,
+            subclass: function.subclass,
+            prog_if: function.prog_if,
+            ..Default::default()
+        };
+
+        let mut slot = 0;
+        for bar in function.bars.iter().flatten() {
+            device.resources[slot] = match *bar {
+                Bar::IoPort { start, len } => DeviceResource {
+                    r#type: DeviceResourceType::IoPort,
+                    start,
+                    len,
+                },
+                Bar::Memory { start, len } => DeviceResource {
+                    r#type: DeviceResourceType::Memory,
+                    start,
+                    len,
+                },
+            };
+            slot += 1;
+        }
+
+        // BARs can use at most 6 slots, keep the irq if there is room left
+        if let Some(irq) = function.interrupt_line
+            && slot < DeviceInfo::RESOURCE_COUNT
+        {
+            device.resources[slot] = DeviceResource {
+                r#type: DeviceResourceType::Irq,
+                start: irq as u64,
+                len: 1,
+            };
+        }
+
+        debug!(
+            "PCI device {:02X}:{:02X}.{}: {:04X}:{:04X} class={:02X}:{:02X}",
+            function.address.bus,
+            function.address.device,
+            function.address.function,
+            function.vendor_id,
+            function.device_id,
+            function.class,
+            function.subclass
+        );
+        devices.push(device);
+    }
+
+    info!("Hardware inventory: {} devices", devices.len());
+
+    *DEVICES.write() = devices;
+}
+
+/// List all devices discovered
+pub fn list() -> Vec<DeviceInfo> {
+    DEVICES.read().clone()
+}
+
+/// Reason why a device cannot be claimed
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ClaimError {
+    /// There is no PCI device at this address
+    NotFound,
+    /// The device is driven by the kernel or by another living process
+    Claimed,
+}
+
+/// Claim a PCI device for the process `pid`, and enable bus mastering on it
+///
+/// A device driven by a process which is not alive anymore (according to `alive`) can be claimed again.
+pub fn claim(
+    address: u64,
+    pid: u64,
+    alive: impl Fn(u64) -> bool,
+) -> Result<DeviceInfo, ClaimError> {
+    let mut devices = DEVICES.write();
+
+    let device = devices
+        .iter_mut()
+        .find(|device| device.bus == DeviceBus::Pci && device.address == address)
+        .ok_or(ClaimError::NotFound)?;
+
+    let owner = device.claimed_by;
+    if owner == DeviceInfo::KERNEL_OWNER || (owner != 0 && owner != pid && alive(owner)) {
+        return Err(ClaimError::Claimed);
+    }
+
+    pci::enable_bus_master(pci::PciAddress::from_u64(address));
+    device.claimed_by = pid;
+
+    Ok(*device)
+}
+
+/// Check if the process drives at least one device
+pub fn drives_device(pid: u64) -> bool {
+    DEVICES.read().iter().any(|device| device.claimed_by == pid)
+}
@@ -0,0 +1,377 @@
+use core::{
+    ptr::{read_volatile, write_volatile},
+    sync::atomic::{AtomicU64, Ordering},
+};
+
+use alloc::vec::Vec;
+use bit_field::BitField;
+use spin::Mutex;
+
+use crate::logging::{debug, info, warn};
+use crate::{
+    interrupts::{ISA_IRQ0, ISA_IRQ_COUNT},
+    memory::{map_iomem, unmap_iomem, Permissions, PhysAddr, VirtAddr, PAGE_SIZE},
+};
+
+use super::{
+    acpi::{Madt, Polarity, Trigger},
+    local_apic,
+};
+
+mod registers {
+    /// Register selector (index of the register to access through the window)
+    pub const IOREGSEL: usize = 0x00;
+    /// Register window
+    pub const IOWIN: usize = 0x10;
+
+    pub const ID: u32 = 0x00;
+    pub const VERSION: u32 = 0x01;
+    /// Redirection table: 2 registers (low, high) per entry
+    pub const REDIRECTION_TABLE: u32 = 0x10;
+}
+
+/// IO APIC
+#[derive(Debug)]
+struct IoApic {
+    base_addr: VirtAddr,
+    gsi_base: u32,
+    entry_count: u32,
+}
+
+impl IoApic {
+    pub unsafe fn new(address: PhysAddr, gsi_base: u32) -> Self {
+        let base_addr = map_iomem(
+            address..address + PAGE_SIZE,
+            Permissions::READ | Permissions::WRITE,
+        )
+        .expect("could not map page into kernel space");
+
+        let mut apic = Self {
+            base_addr,
+            gsi_base,
+            entry_count: 0,
+        };
+
+        apic.entry_count = apic.version().max_redirection_entry() + 1;
+        apic
+    }
+
+    unsafe fn read(&self, reg: u32) -> u32 {
+        write_volatile((self.base_addr + registers::IOREGSEL).as_mut_ptr(), reg);
+        read_volatile((self.base_addr + registers::IOWIN).as_ptr())
+    }
+
+    unsafe fn write(&self, reg: u32, value: u32) {
+        write_volatile((self.base_addr + registers::IOREGSEL).as_mut_ptr(), reg);
+        write_volatile((self.base_addr + registers::IOWIN).as_mut_ptr(), value);
+    }
+
+    /// IO APIC ID
+    pub fn id(&self) -> u32 {
+        unsafe { self.read(registers::ID) }.get_bits(24..28)
+    }
+
+    /// IO APIC Version
+    pub fn version(&self) -> IoApicVersion {
+        IoApicVersion(unsafe { self.read(registers::VERSION) })
+    }
+
+    /// Test if the global system interrupt is handled by this IO APIC
+    pub fn handles(&self, gsi: u32) -> bool {
+        self.gsi_base <= gsi && gsi < self.gsi_base + self.entry_count
+    }
+
+    pub fn redirection_entry(&self, gsi: u32) -> RedirectionEntry {
+        let reg = self.entry_register(gsi);
+        let low = unsafe { self.read(reg) } as u64;
+        let high = unsafe { self.read(reg + 1) } as u64;
+        RedirectionEntry(low | (high << 32))
+    }
+
+    pub fn set_redirection_entry(&self, gsi: u32, value: RedirectionEntry) {
+        let reg = self.entry_register(gsi);
+        // Write the high part (destination) first: the entry may be unmasked by the low part
+        unsafe {
+            self.write(reg + 1, (value.0 >> 32) as u32);
+            self.write(reg, value.0 as u32);
+        }
+    }
+
+    fn entry_register(&self, gsi: u32) -> u32 {
+        assert!(self.handles(gsi));
+        registers::REDIRECTION_TABLE + (gsi - self.gsi_base) * 2
+    }
+}
+
+impl Drop for IoApic {
+    fn drop(&mut self) {
+        unmap_iomem(self.base_addr, 1);
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+struct IoApicVersion(u32);
+
+impl IoApicVersion {
+    pub fn version(&self) -> u32 {
+        self.0.get_bits(0..8)
+    }
+
+    pub fn max_redirection_entry(&self) -> u32 {
+        self.0.get_bits(16..24)
+    }
+}
+
+/// Entry of the redirection table
+///
+/// Delivery mode is always fixed, and destination mode physical.
+#[derive(Debug, Clone, Copy)]
+struct RedirectionEntry(u64);
+
+impl RedirectionEntry {
+    /// New masked entry
+    pub const fn new() -> Self {
+        Self(1 << 16)
+    }
+
+    pub fn vector(&self) -> u8 {
+        self.0.get_bits(0..8) as u8
+    }
+
+    pub fn set_vector(&mut self, value: u8) {
+        self.0.set_bits(0..8, value as u64);
+    }
+
+    pub fn polarity(&self) -> Polarity {
+        if self.0.get_bit(13) {
+            Polarity::ActiveLow
+        } else {
+            Polarity::ActiveHigh
+        }
+    }
+
+    pub fn set_polarity(&mut self, value: Polarity) {
+        self.0.set_bit(13, value == Polarity::ActiveLow);
+    }
+
+    pub fn trigger(&self) -> Trigger {
+        if self.0.get_bit(15) {
+            Trigger::Level
+        } else {
+            Trigger::Edge
+        }
+    }
+
+    pub fn set_trigger(&mut self, value: Trigger) {
+        self.0.set_bit(15, value == Trigger::Level);
+    }
+
+    pub fn masked(&self) -> bool {
+        self.0.get_bit(16)
+    }
+
+    pub fn mask(&mut self) {
+        self.0.set_bit(16, true);
+    }
+
+    pub fn unmask(&mut self) {
+        self.0.set_bit(16, false);
+    }
+
+    /// Local APIC ID of the destination processor
+    pub fn destination(&self) -> u8 {
+        self.0.get_bits(56..64) as u8
+    }
+
+    pub fn set_destination(&mut self, value: u8) {
+        self.0.set_bits(56..64, value as u64);
+    }
+}
+
+/// Route of an ISA interrupt, after ACPI overrides
+#[derive(Debug, Clone, Copy)]
+struct IsaRoute {
+    gsi: u32,
+    polarity: Polarity,
+    trigger: Trigger,
+}
+
+struct IoApics {
+    apics: Vec<IoApic>,
+    isa_routes: [Option<IsaRoute>; ISA_IRQ_COUNT as usize],
+}
+
+impl IoApics {
+    fn find(&self, gsi: u32) -> Option<&IoApic> {
+        self.apics.iter().find(|apic| apic.handles(gsi))
+    }
+}
+
+static IO_APICS: Mutex<IoApics> = Mutex::new(IoApics {
+    apics: Vec::new(),
+    isa_routes: [None; ISA_IRQ_COUNT as usize],
+});
+
+/// Number of interrupts received, per ISA irq
+static ISA_IRQ_COUNTS: [AtomicU64; ISA_IRQ_COUNT as usize] =
+    [const { AtomicU64::new(0) }; ISA_IRQ_COUNT as usize];
+
+/// Setup the IO APICs described by ACPI
+///
+/// All the entries are masked. ISA interrupts are routed (with their polarity and trigger mode from ACPI overrides)
+/// to the vectors `ISA_IRQ0 + irq` of the bootstrap processor, and stay masked until `set_isa_irq_masked` is called.
+pub fn init(madt: &Madt) {
+    let mut io_apics = IO_APICS.lock();
+
+    for entry in madt.io_apics.iter() {
+        let apic = unsafe { IoApic::new(entry.address, entry.gsi_base) };
+
+        info!(
+            "IO APIC: id={}, version={:#X}, GSI={}..{}",
+            apic.id(),
+            apic.version().version(),
+            apic.gsi_base,
+            apic.gsi_base + apic.entry_count
+        );
+
+        for gsi in apic.gsi_base..apic.gsi_base + apic.entry_count {
+            apic.set_redirection_entry(gsi, RedirectionEntry::new());
+        }
+
+        io_apics.apics.push(apic);
+    }
+
+    let destination = local_apic::id() as u8;
+
+    for isa_irq in 0..ISA_IRQ_COUNT {
+        // ISA default: identity mapped, active high, edge triggered
+        let route = match madt.overrides.iter().find(|entry| entry.isa_irq == isa_irq) {
+            Some(entry) => IsaRoute {
+                gsi: entry.gsi,
+                polarity: entry.polarity,
+                trigger: entry.trigger,
+            },
+            None => IsaRoute {
+                gsi: isa_irq as u32,
+                polarity: Polarity::ActiveHigh,
+                trigger: Trigger::Edge,
+            },
+        };
+
+        // eg: ISA irq 0 is usually overridden to GSI 2, so ISA irq 2 (cascade) has no line
+        if madt
+            .overrides
+            .iter()
+            .any(|entry| entry.gsi == route.gsi && entry.isa_irq != isa_irq)
+        {
+            debug!("IO APIC: ISA irq {isa_irq} not connected");
+            continue;
+        }
+
+        let Some(apic) = io_apics.find(route.gsi) else {
+            warn!(
+                "IO APIC: no IO APIC handles ISA irq {isa_irq} (GSI {})",
+                route.gsi
+            );
+            continue;
+        };
+
+        let mut entry = RedirectionEntry::new();
+        entry.set_vector(ISA_IRQ0 + isa_irq);
+        entry.set_polarity(route.polarity);
+        entry.set_trigger(route.trigger);
+        entry.set_destination(destination);
+        apic.set_redirection_entry(route.gsi, entry);
+
+        debug!("IO APIC: ISA irq {isa_irq} -> {route:?}");
+        io_apics.isa_routes[isa_irq as usize] = Some(route);
+    }
+}
+
+/// Test if interrupts are routed through IO APICs
+pub fn present() -> bool {
+    !IO_APICS.lock().apics.is_empty()
+}
+
+/// Mask or unmask an ISA interrupt
+///
+/// Returns false if the interrupt is not routed.
+pub fn set_isa_irq_masked(isa_irq: u8, masked: bool) -> bool {
+    let io_apics = IO_APICS.lock();
+
+    let Some(route) = io_apics.isa_routes.get(isa_irq as usize).copied().flatten() else {
+        return false;
+    };
+
+    let apic = io_apics.find(route.gsi).expect("ISA route without IO APIC");
+    let mut entry = apic.redirection_entry(route.gsi);
+    if masked {
+        entry.mask();
+    } else {
+        entry.unmask();
+    }
+    apic.set_redirection_entry(route.gsi, entry);
+
+    true
+}
+
+/// Mask all the entries, before handing off the machine
+pub fn quiesce() {
+    let io_apics = IO_APICS.lock();
+
+    for apic in io_apics.apics.iter() {
+        for gsi in apic.gsi_base..apic.gsi_base + apic.entry_count {
+            let mut entry = apic.redirection_entry(gsi);
+            entry.mask();
+            apic.set_redirection_entry(gsi, entry);
+        }
+    }
+}
+
+/// Account an ISA interrupt, and mask it
+///
+/// Its source must be serviced before it is unmasked again (level triggered lines would fire again immediately).
+pub fn isa_interrupt(isa_irq: u8) {
+    ISA_IRQ_COUNTS[isa_irq as usize].fetch_add(1, Ordering::Relaxed);
+
+    set_isa_irq_masked(isa_irq, true);
+}
+
+/// Number of interrupts received for an ISA irq
+pub fn isa_irq_count(isa_irq: u8) -> u64 {
+    ISA_IRQ_COUNTS[isa_irq as usize].load(Ordering::Relaxed)
+}
+
+/// Redirection tables of all the IO APICs, to be restored after a sleep state
+#[derive(Debug)]
+pub struct IoApicsState {
+    entries: Vec<Vec<RedirectionEntry>>,
+}
+
+/// Save the redirection tables
+pub fn save() -> IoApicsState {
+    let io_apics = IO_APICS.lock();
+
+    let entries = io_apics
+        .apics
+        .iter()
+        .map(|apic| {
+            (apic.gsi_base..apic.gsi_base + apic.entry_count)
+                .map(|gsi| apic.redirection_entry(gsi))
+                .collect()
+        })
+        .collect();
+
+    IoApicsState { entries }
+}
+
+/// Restore the redirection tables
+pub fn restore(state: &IoApicsState) {
+    let io_apics = IO_APICS.lock();
+
+    for (apic, entries) in io_apics.apics.iter().zip(state.entries.iter()) {
+        for (gsi, entry) in (apic.gsi_base..).zip(entries.iter()) {
+            apic.set_redirection_entry(gsi, *entry);
+        }
+    }
+}
@@ -4,6 +4,7 @@ use core::{
     ptr::{read_volatile, write_volatile},
 };
 
+use crate::logging::{debug, info};
 use crate::{
     interrupts::Irq,
     memory::{map_iomem, unmap_phys, Permissions, VirtAddr, PAGE_SIZE},
@@ -13,7 +14,6 @@ use super::cpu::CPUID;
 
 use super::pit;
 use bit_field::BitField;
-use log::{debug, info};
 use spin::Mutex;
 
 const FS_IN_SEC: usize = 1_000_000_000_000_000;
@@ -747,6 +747,13 @@ pub fn configure_timer() {
     apic.timer().configure(FS_IN_SEC / 100);
 }
 
+/// Get the Local APIC ID of the current processor
+pub fn id() -> usize {
+    let apic = LOCAL_APIC.lock();
+
+    apic.id().value()
+}
+
 /// Signal end of interrupt for Local APIC
 pub fn end_of_interrupt() {
     let apic = LOCAL_APIC.lock();
@@ -754,9 +761,70 @@ pub fn end_of_interrupt() {
     apic.end_of_interrupt();
 }
 
+/// Stop the timer, and mask its interrupt
+pub fn stop_timer() {
+    let apic = LOCAL_APIC.lock();
+
+    let mut lvt = apic.lvt_timer();
+    lvt.mask();
+    apic.set_lvt_timer(lvt);
+
+    // Initial count of 0 stops the timer
+    apic.timer().set_initial_count(0);
+}
+
+/// Software disable the Local APIC: all its local interrupts are masked
+///
+/// Used before handing off the CPU to a new kernel image, which enables it again at its initialization.
+pub fn disable() {
+    let apic = LOCAL_APIC.lock();
+
+    let mut siv = apic.spurious_interrupt_vector();
+    siv.software_disable();
+    apic.set_spurious_interrupt_vector(siv);
+}
+
 /// Get the current errors on Local APIC
 pub fn current_errors() -> LocalApicErrors {
     let apic = LOCAL_APIC.lock();
 
     apic.current_errors()
 }
+
+/// Local APIC state, to be restored after a sleep state (the APIC is reset by the firmware on resume)
+#[derive(Debug, Clone, Copy)]
+pub struct LocalApicState {
+    spurious_interrupt_vector: LocalApicSpuriousInterruptVector,
+    lvt_timer: LocalApicLVTTimer,
+    lvt_error: LocalApicLVTError,
+    timer_divider: usize,
+    timer_initial_count: u32,
+}
+
+/// Save the Local APIC state
+pub fn save() -> LocalApicState {
+    let apic = LOCAL_APIC.lock();
+
+    LocalApicState {
+        spurious_interrupt_vector: apic.spurious_interrupt_vector(),
+        lvt_timer: apic.lvt_timer(),
+        lvt_error: apic.lvt_error(),
+        timer_divider: apic.timer().divider(),
+        timer_initial_count: apic.timer().initial_count(),
+    }
+}
+
+/// Restore the Local APIC state
+///
+/// Note: the timer restarts a full period (the remaining count is not saved).
+pub fn restore(state: &LocalApicState) {
+    let apic = LOCAL_APIC.lock();
+
+    apic.set_spurious_interrupt_vector(state.spurious_interrupt_vector);
+    apic.set_lvt_error(state.lvt_error);
+    apic.set_lvt_timer(state.lvt_timer);
+
+    // Writing the initial count starts the timer: do it last
+    apic.timer().set_divider(state.timer_divider as u32);
+    apic.timer().set_initial_count(state.timer_initial_count);
+}
@@ -1,12 +1,50 @@
+pub mod acpi;
 pub mod cpu;
+pub mod cpuidle;
+pub mod inventory;
+pub mod io_apic;
 pub mod local_apic;
+pub mod pci;
 pub mod pic8259;
 pub mod pit;
+pub mod power;
+pub mod rtc;
 
-pub fn init() {
+use crate::logging::warn;
+use crate::memory::PhysAddr;
+
+/// Initialize the devices driven by the kernel
+///
+/// `rsdp` is the ACPI root pointer given by the bootloader (if any), used to find the IO APICs and the sleep support.
+pub fn init(rsdp: Option<PhysAddr>) {
+    // The legacy PICs are only used to be disabled: interrupts are routed through the IO APIC
     pic8259::init();
     pic8259::disable();
 
     local_apic::init();
     local_apic::configure_timer();
+
+    cpuidle::init();
+
+    match rsdp.and_then(acpi::find_madt) {
+        Some(madt) => io_apic::init(&madt),
+        None => {
+            warn!("No ACPI interrupt controllers description: device interrupts are not available")
+        }
+    }
+
+    power::init(rsdp);
+
+    inventory::init();
+}
+
+/// Quiesce the devices, before handing off the machine (eg: to a new kernel image, or to the firmware on power off).
+///
+/// Note: interrupts are disabled after this call: the caller must restore the devices state to enable them again (see `power::power_off`).
+pub fn quiesce() {
+    x86_64::instructions::interrupts::disable();
+
+    local_apic::stop_timer();
+    io_apic::quiesce();
+    pic8259::disable();
 }
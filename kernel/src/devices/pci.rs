@@ -0,0 +1,243 @@
+use alloc::vec::Vec;
+use bit_field::BitField;
+use spin::Mutex;
+use x86_64::instructions::port::Port;
+
+// from https://wiki.osdev.org/PCI
+
+const CONFIG_ADDRESS: u16 = 0xCF8;
+const CONFIG_DATA: u16 = 0xCFC;
+
+const BAR_COUNT: usize = 6;
+
+struct ConfigSpace {
+    address: Port<u32>,
+    data: Port<u32>,
+}
+
+impl ConfigSpace {
+    pub const fn new() -> Self {
+        Self {
+            address: Port::new(CONFIG_ADDRESS),
+            data: Port::new(CONFIG_DATA),
+        }
+    }
+
+    unsafe fn read(&mut self, address: PciAddress, offset: u8) -> u32 {
+        self.address.write(address.config_address(offset));
+        self.data.read()
+    }
+
+    unsafe fn write(&mut self, address: PciAddress, offset: u8, value: u32) {
+        self.address.write(address.config_address(offset));
+        self.data.write(value);
+    }
+}
+
+static CONFIG_SPACE: Mutex<ConfigSpace> = Mutex::new(ConfigSpace::new());
+
+/// Address of a PCI function
+#[derive(Debug, Clone, Copy)]
+pub struct PciAddress {
+    pub bus: u8,
+    pub device: u8,
+    pub function: u8,
+}
+
+impl PciAddress {
+    fn config_address(&self, offset: u8) -> u32 {
+        let mut value = 0u32;
+        value.set_bit(31, true);
+        value.set_bits(16..24, self.bus as u32);
+        value.set_bits(11..16, self.device as u32);
+        value.set_bits(8..11, self.function as u32);
+        value.set_bits(0..8, (offset & 0xFC) as u32);
+        value
+    }
+
+    /// Get the address as a single value: `bus << 16 | device << 8 | function`
+    pub fn as_u64(&self) -> u64 {
+        (self.bus as u64) << 16 | (self.device as u64) << 8 | self.function as u64
+    }
+
+    /// Build the address from its single value representation (see `as_u64`)
+    pub fn from_u64(value: u64) -> Self {
+        Self {
+            bus: value.get_bits(16..24) as u8,
+            device: value.get_bits(8..16) as u8,
+            function: value.get_bits(0..8) as u8,
+        }
+    }
+}
+
+/// Base address register
+#[derive(Debug, Clone, Copy)]
+pub enum Bar {
+    IoPort { start: u64, len: u64 },
+    Memory { start: u64, len: u64 },
+}
+
+/// Function discovered on the PCI bus
+#[derive(Debug, Clone)]
+pub struct PciFunction {
+    pub address: PciAddress,
+    pub vendor_id: u16,
+    pub device_id: u16,
+    pub class: u8,
+    pub subclass: u8,
+    pub prog_if: u8,
+    pub interrupt_line: Option<u8>,
+    pub bars: [Option<Bar>; BAR_COUNT],
+}
+
+/// Enumerate all functions on the PCI buses (brute force)
+pub fn enumerate() -> Vec<PciFunction> {
+    let mut functions = Vec::new();
+    let mut config = CONFIG_SPACE.lock();
+
+    for bus in 0..=255u8 {
+        for device in 0..32u8 {
+            let address = PciAddress {
+                bus,
+                device,
+                function: 0,
+            };
+
+            if unsafe { vendor_id(&mut config, address) } == 0xFFFF {
+                continue;
+            }
+
+            let header_type = unsafe { config.read(address, 0x0C) }.get_bits(16..24) as u8;
+            let function_count = if header_type.get_bit(7) { 8 } else { 1 };
+
+            for function in 0..function_count {
+                let address = PciAddress {
+                    bus,
+                    device,
+                    function,
+                };
+
+                if unsafe { vendor_id(&mut config, address) } == 0xFFFF {
+                    continue;
+                }
+
+                functions.push(unsafe { read_function(&mut config, address) });
+            }
+        }
+    }
+
+    functions
+}
+
+/// Enable decoding of io-ports and memory BARs, and bus mastering (DMA) on the function
+pub fn enable_bus_master(address: PciAddress) {
+    let mut config = CONFIG_SPACE.lock();
+
+    unsafe {
+        // Note: the high half is the status register, whose bits are cleared by writing 1: write 0 there
+        let mut command = config.read(address, 0x04).get_bits(0..16);
+        command.set_bit(0, true); // io space
+        command.set_bit(1, true); // memory space
+        command.set_bit(2, true); // bus master
+        config.write(address, 0x04, command);
+    }
+}
+
+unsafe fn vendor_id(config: &mut ConfigSpace, address: PciAddress) -> u16 {
+    config.read(address, 0x00).get_bits(0..16) as u16
+}
+
+unsafe fn read_function(config: &mut ConfigSpace, address: PciAddress) -> PciFunction {
+    let ids = config.read(address, 0x00);
+    let class = config.read(address, 0x08);
+    // Note: bit 7 is the multi-function flag
+    let header_type = config.read(address, 0x0C).get_bits(16..23) as u8;
+    let interrupt = config.read(address, 0x3C);
+
+    let mut bars = [None; BAR_COUNT];
+
+    // Only general devices have 6 BARs, bridges have 2
+    let bar_count = match header_type {
+        0x00 => 6,
+        0x01 => 2,
+        _ => 0,
+    };
+
+    let mut index = 0;
+    while index < bar_count {
+        let (bar, used) = read_bar(config, address, index);
+        bars[index] = bar;
+        index += used;
+    }
+
+    let interrupt_line = interrupt.get_bits(0..8) as u8;
+
+    PciFunction {
+        address,
+        vendor_id: ids.get_bits(0..16) as u16,
+        device_id: ids.get_bits(16..32) as u16,
+        class: class.get_bits(24..32) as u8,
+        subclass: class.get_bits(16..24) as u8,
+        prog_if: class.get_bits(8..16) as u8,
+        interrupt_line: if interrupt_line == 0xFF {
+            None
+        } else {
+            Some(interrupt_line)
+        },
+        bars,
+    }
+}
+
+/// Read the BAR at index, and returns the number of BAR slots used (64 bits BARs use 2 slots)
+unsafe fn read_bar(
+    config: &mut ConfigSpace,
+    address: PciAddress,
+    index: usize,
+) -> (Option<Bar>, usize) {
+    let offset = 0x10 + (index as u8) * 4;
+    let value = config.read(address, offset);
+
+    // Size is computed by writing all ones, then reading back the mask
+    config.write(address, offset, 0xFFFF_FFFF);
+    let mask = config.read(address, offset);
+    config.write(address, offset, value);
+
+    if value.get_bit(0) {
+        let start = (value & !0x3) as u64;
+        let len = (!(mask & !0x3)).wrapping_add(1) as u16 as u64;
+
+        if start == 0 || len == 0 {
+            return (None, 1);
+        }
+
+        return (Some(Bar::IoPort { start, len }), 1);
+    }
+
+    let is_64bits = value.get_bits(1..3) == 0x2;
+    let mut start = (value & !0xF) as u64;
+    let mut mask = (mask & !0xF) as u64;
+    let mut used = 1;
+
+    if is_64bits && index + 1 < BAR_COUNT {
+        let high_offset = offset + 4;
+        let high_value = config.read(address, high_offset);
+
+        config.write(address, high_offset, 0xFFFF_FFFF);
+        let high_mask = config.read(address, high_offset);
+        config.write(address, high_offset, high_value);
+
+        start |= (high_value as u64) << 32;
+        mask |= (high_mask as u64) << 32;
+        used = 2;
+    } else {
+        mask |= 0xFFFF_FFFF_0000_0000;
+    }
+
+    let len = (!mask).wrapping_add(1);
+
+    if start == 0 || mask == 0 {
+        return (None, used);
+    }
+
+    (Some(Bar::Memory { start, len }), used)
+}
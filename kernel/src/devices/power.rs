@@ -0,0 +1,145 @@
+use core::arch::asm;
+
+use spin::Once;
+use syscalls::SleepMode;
+use x86_64::instructions::{interrupts, port::Port};
+
+use crate::logging::{info, warn};
+use crate::memory::PhysAddr;
+
+use super::{
+    acpi::{self, SleepInfo},
+    io_apic, local_apic,
+};
+
+/// `SLP_EN` bit of the PM1 control registers: writing it enters the sleep state given by `SLP_TYP`
+const SLP_EN: u16 = 1 << 13;
+const SLP_TYP_SHIFT: u16 = 10;
+
+static SLEEP_INFO: Once<Option<SleepInfo>> = Once::new();
+
+/// Find the ACPI sleep support
+pub fn init(rsdp: Option<PhysAddr>) {
+    let sleep_info = SLEEP_INFO.call_once(|| rsdp.and_then(acpi::find_sleep_info));
+
+    match sleep_info {
+        Some(SleepInfo { s3: Some(_), .. }) => info!("power: S3 supported ({sleep_info:?})"),
+        _ => info!("power: S3 not supported"),
+    }
+
+    if !power_off_supported() {
+        info!("power: S5 not supported");
+    }
+}
+
+/// Test if a sleep mode can be entered
+pub fn supported(mode: SleepMode) -> bool {
+    match mode {
+        SleepMode::Test => true,
+        SleepMode::S3 => matches!(SLEEP_INFO.get(), Some(Some(SleepInfo { s3: Some(_), .. }))),
+    }
+}
+
+/// Enter a sleep state, and come back from it
+///
+/// The Local APIC and IO APIC state is saved, then the devices are quiesced (timer stopped, all interrupts masked).
+/// On return, the saved state is restored and interrupts are delivered again.
+///
+/// Userland is expected to have been frozen (processes suspended) by the caller, and its drivers quiesced.
+///
+/// Note: there is no firmware waking vector yet, so waking up from S3 goes through a normal firmware boot.
+pub fn suspend(mode: SleepMode) {
+    assert!(supported(mode));
+
+    interrupts::without_interrupts(|| {
+        let local_apic_state = local_apic::save();
+        let io_apics_state = io_apic::save();
+
+        local_apic::stop_timer();
+        io_apic::quiesce();
+
+        info!("power: entering {mode:?}");
+
+        match mode {
+            SleepMode::Test => {}
+            SleepMode::S3 => enter_s3(),
+        }
+
+        io_apic::restore(&io_apics_state);
+        local_apic::restore(&local_apic_state);
+
+        info!("power: resumed from {mode:?}");
+    });
+}
+
+fn enter_s3() {
+    let sleep_info = SLEEP_INFO
+        .get()
+        .copied()
+        .flatten()
+        .expect("no ACPI sleep info");
+    let (slp_typ_a, slp_typ_b) = sleep_info.s3.expect("S3 not supported");
+
+    // Caches are lost in S3
+    unsafe { asm!("wbinvd", options(nostack, preserves_flags)) };
+
+    unsafe {
+        write_pm1_control(sleep_info.pm1a_control, slp_typ_a);
+        if sleep_info.pm1b_control != 0 {
+            write_pm1_control(sleep_info.pm1b_control, slp_typ_b);
+        }
+    }
+
+    // The machine should be asleep now: if we get here, the sleep state was not entered
+    warn!("power: S3 not entered");
+}
+
+/// Test if the machine can be powered off
+pub fn power_off_supported() -> bool {
+    matches!(SLEEP_INFO.get(), Some(Some(SleepInfo { s5: Some(_), .. })))
+}
+
+/// Power off the machine (ACPI S5)
+///
+/// The devices are quiesced first (see `devices::quiesce`).
+/// Returns only if the machine could not be powered off: the devices are then restored.
+pub fn power_off() {
+    assert!(power_off_supported());
+
+    let sleep_info = SLEEP_INFO
+        .get()
+        .copied()
+        .flatten()
+        .expect("no ACPI sleep info");
+    let (slp_typ_a, slp_typ_b) = sleep_info.s5.expect("S5 not supported");
+
+    info!("power: powering off");
+
+    interrupts::without_interrupts(|| {
+        let local_apic_state = local_apic::save();
+        let io_apics_state = io_apic::save();
+
+        super::quiesce();
+
+        unsafe {
+            write_pm1_control(sleep_info.pm1a_control, slp_typ_a);
+            if sleep_info.pm1b_control != 0 {
+                write_pm1_control(sleep_info.pm1b_control, slp_typ_b);
+            }
+        }
+
+        warn!("power: S5 not entered");
+
+        io_apic::restore(&io_apics_state);
+        local_apic::restore(&local_apic_state);
+    });
+}
+
+unsafe fn write_pm1_control(port: u16, slp_typ: u8) {
+    let mut port = Port::<u16>::new(port);
+
+    let mut value = port.read();
+    value &= !(0b111 << SLP_TYP_SHIFT);
+    value |= ((slp_typ as u16) << SLP_TYP_SHIFT) | SLP_EN;
+    port.write(value);
+}
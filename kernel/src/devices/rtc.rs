@@ -0,0 +1,143 @@
+use bit_field::BitField;
+use x86_64::instructions::{interrupts, port::Port};
+
+use crate::logging::{info, warn};
+
+/*
+CMOS real-time clock (MC146818 compatible)
+
+Registers are selected through port 0x70, and accessed through port 0x71.
+Values are BCD or binary (status register B bit 2), hours are 12h or 24h (status register B bit 1, PM flag in hours bit 7).
+The clock is updated once a second: registers are not consistent while "update in progress" (status register A bit 7) is set.
+*/
+
+mod registers {
+    pub const SECONDS: u8 = 0x00;
+    pub const MINUTES: u8 = 0x02;
+    pub const HOURS: u8 = 0x04;
+    pub const DAY: u8 = 0x07;
+    pub const MONTH: u8 = 0x08;
+    pub const YEAR: u8 = 0x09;
+    pub const STATUS_A: u8 = 0x0A;
+    pub const STATUS_B: u8 = 0x0B;
+}
+
+/// The RTC has no reliable century register: assume 20xx
+const CENTURY: u64 = 2000;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct DateTime {
+    year: u64,
+    month: u64,
+    day: u64,
+    hours: u64,
+    minutes: u64,
+    seconds: u64,
+}
+
+/// Read the RTC, as seconds since the Unix epoch (UTC is assumed)
+///
+/// Returns None if the RTC gives an invalid date.
+pub fn read() -> Option<u64> {
+    let date_time = interrupts::without_interrupts(read_stable);
+
+    if !(1..=12).contains(&date_time.month)
+        || !(1..=31).contains(&date_time.day)
+        || date_time.hours > 23
+        || date_time.minutes > 59
+        || date_time.seconds > 59
+    {
+        warn!("RTC: invalid date {date_time:?}");
+        return None;
+    }
+
+    let timestamp = unix_timestamp(&date_time);
+
+    info!(
+        "RTC: {:04}-{:02}-{:02} {:02}:{:02}:{:02} (timestamp={timestamp})",
+        date_time.year,
+        date_time.month,
+        date_time.day,
+        date_time.hours,
+        date_time.minutes,
+        date_time.seconds
+    );
+
+    Some(timestamp)
+}
+
+/// Read the date until 2 consecutive reads match, so that we do not get a half updated value
+fn read_stable() -> DateTime {
+    let mut last = read_once();
+
+    loop {
+        let current = read_once();
+        if current == last {
+            return current;
+        }
+
+        last = current;
+    }
+}
+
+fn read_once() -> DateTime {
+    while read_register(registers::STATUS_A).get_bit(7) {}
+
+    let status_b = read_register(registers::STATUS_B);
+    let binary = status_b.get_bit(2);
+    let hours_24 = status_b.get_bit(1);
+
+    let decode = |value: u8| -> u64 {
+        if binary {
+            value as u64
+        } else {
+            ((value >> 4) * 10 + (value & 0x0F)) as u64
+        }
+    };
+
+    let raw_hours = read_register(registers::HOURS);
+    let mut hours = decode(raw_hours & 0x7F);
+    if !hours_24 {
+        // 12h mode: 12AM is 0h, 12PM is 12h
+        hours %= 12;
+        if raw_hours.get_bit(7) {
+            hours += 12;
+        }
+    }
+
+    DateTime {
+        year: CENTURY + decode(read_register(registers::YEAR)),
+        month: decode(read_register(registers::MONTH)),
+        day: decode(read_register(registers::DAY)),
+        hours,
+        minutes: decode(read_register(registers::MINUTES)),
+        seconds: decode(read_register(registers::SECONDS)),
+    }
+}
+
+fn read_register(reg: u8) -> u8 {
+    let mut address = Port::<u8>::new(0x70);
+    let mut data = Port::<u8>::new(0x71);
+
+    unsafe {
+        address.write(reg);
+        data.read()
+    }
+}
+
+fn unix_timestamp(date_time: &DateTime) -> u64 {
+    // Days from civil, from http://howardhinnant.github.io/date_algorithms.html (years from March)
+    let year = if date_time.month <= 2 {
+        date_time.year - 1
+    } else {
+        date_time.year
+    };
+    let era = year / 400;
+    let year_of_era = year - era * 400;
+    let month = (date_time.month + 9) % 12;
+    let day_of_year = (153 * month + 2) / 5 + date_time.day - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    let days = era * 146097 + day_of_era - 719468;
+
+    days * 86400 + date_time.hours * 3600 + date_time.minutes * 60 + date_time.seconds
+}
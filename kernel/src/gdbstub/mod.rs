@@ -0,0 +1,406 @@
+//! GDB remote stub, to debug the kernel itself
+//!
+//! Enabled by the `gdbstub` feature (there is no boot command line yet).
+//! It speaks the GDB remote serial protocol on COM2, and stops at init so that gdb can attach:
+//! `target remote <COM2 chardev>`
+//!
+//! Supported: registers, memory, software breakpoints, continue and single-step.
+
+mod packet;
+
+use alloc::vec::Vec;
+use lazy_static::lazy_static;
+use spin::Mutex;
+use uart_16550::SerialPort;
+use x86_64::registers::{
+    control::{Cr0, Cr0Flags},
+    rflags::RFlags,
+};
+
+use crate::logging::info;
+use crate::{
+    interrupts::InterruptStack,
+    memory::{current_permissions, Permissions, VirtAddr, PAGE_SIZE},
+};
+
+const COM2: u16 = 0x2F8;
+const BREAKPOINT_COUNT: usize = 32;
+const INT3: u8 = 0xCC;
+
+/// Stop reply: SIGTRAP
+const STOP_REPLY: &[u8] = b"S05";
+const OK: &[u8] = b"OK";
+/// EFAULT
+const ERROR_FAULT: &[u8] = b"E0e";
+/// EINVAL
+const ERROR_INVALID: &[u8] = b"E16";
+/// ENOSPC
+const ERROR_NO_SPACE: &[u8] = b"E1c";
+
+lazy_static! {
+    static ref STUB: Mutex<Stub> = Mutex::new(Stub::new());
+}
+
+/// Wait for gdb to attach
+pub fn init() {
+    info!("GDB stub: waiting for gdb on COM2");
+    x86_64::instructions::interrupts::int3();
+}
+
+/// Kernel mode breakpoint exception
+pub fn breakpoint(stack: &mut InterruptStack) {
+    let mut stub = STUB.lock();
+
+    // int3 is a trap: if it comes from one of our breakpoints, point back to it
+    let address = stack.iret.instruction_pointer - 1u64;
+    if stub.find_breakpoint(address).is_some() {
+        stack.iret.instruction_pointer = address;
+    }
+
+    stub.run(stack);
+}
+
+/// Kernel mode debug exception, after a single-step
+pub fn single_step(stack: &mut InterruptStack) {
+    let mut stub = STUB.lock();
+    stub.run(stack);
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Breakpoint {
+    address: VirtAddr,
+    original: u8,
+}
+
+enum Action {
+    Reply(Vec<u8>),
+    Resume,
+}
+
+struct Stub {
+    port: SerialPort,
+    breakpoints: [Option<Breakpoint>; BREAKPOINT_COUNT],
+    /// gdb is waiting for a stop reply
+    resumed: bool,
+}
+
+impl Stub {
+    fn new() -> Self {
+        let mut port = unsafe { SerialPort::new(COM2) };
+        port.init();
+
+        Self {
+            port,
+            breakpoints: [None; BREAKPOINT_COUNT],
+            resumed: false,
+        }
+    }
+
+    /// Process gdb commands until execution is resumed
+    fn run(&mut self, stack: &mut InterruptStack) {
+        if self.resumed {
+            self.resumed = false;
+            packet::send(&mut self.port, STOP_REPLY);
+        }
+
+        loop {
+            let command = packet::receive(&mut self.port);
+
+            match self.process(stack, &command) {
+                Action::Reply(reply) => packet::send(&mut self.port, &reply),
+                Action::Resume => {
+                    self.resumed = true;
+                    return;
+                }
+            }
+        }
+    }
+
+    fn process(&mut self, stack: &mut InterruptStack, command: &[u8]) -> Action {
+        let Some((&name, args)) = command.split_first() else {
+            return Action::Reply(Vec::new());
+        };
+
+        match name {
+            b'?' => Action::Reply(Vec::from(STOP_REPLY)),
+            b'g' => Action::Reply(read_registers(stack)),
+            b'G' => reply_status(write_registers(stack, args)),
+            b'm' => Action::Reply(self.read_memory(args).unwrap_or(Vec::from(ERROR_FAULT))),
+            b'M' => reply_status(self.write_memory(args)),
+            b'Z' => self.insert_breakpoint(args),
+            b'z' => self.remove_breakpoint(args),
+            b'c' => resume(stack, args, false),
+            b's' => resume(stack, args, true),
+            b'D' => {
+                self.detach(stack);
+                packet::send(&mut self.port, OK);
+                Action::Resume
+            }
+            b'k' => {
+                self.detach(stack);
+                Action::Resume
+            }
+            b'H' | b'T' => Action::Reply(Vec::from(OK)),
+            b'q' if args.starts_with(b"Supported") => Action::Reply(Vec::from(b"PacketSize=1000")),
+            b'q' if args.starts_with(b"Attached") => Action::Reply(Vec::from(b"1")),
+            _ => {
+                // Unsupported
+                Action::Reply(Vec::new())
+            }
+        }
+    }
+
+    fn find_breakpoint(&self, address: VirtAddr) -> Option<usize> {
+        self.breakpoints
+            .iter()
+            .position(|slot| slot.is_some_and(|breakpoint| breakpoint.address == address))
+    }
+
+    // Z0,addr,kind
+    fn insert_breakpoint(&mut self, args: &[u8]) -> Action {
+        let Some(address) = parse_breakpoint(args) else {
+            // Only software breakpoints are supported
+            return Action::Reply(Vec::new());
+        };
+
+        if self.find_breakpoint(address).is_some() {
+            return Action::Reply(Vec::from(OK));
+        }
+
+        let Some(slot) = self.breakpoints.iter().position(|slot| slot.is_none()) else {
+            return Action::Reply(Vec::from(ERROR_NO_SPACE));
+        };
+
+        let Some(original) = read_byte(address) else {
+            return Action::Reply(Vec::from(ERROR_FAULT));
+        };
+
+        write_bytes(address, &[INT3]);
+        self.breakpoints[slot] = Some(Breakpoint { address, original });
+
+        Action::Reply(Vec::from(OK))
+    }
+
+    // z0,addr,kind
+    fn remove_breakpoint(&mut self, args: &[u8]) -> Action {
+        let Some(address) = parse_breakpoint(args) else {
+            return Action::Reply(Vec::new());
+        };
+
+        if let Some(slot) = self.find_breakpoint(address) {
+            let breakpoint = self.breakpoints[slot].take().unwrap();
+            write_bytes(breakpoint.address, &[breakpoint.original]);
+        }
+
+        Action::Reply(Vec::from(OK))
+    }
+
+    fn detach(&mut self, stack: &mut InterruptStack) {
+        for slot in self.breakpoints.iter_mut() {
+            if let Some(breakpoint) = slot.take() {
+                write_bytes(breakpoint.address, &[breakpoint.original]);
+            }
+        }
+
+        set_single_step(stack, false);
+    }
+
+    // m addr,length
+    fn read_memory(&self, args: &[u8]) -> Option<Vec<u8>> {
+        let (address, len) = parse_address_len(args)?;
+
+        let mut reply = Vec::with_capacity(len * 2);
+        for offset in 0..len {
+            let byte = read_byte(address + offset as u64)?;
+            packet::push_hex_byte(&mut reply, byte);
+        }
+
+        Some(reply)
+    }
+
+    // M addr,length:XX...
+    fn write_memory(&self, args: &[u8]) -> Option<()> {
+        let separator = args.iter().position(|&byte| byte == b':')?;
+        let (address, len) = parse_address_len(&args[..separator])?;
+        let data = &args[separator + 1..];
+
+        if data.len() != len * 2 {
+            return None;
+        }
+
+        let mut bytes = Vec::with_capacity(len);
+        for digits in data.chunks(2) {
+            bytes.push(packet::parse_hex(digits)? as u8);
+        }
+
+        check_mapped(address, len)?;
+        write_bytes(address, &bytes);
+
+        Some(())
+    }
+}
+
+fn reply_status(status: Option<()>) -> Action {
+    match status {
+        Some(()) => Action::Reply(Vec::from(OK)),
+        None => Action::Reply(Vec::from(ERROR_INVALID)),
+    }
+}
+
+// c [addr] / s [addr]
+fn resume(stack: &mut InterruptStack, args: &[u8], step: bool) -> Action {
+    if !args.is_empty() {
+        let Some(address) = packet::parse_hex(args).and_then(|addr| VirtAddr::try_new(addr).ok())
+        else {
+            return Action::Reply(Vec::from(ERROR_INVALID));
+        };
+
+        stack.iret.instruction_pointer = address;
+    }
+
+    set_single_step(stack, step);
+    Action::Resume
+}
+
+fn set_single_step(stack: &mut InterruptStack, value: bool) {
+    let mut flags = RFlags::from_bits_retain(stack.iret.cpu_flags);
+    flags.set(RFlags::TRAP_FLAG, value);
+    stack.iret.cpu_flags = flags.bits();
+}
+
+/// Registers in gdb amd64 order: rax, rbx, rcx, rdx, rsi, rdi, rbp, rsp, r8-r15, rip (64 bits), then eflags, cs, ss (32 bits)
+fn registers(stack: &InterruptStack) -> [u64; 17] {
+    [
+        stack.scratch.rax as u64,
+        stack.preserved.rbx as u64,
+        stack.scratch.rcx as u64,
+        stack.scratch.rdx as u64,
+        stack.scratch.rsi as u64,
+        stack.scratch.rdi as u64,
+        stack.preserved.rbp as u64,
+        stack.iret.stack_pointer.as_u64(),
+        stack.scratch.r8 as u64,
+        stack.scratch.r9 as u64,
+        stack.scratch.r10 as u64,
+        stack.scratch.r11 as u64,
+        stack.preserved.r12 as u64,
+        stack.preserved.r13 as u64,
+        stack.preserved.r14 as u64,
+        stack.preserved.r15 as u64,
+        stack.iret.instruction_pointer.as_u64(),
+    ]
+}
+
+fn read_registers(stack: &InterruptStack) -> Vec<u8> {
+    let mut reply = Vec::new();
+
+    for value in registers(stack) {
+        packet::push_hex_le(&mut reply, value, 8);
+    }
+
+    packet::push_hex_le(&mut reply, stack.iret.cpu_flags, 4);
+    packet::push_hex_le(&mut reply, stack.iret.code_segment, 4);
+    packet::push_hex_le(&mut reply, stack.iret.stack_segment, 4);
+
+    reply
+}
+
+// Segments are left untouched
+fn write_registers(stack: &mut InterruptStack, args: &[u8]) -> Option<()> {
+    const REG_DIGITS: usize = 16;
+    const FLAGS_DIGITS: usize = 8;
+
+    let mut values = [0u64; 17];
+    for (index, value) in values.iter_mut().enumerate() {
+        let start = index * REG_DIGITS;
+        *value = packet::parse_hex_le(args.get(start..start + REG_DIGITS)?)?;
+    }
+
+    let start = values.len() * REG_DIGITS;
+    let flags = packet::parse_hex_le(args.get(start..start + FLAGS_DIGITS)?)?;
+
+    let stack_pointer = VirtAddr::try_new(values[7]).ok()?;
+    let instruction_pointer = VirtAddr::try_new(values[16]).ok()?;
+
+    stack.scratch.rax = values[0] as usize;
+    stack.preserved.rbx = values[1] as usize;
+    stack.scratch.rcx = values[2] as usize;
+    stack.scratch.rdx = values[3] as usize;
+    stack.scratch.rsi = values[4] as usize;
+    stack.scratch.rdi = values[5] as usize;
+    stack.preserved.rbp = values[6] as usize;
+    stack.iret.stack_pointer = stack_pointer;
+    stack.scratch.r8 = values[8] as usize;
+    stack.scratch.r9 = values[9] as usize;
+    stack.scratch.r10 = values[10] as usize;
+    stack.scratch.r11 = values[11] as usize;
+    stack.preserved.r12 = values[12] as usize;
+    stack.preserved.r13 = values[13] as usize;
+    stack.preserved.r14 = values[14] as usize;
+    stack.preserved.r15 = values[15] as usize;
+    stack.iret.instruction_pointer = instruction_pointer;
+    stack.iret.cpu_flags = flags;
+
+    Some(())
+}
+
+fn parse_address_len(args: &[u8]) -> Option<(VirtAddr, usize)> {
+    let separator = args.iter().position(|&byte| byte == b',')?;
+    let address = VirtAddr::try_new(packet::parse_hex(&args[..separator])?).ok()?;
+    let len = packet::parse_hex(&args[separator + 1..])? as usize;
+
+    Some((address, len))
+}
+
+// 0,addr,kind
+fn parse_breakpoint(args: &[u8]) -> Option<VirtAddr> {
+    let args = args.strip_prefix(b"0,")?;
+    let (address, _kind) = parse_address_len(args)?;
+    Some(address)
+}
+
+/// Check that all the pages of the range are mapped
+fn check_mapped(address: VirtAddr, len: usize) -> Option<()> {
+    if len == 0 {
+        return Some(());
+    }
+
+    let start = address.align_down(PAGE_SIZE as u64);
+    let end = (address + (len - 1) as u64).align_down(PAGE_SIZE as u64);
+
+    let mut page = start;
+    loop {
+        if !current_permissions(page).contains(Permissions::READ) {
+            return None;
+        }
+
+        if page == end {
+            return Some(());
+        }
+
+        page += PAGE_SIZE as u64;
+    }
+}
+
+fn read_byte(address: VirtAddr) -> Option<u8> {
+    check_mapped(address, 1)?;
+    Some(unsafe { address.as_ptr::<u8>().read_volatile() })
+}
+
+/// Write memory, even if it is read-only (eg: kernel code)
+///
+/// Note: the range must be mapped
+fn write_bytes(address: VirtAddr, data: &[u8]) {
+    let flags = Cr0::read();
+
+    unsafe {
+        Cr0::write(flags - Cr0Flags::WRITE_PROTECT);
+
+        let ptr = address.as_mut_ptr::<u8>();
+        for (index, byte) in data.iter().enumerate() {
+            ptr.add(index).write_volatile(*byte);
+        }
+
+        Cr0::write(flags);
+    }
+}
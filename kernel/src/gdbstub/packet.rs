@@ -0,0 +1,106 @@
+use alloc::vec::Vec;
+use uart_16550::SerialPort;
+
+const PACKET_START: u8 = b'$';
+const PACKET_END: u8 = b'#';
+const ACK: u8 = b'+';
+const NACK: u8 = b'-';
+
+/// Block until a valid packet is received, and acknowledge it
+pub fn receive(port: &mut SerialPort) -> Vec<u8> {
+    loop {
+        // Skip everything until packet start (acks, interrupt requests)
+        while port.receive() != PACKET_START {}
+
+        let mut data = Vec::new();
+        let mut checksum: u8 = 0;
+
+        loop {
+            let byte = port.receive();
+            if byte == PACKET_END {
+                break;
+            }
+
+            checksum = checksum.wrapping_add(byte);
+            data.push(byte);
+        }
+
+        let expected = [port.receive(), port.receive()];
+
+        if parse_hex(&expected) == Some(checksum as u64) {
+            port.send_raw(ACK);
+            return data;
+        }
+
+        port.send_raw(NACK);
+    }
+}
+
+/// Send a packet, until it is acknowledged
+pub fn send(port: &mut SerialPort, data: &[u8]) {
+    let checksum = data.iter().fold(0u8, |acc, byte| acc.wrapping_add(*byte));
+
+    loop {
+        port.send_raw(PACKET_START);
+        for byte in data {
+            port.send_raw(*byte);
+        }
+        port.send_raw(PACKET_END);
+        port.send_raw(hex_digit(checksum >> 4));
+        port.send_raw(hex_digit(checksum & 0xF));
+
+        if port.receive() == ACK {
+            return;
+        }
+    }
+}
+
+/// Append the value as little-endian hex bytes, as used by registers and memory
+pub fn push_hex_le(buffer: &mut Vec<u8>, value: u64, size: usize) {
+    for byte in value.to_le_bytes().iter().take(size) {
+        push_hex_byte(buffer, *byte);
+    }
+}
+
+pub fn push_hex_byte(buffer: &mut Vec<u8>, byte: u8) {
+    buffer.push(hex_digit(byte >> 4));
+    buffer.push(hex_digit(byte & 0xF));
+}
+
+/// Parse a big-endian hex number, as used by addresses and lengths
+pub fn parse_hex(data: &[u8]) -> Option<u64> {
+    if data.is_empty() || data.len() > 16 {
+        return None;
+    }
+
+    data.iter().try_fold(0u64, |acc, digit| {
+        Some((acc << 4) | hex_value(*digit)? as u64)
+    })
+}
+
+/// Parse little-endian hex bytes, as used by registers
+pub fn parse_hex_le(data: &[u8]) -> Option<u64> {
+    if data.len() % 2 != 0 || data.len() > 16 {
+        return None;
+    }
+
+    let mut value = 0u64;
+    for (index, byte) in data.chunks(2).enumerate() {
+        value |= (parse_hex(byte)?) << (index * 8);
+    }
+
+    Some(value)
+}
+
+fn hex_digit(value: u8) -> u8 {
+    b"0123456789abcdef"[value as usize]
+}
+
+fn hex_value(digit: u8) -> Option<u8> {
+    match digit {
+        b'0'..=b'9' => Some(digit - b'0'),
+        b'a'..=b'f' => Some(digit - b'a' + 10),
+        b'A'..=b'F' => Some(digit - b'A' + 10),
+        _ => None,
+    }
+}
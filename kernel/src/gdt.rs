@@ -1,3 +1,7 @@
+use core::cell::UnsafeCell;
+use core::mem::size_of;
+use core::ops::Range;
+
 use crate::interrupts::InterruptStack;
 use crate::memory::KernelStack;
 use lazy_static::lazy_static;
@@ -23,8 +27,25 @@ pub const USER_CODE_SELECTOR: SegmentSelector =
 
 static mut FATAL_FAULT_STACK: KernelStack = KernelStack::new();
 
+/// Size of the I/O permission bitmap: one bit per io-port
+const IOMAP_SIZE: usize = 0x10000 / 8;
+
+/// TSS, followed by its I/O permission bitmap
+///
+/// A set bit denies the io-port to userland: all are denied, except the ones granted to the current process
+/// (loaded on process switch, see `user::thread::context_switch`).
+#[repr(C)]
+struct Tss {
+    tss: TaskStateSegment,
+    /// Note: the CPU may read one byte past the bitmap, which must have all its bits set
+    iomap: UnsafeCell<[u8; IOMAP_SIZE + 1]>,
+}
+
+// Note: the bitmap is only updated by the kernel, which runs on a single CPU and is not preemptible
+unsafe impl Sync for Tss {}
+
 lazy_static! {
-    static ref TSS: TaskStateSegment = {
+    static ref TSS: Tss = {
         let mut tss = TaskStateSegment::new();
 
         tss.interrupt_stack_table[FATAL_FAULT_IST_INDEX as usize] = {
@@ -37,7 +58,12 @@ lazy_static! {
             unsafe { InterruptStack::interrupt_stack_top() }
         };
 
-        tss
+        tss.iomap_base = size_of::<TaskStateSegment>() as u16;
+
+        Tss {
+            tss,
+            iomap: UnsafeCell::new([0xFF; IOMAP_SIZE + 1]),
+        }
     };
 }
 
@@ -49,7 +75,7 @@ lazy_static! {
             kernel_data_selector: gdt.add_entry(Descriptor::kernel_data_segment()),
             user_data_selector: gdt.add_entry(Descriptor::user_data_segment()),
             user_code_selector: gdt.add_entry(Descriptor::user_code_segment()),
-            tss_selector: gdt.add_entry(Descriptor::tss_segment(&TSS)),
+            tss_selector: gdt.add_entry(tss_segment(&TSS)),
         };
 
         assert!(selectors.kernel_code_selector.index() == KERNEL_CODE_SELECTOR_INDEX);
@@ -61,6 +87,41 @@ lazy_static! {
     };
 }
 
+/// Build the TSS descriptor
+///
+/// Same as `Descriptor::tss_segment`, with a limit which includes the I/O permission bitmap
+fn tss_segment(tss: &'static Tss) -> Descriptor {
+    let ptr = tss as *const _ as u64;
+    let limit = (size_of::<Tss>() - 1) as u64;
+
+    // present, type 0b1001 (available 64-bit TSS)
+    let mut low = (1 << 47) | (0b1001 << 40);
+    // base
+    low |= (ptr & 0xFF_FFFF) << 16;
+    low |= ((ptr >> 24) & 0xFF) << 56;
+    // limit
+    low |= limit & 0xFFFF;
+    low |= ((limit >> 16) & 0xF) << 48;
+
+    let high = ptr >> 32;
+
+    Descriptor::SystemSegment(low, high)
+}
+
+/// Allow or deny userland access to a range of io-ports, on the current CPU
+pub fn set_io_ports(range: Range<u16>, allowed: bool) {
+    let iomap = unsafe { &mut *TSS.iomap.get() };
+
+    for port in range {
+        let (byte, bit) = (port as usize / 8, port % 8);
+        if allowed {
+            iomap[byte] &= !(1 << bit);
+        } else {
+            iomap[byte] |= 1 << bit;
+        }
+    }
+}
+
 struct Selectors {
     kernel_code_selector: SegmentSelector,
     kernel_data_selector: SegmentSelector,
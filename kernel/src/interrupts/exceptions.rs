@@ -1,8 +1,11 @@
 use x86_64::structures::{gdt::SegmentSelector, idt::PageFaultErrorCode};
 
-use crate::{gdt, user::thread::thread_error};
+use crate::{
+    gdt,
+    user::thread::{current_thread, thread_error},
+};
 
-use super::InterruptStack;
+use super::{debug_status_take, InterruptStack};
 pub use syscalls::Exception;
 
 pub fn divide_error_handler(stack: &mut InterruptStack) {
@@ -10,17 +13,37 @@ pub fn divide_error_handler(stack: &mut InterruptStack) {
         panic!("EXCEPTION: DIVIDE ERROR\n{:#?}", stack);
     }
 
-    thread_error(Exception::DivideError);
+    thread_error(Exception::DivideError, stack.iret.instruction_pointer);
 }
 
 pub fn debug_handler(stack: &mut InterruptStack) {
+    let status = debug_status_take();
+
     if !is_userland(stack) {
-        panic!("EXCEPTION: DEBUG\n{:#?}", stack);
+        // Watchpoints of the current thread also trigger when the kernel accesses the memory on its behalf.
+        // Data watchpoints are traps, so the access is already done: just continue.
+        if status & WATCHPOINTS_STATUS_MASK != 0 {
+            return;
+        }
+
+        #[cfg(feature = "gdbstub")]
+        if status & SINGLE_STEP_STATUS != 0 {
+            return crate::gdbstub::single_step(stack);
+        }
+
+        panic!("EXCEPTION: DEBUG (status=0x{:X})\n{:#?}", status, stack);
     }
 
-    thread_error(Exception::Debug);
+    thread_error(Exception::Debug(status), stack.iret.instruction_pointer);
 }
 
+/// DR6 bits B0-B3: watchpoint triggered
+const WATCHPOINTS_STATUS_MASK: usize = 0xF;
+
+/// DR6 bit BS: single-step
+#[cfg(feature = "gdbstub")]
+const SINGLE_STEP_STATUS: usize = 1 << 14;
+
 pub fn non_maskable_interrupt_handler(stack: &mut InterruptStack) {
     // An non maskable interrupt exception (NMI) occurs as a result of system logic
     // signaling a non-maskable interrupt to the processor.
@@ -33,10 +56,14 @@ pub fn non_maskable_interrupt_handler(stack: &mut InterruptStack) {
 
 pub fn breakpoint_handler(stack: &mut InterruptStack) {
     if !is_userland(stack) {
+        #[cfg(feature = "gdbstub")]
+        return crate::gdbstub::breakpoint(stack);
+
+        #[cfg(not(feature = "gdbstub"))]
         panic!("EXCEPTION: BREAKPOINT\n{:#?}", stack);
     }
 
-    thread_error(Exception::Breakpoint);
+    thread_error(Exception::Breakpoint, stack.iret.instruction_pointer);
 }
 
 pub fn overflow_handler(stack: &mut InterruptStack) {
@@ -44,7 +71,7 @@ pub fn overflow_handler(stack: &mut InterruptStack) {
         panic!("EXCEPTION: OVERFLOW\n{:#?}", stack);
     }
 
-    thread_error(Exception::Overflow);
+    thread_error(Exception::Overflow, stack.iret.instruction_pointer);
 }
 
 pub fn bound_range_exceeded_handler(stack: &mut InterruptStack) {
@@ -52,7 +79,10 @@ pub fn bound_range_exceeded_handler(stack: &mut InterruptStack) {
         panic!("EXCEPTION: BOUND RANGE EXCEEDED\n{:#?}", stack);
     }
 
-    thread_error(Exception::BoundRangeExceeded);
+    thread_error(
+        Exception::BoundRangeExceeded,
+        stack.iret.instruction_pointer,
+    );
 }
 
 pub fn invalid_opcode_handler(stack: &mut InterruptStack) {
@@ -60,7 +90,7 @@ pub fn invalid_opcode_handler(stack: &mut InterruptStack) {
         panic!("EXCEPTION: INVALID OPCODE\n{:#?}", stack);
     }
 
-    thread_error(Exception::InvalidOpcode);
+    thread_error(Exception::InvalidOpcode, stack.iret.instruction_pointer);
 }
 
 pub fn device_not_available_handler(stack: &mut InterruptStack) {
@@ -68,7 +98,10 @@ pub fn device_not_available_handler(stack: &mut InterruptStack) {
         panic!("EXCEPTION: DEVICE NOT AVAILABLE\n{:#?}", stack);
     }
 
-    thread_error(Exception::DeviceNotAvailable);
+    thread_error(
+        Exception::DeviceNotAvailable,
+        stack.iret.instruction_pointer,
+    );
 }
 
 pub fn double_fault_handler(stack: &mut InterruptStack) -> ! {
@@ -94,7 +127,10 @@ pub fn stack_segment_fault_handler(stack: &mut InterruptStack) {
         );
     }
 
-    thread_error(Exception::StackSegmentFault(stack.error_code));
+    thread_error(
+        Exception::StackSegmentFault(stack.error_code),
+        stack.iret.instruction_pointer,
+    );
 }
 
 pub fn general_protection_fault_handler(stack: &mut InterruptStack) {
@@ -105,7 +141,10 @@ pub fn general_protection_fault_handler(stack: &mut InterruptStack) {
         );
     }
 
-    thread_error(Exception::GeneralProtectionFault(stack.error_code));
+    thread_error(
+        Exception::GeneralProtectionFault(stack.error_code),
+        stack.iret.instruction_pointer,
+    );
 }
 
 pub fn page_fault_handler(stack: &mut InterruptStack) {
@@ -125,10 +164,18 @@ pub fn page_fault_handler(stack: &mut InterruptStack) {
         );
     }
 
-    thread_error(Exception::PageFault(
-        stack.error_code,
-        accessed_address.as_u64() as usize,
-    ));
+    // Access to a page not mapped yet of a growable stack: commit it and retry the access
+    let error_code = PageFaultErrorCode::from_bits_retain(stack.error_code as u64);
+    if !error_code.contains(PageFaultErrorCode::PROTECTION_VIOLATION)
+        && current_thread().process().grow_stack(accessed_address)
+    {
+        return;
+    }
+
+    thread_error(
+        Exception::PageFault(stack.error_code, accessed_address.as_u64() as usize),
+        stack.iret.instruction_pointer,
+    );
 }
 
 pub fn x87_floating_point_handler(stack: &mut InterruptStack) {
@@ -136,7 +183,7 @@ pub fn x87_floating_point_handler(stack: &mut InterruptStack) {
         panic!("EXCEPTION: DEVICE NOT AVAILABLE\n{:#?}", stack);
     }
 
-    thread_error(Exception::X87FloatingPoint);
+    thread_error(Exception::X87FloatingPoint, stack.iret.instruction_pointer);
 }
 
 pub fn alignment_check_handler(stack: &mut InterruptStack) {
@@ -144,7 +191,7 @@ pub fn alignment_check_handler(stack: &mut InterruptStack) {
         panic!("EXCEPTION: DEVICE NOT AVAILABLE\n{:#?}", stack);
     }
 
-    thread_error(Exception::AlignmentCheck);
+    thread_error(Exception::AlignmentCheck, stack.iret.instruction_pointer);
 }
 
 pub fn machine_check_handler(stack: &mut InterruptStack) -> ! {
@@ -156,7 +203,7 @@ pub fn simd_floating_point_handler(stack: &mut InterruptStack) {
         panic!("EXCEPTION: SIMD FLOATING POINT\n{:#?}", stack);
     }
 
-    thread_error(Exception::SimdFloatingPoint);
+    thread_error(Exception::SimdFloatingPoint, stack.iret.instruction_pointer);
 }
 
 pub fn virtualization_handler(stack: &mut InterruptStack) {
@@ -171,7 +218,10 @@ pub fn cp_protection_exception_handler(stack: &mut InterruptStack) {
         );
     }
 
-    thread_error(Exception::CpProtectionException(stack.error_code));
+    thread_error(
+        Exception::CpProtectionException(stack.error_code),
+        stack.iret.instruction_pointer,
+    );
 }
 
 pub fn hv_injection_exception_handler(stack: &mut InterruptStack) {
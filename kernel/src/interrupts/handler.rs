@@ -4,9 +4,9 @@
 
 use core::{fmt, mem::size_of};
 
-use log::debug;
 use x86_64::{registers::model_specific::KernelGsBase, structures::idt::InterruptStackFrameValue};
 
+use crate::logging::debug;
 use crate::memory::{KernelStack, VirtAddr};
 
 #[derive(Clone, Copy, Debug)]
@@ -1,9 +1,19 @@
-use log::error;
+use syscalls::ThreadPriority;
 
-use crate::{devices, interrupts::InterruptStack, user::thread};
+use crate::logging::error;
+use crate::{
+    devices,
+    interrupts::InterruptStack,
+    memory,
+    user::{thread, timer},
+};
 
 pub const IRQ0: u8 = 32;
 
+/// Vector of ISA irq 0, when routed through the IO APIC (see `devices::io_apic`)
+pub const ISA_IRQ0: u8 = IRQ0 + 16;
+pub const ISA_IRQ_COUNT: u8 = 16;
+
 #[derive(Debug, Clone, Copy)]
 #[repr(u8)]
 pub enum Irq {
@@ -14,6 +24,17 @@ pub enum Irq {
 pub fn lapic_timer_interrupt_handler(_stack: &mut InterruptStack) {
     let _userland_timer = thread::UserlandTimerInterruptScope::new();
 
+    // Nothing else to run: use idle time to prepare zeroed frames
+    if thread::current_thread().priority() == ThreadPriority::Idle {
+        memory::phys_scrub();
+    }
+
+    // With a virtual clock, time is advanced by the `ClockAdvance` syscall instead
+    if !cfg!(feature = "virtual-clock") {
+        timer::tick();
+    }
+    thread::load_sample();
+
     thread::thread_next();
 
     devices::local_apic::end_of_interrupt();
@@ -27,3 +48,38 @@ pub fn lapic_error_interrupt_handler(_stack: &mut InterruptStack) {
 
     devices::local_apic::end_of_interrupt();
 }
+
+fn isa_interrupt_handler(isa_irq: u8) {
+    devices::io_apic::isa_interrupt(isa_irq);
+
+    devices::local_apic::end_of_interrupt();
+}
+
+macro_rules! isa_interrupt_handlers {
+    ($($name:ident = $isa_irq:expr),* $(,)?) => {
+        $(
+            pub fn $name(_stack: &mut InterruptStack) {
+                isa_interrupt_handler($isa_irq);
+            }
+        )*
+    };
+}
+
+isa_interrupt_handlers!(
+    isa_irq0_handler = 0,
+    isa_irq1_handler = 1,
+    isa_irq2_handler = 2,
+    isa_irq3_handler = 3,
+    isa_irq4_handler = 4,
+    isa_irq5_handler = 5,
+    isa_irq6_handler = 6,
+    isa_irq7_handler = 7,
+    isa_irq8_handler = 8,
+    isa_irq9_handler = 9,
+    isa_irq10_handler = 10,
+    isa_irq11_handler = 11,
+    isa_irq12_handler = 12,
+    isa_irq13_handler = 13,
+    isa_irq14_handler = 14,
+    isa_irq15_handler = 15,
+);
@@ -9,7 +9,11 @@ use core::arch::asm;
 use crate::gdt;
 use crate::memory::VirtAddr;
 use lazy_static::lazy_static;
-use x86_64::registers::model_specific::FsBase;
+use x86_64::registers::debug::{
+    BreakpointCondition, BreakpointSize, DebugAddressRegister, DebugAddressRegisterNumber, Dr0,
+    Dr1, Dr2, Dr3, Dr6, Dr7, Dr7Flags, Dr7Value,
+};
+use x86_64::registers::model_specific::{FsBase, GsBase, KernelGsBase};
 use x86_64::{registers::rflags::RFlags, structures::idt::InterruptDescriptorTable};
 
 use self::handler::init_process_control_region;
@@ -17,7 +21,7 @@ use self::handler::init_process_control_region;
 pub const USERLAND_RFLAGS: RFlags = RFlags::INTERRUPT_FLAG;
 pub use self::exceptions::Exception;
 pub use self::handler::InterruptStack;
-pub use self::irqs::Irq;
+pub use self::irqs::{Irq, ISA_IRQ0, ISA_IRQ_COUNT};
 pub use self::syscalls::SyscallArgs;
 
 // Note:
@@ -156,6 +160,36 @@ lazy_static! {
                 .set_handler_addr(native_handler!(irqs::lapic_error_interrupt_handler))
                 .set_stack_index(gdt::INTERRUPT_IST_INDEX)
                 .set_privilege_level(x86_64::PrivilegeLevel::Ring3);
+
+            macro_rules! isa_irq_entries {
+                ($($isa_irq:expr => $handler:ident),* $(,)?) => {
+                    $(
+                        idt[(ISA_IRQ0 + $isa_irq) as usize]
+                            .set_handler_addr(native_handler!(irqs::$handler))
+                            .set_stack_index(gdt::INTERRUPT_IST_INDEX)
+                            .set_privilege_level(x86_64::PrivilegeLevel::Ring3);
+                    )*
+                };
+            }
+
+            isa_irq_entries!(
+                0 => isa_irq0_handler,
+                1 => isa_irq1_handler,
+                2 => isa_irq2_handler,
+                3 => isa_irq3_handler,
+                4 => isa_irq4_handler,
+                5 => isa_irq5_handler,
+                6 => isa_irq6_handler,
+                7 => isa_irq7_handler,
+                8 => isa_irq8_handler,
+                9 => isa_irq9_handler,
+                10 => isa_irq10_handler,
+                11 => isa_irq11_handler,
+                12 => isa_irq12_handler,
+                13 => isa_irq13_handler,
+                14 => isa_irq14_handler,
+                15 => isa_irq15_handler,
+            );
             }
 
         idt
@@ -172,6 +206,20 @@ pub fn init_userland() {
     syscalls::init();
 }
 
+/// Reset the per-CPU registers set up for userland: syscall entry, processor control region, TLS, watchpoints
+///
+/// Used before handing off the CPU to a new kernel image (see `devices::cpu::teardown`), which must not inherit them.
+pub fn teardown() {
+    watchpoints_write(&[None; ::syscalls::WATCHPOINT_COUNT]);
+    debug_status_take();
+
+    syscalls::teardown();
+
+    KernelGsBase::write(VirtAddr::zero());
+    GsBase::write(VirtAddr::zero());
+    FsBase::write(VirtAddr::zero());
+}
+
 pub fn tls_reg_read() -> VirtAddr {
     FsBase::read()
 }
@@ -180,6 +228,65 @@ pub fn tls_reg_write(addr: VirtAddr) {
     FsBase::write(addr);
 }
 
+/// Hardware watchpoint, as set in debug registers
+#[derive(Debug, Clone, Copy)]
+pub struct Watchpoint {
+    pub address: VirtAddr,
+    pub size: usize,
+    pub kind: ::syscalls::WatchpointKind,
+}
+
+/// Setup debug registers with the given watchpoints
+///
+/// Note: DR7 is always written, so that watchpoints of the previous thread do not remain
+pub fn watchpoints_write(watchpoints: &[Option<Watchpoint>; ::syscalls::WATCHPOINT_COUNT]) {
+    let mut dr7 = Dr7Value::from(Dr7Flags::empty());
+
+    for (index, watchpoint) in watchpoints.iter().enumerate() {
+        let Some(watchpoint) = watchpoint else {
+            continue;
+        };
+
+        let number = DebugAddressRegisterNumber::new(index as u8).expect("bad watchpoint index");
+        let address = watchpoint.address.as_u64();
+
+        match number {
+            DebugAddressRegisterNumber::Dr0 => Dr0::write(address),
+            DebugAddressRegisterNumber::Dr1 => Dr1::write(address),
+            DebugAddressRegisterNumber::Dr2 => Dr2::write(address),
+            DebugAddressRegisterNumber::Dr3 => Dr3::write(address),
+        }
+
+        let condition = match watchpoint.kind {
+            ::syscalls::WatchpointKind::Write => BreakpointCondition::DataWrites,
+            ::syscalls::WatchpointKind::ReadWrite => BreakpointCondition::DataReadsWrites,
+            ::syscalls::WatchpointKind::Execute => BreakpointCondition::InstructionExecution,
+        };
+
+        dr7.insert_flags(Dr7Flags::local_breakpoint_enable(number));
+        dr7.set_condition(number, condition);
+        dr7.set_size(
+            number,
+            BreakpointSize::new(watchpoint.size).expect("bad watchpoint size"),
+        );
+    }
+
+    Dr7::write(dr7);
+}
+
+/// Read the debug status (DR6) and reset it
+///
+/// Note: the processor never clears DR6 by itself
+pub fn debug_status_take() -> usize {
+    let status = Dr6::read_raw();
+
+    unsafe {
+        asm!("mov dr6, {}", in(reg) 0u64, options(nomem, nostack, preserves_flags));
+    }
+
+    status as usize
+}
+
 pub fn syscall_switch(
     syscall_number: usize,
     arg1: usize,
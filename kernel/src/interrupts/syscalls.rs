@@ -35,8 +35,20 @@ pub fn init() {
     let handler = VirtAddr::from_ptr(syscall_native_handler as *const ());
     LStar::write(handler);
 
-    // Clear interrupts on syscall enter
-    SFMask::write(RFlags::INTERRUPT_FLAG);
+    // Clear interrupts on syscall enter, and single-step (set by debuggers on userland threads)
+    SFMask::write(RFlags::INTERRUPT_FLAG | RFlags::TRAP_FLAG);
+}
+
+/// Disable the syscall instruction, and reset its entry point
+pub fn teardown() {
+    unsafe {
+        Efer::update(|flags| {
+            *flags &= !EferFlags::SYSTEM_CALL_EXTENSIONS;
+        });
+    }
+
+    LStar::write(VirtAddr::zero());
+    SFMask::write(RFlags::empty());
 }
 
 #[naked]
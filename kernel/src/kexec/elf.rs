@@ -0,0 +1,332 @@
+//! Minimal ELF loader for the kernel image
+//!
+//! Supports what the bootloader needs for our kernel: a position independent executable (`ET_DYN`) for x86_64,
+//! with `R_X86_64_RELATIVE` relocations only, and no TLS.
+
+use core::ops::Range;
+
+use alloc::vec::Vec;
+use x86_64::structures::paging::PageTableFlags;
+
+use crate::memory::{page_aligned_down, page_aligned_up, PAGE_SIZE};
+
+use super::Error;
+
+const ELF_MAGIC: [u8; 4] = [0x7F, b'E', b'L', b'F'];
+const ELFCLASS64: u8 = 2;
+const ELFDATA2LSB: u8 = 1;
+const ET_DYN: u16 = 3;
+const EM_X86_64: u16 = 62;
+
+const HEADER_SIZE: usize = 64;
+const PROGRAM_HEADER_SIZE: usize = 56;
+const DYNAMIC_ENTRY_SIZE: usize = 16;
+const RELA_SIZE: usize = 24;
+
+const PT_LOAD: u32 = 1;
+const PT_DYNAMIC: u32 = 2;
+const PT_TLS: u32 = 7;
+const PT_GNU_RELRO: u32 = 0x6474_E552;
+
+const PF_X: u32 = 1;
+const PF_W: u32 = 2;
+
+const DT_NULL: u64 = 0;
+const DT_RELA: u64 = 7;
+const DT_RELASZ: u64 = 8;
+const DT_RELAENT: u64 = 9;
+const DT_REL: u64 = 17;
+const DT_RELR: u64 = 36;
+
+const R_X86_64_NONE: u32 = 0;
+const R_X86_64_RELATIVE: u32 = 8;
+
+/// Max end address of the loaded image: it must fit in one level 4 entry, keep it reasonable
+const MAX_IMAGE_END: u64 = 1 << 30;
+
+#[derive(Debug)]
+struct Segment {
+    vaddr: u64,
+    mem_size: u64,
+    offset: usize,
+    file_size: usize,
+    flags: u32,
+}
+
+/// Kernel image, parsed and checked: loading it cannot fail
+#[derive(Debug)]
+pub struct Image<'a> {
+    data: &'a [u8],
+    segments: Vec<Segment>,
+    relro: Option<Range<u64>>,
+    /// File range of the relocations table
+    relocations: Range<usize>,
+    entry: u64,
+    /// Page aligned virtual range covered by the segments
+    span: Range<u64>,
+}
+
+impl<'a> Image<'a> {
+    pub fn parse(data: &'a [u8]) -> Result<Self, Error> {
+        let header = read(data, 0, HEADER_SIZE)?;
+
+        if header[0..4] != ELF_MAGIC || header[4] != ELFCLASS64 || header[5] != ELFDATA2LSB {
+            return Err(Error::InvalidImage("not an ELF64 little endian file"));
+        }
+
+        if read_u16(header, 16) != ET_DYN || read_u16(header, 18) != EM_X86_64 {
+            return Err(Error::InvalidImage(
+                "not a position independent executable for x86_64",
+            ));
+        }
+
+        let entry = read_u64(header, 24);
+        let program_headers = read_u64(header, 32) as usize;
+        let program_header_size = read_u16(header, 54) as usize;
+        let program_header_count = read_u16(header, 56) as usize;
+
+        if program_header_size != PROGRAM_HEADER_SIZE {
+            return Err(Error::InvalidImage("bad program header size"));
+        }
+
+        let mut segments = Vec::new();
+        let mut relro = None;
+        let mut dynamic = None;
+
+        for index in 0..program_header_count {
+            let program_header = read(
+                data,
+                program_headers + index * PROGRAM_HEADER_SIZE,
+                PROGRAM_HEADER_SIZE,
+            )?;
+
+            let offset = read_u64(program_header, 8) as usize;
+            let vaddr = read_u64(program_header, 16);
+            let file_size = read_u64(program_header, 32) as usize;
+            let mem_size = read_u64(program_header, 40);
+
+            match read_u32(program_header, 0) {
+                PT_LOAD => {
+                    read(data, offset, file_size)?;
+
+                    if file_size as u64 > mem_size {
+                        return Err(Error::InvalidImage("segment larger in file than in memory"));
+                    }
+
+                    segments.push(Segment {
+                        vaddr,
+                        mem_size,
+                        offset,
+                        file_size,
+                        flags: read_u32(program_header, 4),
+                    });
+                }
+                PT_DYNAMIC => dynamic = Some(read(data, offset, file_size)?),
+                PT_GNU_RELRO => relro = Some(vaddr..vaddr.saturating_add(mem_size)),
+                PT_TLS => return Err(Error::InvalidImage("TLS is not supported")),
+                _ => {}
+            }
+        }
+
+        let start = segments
+            .iter()
+            .map(|segment| segment.vaddr)
+            .min()
+            .ok_or(Error::InvalidImage("no loadable segment"))?;
+        let end = segments
+            .iter()
+            .map(|segment| segment.vaddr.saturating_add(segment.mem_size))
+            .max()
+            .unwrap_or(start);
+
+        if end > MAX_IMAGE_END {
+            return Err(Error::InvalidImage("image too large"));
+        }
+
+        let mut image = Self {
+            data,
+            segments,
+            relro,
+            relocations: 0..0,
+            entry,
+            span: page_aligned_down(start as usize) as u64..page_aligned_up(end as usize) as u64,
+        };
+
+        if !image.span.contains(&entry) {
+            return Err(Error::InvalidImage("entry point outside of the image"));
+        }
+
+        if let Some(dynamic) = dynamic {
+            image.relocations = image.parse_dynamic(dynamic)?;
+        }
+
+        image.check_relocations()?;
+
+        for page in image.span.clone().step_by(PAGE_SIZE) {
+            if let Some(flags) = image.page_flags(page)
+                && flags.contains(PageTableFlags::WRITABLE)
+                && !flags.contains(PageTableFlags::NO_EXECUTE)
+            {
+                return Err(Error::InvalidImage("page both writable and executable"));
+            }
+        }
+
+        Ok(image)
+    }
+
+    /// Find the relocations table from the dynamic section
+    fn parse_dynamic(&self, dynamic: &[u8]) -> Result<Range<usize>, Error> {
+        let mut rela = None;
+        let mut rela_size = 0;
+
+        for entry in dynamic.chunks_exact(DYNAMIC_ENTRY_SIZE) {
+            let value = read_u64(entry, 8);
+
+            match read_u64(entry, 0) {
+                DT_NULL => break,
+                DT_RELA => rela = Some(value),
+                DT_RELASZ => rela_size = value as usize,
+                DT_RELAENT if value as usize != RELA_SIZE => {
+                    return Err(Error::InvalidImage("bad relocation entry size"));
+                }
+                DT_REL | DT_RELR => {
+                    return Err(Error::InvalidImage("only RELA relocations are supported"));
+                }
+                _ => {}
+            }
+        }
+
+        let Some(rela) = rela else {
+            return Ok(0..0);
+        };
+
+        // The table is read from the file: find the segment which holds it
+        let offset = self
+            .segments
+            .iter()
+            .find(|segment| {
+                rela >= segment.vaddr
+                    && rela.saturating_add(rela_size as u64)
+                        <= segment.vaddr + segment.file_size as u64
+            })
+            .map(|segment| segment.offset + (rela - segment.vaddr) as usize)
+            .ok_or(Error::InvalidImage("relocations table not in a segment"))?;
+
+        read(self.data, offset, rela_size)?;
+
+        Ok(offset..offset + rela_size)
+    }
+
+    fn check_relocations(&self) -> Result<(), Error> {
+        for relocation in self.data[self.relocations.clone()].chunks_exact(RELA_SIZE) {
+            let offset = read_u64(relocation, 0);
+
+            match read_u64(relocation, 8) as u32 {
+                R_X86_64_NONE => {}
+                R_X86_64_RELATIVE
+                    if offset >= self.span.start && offset.saturating_add(8) <= self.span.end => {}
+                R_X86_64_RELATIVE => {
+                    return Err(Error::InvalidImage("relocation outside of the image"));
+                }
+                _ => return Err(Error::InvalidImage("unsupported relocation type")),
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Number of pages of the loaded image
+    pub fn page_count(&self) -> usize {
+        (self.span.end - self.span.start) as usize / PAGE_SIZE
+    }
+
+    /// Virtual address of the first page of the image, relative to the load address
+    pub fn start(&self) -> u64 {
+        self.span.start
+    }
+
+    /// Entry point, relative to the load address
+    pub fn entry(&self) -> u64 {
+        self.entry
+    }
+
+    /// Copy the segments in `memory` (zeroed, `page_count` pages), and apply the relocations for `load_address`
+    pub fn load(&self, memory: &mut [u8], load_address: u64) {
+        assert!(memory.len() == self.page_count() * PAGE_SIZE);
+
+        for segment in self.segments.iter() {
+            let start = (segment.vaddr - self.span.start) as usize;
+
+            memory[start..start + segment.file_size]
+                .copy_from_slice(&self.data[segment.offset..segment.offset + segment.file_size]);
+        }
+
+        for relocation in self.data[self.relocations.clone()].chunks_exact(RELA_SIZE) {
+            if read_u64(relocation, 8) as u32 != R_X86_64_RELATIVE {
+                continue;
+            }
+
+            let start = (read_u64(relocation, 0) - self.span.start) as usize;
+            let value = load_address.wrapping_add(read_u64(relocation, 16));
+
+            memory[start..start + 8].copy_from_slice(&value.to_le_bytes());
+        }
+    }
+
+    /// Flags of the page at `vaddr` (relative to the load address), from the segments which cover it
+    ///
+    /// Returns None if no segment covers the page: it is not mapped.
+    pub fn page_flags(&self, vaddr: u64) -> Option<PageTableFlags> {
+        let page = vaddr..vaddr + PAGE_SIZE as u64;
+        let mut flags = None;
+
+        for segment in self.segments.iter() {
+            if segment.mem_size == 0
+                || segment.vaddr >= page.end
+                || segment.vaddr + segment.mem_size <= page.start
+            {
+                continue;
+            }
+
+            let flags = flags.get_or_insert(PageTableFlags::PRESENT | PageTableFlags::NO_EXECUTE);
+
+            if segment.flags & PF_W != 0 {
+                flags.insert(PageTableFlags::WRITABLE);
+            }
+
+            if segment.flags & PF_X != 0 {
+                flags.remove(PageTableFlags::NO_EXECUTE);
+            }
+        }
+
+        // Read-only after relocation: only the pages fully in the range (the last one may be shared with data)
+        if let Some(relro) = &self.relro
+            && let Some(flags) = flags.as_mut()
+            && relro.start <= page.start
+            && page.end <= relro.end
+        {
+            flags.remove(PageTableFlags::WRITABLE);
+        }
+
+        flags
+    }
+}
+
+fn read(data: &[u8], offset: usize, len: usize) -> Result<&[u8], Error> {
+    offset
+        .checked_add(len)
+        .and_then(|end| data.get(offset..end))
+        .ok_or(Error::InvalidImage("truncated file"))
+}
+
+fn read_u16(data: &[u8], offset: usize) -> u16 {
+    u16::from_le_bytes(data[offset..offset + 2].try_into().unwrap())
+}
+
+fn read_u32(data: &[u8], offset: usize) -> u32 {
+    u32::from_le_bytes(data[offset..offset + 4].try_into().unwrap())
+}
+
+fn read_u64(data: &[u8], offset: usize) -> u64 {
+    u64::from_le_bytes(data[offset..offset + 8].try_into().unwrap())
+}
@@ -0,0 +1,530 @@
+//! kexec-lite: load a new kernel image and jump to it, without going through the firmware and the bootloader again
+//!
+//! Used for fast development iteration: the machine (or QEmu) is not restarted.
+//!
+//! The new kernel is started in the state left by the bootloader (see `memory::paging`):
+//! - its image, a stack, the framebuffer, the boot info and the ramdisk are each mapped at their own level 4 entry
+//! - the physical memory mapping is shared with the current kernel (its page tables come from the bootloader)
+//! - the memory map is the one of the boot: the frames used by the current kernel are usable again
+//!
+//! Everything the new kernel needs is copied in one range of contiguous frames (the handoff range), marked with
+//! `HANDOFF_REGION_KIND` in its memory map, so that it is not used before the new kernel takes it over.
+//! The new kernel frees the parts it drops (stack, ramdisk, boot info), as it does for the bootloader ones.
+//! The first page of the range starts with the `Handoff` state (log sinks, serial port, console), read at boot.
+
+mod elf;
+
+use core::{
+    arch::asm,
+    mem::{align_of, size_of},
+    ops::Range,
+    ptr, slice,
+};
+
+use bootloader_api::{
+    info::{FrameBuffer, MemoryRegion, MemoryRegionKind, MemoryRegions},
+    BootInfo,
+};
+use spin::Mutex;
+use x86_64::structures::paging::{
+    FrameAllocator, Mapper, OffsetPageTable, Page, PageTable, PageTableFlags, PhysFrame, Size4KiB,
+};
+
+use crate::devices;
+use crate::logging::{self, info};
+use crate::memory::{
+    self, align_up, kernel_l4_entry, page_aligned_down, page_aligned_up, phys_to_virt, PhysAddr,
+    VirtAddr, PAGE_SIZE,
+};
+
+/// Kind of the memory region of the handoff range (`MemoryRegionKind::UnknownBios`)
+const HANDOFF_REGION_KIND: u32 = 0x6B65_7865;
+
+const HANDOFF_MAGIC: u64 = u64::from_le_bytes(*b"kexec-HO");
+
+/// Max number of regions of the boot memory map. Past it, the kernel cannot be reloaded.
+const MAX_REGIONS: usize = 256;
+
+/// Stack of the new kernel (same as the bootloader default)
+const STACK_SIZE: usize = 80 * 1024;
+
+/// Pages mapped for the trampoline (it may cross a page boundary)
+const TRAMPOLINE_PAGES: usize = 2;
+
+/// First level 4 entry of the bootloader dynamic range (see `CONFIG` in main)
+const FIRST_SLOT: u16 = 258;
+
+/// Layout of the boot info pages: `Handoff`, then `BootInfo`, then the memory regions
+const BOOT_INFO_OFFSET: usize =
+    align_up(size_of::<Handoff>() as u64, align_of::<BootInfo>() as u64) as usize;
+const REGIONS_OFFSET: usize = align_up(
+    (BOOT_INFO_OFFSET + size_of::<BootInfo>()) as u64,
+    align_of::<MemoryRegion>() as u64,
+) as usize;
+
+#[derive(Debug, Clone, Copy)]
+pub enum Error {
+    /// The new kernel image cannot be loaded
+    InvalidImage(&'static str),
+    /// Not enough contiguous memory for the handoff range
+    NoMemory,
+    /// The boot memory map could not be saved (too many regions)
+    Unsupported,
+}
+
+/// State handed off to the new kernel, at the start of the handoff range
+#[derive(Debug)]
+#[repr(C)]
+struct Handoff {
+    magic: u64,
+    /// Size of the structure: a kernel with another layout ignores it
+    size: u64,
+    /// Number of reloads since the firmware boot
+    generation: u64,
+    logging: logging::HandoffState,
+}
+
+/// Parameters of the boot, kept to reload the kernel (the boot info is unmapped by the memory initialization)
+struct BootState {
+    regions: [MemoryRegion; MAX_REGIONS],
+    /// 0 if the memory map did not fit
+    region_count: usize,
+    rsdp: Option<u64>,
+    generation: u64,
+}
+
+static BOOT_STATE: Mutex<BootState> = Mutex::new(BootState {
+    regions: [MemoryRegion::empty(); MAX_REGIONS],
+    region_count: 0,
+    rsdp: None,
+    generation: 0,
+});
+
+/// Save the boot parameters needed to reload the kernel, and restore the state handed off by the previous kernel, if any
+///
+/// Called first at boot: the log sinks must be restored before the first record.
+pub fn init(boot_info: &BootInfo) {
+    let mut state = BOOT_STATE.lock();
+    let regions = &boot_info.memory_regions;
+
+    if regions.len() <= MAX_REGIONS {
+        state.regions[..regions.len()].copy_from_slice(regions);
+        state.region_count = regions.len();
+    }
+
+    state.rsdp = boot_info.rsdp_addr.into_option();
+
+    let phys_mapping = *boot_info
+        .physical_memory_offset
+        .as_ref()
+        .expect("No physical memory mapping");
+
+    let handoff = regions
+        .iter()
+        .find(|region| region.kind == MemoryRegionKind::UnknownBios(HANDOFF_REGION_KIND))
+        .map(|region| unsafe { &*((phys_mapping + region.start) as *const Handoff) })
+        .filter(|handoff| {
+            handoff.magic == HANDOFF_MAGIC && handoff.size == size_of::<Handoff>() as u64
+        });
+
+    if let Some(handoff) = handoff {
+        logging::restore(&handoff.logging);
+        state.generation = handoff.generation;
+
+        info!(
+            "kexec: reloaded by the previous kernel (generation {})",
+            handoff.generation
+        );
+    }
+
+    if state.region_count == 0 {
+        info!(
+            "kexec: too many memory regions ({}), reload not supported",
+            regions.len()
+        );
+    }
+}
+
+/// Load a new kernel image with its ramdisk, and jump to it
+///
+/// The devices are quiesced and the CPU torn down before the jump. Returns only if the image cannot be loaded:
+/// nothing is changed then.
+///
+/// Note: the kernel is not preemptible, userland does not run anymore from here.
+pub fn reload(kernel: &[u8], ramdisk: &[u8]) -> Result<!, Error> {
+    let image = elf::Image::parse(kernel)?;
+
+    let state = BOOT_STATE.lock();
+    if state.region_count == 0 {
+        return Err(Error::Unsupported);
+    }
+    let boot_regions = &state.regions[..state.region_count];
+
+    let framebuffer = logging::console_framebuffer();
+    let framebuffer_offset = framebuffer.map_or(0, |(addr, _)| {
+        (addr.as_u64() - page_aligned_down(addr.as_u64() as usize) as u64) as usize
+    });
+
+    // Layout of the handoff range, in pages.
+    // The memory map can get 2 more regions: the handoff range splits a usable one.
+    let info_pages =
+        page_count(REGIONS_OFFSET + (boot_regions.len() + 2) * size_of::<MemoryRegion>());
+    let image_pages = image.page_count();
+    let ramdisk_pages = page_count(ramdisk.len());
+    let stack_pages = STACK_SIZE / PAGE_SIZE;
+    let framebuffer_pages = framebuffer.map_or(0, |(_, info)| {
+        page_count(framebuffer_offset + info.byte_len)
+    });
+
+    let mapped = [
+        info_pages,
+        image_pages,
+        ramdisk_pages,
+        stack_pages,
+        TRAMPOLINE_PAGES,
+        framebuffer_pages,
+    ];
+    let table_pages = 1 + mapped
+        .iter()
+        .map(|&pages| table_pages(pages))
+        .sum::<usize>();
+    let total_pages =
+        info_pages + image_pages + ramdisk_pages + stack_pages + TRAMPOLINE_PAGES + table_pages;
+
+    let base = memory::phys_allocate_contiguous(total_pages).ok_or(Error::NoMemory)?;
+    let handoff_range = base.as_u64()..base.as_u64() + (total_pages * PAGE_SIZE) as u64;
+
+    // From here, nothing can fail
+
+    unsafe {
+        ptr::write_bytes(
+            phys_to_virt(base).as_mut_ptr::<u8>(),
+            0,
+            total_pages * PAGE_SIZE,
+        )
+    };
+
+    let mut frames = Frames {
+        next: base,
+        end: PhysAddr::new(handoff_range.end),
+    };
+    let info_phys = frames.take(info_pages);
+    let image_phys = frames.take(image_pages);
+    let ramdisk_phys = frames.take(ramdisk_pages);
+    let stack_phys = frames.take(stack_pages);
+    let trampoline_phys = frames.take(TRAMPOLINE_PAGES);
+    // The rest is used for the page tables
+
+    // Virtual layout: each part at its own level 4 entry, like the bootloader.
+    // The trampoline is at the same address as in the current kernel, which is left out.
+    let trampoline_addr = VirtAddr::new(trampoline as usize as u64);
+    let trampoline_size =
+        unsafe { &kexec_trampoline_end as *const u8 as usize } - trampoline as usize;
+    assert!(trampoline_size <= PAGE_SIZE);
+    let trampoline_page = trampoline_addr.align_down(PAGE_SIZE as u64);
+    let current_slot = u16::from(trampoline_addr.p4_index());
+
+    let mut slots = (FIRST_SLOT..512)
+        .filter(|&slot| slot != current_slot)
+        .map(|slot| VirtAddr::new_truncate((slot as u64) << 39));
+    let kernel_addr = slots.next().unwrap();
+    let stack_addr = slots.next().unwrap();
+    let framebuffer_addr = slots.next().unwrap();
+    let info_addr = slots.next().unwrap();
+    let ramdisk_addr = slots.next().unwrap();
+
+    unsafe {
+        image.load(phys_slice(image_phys, image_pages), kernel_addr.as_u64());
+
+        phys_slice(ramdisk_phys, ramdisk_pages)[..ramdisk.len()].copy_from_slice(ramdisk);
+
+        let offset = (trampoline_addr - trampoline_page) as usize;
+        phys_slice(trampoline_phys, TRAMPOLINE_PAGES)[offset..offset + trampoline_size]
+            .copy_from_slice(slice::from_raw_parts(
+                trampoline_addr.as_ptr(),
+                trampoline_size,
+            ));
+    }
+
+    // Page tables
+    let l4_phys = frames.take(1);
+    let l4_table = unsafe { &mut *phys_to_virt(l4_phys).as_mut_ptr::<PageTable>() };
+
+    let phys_mapping = phys_to_virt(PhysAddr::zero());
+    l4_table[phys_mapping.p4_index()] = kernel_l4_entry(phys_mapping);
+
+    let mut mapper = unsafe { OffsetPageTable::new(l4_table, phys_mapping) };
+    let data = PageTableFlags::PRESENT | PageTableFlags::WRITABLE | PageTableFlags::NO_EXECUTE;
+
+    map(
+        &mut mapper,
+        &mut frames,
+        kernel_addr + image.start(),
+        image_phys,
+        image_pages,
+        |index| image.page_flags(image.start() + (index * PAGE_SIZE) as u64),
+    );
+    map(
+        &mut mapper,
+        &mut frames,
+        stack_addr,
+        stack_phys,
+        stack_pages,
+        |_| Some(data),
+    );
+    map(
+        &mut mapper,
+        &mut frames,
+        info_addr,
+        info_phys,
+        info_pages,
+        |_| Some(data),
+    );
+    map(
+        &mut mapper,
+        &mut frames,
+        ramdisk_addr,
+        ramdisk_phys,
+        ramdisk_pages,
+        |_| Some(PageTableFlags::PRESENT | PageTableFlags::NO_EXECUTE),
+    );
+    map(
+        &mut mapper,
+        &mut frames,
+        trampoline_page,
+        trampoline_phys,
+        TRAMPOLINE_PAGES,
+        |_| Some(PageTableFlags::PRESENT),
+    );
+    if let Some((addr, _)) = framebuffer {
+        let start = PhysAddr::new(addr.as_u64() - framebuffer_offset as u64);
+        map(
+            &mut mapper,
+            &mut frames,
+            framebuffer_addr,
+            start,
+            framebuffer_pages,
+            |_| Some(data),
+        );
+    }
+
+    // Boot info
+    let info = phys_to_virt(info_phys);
+
+    let regions = unsafe {
+        slice::from_raw_parts_mut(
+            (info + REGIONS_OFFSET).as_mut_ptr::<MemoryRegion>(),
+            boot_regions.len() + 2,
+        )
+    };
+    let region_count = carve(boot_regions, &handoff_range, regions);
+
+    // Not dereferenced here: the regions address is only valid in the new address space
+    let regions: MemoryRegions = unsafe {
+        slice::from_raw_parts_mut(
+            (info_addr + REGIONS_OFFSET).as_mut_ptr::<MemoryRegion>(),
+            region_count,
+        )
+    }
+    .into();
+
+    let mut boot_info = BootInfo::new(regions);
+    boot_info.physical_memory_offset = Some(phys_mapping.as_u64()).into();
+    boot_info.rsdp_addr = state.rsdp.into();
+    boot_info.framebuffer = framebuffer
+        .map(|(_, info)| unsafe {
+            FrameBuffer::new((framebuffer_addr + framebuffer_offset).as_u64(), info)
+        })
+        .into();
+    boot_info.ramdisk_addr = Some(ramdisk_addr.as_u64()).into();
+    boot_info.ramdisk_len = ramdisk.len() as u64;
+    // The ELF file is not kept: this is the loaded image
+    boot_info.kernel_addr = image_phys.as_u64();
+    boot_info.kernel_len = (image_pages * PAGE_SIZE) as u64;
+    boot_info.kernel_image_offset = kernel_addr.as_u64();
+
+    unsafe { ptr::write((info + BOOT_INFO_OFFSET).as_mut_ptr(), boot_info) };
+
+    let generation = state.generation + 1;
+    drop(state);
+
+    let entry = kernel_addr + image.entry();
+    let stack_top = stack_addr + STACK_SIZE;
+
+    info!(
+        "kexec: jumping to the new kernel (generation {}, entry={:?}, handoff range={:#x}-{:#x})",
+        generation, entry, handoff_range.start, handoff_range.end
+    );
+
+    devices::quiesce();
+    devices::cpu::teardown();
+
+    // After the last record: the console cursor is up to date
+    let handoff = Handoff {
+        magic: HANDOFF_MAGIC,
+        size: size_of::<Handoff>() as u64,
+        generation,
+        logging: logging::handoff_state(),
+    };
+    unsafe { ptr::write(info.as_mut_ptr(), handoff) };
+
+    unsafe {
+        trampoline(
+            l4_phys.as_u64(),
+            stack_top.as_u64(),
+            (info_addr + BOOT_INFO_OFFSET).as_u64(),
+            entry.as_u64(),
+        )
+    }
+}
+
+/// Build the memory map of the new kernel: the boot one, with the handoff range carved out of the usable regions
+///
+/// The handoff range of the previous reload, if any, is usable again: the current kernel is not needed after the jump.
+/// Contiguous regions of the same kind are merged, so that the map does not grow from a reload to the next.
+///
+/// Returns the number of regions written in `regions`
+fn carve(boot: &[MemoryRegion], handoff: &Range<u64>, regions: &mut [MemoryRegion]) -> usize {
+    let mut count = 0;
+
+    let mut push = |start: u64, end: u64, kind: MemoryRegionKind| {
+        if start >= end {
+            return;
+        }
+
+        if let Some(last) = regions[..count].last_mut()
+            && last.kind == kind
+            && last.end == start
+        {
+            last.end = end;
+            return;
+        }
+
+        regions[count] = MemoryRegion { start, end, kind };
+        count += 1;
+    };
+
+    for region in boot {
+        let kind = match region.kind {
+            MemoryRegionKind::UnknownBios(HANDOFF_REGION_KIND) => MemoryRegionKind::Usable,
+            kind => kind,
+        };
+
+        if kind != MemoryRegionKind::Usable {
+            push(region.start, region.end, kind);
+            continue;
+        }
+
+        push(region.start, region.end.min(handoff.start), kind);
+        push(
+            region.start.max(handoff.start),
+            region.end.min(handoff.end),
+            MemoryRegionKind::UnknownBios(HANDOFF_REGION_KIND),
+        );
+        push(region.start.max(handoff.end), region.end, kind);
+    }
+
+    count
+}
+
+/// Frames of the handoff range, given in order
+struct Frames {
+    next: PhysAddr,
+    end: PhysAddr,
+}
+
+impl Frames {
+    fn take(&mut self, count: usize) -> PhysAddr {
+        let frame = self.next;
+        self.next += (count * PAGE_SIZE) as u64;
+        assert!(self.next <= self.end);
+        frame
+    }
+}
+
+unsafe impl FrameAllocator<Size4KiB> for Frames {
+    fn allocate_frame(&mut self) -> Option<PhysFrame<Size4KiB>> {
+        if self.next >= self.end {
+            return None;
+        }
+
+        Some(PhysFrame::containing_address(self.take(1)))
+    }
+}
+
+/// Map `count` pages from `addr` to the frames from `frame`, with the flags given for each page index (None: not mapped)
+fn map(
+    mapper: &mut OffsetPageTable,
+    frames: &mut Frames,
+    addr: VirtAddr,
+    frame: PhysAddr,
+    count: usize,
+    flags: impl Fn(usize) -> Option<PageTableFlags>,
+) {
+    for index in 0..count {
+        let Some(flags) = flags(index) else {
+            continue;
+        };
+
+        let page = Page::<Size4KiB>::containing_address(addr + index * PAGE_SIZE);
+        let frame = PhysFrame::containing_address(frame + (index * PAGE_SIZE) as u64);
+        let parent_flags = PageTableFlags::PRESENT | PageTableFlags::WRITABLE;
+
+        unsafe { mapper.map_to_with_table_flags(page, frame, flags, parent_flags, frames) }
+            .expect("page tables frames are counted in the handoff range")
+            .ignore();
+    }
+}
+
+/// Number of page tables (levels 3 to 1) needed to map `pages` contiguous pages in a level 4 entry
+fn table_pages(pages: usize) -> usize {
+    if pages == 0 {
+        return 0;
+    }
+
+    // The range may cross a table boundary
+    1 + (pages.div_ceil(512 * 512) + 1) + (pages.div_ceil(512) + 1)
+}
+
+fn page_count(size: usize) -> usize {
+    page_aligned_up(size) / PAGE_SIZE
+}
+
+/// # Safety
+/// The frames must be owned by the caller
+unsafe fn phys_slice<'a>(frame: PhysAddr, pages: usize) -> &'a mut [u8] {
+    slice::from_raw_parts_mut(phys_to_virt(frame).as_mut_ptr(), pages * PAGE_SIZE)
+}
+
+extern "C" {
+    /// End of the trampoline code
+    static kexec_trampoline_end: u8;
+}
+
+/// Switch to the page tables of the new kernel, and jump to its entry point with the boot info
+///
+/// It is mapped at the same address in both page tables, so that execution continues after the switch.
+/// Clearing CR4.PGE flushes the global TLB entries of the current kernel.
+#[naked]
+// The end label is global to measure the code: a naked function is never inlined, so it is not duplicated
+#[allow(named_asm_labels)]
+unsafe extern "C" fn trampoline(page_table: u64, stack_top: u64, boot_info: u64, entry: u64) -> ! {
+    asm!(
+        concat!(
+            "mov rax, cr4;", // Disable global pages: flush all TLB entries
+            "mov r8, rax;",
+            "btr rax, 7;",
+            "mov cr4, rax;",
+            "mov cr3, rdi;", // Switch to the new page tables
+            "mov cr4, r8;",  // Restore global pages
+            "mov rsp, rsi;", // Switch to the new stack
+            "xor ebp, ebp;",
+            "mov rdi, rdx;", // Boot info is the first arg of the entry point
+            "push 0;",       // Fake return address, as after a call
+            "jmp rcx;",
+            ".global kexec_trampoline_end;",
+            "kexec_trampoline_end:",
+        ),
+        options(noreturn)
+    );
+}
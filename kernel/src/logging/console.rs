@@ -0,0 +1,235 @@
+use core::{
+    fmt::{self, Write},
+    ptr,
+};
+
+use bootloader_api::info::{FrameBuffer, FrameBufferInfo, PixelFormat};
+use log::Record;
+use noto_sans_mono_bitmap::{get_raster, get_raster_width, FontWeight, RasterHeight};
+use spin::Mutex;
+use x86_64::{
+    registers::control::Cr3,
+    structures::paging::{OffsetPageTable, PageTable, Translate},
+};
+
+use crate::memory::{map_iomem, page_aligned_up, Permissions, PhysAddr, VirtAddr, PAGE_SIZE};
+
+use super::{Format, Sink};
+
+const FONT_WEIGHT: FontWeight = FontWeight::Regular;
+const CHAR_HEIGHT: RasterHeight = RasterHeight::Size16;
+const CHAR_WIDTH: usize = get_raster_width(FONT_WEIGHT, CHAR_HEIGHT);
+const LINE_HEIGHT: usize = CHAR_HEIGHT.val();
+
+/// Text console on the bootloader framebuffer
+///
+/// Before the memory manager is initialized, the console draws through the bootloader mapping.
+/// This mapping is dropped by the paging initialization, so the console is detached during it, then remapped.
+/// While detached, the buffer is null and records are not displayed.
+static CONSOLE: Mutex<Option<Console>> = Mutex::new(None);
+
+/// Cursor (row, column) handed off by the previous kernel (see `kexec`)
+///
+/// The framebuffer content is then kept, and the console continues below it.
+static RESTORED_CURSOR: Mutex<Option<(usize, usize)>> = Mutex::new(None);
+
+struct Console {
+    buffer: *mut u8,
+    phys_addr: PhysAddr,
+    info: FrameBufferInfo,
+    /// Cursor position, in characters
+    column: usize,
+    row: usize,
+}
+
+unsafe impl Send for Console {}
+
+impl Console {
+    fn columns(&self) -> usize {
+        self.info.width / CHAR_WIDTH
+    }
+
+    fn rows(&self) -> usize {
+        self.info.height / LINE_HEIGHT
+    }
+
+    fn line_bytes(&self) -> usize {
+        self.info.stride * self.info.bytes_per_pixel
+    }
+
+    fn new_line(&mut self) {
+        self.column = 0;
+
+        if self.row + 1 < self.rows() {
+            self.row += 1;
+            return;
+        }
+
+        // Scroll: move all text lines up by one, and clear the last one
+        let text_line = self.line_bytes() * LINE_HEIGHT;
+        let visible = text_line * self.rows();
+
+        unsafe {
+            ptr::copy(self.buffer.add(text_line), self.buffer, visible - text_line);
+            ptr::write_bytes(self.buffer.add(visible - text_line), 0, text_line);
+        }
+    }
+
+    fn put_char(&mut self, c: char) {
+        match c {
+            '\n' => self.new_line(),
+            '\r' => self.column = 0,
+            c => {
+                if self.column >= self.columns() {
+                    self.new_line();
+                }
+
+                let raster = get_raster(c, FONT_WEIGHT, CHAR_HEIGHT)
+                    .or_else(|| get_raster('?', FONT_WEIGHT, CHAR_HEIGHT))
+                    .expect("missing '?' raster");
+
+                let x0 = self.column * CHAR_WIDTH;
+                let y0 = self.row * LINE_HEIGHT;
+
+                for (y, line) in raster.raster().iter().enumerate() {
+                    for (x, intensity) in line.iter().enumerate() {
+                        self.put_pixel(x0 + x, y0 + y, *intensity);
+                    }
+                }
+
+                self.column += 1;
+            }
+        }
+    }
+
+    fn put_pixel(&mut self, x: usize, y: usize, intensity: u8) {
+        let color = match self.info.pixel_format {
+            PixelFormat::Rgb | PixelFormat::Bgr => [intensity, intensity, intensity, 0],
+            PixelFormat::U8 => [if intensity > 128 { 0xFF } else { 0 }, 0, 0, 0],
+            // Unknown format: draw nothing
+            _ => return,
+        };
+
+        let bytes_per_pixel = self.info.bytes_per_pixel;
+        let offset = y * self.line_bytes() + x * bytes_per_pixel;
+
+        unsafe {
+            ptr::copy_nonoverlapping(
+                color.as_ptr(),
+                self.buffer.add(offset),
+                bytes_per_pixel.min(color.len()),
+            );
+        }
+    }
+}
+
+impl Write for Console {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        for c in s.chars() {
+            self.put_char(c);
+        }
+
+        Ok(())
+    }
+}
+
+pub struct ConsoleSink;
+
+impl Sink for ConsoleSink {
+    fn write(&self, record: &Record, _format: Option<&Format>) {
+        let mut console = CONSOLE.lock();
+
+        if let Some(console) = console.as_mut()
+            && !console.buffer.is_null()
+        {
+            let _ = writeln!(console, "{} - {}", record.level(), record.args());
+        }
+    }
+}
+
+/// Attach the console to the bootloader framebuffer
+///
+/// `phys_mapping` is the bootloader physical memory mapping, used to find the physical address of the framebuffer
+pub fn init(framebuffer: &mut FrameBuffer, phys_mapping: VirtAddr) {
+    let info = framebuffer.info();
+    let buffer = framebuffer.buffer_mut();
+    let addr = VirtAddr::from_ptr(buffer.as_ptr());
+
+    // The bootloader tables are still active
+    let phys_addr = unsafe {
+        let (frame, _) = Cr3::read();
+        let l4_table =
+            &mut *(phys_mapping + frame.start_address().as_u64()).as_mut_ptr::<PageTable>();
+        OffsetPageTable::new(l4_table, phys_mapping).translate_addr(addr)
+    };
+
+    let Some(phys_addr) = phys_addr else {
+        return;
+    };
+
+    let (row, column) = match RESTORED_CURSOR.lock().take() {
+        Some((row, column)) => (
+            row.min((info.height / LINE_HEIGHT).saturating_sub(1)),
+            column,
+        ),
+        None => {
+            buffer.fill(0);
+            (0, 0)
+        }
+    };
+
+    *CONSOLE.lock() = Some(Console {
+        buffer: buffer.as_mut_ptr(),
+        phys_addr,
+        info,
+        column,
+        row,
+    });
+}
+
+/// Restore the cursor handed off by the previous kernel, applied when the console is attached
+pub fn restore(row: usize, column: usize) {
+    *RESTORED_CURSOR.lock() = Some((row, column));
+}
+
+/// Get the cursor (row, column), (0, 0) if there is no console
+pub fn cursor() -> (usize, usize) {
+    CONSOLE
+        .lock()
+        .as_ref()
+        .map_or((0, 0), |console| (console.row, console.column))
+}
+
+/// Get the physical address and the description of the framebuffer, if the console is attached to one
+pub fn framebuffer() -> Option<(PhysAddr, FrameBufferInfo)> {
+    CONSOLE
+        .lock()
+        .as_ref()
+        .map(|console| (console.phys_addr, console.info))
+}
+
+/// Detach the console from the bootloader mapping, before it is dropped by the paging initialization
+pub fn detach() {
+    if let Some(console) = CONSOLE.lock().as_mut() {
+        console.buffer = ptr::null_mut();
+    }
+}
+
+/// Map the framebuffer in kernel space, and attach the console to it
+///
+/// Called once the memory manager is initialized.
+pub fn remap() {
+    let mut console = CONSOLE.lock();
+    let Some(console) = console.as_mut() else {
+        return;
+    };
+
+    let start = console.phys_addr.align_down(PAGE_SIZE as u64);
+    let offset = (console.phys_addr - start) as usize;
+    let end = start + page_aligned_up(offset + console.info.byte_len) as u64;
+
+    // If the mapping fails, the console stays detached (cannot log from here: the console lock is held)
+    if let Some(addr) = unsafe { map_iomem(start..end, Permissions::READ | Permissions::WRITE) } {
+        console.buffer = (addr + offset).as_mut_ptr();
+    }
+}
@@ -0,0 +1,313 @@
+mod console;
+mod ring;
+mod serial;
+
+use core::{
+    fmt, ptr,
+    str::{self, FromStr},
+    sync::atomic::{AtomicUsize, Ordering},
+};
+
+use log::{Level, LevelFilter, Metadata, Record};
+use syscalls::{log_record, LogSink};
+
+pub use console::{
+    detach as console_detach, framebuffer as console_framebuffer, init as console_init,
+    remap as console_remap,
+};
+pub use ring::read as ring_read;
+
+/// Destination of log records
+///
+/// The format is given for the records of the kernel macros (`info!`, ...), the args of the record are then
+/// delimited by `Arg`.
+trait Sink: Sync {
+    fn write(&self, record: &Record, format: Option<&Format>);
+}
+
+struct SinkEntry {
+    sink: &'static dyn Sink,
+    level: AtomicUsize,
+}
+
+impl SinkEntry {
+    const fn new(sink: &'static dyn Sink, level: LevelFilter) -> Self {
+        Self {
+            sink,
+            level: AtomicUsize::new(level as usize),
+        }
+    }
+
+    fn level(&self) -> LevelFilter {
+        level_from_usize(self.level.load(Ordering::Relaxed)).expect("invalid level")
+    }
+}
+
+/// Sinks, indexed by `LogSink as usize - 1`
+static SINKS: [SinkEntry; 3] = [
+    SinkEntry::new(&serial::SerialSink, LevelFilter::Debug), // Trace is very verbose
+    SinkEntry::new(&ring::RingSink, LevelFilter::Debug),
+    SinkEntry::new(&console::ConsoleSink, LevelFilter::Info), // Drawing is slow
+];
+
+struct KernelLogger;
+
+impl log::Log for KernelLogger {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        SINKS.iter().any(|entry| metadata.level() <= entry.level())
+    }
+
+    fn log(&self, record: &Record) {
+        for entry in SINKS.iter() {
+            if record.level() <= entry.level() {
+                entry.sink.write(record, None);
+            }
+        }
+    }
+
+    fn flush(&self) {}
+}
+
+static LOGGER: KernelLogger = KernelLogger;
+
+/// Format string of a record of the kernel macros
+///
+/// The binary log sends only its id and the args, if it is internable (see `log_record::is_internable`).
+#[cfg_attr(not(feature = "binary-log"), allow(dead_code))]
+pub struct Format {
+    id: u32,
+    args_count: usize,
+    internable: bool,
+}
+
+impl Format {
+    pub const fn new(format: &str, args_count: usize) -> Self {
+        Self {
+            id: log_record::format_id(format),
+            args_count,
+            internable: log_record::is_internable(format, args_count),
+        }
+    }
+}
+
+/// Arg of a record of the kernel macros: its output is delimited by empty writes of `ARG_BOUNDARY`,
+/// invisible to the text sinks
+pub struct Arg<'a, T: ?Sized>(pub &'a T);
+
+/// Empty strings pointing inside it are arg boundaries
+///
+/// Note: pointing inside, so that an empty string at the end of another object cannot be mistaken for a boundary
+static ARG_BOUNDARY: [u8; 2] = [0; 2];
+
+fn arg_boundary() -> &'static str {
+    // Safety: empty
+    unsafe { str::from_utf8_unchecked(&ARG_BOUNDARY[1..1]) }
+}
+
+/// Check if a write of a formatter is an arg boundary
+#[cfg_attr(not(feature = "binary-log"), allow(dead_code))]
+fn is_arg_boundary(value: &str) -> bool {
+    value.is_empty() && ptr::eq(value.as_ptr(), arg_boundary().as_ptr())
+}
+
+macro_rules! forward_fmt {
+    ($($trait:ident),*) => {
+        $(
+            impl<T: fmt::$trait + ?Sized> fmt::$trait for Arg<'_, T> {
+                fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+                    f.write_str(arg_boundary())?;
+                    let result = fmt::$trait::fmt(self.0, f);
+                    f.write_str(arg_boundary())?;
+                    result
+                }
+            }
+        )*
+    };
+}
+
+forward_fmt!(Display, Debug, LowerHex, UpperHex, Octal, Binary, Pointer, LowerExp, UpperExp);
+
+/// Write a record of the kernel macros
+pub fn write(level: Level, target: &str, format: &Format, args: fmt::Arguments) {
+    let record = Record::builder()
+        .args(args)
+        .level(level)
+        .target(target)
+        .build();
+
+    for entry in SINKS.iter() {
+        if level <= entry.level() {
+            entry.sink.write(&record, Some(format));
+        }
+    }
+}
+
+/// Log a record, like `log::log!`, with its format string interned in the binary log
+///
+/// Usage: `log_at!(level, "format", args...)`
+macro_rules! log_at {
+    ($level:expr, $format:literal $(, $arg:expr)* $(,)?) => {{
+        let level: log::Level = $level;
+        if level <= log::STATIC_MAX_LEVEL && level <= log::max_level() {
+            static FORMAT: $crate::logging::Format =
+                $crate::logging::Format::new($format, <[&str]>::len(&[$(stringify!($arg)),*]));
+
+            $crate::logging::write(
+                level,
+                module_path!(),
+                &FORMAT,
+                format_args!($format, $($crate::logging::Arg(&$arg)),*),
+            );
+        }
+    }};
+}
+
+macro_rules! error {
+    ($($args:tt)*) => { $crate::logging::log_at!(log::Level::Error, $($args)*) };
+}
+
+// Note: `warn` alone is ambiguous with the lint attribute when re-exported
+macro_rules! warn_ {
+    ($($args:tt)*) => { $crate::logging::log_at!(log::Level::Warn, $($args)*) };
+}
+
+macro_rules! info {
+    ($($args:tt)*) => { $crate::logging::log_at!(log::Level::Info, $($args)*) };
+}
+
+macro_rules! debug {
+    ($($args:tt)*) => { $crate::logging::log_at!(log::Level::Debug, $($args)*) };
+}
+
+macro_rules! trace {
+    ($($args:tt)*) => { $crate::logging::log_at!(log::Level::Trace, $($args)*) };
+}
+
+pub(crate) use {debug, error, info, log_at, trace, warn_ as warn};
+
+/// Set the level threshold of a sink
+pub fn set_level(sink: LogSink, level: LevelFilter) {
+    SINKS[sink as usize - 1]
+        .level
+        .store(level as usize, Ordering::Relaxed);
+
+    update_max_level();
+}
+
+/// Get the level threshold of a sink
+pub fn get_level(sink: LogSink) -> LevelFilter {
+    SINKS[sink as usize - 1].level()
+}
+
+pub fn level_from_usize(value: usize) -> Option<LevelFilter> {
+    LevelFilter::iter().find(|level| *level as usize == value)
+}
+
+fn update_max_level() {
+    let max = SINKS
+        .iter()
+        .map(|entry| entry.level())
+        .max()
+        .unwrap_or(LevelFilter::Off);
+
+    log::set_max_level(max);
+}
+
+/// State of the sinks, handed off to a reloaded kernel (see `kexec`)
+#[derive(Debug, Clone, Copy)]
+#[repr(C)]
+pub struct HandoffState {
+    /// Level of each sink, indexed like `SINKS`
+    levels: [u64; 3],
+    /// Console cursor (row, column)
+    console_cursor: [u64; 2],
+}
+
+/// Capture the state of the sinks, to hand it off to a reloaded kernel
+pub fn handoff_state() -> HandoffState {
+    let (row, column) = console::cursor();
+
+    HandoffState {
+        levels: SINKS.each_ref().map(|entry| entry.level() as u64),
+        console_cursor: [row as u64, column as u64],
+    }
+}
+
+/// Restore the state of the sinks handed off by the previous kernel, must be called before the first record
+///
+/// The levels set at runtime replace the ones of the boot parameters, the serial port is taken over without reset,
+/// and the console continues below the output of the previous kernel.
+pub fn restore(state: &HandoffState) {
+    for (entry, level) in SINKS.iter().zip(state.levels) {
+        if let Some(level) = level_from_usize(level as usize) {
+            entry.level.store(level as usize, Ordering::Relaxed);
+        }
+    }
+
+    update_max_level();
+
+    serial::restore();
+
+    let [row, column] = state.console_cursor;
+    console::restore(row as usize, column as usize);
+}
+
+/// Apply the log levels of the boot parameters
+///
+/// Format: `serial=info,ring=trace`
+///
+/// Note: the bootloader does not pass a command line, the parameters are given at build time (`KERNEL_LOG` env variable)
+fn apply_boot_params() {
+    let Some(params) = option_env!("KERNEL_LOG") else {
+        return;
+    };
+
+    for param in params.split(',') {
+        let Some((name, level)) = param.split_once('=') else {
+            continue;
+        };
+
+        let sink = match name.trim() {
+            "serial" => LogSink::Serial,
+            "ring" => LogSink::Ring,
+            "console" => LogSink::Console,
+            _ => continue,
+        };
+
+        if let Ok(level) = LevelFilter::from_str(level.trim()) {
+            set_level(sink, level);
+        }
+    }
+}
+
+pub fn init() {
+    // Note: if set logger fails, there is not much we can do since panic also use the logger
+    let _ = log::set_logger(&LOGGER);
+    apply_boot_params();
+    update_max_level();
+}
+
+/// Size in bytes, shown with binary prefixes and one decimal (eg: "12.3 MiB")
+///
+/// Note: minimal copy of `libruntime::format::Bytes`, which the kernel cannot use
+pub struct Bytes(pub usize);
+
+impl fmt::Display for Bytes {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        const UNITS: [&str; 4] = ["KiB", "MiB", "GiB", "TiB"];
+
+        if self.0 < 1024 {
+            return write!(f, "{} B", self.0);
+        }
+
+        let mut divisor = 1024;
+        let mut unit = 0;
+        while unit + 1 < UNITS.len() && self.0 / divisor >= 1024 {
+            divisor *= 1024;
+            unit += 1;
+        }
+
+        let tenths = (self.0 as u128 * 10 / divisor as u128) as usize;
+        write!(f, "{}.{} {}", tenths / 10, tenths % 10, UNITS[unit])
+    }
+}
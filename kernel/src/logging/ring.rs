@@ -0,0 +1,61 @@
+use core::fmt::{self, Write};
+
+use log::Record;
+use spin::Mutex;
+
+use super::{Format, Sink};
+
+/// Size of the ring, older records are overwritten
+const RING_SIZE: usize = 64 * 1024;
+
+/// In-memory ring of the last records, as text
+///
+/// Note: fixed size, the logger is used before the heap is initialized
+static RING: Mutex<Ring> = Mutex::new(Ring {
+    buffer: [0; RING_SIZE],
+    head: 0,
+});
+
+struct Ring {
+    buffer: [u8; RING_SIZE],
+    /// Number of bytes written since boot
+    head: u64,
+}
+
+impl Write for Ring {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        for &byte in s.as_bytes() {
+            self.buffer[(self.head % RING_SIZE as u64) as usize] = byte;
+            self.head += 1;
+        }
+
+        Ok(())
+    }
+}
+
+pub struct RingSink;
+
+impl Sink for RingSink {
+    fn write(&self, record: &Record, _format: Option<&Format>) {
+        let mut ring = RING.lock();
+        let _ = writeln!(ring, "{} - {}", record.level(), record.args());
+    }
+}
+
+/// Read the ring from `cursor` (number of bytes since boot), and move it after the data read
+///
+/// If the data at `cursor` has been overwritten, reading starts from the oldest data available.
+pub fn read(cursor: &mut u64, buffer: &mut [u8]) -> usize {
+    let ring = RING.lock();
+
+    let oldest = ring.head.saturating_sub(RING_SIZE as u64);
+    let start = (*cursor).clamp(oldest, ring.head);
+    let len = buffer.len().min((ring.head - start) as usize);
+
+    for (index, byte) in buffer[..len].iter_mut().enumerate() {
+        *byte = ring.buffer[((start + index as u64) % RING_SIZE as u64) as usize];
+    }
+
+    *cursor = start + len as u64;
+    len
+}
@@ -0,0 +1,162 @@
+#[cfg(not(feature = "binary-log"))]
+use core::fmt::Write;
+use core::sync::atomic::{AtomicBool, Ordering};
+use lazy_static::lazy_static;
+use log::Record;
+
+use super::{Format, Sink};
+
+lazy_static! {
+    static ref SERIAL1: spin::Mutex<uart_16550::SerialPort> = {
+        let mut port = unsafe { uart_16550::SerialPort::new(0x3F8) };
+        if !HANDED_OFF.load(Ordering::Relaxed) {
+            port.init();
+        }
+        spin::Mutex::new(port)
+    };
+}
+
+/// Set when the port is handed off by the previous kernel (see `kexec`)
+///
+/// It is already initialized: it is not reset, so that the end of the output of the previous kernel is not lost.
+static HANDED_OFF: AtomicBool = AtomicBool::new(false);
+
+/// Take over the port from the previous kernel, must be called before the first record
+pub fn restore() {
+    HANDED_OFF.store(true, Ordering::Relaxed);
+}
+
+/// Serial port (COM1)
+pub struct SerialSink;
+
+impl Sink for SerialSink {
+    fn write(&self, record: &Record, _format: Option<&Format>) {
+        let mut serial = SERIAL1.lock();
+
+        #[cfg(not(feature = "binary-log"))]
+        let _ = writeln!(serial, "{} - {}", record.level(), record.args());
+
+        #[cfg(feature = "binary-log")]
+        binary::write(&mut serial, record, _format);
+    }
+}
+
+#[cfg(feature = "binary-log")]
+mod binary {
+    use core::fmt::{self, Write};
+    use log::Record;
+    use spin::Mutex;
+    use syscalls::{log_record, TICK_NS};
+    use uart_16550::SerialPort;
+
+    use super::super::{is_arg_boundary, Format};
+    use crate::user::{thread, timer};
+
+    /// Max number of targets remembered as defined. Past it, definitions are repeated.
+    const MAX_TARGETS: usize = 256;
+
+    /// Ids of the targets already defined in the stream
+    ///
+    /// Note: fixed size, the logger is used before the heap is initialized
+    static TARGETS: Mutex<([u32; MAX_TARGETS], usize)> = Mutex::new(([0; MAX_TARGETS], 0));
+
+    struct RawWriter<'a>(&'a mut SerialPort);
+
+    impl Write for RawWriter<'_> {
+        fn write_str(&mut self, s: &str) -> fmt::Result {
+            for &byte in s.as_bytes() {
+                self.0.send_raw(byte);
+            }
+
+            Ok(())
+        }
+    }
+
+    /// Writes the args of a formatted record, the text of the format string is dropped
+    struct ArgsWriter<'a> {
+        serial: &'a mut SerialPort,
+        in_arg: bool,
+        count: usize,
+    }
+
+    impl Write for ArgsWriter<'_> {
+        fn write_str(&mut self, s: &str) -> fmt::Result {
+            if is_arg_boundary(s) {
+                if self.in_arg {
+                    self.serial.send_raw(0);
+                    self.count += 1;
+                }
+                self.in_arg = !self.in_arg;
+            } else if self.in_arg {
+                for &byte in s.as_bytes() {
+                    self.serial.send_raw(byte);
+                }
+            }
+
+            Ok(())
+        }
+    }
+
+    pub fn write(serial: &mut SerialPort, record: &Record, format: Option<&Format>) {
+        let target_id = log_record::target_id(record.target());
+
+        if define_target(target_id) {
+            log_record::encode_target(target_id, record.target(), &mut |byte| {
+                serial.send_raw(byte)
+            });
+        }
+
+        let (pid, tid) = thread::try_current_thread()
+            .map_or((0, 0), |thread| (thread.process().id(), thread.id()));
+
+        let header = log_record::RecordHeader {
+            timestamp: timer::ticks() * TICK_NS,
+            pid,
+            tid,
+            level: record.level() as usize as u8,
+            target_id,
+        };
+
+        match format {
+            Some(format) if format.internable => {
+                header.encode_formatted(format.id, format.args_count, &mut |byte| {
+                    serial.send_raw(byte)
+                });
+
+                let mut writer = ArgsWriter {
+                    serial,
+                    in_arg: false,
+                    count: 0,
+                };
+                let _ = writer.write_fmt(*record.args());
+
+                // Keep the stream decodable if formatting stopped early
+                for _ in writer.count..format.args_count {
+                    writer.serial.send_raw(0);
+                }
+            }
+            _ => {
+                header.encode(&mut |byte| serial.send_raw(byte));
+                let _ = write!(RawWriter(serial), "{}", record.args());
+                serial.send_raw(0);
+            }
+        }
+    }
+
+    /// Returns true if the target must be defined in the stream
+    fn define_target(id: u32) -> bool {
+        let mut targets = TARGETS.lock();
+        let (ids, len) = &mut *targets;
+
+        if ids[..*len].contains(&id) {
+            return false;
+        }
+
+        if *len < MAX_TARGETS {
+            ids[*len] = id;
+            *len += 1;
+        }
+
+        true
+    }
+}
@@ -23,23 +23,30 @@ extern crate bootloader_api;
 extern crate lazy_static;
 
 mod devices;
+#[cfg(feature = "gdbstub")]
+mod gdbstub;
 mod gdt;
 mod interrupts;
+mod kexec;
 mod logging;
 mod memory;
 
 mod user;
 
-use crate::memory::VirtAddr;
+use crate::logging::{error, info};
+use crate::memory::{PhysAddr, VirtAddr};
 use bootloader_api::{config::Mapping, entry_point, BootInfo, BootloaderConfig};
 use core::panic::PanicInfo;
-use log::{error, info};
 use syscalls::SyscallNumber;
 use x86_64::registers::model_specific::{Efer, EferFlags};
 
 const CONFIG: BootloaderConfig = {
     let mut config = BootloaderConfig::new_default();
-    config.mappings.dynamic_range_start = Some(0xFFFF_8000_0000_0000);
+    // KASLR: the kernel (and the bootloader mappings) are placed at random level 4 entries, with a random offset.
+    // Level 4 entries #256 (vmalloc) and #257 (physical memory mapping) are kept out of the dynamic range.
+    // Disabled with the gdbstub, so that the kernel base stays the same from a boot to the next one.
+    config.mappings.aslr = !cfg!(feature = "gdbstub");
+    config.mappings.dynamic_range_start = Some(0xFFFF_8100_0000_0000);
     config.mappings.physical_memory = Some(Mapping::FixedAddress(0xFFFF_8080_0000_0000));
     config
 };
@@ -48,6 +55,8 @@ entry_point!(kernel_main, config = &CONFIG);
 
 fn kernel_main(boot_info: &'static mut BootInfo) -> ! {
     logging::init();
+    // Before the first record: restores the log sinks if the kernel was reloaded
+    kexec::init(boot_info);
 
     let efer_flags = Efer::read();
     assert!(efer_flags.contains(EferFlags::LONG_MODE_ENABLE));
@@ -61,14 +70,28 @@ fn kernel_main(boot_info: &'static mut BootInfo) -> ! {
         version.version_patch()
     );
 
+    // Needed to load the symbols in a debugger (eg: gdb `add-symbol-file <kernel> -o <offset>`)
+    info!("Kernel image offset: {:#x}", boot_info.kernel_image_offset);
+
     let physical_memory_offset = VirtAddr::new(*boot_info.physical_memory_offset.as_ref().unwrap());
 
+    if let Some(framebuffer) = boot_info.framebuffer.as_mut() {
+        logging::console_init(framebuffer, physical_memory_offset);
+    }
+
     let ramdisk_start = *boot_info.ramdisk_addr.as_ref().expect("No ramdisk defined") as usize;
     let ramdisk = ramdisk_start..(ramdisk_start + boot_info.ramdisk_len as usize);
 
+    // ACPI tables are not in the boot info mapping, they can be read after memory init
+    let rsdp = boot_info.rsdp_addr.into_option().map(PhysAddr::new);
+
     gdt::init();
     interrupts::init_base();
+
+    // The bootloader framebuffer mapping is dropped by the memory initialization
+    logging::console_detach();
     memory::init(physical_memory_offset, &boot_info.memory_regions, &ramdisk);
+    logging::console_remap();
 
     // Note:
     // boot_info is unmapped from here.
@@ -76,7 +99,12 @@ fn kernel_main(boot_info: &'static mut BootInfo) -> ! {
 
     // From here we can use normal allocations in the kernel.
 
-    devices::init();
+    devices::init(rsdp);
+    user::timer::init_wall_clock(devices::rtc::read());
+
+    #[cfg(feature = "gdbstub")]
+    gdbstub::init();
+
     interrupts::init_userland();
     user::init();
 
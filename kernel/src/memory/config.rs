@@ -3,6 +3,7 @@ use x86_64::VirtAddr;
 pub const PAGE_SIZE: usize = 4096;
 pub const KERNEL_STACK_SIZE: usize = PAGE_SIZE * 5;
 
+/// Start of kernel space (the kernel image itself is placed at a random address by the bootloader)
 pub const KERNEL_START: VirtAddr = VirtAddr::new_truncate(0xFFFF_8000_0000_0000);
 
 pub const VMALLOC_START: VirtAddr = VirtAddr::new_truncate(0xFFFF_8000_4000_0000);
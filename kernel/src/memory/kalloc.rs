@@ -1,10 +1,10 @@
 use core::alloc::{GlobalAlloc, Layout};
 use core::ptr::{self, NonNull};
 use core::sync::atomic::{AtomicUsize, Ordering};
-use log::trace;
 use spin::Mutex;
 use x86_64::{align_up, VirtAddr};
 
+use crate::logging::trace;
 use crate::memory::PAGE_SIZE;
 
 use super::slab::ZoneAllocator;
@@ -1,9 +1,10 @@
 use core::{alloc::Layout, mem, ptr::NonNull, usize};
 
-use log::{debug, info};
 use spin::RwLock;
 use x86_64::{structures::paging::mapper::MapToError, PhysAddr, VirtAddr};
 
+use crate::logging::{debug, info};
+
 use super::{
     buddy::{self, BuddyAllocator},
     config::{KERNEL_START, PAGE_SIZE, VMALLOC_END, VMALLOC_START},
@@ -22,7 +23,7 @@ use super::{
 4096 >> 18 =
 
 => Buddy allocator for vm space
-=> reserve 1G for kernel
+=> keep the first 1G unused (kernel image used to be there, it is now placed at a random level 4 entry)
 
 */
 
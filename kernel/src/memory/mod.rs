@@ -12,23 +12,25 @@ use core::{mem, slice};
 
 use alloc::format;
 use bootloader_api::info::MemoryRegions;
-use log::info;
 
 pub use config::{KERNEL_START, PAGE_SIZE};
+#[cfg(feature = "gdbstub")]
+pub use paging::current_permissions;
 pub use paging::{
-    create_adress_space, drop_initial_kernel_stack, drop_initial_ramdisk,
-    set_current_address_space, AdditionalFlags, AddressSpace, Permissions,
+    create_adress_space, drop_initial_kernel_stack, drop_initial_ramdisk, kernel_l4_entry,
+    phys_to_virt, set_current_address_space, AdditionalFlags, AddressSpace, Permissions,
 };
 pub use phys::{AllocatorError, FrameRef};
 use x86_64::structures::paging::{mapper::MapToError, Size4KiB};
 pub use x86_64::{align_down, align_up, PhysAddr, VirtAddr};
 
 pub type MapError = MapToError<Size4KiB>;
-pub use syscalls::{KallocStats, KvmStats, MemoryStats, PhysStats};
+pub use syscalls::{FrameAudit, KallocStats, KvmStats, MemoryStats, PhysStats};
 pub use x86_64::structures::paging::mapper::UnmapError;
 
+use crate::logging::info;
+use crate::logging::Bytes;
 use config::KERNEL_STACK_SIZE;
-use paging::phys_to_virt;
 
 pub fn init(phys_mapping: VirtAddr, memory_regions: &MemoryRegions, ramdisk: &Range<usize>) {
     phys::init(phys_mapping, memory_regions);
@@ -41,32 +43,38 @@ pub fn init(phys_mapping: VirtAddr, memory_regions: &MemoryRegions, ramdisk: &Ra
     kvm::init();
 
     let stats = stats();
-    const MEGA: usize = 1 * 1024 * 1024;
     info!("Memory allocator initialized. Initial stats:");
     info!(
-        "phys: total={} ({}MB), free={} ({}MB)",
+        "phys: total={} ({}), free={} ({})",
         stats.phys.total,
-        stats.phys.total / MEGA,
+        Bytes(stats.phys.total),
         stats.phys.free,
-        stats.phys.free / MEGA
+        Bytes(stats.phys.free)
+    );
+    info!(
+        "phys: zeroed={} ({}), zeroed hits={}, zeroed misses={}",
+        stats.phys.zeroed,
+        Bytes(stats.phys.zeroed),
+        stats.phys.zeroed_hits,
+        stats.phys.zeroed_misses
     );
     info!(
         "kvm: total={} ({:#X}), used={} ({:#X})",
         stats.kvm.total, stats.kvm.total, stats.kvm.used, stats.kvm.used
     );
     info!(
-        "kalloc: slabs: user={} ({}MB), allocated={} ({}MB)",
+        "kalloc: slabs: user={} ({}), allocated={} ({})",
         stats.kalloc.slabs_user,
-        stats.kalloc.slabs_user / MEGA,
+        Bytes(stats.kalloc.slabs_user),
         stats.kalloc.slabs_allocated,
-        stats.kalloc.slabs_allocated / MEGA
+        Bytes(stats.kalloc.slabs_allocated)
     );
     info!(
-        "kalloc: kvm: user={} ({}MB), allocated={} ({}MB)",
+        "kalloc: kvm: user={} ({}), allocated={} ({})",
         stats.kalloc.kvm_user,
-        stats.kalloc.kvm_user / MEGA,
+        Bytes(stats.kalloc.kvm_user),
         stats.kalloc.kvm_allocated,
-        stats.kalloc.kvm_allocated / MEGA
+        Bytes(stats.kalloc.kvm_allocated)
     );
 }
 
@@ -90,6 +98,49 @@ pub fn phys_allocate() -> Option<FrameRef> {
     }
 }
 
+/// Allocate a frame with zeroed content
+pub fn phys_allocate_zeroed() -> Option<FrameRef> {
+    match phys::allocate_zeroed() {
+        Ok(frame) => Some(frame),
+        Err(err) => {
+            // ensure all types are matched
+            match err {
+                phys::AllocatorError::NoMemory => None,
+            }
+        }
+    }
+}
+
+/// Allocate `count` contiguous frames, returns the first one
+///
+/// The frames are not zeroed, and are never freed: they are meant to be handed off (see `kexec`).
+pub fn phys_allocate_contiguous(count: usize) -> Option<PhysAddr> {
+    match phys::allocate_contiguous(count) {
+        Ok(frame) => Some(frame),
+        Err(err) => {
+            // ensure all types are matched
+            match err {
+                phys::AllocatorError::NoMemory => None,
+            }
+        }
+    }
+}
+
+/// Use idle time to pre-zero free frames
+pub fn phys_scrub() {
+    phys::scrub();
+}
+
+/// Check the physical allocator consistency, and fill the allocator part of the audit
+pub fn phys_audit(audit: &mut FrameAudit) {
+    phys::audit(audit);
+}
+
+/// Get the number of references held on the frame, or None if the frame is not managed by the allocator
+pub fn phys_ref_count(frame: PhysAddr) -> Option<usize> {
+    phys::ref_count(frame)
+}
+
 /// Checks whether the address is in userspace.
 #[inline]
 pub fn is_userspace(addr: VirtAddr) -> bool {
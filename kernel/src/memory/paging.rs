@@ -1,10 +1,11 @@
 use core::{mem, ops::Range, ptr};
-use log::{info, trace};
+
+use crate::logging::{info, trace};
 
 use x86_64::{
     instructions::tlb,
     registers::{
-        control::{Cr3, Cr3Flags},
+        control::{Cr0, Cr0Flags, Cr3, Cr3Flags},
         model_specific::{Efer, EferFlags},
     },
     structures::paging::{
@@ -17,7 +18,6 @@ use x86_64::{
 };
 
 use super::{
-    access_phys,
     config::{KERNEL_START, PAGE_SIZE},
     phys::{self, AllocatorError, FrameRef},
 };
@@ -41,16 +41,23 @@ Level 1 entry size: 0x1000           4K
 ---
 
 Bootloader state:
-INFO - Kernel      0xFFFF_8000_0000_0000
 INFO - Phys mem    0xFFFF_8080_0000_0000
-INFO - Stack       0xFFFF_8100_0000_0000
-INFO - Framebuffer 0xFFFF_8180_0000_0000
-INFO - Boot info   0xFFFF_8200_0000_0000
+Each one at a random L4 entry >= #258 (KASLR), with a random offset:
+INFO - Kernel
+INFO - Stack
+INFO - Framebuffer
+INFO - Boot info
+INFO - Ramdisk
 
 After full initialization (drop of initial kernel stack):
-- Page Table L4 entry #256 (0xFFFF_8000_0000_0000):  Kernel + vmalloc
+- Page Table L4 entry #256 (0xFFFF_8000_0000_0000):  vmalloc
 - Page Table L4 entry #257 (0xFFFF_8080_0000_0000):  Physical memory mapping
-This 2 entries needs to be copied to all Page Tables created for user processes
+- Page Table L4 entry (random):  Kernel
+This 3 entries needs to be copied to all Page Tables created for user processes
+
+W^X:
+- kernel image pages are mapped from the ELF segments: text is not writable, data is not executable (checked at init)
+- physical memory mapping and vmalloc are not executable (NO_EXECUTE on their upper level entries)
 
 */
 
@@ -74,8 +81,7 @@ pub fn create_adress_space() -> Result<AddressSpace, AllocatorError> {
     // Create new empty page table
 
     unsafe {
-        let mut frame = phys::allocate()?;
-        access_phys(&frame).fill(0);
+        let mut frame = phys::allocate_zeroed()?;
 
         let virt = PHYSICAL_MAPPING_ADDRESS + frame.borrow().as_u64();
 
@@ -101,6 +107,11 @@ pub fn init(phys_mapping: VirtAddr, ramdisk: &Range<usize>) {
             *flags |= EferFlags::NO_EXECUTE_ENABLE;
         });
 
+        // Read-only pages (eg: kernel text) must also be read-only for the kernel itself
+        Cr0::update(|flags| {
+            *flags |= Cr0Flags::WRITE_PROTECT;
+        });
+
         PHYSICAL_MAPPING_ADDRESS = phys_mapping;
         KERNEL_ADDRESS_SPACE.page_table = get_current_page_table();
 
@@ -113,8 +124,13 @@ pub fn init(phys_mapping: VirtAddr, ramdisk: &Range<usize>) {
         let stack_var = 42;
         let page_table = KERNEL_ADDRESS_SPACE.get_page_table();
 
-        let (kernel_l4_index, kernel_start, kernel_end) =
-            prepare_mapping(page_table, KERNEL_START, true);
+        // The kernel is at a random address (KASLR): find it from a pointer to its code
+        let (kernel_l4_index, kernel_start, kernel_end) = prepare_mapping(
+            page_table,
+            VirtAddr::new(init as *const () as u64),
+            true,
+            true,
+        );
         info!(
             "Kernel: {:?} -> {:?} (size={})",
             kernel_start,
@@ -123,7 +139,7 @@ pub fn init(phys_mapping: VirtAddr, ramdisk: &Range<usize>) {
         );
 
         let (phys_mapping_l4_index, phys_mapping_start, phys_mapping_end) =
-            prepare_mapping(page_table, PHYSICAL_MAPPING_ADDRESS, false);
+            prepare_mapping(page_table, PHYSICAL_MAPPING_ADDRESS, false, false);
         info!(
             "Physical mapping: {:?} -> {:?} (size={})",
             phys_mapping_start,
@@ -132,7 +148,7 @@ pub fn init(phys_mapping: VirtAddr, ramdisk: &Range<usize>) {
         );
 
         let (kernel_stack_l4_index, kernel_stack_start, kernel_stack_end) =
-            prepare_mapping(page_table, VirtAddr::from_ptr(&stack_var), true);
+            prepare_mapping(page_table, VirtAddr::from_ptr(&stack_var), true, false);
         info!(
             "Kernel stack: {:?} -> {:?} (size={})",
             kernel_stack_start,
@@ -141,7 +157,7 @@ pub fn init(phys_mapping: VirtAddr, ramdisk: &Range<usize>) {
         );
 
         let (ramdisk_l4_index, ramdisk_start, ramdisk_end) =
-            prepare_mapping(page_table, VirtAddr::new(ramdisk.start as u64), true);
+            prepare_mapping(page_table, VirtAddr::new(ramdisk.start as u64), true, false);
         info!(
             "Ramdisk: {:?} -> {:?} (size={})",
             ramdisk_start,
@@ -228,6 +244,8 @@ pub fn drop_initial_ramdisk() {
 /// Preparation:
 /// - verify that the mapped physical pages are marked as used
 /// - add the GLOBAL flag and remove the USER_ACCESSIBLE flag on the entries
+/// - if the region is not executable, add the NO_EXECUTE flag on the level 4 entry,
+///   else check that no page is both writable and executable (W^X)
 ///
 /// Take a pointer into the region, and get its level 4 index to process
 ///
@@ -236,6 +254,7 @@ unsafe fn prepare_mapping(
     page_table: &mut PageTable,
     pointer: VirtAddr,
     check_frame_refs: bool,
+    executable: bool,
 ) -> (PageTableIndex, VirtAddr, VirtAddr) {
     let l4_index = Page::<Size4KiB>::containing_address(pointer).p4_index();
     let l4_entry = &mut page_table[l4_index];
@@ -246,6 +265,10 @@ unsafe fn prepare_mapping(
 
     fix_flags(l4_entry);
 
+    if !executable {
+        l4_entry.set_flags(l4_entry.flags() | PageTableFlags::NO_EXECUTE);
+    }
+
     debug_assert!(
         phys::used(l4_entry.addr()),
         "frame {:?} used by PageTable is not marked as used.",
@@ -267,6 +290,7 @@ unsafe fn prepare_mapping(
                 l3_index,
                 0,
                 0,
+                l3_entry.flags(),
                 l3_entry.addr(),
                 &mut begin,
                 &mut end,
@@ -296,6 +320,7 @@ unsafe fn prepare_mapping(
                     l3_index,
                     l2_index,
                     0,
+                    l2_entry.flags(),
                     l2_entry.addr(),
                     &mut begin,
                     &mut end,
@@ -324,6 +349,7 @@ unsafe fn prepare_mapping(
                     l3_index,
                     l2_index,
                     l1_index,
+                    l1_entry.flags(),
                     l1_entry.addr(),
                     &mut begin,
                     &mut end,
@@ -341,6 +367,7 @@ fn prepare_page<S: PageSize>(
     l3_index: usize,
     l2_index: usize,
     l1_index: usize,
+    flags: PageTableFlags,
     frame: PhysAddr,
     begin: &mut VirtAddr,
     end: &mut VirtAddr,
@@ -359,6 +386,11 @@ fn prepare_page<S: PageSize>(
         "frame {frame:?} (address={address:?}) is not valid."
     );
 
+    assert!(
+        !flags.contains(PageTableFlags::WRITABLE) || flags.contains(PageTableFlags::NO_EXECUTE),
+        "W^X violation: page {address:?} is writable and executable."
+    );
+
     if address < *begin {
         *begin = address;
     }
@@ -438,6 +470,41 @@ unsafe fn get_current_page_table() -> &'static mut PageTable {
     phys_frame_to_page_table(frame.start_address())
 }
 
+/// Get the permissions of the page containing `addr` in the current address space
+///
+/// Returns `Permissions::NONE` if the page is not mapped
+pub fn current_permissions(addr: VirtAddr) -> Permissions {
+    let manager =
+        unsafe { OffsetPageTable::new(get_current_page_table(), PHYSICAL_MAPPING_ADDRESS) };
+
+    match manager.translate(addr) {
+        TranslateResult::Mapped { flags, .. } => {
+            let mut perm = Permissions::READ;
+
+            if flags.contains(PageTableFlags::WRITABLE) {
+                perm |= Permissions::WRITE;
+            }
+
+            if !flags.contains(PageTableFlags::NO_EXECUTE) {
+                perm |= Permissions::EXECUTE;
+            }
+
+            perm
+        }
+
+        _ => Permissions::NONE,
+    }
+}
+
+/// Get the level 4 entry of the kernel address space which covers `addr`
+///
+/// Used to share a kernel mapping with the page tables of a new kernel image (see `kexec`)
+pub fn kernel_l4_entry(addr: VirtAddr) -> PageTableEntry {
+    let index = Page::<Size4KiB>::containing_address(addr).p4_index();
+
+    unsafe { KERNEL_ADDRESS_SPACE.get_page_table()[index].clone() }
+}
+
 /// Install the provided address space as the current one
 ///
 /// # Safety
@@ -750,7 +817,8 @@ fn create_parent_flags(addr: VirtAddr) -> PageTableFlags {
     if is_user_address(addr) {
         flags |= PageTableFlags::USER_ACCESSIBLE;
     } else {
-        flags |= PageTableFlags::GLOBAL;
+        // The kernel never maps executable memory after boot (the kernel image is mapped by the bootloader)
+        flags |= PageTableFlags::GLOBAL | PageTableFlags::NO_EXECUTE;
     }
 
     return flags;
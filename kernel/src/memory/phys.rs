@@ -4,16 +4,22 @@ use core::{
 };
 
 use bootloader_api::info::{MemoryRegionKind, MemoryRegions};
-use log::info;
 use spin::RwLock;
 use x86_64::{PhysAddr, VirtAddr};
 
-use super::{PhysStats, PAGE_SIZE};
+use crate::logging::info;
+
+use super::{paging::phys_to_virt, FrameAudit, PhysStats, PAGE_SIZE};
+
+/// Max number of frames zeroed by one idle scrub pass
+const SCRUB_BATCH_SIZE: usize = 64;
 
 #[derive(Debug)]
 #[repr(C)]
 struct Descriptor {
     ref_count: usize,
+    /// Only meaningful for free frames: frame content is known to be zero
+    zeroed: bool,
     prev: *mut Descriptor,
     next: *mut Descriptor,
 }
@@ -22,12 +28,14 @@ impl Descriptor {
     const fn new() -> Self {
         Self {
             ref_count: 0,
+            zeroed: false,
             prev: ptr::null_mut(),
             next: ptr::null_mut(),
         }
     }
 
     fn r#ref(&mut self) {
+        debug_assert!(self.ref_count < usize::MAX);
         self.ref_count += 1;
     }
 
@@ -109,6 +117,25 @@ impl List {
         self.count -= 1;
     }
 
+    /// Call `f` on each descriptor of the list
+    unsafe fn for_each<F: FnMut(&Descriptor)>(&self, mut f: F) {
+        if self.head.is_null() {
+            return;
+        }
+
+        let mut item = self.head;
+
+        loop {
+            f(&*item);
+
+            item = (*item).next;
+
+            if item == self.head {
+                return;
+            }
+        }
+    }
+
     unsafe fn has(&self, desc: *mut Descriptor) -> bool {
         if self.head.is_null() {
             return false;
@@ -133,7 +160,12 @@ impl List {
 struct Allocator {
     descriptors: *mut [Descriptor],
     used_list: List,
+    /// Free frames with unknown content
     free_list: List,
+    /// Free frames already zeroed by idle scrubbing
+    zeroed_list: List,
+    zeroed_hits: usize,
+    zeroed_misses: usize,
 }
 
 unsafe impl Sync for Allocator {}
@@ -145,6 +177,9 @@ impl Allocator {
             descriptors: slice_from_raw_parts_mut(ptr::null_mut(), 0),
             used_list: List::new(),
             free_list: List::new(),
+            zeroed_list: List::new(),
+            zeroed_hits: 0,
+            zeroed_misses: 0,
         }
     }
 
@@ -172,20 +207,40 @@ impl Allocator {
         }
     }
 
+    /// Allocate a frame with unknown content
+    ///
+    /// Note: zeroed frames are kept for zeroed allocations, and only used if no other frame is free
     unsafe fn allocate(&mut self) -> Result<PhysAddr, AllocatorError> {
-        let desc = self.free_list.head;
+        let desc = if !self.free_list.head.is_null() {
+            self.free_list.head
+        } else {
+            self.zeroed_list.head
+        };
 
         if desc.is_null() {
             return Err(AllocatorError::NoMemory);
         }
 
-        self.free_list.remove(desc);
-        self.used_list.add(desc);
-        let desc_ref = &mut (*desc);
+        self.take(desc);
 
-        desc_ref.r#ref();
+        Ok(self.desc_to_frame(desc))
+    }
+
+    /// Allocate a frame, preferably already zeroed
+    ///
+    /// Returns the frame, and true if it is already zeroed
+    unsafe fn allocate_zeroed(&mut self) -> Result<(PhysAddr, bool), AllocatorError> {
+        let desc = self.zeroed_list.head;
+
+        if desc.is_null() {
+            self.zeroed_misses += 1;
+            return Ok((self.allocate()?, false));
+        }
 
-        Ok(self.desc_to_frame(desc_ref))
+        self.zeroed_hits += 1;
+        self.take(desc);
+
+        Ok((self.desc_to_frame(desc), true))
     }
 
     unsafe fn allocate_at(&mut self, frame: PhysAddr) -> Result<(), AllocatorError> {
@@ -196,19 +251,88 @@ impl Allocator {
             return Err(AllocatorError::NoMemory);
         }
 
-        self.free_list.remove(desc);
-        self.used_list.add(desc);
+        self.take(desc);
+
+        Ok(())
+    }
+
+    /// Allocate `count` contiguous frames, returns the first one
+    ///
+    /// Note: linear scan of the descriptors, only for rare uses
+    unsafe fn allocate_contiguous(&mut self, count: usize) -> Result<PhysAddr, AllocatorError> {
+        let descs = &(*self.descriptors);
+        let mut start = 0;
+
+        // Frame 0 is never usable
+        for (index, desc) in descs.iter().enumerate().skip(1) {
+            if desc.used() {
+                start = 0;
+                continue;
+            }
+
+            if start == 0 {
+                start = index;
+            }
+
+            if index + 1 - start == count {
+                for index in start..=index {
+                    self.take(self.descriptors.as_mut_ptr().add(index));
+                }
+
+                return Ok(PhysAddr::new((start * PAGE_SIZE) as u64));
+            }
+        }
+
+        Err(AllocatorError::NoMemory)
+    }
+
+    /// Move a free frame to the used list, with one reference
+    unsafe fn take(&mut self, desc: *mut Descriptor) {
         let desc_ref = &mut (*desc);
+        debug_assert!(!desc_ref.used());
+
+        if desc_ref.zeroed {
+            self.zeroed_list.remove(desc);
+            desc_ref.zeroed = false;
+        } else {
+            self.free_list.remove(desc);
+        }
 
+        self.used_list.add(desc);
         desc_ref.r#ref();
+    }
 
-        Ok(())
+    /// Zero up to `count` free frames, and move them to the zeroed list
+    ///
+    /// Returns the number of frames zeroed
+    unsafe fn scrub(&mut self, count: usize) -> usize {
+        let mut zeroed = 0;
+
+        while zeroed < count {
+            let desc = self.free_list.head;
+            if desc.is_null() {
+                break;
+            }
+
+            zero_frame(self.desc_to_frame(desc));
+
+            self.free_list.remove(desc);
+            (*desc).zeroed = true;
+            self.zeroed_list.add(desc);
+
+            zeroed += 1;
+        }
+
+        zeroed
     }
 
     unsafe fn r#ref(&mut self, frame: PhysAddr) {
         let desc = self.frame_to_desc(frame);
         let desc_ref = &mut (*desc);
 
+        // Taking a new reference requires to already hold one
+        debug_assert!(desc_ref.used(), "Ref unused frame {:?}", frame);
+
         desc_ref.r#ref();
     }
 
@@ -349,7 +473,53 @@ pub fn stats() -> PhysStats {
 
     PhysStats {
         total: allocator.descriptors.len() * PAGE_SIZE,
-        free: allocator.free_list.count * PAGE_SIZE,
+        free: (allocator.free_list.count + allocator.zeroed_list.count) * PAGE_SIZE,
+        zeroed: allocator.zeroed_list.count * PAGE_SIZE,
+        zeroed_hits: allocator.zeroed_hits,
+        zeroed_misses: allocator.zeroed_misses,
+    }
+}
+
+/// Check the allocator lists against frames reference counts
+pub fn audit(audit: &mut FrameAudit) {
+    let allocator = ALLOCATOR.read();
+
+    unsafe {
+        let mut list_errors = 0;
+
+        allocator.used_list.for_each(|desc| {
+            if !desc.used() || desc.zeroed {
+                list_errors += 1;
+            }
+        });
+
+        allocator.free_list.for_each(|desc| {
+            if desc.used() || desc.zeroed {
+                list_errors += 1;
+            }
+        });
+
+        allocator.zeroed_list.for_each(|desc| {
+            if desc.used() || !desc.zeroed {
+                list_errors += 1;
+            }
+        });
+
+        audit.used_frames = allocator.used_list.count;
+        audit.list_errors = list_errors;
+    }
+}
+
+/// Get the reference count of the frame, or None if the frame is not managed by the allocator
+pub fn ref_count(frame: PhysAddr) -> Option<usize> {
+    let allocator = ALLOCATOR.read();
+    if !allocator.check_frame(frame) {
+        return None;
+    }
+
+    unsafe {
+        let desc = allocator.frame_to_desc(frame);
+        Some((*desc).ref_count)
     }
 }
 
@@ -381,6 +551,40 @@ pub fn allocate() -> Result<FrameRef, AllocatorError> {
     }
 }
 
+/// Allocate a zeroed frame
+///
+/// Frames pre-zeroed at idle time are used first, else the frame is zeroed inline.
+pub fn allocate_zeroed() -> Result<FrameRef, AllocatorError> {
+    let (frame, zeroed) = {
+        let mut allocator = ALLOCATOR.write();
+        unsafe { allocator.allocate_zeroed()? }
+    };
+
+    // Zero outside of the allocator lock
+    if !zeroed {
+        unsafe { zero_frame(frame) };
+    }
+
+    unsafe { Ok(FrameRef::new(frame)) }
+}
+
+/// Pre-zero a batch of free frames, so that later zeroed allocations do not pay the cost
+///
+/// Note: expected to be called when the CPU is idle
+pub fn scrub() {
+    let mut allocator = ALLOCATOR.write();
+
+    unsafe {
+        allocator.scrub(SCRUB_BATCH_SIZE);
+    }
+}
+
+/// Safety: the frame must not be in use
+unsafe fn zero_frame(frame: PhysAddr) {
+    let data: *mut u8 = phys_to_virt(frame).as_mut_ptr();
+    ptr::write_bytes(data, 0, PAGE_SIZE);
+}
+
 pub fn allocate_at(frame: PhysAddr) -> Result<FrameRef, AllocatorError> {
     let mut allocator = ALLOCATOR.write();
 
@@ -390,6 +594,15 @@ pub fn allocate_at(frame: PhysAddr) -> Result<FrameRef, AllocatorError> {
     }
 }
 
+/// Allocate `count` contiguous frames, returns the first one
+///
+/// The frames are returned borrowed: each one holds a reference (see `FrameRef::borrow`).
+pub fn allocate_contiguous(count: usize) -> Result<PhysAddr, AllocatorError> {
+    let mut allocator = ALLOCATOR.write();
+
+    unsafe { allocator.allocate_contiguous(count) }
+}
+
 #[derive(Debug)]
 pub struct FrameRef {
     /// Note: since we do not use the 0 frame, 0 is used as an "empty ref"
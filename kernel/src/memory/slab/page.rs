@@ -5,8 +5,7 @@ use core::{
     sync::atomic::{AtomicU64, Ordering},
 };
 
-use log::trace;
-
+use crate::logging::trace;
 use crate::memory::PAGE_SIZE;
 
 use super::OBJECT_PAGE_METADATA_OVERHEAD;
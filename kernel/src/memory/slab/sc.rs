@@ -5,9 +5,10 @@ use core::{
     ptr::{self, NonNull},
 };
 
-use log::trace;
 use x86_64::VirtAddr;
 
+use crate::logging::trace;
+
 use super::{
     page::{AllocablePage, Bitfield, ObjectPage, PageList, Rawlink},
     AllocationError, OBJECT_PAGE_METADATA_OVERHEAD,
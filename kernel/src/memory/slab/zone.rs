@@ -4,9 +4,9 @@
 
 use core::{alloc::Layout, panic, ptr::NonNull};
 
-use log::trace;
 use x86_64::VirtAddr;
 
+use crate::logging::trace;
 use crate::memory::kvm;
 
 use super::{AllocationError, ObjectPage, SCAllocator};
@@ -0,0 +1,82 @@
+use alloc::{collections::VecDeque, vec::Vec};
+use spin::Mutex;
+use syscalls::{AuditEventType, AuditRecord, AuditRule};
+
+use super::{error::check_arg, thread::Thread, timer, Error};
+
+/// No rule by default: nothing is audited until a rule is set
+static RULES: Mutex<Vec<AuditRule>> = Mutex::new(Vec::new());
+
+static RECORDS: Mutex<Records> = Mutex::new(Records {
+    next_seq: 1,
+    records: VecDeque::new(),
+});
+
+struct Records {
+    next_seq: u64,
+    records: VecDeque<AuditRecord>,
+}
+
+/// Replace the audit rules
+pub fn set_rules(rules: &[AuditRule]) -> Result<(), Error> {
+    check_arg(rules.len() <= AuditRule::MAX_COUNT)?;
+
+    let mut current = RULES.lock();
+    current.clear();
+    current.extend_from_slice(rules);
+
+    Ok(())
+}
+
+/// Record an operation performed by `thread`, if a rule matches it
+pub fn record(thread: &Thread, r#type: AuditEventType, object: u64, name: Option<&str>) {
+    let pid = thread.process().id();
+
+    if !RULES.lock().iter().any(|rule| rule.matches(r#type, pid)) {
+        return;
+    }
+
+    let mut records = RECORDS.lock();
+
+    if records.records.len() == AuditRecord::BUFFER_SIZE {
+        records.records.pop_front();
+    }
+
+    let seq = records.next_seq;
+    records.next_seq += 1;
+
+    records.records.push_back(AuditRecord::new(
+        seq,
+        timer::now(),
+        r#type,
+        pid,
+        thread.id(),
+        object,
+        name,
+    ));
+}
+
+/// Copy the records from sequence number `cursor` into `dest`, and move the cursor after them
+///
+/// Records are not consumed: several readers can follow the log with their own cursor.
+/// Returns the number of records written.
+pub fn read(cursor: &mut u64, dest: &mut [AuditRecord]) -> usize {
+    let records = RECORDS.lock();
+
+    let mut count = 0;
+    for (slot, record) in dest.iter_mut().zip(
+        records
+            .records
+            .iter()
+            .filter(|record| record.seq >= *cursor),
+    ) {
+        *slot = *record;
+        count += 1;
+    }
+
+    if count > 0 {
+        *cursor = dest[count - 1].seq + 1;
+    }
+
+    count
+}
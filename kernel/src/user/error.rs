@@ -41,6 +41,10 @@ pub fn out_of_memory() -> Error {
     Error::OutOfMemory
 }
 
+pub fn memory_access_denied() -> Error {
+    Error::MemoryAccessDenied
+}
+
 /// Check that actual permissions match at least expected
 pub fn check_permissions(actual: Permissions, expected: Permissions) -> Result<(), Error> {
     for perm in [Permissions::READ, Permissions::WRITE, Permissions::EXECUTE] {
@@ -80,3 +84,27 @@ pub fn object_closed() -> Error {
 pub fn object_not_ready() -> Error {
     Error::ObjectNotReady
 }
+
+pub fn partial() -> Error {
+    Error::Partial
+}
+
+pub fn deadline_exceeded() -> Error {
+    Error::DeadlineExceeded
+}
+
+pub fn quota_exceeded() -> Error {
+    Error::QuotaExceeded
+}
+
+pub fn address_in_use() -> Error {
+    Error::AddressInUse
+}
+
+pub fn address_not_mapped() -> Error {
+    Error::AddressNotMapped
+}
+
+pub fn address_reserved() -> Error {
+    Error::AddressReserved
+}
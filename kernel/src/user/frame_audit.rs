@@ -0,0 +1,77 @@
+use alloc::{collections::BTreeMap, sync::Arc};
+
+use crate::logging::warn;
+use crate::memory::{phys_audit, phys_ref_count, FrameAudit, PhysAddr};
+
+use super::{process, MemoryObject};
+
+/// Cross-check physical frames reference counts against user mappings.
+///
+/// Each frame must hold at least one reference per page table entry mapping it,
+/// plus one per memory object containing it.
+/// Other kernel holders cannot be enumerated, so only missing references are detected.
+pub fn audit() -> FrameAudit {
+    let mut audit = FrameAudit::default();
+
+    // Number of references expected for each frame
+    let mut holders: BTreeMap<PhysAddr, usize> = BTreeMap::new();
+    let mut objects: BTreeMap<*const MemoryObject, Arc<MemoryObject>> = BTreeMap::new();
+
+    for pid in process::list() {
+        // Process may have been deleted since the list
+        let Some(process) = process::find(pid) else {
+            continue;
+        };
+
+        process.visit_mapped_pages(|mobj, offset, frame| {
+            let expected = mobj.frame(offset).frame();
+
+            match frame {
+                Some(frame) if frame == expected => {
+                    *holders.entry(frame).or_insert(0) += 1;
+                }
+                _ => {
+                    warn!(
+                        "Frame audit: process {} maps {:?} instead of {:?}",
+                        pid, frame, expected
+                    );
+                    audit.mismatched_pages += 1;
+                }
+            }
+
+            objects
+                .entry(Arc::as_ptr(mobj))
+                .or_insert_with(|| mobj.clone());
+        });
+    }
+
+    audit.mapped_frames = holders.len();
+
+    for mobj in objects.values() {
+        for frame in mobj.frames_iter() {
+            *holders.entry(frame.frame()).or_insert(0) += 1;
+        }
+    }
+
+    phys_audit(&mut audit);
+
+    for (frame, expected) in holders.into_iter() {
+        // Frames not managed by the allocator (eg: device memory) are not ref counted
+        let Some(ref_count) = phys_ref_count(frame) else {
+            continue;
+        };
+
+        if ref_count == 0 {
+            warn!("Frame audit: {:?} is in use but free", frame);
+            audit.free_mapped_frames += 1;
+        } else if ref_count < expected {
+            warn!(
+                "Frame audit: {:?} has {} references, but {} holders",
+                frame, ref_count, expected
+            );
+            audit.underreferenced_frames += 1;
+        }
+    }
+
+    audit
+}
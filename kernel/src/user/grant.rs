@@ -0,0 +1,153 @@
+use core::ops::Range;
+
+use alloc::{
+    sync::{Arc, Weak},
+    vec::Vec,
+};
+use spin::Mutex;
+
+use crate::logging::debug;
+use crate::memory::{Permissions, VirtAddr};
+
+use super::{
+    error::{check_permissions, not_supported, object_closed},
+    id_gen::IdGen,
+    process::Process,
+    Error, MemoryObject,
+};
+
+static IDS: IdGen = IdGen::new();
+
+/// Revocable access to a part of the memory of a process
+///
+/// The owner process creates the grant from one of its mappings, and sends it to other processes, which can map it.
+/// When the owner revokes the grant, all the mappings created from it are unmapped, and it cannot be mapped anymore.
+///
+/// Note: if all the handles to the grant are closed without revoking it, the mappings created from it are kept.
+#[derive(Debug)]
+pub struct Grant {
+    id: u64,
+    owner: u64,
+    size: usize,
+    /// Maximum permissions of the mappings created from the grant
+    perms: Permissions,
+    state: Mutex<State>,
+}
+
+#[derive(Debug)]
+struct State {
+    /// None once revoked
+    memory_object: Option<Arc<MemoryObject>>,
+    offset: usize,
+    /// Mappings created from the grant
+    ///
+    /// They may have been unmapped since: revoking only unmaps what is still tagged with the grant id.
+    mappings: Vec<(Weak<Process>, Range<VirtAddr>)>,
+}
+
+impl Grant {
+    /// Create a grant on a region of the owner process
+    ///
+    /// The region must be part of one mapping of a memory object. `perms` must be allowed by the mapping.
+    ///
+    /// Regions mapped from another grant cannot be granted again (`NotSupported`):
+    /// revoking the other grant would not reach the mappings created from this one.
+    pub fn new(
+        owner: &Arc<Process>,
+        addr: VirtAddr,
+        size: usize,
+        perms: Permissions,
+    ) -> Result<Arc<Self>, Error> {
+        let (memory_object, offset, mapping_perms, grant) = owner.memory_object_at(addr, size)?;
+        check_permissions(mapping_perms, perms)?;
+
+        if grant.is_some() {
+            return Err(not_supported());
+        }
+
+        let grant = Arc::new(Self {
+            id: IDS.generate(),
+            owner: owner.id(),
+            size,
+            perms,
+            state: Mutex::new(State {
+                memory_object: Some(memory_object),
+                offset,
+                mappings: Vec::new(),
+            }),
+        });
+
+        debug!(
+            "Grant {} created by process {} (size={}, perms={:?})",
+            grant.id, grant.owner, grant.size, grant.perms
+        );
+
+        Ok(grant)
+    }
+
+    /// Get the id of the grant, which tags the mappings created from it
+    pub fn id(&self) -> u64 {
+        self.id
+    }
+
+    /// Get the maximum permissions of the mappings created from the grant
+    pub fn permissions(&self) -> Permissions {
+        self.perms
+    }
+
+    /// Get the size of the granted region
+    pub fn size(&self) -> usize {
+        self.size
+    }
+
+    /// Map the granted region into the process
+    ///
+    /// `addr` and the result have the same meaning as in `Process::mmap`.
+    pub fn map(
+        &self,
+        process: &Arc<Process>,
+        addr: VirtAddr,
+        perms: Permissions,
+    ) -> Result<VirtAddr, Error> {
+        // Keep the state locked while mapping, so that a concurrent revoke sees the new mapping
+        let mut state = self.state.lock();
+
+        let Some(memory_object) = state.memory_object.clone() else {
+            return Err(object_closed());
+        };
+
+        let addr = process.mmap_grant(addr, perms, memory_object, state.offset, self)?;
+
+        state
+            .mappings
+            .retain(|(process, _)| process.strong_count() > 0);
+        state
+            .mappings
+            .push((Arc::downgrade(process), addr..addr + self.size));
+
+        Ok(addr)
+    }
+
+    /// Revoke the grant: unmap it from all processes, and forbid new mappings
+    ///
+    /// Only the owner process can revoke the grant. Revoking it again is a noop.
+    pub fn revoke(&self, caller: &Process) -> Result<(), Error> {
+        if caller.id() != self.owner {
+            return Err(not_supported());
+        }
+
+        let mut state = self.state.lock();
+
+        state.memory_object = None;
+
+        for (process, range) in state.mappings.drain(..) {
+            if let Some(process) = process.upgrade() {
+                process.revoke_grant(range, self.id);
+            }
+        }
+
+        debug!("Grant {} revoked", self.id);
+
+        Ok(())
+    }
+}
@@ -1,21 +1,28 @@
 use core::{mem, pin::Pin};
 
-use alloc::sync::Arc;
-use hashbrown::HashMap;
+use alloc::{sync::Arc, vec::Vec};
 use spin::RwLock;
-use syscalls::HandleType;
+use syscalls::{HandleInfo, HandleType, Permissions};
 
 use super::{
-    error::{check_arg_opt, invalid_argument},
-    id_gen::IdGen,
+    error::{check_arg, check_arg_opt, check_permissions, invalid_argument, out_of_memory},
+    grant::Grant,
     ipc::{Port, PortReceiver, PortSender},
-    listener::{ProcessListener, ThreadListener},
+    listener::{PortListener, ProcessListener, ThreadListener},
     process::Process,
     thread::Thread,
+    timer::Timer,
+    trampoline::Trampoline,
     Error, MemoryObject,
 };
 
+/// Maximum number of handles opened at the same time in a process
+pub const MAX_HANDLES: usize = 4096;
+
 /// Handle: Pointer to kernel object, usable from userland
+///
+/// Layout: slot generation in the high 32 bits, slot index + 1 in the low 32 bits (so that 0 stays invalid).
+/// The generation changes each time the slot is released, so a closed handle never aliases the object opened after it in the same slot.
 #[derive(Debug, PartialEq, Eq, Hash, Clone, Copy)]
 pub struct Handle(u64);
 
@@ -49,37 +56,75 @@ impl Handle {
     pub const fn as_usize(&self) -> usize {
         self.0 as usize
     }
+
+    const fn new(index: usize, generation: u32) -> Self {
+        Handle(((generation as u64) << 32) | (index as u64 + 1))
+    }
+
+    /// Get the slot index, if the handle is valid
+    fn index(&self) -> Option<usize> {
+        let low = self.0 as u32;
+        if low == 0 {
+            None
+        } else {
+            Some(low as usize - 1)
+        }
+    }
+
+    fn generation(&self) -> u32 {
+        (self.0 >> 32) as u32
+    }
 }
 
 #[derive(Debug, Clone)]
 pub enum KernelHandle {
-    MemoryObjectHandle(Arc<MemoryObject>),
+    /// Memory object, with the permissions it can be mapped with through this handle
+    MemoryObjectHandle(Arc<MemoryObject>, Permissions),
     ProcessHandle(Arc<Process>),
     ThreadHandle(Arc<Thread>),
     PortReceiverHandle(Arc<PortReceiver>),
     PortSenderHandle(Arc<PortSender>),
     ProcessListenerHandle(Pin<Arc<ProcessListener>>),
     ThreadListenerHandle(Pin<Arc<ThreadListener>>),
+    TimerHandle(Arc<Timer>),
+    PortListenerHandle(Pin<Arc<PortListener>>),
+    GrantHandle(Arc<Grant>),
+    TrampolineHandle(Arc<Trampoline>),
 }
 
 impl KernelHandle {
     pub fn r#type(&self) -> HandleType {
         match self {
-            KernelHandle::MemoryObjectHandle(_) => HandleType::MemoryObject,
+            KernelHandle::MemoryObjectHandle(..) => HandleType::MemoryObject,
             KernelHandle::ProcessHandle(_) => HandleType::Process,
             KernelHandle::ThreadHandle(_) => HandleType::Thread,
             KernelHandle::PortReceiverHandle(_) => HandleType::PortReceiver,
             KernelHandle::PortSenderHandle(_) => HandleType::PortSender,
             KernelHandle::ProcessListenerHandle(_) => HandleType::ProcessListener,
             KernelHandle::ThreadListenerHandle(_) => HandleType::ThreadListener,
+            KernelHandle::TimerHandle(_) => HandleType::Timer,
+            KernelHandle::PortListenerHandle(_) => HandleType::PortListener,
+            KernelHandle::GrantHandle(_) => HandleType::Grant,
+            KernelHandle::TrampolineHandle(_) => HandleType::Trampoline,
+        }
+    }
+
+    /// Get the id of the object: pid for processes, tid for threads, port id for ports, 0 for the other types
+    pub fn object_id(&self) -> u64 {
+        match self {
+            KernelHandle::ProcessHandle(process) => process.id(),
+            KernelHandle::ThreadHandle(thread) => thread.id(),
+            KernelHandle::PortReceiverHandle(receiver) => receiver.id(),
+            KernelHandle::PortSenderHandle(sender) => sender.id(),
+            _ => 0,
         }
     }
 
     /// Check if the 2 handles points to the same object
     pub fn is_obj_eq(&self, other: &KernelHandle) -> bool {
         match self {
-            KernelHandle::MemoryObjectHandle(self_obj) => {
-                if let KernelHandle::MemoryObjectHandle(other_obj) = other {
+            KernelHandle::MemoryObjectHandle(self_obj, _) => {
+                if let KernelHandle::MemoryObjectHandle(other_obj, _) = other {
                     Arc::ptr_eq(self_obj, other_obj)
                 } else {
                     false
@@ -131,22 +176,181 @@ impl KernelHandle {
                     false
                 }
             }
+            KernelHandle::TimerHandle(self_obj) => {
+                if let KernelHandle::TimerHandle(other_obj) = other {
+                    Arc::ptr_eq(self_obj, other_obj)
+                } else {
+                    false
+                }
+            }
+            KernelHandle::PortListenerHandle(self_obj) => {
+                if let KernelHandle::PortListenerHandle(other_obj) = other {
+                    let self_ptr: *const _ = self_obj.as_ref().get_ref();
+                    let other_ptr: *const _ = other_obj.as_ref().get_ref();
+                    core::ptr::addr_eq(self_ptr, other_ptr)
+                } else {
+                    false
+                }
+            }
+            KernelHandle::GrantHandle(self_obj) => {
+                if let KernelHandle::GrantHandle(other_obj) = other {
+                    Arc::ptr_eq(self_obj, other_obj)
+                } else {
+                    false
+                }
+            }
+            KernelHandle::TrampolineHandle(self_obj) => {
+                if let KernelHandle::TrampolineHandle(other_obj) = other {
+                    Arc::ptr_eq(self_obj, other_obj)
+                } else {
+                    false
+                }
+            }
         }
     }
 }
 
+#[derive(Debug)]
+struct Slot {
+    generation: u32,
+    handle_impl: Option<KernelHandle>,
+}
+
+/// Table of handles: lookup, allocation and release are O(1)
+#[derive(Debug)]
+struct Table {
+    slots: Vec<Slot>,
+    free_list: Vec<usize>,
+    len: usize,
+}
+
+impl Table {
+    const fn new() -> Self {
+        Self {
+            slots: Vec::new(),
+            free_list: Vec::new(),
+            len: 0,
+        }
+    }
+
+    fn len(&self) -> usize {
+        self.len
+    }
+
+    fn available(&self) -> usize {
+        MAX_HANDLES - self.len
+    }
+
+    fn insert(&mut self, handle_impl: KernelHandle) -> Result<Handle, Error> {
+        let index = if let Some(index) = self.free_list.pop() {
+            index
+        } else if self.slots.len() < MAX_HANDLES {
+            self.slots.push(Slot {
+                generation: 0,
+                handle_impl: None,
+            });
+            self.slots.len() - 1
+        } else {
+            return Err(out_of_memory());
+        };
+
+        let slot = &mut self.slots[index];
+        debug_assert!(slot.handle_impl.is_none());
+        slot.handle_impl = Some(handle_impl);
+        self.len += 1;
+
+        Ok(Handle::new(index, slot.generation))
+    }
+
+    /// Insert at the slot of `handle`, which must be free, with its generation
+    ///
+    /// The free list is scanned: this is meant for rare use (restore of a process).
+    fn insert_at(&mut self, handle: Handle, handle_impl: KernelHandle) -> Result<(), Error> {
+        let index = check_arg_opt(handle.index())?;
+        check_arg(index < MAX_HANDLES)?;
+
+        while self.slots.len() <= index {
+            self.free_list.push(self.slots.len());
+            self.slots.push(Slot {
+                generation: 0,
+                handle_impl: None,
+            });
+        }
+
+        check_arg(self.slots[index].handle_impl.is_none())?;
+
+        let position = self
+            .free_list
+            .iter()
+            .position(|&free| free == index)
+            .expect("free slot missing from the free list");
+        self.free_list.swap_remove(position);
+
+        let slot = &mut self.slots[index];
+        slot.generation = handle.generation();
+        slot.handle_impl = Some(handle_impl);
+        self.len += 1;
+
+        Ok(())
+    }
+
+    fn iter(&self) -> impl Iterator<Item = (Handle, &KernelHandle)> {
+        self.slots.iter().enumerate().filter_map(|(index, slot)| {
+            let handle_impl = slot.handle_impl.as_ref()?;
+            Some((Handle::new(index, slot.generation), handle_impl))
+        })
+    }
+
+    fn get(&self, handle: Handle) -> Option<&KernelHandle> {
+        let slot = self.slots.get(handle.index()?)?;
+
+        if slot.generation != handle.generation() {
+            return None;
+        }
+
+        slot.handle_impl.as_ref()
+    }
+
+    fn remove(&mut self, handle: Handle) -> Option<KernelHandle> {
+        let index = handle.index()?;
+        let slot = self.slots.get_mut(index)?;
+
+        if slot.generation != handle.generation() {
+            return None;
+        }
+
+        let handle_impl = slot.handle_impl.take()?;
+
+        // Note: generation wraps after 2^32 reuses of the same slot
+        slot.generation = slot.generation.wrapping_add(1);
+        self.free_list.push(index);
+        self.len -= 1;
+
+        Some(handle_impl)
+    }
+
+    fn clear(&mut self) {
+        for (index, slot) in self.slots.iter_mut().enumerate() {
+            if slot.handle_impl.take().is_some() {
+                slot.generation = slot.generation.wrapping_add(1);
+                self.free_list.push(index);
+            }
+        }
+
+        self.len = 0;
+    }
+}
+
 /// Handles management in a process
 #[derive(Debug)]
 pub struct Handles {
-    id_gen: IdGen,
-    handles: RwLock<HashMap<Handle, KernelHandle>>,
+    handles: RwLock<Table>,
 }
 
 impl Handles {
     pub fn new() -> Self {
         Self {
-            id_gen: IdGen::new(),
-            handles: RwLock::new(HashMap::new()),
+            handles: RwLock::new(Table::new()),
         }
     }
 
@@ -157,56 +361,131 @@ impl Handles {
         handles.len()
     }
 
+    /// Get the number of handles which can still be opened
+    pub fn available(&self) -> usize {
+        let handles = self.handles.read();
+
+        handles.available()
+    }
+
     /// Open the given memory object in the process
-    pub fn open_memory_object(&self, memory_object: Arc<MemoryObject>) -> Handle {
-        self.open(KernelHandle::MemoryObjectHandle(memory_object))
+    pub fn open_memory_object(&self, memory_object: Arc<MemoryObject>) -> Result<Handle, Error> {
+        self.open(KernelHandle::MemoryObjectHandle(
+            memory_object,
+            Permissions::READ | Permissions::WRITE | Permissions::EXECUTE,
+        ))
+    }
+
+    /// Open a new handle to the memory object of `handle`, which can only map it with `perms`
+    ///
+    /// The new handle cannot have more permissions than `handle`. It is meant to be sent to another process
+    /// (eg: a server sharing a result buffer read-only): the restriction is enforced when the object is mapped.
+    pub fn open_restricted_memory_object(
+        &self,
+        handle: Handle,
+        perms: Permissions,
+    ) -> Result<Handle, Error> {
+        let (memory_object, allowed) = self.get_memory_object_with_perms(handle)?;
+
+        self.open(KernelHandle::MemoryObjectHandle(
+            memory_object,
+            allowed.intersection(perms),
+        ))
     }
 
     /// Open the given process in the process
-    pub fn open_process(&self, process: Arc<Process>) -> Handle {
+    pub fn open_process(&self, process: Arc<Process>) -> Result<Handle, Error> {
         self.open(KernelHandle::ProcessHandle(process))
     }
 
     /// Open the given thread in the process
-    pub fn open_thread(&self, thread: Arc<Thread>) -> Handle {
+    pub fn open_thread(&self, thread: Arc<Thread>) -> Result<Handle, Error> {
         self.open(KernelHandle::ThreadHandle(thread))
     }
 
     /// Open the given port receiver in the process
-    pub fn open_port_receiver(&self, port: Arc<PortReceiver>) -> Handle {
+    pub fn open_port_receiver(&self, port: Arc<PortReceiver>) -> Result<Handle, Error> {
         self.open(KernelHandle::PortReceiverHandle(port))
     }
 
     /// Open the given port sender in the process
-    pub fn open_port_sender(&self, port: Arc<PortSender>) -> Handle {
+    pub fn open_port_sender(&self, port: Arc<PortSender>) -> Result<Handle, Error> {
         self.open(KernelHandle::PortSenderHandle(port))
     }
 
     /// Open the given process listener in the process
-    pub fn open_process_listener(&self, listener: Pin<Arc<ProcessListener>>) -> Handle {
+    pub fn open_process_listener(
+        &self,
+        listener: Pin<Arc<ProcessListener>>,
+    ) -> Result<Handle, Error> {
         self.open(KernelHandle::ProcessListenerHandle(listener))
     }
 
     /// Open the given thread listener in the process
-    pub fn open_thread_listener(&self, listener: Pin<Arc<ThreadListener>>) -> Handle {
+    pub fn open_thread_listener(
+        &self,
+        listener: Pin<Arc<ThreadListener>>,
+    ) -> Result<Handle, Error> {
         self.open(KernelHandle::ThreadListenerHandle(listener))
     }
 
+    /// Open the given port listener in the process
+    pub fn open_port_listener(&self, listener: Pin<Arc<PortListener>>) -> Result<Handle, Error> {
+        self.open(KernelHandle::PortListenerHandle(listener))
+    }
+
+    /// Open the given timer in the process
+    pub fn open_timer(&self, timer: Arc<Timer>) -> Result<Handle, Error> {
+        self.open(KernelHandle::TimerHandle(timer))
+    }
+
+    /// Open the given grant in the process
+    pub fn open_grant(&self, grant: Arc<Grant>) -> Result<Handle, Error> {
+        self.open(KernelHandle::GrantHandle(grant))
+    }
+
+    /// Open the given trampoline in the process
+    pub fn open_trampoline(&self, trampoline: Arc<Trampoline>) -> Result<Handle, Error> {
+        self.open(KernelHandle::TrampolineHandle(trampoline))
+    }
+
     /// Open raw kernel handle
-    pub fn open(&self, handle_impl: KernelHandle) -> Handle {
-        let handle = Handle(self.id_gen.generate());
+    ///
+    /// Fails with OutOfMemory if the process has reached `MAX_HANDLES`
+    pub fn open(&self, handle_impl: KernelHandle) -> Result<Handle, Error> {
+        let mut handles = self.handles.write();
 
+        handles.insert(handle_impl)
+    }
+
+    /// Open raw kernel handle, with the given handle value
+    ///
+    /// Fails with InvalidArgument if the value is invalid or already used
+    pub fn open_at(&self, handle: Handle, handle_impl: KernelHandle) -> Result<(), Error> {
         let mut handles = self.handles.write();
-        handles.insert_unique_unchecked(handle, handle_impl);
 
-        handle
+        handles.insert_at(handle, handle_impl)
+    }
+
+    /// Describe the opened handles
+    pub fn infos(&self) -> Vec<HandleInfo> {
+        let handles = self.handles.read();
+
+        handles
+            .iter()
+            .map(|(handle, handle_impl)| HandleInfo {
+                handle: handle.as_u64(),
+                r#type: handle_impl.r#type(),
+                object_id: handle_impl.object_id(),
+            })
+            .collect()
     }
 
     /// Retrieve the type of the handle
     pub fn r#type(&self, handle: Handle) -> Result<HandleType, Error> {
         let handles = self.handles.read();
 
-        let handle_impl = check_arg_opt(handles.get(&handle))?;
+        let handle_impl = check_arg_opt(handles.get(handle))?;
 
         Ok(handle_impl.r#type())
     }
@@ -214,8 +493,8 @@ impl Handles {
     pub fn is_obj_eq(&self, handle1: Handle, handle2: Handle) -> Result<bool, Error> {
         let handles = self.handles.read();
 
-        let handle1_impl = check_arg_opt(handles.get(&handle1))?;
-        let handle2_impl = check_arg_opt(handles.get(&handle2))?;
+        let handle1_impl = check_arg_opt(handles.get(handle1))?;
+        let handle2_impl = check_arg_opt(handles.get(handle2))?;
 
         Ok(handle1_impl.is_obj_eq(handle2_impl))
     }
@@ -223,7 +502,7 @@ impl Handles {
     pub fn get(&self, handle: Handle) -> Result<KernelHandle, Error> {
         let handles = self.handles.read();
 
-        let handle_impl = check_arg_opt(handles.get(&handle))?;
+        let handle_impl = check_arg_opt(handles.get(handle))?;
 
         Ok(handle_impl.clone())
     }
@@ -232,20 +511,51 @@ impl Handles {
     pub fn get_memory_object(&self, handle: Handle) -> Result<Arc<MemoryObject>, Error> {
         let handles = self.handles.read();
 
-        let handle_impl = check_arg_opt(handles.get(&handle))?;
+        let handle_impl = check_arg_opt(handles.get(handle))?;
 
-        if let KernelHandle::MemoryObjectHandle(memory_object) = handle_impl {
+        if let KernelHandle::MemoryObjectHandle(memory_object, _) = handle_impl {
             Ok(memory_object.clone())
         } else {
             Err(invalid_argument())
         }
     }
 
+    /// Retrieve the memory object from the handle, to map it with `perms`
+    ///
+    /// Fails with `MemoryAccessDenied` if the handle does not allow them.
+    /// Also returns the permissions the handle allows, which are the maximum permissions of the mapping.
+    pub fn get_mappable_memory_object(
+        &self,
+        handle: Handle,
+        perms: Permissions,
+    ) -> Result<(Arc<MemoryObject>, Permissions), Error> {
+        let (memory_object, allowed) = self.get_memory_object_with_perms(handle)?;
+
+        check_permissions(allowed, perms)?;
+
+        Ok((memory_object, allowed))
+    }
+
+    fn get_memory_object_with_perms(
+        &self,
+        handle: Handle,
+    ) -> Result<(Arc<MemoryObject>, Permissions), Error> {
+        let handles = self.handles.read();
+
+        let handle_impl = check_arg_opt(handles.get(handle))?;
+
+        if let KernelHandle::MemoryObjectHandle(memory_object, allowed) = handle_impl {
+            Ok((memory_object.clone(), *allowed))
+        } else {
+            Err(invalid_argument())
+        }
+    }
+
     /// Retrieve the process from the handle
     pub fn get_process(&self, handle: Handle) -> Result<Arc<Process>, Error> {
         let handles = self.handles.read();
 
-        let handle_impl = check_arg_opt(handles.get(&handle))?;
+        let handle_impl = check_arg_opt(handles.get(handle))?;
 
         if let KernelHandle::ProcessHandle(process) = handle_impl {
             Ok(process.clone())
@@ -258,7 +568,7 @@ impl Handles {
     pub fn get_thread(&self, handle: Handle) -> Result<Arc<Thread>, Error> {
         let handles = self.handles.read();
 
-        let handle_impl = check_arg_opt(handles.get(&handle))?;
+        let handle_impl = check_arg_opt(handles.get(handle))?;
 
         if let KernelHandle::ThreadHandle(thread) = handle_impl {
             Ok(thread.clone())
@@ -271,7 +581,7 @@ impl Handles {
     pub fn get_port_receiver(&self, handle: Handle) -> Result<Arc<PortReceiver>, Error> {
         let handles = self.handles.read();
 
-        let handle_impl = check_arg_opt(handles.get(&handle))?;
+        let handle_impl = check_arg_opt(handles.get(handle))?;
 
         if let KernelHandle::PortReceiverHandle(port_receiver) = handle_impl {
             Ok(port_receiver.clone())
@@ -284,7 +594,7 @@ impl Handles {
     pub fn get_port_sender(&self, handle: Handle) -> Result<Arc<PortSender>, Error> {
         let handles = self.handles.read();
 
-        let handle_impl = check_arg_opt(handles.get(&handle))?;
+        let handle_impl = check_arg_opt(handles.get(handle))?;
 
         if let KernelHandle::PortSenderHandle(port_sender) = handle_impl {
             Ok(port_sender.clone())
@@ -297,7 +607,7 @@ impl Handles {
     pub fn get_port(&self, handle: Handle) -> Result<Arc<Port>, Error> {
         let handles = self.handles.read();
 
-        let handle_impl = check_arg_opt(handles.get(&handle))?;
+        let handle_impl = check_arg_opt(handles.get(handle))?;
 
         if let KernelHandle::PortSenderHandle(port_sender) = handle_impl {
             Ok(port_sender.port().clone())
@@ -312,7 +622,7 @@ impl Handles {
     pub fn get_process_listener(&self, handle: Handle) -> Result<Pin<Arc<ProcessListener>>, Error> {
         let handles = self.handles.read();
 
-        let handle_impl = check_arg_opt(handles.get(&handle))?;
+        let handle_impl = check_arg_opt(handles.get(handle))?;
 
         if let KernelHandle::ProcessListenerHandle(process_listener) = handle_impl {
             Ok(process_listener.clone())
@@ -325,7 +635,7 @@ impl Handles {
     pub fn get_thread_listener(&self, handle: Handle) -> Result<Pin<Arc<ThreadListener>>, Error> {
         let handles = self.handles.read();
 
-        let handle_impl = check_arg_opt(handles.get(&handle))?;
+        let handle_impl = check_arg_opt(handles.get(handle))?;
 
         if let KernelHandle::ThreadListenerHandle(thread_listener) = handle_impl {
             Ok(thread_listener.clone())
@@ -334,11 +644,50 @@ impl Handles {
         }
     }
 
+    /// Retrieve the timer from the handle
+    pub fn get_timer(&self, handle: Handle) -> Result<Arc<Timer>, Error> {
+        let handles = self.handles.read();
+
+        let handle_impl = check_arg_opt(handles.get(handle))?;
+
+        if let KernelHandle::TimerHandle(timer) = handle_impl {
+            Ok(timer.clone())
+        } else {
+            Err(invalid_argument())
+        }
+    }
+
+    /// Retrieve the grant from the handle
+    pub fn get_grant(&self, handle: Handle) -> Result<Arc<Grant>, Error> {
+        let handles = self.handles.read();
+
+        let handle_impl = check_arg_opt(handles.get(handle))?;
+
+        if let KernelHandle::GrantHandle(grant) = handle_impl {
+            Ok(grant.clone())
+        } else {
+            Err(invalid_argument())
+        }
+    }
+
+    /// Retrieve the trampoline from the handle
+    pub fn get_trampoline(&self, handle: Handle) -> Result<Arc<Trampoline>, Error> {
+        let handles = self.handles.read();
+
+        let handle_impl = check_arg_opt(handles.get(handle))?;
+
+        if let KernelHandle::TrampolineHandle(trampoline) = handle_impl {
+            Ok(trampoline.clone())
+        } else {
+            Err(invalid_argument())
+        }
+    }
+
     /// Close the handle
     pub fn close(&self, handle: Handle) -> Result<(), Error> {
         let mut handles = self.handles.write();
 
-        let handle_impl = check_arg_opt(handles.remove(&handle))?;
+        let handle_impl = check_arg_opt(handles.remove(handle))?;
 
         // Let's be explicit
         mem::drop(handle_impl);
@@ -351,12 +700,12 @@ impl Handles {
         let new_handle_impl = {
             let handles = self.handles.read();
 
-            let handle_impl = check_arg_opt(handles.get(&handle))?;
+            let handle_impl = check_arg_opt(handles.get(handle))?;
 
             handle_impl.clone()
         };
 
-        Ok(self.open(new_handle_impl))
+        self.open(new_handle_impl)
     }
 
     /// Close all the handles in the container
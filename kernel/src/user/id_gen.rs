@@ -1,5 +1,11 @@
 use core::sync::atomic::{AtomicU64, Ordering};
 
+/// Generator of object ids (pids, tids, port ids, ...)
+///
+/// Policy: ids are 64-bit, allocated in increasing order from 1, and never reused within a boot.
+/// Userland can then keep an id (eg: in a table, or in a queued listener event) without risking that it designates
+/// another object later: a terminated process is never confused with a new one.
+/// 0 is never allocated, it means "none" in the ABI.
 #[derive(Debug)]
 pub struct IdGen {
     counter: AtomicU64,
@@ -13,8 +19,9 @@ impl IdGen {
     }
 
     pub fn generate(&self) -> u64 {
-        let id = self.counter.fetch_add(1, Ordering::Relaxed);
-        assert!(id > 0, "counter wrapped");
-        id
+        // Never wrap, even after the panic: the ids would be reused
+        self.counter
+            .fetch_update(Ordering::Relaxed, Ordering::Relaxed, |id| id.checked_add(1))
+            .expect("ids exhausted")
     }
 }
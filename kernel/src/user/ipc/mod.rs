@@ -2,15 +2,23 @@ mod port;
 mod port_access;
 mod ports;
 
-use alloc::{sync::Arc, vec::Vec};
+use alloc::{string::String, sync::Arc, vec::Vec};
 use syscalls::Error;
 
-pub use self::port::Port;
+use super::process::QuotaCharge;
+
+pub use self::port::{Handoff, Port, PortFilter};
 pub use self::port_access::{PortReceiver, PortSender};
 use self::ports::PORTS;
 
-pub fn create(name: Option<&str>) -> Result<(Arc<PortReceiver>, Arc<PortSender>), Error> {
-    PORTS.create(name)
+pub fn create(
+    owner_pid: u64,
+    name: Option<&str>,
+    data_capacity: usize,
+    broadcast: bool,
+    charge: QuotaCharge,
+) -> Result<(Arc<PortReceiver>, Arc<PortSender>), Error> {
+    PORTS.create(owner_pid, name, data_capacity, broadcast, charge)
 }
 
 pub fn find_by_id(id: u64) -> Option<Arc<PortSender>> {
@@ -24,3 +32,11 @@ pub fn find_by_name(name: &str) -> Option<Arc<PortSender>> {
 pub fn list() -> Vec<u64> {
     PORTS.list()
 }
+
+pub fn list_named() -> Vec<(u64, String)> {
+    PORTS.list_named()
+}
+
+pub fn list_by_owner(pid: u64) -> Vec<u64> {
+    PORTS.list_by_owner(pid)
+}
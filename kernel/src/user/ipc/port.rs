@@ -1,12 +1,23 @@
-use alloc::{collections::LinkedList, string::String, sync::Arc};
-use spin::RwLock;
-use syscalls::{Error, Message};
+use core::{
+    mem,
+    ops::Range,
+    sync::atomic::{AtomicUsize, Ordering},
+};
+
+use alloc::{
+    collections::LinkedList,
+    sync::{Arc, Weak},
+    vec::Vec,
+};
+use spin::{Mutex, RwLock, RwLockWriteGuard};
+use syscalls::{Error, HandleType, Message, MessageHeader, WaitCause};
 
 use crate::user::{
-    error::{object_closed, object_not_ready},
+    error::{check_arg, check_arg_opt, object_closed, object_not_ready, out_of_memory},
     handle::{Handle, KernelHandle},
-    process::Process,
-    thread::{self, WaitQueue},
+    process::{Process, QuotaCharge},
+    strings::{self, Interned},
+    thread::{self, Thread, WaitQueue},
 };
 
 use super::ports::remove_port;
@@ -14,8 +25,15 @@ use super::ports::remove_port;
 /// Standalone function, so that Port::new() can remain private
 ///
 /// Note: Only Port type is exported by port module, not this function
-pub fn new(id: u64, name: Option<&str>) -> Arc<Port> {
-    Port::new(id, name)
+pub fn new(
+    id: u64,
+    owner_pid: u64,
+    name: Option<&str>,
+    data_capacity: usize,
+    broadcast: bool,
+    charge: QuotaCharge,
+) -> Arc<Port> {
+    Port::new(id, owner_pid, name, data_capacity, broadcast, charge)
 }
 
 /// Port: implementation of a mailbox
@@ -26,27 +44,110 @@ pub fn new(id: u64, name: Option<&str>) -> Arc<Port> {
 #[derive(Debug)]
 pub struct Port {
     id: u64,
-    name: Option<String>,
+    owner_pid: u64,
+    name: Option<Interned>,
+    data_capacity: usize,
+    broadcast: bool,
+    /// Number of broadcast messages this port missed because its queue was full
+    broadcast_dropped: AtomicUsize,
     data: RwLock<Data>,
     receiver_queue: Arc<WaitQueue>,
+    /// Released with the port
+    _charge: QuotaCharge,
 }
 
 #[derive(Debug)]
 struct Data {
     message_queue: LinkedList<InternalMessage>,
     closed: bool,
+    filter: Option<PortFilter>,
+    subscribers: Vec<Subscriber>,
+    /// Receivers blocked in a receive on this port only, oldest first
+    direct_receivers: Vec<DirectReceiver>,
+}
+
+/// Receiver blocked in a receive on the port: a sender of the same process hands off its message directly to it
+///
+/// Note: the thread is weak, a terminated receiver is skipped.
+#[derive(Debug)]
+struct DirectReceiver {
+    thread: Weak<Thread>,
+    handoff: Arc<Handoff>,
+}
+
+/// Message handed off directly to a blocked receiver, bypassing the queue
+#[derive(Debug)]
+pub struct Handoff(Mutex<Option<InternalMessage>>);
+
+impl Handoff {
+    fn new() -> Arc<Self> {
+        Arc::new(Self(Mutex::new(None)))
+    }
+
+    /// Take the message handed off to the receiver, if any
+    pub fn take(&self, receiver: &Arc<Process>) -> Option<Message> {
+        let message = self.0.lock().take()?;
+        Some(message.to(receiver))
+    }
+}
+
+/// Subscriber of a broadcast port
+///
+/// Note: the port is weak, so that a subscription does not keep it alive. Dead subscribers are pruned on broadcast.
+#[derive(Debug)]
+struct Subscriber {
+    port: Weak<Port>,
+    max_queued: usize,
+}
+
+/// Filter of a port: messages whose type is not in `ranges` are routed to `alternate`
+///
+/// Note: the alternate port is weak, so that ports filtering to each other do not keep themselves alive
+#[derive(Debug)]
+pub struct PortFilter {
+    ranges: Vec<Range<u64>>,
+    alternate: Weak<Port>,
+}
+
+impl PortFilter {
+    pub fn new(ranges: Vec<Range<u64>>, alternate: &Arc<Port>) -> Self {
+        Self {
+            ranges,
+            alternate: Arc::downgrade(alternate),
+        }
+    }
+
+    fn matches(&self, message: &Message) -> bool {
+        let r#type = message.data[0];
+        self.ranges.iter().any(|range| range.contains(&r#type))
+    }
 }
 
 impl Port {
-    fn new(id: u64, name: Option<&str>) -> Arc<Self> {
+    fn new(
+        id: u64,
+        owner_pid: u64,
+        name: Option<&str>,
+        data_capacity: usize,
+        broadcast: bool,
+        charge: QuotaCharge,
+    ) -> Arc<Self> {
         Arc::new(Self {
             id,
-            name: name.map(String::from),
+            owner_pid,
+            name: name.map(strings::intern),
+            data_capacity,
+            broadcast,
+            broadcast_dropped: AtomicUsize::new(0),
             data: RwLock::new(Data {
                 message_queue: LinkedList::new(),
                 closed: false,
+                filter: None,
+                subscribers: Vec::new(),
+                direct_receivers: Vec::new(),
             }),
-            receiver_queue: Arc::new(WaitQueue::new()),
+            receiver_queue: Arc::new(WaitQueue::new(WaitCause::Port, id)),
+            _charge: charge,
         })
     }
 
@@ -55,27 +156,264 @@ impl Port {
         self.id
     }
 
+    /// Get the pid of the process which created the port
+    pub fn owner_pid(&self) -> u64 {
+        self.owner_pid
+    }
+
     /// Get the port name
     pub fn name<'a>(&'a self) -> Option<&'a str> {
-        self.name.as_ref().map(|x| x.as_str())
+        self.name.as_deref()
+    }
+
+    /// Get the id of the port name in the strings table (0 if no name)
+    pub fn name_id(&self) -> u64 {
+        self.name.as_ref().map_or(0, Interned::id)
+    }
+
+    /// Get the number of data items usable in messages
+    pub fn data_capacity(&self) -> usize {
+        self.data_capacity
+    }
+
+    /// Get if the port is a broadcast port
+    pub fn broadcast(&self) -> bool {
+        self.broadcast
+    }
+
+    /// Get the number of broadcast messages this port missed because its queue was full
+    pub fn broadcast_dropped(&self) -> usize {
+        self.broadcast_dropped.load(Ordering::Relaxed)
     }
 
     /// Send a message to the port
+    ///
+    /// If the port has a filter, non-matching messages are routed to its alternate port.
+    /// If the port is a broadcast port, a copy of the message is delivered to each subscriber.
     pub fn send(&self, sender: Option<&Arc<Process>>, message: Message) -> Result<(), Error> {
+        if self.broadcast {
+            return self.fan_out(sender, message);
+        }
+
+        if let Some(alternate) = self.route(&message) {
+            // Only one hop: the filter of the alternate port is not applied
+            return alternate.deliver(sender, message);
+        }
+
+        self.deliver(sender, message)
+    }
+
+    /// Get the alternate port if the message must be routed
+    fn route(&self, message: &Message) -> Option<Arc<Port>> {
+        let alternate = {
+            let data = self.data.read();
+            let filter = data.filter.as_ref()?;
+
+            if filter.matches(message) {
+                return None;
+            }
+
+            filter.alternate.upgrade()
+        }?;
+
+        // If the alternate port is closed, keep the message here
+        if alternate.closed() {
+            None
+        } else {
+            Some(alternate)
+        }
+    }
+
+    /// Deliver a copy of the message to each subscriber of the broadcast port
+    ///
+    /// Messages cannot carry handles, since they cannot be duplicated.
+    /// Subscribers whose queue is full (or which cannot accept the message) miss it: this is counted in their `broadcast_dropped`.
+    fn fan_out(&self, sender: Option<&Arc<Process>>, message: Message) -> Result<(), Error> {
+        check_arg(
+            message
+                .handles
+                .iter()
+                .all(|&handle| !Handle::from(handle).valid()),
+        )?;
+        self.check_data_capacity(sender, &message)?;
+
+        let subscribers: Vec<(Arc<Port>, usize)> = {
+            let mut data = self.data.write();
+            if data.closed {
+                return Err(object_closed());
+            }
+
+            data.subscribers
+                .retain(|subscriber| subscriber.port.strong_count() > 0);
+
+            data.subscribers
+                .iter()
+                .filter_map(|subscriber| Some((subscriber.port.upgrade()?, subscriber.max_queued)))
+                .collect()
+        };
+
+        // Deliver outside of the lock, subscribers have their own
+        for (port, max_queued) in subscribers {
+            if port.message_queue_count() >= max_queued
+                || port.deliver(sender, message.clone()).is_err()
+            {
+                port.broadcast_dropped.fetch_add(1, Ordering::Relaxed);
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Subscribe a port to the broadcast port
+    ///
+    /// The subscriber misses messages while it has `max_queued` messages (or more) waiting in its queue.
+    pub fn subscribe(&self, subscriber: &Arc<Port>, max_queued: usize) -> Result<(), Error> {
+        check_arg(self.broadcast)?;
+        // No chain of broadcast ports
+        check_arg(!subscriber.broadcast)?;
+        check_arg(max_queued > 0)?;
+
+        let mut data = self.data.write();
+        if data.closed {
+            return Err(object_closed());
+        }
+
+        let port = Arc::downgrade(subscriber);
+        check_arg(
+            !data
+                .subscribers
+                .iter()
+                .any(|existing| existing.port.ptr_eq(&port)),
+        )?;
+
+        data.subscribers.push(Subscriber { port, max_queued });
+
+        Ok(())
+    }
+
+    /// Unsubscribe a port from the broadcast port
+    pub fn unsubscribe(&self, subscriber: &Arc<Port>) -> Result<(), Error> {
+        check_arg(self.broadcast)?;
+
+        let mut data = self.data.write();
+        let port = Arc::downgrade(subscriber);
+        let index = data
+            .subscribers
+            .iter()
+            .position(|existing| existing.port.ptr_eq(&port));
+
+        data.subscribers.remove(check_arg_opt(index)?);
+
+        Ok(())
+    }
+
+    /// Get the number of ports subscribed to the broadcast port
+    pub fn subscriber_count(&self) -> usize {
+        let data = self.data.read();
+        data.subscribers
+            .iter()
+            .filter(|subscriber| subscriber.port.strong_count() > 0)
+            .count()
+    }
+
+    /// Data beyond the capacity of the port must be unused.
+    ///
+    /// Kernel messages (listeners events) have a fixed layout, they are not checked.
+    fn check_data_capacity(
+        &self,
+        sender: Option<&Arc<Process>>,
+        message: &Message,
+    ) -> Result<(), Error> {
+        if sender.is_some() {
+            check_arg(
+                message.data[self.data_capacity..]
+                    .iter()
+                    .all(|&item| item == 0),
+            )?;
+        }
+
+        Ok(())
+    }
+
+    /// Put the message in the queue of the port
+    fn deliver(&self, sender: Option<&Arc<Process>>, message: Message) -> Result<(), Error> {
+        self.check_data_capacity(sender, &message)?;
+
         let mut data = self.data.write();
         if data.closed {
             return Err(object_closed());
         }
 
         let message = InternalMessage::from(sender, &message)?;
+
+        // Fast path: if a receiver of the sending process is blocked on the port only, hand off the message directly to it.
+        // It does not go through the queue, the other receivers are not woken, and the receiver runs next at its priority.
+        let message = match sender {
+            Some(process) => match self.hand_off(data, process, message) {
+                Ok(()) => return Ok(()),
+                Err(message) => {
+                    data = self.data.write();
+                    // It may have been closed while the lock was released
+                    if data.closed {
+                        return Err(object_closed());
+                    }
+                    message
+                }
+            },
+            None => message,
+        };
+
         data.message_queue.push_back(message);
 
-        // Wake up any waiting receiver
-        thread::wait_queue_wake_all(&self.receiver_queue);
+        // Wake up the waiting receivers outside of the lock (they may run right away),
+        // so that the message is never left without a receiver awake
+        drop(data);
+        let woken = thread::wait_queue_wake_all(&self.receiver_queue);
+
+        // A receiver of another process is likely to serve a request of the sender: let it run if the sender blocks
+        if let (Some(_), Some(woken)) = (sender, woken) {
+            thread::directed_yield_hint(&woken);
+        }
 
         Ok(())
     }
 
+    /// Hand off the message to a receiver of `process` blocked on the port
+    ///
+    /// Gives the message back if there is none.
+    fn hand_off(
+        &self,
+        mut data: RwLockWriteGuard<'_, Data>,
+        process: &Arc<Process>,
+        message: InternalMessage,
+    ) -> Result<(), InternalMessage> {
+        let found = data.direct_receivers.iter().position(|receiver| {
+            receiver.thread.upgrade().map_or(false, |thread| {
+                Arc::ptr_eq(thread.process(), process)
+                    && message.handle_count() <= process.handles().available()
+            })
+        });
+
+        let Some(index) = found else {
+            return Err(message);
+        };
+
+        let receiver = data.direct_receivers.remove(index);
+        let thread = receiver.thread.upgrade().expect("receiver checked alive");
+        *receiver.handoff.0.lock() = Some(message);
+
+        // Wake it up outside of the lock: it runs the end of its receive right away
+        drop(data);
+
+        if thread::wait_queue_handoff(&self.receiver_queue, &thread) {
+            return Ok(());
+        }
+
+        // It was not waiting anymore (eg: terminated)
+        let message = receiver.handoff.0.lock().take();
+        Err(message.expect("handed off message lost"))
+    }
+
     /// Receive a message from the port
     ///
     /// Note: the operation does not block, and return Error::ObjectNotReady if there is no message available
@@ -84,11 +422,76 @@ impl Port {
         // Should not be able to receive on closed port since there is no receiver anymore
         assert!(!data.closed);
 
-        if let Some(message) = data.message_queue.pop_front() {
-            Ok(message.to(receiver))
-        } else {
-            Err(object_not_ready())
+        let Some(message) = data.message_queue.front() else {
+            return Err(object_not_ready());
+        };
+
+        // Keep the message queued if its handles cannot be opened in the receiver
+        if message.handle_count() > receiver.handles().available() {
+            return Err(out_of_memory());
+        }
+
+        let message = data.message_queue.pop_front().expect("Message queue empty");
+        Ok(message.to(receiver))
+    }
+
+    /// Prepare a blocking receive: the thread waits on the returned queue,
+    /// and a sender of its process can hand off a message directly to it meanwhile (see `Handoff`)
+    ///
+    /// Return None if a message is already queued
+    pub fn prepare_receive(&self, thread: &Arc<Thread>) -> Option<(&Arc<WaitQueue>, Arc<Handoff>)> {
+        let mut data = self.data.write();
+        assert!(!data.closed);
+
+        if !data.message_queue.is_empty() {
+            return None;
         }
+
+        let handoff = Handoff::new();
+        data.direct_receivers.push(DirectReceiver {
+            thread: Arc::downgrade(thread),
+            handoff: handoff.clone(),
+        });
+
+        Some((&self.receiver_queue, handoff))
+    }
+
+    /// End a blocking receive, once the thread has been woken up
+    pub fn finish_receive(&self, handoff: &Arc<Handoff>) {
+        let mut data = self.data.write();
+        data.direct_receivers
+            .retain(|receiver| !Arc::ptr_eq(&receiver.handoff, handoff));
+    }
+
+    /// Get the header of the first message, without receiving it
+    ///
+    /// Note: the operation does not block, and return Error::ObjectNotReady if there is no message available
+    pub fn peek(&self) -> Result<MessageHeader, Error> {
+        let data = self.data.read();
+        assert!(!data.closed);
+
+        let message = data.message_queue.front().ok_or_else(object_not_ready)?;
+
+        Ok(message.header())
+    }
+
+    /// Drop the first message, closing its handles instead of opening them in a receiver
+    ///
+    /// Note: the operation does not block, and return Error::ObjectNotReady if there is no message available
+    pub fn discard(&self) -> Result<(), Error> {
+        let mut data = self.data.write();
+        assert!(!data.closed);
+
+        let message = data
+            .message_queue
+            .pop_front()
+            .ok_or_else(object_not_ready)?;
+
+        // Drop the message outside of the lock: dropping its handles may send notifications
+        drop(data);
+        drop(message);
+
+        Ok(())
     }
 
     /// Called when receiver is dropped: No one will ever be able to read the messages, so drop them
@@ -98,7 +501,13 @@ impl Port {
         assert!(!data.closed);
 
         data.closed = true;
-        data.message_queue.clear();
+        data.filter = None;
+        data.subscribers.clear();
+
+        // Drop the messages outside of the lock: dropping their handles may send notifications (eg: memory object release)
+        let messages = mem::take(&mut data.message_queue);
+        drop(data);
+        drop(messages);
 
         // Wait up any sleeping receivers (They won't be able to receive)
         thread::wait_queue_wake_all(&self.receiver_queue);
@@ -119,6 +528,12 @@ impl Port {
         }
     }
 
+    /// Set or clear (if `None`) the filter of the port
+    pub fn set_filter(&self, filter: Option<PortFilter>) {
+        let mut data = self.data.write();
+        data.filter = filter;
+    }
+
     pub fn closed(&self) -> bool {
         let data = self.data.read();
         data.closed
@@ -144,6 +559,10 @@ impl Drop for Port {
 struct InternalMessage {
     data: [u64; Message::DATA_SIZE],
     handles: [Option<KernelHandle>; Message::HANDLE_COUNT],
+    sender_pid: u64,
+    correlation: u64,
+    deadline: u64,
+    token: u64,
 }
 
 impl InternalMessage {
@@ -153,6 +572,10 @@ impl InternalMessage {
         let mut internal_message = InternalMessage {
             data: message.data,
             handles: [NO_HANDLE; Message::HANDLE_COUNT],
+            sender_pid: sender.map_or(0, |process| process.id()),
+            correlation: message.correlation,
+            deadline: message.deadline,
+            token: message.token,
         };
 
         for index in 0..Message::HANDLE_COUNT {
@@ -182,6 +605,29 @@ impl InternalMessage {
         Ok(internal_message)
     }
 
+    pub fn header(&self) -> MessageHeader {
+        MessageHeader {
+            data: self.data,
+            handle_types: self.handles.each_ref().map(|handle| {
+                handle
+                    .as_ref()
+                    .map_or(HandleType::Invalid, |handle| handle.r#type())
+            }),
+            sender_pid: self.sender_pid,
+            correlation: self.correlation,
+            deadline: self.deadline,
+            token: self.token,
+        }
+    }
+
+    pub fn handle_count(&self) -> usize {
+        self.handles
+            .iter()
+            .filter(|handle| handle.is_some())
+            .count()
+    }
+
+    /// Note: the receiver must have enough available handles
     pub fn to(self, receiver: &Arc<Process>) -> Message {
         // Create handles in the receiver
         const NO_HANDLE: u64 = Handle::invalid().as_u64();
@@ -189,11 +635,19 @@ impl InternalMessage {
         let mut message = Message {
             data: self.data,
             handles: [NO_HANDLE; Message::HANDLE_COUNT],
+            correlation: self.correlation,
+            deadline: self.deadline,
+            token: self.token,
+            sender_pid: self.sender_pid,
         };
 
         for index in 0..Message::HANDLE_COUNT {
             if let Some(kernel_handle) = &self.handles[index] {
-                message.handles[index] = receiver.handles().open(kernel_handle.clone()).as_u64();
+                message.handles[index] = receiver
+                    .handles()
+                    .open(kernel_handle.clone())
+                    .expect("Could not open message handle")
+                    .as_u64();
             }
         }
 
@@ -1,9 +1,12 @@
 use alloc::sync::Arc;
-use syscalls::{Error, Message};
+use syscalls::{Error, Message, MessageHeader};
 
-use crate::user::{process::Process, thread::WaitQueue};
+use crate::user::{
+    process::Process,
+    thread::{Thread, WaitQueue},
+};
 
-use super::Port;
+use super::{Handoff, Port};
 
 pub fn access(port: Arc<Port>) -> (Arc<PortReceiver>, Arc<PortSender>) {
     (PortReceiver::new(port.clone()), PortSender::new(port))
@@ -36,6 +39,20 @@ impl PortReceiver {
         self.port.receive(receiver)
     }
 
+    /// Get the header of the first message, without receiving it
+    ///
+    /// Note: the operation does not block
+    pub fn peek(&self) -> Result<MessageHeader, Error> {
+        self.port.peek()
+    }
+
+    /// Drop the first message without receiving it
+    ///
+    /// Note: the operation does not block
+    pub fn discard(&self) -> Result<(), Error> {
+        self.port.discard()
+    }
+
     /// Prepare a wait on the port
     ///
     /// Return None if the port is already ready for receive
@@ -43,6 +60,18 @@ impl PortReceiver {
         self.port.prepare_wait()
     }
 
+    /// Prepare a blocking receive on the port
+    ///
+    /// Return None if a message is already queued
+    pub fn prepare_receive(&self, thread: &Arc<Thread>) -> Option<(&Arc<WaitQueue>, Arc<Handoff>)> {
+        self.port.prepare_receive(thread)
+    }
+
+    /// End a blocking receive on the port
+    pub fn finish_receive(&self, handoff: &Arc<Handoff>) {
+        self.port.finish_receive(handoff)
+    }
+
     /// Get the inner port
     pub fn port(&self) -> &Arc<Port> {
         &self.port
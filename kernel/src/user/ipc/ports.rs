@@ -1,11 +1,13 @@
 use lazy_static::lazy_static;
 
 use alloc::{string::String, sync::Arc, vec::Vec};
-use syscalls::Error;
+use syscalls::{Error, Message};
 
 use crate::user::{
     error::{check_arg, duplicate_name},
     id_gen::IdGen,
+    listener::{self, PortEventType},
+    process::QuotaCharge,
     weak_map::WeakMap,
 };
 
@@ -36,8 +38,14 @@ impl Ports {
     /// Note: if specified, port name must be unique
     pub fn create(
         &self,
+        owner_pid: u64,
         name: Option<&str>,
+        data_capacity: usize,
+        broadcast: bool,
+        charge: QuotaCharge,
     ) -> Result<(Arc<PortReceiver>, Arc<PortSender>), Error> {
+        check_arg((Message::MIN_DATA_CAPACITY..=Message::DATA_SIZE).contains(&data_capacity))?;
+
         let name_str = name.map(String::from);
 
         if let Some(name_str) = &name_str {
@@ -50,16 +58,17 @@ impl Ports {
         }
 
         let id = self.id_gen.generate();
-        let port = port::new(id, name);
+        let port = port::new(id, owner_pid, name, data_capacity, broadcast, charge);
         let (receiver, sender) = access(port);
 
+        self.ports.insert(id, &sender);
+
         if let Some(name_str) = name_str {
             // TODO: thread safety
-            self.names_map.insert(name_str, &sender);
+            self.names_map.insert(name_str.clone(), &sender);
+            listener::notify_port(id, &name_str, PortEventType::Registered);
         }
 
-        self.ports.insert(id, &sender);
-
         Ok((receiver, sender))
     }
 
@@ -69,6 +78,7 @@ impl Ports {
 
         if let Some(name) = port.name() {
             self.names_map.remove(String::from(name));
+            listener::notify_port(port.id(), name, PortEventType::Unregistered);
         }
     }
 
@@ -86,6 +96,21 @@ impl Ports {
     pub fn list(&self) -> Vec<u64> {
         self.ports.keys()
     }
+
+    /// List the named ports, with their ids
+    pub fn list_named(&self) -> Vec<(u64, String)> {
+        self.names_map
+            .keys()
+            .into_iter()
+            .filter_map(|name| Some((self.find_by_name(&name)?.id(), name)))
+            .collect()
+    }
+
+    /// List ids of the ports created by the given process
+    pub fn list_by_owner(&self, pid: u64) -> Vec<u64> {
+        self.ports
+            .keys_matching(|sender| sender.port().owner_pid() == pid)
+    }
 }
 
 /// Reserved for port drop
@@ -0,0 +1,41 @@
+use alloc::sync::Arc;
+use syscalls::{MemoryObjectEvent, MemoryObjectEventType};
+
+use crate::logging::debug;
+use crate::user::ipc::PortSender;
+
+use super::message_builder::MessageBuilder;
+
+/// Subscription to the release of a memory object
+///
+/// Note: it is owned by the memory object itself, and notified when it is dropped
+#[derive(Debug)]
+pub struct MemoryObjectReleaseListener {
+    port: Arc<PortSender>,
+    cookie: u64,
+}
+
+impl MemoryObjectReleaseListener {
+    pub fn new(port: Arc<PortSender>, cookie: u64) -> Self {
+        Self { port, cookie }
+    }
+
+    pub fn notify(&self) {
+        let mut builder = MessageBuilder::new();
+
+        let event = builder.data_mut::<MemoryObjectEvent>();
+        event.cookie = self.cookie;
+        event.r#type = MemoryObjectEventType::Released;
+
+        match self.port.kernel_send(builder.message()) {
+            Ok(()) => {}
+            Err(err) => {
+                debug!(
+                    "Failed to send MemoryObjectEvent message to port {}: {:?}",
+                    self.port.id(),
+                    err
+                );
+            }
+        }
+    }
+}
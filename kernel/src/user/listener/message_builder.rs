@@ -14,6 +14,10 @@ impl MessageBuilder {
             message: Message {
                 data: [0; Message::DATA_SIZE],
                 handles: [Handle::invalid().as_u64(); Message::HANDLE_COUNT],
+                correlation: 0,
+                deadline: 0,
+                token: 0,
+                sender_pid: 0,
             },
         }
     }
@@ -1,12 +1,17 @@
 mod filters;
 mod list;
+mod memory_object;
 mod message_builder;
+mod port;
 mod process;
 mod thread;
 
 use self::list::ListenerList;
 pub use self::{
+    memory_object::MemoryObjectReleaseListener,
+    message_builder::MessageBuilder,
+    port::{notify_port, PortListener},
     process::{notify_process, ProcessListener},
     thread::{notify_thread, ThreadListener},
 };
-pub use syscalls::{ProcessEventType, ThreadEventType};
+pub use syscalls::{PortEventType, ProcessEventType, ThreadEventType};
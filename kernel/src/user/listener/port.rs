@@ -0,0 +1,102 @@
+use alloc::{string::String, sync::Arc};
+use core::{marker::PhantomPinned, pin::Pin};
+use lazy_static::lazy_static;
+use syscalls::{PortEvent, PortEventType};
+
+use crate::logging::debug;
+use crate::user::{
+    ipc::{self, PortSender},
+    process::QuotaCharge,
+};
+
+use super::{message_builder::MessageBuilder, ListenerList};
+
+lazy_static! {
+    static ref LISTENERS: ListenerList<PortListener> = ListenerList::new();
+}
+
+/// Notify the registration or unregistration of a named port
+pub fn notify_port(id: u64, name: &str, r#type: PortEventType) {
+    LISTENERS.notify(|listener| listener.notify(id, name, r#type));
+}
+
+/// Represent a listener on port names
+///
+/// It lets clients wait for a server to come up, instead of retrying to open its port.
+#[derive(Debug)]
+pub struct PortListener {
+    /// Empty matches all named ports
+    name: String,
+    is_prefix: bool,
+    port: Arc<PortSender>,
+    _charge: QuotaCharge,
+    _marker: PhantomPinned,
+}
+
+unsafe impl Sync for PortListener {}
+unsafe impl Send for PortListener {}
+
+impl PortListener {
+    pub fn new(
+        port: Arc<PortSender>,
+        name: &str,
+        is_prefix: bool,
+        charge: QuotaCharge,
+    ) -> Pin<Arc<Self>> {
+        let listener = Arc::pin(Self {
+            name: String::from(name),
+            is_prefix,
+            port,
+            _charge: charge,
+            _marker: PhantomPinned,
+        });
+
+        // Note: need not move since we keep tracks of pointers
+        LISTENERS.add(&listener);
+
+        // Report the ports already registered, so that the client does not race with the server
+        // Note: the kernel is not preemptible, no port can be registered meanwhile
+        for (id, name) in ipc::list_named() {
+            listener.notify(id, &name, PortEventType::Registered);
+        }
+
+        listener
+    }
+
+    fn matches(&self, name: &str) -> bool {
+        if self.is_prefix {
+            name.starts_with(self.name.as_str())
+        } else {
+            name == self.name
+        }
+    }
+
+    fn notify(&self, id: u64, name: &str, r#type: PortEventType) {
+        if !self.matches(name) {
+            return;
+        }
+
+        let mut builder = MessageBuilder::new();
+
+        let event = builder.data_mut::<PortEvent>();
+        event.port_id = id;
+        event.r#type = r#type;
+
+        match self.port.kernel_send(builder.message()) {
+            Ok(()) => {}
+            Err(err) => {
+                debug!(
+                    "Failed to send PortEvent message to port {}: {:?}",
+                    self.port.id(),
+                    err
+                );
+            }
+        }
+    }
+}
+
+impl Drop for PortListener {
+    fn drop(&mut self) {
+        LISTENERS.remove(self);
+    }
+}
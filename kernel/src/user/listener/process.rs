@@ -2,10 +2,13 @@ use alloc::{boxed::Box, sync::Arc};
 use core::{fmt::Debug, marker::PhantomPinned, pin::Pin};
 use hashbrown::HashSet;
 use lazy_static::lazy_static;
-use log::debug;
 use syscalls::{ProcessEvent, ProcessEventType};
 
-use crate::user::{ipc::PortSender, process::Process};
+use crate::logging::debug;
+use crate::user::{
+    ipc::PortSender,
+    process::{Process, QuotaCharge},
+};
 
 use super::{message_builder::MessageBuilder, ListenerList};
 
@@ -22,6 +25,7 @@ pub fn notify_process(process: &Process, r#type: ProcessEventType) {
 pub struct ProcessListener {
     filter: Box<dyn Filter>,
     port: Arc<PortSender>,
+    _charge: QuotaCharge,
     _marker: PhantomPinned,
 }
 
@@ -29,7 +33,7 @@ unsafe impl Sync for ProcessListener {}
 unsafe impl Send for ProcessListener {}
 
 impl ProcessListener {
-    pub fn new(port: Arc<PortSender>, pids: Option<&[u64]>) -> Pin<Arc<Self>> {
+    pub fn new(port: Arc<PortSender>, pids: Option<&[u64]>, charge: QuotaCharge) -> Pin<Arc<Self>> {
         let filter = if let Some(list) = pids {
             PidsFilter::new(list)
         } else {
@@ -39,6 +43,7 @@ impl ProcessListener {
         let listener = Arc::pin(Self {
             port,
             filter,
+            _charge: charge,
             _marker: PhantomPinned,
         });
 
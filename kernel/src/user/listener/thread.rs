@@ -2,10 +2,10 @@ use alloc::{boxed::Box, sync::Arc};
 use core::{fmt::Debug, marker::PhantomPinned, pin::Pin};
 use hashbrown::HashSet;
 use lazy_static::lazy_static;
-use log::debug;
 use syscalls::{ThreadEvent, ThreadEventType};
 
-use crate::user::{ipc::PortSender, thread::Thread};
+use crate::logging::debug;
+use crate::user::{ipc::PortSender, process::QuotaCharge, thread::Thread};
 
 use super::{message_builder::MessageBuilder, ListenerList};
 
@@ -22,6 +22,7 @@ pub fn notify_thread(thread: &Thread, r#type: ThreadEventType) {
 pub struct ThreadListener {
     filter: Box<dyn Filter>,
     port: Arc<PortSender>,
+    _charge: QuotaCharge,
     _marker: PhantomPinned,
 }
 
@@ -29,7 +30,12 @@ unsafe impl Sync for ThreadListener {}
 unsafe impl Send for ThreadListener {}
 
 impl ThreadListener {
-    pub fn new(port: Arc<PortSender>, ids: Option<&[u64]>, is_pids: bool) -> Pin<Arc<Self>> {
+    pub fn new(
+        port: Arc<PortSender>,
+        ids: Option<&[u64]>,
+        is_pids: bool,
+        charge: QuotaCharge,
+    ) -> Pin<Arc<Self>> {
         let filter = if let Some(list) = ids {
             if is_pids {
                 PidsFilter::new(list)
@@ -43,6 +49,7 @@ impl ThreadListener {
         let listener = Arc::pin(Self {
             port,
             filter,
+            _charge: charge,
             _marker: PhantomPinned,
         });
 
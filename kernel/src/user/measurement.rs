@@ -0,0 +1,59 @@
+use alloc::vec::Vec;
+use spin::Mutex;
+use syscalls::{sha256, Measurement};
+
+use crate::logging::info;
+
+use super::{error::out_of_memory, Error};
+
+static LOG: Mutex<Log> = Mutex::new(Log {
+    chain: Measurement::INITIAL_CHAIN,
+    entries: Vec::new(),
+});
+
+struct Log {
+    chain: [u8; sha256::DIGEST_SIZE],
+    entries: Vec<Measurement>,
+}
+
+/// Measure `data` and append it to the log
+///
+/// `pid` is the process which requested the measurement (0 for the kernel).
+/// Fails if the log is full: components cannot be loaded unmeasured.
+pub fn measure(pid: u64, name: &str, data: &[u8]) -> Result<(), Error> {
+    let digest = sha256::digest(data);
+
+    let mut log = LOG.lock();
+
+    if log.entries.len() == Measurement::MAX_COUNT {
+        return Err(out_of_memory());
+    }
+
+    let index = log.entries.len() as u64;
+    log.chain = Measurement::extend(&log.chain, &digest);
+    let entry = Measurement::new(index, pid, digest, log.chain, name);
+    log.entries.push(entry);
+
+    info!(
+        "Measured '{}' (index={}, pid={}, size={})",
+        entry.name(),
+        index,
+        pid,
+        data.len()
+    );
+
+    Ok(())
+}
+
+/// Copy the log entries from index `start` into `dest`
+///
+/// Returns the number of entries written.
+pub fn read(start: usize, dest: &mut [Measurement]) -> usize {
+    let log = LOG.lock();
+
+    let entries = log.entries.get(start..).unwrap_or(&[]);
+    let count = entries.len().min(dest.len());
+    dest[..count].copy_from_slice(&entries[..count]);
+
+    count
+}
@@ -1,29 +1,44 @@
 use core::slice::Iter;
 
-use crate::memory::{access_phys, is_page_aligned, phys_allocate, FrameRef, PAGE_SIZE};
+use crate::memory::{is_page_aligned, phys_allocate, phys_allocate_zeroed, FrameRef, PAGE_SIZE};
 use alloc::{sync::Arc, vec::Vec};
+use spin::Mutex;
 
-use super::{error::*, Error};
+use super::{error::*, listener::MemoryObjectReleaseListener, Error};
 
 /// Represent a area in physical memory, that can be mapped into processes
 #[derive(Debug)]
 pub struct MemoryObject {
     pages: Vec<FrameRef>,
+    release_listeners: Mutex<Vec<MemoryObjectReleaseListener>>,
 }
 
 impl MemoryObject {
-    /// Create a new memory object of the given size
+    /// Create a new memory object of the given size, with zeroed content
     pub fn new(size: usize) -> Result<Arc<Self>, Error> {
+        Self::new_with(size, phys_allocate_zeroed)
+    }
+
+    /// Create a new memory object of the given size, with unspecified content
+    ///
+    /// Note: the content may contain data of freed memory (including of other processes), it must be fully overwritten before being exposed.
+    /// Only privileged threads can create them from userland.
+    pub fn new_uninitialized(size: usize) -> Result<Arc<Self>, Error> {
+        Self::new_with(size, phys_allocate)
+    }
+
+    fn new_with(size: usize, allocate: fn() -> Option<FrameRef>) -> Result<Arc<Self>, Error> {
         check_page_alignment(size)?;
         check_positive(size)?;
 
         let page_count = size / PAGE_SIZE;
         let mut object = Self {
             pages: Vec::with_capacity(page_count),
+            release_listeners: Mutex::new(Vec::new()),
         };
 
         for _ in 0..page_count {
-            match phys_allocate() {
+            match allocate() {
                 Some(frame) => {
                     object.pages.push(frame);
                 }
@@ -35,10 +50,6 @@ impl MemoryObject {
             }
         }
 
-        for page in object.pages.iter() {
-            Self::zero_page(page);
-        }
-
         return Ok(Arc::new(object));
     }
 
@@ -47,12 +58,10 @@ impl MemoryObject {
     /// Note: frames will not be zeroed
     ///
     pub fn from_frames(frames: Vec<FrameRef>) -> Arc<Self> {
-        Arc::new(Self { pages: frames })
-    }
-
-    fn zero_page(page: &FrameRef) {
-        let page_data = unsafe { access_phys(page) };
-        page_data.fill(0);
+        Arc::new(Self {
+            pages: frames,
+            release_listeners: Mutex::new(Vec::new()),
+        })
     }
 
     /// Get the size of the memory object
@@ -71,4 +80,24 @@ impl MemoryObject {
         assert!(offset < self.size());
         &self.pages[offset / PAGE_SIZE]
     }
+
+    /// Notify the port when the memory object is released (no more handle nor mapping)
+    pub fn add_release_listener(&self, listener: MemoryObjectReleaseListener) {
+        let mut listeners = self.release_listeners.lock();
+        listeners.push(listener);
+    }
+
+    /// Test if a port is notified when the memory object is released
+    pub fn has_release_listeners(&self) -> bool {
+        let listeners = self.release_listeners.lock();
+        !listeners.is_empty()
+    }
+}
+
+impl Drop for MemoryObject {
+    fn drop(&mut self) {
+        for listener in self.release_listeners.get_mut().iter() {
+            listener.notify();
+        }
+    }
 }
@@ -1,12 +1,19 @@
+mod audit;
 mod error;
+mod frame_audit;
+mod grant;
 mod handle;
 mod id_gen;
 pub mod ipc;
 mod listener;
+mod measurement;
 mod memory_object;
 pub mod process;
+mod strings;
 mod syscalls;
 pub mod thread;
+pub mod timer;
+mod trampoline;
 mod weak_map;
 
 pub use error::Error;
@@ -0,0 +1,114 @@
+//! Invariants of the mapping API
+//!
+//! All operations take a range, which must be non-empty, page aligned, and fully in userspace.
+//! Each operation validates the whole range before changing anything: on error, the address space is left untouched.
+//!
+//! Semantics of the operations on ranges which do not match existing mappings:
+//! - `mmap` at a given address, `mreserve`: the range must be free. Existing mappings are never replaced (`Overlap`).
+//! - `mcommit`: the range must be fully covered by reservations, possibly several (`NotReserved`).
+//! - `munmap`: any range. Mappings crossing its edges are split, and only their parts inside the range are unmapped.
+//!   Unmapped parts of the range are ignored: unmapping a free range is a successful noop.
+//! - `mprotect`: the range must be fully covered by mappings backed by a memory object, possibly several
+//!   (`NotMapped` if part of it is free, `Reserved` if part of it is a reservation).
+//!   The new permissions must be allowed by all of them: mappings keep the maximum permissions of the memory object
+//!   handle or the grant they have been mapped from (`PermissionsExceeded`).
+//!   Mappings crossing its edges are split, and adjacent mappings which become identical are merged back.
+//! - `mname`: the range must be fully covered by mappings, possibly several, reservations included (`NotMapped`).
+//!   Mappings crossing its edges are split, as for `mprotect`.
+//! - `memory_object_at`: the range must be inside one mapping backed by a memory object (`MultipleMappings`, `NotMapped`, `Reserved`).
+//!
+//! Syscalls report `Overlap` as `AddressInUse`, `NotMapped` as `AddressNotMapped`, `Reserved` as `AddressReserved`
+//! and `PermissionsExceeded` as `MemoryAccessDenied`.
+//! `NotReserved` and `MultipleMappings` are reported as `InvalidArgument`. In all cases, the precise reason is logged.
+
+use core::{fmt, ops::Range};
+
+use crate::{
+    memory::VirtAddr,
+    user::{
+        error::{
+            address_in_use, address_not_mapped, address_reserved, check_arg, check_arg_opt,
+            check_is_userspace, check_page_alignment, check_positive, invalid_argument,
+            memory_access_denied,
+        },
+        Error, MemoryObject,
+    },
+};
+
+use super::mappings::USER_SPACE_START;
+
+/// Violation of the invariants of a mapping operation
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MappingError {
+    /// Part of the range is already used
+    Overlap,
+    /// Part of the range is not mapped
+    NotMapped,
+    /// Part of the range is a reservation, which has no memory to access
+    Reserved,
+    /// Part of the range is not a reservation
+    NotReserved,
+    /// The range spans several mappings
+    MultipleMappings,
+    /// The permissions are above the maximum permissions of a mapping of the range
+    PermissionsExceeded,
+}
+
+impl fmt::Display for MappingError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let description = match self {
+            MappingError::Overlap => "range overlaps an existing mapping",
+            MappingError::NotMapped => "range is not fully mapped",
+            MappingError::Reserved => "range contains a reservation",
+            MappingError::NotReserved => "range is not fully reserved",
+            MappingError::MultipleMappings => "range spans several mappings",
+            MappingError::PermissionsExceeded => {
+                "permissions not allowed by a mapping of the range"
+            }
+        };
+
+        f.write_str(description)
+    }
+}
+
+impl From<MappingError> for Error {
+    fn from(err: MappingError) -> Self {
+        match err {
+            MappingError::Overlap => address_in_use(),
+            MappingError::NotMapped => address_not_mapped(),
+            MappingError::Reserved => address_reserved(),
+            MappingError::PermissionsExceeded => memory_access_denied(),
+            MappingError::NotReserved | MappingError::MultipleMappings => invalid_argument(),
+        }
+    }
+}
+
+/// Check the range of a mapping operation, and build it
+///
+/// The range must be non-empty, page aligned, and fully in userspace (without overflowing the address space).
+/// The first page is never mapped, so that null pointers always fault.
+pub fn check_range(addr: VirtAddr, size: usize) -> Result<Range<VirtAddr>, Error> {
+    check_positive(size)?;
+    check_page_alignment(size)?;
+    check_is_userspace(addr)?;
+    check_page_alignment(addr.as_u64() as usize)?;
+    check_arg(addr >= USER_SPACE_START)?;
+
+    let end = addr
+        .as_u64()
+        .checked_add(size as u64)
+        .and_then(|end| VirtAddr::try_new(end).ok());
+    let end = check_is_userspace(check_arg_opt(end)?)?;
+
+    Ok(addr..end)
+}
+
+/// Check that `size` bytes from `offset` are inside the memory object
+pub fn check_memory_object_range(
+    memory_object: &MemoryObject,
+    offset: usize,
+    size: usize,
+) -> Result<(), Error> {
+    let end = check_arg_opt(offset.checked_add(size))?;
+    check_arg(end <= memory_object.size())
+}
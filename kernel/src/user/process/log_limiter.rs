@@ -0,0 +1,78 @@
+use core::mem;
+
+use alloc::string::{String, ToString};
+use log::Level;
+use syscalls::TICK_NS;
+
+/// Length of a rate limit window, in ticks (1 second)
+const WINDOW_TICKS: u64 = 1_000_000_000 / TICK_NS;
+
+/// Max number of records logged by a process per window
+const WINDOW_MAX_RECORDS: usize = 100;
+
+/// Rate limit and repeated message suppression of the records logged by a process
+///
+/// This prevents a process in a tight error loop from flooding the console.
+#[derive(Debug)]
+pub struct LogLimiter {
+    window_start: u64,
+    window_count: usize,
+    dropped: usize,
+    last: Option<(Level, String)>,
+    repeated: usize,
+}
+
+/// Outcome of a record submitted to the limiter
+#[derive(Debug, Default)]
+pub struct LogVerdict {
+    /// Number of times the previous record has been repeated and suppressed, to report before this record
+    pub repeated: usize,
+    /// Number of records dropped by the rate limit in the previous window, to report before this record
+    pub dropped: usize,
+    /// The record must be logged
+    pub accepted: bool,
+}
+
+impl LogLimiter {
+    pub const fn new() -> Self {
+        Self {
+            window_start: 0,
+            window_count: 0,
+            dropped: 0,
+            last: None,
+            repeated: 0,
+        }
+    }
+
+    /// Submit a record, at tick `now`
+    pub fn submit(&mut self, level: Level, message: &str, now: u64) -> LogVerdict {
+        let mut verdict = LogVerdict::default();
+
+        if now - self.window_start >= WINDOW_TICKS {
+            self.window_start = now;
+            self.window_count = 0;
+            verdict.dropped = mem::take(&mut self.dropped);
+        }
+
+        // Repeated records do not count in the rate limit
+        if let Some((last_level, last_message)) = &self.last
+            && *last_level == level
+            && last_message == message
+        {
+            self.repeated += 1;
+            return verdict;
+        }
+
+        verdict.repeated = mem::take(&mut self.repeated);
+
+        if self.window_count >= WINDOW_MAX_RECORDS {
+            self.dropped += 1;
+            return verdict;
+        }
+
+        self.window_count += 1;
+        self.last = Some((level, message.to_string()));
+        verdict.accepted = true;
+        verdict
+    }
+}
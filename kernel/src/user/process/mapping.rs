@@ -1,6 +1,9 @@
 use core::{mem, ops::Range};
 
-use alloc::sync::{Arc, Weak};
+use alloc::{
+    string::String,
+    sync::{Arc, Weak},
+};
 
 use crate::{
     memory::{
@@ -19,6 +22,14 @@ pub struct Mapping {
     /// null if perms is NONE
     memory_object: Option<Arc<MemoryObject>>,
     offset: usize,
+    /// Label for memory investigations (eg: "heap", "stack:12")
+    name: Option<String>,
+    /// Maximum permissions that `mprotect` can give to the mapping: the ones allowed by the handle or the grant it has been mapped from
+    max_perms: Permissions,
+    /// Id of the grant this mapping has been created from, so that revoking the grant can find it
+    grant: Option<u64>,
+    /// Reservation of a stack, committed on page fault from the faulting address up to its end
+    grows_down: bool,
 }
 
 /// Mapping of a memory object in a process
@@ -36,6 +47,10 @@ impl Mapping {
             range,
             memory_object,
             offset,
+            name: None,
+            max_perms: Permissions::READ | Permissions::WRITE | Permissions::EXECUTE,
+            grant: None,
+            grows_down: false,
         };
 
         if let Some(ref _mobj) = mapping.memory_object {
@@ -100,11 +115,70 @@ impl Mapping {
         self.offset
     }
 
+    /// Get the name of the mapping
+    pub fn name(&self) -> Option<&str> {
+        self.name.as_deref()
+    }
+
+    /// Set the name of the mapping
+    pub fn set_name(&mut self, name: Option<&str>) {
+        self.name = name.map(String::from);
+    }
+
+    /// Get the maximum permissions that the mapping can be given
+    pub fn max_permissions(&self) -> Permissions {
+        self.max_perms
+    }
+
+    /// Set the maximum permissions that the mapping can be given (all by default)
+    pub fn set_max_permissions(&mut self, max_perms: Permissions) {
+        self.max_perms = max_perms;
+    }
+
+    /// Get the id of the grant this mapping has been created from, if any
+    pub fn grant(&self) -> Option<u64> {
+        self.grant
+    }
+
+    /// Set the id of the grant this mapping has been created from
+    pub fn set_grant(&mut self, grant: Option<u64>) {
+        self.grant = grant;
+    }
+
+    /// Test if this mapping is the growable part of a stack reservation
+    pub fn grows_down(&self) -> bool {
+        self.grows_down
+    }
+
+    /// Mark this reservation as the growable part of a stack
+    pub fn set_grows_down(&mut self, grows_down: bool) {
+        assert!(self.memory_object.is_none() || !grows_down);
+        self.grows_down = grows_down;
+    }
+
+    /// Replace the memory object of the mapping by a new one, made of the frames it maps
+    ///
+    /// The content is kept, the mapping offset becomes 0.
+    pub fn own_frames(&mut self) {
+        let memory_object = self
+            .memory_object
+            .as_ref()
+            .expect("Cannot own frames of a reservation");
+
+        let frames = (0..self.size())
+            .step_by(PAGE_SIZE)
+            .map(|offset| memory_object.frame(self.offset + offset).clone())
+            .collect();
+
+        self.memory_object = Some(MemoryObject::from_frames(frames));
+        self.offset = 0;
+    }
+
     /// Split this mapping at `addr` into 2 parts.
     ///
     /// self will have the lower part, and the return value will have the higher part.
     ///
-    /// Both will have same MemoryObject, and same permissions
+    /// Both will have same MemoryObject, same permissions (and maximum permissions), same name, same grant and same growth
     pub fn split(&mut self, addr: VirtAddr) -> Mapping {
         assert!(is_userspace(addr));
         assert!(is_page_aligned(addr.as_u64() as usize));
@@ -127,6 +201,10 @@ impl Mapping {
             range: addr..range.end,
             memory_object: self.memory_object.clone(),
             offset: other_offset,
+            name: self.name.clone(),
+            max_perms: self.max_perms,
+            grant: self.grant,
+            grows_down: self.grows_down,
         }
     }
 
@@ -142,21 +220,31 @@ impl Mapping {
     /// Test if the other mapping camn be merged into self:
     /// - the other mapping have to start at the end of self.
     /// - both mapping permissions must be same
+    /// - both mapping names must be same
+    /// - both mapping maximum permissions must be same
+    /// - both mapping grants must be same
+    /// - both mappings must grow the same way
+    /// - both must reference a MemoryObject, or none (reservations)
     /// - if they are referencing a MemoryObject, it must be the same, and offset must correspond
     pub fn can_merge(&self, other: &Mapping) -> bool {
-        if self.range().end != other.range().start || other.permissions() != self.permissions() {
+        if self.range().end != other.range().start
+            || other.name != self.name
+            || other.max_perms != self.max_perms
+            || other.grant != self.grant
+            || other.grows_down != self.grows_down
+        {
             return false;
         }
 
-        if let Some(lower_mobj) = self.memory_object.as_ref() {
-            if !(Arc::ptr_eq(&lower_mobj, other.memory_object.as_ref().unwrap()))
-                || other.offset != self.offset + self.size()
-            {
-                return false;
+        match (self.memory_object.as_ref(), other.memory_object.as_ref()) {
+            (None, None) => true,
+            (Some(lower_mobj), Some(upper_mobj)) => {
+                Arc::ptr_eq(lower_mobj, upper_mobj)
+                    && other.offset == self.offset + self.size()
+                    && other.permissions() == self.permissions()
             }
+            _ => false,
         }
-
-        return true;
     }
 
     unsafe fn map(
@@ -6,17 +6,17 @@ use core::{
     panic,
 };
 
-use alloc::{collections::BTreeMap, format, rc::Rc};
+use alloc::{collections::BTreeMap, format, rc::Rc, sync::Arc, vec::Vec};
 
 use crate::{
     memory::{Permissions, VirtAddr, KERNEL_START, PAGE_SIZE},
     user::{error::out_of_memory, Error},
 };
 
-use super::mapping::Mapping;
+use super::{invariants::MappingError, mapping::Mapping};
 
 // Forbid use of NULL as valid address
-const USER_SPACE_START: VirtAddr = VirtAddr::new_truncate(PAGE_SIZE as u64);
+pub const USER_SPACE_START: VirtAddr = VirtAddr::new_truncate(PAGE_SIZE as u64);
 const USER_SPACE_END: VirtAddr = KERNEL_START;
 
 #[derive(Debug)]
@@ -149,6 +149,15 @@ impl Node {
     }
 }
 
+/// Address space layout of a process: a sorted list of areas, each one free or used by a mapping
+///
+/// The table never holds two adjacent areas which could be merged:
+/// - operations on part of a mapping split it at the edges of their range (see `Mapping::split`),
+///   each part keeping its offset in the memory object
+/// - once done, the areas around the edges are merged back when compatible (see `Mapping::can_merge`):
+///   adjacent free areas, adjacent reservations, or adjacent mappings of the same memory object at contiguous offsets
+///
+/// So that unmapping parts of a mapping, or changing and restoring their permissions, does not fragment the table.
 #[derive(Debug)]
 pub struct Mappings {
     nodes: BTreeMap<VirtAddr, Node>,
@@ -189,6 +198,15 @@ impl Mappings {
         len
     }
 
+    /// Call `f` on each mapping, ordered by address
+    pub fn for_each<F: FnMut(&Mapping)>(&self, mut f: F) {
+        for node in self.nodes.values() {
+            if let Some(mapping) = node.next.is_used() {
+                f(&mapping);
+            }
+        }
+    }
+
     pub fn add(&mut self, mapping: Mapping) {
         let new_area = Area::from_mapping(mapping);
 
@@ -262,13 +280,109 @@ impl Mappings {
         return false;
     }
 
+    /// Get the parts of `range` covered by mappings created from the given grant
+    pub fn grant_ranges(&self, range: &Range<VirtAddr>, grant: u64) -> Vec<Range<VirtAddr>> {
+        let mut ranges = Vec::new();
+
+        for node in self.nodes.values() {
+            let area = &node.next;
+
+            if area.range.end <= range.start || area.range.start >= range.end {
+                continue;
+            }
+
+            if let Some(mapping) = area.is_used()
+                && mapping.grant() == Some(grant)
+            {
+                let start = area.range.start.max(range.start);
+                let end = area.range.end.min(range.end);
+                ranges.push(start..end);
+            }
+        }
+
+        ranges
+    }
+
+    /// Check that `range` is free
+    pub fn check_free(&self, range: &Range<VirtAddr>) -> Result<(), MappingError> {
+        if self.overlaps(range) {
+            Err(MappingError::Overlap)
+        } else {
+            Ok(())
+        }
+    }
+
+    /// Check that `range` is fully covered by reservations (mappings without memory object)
+    pub fn check_reserved(&self, range: &Range<VirtAddr>) -> Result<(), MappingError> {
+        for area in self.areas_in(range) {
+            match area.is_used() {
+                Some(mapping) if mapping.memory_object().is_none() => {}
+                _ => return Err(MappingError::NotReserved),
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Check that `range` is fully covered by mappings
+    ///
+    /// If `accessible` is set, they must all be backed by a memory object (no reservation).
+    pub fn check_mapped(
+        &self,
+        range: &Range<VirtAddr>,
+        accessible: bool,
+    ) -> Result<(), MappingError> {
+        for area in self.areas_in(range) {
+            match area.is_used() {
+                None => return Err(MappingError::NotMapped),
+                Some(mapping) if accessible && mapping.memory_object().is_none() => {
+                    return Err(MappingError::Reserved)
+                }
+                Some(_) => {}
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Check that `perms` are allowed by all the mappings of `range`
+    pub fn check_max_permissions(
+        &self,
+        range: &Range<VirtAddr>,
+        perms: Permissions,
+    ) -> Result<(), MappingError> {
+        for area in self.areas_in(range) {
+            if let Some(mapping) = area.is_used()
+                && !mapping.max_permissions().contains(perms)
+            {
+                return Err(MappingError::PermissionsExceeded);
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Get the mapping containing `addr`, if any
+    pub fn mapping_at(&self, addr: VirtAddr) -> Option<Ref<Mapping>> {
+        let area = self.get(addr);
+
+        // Borrow from the node, not from the temporary Rc
+        let node = self.nodes.get(&area.range.start)?;
+        node.next.is_used()
+    }
+
     /// Check that the given range is only part of one mapping area
-    pub fn is_contigous_mapping(&self, range: &Range<VirtAddr>) -> bool {
-        // Get a cursor starting from the node before our range
+    pub fn check_single_mapping(&self, range: &Range<VirtAddr>) -> Result<(), MappingError> {
         let start_area = self.get(range.start);
         let end_area = self.get(last_page(&range));
 
-        Rc::ptr_eq(&start_area, &end_area) && start_area.is_used().is_some()
+        if start_area.is_used().is_none() {
+            Err(MappingError::NotMapped)
+        } else if !Rc::ptr_eq(&start_area, &end_area) {
+            Err(MappingError::MultipleMappings)
+        } else {
+            Ok(())
+        }
     }
 
     pub fn remove_range(&mut self, range: Range<VirtAddr>) {
@@ -294,7 +408,7 @@ impl Mappings {
             let area = self
                 .nodes
                 .get(&addr)
-                .expect(&format!("missing node {:?}", addr))
+                .unwrap_or_else(|| panic!("missing node {:?}", addr))
                 .next
                 .clone();
             self.replace(Area::empty(area.range.clone()));
@@ -305,12 +419,14 @@ impl Mappings {
             }
         }
 
+        self.release_unmapped_frames(&range);
+
         // Merge all empty area inside
         loop {
             let start_area = &self
                 .nodes
                 .get(&range.start)
-                .expect(&format!("missing node {:?}", addr))
+                .unwrap_or_else(|| panic!("missing node {:?}", addr))
                 .next;
 
             let addr = start_area.range.end;
@@ -335,39 +451,102 @@ impl Mappings {
         self.check_consistency();
     }
 
+    /// Free the frames which are not mapped anymore, after a part of a mapping has been removed
+    ///
+    /// The parts of the mapping left around `range` keep its whole memory object alive.
+    /// If nothing else references it (handle, other mapping, release listener), each part gets its own memory object
+    /// (see `Mapping::own_frames`), so that the frames of the removed part are freed with the old one.
+    fn release_unmapped_frames(&mut self, range: &Range<VirtAddr>) {
+        let left_area = self.nodes[&range.start].prev.clone();
+        let right_area = self.nodes[&range.end].next.clone();
+
+        let memory_object = |area: &Area| {
+            area.is_used()
+                .and_then(|mapping| mapping.memory_object().cloned())
+        };
+
+        let (left_mobj, right_mobj) = (memory_object(&left_area), memory_object(&right_area));
+        let Some(mobj) = left_mobj.as_ref().or(right_mobj.as_ref()) else {
+            return;
+        };
+
+        let parts: Vec<&Rc<Area>> = [(&left_area, &left_mobj), (&right_area, &right_mobj)]
+            .into_iter()
+            .filter(|(_, part_mobj)| {
+                part_mobj
+                    .as_ref()
+                    .is_some_and(|part_mobj| Arc::ptr_eq(part_mobj, mobj))
+            })
+            .map(|(area, _)| area)
+            .collect();
+
+        // References: the parts, and the clones above
+        let references = 2 * parts.len();
+        if Arc::strong_count(mobj) != references || mobj.has_release_listeners() {
+            return;
+        }
+
+        drop((left_mobj, right_mobj));
+
+        for area in parts {
+            if let AreaContent::Used(mapping) = &mut *area.content.borrow_mut() {
+                mapping.own_frames();
+            }
+        }
+    }
+
     pub fn update_access_range(&mut self, range: Range<VirtAddr>, perms: Permissions) {
+        self.update_range(range, |mapping| mapping.set_permissions(perms));
+    }
+
+    pub fn update_name_range(&mut self, range: Range<VirtAddr>, name: Option<&str>) {
+        self.update_range(range, |mapping| mapping.set_name(name));
+    }
+
+    /// Make the mappings fit exactly the range, update them, and merge them back if possible
+    ///
+    /// The range must be fully covered by mappings (see `check_mapped`).
+    fn update_range<F: FnMut(&mut Mapping)>(&mut self, range: Range<VirtAddr>, mut update: F) {
         // Make entries fit perfectly on boundaries
-        let mut start_area = self.get(range.start);
+        let start_area = self.get(range.start);
         if start_area.range.start < range.start {
             // need to split
             self.split(start_area, range.start);
         }
 
-        let mut end_area = self.get(last_page(&range));
+        let end_area = self.get(last_page(&range));
         if end_area.range.end > range.end {
             // need to split
             self.split(end_area, range.end);
         }
 
-        start_area = self.get(range.start);
-        end_area = self.get(last_page(&range));
-
-        // Ensure there is only one range inside
-        assert!(Rc::ptr_eq(&start_area, &end_area));
-        let area = start_area;
+        let mut addr = range.start;
+        while addr < range.end {
+            let area = self
+                .nodes
+                .get(&addr)
+                .unwrap_or_else(|| panic!("missing node {:?}", addr))
+                .next
+                .clone();
 
-        let mut mapping = area.take_mapping();
-        mapping.set_permissions(perms);
-        // Note: even if permissions update failed, we must still deal with setting the mapping back
-        self.replace(Area::from_mapping(mapping));
+            let mut mapping = area.take_mapping();
+            update(&mut mapping);
+            self.replace(Area::from_mapping(mapping));
 
-        // Check if we can merge with prev/next area
-        if self.can_merge(range.start) {
-            self.merge(range.start);
+            addr = area.range.end;
         }
 
-        if self.can_merge(range.end) {
-            self.merge(range.end);
+        // Merge the mappings which became identical, inside the range and with prev/next area
+        let addrs: Vec<VirtAddr> = self
+            .nodes
+            .range(range.start..=range.end)
+            .map(|(&addr, _)| addr)
+            .collect();
+
+        for addr in addrs {
+            if self.can_merge(addr) {
+                self.merge(addr);
+            }
         }
 
         #[cfg(debug_assertions)]
@@ -506,6 +685,15 @@ impl Mappings {
         end.prev = new_area;
     }
 
+    /// Iterate over the areas intersecting `range`
+    fn areas_in<'a>(&'a self, range: &Range<VirtAddr>) -> impl Iterator<Item = &'a Rc<Area>> {
+        let first = self.get(range.start).range.start;
+
+        self.nodes
+            .range(first..range.end)
+            .map(|(_, node)| &node.next)
+    }
+
     fn get(&self, addr: VirtAddr) -> Rc<Area> {
         let (_, node) = self
             .nodes
@@ -555,6 +743,18 @@ impl Mappings {
                 };
 
                 assert!(!is_invalid);
+
+                // Check the memory object offset bookkeeping
+                if let Some(mapping) = next.is_used() {
+                    assert!(*mapping.range() == next.range);
+
+                    if let Some(mobj) = mapping.memory_object() {
+                        assert!(crate::memory::is_page_aligned(mapping.offset()));
+                        assert!(mapping.offset() + mapping.size() <= mobj.size());
+                    } else {
+                        assert!(mapping.offset() == 0);
+                    }
+                }
             }
         }
 
@@ -563,7 +763,7 @@ impl Mappings {
         }
 
         // Very verbose: write out a summary of the address space
-        use log::trace;
+        use crate::logging::trace;
 
         trace!("BEGIN check_consistency");
 
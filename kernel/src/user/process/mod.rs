@@ -1,8 +1,11 @@
+mod invariants;
+mod log_limiter;
 mod mapping;
 mod mappings;
 mod memory_access;
 mod process;
 mod processes;
+mod quota;
 
 use alloc::sync::Arc;
 use alloc::vec::Vec;
@@ -10,11 +13,12 @@ use alloc::vec::Vec;
 pub use self::memory_access::{MemoryAccess, TypedMemoryAccess};
 pub use self::process::{process_remove_thread, Process};
 use self::processes::PROCESSES;
+pub use self::quota::{QuotaCharge, QuotaKind};
 
 use super::Error;
 
-pub fn create(name: &str) -> Result<Arc<Process>, Error> {
-    PROCESSES.create(name)
+pub fn create(name: &str, creator: Option<u64>) -> Result<Arc<Process>, Error> {
+    PROCESSES.create(name, creator)
 }
 
 pub fn find(pid: u64) -> Option<Arc<Process>> {
@@ -1,37 +1,54 @@
 use core::{
     ops::Range,
-    sync::atomic::{AtomicBool, Ordering},
+    sync::atomic::{AtomicBool, AtomicU32, Ordering},
 };
 
-use alloc::{string::String, sync::Arc, vec::Vec};
-use log::{debug, trace};
-use spin::{RwLock, RwLockReadGuard};
+use alloc::{format, string::String, sync::Arc, vec::Vec};
+use spin::{Mutex, RwLock, RwLockReadGuard};
+use syscalls::{MappingInfo, SandboxFlags};
 
+use crate::logging::{debug, trace};
 use crate::{
-    memory::{create_adress_space, AddressSpace, AllocatorError, Permissions, VirtAddr},
+    memory::{
+        create_adress_space, is_userspace, page_aligned_down, AddressSpace, AllocatorError,
+        Permissions, PhysAddr, VirtAddr, PAGE_SIZE,
+    },
     user::{
-        error::check_any_permissions, handle::Handles, listener, thread::Thread, weak_map::WeakMap,
+        error::{check_any_permissions, check_permissions},
+        handle::Handles,
+        listener,
+        strings::{self, Interned},
+        thread::Thread,
+        timer,
+        weak_map::WeakMap,
     },
 };
 
 use super::{
+    invariants::{check_memory_object_range, check_range, MappingError},
+    log_limiter::{LogLimiter, LogVerdict},
     mapping::Mapping,
     mappings::Mappings,
     memory_access::{self, TypedMemoryAccess, TypedSliceMemoryAccess},
     processes::remove_process,
+    quota::{ObjectQuota, QuotaCharge, QuotaKind},
     MemoryAccess,
 };
 
 use crate::user::{
-    error::{check_arg, check_is_userspace, check_page_alignment, check_positive, out_of_memory},
+    error::{
+        check_arg, check_arg_opt, check_is_userspace, check_page_alignment, check_positive,
+        out_of_memory,
+    },
+    grant::Grant,
     Error, MemoryObject,
 };
 
 /// Standalone function, so that Process::new() can remain private
 ///
 /// Note: Only Process type is exported by process module, not this function
-pub fn new(id: u64, name: &str) -> Result<Arc<Process>, Error> {
-    Process::new(id, name)
+pub fn new(id: u64, name: &str, creator: Option<u64>) -> Result<Arc<Process>, Error> {
+    Process::new(id, name, creator)
 }
 
 /// Used from thread drop
@@ -44,17 +61,26 @@ pub fn process_remove_thread(thread: &Thread) {
 #[derive(Debug)]
 pub struct Process {
     id: u64,
-    name: RwLock<String>,
+    name: RwLock<Interned>,
+    /// Pid of the process which created it (none for init)
+    creator: Option<u64>,
     address_space: RwLock<AddressSpace>,
     /// Note: ordered by address
     mappings: RwLock<Mappings>,
     threads: WeakMap<u64, Thread>,
     handles: Handles,
     terminated: AtomicBool,
+    suspended: AtomicBool,
+    log_limiter: Mutex<LogLimiter>,
+    object_quota: Arc<ObjectQuota>,
+    /// Io-ports ranges granted to the process (loaded in the TSS bitmap when it runs)
+    io_ports: RwLock<Vec<Range<u16>>>,
+    /// Bits of `SandboxFlags`
+    sandbox: AtomicU32,
 }
 
 impl Process {
-    fn new(id: u64, name: &str) -> Result<Arc<Self>, Error> {
+    fn new(id: u64, name: &str, creator: Option<u64>) -> Result<Arc<Self>, Error> {
         let address_space = match create_adress_space() {
             Ok(address_space) => address_space,
             Err(err) => {
@@ -67,12 +93,22 @@ impl Process {
 
         let process = Arc::new(Self {
             id,
-            name: RwLock::new(String::from(name)),
+            name: RwLock::new(strings::intern(name)),
+            creator,
             address_space: RwLock::new(address_space),
             mappings: RwLock::new(Mappings::new()),
             threads: WeakMap::new(),
             handles: Handles::new(),
             terminated: AtomicBool::new(false),
+            suspended: AtomicBool::new(false),
+            log_limiter: Mutex::new(LogLimiter::new()),
+            object_quota: ObjectQuota::new(),
+            io_ports: RwLock::new(Vec::new()),
+            // Init has all the rights, others get them from their spawner
+            sandbox: AtomicU32::new(match creator {
+                None => SandboxFlags::ALL.bits(),
+                Some(_) => SandboxFlags::NONE.bits(),
+            }),
         });
 
         debug!(
@@ -90,14 +126,45 @@ impl Process {
     }
 
     /// Get the process name
-    pub fn name<'a>(&'a self) -> RwLockReadGuard<'a, String> {
+    pub fn name(&self) -> RwLockReadGuard<'_, Interned> {
         self.name.read()
     }
 
+    /// Get the pid of the process which created this one
+    ///
+    /// Note: none for init
+    pub fn creator(&self) -> Option<u64> {
+        self.creator
+    }
+
     /// Set the process name
     pub fn set_name(&self, value: &str) {
+        let value = strings::intern(value);
         let mut name = self.name.write();
-        *name = String::from(value);
+        *name = value;
+    }
+
+    /// Describe an address of the process for logs: the mapping containing it, its offset in it and its permissions
+    ///
+    /// eg: `0x200000123 (heap+0x123, READ | WRITE)`, `0x10 (unmapped)`
+    pub fn describe_address(&self, addr: VirtAddr) -> String {
+        if !is_userspace(addr) {
+            return format!("{:#x} (kernel)", addr.as_u64());
+        }
+
+        let mappings = self.mappings.read();
+        let description = match mappings.mapping_at(addr) {
+            Some(mapping) => format!(
+                "{:#x} ({}+{:#x}, {:?})",
+                addr.as_u64(),
+                mapping.name().unwrap_or("<anonymous>"),
+                addr - mapping.range().start,
+                mapping.permissions()
+            ),
+            None => format!("{:#x} (unmapped)", addr.as_u64()),
+        };
+
+        description
     }
 
     /// Get address space of the process
@@ -105,34 +172,88 @@ impl Process {
         &self.address_space
     }
 
+    /// Check an invariant of a mapping operation, and log why it is violated
+    fn check_invariant(
+        &self,
+        operation: &str,
+        range: &Range<VirtAddr>,
+        result: Result<(), MappingError>,
+    ) -> Result<(), Error> {
+        result.map_err(|err| {
+            debug!(
+                "Process {}: {} at {:?} rejected: {}",
+                self.id, operation, range, err
+            );
+            err.into()
+        })
+    }
+
     /// Map a MemoryObject (or part of it) into the process address space, with the given permissions.
     ///
     /// Notes:
     /// - If `addr` is `null`, an address where the mapping can fit will be found.
-    /// - If `addr` is not `null`, this function cannot overwrite part of an existing mapping. Call unmap() before.
+    /// - If `addr` is not `null`, the range must be free: this function never overwrites part of an existing mapping. Call unmap() before.
+    /// - `max_perms` are the permissions allowed by the memory object handle: `mprotect` cannot give more to the mapping.
     ///
+    /// See `invariants` for the semantics of all mapping operations.
     pub fn mmap(
         self: &Arc<Self>,
         addr: VirtAddr,
         size: usize,
         perms: Permissions,
+        max_perms: Permissions,
         memory_object: Option<Arc<MemoryObject>>,
         offset: usize,
+    ) -> Result<VirtAddr, Error> {
+        check_permissions(max_perms, perms)?;
+
+        self.mmap_impl(addr, size, perms, memory_object, offset, |mapping| {
+            mapping.set_max_permissions(max_perms)
+        })
+    }
+
+    /// Map the granted part of a memory object: same as `mmap`, but the mapping is tagged with the grant id,
+    /// so that `revoke_grant` can find it, and its maximum permissions are the ones of the grant.
+    pub fn mmap_grant(
+        self: &Arc<Self>,
+        addr: VirtAddr,
+        perms: Permissions,
+        memory_object: Arc<MemoryObject>,
+        offset: usize,
+        grant: &Grant,
+    ) -> Result<VirtAddr, Error> {
+        check_permissions(grant.permissions(), perms)?;
+
+        let size = grant.size();
+        self.mmap_impl(addr, size, perms, Some(memory_object), offset, |mapping| {
+            mapping.set_max_permissions(grant.permissions());
+            mapping.set_grant(Some(grant.id()));
+        })
+    }
+
+    fn mmap_impl(
+        self: &Arc<Self>,
+        addr: VirtAddr,
+        size: usize,
+        perms: Permissions,
+        memory_object: Option<Arc<MemoryObject>>,
+        offset: usize,
+        setup: impl FnOnce(&mut Mapping),
     ) -> Result<VirtAddr, Error> {
         check_positive(size)?;
         check_page_alignment(size)?;
         check_page_alignment(offset)?;
 
-        if !addr.is_null() {
-            check_is_userspace(addr)?;
-            check_page_alignment(addr.as_u64() as usize)?;
-            check_is_userspace(addr + size)?;
-        }
+        let range = if addr.is_null() {
+            None
+        } else {
+            Some(check_range(addr, size)?)
+        };
 
         if let Some(ref mobj) = memory_object {
             // Force some access on memory object, this ease checks
             check_arg(perms != Permissions::NONE)?;
-            check_arg(size + offset <= mobj.size())?;
+            check_memory_object_range(mobj, offset, size)?;
         } else {
             check_arg(perms == Permissions::NONE)?;
         }
@@ -141,17 +262,15 @@ impl Process {
 
         let mut mappings = self.mappings.write();
 
-        let range = if addr.is_null() {
-            mappings.find_space(size)?
-        } else {
-            let range = addr..addr + size;
-            // Unmap the range first.
-            // TODO: if the mapping fails, we cannot map back what was previously
-            mappings.remove_range(range.clone());
+        let range = if let Some(range) = range {
+            self.check_invariant("mmap", &range, mappings.check_free(&range))?;
             range
+        } else {
+            mappings.find_space(size)?
         };
 
-        let mapping = Mapping::new(self, range.clone(), perms, memory_object, offset)?;
+        let mut mapping = Mapping::new(self, range.clone(), perms, memory_object, offset)?;
+        setup(&mut mapping);
         let addr = mapping.range().start;
 
         mappings.add(mapping);
@@ -166,24 +285,209 @@ impl Process {
         Ok(addr)
     }
 
+    /// Reserve an area of the process address space, without backing it with memory
+    ///
+    /// Accesses to a reservation fault, and its addresses are not used by other mappings until it is unmapped.
+    /// Use `mcommit` to back parts of it with memory later.
+    ///
+    /// Reserving at a given address fails if the area is already used.
+    pub fn mreserve(self: &Arc<Self>, addr: VirtAddr, size: usize) -> Result<VirtAddr, Error> {
+        check_positive(size)?;
+        check_page_alignment(size)?;
+
+        let range = if addr.is_null() {
+            None
+        } else {
+            Some(check_range(addr, size)?)
+        };
+
+        let mut mappings = self.mappings.write();
+
+        let range = if let Some(range) = range {
+            self.check_invariant("mreserve", &range, mappings.check_free(&range))?;
+            range
+        } else {
+            mappings.find_space(size)?
+        };
+
+        mappings.add(Mapping::new(
+            self,
+            range.clone(),
+            Permissions::NONE,
+            None,
+            0,
+        )?);
+
+        trace!("Process {}: reserved {:?}", self.id, range);
+
+        Ok(range.start)
+    }
+
+    /// Back a part of a reservation with a memory object (or part of it), with the given permissions
+    ///
+    /// The area must be fully covered by reservations (see `mreserve`), so that committing never replaces memory in use.
+    /// `max_perms` have the same meaning as in `mmap`.
+    pub fn mcommit(
+        self: &Arc<Self>,
+        addr: VirtAddr,
+        size: usize,
+        perms: Permissions,
+        max_perms: Permissions,
+        memory_object: Arc<MemoryObject>,
+        offset: usize,
+    ) -> Result<(), Error> {
+        let range = check_range(addr, size)?;
+        check_page_alignment(offset)?;
+        check_arg(perms != Permissions::NONE)?;
+        check_permissions(max_perms, perms)?;
+        check_memory_object_range(&memory_object, offset, size)?;
+
+        let mut mappings = self.mappings.write();
+
+        self.check_invariant("mcommit", &range, mappings.check_reserved(&range))?;
+
+        // Reservations have no page mapped: the new mapping can be created before removing them,
+        // so that they are kept if it fails.
+        let mut mapping = Mapping::new(self, range.clone(), perms, Some(memory_object), offset)?;
+        mapping.set_max_permissions(max_perms);
+
+        mappings.remove_range(range.clone());
+        mappings.add(mapping);
+
+        trace!(
+            "Process {}: committed {:?} with perms {:?}",
+            self.id,
+            range,
+            perms
+        );
+
+        Ok(())
+    }
+
+    /// Map a stack of at most `max_size`, with `initial_size` committed at its top
+    ///
+    /// The region is made of, from bottom to top:
+    /// - a guard page (reservation): accesses fault
+    /// - the growable part (reservation): accesses commit memory down to the faulting address (see `grow_stack`)
+    /// - the committed part
+    ///
+    /// Returns the start of the region (guard page included): its size is `max_size + PAGE_SIZE`.
+    pub fn mmap_stack(
+        self: &Arc<Self>,
+        max_size: usize,
+        initial_size: usize,
+    ) -> Result<VirtAddr, Error> {
+        check_positive(initial_size)?;
+        check_page_alignment(initial_size)?;
+        check_page_alignment(max_size)?;
+        check_arg(initial_size <= max_size)?;
+
+        let memory_object = MemoryObject::new(initial_size)?;
+
+        let mut mappings = self.mappings.write();
+
+        let range = mappings.find_space(max_size + PAGE_SIZE)?;
+        let guard_end = range.start + PAGE_SIZE;
+        let committed_start = range.end - initial_size;
+
+        mappings.add(Mapping::new(
+            self,
+            range.start..guard_end,
+            Permissions::NONE,
+            None,
+            0,
+        )?);
+
+        if committed_start > guard_end {
+            let mut growable =
+                Mapping::new(self, guard_end..committed_start, Permissions::NONE, None, 0)?;
+            growable.set_grows_down(true);
+            mappings.add(growable);
+        }
+
+        mappings.add(Mapping::new(
+            self,
+            committed_start..range.end,
+            Permissions::READ | Permissions::WRITE,
+            Some(memory_object),
+            0,
+        )?);
+
+        trace!(
+            "Process {}: stack mapped at {:?} (committed from {:?})",
+            self.id,
+            range,
+            committed_start
+        );
+
+        Ok(range.start)
+    }
+
+    /// Commit the growable part of a stack which contains the faulting address
+    ///
+    /// At least `STACK_GROWTH_SIZE` is committed at once (bounded by the growable part), to limit faults and mappings.
+    ///
+    /// Returns false if the address is not in the growable part of a stack: the fault is a real error.
+    pub fn grow_stack(self: &Arc<Self>, addr: VirtAddr) -> bool {
+        const STACK_GROWTH_SIZE: usize = PAGE_SIZE * 16;
+
+        let mut mappings = self.mappings.write();
+
+        let (range, name) = {
+            let Some(mapping) = mappings.mapping_at(addr) else {
+                return false;
+            };
+
+            if !mapping.grows_down() {
+                return false;
+            }
+
+            let mapping_range = mapping.range().clone();
+            let end = mapping_range.end;
+            let start = VirtAddr::new(page_aligned_down(addr.as_u64() as usize) as u64)
+                .min(end - STACK_GROWTH_SIZE as u64)
+                .max(mapping_range.start);
+
+            (start..end, mapping.name().map(String::from))
+        };
+
+        let size = (range.end - range.start) as usize;
+        let Ok(memory_object) = MemoryObject::new(size) else {
+            return false;
+        };
+
+        let Ok(mut committed) = Mapping::new(
+            self,
+            range.clone(),
+            Permissions::READ | Permissions::WRITE,
+            Some(memory_object),
+            0,
+        ) else {
+            return false;
+        };
+
+        committed.set_name(name.as_deref());
+
+        mappings.remove_range(range.clone());
+        mappings.add(committed);
+
+        trace!("Process {}: stack grown at {:?}", self.id, range);
+
+        true
+    }
+
     /// Unmap the address space from addr to addr+size.
     ///
     /// Notes:
     /// - It may contains multiple mappings,
-    /// - addr or addr+size may be in the middle of a mapping
+    /// - addr or addr+size may be in the middle of a mapping: it is split, and only the part inside the range is unmapped
     /// - part of the specified area my not be mapped. In consequence, calling unmap() on an unmapped area is a successful noop.
     ///
     pub fn munmap(&self, addr: VirtAddr, size: usize) -> Result<(), Error> {
-        check_positive(size)?;
-        check_page_alignment(size)?;
-        check_is_userspace(addr)?;
-        check_page_alignment(addr.as_u64() as usize)?;
-        check_is_userspace(addr + size)?;
+        let range = check_range(addr, size)?;
 
         let mut mappings = self.mappings.write();
 
-        let range = addr..addr + size;
-
         mappings.remove_range(range.clone());
 
         trace!("Process {}: unmapped at {:?}", self.id, range);
@@ -191,24 +495,85 @@ impl Process {
         Ok(())
     }
 
+    /// Get the memory object backing the given memory region, its offset in the memory object, the permissions of the region,
+    /// and the grant it has been mapped from
+    ///
+    /// Notes:
+    /// - It can only contains one mapping, which must have a memory object
+    /// - The mapping may be larger than the given region.
+    pub fn memory_object_at(
+        &self,
+        addr: VirtAddr,
+        size: usize,
+    ) -> Result<(Arc<MemoryObject>, usize, Permissions, Option<u64>), Error> {
+        let range = check_range(addr, size)?;
+
+        let mappings = self.mappings.read();
+
+        self.check_invariant(
+            "memory_object_at",
+            &range,
+            mappings.check_single_mapping(&range),
+        )?;
+        self.check_invariant(
+            "memory_object_at",
+            &range,
+            mappings.check_mapped(&range, true),
+        )?;
+
+        let mapping = check_arg_opt(mappings.mapping_at(addr))?;
+        let memory_object = check_arg_opt(mapping.memory_object())?.clone();
+        let offset = mapping.offset() + (addr - mapping.range().start) as usize;
+
+        Ok((
+            memory_object,
+            offset,
+            mapping.permissions(),
+            mapping.grant(),
+        ))
+    }
+
+    /// Unmap the parts of `range` mapped from the given grant
+    ///
+    /// The unmapped parts are replaced by reservations: accesses fault, and the addresses are not reused
+    /// until the process unmaps them (so that it does not unmap something else instead later).
+    pub fn revoke_grant(self: &Arc<Self>, range: Range<VirtAddr>, grant: u64) {
+        let mut mappings = self.mappings.write();
+
+        for range in mappings.grant_ranges(&range, grant) {
+            mappings.remove_range(range.clone());
+
+            let reservation = Mapping::new(self, range.clone(), Permissions::NONE, None, 0)
+                .expect("Could not create reservation");
+            mappings.add(reservation);
+
+            trace!(
+                "Process {}: grant {} revoked at {:?}",
+                self.id,
+                grant,
+                range
+            );
+        }
+    }
+
     /// Change the permissions for the given memory region
     ///
     /// Notes:
-    /// - It can only contains one mapping
-    /// - The mapping may be larger than the given region. It will be split.
+    /// - It may contains multiple mappings, but must be fully mapped, without reservation
+    /// - The permissions must be allowed by all the mappings (see `mmap`)
+    /// - The mappings at its edges may be larger than the given region. They will be split.
     pub fn mprotect(&self, addr: VirtAddr, size: usize, perms: Permissions) -> Result<(), Error> {
-        check_positive(size)?;
-        check_page_alignment(size)?;
-        check_is_userspace(addr)?;
-        check_page_alignment(addr.as_u64() as usize)?;
-        check_is_userspace(addr + size)?;
+        let range = check_range(addr, size)?;
         check_any_permissions(perms)?;
 
         let mut mappings = self.mappings.write();
 
-        let range = addr..addr + size;
-
-        check_arg(mappings.is_contigous_mapping(&range))?;
+        self.check_invariant("mprotect", &range, mappings.check_mapped(&range, true))?;
+        self.check_invariant(
+            "mprotect",
+            &range,
+            mappings.check_max_permissions(&range, perms),
+        )?;
 
         mappings.update_access_range(range.clone(), perms);
 
@@ -222,6 +587,25 @@ impl Process {
         Ok(())
     }
 
+    /// Set the name of the given memory region, or clear it if `name` is None
+    ///
+    /// Notes:
+    /// - It may contains multiple mappings (reservations included), but must be fully mapped
+    /// - The mappings at its edges may be larger than the given region. They will be split.
+    pub fn mname(&self, addr: VirtAddr, size: usize, name: Option<&str>) -> Result<(), Error> {
+        let range = check_range(addr, size)?;
+
+        let mut mappings = self.mappings.write();
+
+        self.check_invariant("mname", &range, mappings.check_mapped(&range, false))?;
+
+        mappings.update_name_range(range.clone(), name);
+
+        trace!("Process {}: mname at {:?} -> {:?}", self.id, range, name);
+
+        Ok(())
+    }
+
     /// Create a new memory access to a part of the process VM
     ///
     /// permissions are at least expected permission in address space.
@@ -286,6 +670,64 @@ impl Process {
         self.terminated.load(Ordering::Relaxed)
     }
 
+    /// Get if the process is suspended: its threads are not scheduled
+    pub fn suspended(&self) -> bool {
+        self.suspended.load(Ordering::Relaxed)
+    }
+
+    /// Mark the process as suspended or not
+    ///
+    /// Note: the threads must then be suspended or unsuspended
+    pub fn set_suspended(&self, value: bool) {
+        self.suspended.store(value, Ordering::Relaxed);
+    }
+
+    /// Submit a record logged by the process to its rate limit and repeated message suppression
+    pub fn log_submit(&self, level: log::Level, message: &str) -> LogVerdict {
+        let mut limiter = self.log_limiter.lock();
+        limiter.submit(level, message, timer::ticks())
+    }
+
+    /// Get the sandbox rights of the process
+    pub fn sandbox(&self) -> SandboxFlags {
+        SandboxFlags::from_bits(self.sandbox.load(Ordering::Relaxed))
+    }
+
+    /// Set the sandbox rights of the process
+    pub fn set_sandbox(&self, value: SandboxFlags) {
+        self.sandbox.store(value.bits(), Ordering::Relaxed);
+    }
+
+    /// Get the io-ports ranges granted to the process
+    pub fn io_ports(&self) -> RwLockReadGuard<'_, Vec<Range<u16>>> {
+        self.io_ports.read()
+    }
+
+    /// Check if all the io-ports of the range are granted to the process
+    pub fn has_io_ports(&self, range: &Range<u16>) -> bool {
+        let io_ports = self.io_ports.read();
+        range
+            .clone()
+            .all(|port| io_ports.iter().any(|granted| granted.contains(&port)))
+    }
+
+    /// Grant a range of io-ports to the process
+    ///
+    /// Note: the TSS bitmap is only updated on the next switch to the process
+    pub fn grant_io_ports(&self, range: Range<u16>) {
+        self.io_ports.write().push(range);
+    }
+
+    /// Get the quota of kernel objects of the process
+    pub fn object_quota(&self) -> &ObjectQuota {
+        &self.object_quota
+    }
+
+    /// Charge a new kernel object to the process quota
+    pub fn charge_object(&self, kind: QuotaKind) -> Result<QuotaCharge, Error> {
+        self.object_quota.charge(kind)
+    }
+
     /// Get the handle manager of the process
     pub fn handles(&self) -> &Handles {
         &self.handles
@@ -307,6 +749,74 @@ impl Process {
 
         mappings.len()
     }
+
+    /// Get information about all the mappings of the process, ordered by address
+    pub fn mappings_info(&self) -> Vec<MappingInfo> {
+        let mappings = self.mappings.read();
+        let mut list = Vec::with_capacity(mappings.len());
+
+        mappings.for_each(|mapping| {
+            list.push(Self::mapping_info(mapping));
+        });
+
+        list
+    }
+
+    /// Get the mappings which overlap the given range (reservations included), ordered by address
+    ///
+    /// Lets loaders find what is in the way of a fixed address mapping.
+    pub fn mappings_info_in(&self, addr: VirtAddr, size: usize) -> Result<Vec<MappingInfo>, Error> {
+        check_positive(size)?;
+        check_is_userspace(addr)?;
+        check_is_userspace(addr + size)?;
+
+        let range = addr..addr + size;
+        let mappings = self.mappings.read();
+        let mut list = Vec::new();
+
+        mappings.for_each(|mapping| {
+            if mapping.range().start < range.end && range.start < mapping.range().end {
+                list.push(Self::mapping_info(mapping));
+            }
+        });
+
+        Ok(list)
+    }
+
+    fn mapping_info(mapping: &Mapping) -> MappingInfo {
+        MappingInfo::new(
+            mapping.range().start.as_u64() as usize,
+            mapping.size(),
+            mapping.permissions(),
+            mapping.memory_object().is_some(),
+            mapping.grows_down(),
+            mapping.name(),
+        )
+    }
+
+    /// Call `f` on each page mapped from a memory object.
+    ///
+    /// `f` gets the memory object, the offset of the page inside it, and the frame found in the page table.
+    pub fn visit_mapped_pages<F: FnMut(&Arc<MemoryObject>, usize, Option<PhysAddr>)>(
+        &self,
+        mut f: F,
+    ) {
+        let mappings = self.mappings.read();
+        let address_space = self.address_space.read();
+
+        mappings.for_each(|mapping| {
+            let Some(mobj) = mapping.memory_object() else {
+                return;
+            };
+
+            let mut offset = mapping.offset();
+            for addr in mapping.range().clone().step_by(PAGE_SIZE) {
+                let (frame, _, _) = unsafe { address_space.get_infos(addr) };
+                f(mobj, offset, frame);
+                offset += PAGE_SIZE;
+            }
+        });
+    }
 }
 
 impl Drop for Process {
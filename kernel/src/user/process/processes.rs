@@ -25,9 +25,9 @@ impl Processes {
     }
 
     /// Create a new process
-    pub fn create(&self, name: &str) -> Result<Arc<Process>, Error> {
+    pub fn create(&self, name: &str, creator: Option<u64>) -> Result<Arc<Process>, Error> {
         let id = self.id_gen.generate();
-        let process = process::new(id, name)?;
+        let process = process::new(id, name, creator)?;
 
         self.processes.insert(id, &process);
 
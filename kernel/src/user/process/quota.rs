@@ -0,0 +1,109 @@
+use core::sync::atomic::{AtomicUsize, Ordering};
+
+use alloc::sync::Arc;
+use spin::Mutex;
+use syscalls::ObjectCounts;
+
+use crate::user::{error::quota_exceeded, Error};
+
+/// Kind of kernel object accounted in the quota of a process
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum QuotaKind {
+    Port,
+    Timer,
+    Listener,
+}
+
+/// Limits on the number of kernel objects a process can create, and its current usage
+///
+/// Objects are charged to the process which created them, for their whole life:
+/// they stay charged even if their handles are sent to another process.
+#[derive(Debug)]
+pub struct ObjectQuota {
+    limits: Mutex<ObjectCounts>,
+    ports: AtomicUsize,
+    timers: AtomicUsize,
+    listeners: AtomicUsize,
+}
+
+impl ObjectQuota {
+    pub fn new() -> Arc<Self> {
+        Arc::new(Self {
+            limits: Mutex::new(ObjectCounts::DEFAULT_LIMITS),
+            ports: AtomicUsize::new(0),
+            timers: AtomicUsize::new(0),
+            listeners: AtomicUsize::new(0),
+        })
+    }
+
+    /// Get the limits
+    pub fn limits(&self) -> ObjectCounts {
+        *self.limits.lock()
+    }
+
+    /// Set the limits
+    ///
+    /// Note: they can be lower than the current usage, then the creation of objects of this kind fails until enough have been released
+    pub fn set_limits(&self, value: ObjectCounts) {
+        *self.limits.lock() = value;
+    }
+
+    /// Get the current usage
+    pub fn usage(&self) -> ObjectCounts {
+        ObjectCounts {
+            ports: self.ports.load(Ordering::Relaxed),
+            timers: self.timers.load(Ordering::Relaxed),
+            listeners: self.listeners.load(Ordering::Relaxed),
+        }
+    }
+
+    /// Charge a new object: the returned charge must be kept by the object, and released with it
+    pub fn charge(self: &Arc<Self>, kind: QuotaKind) -> Result<QuotaCharge, Error> {
+        let limits = self.limits();
+        let (counter, limit) = match kind {
+            QuotaKind::Port => (&self.ports, limits.ports),
+            QuotaKind::Timer => (&self.timers, limits.timers),
+            QuotaKind::Listener => (&self.listeners, limits.listeners),
+        };
+
+        counter
+            .fetch_update(Ordering::Relaxed, Ordering::Relaxed, |count| {
+                if count < limit {
+                    Some(count + 1)
+                } else {
+                    None
+                }
+            })
+            .map_err(|_| quota_exceeded())?;
+
+        Ok(QuotaCharge {
+            quota: self.clone(),
+            kind,
+        })
+    }
+
+    fn release(&self, kind: QuotaKind) {
+        let counter = match kind {
+            QuotaKind::Port => &self.ports,
+            QuotaKind::Timer => &self.timers,
+            QuotaKind::Listener => &self.listeners,
+        };
+
+        counter.fetch_sub(1, Ordering::Relaxed);
+    }
+}
+
+/// Object charged to the quota of a process: released on drop
+///
+/// Note: holds the quota and not the process, so that the objects do not keep their creator alive.
+#[derive(Debug)]
+pub struct QuotaCharge {
+    quota: Arc<ObjectQuota>,
+    kind: QuotaKind,
+}
+
+impl Drop for QuotaCharge {
+    fn drop(&mut self) {
+        self.quota.release(self.kind);
+    }
+}
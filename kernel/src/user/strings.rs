@@ -0,0 +1,126 @@
+use core::{fmt, ops::Deref};
+
+use alloc::{collections::BTreeMap, sync::Arc};
+use spin::Mutex;
+
+use super::id_gen::IdGen;
+
+static IDS: IdGen = IdGen::new();
+
+static TABLE: Mutex<Table> = Mutex::new(Table::new());
+
+/// System-wide table of interned strings (process, thread and port names)
+///
+/// Each distinct string is stored once, and gets an id which is never reused for another string.
+/// Userland gets the ids in info structs and resolves them with `StringLookup`: since a string never changes,
+/// lookups can be cached forever. A rename gives a new id, so that caches never show stale names.
+struct Table {
+    by_value: BTreeMap<Arc<str>, u64>,
+    /// Value: (string, reference count)
+    by_id: BTreeMap<u64, (Arc<str>, usize)>,
+}
+
+impl Table {
+    const fn new() -> Self {
+        Self {
+            by_value: BTreeMap::new(),
+            by_id: BTreeMap::new(),
+        }
+    }
+}
+
+/// Interned string: the entry is removed from the table when its last reference is dropped
+pub struct Interned {
+    id: u64,
+    value: Arc<str>,
+}
+
+impl Interned {
+    /// Get the id of the string
+    pub fn id(&self) -> u64 {
+        self.id
+    }
+
+    pub fn as_str(&self) -> &str {
+        &self.value
+    }
+}
+
+impl Deref for Interned {
+    type Target = str;
+
+    fn deref(&self) -> &str {
+        &self.value
+    }
+}
+
+impl fmt::Debug for Interned {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:?}#{}", &*self.value, self.id)
+    }
+}
+
+impl fmt::Display for Interned {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.value)
+    }
+}
+
+impl Clone for Interned {
+    fn clone(&self) -> Self {
+        let mut table = TABLE.lock();
+        let (_, count) = table
+            .by_id
+            .get_mut(&self.id)
+            .expect("interned string not found");
+        *count += 1;
+
+        Self {
+            id: self.id,
+            value: self.value.clone(),
+        }
+    }
+}
+
+impl Drop for Interned {
+    fn drop(&mut self) {
+        let mut table = TABLE.lock();
+        let (_, count) = table
+            .by_id
+            .get_mut(&self.id)
+            .expect("interned string not found");
+        *count -= 1;
+
+        if *count == 0 {
+            table.by_id.remove(&self.id);
+            table.by_value.remove(&self.value);
+        }
+    }
+}
+
+/// Intern a string
+pub fn intern(value: &str) -> Interned {
+    let mut table = TABLE.lock();
+
+    if let Some(&id) = table.by_value.get(value) {
+        let (value, count) = table.by_id.get_mut(&id).expect("interned string not found");
+        *count += 1;
+        return Interned {
+            id,
+            value: value.clone(),
+        };
+    }
+
+    let id = IDS.generate();
+    let value: Arc<str> = Arc::from(value);
+    table.by_value.insert(value.clone(), id);
+    table.by_id.insert(id, (value.clone(), 1));
+
+    Interned { id, value }
+}
+
+/// Get an interned string from its id, if it is still in use
+pub fn lookup(id: u64) -> Option<Arc<str>> {
+    let table = TABLE.lock();
+    table.by_id.get(&id).map(|(value, _)| value.clone())
+}
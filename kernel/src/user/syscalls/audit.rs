@@ -0,0 +1,111 @@
+use syscalls::{AuditEventType, AuditRecord, AuditRule, Permissions};
+
+use crate::{
+    memory::VirtAddr,
+    user::{
+        audit,
+        error::{check_arg, invalid_argument, not_supported},
+        syscalls::{context::Context, helpers::StringReader},
+        Error,
+    },
+};
+
+pub async fn set_rules(context: Context) -> Result<(), Error> {
+    let rules_ptr = context.arg1();
+    let count = context.arg2();
+
+    let thread = context.owner();
+    let process = thread.process();
+
+    // Rules decide what gets audited: reserved to privileged threads
+    if !thread.privileged() {
+        return Err(not_supported());
+    }
+
+    check_arg(count <= AuditRule::MAX_COUNT)?;
+
+    let rules_access = process.vm_access_typed_slice::<AuditRule>(
+        VirtAddr::new(rules_ptr as u64),
+        count,
+        Permissions::READ,
+    )?;
+
+    audit::set_rules(rules_access.get())
+}
+
+pub async fn read(context: Context) -> Result<(), Error> {
+    let cursor_ptr = context.arg1();
+    let array_ptr = context.arg2();
+    let count_ptr = context.arg3();
+
+    let thread = context.owner();
+    let process = thread.process();
+
+    // Records describe the operations of all processes: reserved to privileged threads
+    if !thread.privileged() {
+        return Err(not_supported());
+    }
+
+    // In/out: sequence number of the next record to read
+    let mut cursor_access = process.vm_access_typed::<u64>(
+        VirtAddr::new(cursor_ptr as u64),
+        Permissions::READ | Permissions::WRITE,
+    )?;
+
+    // In: size of the array, out: number of records read
+    let mut count_access = process.vm_access_typed::<usize>(
+        VirtAddr::new(count_ptr as u64),
+        Permissions::READ | Permissions::WRITE,
+    )?;
+
+    let mut array_access = process.vm_access_typed_slice::<AuditRecord>(
+        VirtAddr::new(array_ptr as u64),
+        *count_access.get(),
+        Permissions::READ | Permissions::WRITE,
+    )?;
+
+    *count_access.get_mut() = audit::read(cursor_access.get_mut(), array_access.get_mut());
+
+    Ok(())
+}
+
+pub async fn submit(context: Context) -> Result<(), Error> {
+    let r#type = context.arg1();
+    let object = context.arg2();
+    let name_ptr = context.arg3();
+    let name_len = context.arg4();
+
+    let thread = context.owner();
+
+    // Only server operations can be submitted: kernel records cannot be forged
+    let r#type = parse_type(r#type)?;
+    check_arg(r#type.is_server_operation())?;
+
+    let name_reader = StringReader::new(&context, name_ptr, name_len)?;
+    let name = name_reader.str()?;
+    let name = if name.is_empty() { None } else { Some(name) };
+
+    audit::record(&thread, r#type, object as u64, name);
+
+    Ok(())
+}
+
+fn parse_type(r#type: usize) -> Result<AuditEventType, Error> {
+    const PROCESS_CREATE_USIZE: usize = AuditEventType::ProcessCreate as usize;
+    const PORT_REGISTER_USIZE: usize = AuditEventType::PortRegister as usize;
+    const IO_PORT_GRANT_USIZE: usize = AuditEventType::IoPortGrant as usize;
+    const MOUNT_USIZE: usize = AuditEventType::Mount as usize;
+    const UNMOUNT_USIZE: usize = AuditEventType::Unmount as usize;
+    const CLOCK_SET_USIZE: usize = AuditEventType::ClockSet as usize;
+    const DEVICE_CLAIM_USIZE: usize = AuditEventType::DeviceClaim as usize;
+    match r#type {
+        PROCESS_CREATE_USIZE => Ok(AuditEventType::ProcessCreate),
+        PORT_REGISTER_USIZE => Ok(AuditEventType::PortRegister),
+        IO_PORT_GRANT_USIZE => Ok(AuditEventType::IoPortGrant),
+        MOUNT_USIZE => Ok(AuditEventType::Mount),
+        UNMOUNT_USIZE => Ok(AuditEventType::Unmount),
+        CLOCK_SET_USIZE => Ok(AuditEventType::ClockSet),
+        DEVICE_CLAIM_USIZE => Ok(AuditEventType::DeviceClaim),
+        _ => Err(invalid_argument()),
+    }
+}
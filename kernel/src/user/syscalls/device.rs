@@ -0,0 +1,62 @@
+use syscalls::{AuditEventType, DeviceInfo, DeviceResourceType, SandboxFlags};
+
+use crate::{
+    devices::inventory::{self, ClaimError},
+    gdt,
+    user::{
+        audit,
+        error::{check_arg, not_supported, object_not_ready},
+        process, Error,
+    },
+};
+
+use super::{context::Context, helpers::ListOutputWriter};
+
+pub async fn list(context: Context) -> Result<(), Error> {
+    let array_ptr = context.arg1();
+    let count_ptr = context.arg2();
+
+    let mut writer = ListOutputWriter::<DeviceInfo>::new(&context, array_ptr, count_ptr)?;
+
+    writer.fill(&inventory::list());
+
+    Ok(())
+}
+
+/// Claim a PCI device to drive it
+///
+/// The io-ports of the device are granted to the caller, and bus mastering is enabled so that it can use DMA.
+pub async fn claim(context: Context) -> Result<(), Error> {
+    let address = context.arg1();
+
+    let thread = context.owner();
+    let process = thread.process();
+
+    if !thread.privileged() && !process.sandbox().contains(SandboxFlags::DEVICE) {
+        return Err(not_supported());
+    }
+
+    let alive = |pid| process::find(pid).is_some_and(|owner| !owner.terminated());
+    let device = match inventory::claim(address as u64, process.id(), alive) {
+        Ok(device) => device,
+        Err(ClaimError::NotFound) => return Err(Error::ObjectNotFound),
+        Err(ClaimError::Claimed) => return Err(object_not_ready()),
+    };
+
+    for resource in device.resources.iter() {
+        if resource.r#type != DeviceResourceType::IoPort {
+            continue;
+        }
+
+        let end = resource.start + resource.len;
+        check_arg(end <= u16::MAX as u64)?;
+        let range = resource.start as u16..end as u16;
+
+        process.grant_io_ports(range.clone());
+        gdt::set_io_ports(range, true);
+    }
+
+    audit::record(&thread, AuditEventType::DeviceClaim, address as u64, None);
+
+    Ok(())
+}
@@ -1,4 +1,5 @@
 use core::{
+    arch::x86_64::_rdtsc,
     cell::RefCell,
     fmt,
     future::{pending, Future},
@@ -7,7 +8,7 @@ use core::{
     task,
 };
 
-use super::Context;
+use super::{stats, Context};
 use alloc::{
     boxed::Box,
     sync::{Arc, Weak},
@@ -15,10 +16,10 @@ use alloc::{
 };
 use hashbrown::HashMap;
 use lazy_static::lazy_static;
-use log::trace;
 use spin::RwLock;
 use syscalls::{Error, SUCCESS};
 
+use crate::logging::trace;
 use crate::{
     interrupts::SyscallArgs,
     user::{
@@ -88,7 +89,7 @@ pub fn execute_syscall(n: usize, context: SyscallArgs) {
     // If the number is not in struct we just won't get the key
     let syscall_number: SyscallNumber = unsafe { mem::transmute(n) };
 
-    trace!("Syscall {syscall_number:?} {context:?}");
+    trace!("Syscall {:?} {:?}", syscall_number, context);
 
     // Do not keep the lock while executing, else we cannot register/unregister syscalls from a syscall
     let handler = {
@@ -120,13 +121,13 @@ pub fn register_syscall<Handler: SyscallHandler>(syscall_number: SyscallNumber,
         let context = Context::from(inner, &thread);
         let future = handler(context);
 
-        let executor = SyscallExecutor::new(thread.clone(), future);
+        let executor = SyscallExecutor::new(thread.clone(), syscall_number, future);
         thread.syscall_enter(executor.clone());
 
         match executor.run_once() {
             task::Poll::Ready(result) => {
                 // Syscall completed synchronously
-                trace!("Syscall ret={result:?}");
+                trace!("Syscall ret={:?}", result);
                 thread.syscall_exit(prepare_result(result));
             }
             task::Poll::Pending => {
@@ -154,6 +155,8 @@ pub fn prepare_result(result: Result<(), Error>) -> usize {
 
 pub struct SyscallExecutor {
     thread: Weak<Thread>,
+    syscall_number: SyscallNumber,
+    begin_ticks: u64,
     future: RefCell<Pin<Box<dyn Future<Output = Result<(), Error>>>>>,
 }
 
@@ -161,6 +164,7 @@ impl fmt::Debug for SyscallExecutor {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         f.debug_struct("SyscallExecutor")
             .field("thread", &self.thread)
+            .field("syscall_number", &self.syscall_number)
             .field("future", &"<future>")
             .finish()
     }
@@ -172,10 +176,13 @@ unsafe impl Send for SyscallExecutor {}
 impl SyscallExecutor {
     pub fn new<TFuture: Future<Output = Result<(), Error>> + 'static>(
         thread: Arc<Thread>,
+        syscall_number: SyscallNumber,
         future: TFuture,
     ) -> Arc<Self> {
         Arc::new(Self {
             thread: Arc::downgrade(&thread),
+            syscall_number,
+            begin_ticks: unsafe { _rdtsc() },
             future: RefCell::new(Box::pin(future)),
         })
     }
@@ -187,7 +194,13 @@ impl SyscallExecutor {
         let future = borrowed.as_mut();
 
         match future.poll(&mut ctx) {
-            task::Poll::Ready(result) => task::Poll::Ready(result),
+            task::Poll::Ready(result) => {
+                // Latency includes the time spent waiting, for blocking syscalls
+                let ticks = unsafe { _rdtsc() } - self.begin_ticks;
+                stats::record(self.syscall_number, ticks);
+
+                task::Poll::Ready(result)
+            }
             task::Poll::Pending => {
                 let thread = self
                     .thread
@@ -227,7 +240,8 @@ struct SleepFuture {
 
 enum SleepFutureState {
     Created(Vec<Arc<WaitQueue>>),
-    Sleeping(Arc<WaitResult>),
+    /// Result, and time stamp counter value when the thread started to sleep
+    Sleeping(Arc<WaitResult>, u64),
     Terminated,
 }
 
@@ -257,12 +271,14 @@ impl Future for SleepFuture {
                 let wait_context = WaitCtx::new(self_future.thread.clone(), wait_result.clone());
                 thread_sleep(&thread, wait_context, &wait_queues);
 
-                self_future.state = SleepFutureState::Sleeping(wait_result);
+                self_future.state = SleepFutureState::Sleeping(wait_result, unsafe { _rdtsc() });
 
                 task::Poll::Pending
             }
-            SleepFutureState::Sleeping(wait_result) => {
+            SleepFutureState::Sleeping(wait_result, begin_ticks) => {
                 let result = wait_result.take();
+                // Tune the spin budget of the queue (see `thread::wait_queue_spin`)
+                result.record_wait(unsafe { _rdtsc() } - begin_ticks);
 
                 self_future.state = SleepFutureState::Terminated;
 
@@ -320,7 +336,7 @@ impl thread::WaitingContext for WaitCtx {
         match executor.run_once() {
             task::Poll::Ready(result) => {
                 // Syscall terminated, set result
-                trace!("Syscall ret={result:?}");
+                trace!("Syscall ret={:?}", result);
                 thread.syscall_exit(prepare_result(result));
             }
             task::Poll::Pending => {
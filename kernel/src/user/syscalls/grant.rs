@@ -0,0 +1,75 @@
+use crate::{
+    memory::{Permissions, VirtAddr},
+    user::{grant::Grant, Error},
+};
+
+use super::{context::Context, helpers::HandleOutputWriter};
+
+pub async fn create(context: Context) -> Result<(), Error> {
+    let addr = context.arg1();
+    let size = context.arg2();
+    let perms = context.arg3();
+    let handle_out_ptr = context.arg4();
+
+    let thread = context.owner();
+    let process = thread.process();
+
+    let mut handle_out = HandleOutputWriter::new(&context, handle_out_ptr)?;
+
+    let grant = Grant::new(
+        process,
+        VirtAddr::new(addr as u64),
+        size,
+        Permissions::from_bits_retain(perms as u64),
+    )?;
+
+    let handle = process.handles().open_grant(grant)?;
+
+    handle_out.set(handle);
+    Ok(())
+}
+
+pub async fn map(context: Context) -> Result<(), Error> {
+    let process_handle = context.arg1();
+    let grant_handle = context.arg2();
+    let addr_ptr = context.arg3();
+    let perms = context.arg4();
+    let size_ptr = context.arg5();
+
+    let thread = context.owner();
+    let process = thread.process();
+
+    let target_process = process.handles().get_process(process_handle.into())?;
+    let grant = process.handles().get_grant(grant_handle.into())?;
+
+    let mut addr_access = process.vm_access_typed::<VirtAddr>(
+        VirtAddr::new(addr_ptr as u64),
+        Permissions::READ | Permissions::WRITE,
+    )?;
+
+    let mut size_access = process.vm_access_typed::<usize>(
+        VirtAddr::new(size_ptr as u64),
+        Permissions::READ | Permissions::WRITE,
+    )?;
+
+    let addr = grant.map(
+        &target_process,
+        *addr_access.get(),
+        Permissions::from_bits_retain(perms as u64),
+    )?;
+
+    *addr_access.get_mut() = addr;
+    *size_access.get_mut() = grant.size();
+    Ok(())
+}
+
+pub async fn revoke(context: Context) -> Result<(), Error> {
+    let grant_handle = context.arg1();
+
+    let thread = context.owner();
+    let process = thread.process();
+
+    let grant = process.handles().get_grant(grant_handle.into())?;
+
+    grant.revoke(process)
+}
@@ -1,17 +1,17 @@
 use core::{cmp::min, marker::PhantomData, mem::size_of};
 
-use syscalls::{Error, Permissions};
+use syscalls::{Error, Permissions, MAPPING_BUDGET_SIZE};
 
 use crate::{
     memory::VirtAddr,
     user::{
-        error::check_arg_res,
+        error::{check_arg_res, partial},
         handle::Handle,
         process::{MemoryAccess, TypedMemoryAccess},
     },
 };
 
-use alloc::str;
+use alloc::{str, vec::Vec};
 
 use super::context::Context;
 
@@ -104,3 +104,33 @@ impl StringReader {
         check_arg_res(str::from_utf8(self.access.get_slice::<u8>()))
     }
 }
+
+/// Get the ids from `start` (included), ordered, to list them by page
+///
+/// `ListOutputWriter::fill` copies the ones that fit in the output array and reports how many there are,
+/// so that userland knows if there is a next page.
+pub fn ids_from(mut ids: Vec<u64>, start: u64) -> Vec<u64> {
+    ids.retain(|id| *id >= start);
+    ids.sort_unstable();
+    ids
+}
+
+/// Clip the size of a range to the mapping budget
+///
+/// Returns the clipped size, and true if the range has been clipped.
+pub fn clip_to_budget(size: usize) -> (usize, bool) {
+    if size > MAPPING_BUDGET_SIZE {
+        (MAPPING_BUDGET_SIZE, true)
+    } else {
+        (size, false)
+    }
+}
+
+/// Syscall result once the (possibly clipped) range has been processed
+pub fn budget_result(clipped: bool) -> Result<(), Error> {
+    if clipped {
+        Err(partial())
+    } else {
+        Ok(())
+    }
+}
@@ -3,17 +3,17 @@ use core::ops::Range;
 use core::slice;
 
 use crate::interrupts::SyscallArgs;
+use crate::logging::info;
 use crate::memory::{
     self, drop_initial_kernel_stack, drop_initial_ramdisk, is_page_aligned, page_aligned_up,
     Permissions, PAGE_SIZE,
 };
-use crate::user;
 use crate::user::process;
 use crate::user::syscalls::engine::unregister_syscall;
+use crate::user::{self, measurement};
 use crate::{memory::VirtAddr, user::MemoryObject};
 use alloc::sync::Arc;
-use log::info;
-use syscalls::{SyscallNumber, ThreadPriority};
+use syscalls::{ramdisk::RamdiskTrailer, SyscallNumber, ThreadPriority};
 
 const BASE_ADDRESS: VirtAddr = VirtAddr::new_truncate(0x200000);
 const SIZE_OF_HEADERS: usize = PAGE_SIZE;
@@ -27,17 +27,64 @@ pub fn setup(context: SyscallArgs) {
     // Drop initial kernel stack (not used anymore, we are on regular interrupt stack)
     drop_initial_kernel_stack();
 
+    let binary = check_ramdisk(&ramdisk);
+
+    // First entry of the measurement log: the ramdisk contains everything loaded afterwards
+    let data = unsafe { slice::from_raw_parts(binary.start as *const u8, binary.len()) };
+    measurement::measure(0, "ramdisk", data).expect("Could not measure ramdisk");
+
     info!("Loading init binary");
-    let mobj = load_mem(&ramdisk);
+    let mobj = load_mem(&binary);
 
     // Drop it before we create the process
     drop_initial_ramdisk();
 
-    create_process(mobj, &ramdisk);
+    create_process(mobj, &binary);
 
     user::thread::initial_setup_thread();
 }
 
+/// Check the ramdisk integrity, and get the range of the init binary inside it
+fn check_ramdisk(ramdisk: &Range<usize>) -> Range<usize> {
+    let data = unsafe { slice::from_raw_parts(ramdisk.start as *const u8, ramdisk.len()) };
+
+    let trailer = RamdiskTrailer::from_ramdisk(data).unwrap_or_else(|| {
+        panic!(
+            "Ramdisk corrupted: too small to contain a trailer (size={})",
+            ramdisk.len()
+        )
+    });
+
+    if trailer.magic != RamdiskTrailer::MAGIC {
+        panic!(
+            "Ramdisk corrupted: bad trailer magic (expected={:#018X}, found={:#018X})",
+            RamdiskTrailer::MAGIC,
+            trailer.magic
+        );
+    }
+
+    let data_len = trailer.data_len as usize;
+    if data_len != ramdisk.len() - RamdiskTrailer::SIZE {
+        panic!(
+            "Ramdisk corrupted: bad data size (trailer={}, actual={})",
+            data_len,
+            ramdisk.len() - RamdiskTrailer::SIZE
+        );
+    }
+
+    let checksum = syscalls::ramdisk::checksum(&data[..data_len]);
+    if checksum != trailer.checksum {
+        panic!(
+            "Ramdisk corrupted: checksum mismatch (expected={:#018X}, computed={:#018X})",
+            trailer.checksum, checksum
+        );
+    }
+
+    info!("Ramdisk checksum OK ({:#018X})", checksum);
+
+    ramdisk.start..(ramdisk.start + data_len)
+}
+
 fn load_mem(ramdisk: &Range<usize>) -> Arc<MemoryObject> {
     // Load init binary memory contained in ramdisk
     let mem_size = page_aligned_up(ramdisk.len());
@@ -61,13 +108,14 @@ fn load_mem(ramdisk: &Range<usize>) -> Arc<MemoryObject> {
 }
 
 fn create_process(mobj: Arc<MemoryObject>, ramdisk: &Range<usize>) {
-    let process = process::create("init").expect("Failed to create init process");
+    let process = process::create("init", None).expect("Failed to create init process");
 
     process
         .mmap(
             BASE_ADDRESS,
             mobj.size(),
             Permissions::READ | Permissions::WRITE | Permissions::EXECUTE,
+            Permissions::READ | Permissions::WRITE | Permissions::EXECUTE,
             Some(mobj),
             0,
         )
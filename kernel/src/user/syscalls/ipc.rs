@@ -3,20 +3,24 @@ use core::cmp::min;
 use alloc::vec::Vec;
 use bit_field::BitArray;
 use hashbrown::HashMap;
-use syscalls::{Message, PortInfo, ProcessInfo};
+use syscalls::{AuditEventType, Message, MessageHeader, PortFilterRange, PortInfo, ProcessInfo};
 
 use crate::{
     memory::{align_up, Permissions, VirtAddr},
     user::{
+        audit,
         error::{check_arg, check_found},
         handle::Handle,
-        ipc, Error,
+        ipc,
+        process::QuotaKind,
+        thread::wait_queue_spin,
+        Error,
     },
 };
 
 use super::{
     context::Context,
-    helpers::{HandleOutputWriter, ListOutputWriter, StringReader},
+    helpers::{ids_from, HandleOutputWriter, ListOutputWriter, StringReader},
 };
 
 // set one of id or name
@@ -43,7 +47,7 @@ pub async fn open(context: Context) -> Result<(), Error> {
         ipc::find_by_name(name)
     })?;
 
-    let handle = process.handles().open_port_sender(target_port);
+    let handle = process.handles().open_port_sender(target_port)?;
 
     handle_out.set(handle);
     Ok(())
@@ -54,6 +58,8 @@ pub async fn create(context: Context) -> Result<(), Error> {
     let name_len = context.arg2();
     let handle_receiver_out_ptr = context.arg3();
     let handle_sender_out_ptr = context.arg4();
+    let data_capacity = context.arg5();
+    let broadcast = context.arg6() != 0;
 
     let thread = context.owner();
     let process = thread.process();
@@ -65,10 +71,32 @@ pub async fn create(context: Context) -> Result<(), Error> {
 
     let name = if name.len() > 0 { Some(name) } else { None };
 
-    let (receiver, sender) = ipc::create(name)?;
+    // 0 means the full message size
+    let data_capacity = if data_capacity == 0 {
+        Message::DATA_SIZE
+    } else {
+        data_capacity
+    };
 
-    let receiver_handle = process.handles().open_port_receiver(receiver);
-    let sender_handle = process.handles().open_port_sender(sender);
+    let charge = process.charge_object(QuotaKind::Port)?;
+    let (receiver, sender) = ipc::create(process.id(), name, data_capacity, broadcast, charge)?;
+
+    if name.is_some() {
+        audit::record(&thread, AuditEventType::PortRegister, receiver.id(), name);
+    }
+
+    let receiver_handle = process.handles().open_port_receiver(receiver)?;
+    let sender_handle = match process.handles().open_port_sender(sender) {
+        Ok(handle) => handle,
+        Err(err) => {
+            // Do not leave a half-opened port in the process
+            process
+                .handles()
+                .close(receiver_handle)
+                .expect("Could not close handle");
+            return Err(err);
+        }
+    };
 
     handle_receiver_out.set(receiver_handle);
     handle_sender_out.set(sender_handle);
@@ -87,7 +115,19 @@ pub async fn send(context: Context) -> Result<(), Error> {
     let user_message =
         process.vm_access_typed::<Message>(VirtAddr::new(message_ptr as u64), Permissions::READ)?;
 
-    let message = user_message.get().clone();
+    let mut message = user_message.get().clone();
+
+    // Propagate the request being handled, or start a new one
+    if message.correlation == 0 {
+        message.correlation = match thread.correlation() {
+            0 => thread.new_correlation(),
+            correlation => correlation,
+        };
+    }
+
+    if message.deadline == 0 {
+        message.deadline = thread.deadline();
+    }
 
     target_port_sender.send(process, message)
 }
@@ -108,11 +148,103 @@ pub async fn receive(context: Context) -> Result<(), Error> {
 
     let message = target_port_receiver.receive(process)?;
 
+    // Kernel messages (eg: listeners) do not belong to any request
+    if message.correlation != 0 {
+        thread.set_correlation(message.correlation);
+        thread.set_deadline(message.deadline);
+    }
+
+    *user_message.get_mut() = message;
+
+    Ok(())
+}
+
+/// Receive a message from a port, waiting for one if there is none
+///
+/// While the thread waits, a sender of the same process hands off its message directly to it, bypassing the queue.
+pub async fn blocking_receive(context: Context) -> Result<(), Error> {
+    let port_handle = context.arg1();
+    let message_ptr = context.arg2();
+
+    let thread = context.owner();
+    let process = thread.process();
+
+    let target_port_receiver = process.handles().get_port_receiver(port_handle.into())?;
+
+    let mut user_message = process.vm_access_typed::<Message>(
+        VirtAddr::new(message_ptr as u64),
+        Permissions::READ | Permissions::WRITE,
+    )?;
+
+    let message = loop {
+        match target_port_receiver.receive(process) {
+            Err(Error::ObjectNotReady) => {}
+            result => break result?,
+        }
+
+        if let Some(queue) = target_port_receiver.prepare_wait() {
+            let ready = || target_port_receiver.prepare_wait().is_none();
+            if wait_queue_spin(&[queue.clone()], ready) {
+                continue;
+            }
+        }
+
+        let Some((queue, handoff)) = target_port_receiver.prepare_receive(&thread) else {
+            // A message has been queued meanwhile
+            continue;
+        };
+
+        super::sleep(&context, Vec::from([queue.clone()])).await;
+        target_port_receiver.finish_receive(&handoff);
+
+        if let Some(message) = handoff.take(process) {
+            break message;
+        }
+
+        // Woken by a queued message: it may have been taken by another receiver, try again
+    };
+
+    // Kernel messages (eg: listeners) do not belong to any request
+    if message.correlation != 0 {
+        thread.set_correlation(message.correlation);
+        thread.set_deadline(message.deadline);
+    }
+
     *user_message.get_mut() = message;
 
     Ok(())
 }
 
+pub async fn peek(context: Context) -> Result<(), Error> {
+    let port_handle = context.arg1();
+    let header_ptr = context.arg2();
+
+    let thread = context.owner();
+    let process = thread.process();
+
+    let target_port_receiver = process.handles().get_port_receiver(port_handle.into())?;
+
+    let mut user_header = process.vm_access_typed::<MessageHeader>(
+        VirtAddr::new(header_ptr as u64),
+        Permissions::READ | Permissions::WRITE,
+    )?;
+
+    *user_header.get_mut() = target_port_receiver.peek()?;
+
+    Ok(())
+}
+
+pub async fn discard(context: Context) -> Result<(), Error> {
+    let port_handle = context.arg1();
+
+    let thread = context.owner();
+    let process = thread.process();
+
+    let target_port_receiver = process.handles().get_port_receiver(port_handle.into())?;
+
+    target_port_receiver.discard()
+}
+
 pub async fn wait(context: Context) -> Result<(), Error> {
     let port_handle_array_ptr = context.arg1();
     let ready_bit_array_ptr = context.arg2();
@@ -136,10 +268,12 @@ pub async fn wait(context: Context) -> Result<(), Error> {
     let ready_bits = ready_bit_array_access.get_mut();
     ready_bits.fill(0);
 
+    let mut ports = Vec::new();
     let mut queues = Vec::new();
     let mut queue_map = HashMap::new();
     let mut is_sync = false;
 
+    ports.reserve(port_count);
     queues.reserve(port_count);
 
     for (index, &handle) in port_handle_array_access.get().iter().enumerate() {
@@ -151,12 +285,23 @@ pub async fn wait(context: Context) -> Result<(), Error> {
             ready_bits.set_bit(index, true);
             is_sync = true;
         }
+
+        ports.push(port);
     }
 
     if is_sync {
         return Ok(());
     }
 
+    let ready = || ports.iter().any(|port| port.prepare_wait().is_none());
+    if wait_queue_spin(&queues, ready) {
+        for (index, port) in ports.iter().enumerate() {
+            ready_bits.set_bit(index, port.prepare_wait().is_none());
+        }
+
+        return Ok(());
+    }
+
     let woken_queue = super::sleep(&context, queues).await;
     let index = *queue_map
         .get(&(woken_queue.as_ref() as *const _))
@@ -187,8 +332,14 @@ pub async fn info(context: Context) -> Result<(), Error> {
         id: target_port.id(),
         name: [0; PortInfo::NAME_LEN],
         closed: target_port.closed(),
+        owner_pid: target_port.owner_pid(),
+        broadcast: target_port.broadcast(),
+        subscriber_count: target_port.subscriber_count(),
+        broadcast_dropped: target_port.broadcast_dropped(),
+        data_capacity: target_port.data_capacity(),
         message_queue_count: target_port.message_queue_count(),
         waiting_receiver_count: target_port.waiting_receiver_count(),
+        name_id: target_port.name_id(),
     };
 
     let src_name = target_port.name().unwrap_or("").as_bytes();
@@ -198,19 +349,105 @@ pub async fn info(context: Context) -> Result<(), Error> {
     Ok(())
 }
 
+/// Note: the subscriber is a receiver handle, so that only the process receiving from a port can subscribe it
+pub async fn subscribe(context: Context) -> Result<(), Error> {
+    let port_handle = context.arg1();
+    let subscriber_handle = context.arg2();
+    let max_queued = context.arg3();
+
+    let thread = context.owner();
+    let process = thread.process();
+
+    let target_port_sender = process.handles().get_port_sender(port_handle.into())?;
+    let subscriber_port_receiver = process
+        .handles()
+        .get_port_receiver(subscriber_handle.into())?;
+
+    target_port_sender
+        .port()
+        .subscribe(subscriber_port_receiver.port(), max_queued)
+}
+
+pub async fn unsubscribe(context: Context) -> Result<(), Error> {
+    let port_handle = context.arg1();
+    let subscriber_handle = context.arg2();
+
+    let thread = context.owner();
+    let process = thread.process();
+
+    let target_port_sender = process.handles().get_port_sender(port_handle.into())?;
+    let subscriber_port_receiver = process
+        .handles()
+        .get_port_receiver(subscriber_handle.into())?;
+
+    target_port_sender
+        .port()
+        .unsubscribe(subscriber_port_receiver.port())
+}
+
 /// count_ptr:
 /// - on input -> element count in array
 /// - on output -> real number of ports. Can be smaller or larger than array. If larger, the array is truncated
 pub async fn list(context: Context) -> Result<(), Error> {
-    let array_ptr = context.arg1();
-    let count_ptr = context.arg2();
+    let start = context.arg1() as u64;
+    let array_ptr = context.arg2();
+    let count_ptr = context.arg3();
 
     //let thread = context.owner();
     //let process = thread.process();
 
     let mut writer = ListOutputWriter::<u64>::new(&context, array_ptr, count_ptr)?;
 
-    writer.fill(&ipc::list());
+    writer.fill(&ids_from(ipc::list(), start));
+
+    Ok(())
+}
+
+pub async fn set_filter(context: Context) -> Result<(), Error> {
+    let port_handle = context.arg1();
+    let ranges_ptr = context.arg2();
+    let ranges_count = context.arg3();
+    let alternate_handle = context.arg4();
+
+    let thread = context.owner();
+    let process = thread.process();
+
+    let target_port_receiver = process.handles().get_port_receiver(port_handle.into())?;
+
+    // No range: clear the filter
+    if ranges_count == 0 {
+        target_port_receiver.port().set_filter(None);
+        return Ok(());
+    }
+
+    check_arg(ranges_count <= PortFilterRange::MAX_COUNT)?;
+
+    // The alternate port is given by its receiver, and must belong to the same process:
+    // a filter cannot divert messages to a port of another process
+    let alternate_port_receiver = process
+        .handles()
+        .get_port_receiver(alternate_handle.into())?;
+    check_arg(alternate_port_receiver.id() != target_port_receiver.id())?;
+    check_arg(alternate_port_receiver.port().owner_pid() == process.id())?;
+
+    let ranges_access = process.vm_access_typed_slice::<PortFilterRange>(
+        VirtAddr::new(ranges_ptr as u64),
+        ranges_count,
+        Permissions::READ,
+    )?;
+
+    let mut ranges = Vec::with_capacity(ranges_count);
+    for range in ranges_access.get() {
+        check_arg(range.start < range.end)?;
+        ranges.push(range.start..range.end);
+    }
+
+    target_port_receiver
+        .port()
+        .set_filter(Some(ipc::PortFilter::new(
+            ranges,
+            alternate_port_receiver.port(),
+        )));
 
     Ok(())
 }
@@ -2,10 +2,16 @@ use syscalls::{Error, Permissions};
 
 use crate::{
     memory::VirtAddr,
-    user::listener::{ProcessListener, ThreadListener},
+    user::{
+        listener::{PortListener, ProcessListener, ThreadListener},
+        process::QuotaKind,
+    },
 };
 
-use super::{context::Context, helpers::HandleOutputWriter};
+use super::{
+    context::Context,
+    helpers::{HandleOutputWriter, StringReader},
+};
 
 pub async fn create_process(context: Context) -> Result<(), Error> {
     let port_handle = context.arg1();
@@ -36,9 +42,10 @@ pub async fn create_process(context: Context) -> Result<(), Error> {
         None
     };
 
-    let process_listener = ProcessListener::new(port, pids);
+    let charge = process.charge_object(QuotaKind::Listener)?;
+    let process_listener = ProcessListener::new(port, pids, charge);
 
-    let handle = process.handles().open_process_listener(process_listener);
+    let handle = process.handles().open_process_listener(process_listener)?;
 
     handle_out.set(handle);
     Ok(())
@@ -74,9 +81,36 @@ pub async fn create_thread(context: Context) -> Result<(), Error> {
         None
     };
 
-    let thread_listener = ThreadListener::new(port, ids, is_pids);
+    let charge = process.charge_object(QuotaKind::Listener)?;
+    let thread_listener = ThreadListener::new(port, ids, is_pids, charge);
+
+    let handle = process.handles().open_thread_listener(thread_listener)?;
+
+    handle_out.set(handle);
+    Ok(())
+}
+
+pub async fn create_port(context: Context) -> Result<(), Error> {
+    let port_handle = context.arg1();
+    let name_ptr = context.arg2();
+    let name_len = context.arg3();
+    let is_prefix = context.arg4() > 0;
+    let handle_out_ptr = context.arg5();
+
+    let thread = context.owner();
+    let process = thread.process();
+
+    let mut handle_out = HandleOutputWriter::new(&context, handle_out_ptr)?;
+
+    let port = process.handles().get_port_sender(port_handle.into())?;
+
+    let name_reader = StringReader::new(&context, name_ptr, name_len)?;
+    let name = name_reader.str()?;
+
+    let charge = process.charge_object(QuotaKind::Listener)?;
+    let port_listener = PortListener::new(port, name, is_prefix, charge);
 
-    let handle = process.handles().open_thread_listener(thread_listener);
+    let handle = process.handles().open_port_listener(port_listener)?;
 
     handle_out.set(handle);
     Ok(())
@@ -1,10 +1,17 @@
-use alloc::{format, string::String};
+use alloc::{format, string::String, sync::Arc};
 use log::Level;
+use syscalls::{LogSink, Permissions};
 
-use crate::user::{
-    error::invalid_argument,
-    syscalls::{context::Context, helpers::StringReader},
-    Error,
+use crate::{
+    logging::{self, log_at, warn},
+    memory::VirtAddr,
+    user::{
+        error::invalid_argument,
+        process::Process,
+        syscalls::{context::Context, helpers::StringReader, process::check_inspect},
+        thread::{self, Thread},
+        Error,
+    },
 };
 
 pub async fn log(context: Context) -> Result<(), Error> {
@@ -17,27 +24,176 @@ pub async fn log(context: Context) -> Result<(), Error> {
 
     let message_reader = StringReader::new(&context, message_ptr, message_len)?;
 
-    let pid = process.id();
-    let tid = thread.id();
-    let pname = process.name();
-    let tname = thread.name();
     let level = parse_level(level)?;
     let message = message_reader.str()?;
 
+    submit(process, &thread, thread.correlation(), level, message);
+
+    Ok(())
+}
+
+/// Log a record on behalf of a thread of another process (eg: log-server draining the buffered records of its clients)
+///
+/// The caller must be able to inspect the process. The record is attributed to it, and counts in its rate limit.
+/// The thread may have exited meanwhile: the record is kept, without the thread name.
+pub async fn relay(context: Context) -> Result<(), Error> {
+    let process_handle = context.arg1();
+    let tid = context.arg2() as u64;
+    let level = context.arg3();
+    let message_ptr = context.arg4();
+    let message_len = context.arg5();
+
+    let thread = context.owner();
+    let process = thread.process();
+
+    let target_process = process.handles().get_process(process_handle.into())?;
+    check_inspect(&thread, &target_process)?;
+
+    let message_reader = StringReader::new(&context, message_ptr, message_len)?;
+
+    let level = parse_level(level)?;
+    let message = message_reader.str()?;
+
+    let target_thread = thread::find(tid)
+        .filter(|thread| Arc::ptr_eq(thread.process(), &target_process))
+        .ok_or(tid);
+
+    match &target_thread {
+        Ok(thread) => submit(&target_process, thread, 0, level, message),
+        Err(tid) => submit_anonymous(&target_process, *tid, level, message),
+    }
+
+    Ok(())
+}
+
+fn submit(process: &Process, thread: &Thread, correlation: u64, level: Level, message: &str) {
+    let tname = thread.name();
+
     let thread_suffix = if let Some(name) = &*tname {
         format!(" ({})", name)
     } else {
         String::new()
     };
 
-    log::log!(
+    log_record(
+        process,
+        thread.id(),
+        &thread_suffix,
+        correlation,
         level,
-        "(pid={pid} ({pname}), tid={tid}{thread_suffix}): {message}"
+        message,
     );
+}
+
+fn submit_anonymous(process: &Process, tid: u64, level: Level, message: &str) {
+    log_record(process, tid, "", 0, level, message);
+}
+
+fn log_record(
+    process: &Process,
+    tid: u64,
+    thread_suffix: &str,
+    correlation: u64,
+    level: Level,
+    message: &str,
+) {
+    let pid = process.id();
+    let pname = process.name();
+
+    let correlation_suffix = match correlation {
+        0 => String::new(),
+        correlation => format!(", cid={correlation}"),
+    };
+
+    let verdict = process.log_submit(level, message);
+
+    if verdict.repeated > 0 {
+        log_at!(
+            level,
+            "(pid={} ({})): last message repeated {} times",
+            pid,
+            pname,
+            verdict.repeated
+        );
+    }
+
+    if verdict.dropped > 0 {
+        warn!(
+            "(pid={} ({})): {} messages dropped by rate limit",
+            pid, pname, verdict.dropped
+        );
+    }
+
+    if verdict.accepted {
+        // Explicit args: interned in the binary log
+        log_at!(
+            level,
+            "(pid={} ({}), tid={}{}{}): {}",
+            pid,
+            pname,
+            tid,
+            thread_suffix,
+            correlation_suffix,
+            message
+        );
+    }
+}
+
+pub async fn read(context: Context) -> Result<(), Error> {
+    let cursor_ptr = context.arg1();
+    let buffer_ptr = context.arg2();
+    let len_ptr = context.arg3();
+
+    let thread = context.owner();
+    let process = thread.process();
+
+    // In/out: position in the ring
+    let mut cursor_access = process.vm_access_typed::<u64>(
+        VirtAddr::new(cursor_ptr as u64),
+        Permissions::READ | Permissions::WRITE,
+    )?;
+
+    // In: size of the buffer, out: number of bytes read
+    let mut len_access = process.vm_access_typed::<usize>(
+        VirtAddr::new(len_ptr as u64),
+        Permissions::READ | Permissions::WRITE,
+    )?;
+
+    let mut buffer_access = process.vm_access_typed_slice::<u8>(
+        VirtAddr::new(buffer_ptr as u64),
+        *len_access.get(),
+        Permissions::READ | Permissions::WRITE,
+    )?;
+
+    *len_access.get_mut() = logging::ring_read(cursor_access.get_mut(), buffer_access.get_mut());
+
+    Ok(())
+}
+
+pub async fn set_level(context: Context) -> Result<(), Error> {
+    let sink = context.arg1();
+    let level = context.arg2();
+
+    let sink = parse_sink(sink)?;
+    let level = logging::level_from_usize(level).ok_or_else(invalid_argument)?;
+
+    logging::set_level(sink, level);
 
     Ok(())
 }
 
+fn parse_sink(sink: usize) -> Result<LogSink, Error> {
+    const SERIAL_USIZE: usize = LogSink::Serial as usize;
+    const RING_USIZE: usize = LogSink::Ring as usize;
+    const CONSOLE_USIZE: usize = LogSink::Console as usize;
+    match sink {
+        SERIAL_USIZE => Ok(LogSink::Serial),
+        RING_USIZE => Ok(LogSink::Ring),
+        CONSOLE_USIZE => Ok(LogSink::Console),
+        _ => Err(invalid_argument()),
+    }
+}
+
 fn parse_level(level: usize) -> Result<Level, Error> {
     const ERROR_USIZE: usize = Level::Error as usize;
     const WARN_USIZE: usize = Level::Warn as usize;
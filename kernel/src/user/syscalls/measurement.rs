@@ -0,0 +1,59 @@
+use syscalls::{Measurement, Permissions};
+
+use crate::{
+    memory::VirtAddr,
+    user::{
+        error::check_arg,
+        measurement,
+        syscalls::{context::Context, helpers::StringReader},
+        Error,
+    },
+};
+
+pub async fn extend(context: Context) -> Result<(), Error> {
+    let name_ptr = context.arg1();
+    let name_len = context.arg2();
+    let data_ptr = context.arg3();
+    let data_len = context.arg4();
+
+    let thread = context.owner();
+    let process = thread.process();
+
+    let name_reader = StringReader::new(&context, name_ptr, name_len)?;
+    let name = name_reader.str()?;
+    check_arg(!name.is_empty())?;
+
+    // The kernel computes the digest itself from the data: callers cannot submit arbitrary digests
+    let data_access = process.vm_access_typed_slice::<u8>(
+        VirtAddr::new(data_ptr as u64),
+        data_len,
+        Permissions::READ,
+    )?;
+
+    measurement::measure(process.id(), name, data_access.get())
+}
+
+pub async fn read(context: Context) -> Result<(), Error> {
+    let start = context.arg1();
+    let array_ptr = context.arg2();
+    let count_ptr = context.arg3();
+
+    let thread = context.owner();
+    let process = thread.process();
+
+    // In: size of the array, out: number of entries read
+    let mut count_access = process.vm_access_typed::<usize>(
+        VirtAddr::new(count_ptr as u64),
+        Permissions::READ | Permissions::WRITE,
+    )?;
+
+    let mut array_access = process.vm_access_typed_slice::<Measurement>(
+        VirtAddr::new(array_ptr as u64),
+        *count_access.get(),
+        Permissions::READ | Permissions::WRITE,
+    )?;
+
+    *count_access.get_mut() = measurement::read(start, array_access.get_mut());
+
+    Ok(())
+}
@@ -1,8 +1,8 @@
-use syscalls::MemoryStats;
+use syscalls::{FrameAudit, MemoryStats};
 
 use crate::{
     memory::{self, Permissions, VirtAddr},
-    user::Error,
+    user::{frame_audit, Error},
 };
 
 use super::context::Context;
@@ -22,3 +22,24 @@ pub async fn stats(context: Context) -> Result<(), Error> {
 
     Ok(())
 }
+
+pub async fn audit_frames(context: Context) -> Result<(), Error> {
+    let audit_ptr = context.arg1();
+
+    let thread = context.owner();
+    let process = thread.process();
+
+    // Audit walks all processes: reserved to privileged threads
+    if !thread.privileged() {
+        return Err(Error::NotSupported);
+    }
+
+    let mut user_access = process.vm_access_typed::<FrameAudit>(
+        VirtAddr::new(audit_ptr as u64),
+        Permissions::READ | Permissions::WRITE,
+    )?;
+
+    *user_access.get_mut() = frame_audit::audit();
+
+    Ok(())
+}
@@ -1,20 +1,109 @@
-use crate::user::{Error, MemoryObject};
+use syscalls::{MemoryObjectFlags, Permissions};
+
+use crate::{
+    devices::inventory,
+    memory::{is_page_aligned, VirtAddr},
+    user::{
+        error::{check_arg, check_arg_opt, not_supported},
+        listener::MemoryObjectReleaseListener,
+        Error, MemoryObject,
+    },
+};
 
 use super::{context::Context, helpers::HandleOutputWriter};
 
 pub async fn create(context: Context) -> Result<(), Error> {
     let size = context.arg1();
-    let handle_out_ptr = context.arg2();
+    let flags = context.arg2();
+    let handle_out_ptr = context.arg3();
 
     let thread = context.owner();
     let process = thread.process();
 
     let mut handle_out = HandleOutputWriter::new(&context, handle_out_ptr)?;
 
-    let memory_object = MemoryObject::new(size)?;
+    let flags = check_arg_opt(MemoryObjectFlags::from_bits(flags as u64))?;
+
+    let memory_object = if flags.contains(MemoryObjectFlags::UNINITIALIZED) {
+        // The content may be data of other processes: reserved to privileged threads
+        if !thread.privileged() {
+            return Err(not_supported());
+        }
+
+        MemoryObject::new_uninitialized(size)?
+    } else {
+        MemoryObject::new(size)?
+    };
 
-    let handle = process.handles().open_memory_object(memory_object);
+    let handle = process.handles().open_memory_object(memory_object)?;
 
     handle_out.set(handle);
     Ok(())
 }
+
+pub async fn notify_release(context: Context) -> Result<(), Error> {
+    let memory_object_handle = context.arg1();
+    let port_handle = context.arg2();
+    let cookie = context.arg3();
+
+    let thread = context.owner();
+    let process = thread.process();
+
+    let memory_object = process
+        .handles()
+        .get_memory_object(memory_object_handle.into())?;
+    let port = process.handles().get_port_sender(port_handle.into())?;
+
+    memory_object.add_release_listener(MemoryObjectReleaseListener::new(port, cookie as u64));
+
+    Ok(())
+}
+
+pub async fn restrict(context: Context) -> Result<(), Error> {
+    let memory_object_handle = context.arg1();
+    let perms = context.arg2();
+    let handle_out_ptr = context.arg3();
+
+    let thread = context.owner();
+    let process = thread.process();
+
+    let mut handle_out = HandleOutputWriter::new(&context, handle_out_ptr)?;
+
+    let perms = check_arg_opt(Permissions::from_bits(perms as u64))?;
+
+    let handle = process
+        .handles()
+        .open_restricted_memory_object(memory_object_handle.into(), perms)?;
+
+    handle_out.set(handle);
+    Ok(())
+}
+
+/// Get the physical address of a page of the memory object, to program DMA transfers
+///
+/// Reserved to privileged threads and to processes which drive a device (see `device::claim`):
+/// a device can access the whole memory, so the driver is trusted anyway.
+pub async fn physical_address(context: Context) -> Result<(), Error> {
+    let memory_object_handle = context.arg1();
+    let offset = context.arg2();
+    let address_out_ptr = context.arg3();
+
+    let thread = context.owner();
+    let process = thread.process();
+
+    if !thread.privileged() && !inventory::drives_device(process.id()) {
+        return Err(not_supported());
+    }
+
+    let memory_object = process
+        .handles()
+        .get_memory_object(memory_object_handle.into())?;
+
+    check_arg(is_page_aligned(offset) && offset < memory_object.size())?;
+
+    let mut address_access = process
+        .vm_access_typed::<u64>(VirtAddr::new(address_out_ptr as u64), Permissions::WRITE)?;
+
+    *address_access.get_mut() = memory_object.frame(offset).frame().as_u64();
+    Ok(())
+}
@@ -1,15 +1,24 @@
+mod audit;
 mod context;
+mod device;
 mod engine;
+mod grant;
 mod handle;
 mod helpers;
 mod init;
 mod ipc;
 mod listener;
 mod logging;
+mod measurement;
 mod memory;
 mod memory_object;
+mod power;
 mod process;
+mod stats;
+mod strings;
 mod thread;
+mod timer;
+mod trampoline;
 
 pub use self::context::Context;
 use self::engine::{register_syscall, register_syscall_raw};
@@ -20,6 +29,8 @@ use syscalls::SyscallNumber;
 
 pub fn init() {
     register_syscall(SyscallNumber::Log, logging::log);
+    register_syscall(SyscallNumber::LogRead, logging::read);
+    register_syscall(SyscallNumber::LogSetLevel, logging::set_level);
 
     register_syscall(SyscallNumber::HandleClose, handle::close);
     register_syscall(SyscallNumber::HandleDuplicate, handle::duplicate);
@@ -31,14 +42,34 @@ pub fn init() {
     register_syscall(SyscallNumber::ProcessMMap, process::mmap);
     register_syscall(SyscallNumber::ProcessMUnmap, process::munmap);
     register_syscall(SyscallNumber::ProcessMProtect, process::mprotect);
+    register_syscall(SyscallNumber::ProcessMName, process::mname);
+    register_syscall(SyscallNumber::ProcessMappings, process::mappings);
     register_syscall(SyscallNumber::ProcessExit, process::exit);
     register_syscall(SyscallNumber::ProcessKill, process::kill);
+    register_syscall(SyscallNumber::ProcessSuspend, process::suspend);
+    register_syscall(SyscallNumber::ProcessResume, process::resume);
+    register_syscall(
+        SyscallNumber::ProcessSetObjectLimits,
+        process::set_object_limits,
+    );
+    register_syscall(SyscallNumber::ProcessObjectUsage, process::object_usage);
+    register_syscall(SyscallNumber::ProcessGrantIoPorts, process::grant_io_ports);
+    register_syscall(SyscallNumber::ProcessSetSandbox, process::set_sandbox);
+    register_syscall(SyscallNumber::ProcessMemoryRead, process::memory_read);
+    register_syscall(SyscallNumber::ProcessMemoryWrite, process::memory_write);
+    register_syscall(SyscallNumber::ProcessHandles, process::handles);
+    register_syscall(SyscallNumber::ProcessInstallHandle, process::install_handle);
+    register_syscall(SyscallNumber::LogRelay, logging::relay);
     register_syscall(SyscallNumber::ProcessInfo, process::info);
     register_syscall(SyscallNumber::ProcessList, process::list);
     register_syscall(SyscallNumber::ProcessSetName, process::set_name);
     register_syscall(SyscallNumber::ProcessGetName, process::get_name);
+    register_syscall(SyscallNumber::ProcessNames, process::names);
+    register_syscall(SyscallNumber::ProcessThreads, process::threads);
+    register_syscall(SyscallNumber::ProcessPorts, process::ports);
 
     register_syscall(SyscallNumber::ThreadOpenSelf, thread::open_self);
+    register_syscall(SyscallNumber::ThreadGetIds, thread::get_ids);
     register_syscall(SyscallNumber::ThreadOpen, thread::open);
     register_syscall(SyscallNumber::ThreadCreate, thread::create);
     register_syscall(SyscallNumber::ThreadExit, thread::exit);
@@ -47,28 +78,104 @@ pub fn init() {
     register_syscall(SyscallNumber::ThreadList, thread::list);
     register_syscall(SyscallNumber::ThreadSetName, thread::set_name);
     register_syscall(SyscallNumber::ThreadGetName, thread::get_name);
+    register_syscall(SyscallNumber::ThreadNames, thread::names);
     register_syscall(SyscallNumber::ThreadErrorInfo, thread::error_info);
     register_syscall(SyscallNumber::ThreadContext, thread::context);
     register_syscall(SyscallNumber::ThreadUpdateContext, thread::update_context);
     register_syscall(SyscallNumber::ThreadResume, thread::resume);
+    register_syscall(SyscallNumber::ThreadSetWatchpoint, thread::set_watchpoint);
+    register_syscall(
+        SyscallNumber::ThreadClearWatchpoint,
+        thread::clear_watchpoint,
+    );
+    register_syscall(
+        SyscallNumber::ThreadSchedTraceEnable,
+        thread::sched_trace_enable,
+    );
+    register_syscall(
+        SyscallNumber::ThreadSchedTraceRead,
+        thread::sched_trace_read,
+    );
 
     register_syscall(SyscallNumber::MemoryObjectCreate, memory_object::create);
+    register_syscall(
+        SyscallNumber::MemoryObjectNotifyRelease,
+        memory_object::notify_release,
+    );
+    register_syscall(SyscallNumber::MemoryObjectRestrict, memory_object::restrict);
+    register_syscall(
+        SyscallNumber::MemoryObjectPhysicalAddress,
+        memory_object::physical_address,
+    );
+
+    register_syscall(SyscallNumber::StringLookup, strings::lookup);
 
     register_syscall(SyscallNumber::PortOpen, ipc::open);
     register_syscall(SyscallNumber::PortCreate, ipc::create);
     register_syscall(SyscallNumber::PortSend, ipc::send);
     register_syscall(SyscallNumber::PortReceive, ipc::receive);
+    register_syscall(SyscallNumber::PortBlockingReceive, ipc::blocking_receive);
     register_syscall(SyscallNumber::PortWait, ipc::wait);
     register_syscall(SyscallNumber::PortInfo, ipc::info);
     register_syscall(SyscallNumber::PortList, ipc::list);
+    register_syscall(SyscallNumber::PortSetFilter, ipc::set_filter);
+    register_syscall(SyscallNumber::PortPeek, ipc::peek);
+    register_syscall(SyscallNumber::PortDiscard, ipc::discard);
+    register_syscall(SyscallNumber::PortSubscribe, ipc::subscribe);
+    register_syscall(SyscallNumber::PortUnsubscribe, ipc::unsubscribe);
 
     register_syscall(
         SyscallNumber::ListenerCreateProcess,
         listener::create_process,
     );
     register_syscall(SyscallNumber::ListenerCreateThread, listener::create_thread);
+    register_syscall(SyscallNumber::ListenerCreatePort, listener::create_port);
+
+    register_syscall(SyscallNumber::TimerCreate, timer::create);
+    register_syscall(SyscallNumber::TimerArm, timer::arm);
+    register_syscall(SyscallNumber::TimerCancel, timer::cancel);
+    register_syscall(SyscallNumber::TimerStats, timer::stats);
+    register_syscall(SyscallNumber::ClockSetWall, timer::set_wall_clock);
+    register_syscall(SyscallNumber::ClockAdvance, timer::advance);
 
     register_syscall(SyscallNumber::MemoryStats, memory::stats);
+    register_syscall(SyscallNumber::MemoryAuditFrames, memory::audit_frames);
+
+    register_syscall(SyscallNumber::SyscallStats, stats::syscalls);
+    register_syscall(SyscallNumber::SystemInfo, stats::system_info);
+    register_syscall(SyscallNumber::IdleStats, stats::idle);
+    register_syscall(SyscallNumber::ReadyLatencyStats, stats::ready_latencies);
+
+    register_syscall(SyscallNumber::DeviceList, device::list);
+    register_syscall(SyscallNumber::DeviceClaim, device::claim);
+
+    register_syscall(SyscallNumber::SystemSuspend, power::suspend);
+    register_syscall(SyscallNumber::SystemPowerOff, power::power_off);
+    register_syscall(SyscallNumber::KernelReload, power::reload);
+
+    register_syscall(SyscallNumber::GrantCreate, grant::create);
+    register_syscall(SyscallNumber::GrantMap, grant::map);
+    register_syscall(SyscallNumber::GrantRevoke, grant::revoke);
+
+    register_syscall(SyscallNumber::TrampolineCreate, trampoline::create);
+    register_syscall(SyscallNumber::TrampolineSpawn, trampoline::spawn);
+
+    register_syscall(SyscallNumber::ProcessQueryRange, process::query_range);
+
+    register_syscall(SyscallNumber::ThreadNewCorrelation, thread::new_correlation);
+    register_syscall(SyscallNumber::ThreadSetDeadline, thread::set_deadline);
+    register_syscall(SyscallNumber::ThreadCheckDeadline, thread::check_deadline);
+
+    register_syscall(SyscallNumber::ProcessMMapStack, process::mmap_stack);
+    register_syscall(SyscallNumber::ProcessMReserve, process::mreserve);
+    register_syscall(SyscallNumber::ProcessMCommit, process::mcommit);
+
+    register_syscall(SyscallNumber::AuditSetRules, audit::set_rules);
+    register_syscall(SyscallNumber::AuditRead, audit::read);
+    register_syscall(SyscallNumber::AuditSubmit, audit::submit);
+
+    register_syscall(SyscallNumber::MeasurementExtend, measurement::extend);
+    register_syscall(SyscallNumber::MeasurementRead, measurement::read);
 
     register_syscall_raw(SyscallNumber::InitSetup, init::setup);
 }
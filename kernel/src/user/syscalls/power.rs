@@ -0,0 +1,90 @@
+use core::mem;
+
+use syscalls::{Permissions, SleepMode};
+
+use crate::{
+    devices::power,
+    kexec,
+    logging::warn,
+    memory::VirtAddr,
+    user::{
+        error::{check_arg, check_positive, invalid_argument, not_supported, out_of_memory},
+        Error,
+    },
+};
+
+use super::context::Context;
+
+pub async fn suspend(context: Context) -> Result<(), Error> {
+    let mode = context.arg1();
+
+    // Stops the whole machine: reserved to privileged threads
+    if !context.owner().privileged() {
+        return Err(not_supported());
+    }
+
+    check_arg(mode == SleepMode::Test as usize || mode == SleepMode::S3 as usize)?;
+    let mode: SleepMode = unsafe { mem::transmute(mode as u64) };
+
+    if !power::supported(mode) {
+        return Err(not_supported());
+    }
+
+    // The kernel is not preemptible: no other thread runs until the machine is resumed
+    power::suspend(mode);
+
+    Ok(())
+}
+
+pub async fn power_off(context: Context) -> Result<(), Error> {
+    // Stops the whole machine: reserved to privileged threads
+    if !context.owner().privileged() {
+        return Err(not_supported());
+    }
+
+    if !power::power_off_supported() {
+        return Err(not_supported());
+    }
+
+    power::power_off();
+
+    // Could not power off
+    Err(not_supported())
+}
+
+/// Load a new kernel image with its ramdisk, and jump to it (kexec-lite, see `kexec`)
+pub async fn reload(context: Context) -> Result<(), Error> {
+    let kernel_ptr = context.arg1();
+    let kernel_len = context.arg2();
+    let ramdisk_ptr = context.arg3();
+    let ramdisk_len = context.arg4();
+
+    let thread = context.owner();
+    let process = thread.process();
+
+    // Replaces the whole system: reserved to privileged threads
+    if !thread.privileged() {
+        return Err(not_supported());
+    }
+
+    let kernel = process.vm_access_typed_slice::<u8>(
+        VirtAddr::new(kernel_ptr as u64),
+        check_positive(kernel_len)?,
+        Permissions::READ,
+    )?;
+    let ramdisk = process.vm_access_typed_slice::<u8>(
+        VirtAddr::new(ramdisk_ptr as u64),
+        check_positive(ramdisk_len)?,
+        Permissions::READ,
+    )?;
+
+    // Returns only if the new kernel cannot be loaded
+    match kexec::reload(kernel.get(), ramdisk.get()) {
+        Err(kexec::Error::InvalidImage(reason)) => {
+            warn!("kexec: cannot load the kernel image: {}", reason);
+            Err(invalid_argument())
+        }
+        Err(kexec::Error::NoMemory) => Err(out_of_memory()),
+        Err(kexec::Error::Unsupported) => Err(not_supported()),
+    }
+}
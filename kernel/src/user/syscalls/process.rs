@@ -1,20 +1,31 @@
-use core::cmp::min;
+use core::{cmp::min, ops::Range};
 
-use alloc::{format, sync::Arc};
-use syscalls::ProcessInfo;
+use alloc::{format, sync::Arc, vec::Vec};
+use syscalls::{
+    AuditEventType, HandleInfo, MappingInfo, NameEntry, ObjectCounts, ProcessInfo, SandboxFlags,
+    ThreadPriority, PROCESS_MEMORY_MAX_SIZE,
+};
 
+use crate::logging::debug;
 use crate::{
+    gdt,
     memory::{Permissions, VirtAddr},
     user::{
-        error::{check_arg, check_found},
+        audit,
+        error::{check_arg, check_arg_opt, check_found, check_is_userspace, not_supported},
         handle::Handle,
-        process, thread, Error,
+        ipc,
+        process::{self, Process},
+        thread::{self, Thread},
+        Error,
     },
 };
 
 use super::{
     context::Context,
-    helpers::{HandleOutputWriter, ListOutputWriter, StringReader},
+    helpers::{
+        budget_result, clip_to_budget, ids_from, HandleOutputWriter, ListOutputWriter, StringReader,
+    },
 };
 
 pub async fn open_self(context: Context) -> Result<(), Error> {
@@ -25,7 +36,7 @@ pub async fn open_self(context: Context) -> Result<(), Error> {
 
     let mut handle_out = HandleOutputWriter::new(&context, handle_out_ptr)?;
 
-    let handle = process.handles().open_process(process.clone());
+    let handle = process.handles().open_process(process.clone())?;
 
     handle_out.set(handle);
     Ok(())
@@ -41,7 +52,7 @@ pub async fn open(context: Context) -> Result<(), Error> {
     let mut handle_out = HandleOutputWriter::new(&context, handle_out_ptr)?;
 
     let target_process = check_found(process::find(pid as u64))?;
-    let handle = process.handles().open_process(target_process);
+    let handle = process.handles().open_process(target_process)?;
 
     handle_out.set(handle);
     Ok(())
@@ -60,9 +71,20 @@ pub async fn create(context: Context) -> Result<(), Error> {
     let name = name_reader.str()?;
     check_arg(name.len() > 0)?;
 
-    let new_process = process::create(name)?;
+    if !thread.privileged() && !process.sandbox().contains(SandboxFlags::PROCESS_CREATE) {
+        return Err(not_supported());
+    }
+
+    let new_process = process::create(name, Some(process.id()))?;
+
+    audit::record(
+        &thread,
+        AuditEventType::ProcessCreate,
+        new_process.id(),
+        Some(name),
+    );
 
-    let handle = process.handles().open_process(new_process);
+    let handle = process.handles().open_process(new_process)?;
 
     handle_out.set(handle);
     Ok(())
@@ -81,12 +103,15 @@ pub async fn mmap(context: Context) -> Result<(), Error> {
 
     let target_process = process.handles().get_process(process_handle.into())?;
 
-    let memory_object = {
+    let (memory_object, max_perms) = {
         let handle: Handle = memory_object_handle.into();
         if handle.valid() {
-            Some(process.handles().get_memory_object(handle)?)
+            let (memory_object, max_perms) = process
+                .handles()
+                .get_mappable_memory_object(handle, Permissions::from_bits_retain(perms as u64))?;
+            (Some(memory_object), max_perms)
         } else {
-            None
+            (None, Permissions::NONE)
         }
     };
 
@@ -99,6 +124,7 @@ pub async fn mmap(context: Context) -> Result<(), Error> {
         *addr_access.get(),
         size,
         Permissions::from_bits_retain(perms as u64),
+        max_perms,
         memory_object,
         offset,
     )?;
@@ -107,6 +133,76 @@ pub async fn mmap(context: Context) -> Result<(), Error> {
     Ok(())
 }
 
+pub async fn mmap_stack(context: Context) -> Result<(), Error> {
+    let process_handle = context.arg1();
+    let max_size = context.arg2();
+    let initial_size = context.arg3();
+    let addr_ptr = context.arg4();
+
+    let thread = context.owner();
+    let process = thread.process();
+
+    let target_process = process.handles().get_process(process_handle.into())?;
+
+    let mut addr_access = process.vm_access_typed::<VirtAddr>(
+        VirtAddr::new(addr_ptr as u64),
+        Permissions::READ | Permissions::WRITE,
+    )?;
+
+    let addr = target_process.mmap_stack(max_size, initial_size)?;
+
+    *addr_access.get_mut() = addr;
+    Ok(())
+}
+
+pub async fn mreserve(context: Context) -> Result<(), Error> {
+    let process_handle = context.arg1();
+    let addr_ptr = context.arg2();
+    let size = context.arg3();
+
+    let thread = context.owner();
+    let process = thread.process();
+
+    let target_process = process.handles().get_process(process_handle.into())?;
+
+    let mut addr_access = process.vm_access_typed::<VirtAddr>(
+        VirtAddr::new(addr_ptr as u64),
+        Permissions::READ | Permissions::WRITE,
+    )?;
+
+    let addr = target_process.mreserve(*addr_access.get(), size)?;
+
+    *addr_access.get_mut() = addr;
+    Ok(())
+}
+
+pub async fn mcommit(context: Context) -> Result<(), Error> {
+    let process_handle = context.arg1();
+    let addr = context.arg2();
+    let size = context.arg3();
+    let perms = context.arg4();
+    let memory_object_handle = context.arg5();
+    let offset = context.arg6();
+
+    let thread = context.owner();
+    let process = thread.process();
+
+    let target_process = process.handles().get_process(process_handle.into())?;
+    let (memory_object, max_perms) = process.handles().get_mappable_memory_object(
+        memory_object_handle.into(),
+        Permissions::from_bits_retain(perms as u64),
+    )?;
+
+    target_process.mcommit(
+        VirtAddr::new(addr as u64),
+        size,
+        Permissions::from_bits_retain(perms as u64),
+        max_perms,
+        memory_object,
+        offset,
+    )
+}
+
 pub async fn munmap(context: Context) -> Result<(), Error> {
     let process_handle = context.arg1();
     let addr = context.arg2();
@@ -117,7 +213,10 @@ pub async fn munmap(context: Context) -> Result<(), Error> {
 
     let target_process = process.handles().get_process(process_handle.into())?;
 
-    target_process.munmap(VirtAddr::new(addr as u64), size)
+    let (size, partial) = clip_to_budget(size);
+    target_process.munmap(VirtAddr::new(addr as u64), size)?;
+
+    budget_result(partial)
 }
 
 pub async fn mprotect(context: Context) -> Result<(), Error> {
@@ -131,11 +230,79 @@ pub async fn mprotect(context: Context) -> Result<(), Error> {
 
     let target_process = process.handles().get_process(process_handle.into())?;
 
+    let (size, partial) = clip_to_budget(size);
     target_process.mprotect(
         VirtAddr::new(addr as u64),
         size,
         Permissions::from_bits_retain(perms as u64),
-    )
+    )?;
+
+    budget_result(partial)
+}
+
+pub async fn mname(context: Context) -> Result<(), Error> {
+    let process_handle = context.arg1();
+    let addr = context.arg2();
+    let size = context.arg3();
+    let name_ptr = context.arg4();
+    let name_len = context.arg5();
+
+    let thread = context.owner();
+    let process = thread.process();
+
+    let target_process = process.handles().get_process(process_handle.into())?;
+
+    let (size, partial) = clip_to_budget(size);
+
+    // Empty name clears it
+    if name_len == 0 {
+        target_process.mname(VirtAddr::new(addr as u64), size, None)?;
+        return budget_result(partial);
+    }
+
+    check_arg(name_len <= MappingInfo::NAME_LEN)?;
+    let name_reader = StringReader::new(&context, name_ptr, name_len)?;
+    let name = name_reader.str()?;
+
+    target_process.mname(VirtAddr::new(addr as u64), size, Some(name))?;
+
+    budget_result(partial)
+}
+
+pub async fn mappings(context: Context) -> Result<(), Error> {
+    let process_handle = context.arg1();
+    let array_ptr = context.arg2();
+    let count_ptr = context.arg3();
+
+    let thread = context.owner();
+    let process = thread.process();
+
+    let target_process = process.handles().get_process(process_handle.into())?;
+
+    let mut writer = ListOutputWriter::<MappingInfo>::new(&context, array_ptr, count_ptr)?;
+
+    writer.fill(&target_process.mappings_info());
+
+    Ok(())
+}
+
+pub async fn query_range(context: Context) -> Result<(), Error> {
+    let process_handle = context.arg1();
+    let addr = context.arg2();
+    let size = context.arg3();
+    let array_ptr = context.arg4();
+    let count_ptr = context.arg5();
+
+    let thread = context.owner();
+    let process = thread.process();
+
+    let target_process = process.handles().get_process(process_handle.into())?;
+
+    let mut writer = ListOutputWriter::<MappingInfo>::new(&context, array_ptr, count_ptr)?;
+
+    writer.fill(&target_process.mappings_info_in(VirtAddr::new(addr as u64), size)?);
+
+    Ok(())
 }
 
 pub async fn exit(context: Context) -> Result<(), Error> {
@@ -169,6 +336,13 @@ pub async fn kill(context: Context) -> Result<(), Error> {
     // Forbid to kill self
     check_arg(!Arc::ptr_eq(&process, &target_process))?;
 
+    debug!(
+        "Process {} '{}' killed by thread {}",
+        target_process.id(),
+        target_process.name(),
+        thread.describe()
+    );
+
     // TODO: must be atomic (no thread must be created in the process while doing this)
     for tid in target_process.threads() {
         let thread = crate::user::thread::find(tid).expect("Thread does not exist");
@@ -181,6 +355,64 @@ pub async fn kill(context: Context) -> Result<(), Error> {
     Ok(())
 }
 
+pub async fn suspend(context: Context) -> Result<(), Error> {
+    let process_handle = context.arg1();
+
+    let thread = context.owner();
+    let process = thread.process();
+
+    let target_process = process.handles().get_process(process_handle.into())?;
+
+    // Forbid to suspend self: the caller would have no way to resume
+    check_arg(!Arc::ptr_eq(process, &target_process))?;
+    check_suspend(&thread, &target_process)?;
+    check_arg(!target_process.suspended())?;
+
+    let threads: Vec<_> = target_process
+        .threads()
+        .into_iter()
+        .filter_map(thread::find)
+        .collect();
+
+    // The scheduler always needs a thread to run
+    check_arg(
+        !threads
+            .iter()
+            .any(|thread| thread.priority() == ThreadPriority::Idle),
+    )?;
+
+    // Note: atomic since the kernel is not preemptible, no thread can run or be created in the process meanwhile
+    target_process.set_suspended(true);
+
+    for thread in threads.iter() {
+        thread::thread_suspend(thread);
+    }
+
+    Ok(())
+}
+
+pub async fn resume(context: Context) -> Result<(), Error> {
+    let process_handle = context.arg1();
+
+    let thread = context.owner();
+    let process = thread.process();
+
+    let target_process = process.handles().get_process(process_handle.into())?;
+
+    check_suspend(&thread, &target_process)?;
+    check_arg(target_process.suspended())?;
+
+    target_process.set_suspended(false);
+
+    for tid in target_process.threads() {
+        if let Some(thread) = thread::find(tid) {
+            thread::thread_unsuspend(&thread);
+        }
+    }
+
+    Ok(())
+}
+
 pub async fn info(context: Context) -> Result<(), Error> {
     let process_handle = context.arg1();
     let info_ptr = context.arg2();
@@ -204,9 +436,14 @@ pub async fn info(context: Context) -> Result<(), Error> {
         mapping_count: target_process.mapping_count(),
         handle_count: target_process.handles().len(),
         terminated: target_process.terminated(),
+        suspended: target_process.suspended(),
+        name_id: 0,
+        creator_pid: target_process.creator().unwrap_or(0),
+        sandbox: target_process.sandbox(),
     };
 
     let process_name = target_process.name();
+    info.name_id = process_name.id();
     let src_name = process_name.as_bytes();
     let name_len = min(ProcessInfo::NAME_LEN, src_name.len());
     info.name[0..name_len].copy_from_slice(&src_name[0..name_len]);
@@ -214,19 +451,295 @@ pub async fn info(context: Context) -> Result<(), Error> {
     Ok(())
 }
 
+/// Set the limits on the number of kernel objects the process can create
+///
+/// Note: a process cannot set its own limits, this is up to its spawner
+pub async fn set_object_limits(context: Context) -> Result<(), Error> {
+    let process_handle = context.arg1();
+    let limits_ptr = context.arg2();
+
+    let thread = context.owner();
+    let process = thread.process();
+
+    let target_process = process.handles().get_process(process_handle.into())?;
+
+    check_arg(!Arc::ptr_eq(process, &target_process))?;
+    check_control(&thread, &target_process)?;
+
+    let user_access = process
+        .vm_access_typed::<ObjectCounts>(VirtAddr::new(limits_ptr as u64), Permissions::READ)?;
+
+    target_process.object_quota().set_limits(*user_access.get());
+
+    Ok(())
+}
+
+/// Set the sandbox rights of a process
+///
+/// The caller must control the target process (see `check_control`), and cannot give rights it does not have itself
+/// (unless it is privileged).
+pub async fn set_sandbox(context: Context) -> Result<(), Error> {
+    let process_handle = context.arg1();
+    let flags = SandboxFlags::from_bits(context.arg2() as u32);
+
+    let thread = context.owner();
+    let process = thread.process();
+
+    check_arg(SandboxFlags::ALL.contains(flags))?;
+
+    let target_process = process.handles().get_process(process_handle.into())?;
+
+    check_arg(!Arc::ptr_eq(process, &target_process))?;
+    check_control(&thread, &target_process)?;
+
+    if !thread.privileged() && !process.sandbox().contains(flags) {
+        return Err(not_supported());
+    }
+
+    target_process.set_sandbox(flags);
+
+    Ok(())
+}
+
+/// Grant a range of io-ports to a process
+///
+/// The caller must control the target process (see `check_control`), and hold the io-ports itself:
+/// only privileged threads and init (which delegates the io-ports of the machine to the drivers it spawns) can grant others.
+pub async fn grant_io_ports(context: Context) -> Result<(), Error> {
+    let process_handle = context.arg1();
+    let start = context.arg2();
+    let end = context.arg3();
+
+    let thread = context.owner();
+    let process = thread.process();
+
+    check_arg(start < end && end <= u16::MAX as usize)?;
+    let range = start as u16..end as u16;
+
+    let target_process = process.handles().get_process(process_handle.into())?;
+
+    check_arg(!target_process.terminated())?;
+    check_control(&thread, &target_process)?;
+
+    let can_delegate =
+        thread.privileged() || process.creator().is_none() || process.has_io_ports(&range);
+    if !can_delegate {
+        return Err(not_supported());
+    }
+
+    target_process.grant_io_ports(range.clone());
+
+    if Arc::ptr_eq(process, &target_process) {
+        gdt::set_io_ports(range.clone(), true);
+    }
+
+    audit::record(
+        &thread,
+        AuditEventType::IoPortGrant,
+        target_process.id(),
+        Some(&format!("0x{:04X}..0x{:04X}", range.start, range.end)),
+    );
+
+    Ok(())
+}
+
+/// Read the memory of a process (debuggers, crash dumps)
+///
+/// The caller must be privileged, in the same process, or have the `DEBUG` sandbox right.
+pub async fn memory_read(context: Context) -> Result<(), Error> {
+    let process_handle = context.arg1();
+    let addr = context.arg2();
+    let buffer_ptr = context.arg3();
+    let len = context.arg4();
+
+    let thread = context.owner();
+    let process = thread.process();
+
+    let target_process = process.handles().get_process(process_handle.into())?;
+    let range = check_memory_range(&thread, &target_process, addr, len)?;
+    if range.is_empty() {
+        return Ok(());
+    }
+
+    let source = target_process.vm_access(range, Permissions::READ)?;
+    let mut dest = process.vm_access(
+        VirtAddr::new(buffer_ptr as u64)..VirtAddr::new((buffer_ptr + len) as u64),
+        Permissions::WRITE,
+    )?;
+
+    dest.get_slice_mut::<u8>()
+        .copy_from_slice(source.get_slice::<u8>());
+
+    Ok(())
+}
+
+/// Write the memory of a process (debuggers)
+///
+/// Same rights than `memory_read`. Only writable memory can be written: code is never patched,
+/// since its memory objects may be shared with other processes.
+pub async fn memory_write(context: Context) -> Result<(), Error> {
+    let process_handle = context.arg1();
+    let addr = context.arg2();
+    let buffer_ptr = context.arg3();
+    let len = context.arg4();
+
+    let thread = context.owner();
+    let process = thread.process();
+
+    let target_process = process.handles().get_process(process_handle.into())?;
+    let range = check_memory_range(&thread, &target_process, addr, len)?;
+    if range.is_empty() {
+        return Ok(());
+    }
+
+    let source = process.vm_access(
+        VirtAddr::new(buffer_ptr as u64)..VirtAddr::new((buffer_ptr + len) as u64),
+        Permissions::READ,
+    )?;
+    let mut dest = target_process.vm_access(range, Permissions::WRITE)?;
+
+    dest.get_slice_mut::<u8>()
+        .copy_from_slice(source.get_slice::<u8>());
+
+    Ok(())
+}
+
+/// List the handles opened by a process (debuggers, checkpoints)
+///
+/// Same rights than `memory_read`.
+pub async fn handles(context: Context) -> Result<(), Error> {
+    let process_handle = context.arg1();
+    let array_ptr = context.arg2();
+    let count_ptr = context.arg3();
+
+    let thread = context.owner();
+    let process = thread.process();
+
+    let target_process = process.handles().get_process(process_handle.into())?;
+    check_inspect(&thread, &target_process)?;
+
+    let mut writer = ListOutputWriter::<HandleInfo>::new(&context, array_ptr, count_ptr)?;
+
+    writer.fill(&target_process.handles().infos());
+
+    Ok(())
+}
+
+/// Open an object of the caller in a process, with the given handle value (restore of a checkpoint)
+///
+/// The caller must control the target process. The value must not be used in the target process.
+pub async fn install_handle(context: Context) -> Result<(), Error> {
+    let process_handle = context.arg1();
+    let target_handle = context.arg2();
+    let source_handle = context.arg3();
+
+    let thread = context.owner();
+    let process = thread.process();
+
+    let target_process = process.handles().get_process(process_handle.into())?;
+    check_control(&thread, &target_process)?;
+
+    let handle_impl = process.handles().get(source_handle.into())?;
+
+    target_process
+        .handles()
+        .open_at(target_handle.into(), handle_impl)
+}
+
+/// Check the rights and the range of an access to the memory of a process
+fn check_memory_range(
+    thread: &Thread,
+    target_process: &Arc<Process>,
+    addr: usize,
+    len: usize,
+) -> Result<Range<VirtAddr>, Error> {
+    check_arg(!target_process.terminated())?;
+    check_arg(len <= PROCESS_MEMORY_MAX_SIZE)?;
+    check_inspect(thread, target_process)?;
+
+    let end = check_arg_opt(addr.checked_add(len))?;
+    let range = check_is_userspace(VirtAddr::new(addr as u64))?
+        ..check_is_userspace(VirtAddr::new(end as u64))?;
+
+    Ok(range)
+}
+
+pub async fn object_usage(context: Context) -> Result<(), Error> {
+    let process_handle = context.arg1();
+    let usage_ptr = context.arg2();
+    let limits_ptr = context.arg3();
+
+    let thread = context.owner();
+    let process = thread.process();
+
+    let target_process = process.handles().get_process(process_handle.into())?;
+
+    let mut usage_access = process.vm_access_typed::<ObjectCounts>(
+        VirtAddr::new(usage_ptr as u64),
+        Permissions::READ | Permissions::WRITE,
+    )?;
+    let mut limits_access = process.vm_access_typed::<ObjectCounts>(
+        VirtAddr::new(limits_ptr as u64),
+        Permissions::READ | Permissions::WRITE,
+    )?;
+
+    let quota = target_process.object_quota();
+    *usage_access.get_mut() = quota.usage();
+    *limits_access.get_mut() = quota.limits();
+
+    Ok(())
+}
+
 /// count_ptr:
 /// - on input -> element count in array
 /// - on output -> real number of processes. Can be smaller or larger than array. If larger, the array is truncated
 pub async fn list(context: Context) -> Result<(), Error> {
-    let array_ptr = context.arg1();
-    let count_ptr = context.arg2();
+    let start = context.arg1() as u64;
+    let array_ptr = context.arg2();
+    let count_ptr = context.arg3();
 
     //let thread = context.owner();
     //let process = thread.process();
 
     let mut writer = ListOutputWriter::<u64>::new(&context, array_ptr, count_ptr)?;
 
-    writer.fill(&process::list());
+    writer.fill(&ids_from(process::list(), start));
+
+    Ok(())
+}
+
+pub async fn threads(context: Context) -> Result<(), Error> {
+    let process_handle = context.arg1();
+    let start = context.arg2() as u64;
+    let array_ptr = context.arg3();
+    let count_ptr = context.arg4();
+
+    let thread = context.owner();
+    let process = thread.process();
+
+    let target_process = process.handles().get_process(process_handle.into())?;
+
+    let mut writer = ListOutputWriter::<u64>::new(&context, array_ptr, count_ptr)?;
+
+    writer.fill(&ids_from(target_process.threads(), start));
+
+    Ok(())
+}
+
+pub async fn ports(context: Context) -> Result<(), Error> {
+    let process_handle = context.arg1();
+    let start = context.arg2() as u64;
+    let array_ptr = context.arg3();
+    let count_ptr = context.arg4();
+
+    let thread = context.owner();
+    let process = thread.process();
+
+    let target_process = process.handles().get_process(process_handle.into())?;
+
+    let mut writer = ListOutputWriter::<u64>::new(&context, array_ptr, count_ptr)?;
+
+    writer.fill(&ids_from(ipc::list_by_owner(target_process.id()), start));
 
     Ok(())
 }
@@ -268,3 +781,65 @@ pub async fn get_name(context: Context) -> Result<(), Error> {
 
     Ok(())
 }
+
+pub async fn names(context: Context) -> Result<(), Error> {
+    let ids_ptr = context.arg1();
+    let entries_ptr = context.arg2();
+    let count = context.arg3();
+
+    let thread = context.owner();
+    let process = thread.process();
+
+    let ids_access = process.vm_access_typed_slice::<u64>(
+        VirtAddr::new(ids_ptr as u64),
+        count,
+        Permissions::READ,
+    )?;
+
+    let mut entries_access = process.vm_access_typed_slice::<NameEntry>(
+        VirtAddr::new(entries_ptr as u64),
+        count,
+        Permissions::READ | Permissions::WRITE,
+    )?;
+
+    for (&pid, entry) in ids_access.get().iter().zip(entries_access.get_mut()) {
+        *entry = match process::find(pid) {
+            Some(target_process) => NameEntry::new(pid, Some(&target_process.name())),
+            None => NameEntry::not_found(pid),
+        };
+    }
+
+    Ok(())
+}
+
+/// Check that the thread can control the target process: it must be privileged, or belong to the process which created it
+///
+/// Note: a process handle can be opened by anyone, it does not grant this
+fn check_control(thread: &Thread, target_process: &Process) -> Result<(), Error> {
+    if thread.privileged() || target_process.creator() == Some(thread.process().id()) {
+        Ok(())
+    } else {
+        Err(not_supported())
+    }
+}
+
+/// Check that the thread can inspect the target process: it must be privileged, in the same process, or be a debugger (`DEBUG` sandbox right)
+pub(super) fn check_inspect(thread: &Thread, target_process: &Arc<Process>) -> Result<(), Error> {
+    if thread.privileged()
+        || Arc::ptr_eq(thread.process(), target_process)
+        || thread.process().sandbox().contains(SandboxFlags::DEBUG)
+    {
+        Ok(())
+    } else {
+        Err(not_supported())
+    }
+}
+
+/// Check that the thread can suspend and resume the target process: it must control it, or be a debugger (`DEBUG` sandbox right)
+fn check_suspend(thread: &Thread, target_process: &Process) -> Result<(), Error> {
+    if thread.process().sandbox().contains(SandboxFlags::DEBUG) {
+        Ok(())
+    } else {
+        check_control(thread, target_process)
+    }
+}
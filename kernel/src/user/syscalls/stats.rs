@@ -0,0 +1,94 @@
+use alloc::{collections::BTreeMap, vec::Vec};
+use spin::Mutex;
+use syscalls::{Error, IdleStats, ReadyLatency, SyscallLatency, SyscallNumber, SystemInfo};
+
+use crate::{
+    memory::{self, Permissions, VirtAddr},
+    user::{process, thread, timer},
+};
+
+use super::{helpers::ListOutputWriter, Context};
+
+/// Latency histograms, by syscall number
+///
+/// Note: there is only one CPU for now, so there is no per-CPU split to aggregate on read
+static LATENCIES: Mutex<BTreeMap<usize, SyscallLatency>> = Mutex::new(BTreeMap::new());
+
+/// Record the latency of a completed syscall
+pub fn record(syscall_number: SyscallNumber, ticks: u64) {
+    let number = syscall_number as usize;
+    let mut latencies = LATENCIES.lock();
+
+    latencies
+        .entry(number)
+        .or_insert_with(|| SyscallLatency::new(number))
+        .add(ticks);
+}
+
+pub async fn syscalls(context: Context) -> Result<(), Error> {
+    let array_ptr = context.arg1();
+    let count_ptr = context.arg2();
+
+    let mut writer = ListOutputWriter::<SyscallLatency>::new(&context, array_ptr, count_ptr)?;
+
+    // Copy first, so that the lock is not held while writing into userland memory
+    let latencies: Vec<SyscallLatency> = LATENCIES.lock().values().copied().collect();
+    writer.fill(&latencies);
+
+    Ok(())
+}
+
+pub async fn system_info(context: Context) -> Result<(), Error> {
+    let info_ptr = context.arg1();
+
+    let owner = context.owner();
+    let current_process = owner.process();
+
+    let mut user_access = current_process.vm_access_typed::<SystemInfo>(
+        VirtAddr::new(info_ptr as u64),
+        Permissions::READ | Permissions::WRITE,
+    )?;
+
+    let phys = memory::stats().phys;
+
+    *user_access.get_mut() = SystemInfo {
+        uptime: timer::now(),
+        memory_total: phys.total,
+        memory_free: phys.free,
+        processes: process::list().len(),
+        threads: thread::list().len(),
+        runnable: thread::runnable(),
+        load: thread::load(),
+        boot_time: timer::boot_time(),
+        wall_clock: timer::wall_clock(),
+    };
+
+    Ok(())
+}
+
+pub async fn idle(context: Context) -> Result<(), Error> {
+    let stats_ptr = context.arg1();
+
+    let owner = context.owner();
+    let current_process = owner.process();
+
+    let mut user_access = current_process.vm_access_typed::<IdleStats>(
+        VirtAddr::new(stats_ptr as u64),
+        Permissions::READ | Permissions::WRITE,
+    )?;
+
+    *user_access.get_mut() = thread::idle_stats();
+
+    Ok(())
+}
+
+pub async fn ready_latencies(context: Context) -> Result<(), Error> {
+    let array_ptr = context.arg1();
+    let count_ptr = context.arg2();
+
+    let mut writer = ListOutputWriter::<ReadyLatency>::new(&context, array_ptr, count_ptr)?;
+
+    writer.fill(&thread::ready_latencies());
+
+    Ok(())
+}
@@ -0,0 +1,38 @@
+use syscalls::{Error, NameEntry};
+
+use crate::{
+    memory::{Permissions, VirtAddr},
+    user::strings,
+};
+
+use super::context::Context;
+
+pub async fn lookup(context: Context) -> Result<(), Error> {
+    let ids_ptr = context.arg1();
+    let entries_ptr = context.arg2();
+    let count = context.arg3();
+
+    let thread = context.owner();
+    let process = thread.process();
+
+    let ids_access = process.vm_access_typed_slice::<u64>(
+        VirtAddr::new(ids_ptr as u64),
+        count,
+        Permissions::READ,
+    )?;
+
+    let mut entries_access = process.vm_access_typed_slice::<NameEntry>(
+        VirtAddr::new(entries_ptr as u64),
+        count,
+        Permissions::READ | Permissions::WRITE,
+    )?;
+
+    for (&id, entry) in ids_access.get().iter().zip(entries_access.get_mut()) {
+        *entry = match strings::lookup(id) {
+            Some(value) => NameEntry::new(id, Some(&value)),
+            None => NameEntry::not_found(id),
+        };
+    }
+
+    Ok(())
+}
@@ -2,22 +2,28 @@ use core::{cmp::min, mem};
 
 use alloc::sync::Arc;
 use syscalls::{
-    Exception, Permissions, ThreadContext, ThreadContextRegister, ThreadCreationParameters,
-    ThreadInfo, ThreadPriority, ThreadState,
+    CurrentIds, Exception, NameEntry, Permissions, SandboxFlags, SchedEvent, ThreadContext,
+    ThreadContextRegister, ThreadCreationParameters, ThreadInfo, ThreadPriority, ThreadState,
+    WatchpointKind, WATCHPOINT_COUNT,
 };
 
+use crate::logging::debug;
 use crate::{
+    interrupts::Watchpoint,
     memory::VirtAddr,
     user::{
-        error::{check_arg, check_found, check_is_userspace, invalid_argument},
-        thread::{self, thread_resume},
-        Error,
+        error::{
+            check_arg, check_found, check_is_userspace, deadline_exceeded, invalid_argument,
+            not_supported, out_of_memory,
+        },
+        thread::{self, thread_resume, Thread},
+        timer, Error,
     },
 };
 
 use super::{
     context::Context,
-    helpers::{HandleOutputWriter, ListOutputWriter, StringReader},
+    helpers::{ids_from, HandleOutputWriter, ListOutputWriter, StringReader},
 };
 
 pub async fn open_self(context: Context) -> Result<(), Error> {
@@ -28,12 +34,29 @@ pub async fn open_self(context: Context) -> Result<(), Error> {
 
     let mut handle_out = HandleOutputWriter::new(&context, handle_out_ptr)?;
 
-    let handle = process.handles().open_thread(thread.clone());
+    let handle = process.handles().open_thread(thread.clone())?;
 
     handle_out.set(handle);
     Ok(())
 }
 
+pub async fn get_ids(context: Context) -> Result<(), Error> {
+    let ids_ptr = context.arg1();
+
+    let thread = context.owner();
+    let process = thread.process();
+
+    let mut user_ids =
+        process.vm_access_typed::<CurrentIds>(VirtAddr::new(ids_ptr as u64), Permissions::WRITE)?;
+
+    *user_ids.get_mut() = CurrentIds {
+        pid: process.id(),
+        tid: thread.id(),
+    };
+
+    Ok(())
+}
+
 pub async fn open(context: Context) -> Result<(), Error> {
     let tid = context.arg1();
     let handle_out_ptr = context.arg2();
@@ -44,7 +67,7 @@ pub async fn open(context: Context) -> Result<(), Error> {
     let mut handle_out = HandleOutputWriter::new(&context, handle_out_ptr)?;
 
     let target_thread = check_found(thread::find(tid as u64))?;
-    let handle = process.handles().open_thread(target_thread.clone());
+    let handle = process.handles().open_thread(target_thread.clone())?;
 
     handle_out.set(handle);
     Ok(())
@@ -90,6 +113,11 @@ pub async fn create(context: Context) -> Result<(), Error> {
     // Forbid to thread threads on terminated processes
     check_arg(!target_process.terminated())?;
 
+    // The thread cannot be undone once created: check first that its handle can be opened
+    if process.handles().available() == 0 {
+        return Err(out_of_memory());
+    }
+
     let new_thread = thread::create(
         name,
         target_process.clone(),
@@ -101,7 +129,10 @@ pub async fn create(context: Context) -> Result<(), Error> {
         check_is_userspace(VirtAddr::new(params.tls as u64))?,
     );
 
-    let handle = process.handles().open_thread(new_thread);
+    let handle = process
+        .handles()
+        .open_thread(new_thread)
+        .expect("Could not open thread handle");
 
     handle_out.set(handle);
     Ok(())
@@ -122,6 +153,12 @@ pub async fn kill(context: Context) -> Result<(), Error> {
     // Forbid to kill self
     check_arg(!Arc::ptr_eq(&thread, &target_thread))?;
 
+    debug!(
+        "Thread {} killed by thread {}",
+        target_thread.describe(),
+        thread.describe()
+    );
+
     thread::thread_terminate(&target_thread);
 
     Ok(())
@@ -163,6 +200,7 @@ pub async fn info(context: Context) -> Result<(), Error> {
         thread::ThreadState::Waiting(_) => ThreadState::Waiting,
         thread::ThreadState::Error(_) => ThreadState::Error,
         thread::ThreadState::Terminated => ThreadState::Terminated,
+        thread::ThreadState::Suspended => ThreadState::Suspended,
     };
 
     let info = &mut *user_access.get_mut();
@@ -175,10 +213,12 @@ pub async fn info(context: Context) -> Result<(), Error> {
         priority: target_thread.priority(),
         state,
         ticks: target_thread.ticks(),
+        name_id: 0,
     };
 
     let thread_name = target_thread.name();
     if let Some(thread_name) = &*thread_name {
+        info.name_id = thread_name.id();
         let src_name = thread_name.as_bytes();
         let name_len = min(ThreadInfo::NAME_LEN, src_name.len());
         info.name[0..name_len].copy_from_slice(&src_name[0..name_len]);
@@ -191,15 +231,16 @@ pub async fn info(context: Context) -> Result<(), Error> {
 /// - on input -> element count in array
 /// - on output -> real number of processes. Can be smaller or larger than array. If larger, the array is truncated
 pub async fn list(context: Context) -> Result<(), Error> {
-    let array_ptr = context.arg1();
-    let count_ptr = context.arg2();
+    let start = context.arg1() as u64;
+    let array_ptr = context.arg2();
+    let count_ptr = context.arg3();
 
     //let thread = context.owner();
     //let process = thread.process();
 
     let mut writer = ListOutputWriter::<u64>::new(&context, array_ptr, count_ptr)?;
 
-    writer.fill(&thread::list());
+    writer.fill(&ids_from(thread::list(), start));
 
     Ok(())
 }
@@ -246,6 +287,37 @@ pub async fn get_name(context: Context) -> Result<(), Error> {
 
     Ok(())
 }
+
+pub async fn names(context: Context) -> Result<(), Error> {
+    let ids_ptr = context.arg1();
+    let entries_ptr = context.arg2();
+    let count = context.arg3();
+
+    let thread = context.owner();
+    let process = thread.process();
+
+    let ids_access = process.vm_access_typed_slice::<u64>(
+        VirtAddr::new(ids_ptr as u64),
+        count,
+        Permissions::READ,
+    )?;
+
+    let mut entries_access = process.vm_access_typed_slice::<NameEntry>(
+        VirtAddr::new(entries_ptr as u64),
+        count,
+        Permissions::READ | Permissions::WRITE,
+    )?;
+
+    for (&tid, entry) in ids_access.get().iter().zip(entries_access.get_mut()) {
+        *entry = match thread::find(tid) {
+            Some(target_thread) => NameEntry::new(tid, target_thread.name().as_deref()),
+            None => NameEntry::not_found(tid),
+        };
+    }
+
+    Ok(())
+}
+
 pub async fn error_info(context: Context) -> Result<(), Error> {
     let thread_handle = context.arg1();
     let info_ptr = context.arg2();
@@ -283,7 +355,8 @@ pub async fn context(context: Context) -> Result<(), Error> {
         Permissions::READ | Permissions::WRITE,
     )?;
 
-    check_arg(target_thread.state().is_error().is_some())?;
+    check_arg(context_readable(&thread, &target_thread))?;
+    check_debug(&thread, &target_thread)?;
 
     // TODO: not atomic with check
     target_thread.get_user_context(user_access.get_mut());
@@ -291,6 +364,34 @@ pub async fn context(context: Context) -> Result<(), Error> {
     Ok(())
 }
 
+/// The context of a thread can be read:
+/// - by its supervisor, when it is in error state or suspended
+/// - by another thread of its process, when it is not terminated: the context is the one saved on its last kernel entry (syscall, interrupt)
+fn context_readable(caller: &Arc<Thread>, target: &Arc<Thread>) -> bool {
+    let state = target.state();
+
+    if state.is_error().is_some() || state.is_suspended() {
+        return true;
+    }
+
+    // Note: on a single CPU, any thread but the caller is not executing
+    Arc::ptr_eq(caller.process(), target.process())
+        && !state.is_executing()
+        && !state.is_terminated()
+}
+
+/// Supervising a thread of another process needs the `DEBUG` sandbox right
+fn check_debug(caller: &Thread, target: &Thread) -> Result<(), Error> {
+    if caller.privileged()
+        || Arc::ptr_eq(caller.process(), target.process())
+        || caller.process().sandbox().contains(SandboxFlags::DEBUG)
+    {
+        Ok(())
+    } else {
+        Err(not_supported())
+    }
+}
+
 pub async fn update_context(context: Context) -> Result<(), Error> {
     let thread_handle = context.arg1();
     let regs_array_ptr = context.arg2();
@@ -307,7 +408,12 @@ pub async fn update_context(context: Context) -> Result<(), Error> {
         Permissions::READ,
     )?;
 
-    check_arg(target_thread.state().is_error().is_some())?;
+    {
+        let state = target_thread.state();
+        check_arg(state.is_error().is_some() || state.is_suspended())?;
+    }
+
+    check_debug(&thread, &target_thread)?;
 
     // TODO: not atomic with check
     target_thread.update_user_context(regs_access.get())
@@ -322,8 +428,140 @@ pub async fn resume(context: Context) -> Result<(), Error> {
     let target_thread = process.handles().get_thread(thread_handle.into())?;
 
     check_arg(target_thread.state().is_error().is_some())?;
+    check_debug(&thread, &target_thread)?;
 
     thread_resume(&target_thread);
 
     Ok(())
 }
+
+pub async fn set_watchpoint(context: Context) -> Result<(), Error> {
+    let thread_handle = context.arg1();
+    let index = context.arg2();
+    let address = context.arg3();
+    let size = context.arg4();
+    let kind = context.arg5();
+
+    let thread = context.owner();
+    let process = thread.process();
+
+    let target_thread = process.handles().get_thread(thread_handle.into())?;
+
+    // Debug registers of the current thread are already loaded
+    check_arg(!Arc::ptr_eq(&thread, &target_thread))?;
+    check_debug(&thread, &target_thread)?;
+
+    check_arg(index < WATCHPOINT_COUNT)?;
+    check_arg(size == 1 || size == 2 || size == 4 || size == 8)?;
+    check_arg(address % size == 0)?;
+    check_arg(
+        kind == WatchpointKind::Write as usize
+            || kind == WatchpointKind::ReadWrite as usize
+            || (kind == WatchpointKind::Execute as usize && size == 1),
+    )?;
+    let address = check_is_userspace(VirtAddr::new(address as u64))?;
+    let kind: WatchpointKind = unsafe { mem::transmute(kind) };
+
+    target_thread.set_watchpoint(
+        index,
+        Some(Watchpoint {
+            address,
+            size,
+            kind,
+        }),
+    );
+
+    Ok(())
+}
+
+pub async fn clear_watchpoint(context: Context) -> Result<(), Error> {
+    let thread_handle = context.arg1();
+    let index = context.arg2();
+
+    let thread = context.owner();
+    let process = thread.process();
+
+    let target_thread = process.handles().get_thread(thread_handle.into())?;
+
+    // Debug registers of the current thread are already loaded
+    check_arg(!Arc::ptr_eq(&thread, &target_thread))?;
+    check_debug(&thread, &target_thread)?;
+
+    check_arg(index < WATCHPOINT_COUNT)?;
+
+    target_thread.set_watchpoint(index, None);
+
+    Ok(())
+}
+
+pub async fn sched_trace_enable(context: Context) -> Result<(), Error> {
+    let enabled = context.arg1();
+
+    thread::sched_trace_set_enabled(enabled != 0);
+
+    Ok(())
+}
+
+pub async fn sched_trace_read(context: Context) -> Result<(), Error> {
+    let array_ptr = context.arg1();
+    let count_ptr = context.arg2();
+
+    let thread = context.owner();
+    let process = thread.process();
+
+    // In: size of the array, out: number of events read
+    let mut count_access = process.vm_access_typed::<usize>(
+        VirtAddr::new(count_ptr as u64),
+        Permissions::READ | Permissions::WRITE,
+    )?;
+
+    let mut array_access = process.vm_access_typed_slice::<SchedEvent>(
+        VirtAddr::new(array_ptr as u64),
+        *count_access.get(),
+        Permissions::READ | Permissions::WRITE,
+    )?;
+
+    *count_access.get_mut() = thread::sched_trace_drain(array_access.get_mut());
+
+    Ok(())
+}
+
+pub async fn new_correlation(context: Context) -> Result<(), Error> {
+    let correlation_ptr = context.arg1();
+
+    let thread = context.owner();
+    let process = thread.process();
+
+    let mut user_correlation = process
+        .vm_access_typed::<u64>(VirtAddr::new(correlation_ptr as u64), Permissions::WRITE)?;
+
+    *user_correlation.get_mut() = thread.new_correlation();
+
+    Ok(())
+}
+
+pub async fn set_deadline(context: Context) -> Result<(), Error> {
+    let timeout = context.arg1() as u64;
+
+    let thread = context.owner();
+
+    let deadline = if timeout == 0 {
+        0
+    } else {
+        timer::now().saturating_add(timeout)
+    };
+
+    thread.set_deadline(deadline);
+
+    Ok(())
+}
+
+pub async fn check_deadline(context: Context) -> Result<(), Error> {
+    let thread = context.owner();
+
+    match thread.deadline() {
+        0 => Ok(()),
+        deadline if timer::now() < deadline => Ok(()),
+        _ => Err(deadline_exceeded()),
+    }
+}
@@ -0,0 +1,114 @@
+use syscalls::{AuditEventType, Error, Permissions, TimerStats, MAX_ADVANCE_NS, TICK_NS};
+
+use crate::{
+    memory::VirtAddr,
+    user::{
+        audit,
+        error::{check_arg, not_supported},
+        process::QuotaKind,
+        timer::{self, Timer},
+    },
+};
+
+use super::{context::Context, helpers::HandleOutputWriter};
+
+pub async fn create(context: Context) -> Result<(), Error> {
+    let port_handle = context.arg1();
+    let handle_out_ptr = context.arg2();
+
+    let thread = context.owner();
+    let process = thread.process();
+
+    let mut handle_out = HandleOutputWriter::new(&context, handle_out_ptr)?;
+
+    let port = process.handles().get_port_sender(port_handle.into())?;
+
+    let charge = process.charge_object(QuotaKind::Timer)?;
+    let handle = process.handles().open_timer(Timer::new(port, charge))?;
+
+    handle_out.set(handle);
+    Ok(())
+}
+
+pub async fn arm(context: Context) -> Result<(), Error> {
+    let timer_handle = context.arg1();
+    let delay = context.arg2();
+    let slack = context.arg3();
+
+    let thread = context.owner();
+    let process = thread.process();
+
+    let timer = process.handles().get_timer(timer_handle.into())?;
+
+    timer.arm(delay as u64, slack as u64);
+
+    Ok(())
+}
+
+pub async fn cancel(context: Context) -> Result<(), Error> {
+    let timer_handle = context.arg1();
+
+    let thread = context.owner();
+    let process = thread.process();
+
+    let timer = process.handles().get_timer(timer_handle.into())?;
+
+    timer.cancel();
+
+    Ok(())
+}
+
+pub async fn stats(context: Context) -> Result<(), Error> {
+    let stats_ptr = context.arg1();
+
+    let thread = context.owner();
+    let process = thread.process();
+
+    let mut user_access = process.vm_access_typed::<TimerStats>(
+        VirtAddr::new(stats_ptr as u64),
+        Permissions::READ | Permissions::WRITE,
+    )?;
+
+    *user_access.get_mut() = timer::stats();
+
+    Ok(())
+}
+
+pub async fn set_wall_clock(context: Context) -> Result<(), Error> {
+    let wall_clock = context.arg1() as u64;
+
+    let thread = context.owner();
+
+    // Moves the time of the whole system: reserved to privileged threads
+    if !thread.privileged() {
+        return Err(not_supported());
+    }
+
+    check_arg(timer::set_wall_clock(wall_clock))?;
+
+    audit::record(
+        &thread,
+        AuditEventType::ClockSet,
+        wall_clock / 1_000_000_000,
+        None,
+    );
+
+    Ok(())
+}
+
+pub async fn advance(context: Context) -> Result<(), Error> {
+    let delay = context.arg1() as u64;
+
+    // Moves the time of the whole system: reserved to privileged threads
+    if !context.owner().privileged() {
+        return Err(not_supported());
+    }
+
+    check_arg(delay <= MAX_ADVANCE_NS)?;
+
+    if !timer::advance(delay.div_ceil(TICK_NS)) {
+        return Err(not_supported());
+    }
+
+    Ok(())
+}
@@ -0,0 +1,93 @@
+use syscalls::{Permissions, ThreadPriority, TrampolineParameters};
+
+use crate::{
+    memory::VirtAddr,
+    user::{
+        error::{check_arg, out_of_memory},
+        trampoline::Trampoline,
+        Error,
+    },
+};
+
+use super::{
+    context::Context,
+    helpers::{HandleOutputWriter, StringReader},
+};
+
+pub async fn create(context: Context) -> Result<(), Error> {
+    let params_ptr = context.arg1();
+    let handle_out_ptr = context.arg2();
+
+    let thread = context.owner();
+    let process = thread.process();
+
+    let params_access = process.vm_access_typed::<TrampolineParameters>(
+        VirtAddr::new(params_ptr as u64),
+        Permissions::READ,
+    )?;
+
+    let mut handle_out = HandleOutputWriter::new(&context, handle_out_ptr)?;
+
+    let params = params_access.get();
+
+    // Idle threads are reserved to the idle process
+    check_arg(params.priority != ThreadPriority::Idle)?;
+
+    let trampoline = Trampoline::new(
+        process,
+        params.priority,
+        VirtAddr::new(params.entry_point as u64),
+        VirtAddr::new(params.stack_top as u64),
+        VirtAddr::new(params.tls as u64),
+    )?;
+
+    let handle = process.handles().open_trampoline(trampoline)?;
+
+    handle_out.set(handle);
+    Ok(())
+}
+
+pub async fn spawn(context: Context) -> Result<(), Error> {
+    let trampoline_handle = context.arg1();
+    let name_ptr = context.arg2();
+    let name_len = context.arg3();
+    let arg = context.arg4();
+    let handle_out_ptr = context.arg5();
+
+    let thread = context.owner();
+    let process = thread.process();
+
+    let trampoline = process.handles().get_trampoline(trampoline_handle.into())?;
+
+    // Need to keep reader because name is borrowed from it
+    let name_reader = if name_ptr > 0 {
+        Some(StringReader::new(&context, name_ptr, name_len)?)
+    } else {
+        None
+    };
+
+    let name = if let Some(name_reader) = &name_reader {
+        let name = name_reader.str()?;
+        check_arg(!name.is_empty())?;
+        Some(name)
+    } else {
+        None
+    };
+
+    let mut handle_out = HandleOutputWriter::new(&context, handle_out_ptr)?;
+
+    // The thread cannot be undone once created: check first that its handle can be opened
+    if process.handles().available() == 0 {
+        return Err(out_of_memory());
+    }
+
+    let new_thread = trampoline.spawn(name, arg)?;
+
+    let handle = process
+        .handles()
+        .open_thread(new_thread)
+        .expect("Could not open thread handle");
+
+    handle_out.set(handle);
+    Ok(())
+}
@@ -0,0 +1,46 @@
+use core::{
+    arch::x86_64::_rdtsc,
+    sync::atomic::{AtomicU64, Ordering},
+};
+
+use syscalls::IdleStats;
+
+use crate::devices::cpuidle;
+
+static ENTRIES: AtomicU64 = AtomicU64::new(0);
+static RESIDENCY_TICKS: AtomicU64 = AtomicU64::new(0);
+
+/// TSC value when the idle thread has been switched in (0 if not idle)
+static IDLE_SINCE: AtomicU64 = AtomicU64::new(0);
+
+/// Called when switching to an idle thread
+pub fn enter() {
+    ENTRIES.fetch_add(1, Ordering::Relaxed);
+    IDLE_SINCE.store(unsafe { _rdtsc() }, Ordering::Relaxed);
+}
+
+/// Called when switching from an idle thread
+pub fn exit() {
+    let since = IDLE_SINCE.swap(0, Ordering::Relaxed);
+    if since != 0 {
+        RESIDENCY_TICKS.fetch_add(unsafe { _rdtsc() } - since, Ordering::Relaxed);
+    }
+}
+
+/// Get the idle policy and the residency counters
+pub fn stats() -> IdleStats {
+    let now = unsafe { _rdtsc() };
+    let (method, mwait_hint) = cpuidle::policy();
+
+    // Account the current idle period, if any (eg: read from an interrupt)
+    let since = IDLE_SINCE.load(Ordering::Relaxed);
+    let current = if since != 0 { now - since } else { 0 };
+
+    IdleStats {
+        method,
+        mwait_hint: mwait_hint as u64,
+        entries: ENTRIES.load(Ordering::Relaxed),
+        residency_ticks: RESIDENCY_TICKS.load(Ordering::Relaxed) + current,
+        total_ticks: now,
+    }
+}
@@ -0,0 +1,50 @@
+use core::sync::atomic::{AtomicU64, Ordering};
+
+use syscalls::{SystemInfo, TICK_NS};
+
+use super::{current_thread, scheduler::SCHEDULER, ThreadPriority};
+
+/// Sampling period of the load average, in ticks (5 seconds)
+const SAMPLE_TICKS: u64 = 5_000_000_000 / TICK_NS;
+
+/// `LOAD_SCALE * exp(-5s / 1min)`: decay of the average on each sample
+const DECAY: u64 = 1884;
+
+const SCALE: u64 = SystemInfo::LOAD_SCALE;
+
+static SAMPLE_COUNTDOWN: AtomicU64 = AtomicU64::new(SAMPLE_TICKS);
+
+/// Exponential moving average of runnable threads over one minute, in `SystemInfo::LOAD_SCALE` units
+static LOAD: AtomicU64 = AtomicU64::new(0);
+
+/// Get the number of threads ready or executing, idle threads excluded
+pub fn runnable() -> usize {
+    let executing = if current_thread().priority() == ThreadPriority::Idle {
+        0
+    } else {
+        1
+    };
+
+    SCHEDULER.ready_count() + executing
+}
+
+/// Get the load average over the last minute, in `SystemInfo::LOAD_SCALE` units
+pub fn load() -> u64 {
+    LOAD.load(Ordering::Relaxed)
+}
+
+/// Account a tick in the load average
+///
+/// Called from the timer interrupt.
+pub fn sample() {
+    if SAMPLE_COUNTDOWN.fetch_sub(1, Ordering::Relaxed) > 1 {
+        return;
+    }
+
+    SAMPLE_COUNTDOWN.store(SAMPLE_TICKS, Ordering::Relaxed);
+
+    let active = runnable() as u64 * SCALE;
+    let load = LOAD.load(Ordering::Relaxed);
+    let load = (load * DECAY + active * (SCALE - DECAY)) / SCALE;
+    LOAD.store(load, Ordering::Relaxed);
+}
@@ -1,26 +1,38 @@
+mod idle;
+mod load;
 mod queue;
+mod sched_trace;
 mod scheduler;
 mod thread;
 mod threads;
 mod wait_queue;
 
+use core::hint;
+
 use alloc::{sync::Arc, vec::Vec};
 use hashbrown::HashSet;
-use log::debug;
 use spin::RwLock;
 
-use self::{
-    scheduler::SCHEDULER,
-    thread::{add_ticks, load_segments, syscall_clear, update_state, WaitQueueRef, WaitingData},
-    threads::THREADS,
-};
 pub use self::{
+    idle::stats as idle_stats,
+    load::{load, runnable, sample as load_sample},
+    sched_trace::{drain as sched_trace_drain, set_enabled as sched_trace_set_enabled},
     thread::{Thread, ThreadPriority, ThreadState, WaitingContext},
     wait_queue::WaitQueue,
 };
+use self::{
+    scheduler::SCHEDULER,
+    thread::{
+        add_ticks, load_segments, set_yield_target, syscall_clear, take_yield_target, update_state,
+        WaitQueueRef, WaitingData,
+    },
+    threads::THREADS,
+};
 
 use super::process::Process;
-use crate::{interrupts::Exception, memory::VirtAddr, user::listener};
+use crate::logging::debug;
+use crate::{gdt, interrupts::Exception, memory::VirtAddr, user::listener};
+use syscalls::{ReadyLatency, SchedEventType, WaitCause};
 
 pub fn create(
     name: Option<&str>,
@@ -45,6 +57,7 @@ pub fn create(
 
     assert!(thread.state().is_ready());
     SCHEDULER.add(thread.clone());
+    park_if_suspended(&thread);
 
     thread
 }
@@ -67,6 +80,10 @@ pub fn initial_setup_thread() {
     let address_space = new_process.address_space().write();
     unsafe { crate::memory::set_current_address_space(&address_space) };
 
+    for range in new_process.io_ports().iter() {
+        gdt::set_io_ports(range.clone(), true);
+    }
+
     unsafe { thread::load(&new_thread) };
 
     let mut current = CURRENT_THREAD.write();
@@ -85,6 +102,13 @@ pub fn current_thread() -> Arc<Thread> {
     current.as_ref().expect("No current thread").clone()
 }
 
+/// Obtain the current executing thread if any, without blocking
+///
+/// Note: used by the logger, which may be called while the current thread is switched
+pub fn try_current_thread() -> Option<Arc<Thread>> {
+    CURRENT_THREAD.try_read()?.as_ref().cloned()
+}
+
 fn context_switch(new_thread: Arc<Thread>) {
     assert!(new_thread.state().is_ready());
 
@@ -97,12 +121,29 @@ fn context_switch(new_thread: Arc<Thread>) {
         return;
     }
 
+    sched_trace::record(
+        SchedEventType::Switch,
+        new_thread.id(),
+        old_thread.id(),
+        (WaitCause::None, 0),
+    );
+
+    if old_thread.priority() == ThreadPriority::Idle {
+        idle::exit();
+    }
+
+    if new_thread.priority() == ThreadPriority::Idle {
+        idle::enter();
+    }
+
     unsafe { thread::save(old_thread) };
 
     let new_process = new_thread.process();
     if !Arc::ptr_eq(old_thread.process(), new_process) {
         let address_space = new_process.address_space().read();
         unsafe { crate::memory::set_current_address_space(&address_space) };
+
+        switch_io_ports(old_thread.process(), new_process);
     }
 
     unsafe { thread::load(&new_thread) };
@@ -111,6 +152,17 @@ fn context_switch(new_thread: Arc<Thread>) {
     *current = Some(new_thread);
 }
 
+/// Load the io-ports granted to the new process in the TSS bitmap, in place of the ones of the old process
+fn switch_io_ports(old_process: &Process, new_process: &Process) {
+    for range in old_process.io_ports().iter() {
+        gdt::set_io_ports(range.clone(), false);
+    }
+
+    for range in new_process.io_ports().iter() {
+        gdt::set_io_ports(range.clone(), true);
+    }
+}
+
 /// Add the thread to the specified wait queues
 pub fn thread_sleep<Context: WaitingContext + 'static>(
     thread: &Arc<Thread>,
@@ -121,6 +173,8 @@ pub fn thread_sleep<Context: WaitingContext + 'static>(
 
     match *thread.state() {
         ThreadState::Executing => {
+            directed_yield(thread);
+
             // Note: syscall must be async
             context_switch(SCHEDULER.schedule());
         }
@@ -135,6 +189,8 @@ pub fn thread_sleep<Context: WaitingContext + 'static>(
     let mut set: HashSet<WaitQueueRef> = HashSet::new();
 
     for wait_queue in wait_queues {
+        sched_trace::record(SchedEventType::Sleep, thread.id(), 0, wait_queue.cause());
+
         set.insert(wait_queue.into());
         wait_queue.add(thread.clone());
     }
@@ -142,6 +198,37 @@ pub fn thread_sleep<Context: WaitingContext + 'static>(
     update_state(thread, ThreadState::Waiting(WaitingData::new(context, set)));
 }
 
+/// Record that the current thread woke up `target` with a message (eg: a request to a server)
+///
+/// If the current thread blocks before the end of its time slice (eg: waiting for the reply),
+/// `target` runs next, before the other ready threads of its priority.
+/// This shortens the round-trip of synchronous requests, especially along chains of servers.
+pub fn directed_yield_hint(target: &Arc<Thread>) {
+    if let Some(current) = try_current_thread() {
+        set_yield_target(&current, target);
+    }
+}
+
+/// Apply the directed yield hint of the blocking thread
+fn directed_yield(thread: &Arc<Thread>) {
+    let Some(target) = take_yield_target(thread) else {
+        return;
+    };
+
+    // It may already have run, or be waiting again
+    if !target.state().is_ready() {
+        return;
+    }
+
+    SCHEDULER.move_next(&target);
+    sched_trace::record(
+        SchedEventType::DirectedYield,
+        target.id(),
+        thread.id(),
+        (WaitCause::None, 0),
+    );
+}
+
 /// Terminated the given thread
 pub fn thread_terminate(thread: &Arc<Thread>) {
     match &*thread.state() {
@@ -158,7 +245,7 @@ pub fn thread_terminate(thread: &Arc<Thread>) {
                 wait_queue.remove(&thread);
             }
         }
-        ThreadState::Error(_) => {
+        ThreadState::Error(_) | ThreadState::Suspended => {
             // Nothing to do, thread is already in no queue
         }
         ThreadState::Terminated => {
@@ -176,6 +263,8 @@ pub fn thread_next() {
     // Add the current thread is the ready list and trigger the scheduler.
     // Note: the same thread may pop out if there is only one ready/executing thread
     let old_thread = current_thread();
+    // The slice is over: the hint is stale
+    take_yield_target(&old_thread);
     update_state(&old_thread, ThreadState::Ready);
     SCHEDULER.add(old_thread);
 
@@ -183,10 +272,26 @@ pub fn thread_next() {
 }
 
 /// Triggered from exception handler: mark the current thread as errored
-pub fn thread_error(error: Exception) {
+pub fn thread_error(error: Exception, instruction_pointer: VirtAddr) {
     let thread = current_thread();
-
-    debug!("Thread {} error: {:?}", thread.id(), error);
+    let process = thread.process();
+
+    // Logged before the switch, so that the record is attributed to the thread
+    match error {
+        Exception::PageFault(_, accessed_address) => debug!(
+            "Thread {} error: {:?} at {}, accessing {}",
+            thread.describe(),
+            error,
+            process.describe_address(instruction_pointer),
+            process.describe_address(VirtAddr::new(accessed_address as u64))
+        ),
+        _ => debug!(
+            "Thread {} error: {:?} at {}",
+            thread.describe(),
+            error,
+            process.describe_address(instruction_pointer)
+        ),
+    }
 
     context_switch(SCHEDULER.schedule());
 
@@ -201,11 +306,74 @@ pub fn thread_resume(thread: &Arc<Thread>) {
     // Set it ready
     update_state(&thread, ThreadState::Ready);
     SCHEDULER.add(thread.clone());
+    park_if_suspended(thread);
 
     listener::notify_thread(&thread, listener::ThreadEventType::Resumed);
 }
 
-/// Wait up one thread from the wait queue
+/// Suspend the given thread, whose process has been marked as suspended
+///
+/// Ready threads leave the scheduler now.
+/// Waiting threads stay in their wait queues: they are suspended after their wakeup, once their syscall has been processed.
+/// Errored threads are suspended if they are resumed.
+///
+/// Note: on a single CPU, the target thread cannot be executing, so it is always at a safe point
+pub fn thread_suspend(thread: &Arc<Thread>) {
+    assert!(thread.process().suspended());
+
+    park_if_suspended(thread);
+}
+
+/// Put back the given suspended thread in the scheduler, after its process has been resumed
+pub fn thread_unsuspend(thread: &Arc<Thread>) {
+    assert!(!thread.process().suspended());
+
+    if thread.state().is_suspended() {
+        update_state(thread, ThreadState::Ready);
+        SCHEDULER.add(thread.clone());
+    }
+}
+
+/// Remove the ready thread from the scheduler if its process is suspended
+fn park_if_suspended(thread: &Arc<Thread>) {
+    if thread.process().suspended() && thread.state().is_ready() {
+        SCHEDULER.remove(thread);
+        update_state(thread, ThreadState::Suspended);
+    }
+}
+
+/// Poll `ready` a bounded number of times before blocking on the wait queues
+///
+/// On short critical sections (eg: ping-pong IPC), the object may be released before a context switch would be done.
+/// The number of polls is the largest spin budget of the queues (see `WaitQueue`).
+///
+/// Note: on a single CPU, nothing can release the object while we spin (syscalls run with interrupts disabled):
+/// failed spins halve the budget, so it only stays up if waits keep being short.
+///
+/// returns: true if `ready` returned true, false if the thread has to block
+pub fn wait_queue_spin<F: FnMut() -> bool>(wait_queues: &[Arc<WaitQueue>], mut ready: F) -> bool {
+    let budget = wait_queues
+        .iter()
+        .map(|wait_queue| wait_queue.spin_budget())
+        .max()
+        .unwrap_or(0);
+
+    for _ in 0..budget {
+        hint::spin_loop();
+
+        if ready() {
+            return true;
+        }
+    }
+
+    for wait_queue in wait_queues {
+        wait_queue.spin_failed();
+    }
+
+    false
+}
+
+/// Wake up the thread which has been waiting the longest on the wait queue
 ///
 /// returns: true if OK, false if the wait_queue was empty
 pub fn wait_queue_wake_one(wait_queue: &Arc<WaitQueue>) -> bool {
@@ -214,6 +382,36 @@ pub fn wait_queue_wake_one(wait_queue: &Arc<WaitQueue>) -> bool {
         None => return false,
     };
 
+    wake_thread(wait_queue, thread, false);
+
+    true
+}
+
+/// Wake up `target` if it waits on the wait queue, and make it the next one to run at its priority
+///
+/// This is the direct handoff of a message to a receiver of the same process (eg: reactor self-notification):
+/// only this thread is woken, since the event has been given to it.
+///
+/// returns: true if OK, false if the thread was not waiting on the queue
+pub fn wait_queue_handoff(wait_queue: &Arc<WaitQueue>, target: &Arc<Thread>) -> bool {
+    let thread = match wait_queue.wake_matching(|thread| thread.id() == target.id()) {
+        Some(thread) => thread,
+        None => return false,
+    };
+
+    wake_thread(wait_queue, thread, true);
+
+    true
+}
+
+/// Wake up a thread which has been removed from `wait_queue`
+fn wake_thread(wait_queue: &Arc<WaitQueue>, thread: Arc<Thread>, run_next: bool) {
+    let waker = CURRENT_THREAD
+        .read()
+        .as_ref()
+        .map_or(0, |current| current.id());
+    sched_trace::record(SchedEventType::Wake, thread.id(), waker, wait_queue.cause());
+
     let wait_context = {
         let state = thread.state();
         let data = state.is_waiting().expect("thread not waiting");
@@ -231,17 +429,48 @@ pub fn wait_queue_wake_one(wait_queue: &Arc<WaitQueue>) -> bool {
 
     // Set it ready
     update_state(&thread, ThreadState::Ready);
-    SCHEDULER.add(thread);
+    if run_next {
+        SCHEDULER.add_next(thread.clone());
+    } else {
+        SCHEDULER.add(thread.clone());
+    }
 
     // Resume it
     wait_context.wakeup(wait_queue);
 
-    return true;
+    // The syscall has been processed, it is now at a safe point
+    park_if_suspended(&thread);
+}
+
+/// Wake up all threads from the wait queue, in FIFO order
+///
+/// Only the threads waiting at call time are woken: a woken thread which waits again on the queue
+/// (eg: it lost the race for a message) goes to the back of the queue, and is not woken again by this call.
+/// As all receivers of a port are woken on each message, losers are served first on the next one: no waiter starves.
+///
+/// Returns the first woken thread (the one which has been waiting the longest), if any.
+pub fn wait_queue_wake_all(wait_queue: &Arc<WaitQueue>) -> Option<Arc<Thread>> {
+    let count = wait_queue.len();
+    let mut first = None;
+
+    for _ in 0..count {
+        let Some(thread) = wait_queue.wake() else {
+            break;
+        };
+
+        if first.is_none() {
+            first = Some(thread.clone());
+        }
+
+        wake_thread(wait_queue, thread, false);
+    }
+
+    first
 }
 
-/// Wait up all threads from the wait queue
-pub fn wait_queue_wake_all(wait_queue: &Arc<WaitQueue>) {
-    while wait_queue_wake_one(wait_queue) {}
+/// Get the time spent by threads in the ready list, by priority (highest first, idle excluded)
+pub fn ready_latencies() -> [ReadyLatency; 6] {
+    SCHEDULER.ready_latencies()
 }
 
 /// Set the thread priority
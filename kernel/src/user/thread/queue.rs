@@ -56,7 +56,9 @@ impl Into<Box<Node>> for NodePtr {
 
 unsafe impl Sync for NodePtr {}
 
-/// Queue implementation with thread fast removal
+/// FIFO queue implementation with thread fast removal
+///
+/// Threads are added at the head and popped from the tail.
 #[derive(Debug)]
 pub struct Queue {
     head: NodePtr,
@@ -106,6 +108,48 @@ impl Queue {
         self.map.insert(id, new_node_ptr);
     }
 
+    /// Add a new thread to this queue, so that it is the next one to pop
+    pub fn add_next(&mut self, thread: Arc<Thread>) {
+        let id = thread.id();
+        assert!(!self.map.contains_key(&id));
+
+        let new_node = Box::new(Node {
+            prev: self.tail,
+            next: NodePtr::null(),
+            thread,
+        });
+
+        // Move the node out of Box.
+        // It will get back in at deletion time
+        let new_node_ptr = NodePtr::from(new_node);
+
+        if let Some(tail) = self.tail.as_mut_ref() {
+            tail.next = new_node_ptr;
+        } else {
+            // no node in the queue, add head too
+            self.head = new_node_ptr;
+        }
+
+        self.tail = new_node_ptr;
+
+        self.map.insert(id, new_node_ptr);
+    }
+
+    /// Find the first thread (in pop order) matching the predicate
+    pub fn find<F: Fn(&Thread) -> bool>(&self, predicate: F) -> Option<Arc<Thread>> {
+        let mut node_ptr = self.tail;
+
+        while let Some(node) = node_ptr.as_ref() {
+            if predicate(&node.thread) {
+                return Some(node.thread.clone());
+            }
+
+            node_ptr = node.prev;
+        }
+
+        None
+    }
+
     /// Remove a thread from the queue
     pub fn remove(&mut self, thread: &Arc<Thread>) -> bool {
         let id = thread.id();
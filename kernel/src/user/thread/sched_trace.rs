@@ -0,0 +1,63 @@
+use core::sync::atomic::{AtomicBool, Ordering};
+
+use alloc::collections::VecDeque;
+use spin::Mutex;
+use syscalls::{SchedEvent, SchedEventType, WaitCause};
+
+/// Tracing is disabled by default, so that scheduling paths only pay an atomic load
+static ENABLED: AtomicBool = AtomicBool::new(false);
+static EVENTS: Mutex<VecDeque<SchedEvent>> = Mutex::new(VecDeque::new());
+
+/// Enable or disable the scheduler tracing
+///
+/// Note: pending events are dropped on disable
+pub fn set_enabled(value: bool) {
+    ENABLED.store(value, Ordering::Relaxed);
+
+    if !value {
+        let mut events = EVENTS.lock();
+        events.clear();
+        events.shrink_to_fit();
+    }
+}
+
+/// Record a scheduler event, if tracing is enabled
+pub fn record(
+    r#type: SchedEventType,
+    tid: u64,
+    other_tid: u64,
+    (cause, cause_id): (WaitCause, u64),
+) {
+    if !ENABLED.load(Ordering::Relaxed) {
+        return;
+    }
+
+    let mut events = EVENTS.lock();
+
+    if events.len() == SchedEvent::BUFFER_SIZE {
+        events.pop_front();
+    }
+
+    events.push_back(SchedEvent {
+        timestamp: unsafe { core::arch::x86_64::_rdtsc() },
+        r#type,
+        tid,
+        other_tid,
+        cause,
+        cause_id,
+    });
+}
+
+/// Move the oldest events into `dest`
+///
+/// Returns the number of events written
+pub fn drain(dest: &mut [SchedEvent]) -> usize {
+    let mut events = EVENTS.lock();
+    let count = dest.len().min(events.len());
+
+    for (slot, event) in dest.iter_mut().zip(events.drain(..count)) {
+        *slot = event;
+    }
+
+    count
+}
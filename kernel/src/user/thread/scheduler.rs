@@ -1,10 +1,16 @@
+use core::arch::x86_64::_rdtsc;
+
 use lazy_static::lazy_static;
 
 use alloc::sync::Arc;
-use spin::RwLock;
-use syscalls::ThreadPriority;
+use spin::{Mutex, RwLock};
+use syscalls::{ReadyLatency, ThreadPriority};
 
-use super::{queue::Queue, Thread};
+use super::{
+    queue::Queue,
+    thread::{ready_since, set_ready_since},
+    Thread,
+};
 
 lazy_static! {
     pub static ref SCHEDULER: Scheduler = Scheduler::new();
@@ -13,6 +19,8 @@ lazy_static! {
 #[derive(Debug)]
 pub struct Scheduler {
     ready_list: RwLock<[Queue; 7]>,
+    /// Time spent in the ready list, by priority (same index as `ready_list`), idle threads excluded
+    ready_latencies: Mutex<[ReadyLatency; 6]>,
 }
 
 impl Scheduler {
@@ -27,6 +35,14 @@ impl Scheduler {
                 Queue::new(),
                 Queue::new(),
             ]),
+            ready_latencies: Mutex::new([
+                ReadyLatency::new(ThreadPriority::TimeCritical),
+                ReadyLatency::new(ThreadPriority::Highest),
+                ReadyLatency::new(ThreadPriority::AboveNormal),
+                ReadyLatency::new(ThreadPriority::Normal),
+                ReadyLatency::new(ThreadPriority::BelowNormal),
+                ReadyLatency::new(ThreadPriority::Lowest),
+            ]),
         }
     }
 
@@ -38,12 +54,37 @@ impl Scheduler {
     /// Add a new thread to the ready list
     pub fn add(&self, thread: Arc<Thread>) {
         assert!(thread.state().is_ready());
+        set_ready_since(&thread, unsafe { _rdtsc() });
 
         let mut ready_list = self.ready_list.write();
         let list = &mut ready_list[Self::index(thread.priority())];
         list.add(thread);
     }
 
+    /// Add a new thread to the ready list, so that it runs before other threads of the same priority
+    pub fn add_next(&self, thread: Arc<Thread>) {
+        assert!(thread.state().is_ready());
+        set_ready_since(&thread, unsafe { _rdtsc() });
+
+        let mut ready_list = self.ready_list.write();
+        let list = &mut ready_list[Self::index(thread.priority())];
+        list.add_next(thread);
+    }
+
+    /// Move a thread of the ready list, so that it runs before other threads of the same priority
+    ///
+    /// It keeps the time at which it became ready: its ready latency covers its whole wait.
+    pub fn move_next(&self, thread: &Arc<Thread>) {
+        let mut ready_list = self.ready_list.write();
+        let list = &mut ready_list[Self::index(thread.priority())];
+        assert!(
+            list.remove(thread),
+            "thread {} not found in scheduler ready list",
+            thread.id()
+        );
+        list.add_next(thread.clone());
+    }
+
     /// Remove a thread from the ready list
     pub fn remove(&self, thread: &Arc<Thread>) {
         let mut ready_list = self.ready_list.write();
@@ -55,17 +96,39 @@ impl Scheduler {
         );
     }
 
+    /// Get the number of threads in the ready list, idle threads excluded
+    pub fn ready_count(&self) -> usize {
+        let ready_list = self.ready_list.read();
+        let idle = Self::index(ThreadPriority::Idle);
+
+        ready_list
+            .iter()
+            .enumerate()
+            .filter(|(index, _)| *index != idle)
+            .map(|(_, list)| list.len())
+            .sum()
+    }
+
     /// Decide which thread should be next executed, and pop it out of the ready list
     pub fn schedule(&self) -> Arc<Thread> {
         let mut ready_list = self.ready_list.write();
 
         // Hightest priority first
-        for list in ready_list.iter_mut() {
+        for (index, list) in ready_list.iter_mut().enumerate() {
             if let Some(thread) = list.pop() {
+                if let Some(latency) = self.ready_latencies.lock().get_mut(index) {
+                    latency.add(unsafe { _rdtsc() } - ready_since(&thread));
+                }
+
                 return thread;
             }
         }
 
         panic!("Ready list empty !");
     }
+
+    /// Get the ready latency histograms, by priority (highest first)
+    pub fn ready_latencies(&self) -> [ReadyLatency; 6] {
+        *self.ready_latencies.lock()
+    }
 }
@@ -4,26 +4,28 @@ use core::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
 use core::{fmt, mem};
 
 use alloc::boxed::Box;
-use alloc::string::String;
 use alloc::sync::{Arc, Weak};
 use hashbrown::HashSet;
-use log::debug;
 use spin::{Mutex, RwLock, RwLockReadGuard};
-use syscalls::Error;
 pub use syscalls::ThreadPriority;
+use syscalls::{Error, WATCHPOINT_COUNT};
 use x86_64::registers::rflags::RFlags;
 
 use crate::gdt::{
     KERNEL_CODE_SELECTOR, KERNEL_DATA_SELECTOR, USER_CODE_SELECTOR, USER_DATA_SELECTOR,
 };
 use crate::interrupts::{
-    tls_reg_read, tls_reg_write, Exception, InterruptStack, SyscallArgs, USERLAND_RFLAGS,
+    tls_reg_read, tls_reg_write, watchpoints_write, Exception, InterruptStack, SyscallArgs,
+    Watchpoint, USERLAND_RFLAGS,
 };
+use crate::logging::debug;
 use crate::memory::{is_userspace, VirtAddr};
 use crate::user::{
     error::invalid_argument,
+    id_gen::IdGen,
     listener,
     process::{process_remove_thread, Process},
+    strings::{self, Interned},
     syscalls::SyscallExecutor,
 };
 
@@ -77,6 +79,26 @@ pub fn add_ticks(thread: &Arc<Thread>, ticks: usize) {
     thread.add_ticks(ticks);
 }
 
+/// Record the time (TSC value) at which the thread is added to the ready list
+pub fn set_ready_since(thread: &Arc<Thread>, tsc: u64) {
+    thread.ready_since.store(tsc, Ordering::Relaxed);
+}
+
+/// Get the time (TSC value) at which the thread has been added to the ready list
+pub fn ready_since(thread: &Arc<Thread>) -> u64 {
+    thread.ready_since.load(Ordering::Relaxed)
+}
+
+/// Set the thread to run next if `thread` blocks
+pub fn set_yield_target(thread: &Arc<Thread>, target: &Arc<Thread>) {
+    *thread.yield_target.lock() = Some(Arc::downgrade(target));
+}
+
+/// Get and clear the thread to run next if `thread` blocks
+pub fn take_yield_target(thread: &Arc<Thread>) -> Option<Arc<Thread>> {
+    thread.yield_target.lock().take()?.upgrade()
+}
+
 // Unconditionaly clear the current syscall executor if any
 pub fn syscall_clear(thread: &Arc<Thread>) {
     thread.syscall_clear();
@@ -96,11 +118,13 @@ pub unsafe fn load_segments(thread: &Arc<Thread>) {
     ThreadContext::load_segments(thread.privileged);
 }
 
+static CORRELATION_IDS: IdGen = IdGen::new();
+
 /// Thread of execution
 #[derive(Debug)]
 pub struct Thread {
     id: u64,
-    name: RwLock<Option<String>>,
+    name: RwLock<Option<Interned>>,
     process: Arc<Process>,
     privileged: bool,
     priority: AtomicU64,
@@ -108,6 +132,12 @@ pub struct Thread {
     context: Mutex<ThreadContext>,
     syscall: Mutex<Option<Arc<SyscallExecutor>>>,
     ticks: AtomicUsize,
+    correlation: AtomicU64,
+    deadline: AtomicU64,
+    /// TSC value at which the thread has been added to the ready list
+    ready_since: AtomicU64,
+    /// Thread woken by a message sent by this thread: it runs next if this thread blocks (directed yield)
+    yield_target: Mutex<Option<Weak<Thread>>>,
 }
 
 impl Thread {
@@ -124,7 +154,7 @@ impl Thread {
     ) -> Arc<Self> {
         let thread = Arc::new(Self {
             id,
-            name: RwLock::new(name.map(String::from)),
+            name: RwLock::new(name.map(strings::intern)),
             process,
             privileged,
             priority: AtomicU64::new(priority as u64),
@@ -132,6 +162,10 @@ impl Thread {
             context: Mutex::new(ThreadContext::new(thread_start, stack_top, arg, tls)),
             syscall: Mutex::new(None),
             ticks: AtomicUsize::new(0),
+            correlation: AtomicU64::new(0),
+            deadline: AtomicU64::new(0),
+            ready_since: AtomicU64::new(0),
+            yield_target: Mutex::new(None),
         });
 
         debug!(
@@ -154,14 +188,22 @@ impl Thread {
     }
 
     /// Get the thread name
-    pub fn name<'a>(&'a self) -> RwLockReadGuard<'a, Option<String>> {
+    pub fn name(&self) -> RwLockReadGuard<'_, Option<Interned>> {
         self.name.read()
     }
 
     /// Set the thread name
     pub fn set_name(&self, value: Option<&str>) {
+        let value = value.map(strings::intern);
         let mut name = self.name.write();
-        *name = value.map(String::from);
+        *name = value;
+    }
+
+    /// Describe the thread for logs, with its name and its process
+    ///
+    /// Raw ids are hard to match with servers, especially during boot when many threads start together.
+    pub fn describe(&self) -> Describe<'_> {
+        Describe(self)
     }
 
     /// Get the process the threaad belong to
@@ -194,6 +236,33 @@ impl Thread {
         self.ticks.load(Ordering::Relaxed)
     }
 
+    /// Get the correlation id of the request the thread is currently working on (0 if none)
+    pub fn correlation(&self) -> u64 {
+        self.correlation.load(Ordering::Relaxed)
+    }
+
+    /// Set the correlation id of the request the thread is currently working on
+    pub fn set_correlation(&self, correlation: u64) {
+        self.correlation.store(correlation, Ordering::Relaxed);
+    }
+
+    /// Start a new request: generate a new correlation id and set it as current
+    pub fn new_correlation(&self) -> u64 {
+        let correlation = CORRELATION_IDS.generate();
+        self.set_correlation(correlation);
+        correlation
+    }
+
+    /// Get the deadline of the request the thread is currently working on, in nanoseconds since boot (0 if none)
+    pub fn deadline(&self) -> u64 {
+        self.deadline.load(Ordering::Relaxed)
+    }
+
+    /// Set the deadline of the request the thread is currently working on
+    pub fn set_deadline(&self, deadline: u64) {
+        self.deadline.store(deadline, Ordering::Relaxed);
+    }
+
     /// Add CPU ticks
     fn add_ticks(&self, ticks: usize) {
         self.ticks.fetch_add(ticks, Ordering::Relaxed);
@@ -217,7 +286,10 @@ impl Thread {
             match *state {
                 ThreadState::Executing => true,
                 ThreadState::Ready => false,
-                ThreadState::Waiting(_) | ThreadState::Error(_) | ThreadState::Terminated => {
+                ThreadState::Waiting(_)
+                | ThreadState::Error(_)
+                | ThreadState::Terminated
+                | ThreadState::Suspended => {
                     panic!("Bad thread state to exit syscall: {:?}", *state)
                 }
             }
@@ -273,6 +345,32 @@ impl Thread {
 
         Ok(())
     }
+
+    /// Set or clear (if `None`) an hardware watchpoint of the thread
+    ///
+    /// Note: it is applied the next time the thread is scheduled
+    pub fn set_watchpoint(&self, index: usize, watchpoint: Option<Watchpoint>) {
+        assert!(!self.state().is_executing());
+
+        let mut context = self.context.lock();
+        context.watchpoints[index] = watchpoint;
+    }
+}
+
+/// Thread description for logs: `12 'name' (process 3 'name')`
+pub struct Describe<'a>(&'a Thread);
+
+impl fmt::Display for Describe<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.0.id)?;
+
+        if let Some(name) = &*self.0.name() {
+            write!(f, " '{}'", name)?;
+        }
+
+        let process = self.0.process();
+        write!(f, " (process {} '{}')", process.id(), process.name())
+    }
 }
 
 impl Drop for Thread {
@@ -306,6 +404,11 @@ pub enum ThreadState {
 
     /// This thread has been terminated
     Terminated,
+
+    /// This thread is ready, but its process is suspended
+    ///
+    /// It is not in the scheduler until its process is resumed.
+    Suspended,
 }
 
 /// Data associated with the wait state of a thread
@@ -434,8 +537,25 @@ impl ThreadState {
             false
         }
     }
+
+    pub fn is_suspended(&self) -> bool {
+        matches!(self, ThreadState::Suspended)
+    }
 }
 
+/// CPU flags a supervisor can update: the ones userland can change itself, and single-step
+const USER_CPU_FLAGS: RFlags = RFlags::CARRY_FLAG
+    .union(RFlags::PARITY_FLAG)
+    .union(RFlags::AUXILIARY_CARRY_FLAG)
+    .union(RFlags::ZERO_FLAG)
+    .union(RFlags::SIGN_FLAG)
+    .union(RFlags::TRAP_FLAG)
+    .union(RFlags::DIRECTION_FLAG)
+    .union(RFlags::OVERFLOW_FLAG);
+
+/// RFLAGS bit 1 is reserved and always set by the CPU: contexts saved from userland have it, the initial ones do not
+const RFLAGS_RESERVED_ONE: u64 = 1 << 1;
+
 /// Saved context of the thread.
 struct ThreadContext {
     rax: usize,
@@ -464,6 +584,9 @@ struct ThreadContext {
 
     // FS base value (used for TLS)
     tls: VirtAddr,
+
+    /// Hardware watchpoints (debug registers)
+    watchpoints: [Option<Watchpoint>; WATCHPOINT_COUNT],
 }
 
 impl ThreadContext {
@@ -493,6 +616,7 @@ impl ThreadContext {
             instruction_pointer: thread_start,
             cpu_flags: USERLAND_RFLAGS,
             tls,
+            watchpoints: [None; WATCHPOINT_COUNT],
         }
     }
 
@@ -554,6 +678,7 @@ impl ThreadContext {
         }
 
         tls_reg_write(self.tls);
+        watchpoints_write(&self.watchpoints);
     }
 
     /// Setup the interrupt stack with the right segments.
@@ -602,8 +727,9 @@ impl ThreadContext {
                 is_userspace(addr)
             }
             syscalls::ThreadContextRegister::CpuFlags => {
-                // Cannot change CPU Flags for now
-                false
+                // Only the flags that userland can change itself, and single-step
+                let changed = self.cpu_flags.bits() ^ value as u64;
+                changed & !(USER_CPU_FLAGS.bits() | RFLAGS_RESERVED_ONE) == 0
             }
             syscalls::ThreadContextRegister::TLS => {
                 let addr = VirtAddr::new(value as u64);
@@ -667,7 +793,7 @@ impl ThreadContext {
                 self.instruction_pointer = VirtAddr::new(value as u64);
             }
             syscalls::ThreadContextRegister::CpuFlags => {
-                panic!("Cannot update CPU Flags from user");
+                self.cpu_flags = RFlags::from_bits_retain(value as u64);
             }
             syscalls::ThreadContextRegister::TLS => {
                 self.tls = VirtAddr::new(value as u64);
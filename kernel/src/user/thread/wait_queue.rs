@@ -1,21 +1,82 @@
+use core::sync::atomic::{AtomicU32, Ordering};
+
 use alloc::sync::Arc;
 use spin::RwLock;
+use syscalls::WaitCause;
 
 use super::{queue::Queue, Thread};
 
+/// Queue of threads waiting on an object
+///
+/// Wake order is FIFO: the thread which has been waiting the longest is woken first.
+/// A thread waiting on several queues has its own position in each of them, from the time it started to wait.
+///
+/// Woken threads are added to the ready list in wake order, so among threads of the same priority,
+/// the oldest waiter runs first. The only exception is a handoff (see `thread::wait_queue_handoff`),
+/// which runs the woken thread before the other ready threads of its priority.
+///
+/// Before blocking, a thread may poll the object a bounded number of times (see `thread::wait_queue_spin`).
+/// The spin budget of the queue is tuned from the last waits: it grows when a wait was short enough to be spun instead,
+/// and is halved each time a spin fails.
 #[derive(Debug)]
 pub struct WaitQueue {
     queue: RwLock<Queue>,
+    cause: WaitCause,
+    cause_id: u64,
+    spin_budget: AtomicU32,
 }
 
+/// Maximum number of polls before blocking, by object type
+fn spin_limit(cause: WaitCause) -> u32 {
+    match cause {
+        WaitCause::None => 0,
+        WaitCause::Port => 256,
+    }
+}
+
+/// Time stamp counter ticks of one poll, used to tell if a wait could have been spun instead
+const SPIN_POLL_TICKS: u64 = 100;
+
 impl WaitQueue {
-    pub fn new() -> Self {
+    /// Create a new wait queue, attached to the given object (used for tracing)
+    pub fn new(cause: WaitCause, cause_id: u64) -> Self {
         Self {
             queue: RwLock::new(Queue::new()),
+            cause,
+            cause_id,
+            spin_budget: AtomicU32::new(0),
+        }
+    }
+
+    /// Get the object this wait queue is attached to
+    pub fn cause(&self) -> (WaitCause, u64) {
+        (self.cause, self.cause_id)
+    }
+
+    /// Get the number of polls to do before blocking on this queue
+    pub fn spin_budget(&self) -> u32 {
+        self.spin_budget.load(Ordering::Relaxed)
+    }
+
+    /// Adjust the spin budget after a failed spin: the object is held longer than the spin lasts
+    pub fn spin_failed(&self) {
+        let budget = self.spin_budget();
+        self.spin_budget.store(budget / 2, Ordering::Relaxed);
+    }
+
+    /// Record the duration of a wait which ended by a wake on this queue
+    ///
+    /// If spinning could have covered it, the spin budget grows up to the limit of the object type.
+    pub fn record_wait(&self, ticks: u64) {
+        let polls = ticks / SPIN_POLL_TICKS + 1;
+        let limit = spin_limit(self.cause);
+
+        if polls <= limit as u64 {
+            self.spin_budget.fetch_max(polls as u32, Ordering::Relaxed);
         }
     }
 
-    /// Add a new thread to this wait queue
+    /// Add a new thread at the back of this wait queue
     pub fn add(&self, thread: Arc<Thread>) {
         let mut queue = self.queue.write();
         queue.add(thread);
@@ -31,12 +92,20 @@ impl WaitQueue {
         );
     }
 
-    /// Wake up a thread to this wait queue
+    /// Wake up the thread which has been waiting the longest
     pub fn wake(&self) -> Option<Arc<Thread>> {
         let mut queue = self.queue.write();
         queue.pop()
     }
 
+    /// Wake up the thread which has been waiting the longest, among the ones matching the predicate
+    pub fn wake_matching<F: Fn(&Thread) -> bool>(&self, predicate: F) -> Option<Arc<Thread>> {
+        let mut queue = self.queue.write();
+        let thread = queue.find(predicate)?;
+        queue.remove(&thread);
+        Some(thread)
+    }
+
     /// Get the number of waiting threads in this queue
     pub fn len(&self) -> usize {
         let queue = self.queue.read();
@@ -0,0 +1,332 @@
+use core::{
+    arch::x86_64::_rdtsc,
+    sync::atomic::{AtomicU64, Ordering},
+};
+
+use alloc::{
+    collections::BTreeMap,
+    sync::{Arc, Weak},
+    vec::Vec,
+};
+use spin::Mutex;
+use syscalls::{TimerEvent, TimerStats, TICK_NS};
+
+use crate::logging::debug;
+
+use super::{ipc::PortSender, listener::MessageBuilder, process::QuotaCharge};
+
+static TICKS: AtomicU64 = AtomicU64::new(0);
+static WAKEUPS: AtomicU64 = AtomicU64::new(0);
+static FIRED: AtomicU64 = AtomicU64::new(0);
+static COALESCED: AtomicU64 = AtomicU64::new(0);
+static TIMERS: AtomicU64 = AtomicU64::new(0);
+static PROCESSING_TICKS: AtomicU64 = AtomicU64::new(0);
+static PROCESSING_MAX_TICKS: AtomicU64 = AtomicU64::new(0);
+
+static NEXT_ID: AtomicU64 = AtomicU64::new(1);
+
+/// Wall clock at boot (tick 0), in nanoseconds since the Unix epoch
+///
+/// Wall clock adjustments move it, so that the wall clock is always `BOOT_TIME + now()`, and uptime is not affected.
+static BOOT_TIME: AtomicU64 = AtomicU64::new(0);
+
+static ARMED: Mutex<Armed> = Mutex::new(Armed::new());
+
+/// Deadline of an armed timer, in ticks
+#[derive(Debug, Clone, Copy)]
+struct Deadline {
+    /// The timer may fire from this tick
+    earliest: u64,
+    /// The timer must fire at this tick
+    latest: u64,
+}
+
+/// Armed timers, ordered by deadlines
+///
+/// A tick only looks at the first entries: its cost depends on the number of timers it fires,
+/// not on the number of armed timers. Arming and cancelling are `O(log n)`.
+/// Timers remove themselves on drop, so that the index never holds dead timers.
+#[derive(Debug)]
+struct Armed {
+    /// Key: (latest, timer id), to find if a timer must fire on a tick
+    by_latest: BTreeMap<(u64, u64), ()>,
+    /// Key: (earliest, timer id), to find the timers that can fire on a tick. Value: (latest, timer)
+    by_earliest: BTreeMap<(u64, u64), (u64, Weak<Timer>)>,
+}
+
+impl Armed {
+    const fn new() -> Self {
+        Self {
+            by_latest: BTreeMap::new(),
+            by_earliest: BTreeMap::new(),
+        }
+    }
+
+    fn insert(&mut self, id: u64, deadline: Deadline, timer: Weak<Timer>) {
+        self.by_latest.insert((deadline.latest, id), ());
+        self.by_earliest
+            .insert((deadline.earliest, id), (deadline.latest, timer));
+    }
+
+    fn remove(&mut self, id: u64, deadline: Deadline) {
+        self.by_latest.remove(&(deadline.latest, id));
+        self.by_earliest.remove(&(deadline.earliest, id));
+    }
+
+    /// Get the next tick at which a timer reaches the end of its slack
+    fn next_latest(&self) -> Option<u64> {
+        self.by_latest
+            .first_key_value()
+            .map(|(&(latest, _), _)| latest)
+    }
+
+    /// Test if at least one timer reaches the end of its slack at tick `now`
+    fn must_fire(&self, now: u64) -> bool {
+        self.by_latest
+            .first_key_value()
+            .map_or(false, |(&(latest, _), _)| latest <= now)
+    }
+
+    /// Remove the next timer that can fire at tick `now`
+    fn pop_due(&mut self, now: u64) -> Option<(Weak<Timer>, Deadline)> {
+        let entry = self.by_earliest.first_entry()?;
+        let &(earliest, id) = entry.key();
+        if earliest > now {
+            return None;
+        }
+
+        let (latest, timer) = entry.remove();
+        self.by_latest.remove(&(latest, id));
+
+        Some((timer, Deadline { earliest, latest }))
+    }
+
+    fn len(&self) -> usize {
+        self.by_earliest.len()
+    }
+}
+
+/// Timer: when it fires, a `TimerEvent` is sent to its port
+///
+/// Timers are coalesced: on a tick where at least one timer reaches the end of its slack,
+/// all timers whose deadline is reached fire together, so that nearby deadlines cost a single wakeup.
+/// On other ticks, no timer fires.
+#[derive(Debug)]
+pub struct Timer {
+    id: u64,
+    port: Arc<PortSender>,
+    deadline: Mutex<Option<Deadline>>,
+    /// Released with the timer
+    _charge: QuotaCharge,
+}
+
+impl Timer {
+    pub fn new(port: Arc<PortSender>, charge: QuotaCharge) -> Arc<Self> {
+        TIMERS.fetch_add(1, Ordering::Relaxed);
+
+        Arc::new(Self {
+            id: NEXT_ID.fetch_add(1, Ordering::Relaxed),
+            port,
+            deadline: Mutex::new(None),
+            _charge: charge,
+        })
+    }
+
+    /// Arm the timer to fire after `delay` nanoseconds, or up to `slack` nanoseconds later
+    ///
+    /// If the timer is already armed, its deadline is replaced.
+    pub fn arm(self: &Arc<Self>, delay: u64, slack: u64) {
+        let now = TICKS.load(Ordering::Relaxed);
+
+        // Round up the deadline, never fire before it
+        let earliest = now + delay.div_ceil(TICK_NS).max(1);
+        // Round down the slack, never fire after it
+        let latest = earliest + slack / TICK_NS;
+
+        let mut armed = ARMED.lock();
+        let mut current = self.deadline.lock();
+
+        if let Some(previous) = current.take() {
+            armed.remove(self.id, previous);
+        }
+
+        let deadline = Deadline { earliest, latest };
+        armed.insert(self.id, deadline, Arc::downgrade(self));
+        *current = Some(deadline);
+    }
+
+    /// Cancel the timer, if it is armed
+    pub fn cancel(self: &Arc<Self>) {
+        let mut armed = ARMED.lock();
+        let mut current = self.deadline.lock();
+
+        if let Some(previous) = current.take() {
+            armed.remove(self.id, previous);
+        }
+    }
+
+    fn fire(&self, deadline: Deadline, now: u64) {
+        let mut builder = MessageBuilder::new();
+
+        let event = builder.data_mut::<TimerEvent>();
+        event.deadline = deadline.earliest * TICK_NS;
+        event.fired = now * TICK_NS;
+
+        match self.port.kernel_send(builder.message()) {
+            Ok(()) => {}
+            Err(err) => {
+                debug!(
+                    "Failed to send TimerEvent message to port {}: {:?}",
+                    self.port.id(),
+                    err
+                );
+            }
+        }
+    }
+}
+
+impl Drop for Timer {
+    fn drop(&mut self) {
+        TIMERS.fetch_sub(1, Ordering::Relaxed);
+
+        if let Some(deadline) = self.deadline.get_mut().take() {
+            ARMED.lock().remove(self.id, deadline);
+        }
+    }
+}
+
+/// Process a tick: fire the due timers
+///
+/// Called from the timer interrupt.
+pub fn tick() {
+    let begin = unsafe { _rdtsc() };
+    let now = TICKS.fetch_add(1, Ordering::Relaxed) + 1;
+
+    let due = {
+        let mut armed = ARMED.lock();
+
+        if !armed.must_fire(now) {
+            drop(armed);
+            record_processing(begin);
+            return;
+        }
+
+        let mut due = Vec::new();
+
+        while let Some((timer, deadline)) = armed.pop_due(now) {
+            // Dropped timers remove themselves, but be defensive
+            let Some(timer) = timer.upgrade() else {
+                continue;
+            };
+
+            *timer.deadline.lock() = None;
+            due.push((timer, deadline));
+        }
+
+        due
+    };
+
+    WAKEUPS.fetch_add(1, Ordering::Relaxed);
+
+    // Send outside of the lock: it may wake up threads
+    for (timer, deadline) in due {
+        FIRED.fetch_add(1, Ordering::Relaxed);
+        if deadline.latest > now {
+            COALESCED.fetch_add(1, Ordering::Relaxed);
+        }
+
+        timer.fire(deadline, now);
+    }
+
+    record_processing(begin);
+}
+
+fn record_processing(begin: u64) {
+    let ticks = unsafe { _rdtsc() } - begin;
+
+    PROCESSING_TICKS.fetch_add(ticks, Ordering::Relaxed);
+    PROCESSING_MAX_TICKS.fetch_max(ticks, Ordering::Relaxed);
+}
+
+/// Get the number of ticks since boot
+pub fn ticks() -> u64 {
+    TICKS.load(Ordering::Relaxed)
+}
+
+/// Get the time since boot, in nanoseconds (tick resolution)
+pub fn now() -> u64 {
+    ticks() * TICK_NS
+}
+
+/// Advance a virtual clock by `ticks`, firing the timers due on the way
+///
+/// Time jumps directly to the next tick at which a timer must fire: the cost depends on the number of
+/// these ticks, not on `ticks`. Timers fire as if all the ticks had been processed (same coalescing).
+///
+/// Returns false if the kernel runs on the real clock (`virtual-clock` feature disabled).
+pub fn advance(ticks: u64) -> bool {
+    if !cfg!(feature = "virtual-clock") {
+        return false;
+    }
+
+    let target = TICKS.load(Ordering::Relaxed) + ticks;
+
+    loop {
+        let next = ARMED.lock().next_latest();
+
+        match next {
+            Some(latest) if latest <= target => {
+                // Note: armed deadlines are always after the current tick
+                TICKS.store(latest - 1, Ordering::Relaxed);
+                tick();
+            }
+            _ => {
+                TICKS.store(target, Ordering::Relaxed);
+                return true;
+            }
+        }
+    }
+}
+
+/// Setup the wall clock from the RTC timestamp (in seconds since the Unix epoch), read at boot
+pub fn init_wall_clock(timestamp: Option<u64>) {
+    let wall_clock = timestamp.unwrap_or(0) * 1_000_000_000;
+    BOOT_TIME.store(wall_clock.saturating_sub(now()), Ordering::Relaxed);
+}
+
+/// Get the wall clock at boot, in nanoseconds since the Unix epoch
+pub fn boot_time() -> u64 {
+    BOOT_TIME.load(Ordering::Relaxed)
+}
+
+/// Get the wall clock, in nanoseconds since the Unix epoch (tick resolution)
+pub fn wall_clock() -> u64 {
+    boot_time() + now()
+}
+
+/// Adjust the wall clock (eg: from a time service)
+///
+/// Returns false if the value is before boot.
+pub fn set_wall_clock(value: u64) -> bool {
+    let Some(boot_time) = value.checked_sub(now()) else {
+        return false;
+    };
+
+    BOOT_TIME.store(boot_time, Ordering::Relaxed);
+    true
+}
+
+/// Get timers statistics
+pub fn stats() -> TimerStats {
+    TimerStats {
+        ticks: TICKS.load(Ordering::Relaxed),
+        wakeups: WAKEUPS.load(Ordering::Relaxed),
+        fired: FIRED.load(Ordering::Relaxed),
+        coalesced: COALESCED.load(Ordering::Relaxed),
+        armed: ARMED.lock().len() as u64,
+        timers: TIMERS.load(Ordering::Relaxed),
+        processing_ticks: PROCESSING_TICKS.load(Ordering::Relaxed),
+        processing_max_ticks: PROCESSING_MAX_TICKS.load(Ordering::Relaxed),
+        virtual_clock: cfg!(feature = "virtual-clock"),
+    }
+}
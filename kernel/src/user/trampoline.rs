@@ -0,0 +1,98 @@
+use alloc::sync::{Arc, Weak};
+use spin::Mutex;
+use syscalls::ThreadPriority;
+
+use crate::logging::debug;
+use crate::memory::VirtAddr;
+
+use super::{
+    error::{check_is_userspace, object_closed, object_not_ready},
+    id_gen::IdGen,
+    process::Process,
+    thread::{self, Thread},
+    Error,
+};
+
+static IDS: IdGen = IdGen::new();
+
+/// Entry point of a process, authorized by it for threads created by other processes
+///
+/// The owner process fixes everything the new thread starts with (entry point, stack, TLS, priority),
+/// then sends the trampoline to a server. The server can only choose the argument passed to the entry point.
+/// This lets a server run work in the address space of its client (eg: the continuation of a blocking call)
+/// without being able to start code anywhere in it.
+///
+/// The stack is reused: only one thread created from the trampoline can live at a time.
+#[derive(Debug)]
+pub struct Trampoline {
+    id: u64,
+    process: Weak<Process>,
+    priority: ThreadPriority,
+    entry_point: VirtAddr,
+    stack_top: VirtAddr,
+    tls: VirtAddr,
+    running: Mutex<Weak<Thread>>,
+}
+
+impl Trampoline {
+    pub fn new(
+        owner: &Arc<Process>,
+        priority: ThreadPriority,
+        entry_point: VirtAddr,
+        stack_top: VirtAddr,
+        tls: VirtAddr,
+    ) -> Result<Arc<Self>, Error> {
+        let trampoline = Arc::new(Self {
+            id: IDS.generate(),
+            process: Arc::downgrade(owner),
+            priority,
+            entry_point: check_is_userspace(entry_point)?,
+            stack_top: check_is_userspace(stack_top)?,
+            tls: check_is_userspace(tls)?,
+            running: Mutex::new(Weak::new()),
+        });
+
+        debug!(
+            "Trampoline {} created by process {} (entry_point={:?})",
+            trampoline.id,
+            owner.id(),
+            trampoline.entry_point
+        );
+
+        Ok(trampoline)
+    }
+
+    /// Create a thread in the owner process, starting at the trampoline entry point with `arg`
+    ///
+    /// Fails with `ObjectNotReady` if the previous thread created from the trampoline is still alive,
+    /// and with `ObjectClosed` if the owner process is terminated.
+    pub fn spawn(&self, name: Option<&str>, arg: usize) -> Result<Arc<Thread>, Error> {
+        let process = self.process.upgrade().ok_or_else(object_closed)?;
+        if process.terminated() {
+            return Err(object_closed());
+        }
+
+        let mut running = self.running.lock();
+        if running
+            .upgrade()
+            .map_or(false, |thread| !thread.state().is_terminated())
+        {
+            return Err(object_not_ready());
+        }
+
+        let thread = thread::create(
+            name,
+            process,
+            false,
+            self.priority,
+            self.entry_point,
+            self.stack_top,
+            arg,
+            self.tls,
+        );
+
+        *running = Arc::downgrade(&thread);
+
+        Ok(thread)
+    }
+}
@@ -62,6 +62,21 @@ impl<Key: Eq + Hash + Clone + Debug, Value> WeakMap<Key, Value> {
         map.keys().map(|key| key.clone()).collect()
     }
 
+    /// List the keys of the items matching the predicate
+    ///
+    /// Note:
+    /// The map stays locked while the predicate runs
+    pub fn keys_matching<Predicate: Fn(&Arc<Value>) -> bool>(
+        &self,
+        predicate: Predicate,
+    ) -> Vec<Key> {
+        let map = self.map.read();
+        map.iter()
+            .filter(|(_, weak)| weak.upgrade().map_or(false, |value| predicate(&value)))
+            .map(|(key, _)| key.clone())
+            .collect()
+    }
+
     /// Get the number of items in the map
     pub fn len(&self) -> usize {
         let map = self.map.read();
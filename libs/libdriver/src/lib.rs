@@ -0,0 +1,6 @@
+#![no_std]
+
+pub mod mmio;
+pub mod pio;
+pub mod power;
+pub mod uart;
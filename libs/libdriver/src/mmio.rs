@@ -0,0 +1,193 @@
+//! MMIO register access
+//!
+//! A device register block is declared as a `#[repr(C)]` struct of registers matching the device layout,
+//! then laid over the mapped device memory.
+//! All accesses are volatile, and access rights are checked at compile time.
+//!
+//! ```ignore
+//! #[repr(C)]
+//! struct Registers {
+//!     control: ReadWrite<u32>,
+//!     status: ReadOnly<u32>,
+//!     _reserved: [u32; 2],
+//!     doorbell: WriteOnly<u32>,
+//! }
+//!
+//! const CONTROL_ENABLE: Field<u32> = Field::new(0, 1);
+//! const STATUS_READY: Field<u32> = Field::new(3, 1);
+//!
+//! let registers = unsafe { mmio::block::<Registers>(mapping.address()) };
+//! registers.control.write_field(CONTROL_ENABLE, 1);
+//! while registers.status.read_field(STATUS_READY) == 0 {}
+//! ```
+
+use core::{
+    cell::UnsafeCell,
+    marker::PhantomData,
+    mem::{align_of, size_of},
+    ptr,
+};
+
+/// Integer type that can be stored in a register
+pub trait RegisterValue: Copy + private::Sealed {
+    fn to_u64(self) -> u64;
+    fn from_u64(value: u64) -> Self;
+}
+
+macro_rules! register_value {
+    ($type:ty) => {
+        impl private::Sealed for $type {}
+
+        impl RegisterValue for $type {
+            fn to_u64(self) -> u64 {
+                self as u64
+            }
+
+            fn from_u64(value: u64) -> Self {
+                value as $type
+            }
+        }
+    };
+}
+
+register_value!(u8);
+register_value!(u16);
+register_value!(u32);
+register_value!(u64);
+
+mod private {
+    pub trait Sealed {}
+}
+
+/// Register access which allows reads
+pub trait Readable {}
+
+/// Register access which allows writes
+pub trait Writable {}
+
+/// Read-only access
+#[derive(Debug)]
+pub struct Read;
+
+/// Write-only access
+#[derive(Debug)]
+pub struct Write;
+
+/// Read-write access
+#[derive(Debug)]
+pub struct ReadWriteAccess;
+
+impl Readable for Read {}
+impl Writable for Write {}
+impl Readable for ReadWriteAccess {}
+impl Writable for ReadWriteAccess {}
+
+/// Read-only register
+pub type ReadOnly<T> = Register<T, Read>;
+
+/// Write-only register
+pub type WriteOnly<T> = Register<T, Write>;
+
+/// Read-write register
+pub type ReadWrite<T> = Register<T, ReadWriteAccess>;
+
+/// Device register
+///
+/// Note: it is never constructed, only laid over device memory with `block`
+#[repr(transparent)]
+pub struct Register<T: RegisterValue, Access> {
+    value: UnsafeCell<T>,
+    _access: PhantomData<Access>,
+}
+
+impl<T: RegisterValue, Access: Readable> Register<T, Access> {
+    /// Read the register value
+    pub fn read(&self) -> T {
+        unsafe { ptr::read_volatile(self.value.get()) }
+    }
+
+    /// Read a field of the register
+    pub fn read_field(&self, field: Field<T>) -> T {
+        field.get(self.read())
+    }
+}
+
+impl<T: RegisterValue, Access: Writable> Register<T, Access> {
+    /// Write the register value
+    pub fn write(&self, value: T) {
+        unsafe { ptr::write_volatile(self.value.get(), value) }
+    }
+}
+
+impl<T: RegisterValue, Access: Readable + Writable> Register<T, Access> {
+    /// Read the register, update its value, and write it back
+    ///
+    /// Note: this is not atomic regarding the device
+    pub fn modify<F: FnOnce(T) -> T>(&self, f: F) {
+        let value = self.read();
+        self.write(f(value));
+    }
+
+    /// Update a field of the register, keeping the other bits
+    pub fn write_field(&self, field: Field<T>, value: T) {
+        self.modify(|current| field.set(current, value));
+    }
+}
+
+/// Bit field inside a register
+#[derive(Debug, Clone, Copy)]
+pub struct Field<T: RegisterValue> {
+    shift: u32,
+    width: u32,
+    _type: PhantomData<T>,
+}
+
+impl<T: RegisterValue> Field<T> {
+    /// Declare a field of `width` bits, starting at bit `shift`
+    pub const fn new(shift: u32, width: u32) -> Self {
+        assert!(width > 0);
+        assert!(shift + width <= (size_of::<T>() * 8) as u32);
+
+        Self {
+            shift,
+            width,
+            _type: PhantomData,
+        }
+    }
+
+    /// Get the mask of the field, in register position
+    pub fn mask(&self) -> T {
+        T::from_u64(self.raw_mask())
+    }
+
+    /// Extract the field from a register value
+    pub fn get(&self, register: T) -> T {
+        T::from_u64((register.to_u64() & self.raw_mask()) >> self.shift)
+    }
+
+    /// Set the field in a register value
+    ///
+    /// Note: extra bits of `value` are truncated
+    pub fn set(&self, register: T, value: T) -> T {
+        let mask = self.raw_mask();
+        let value = (value.to_u64() << self.shift) & mask;
+        T::from_u64((register.to_u64() & !mask) | value)
+    }
+
+    fn raw_mask(&self) -> u64 {
+        (u64::MAX >> (u64::BITS - self.width)) << self.shift
+    }
+}
+
+/// Lay a register block over device memory
+///
+/// # Safety
+///
+/// `address` must point to mapped device memory at least as large as `T`, which stays mapped for `'a`,
+/// and `T` must only contain registers (and padding) matching the device layout.
+pub unsafe fn block<'a, T>(address: usize) -> &'a T {
+    assert!(address != 0);
+    assert!(address % align_of::<T>() == 0);
+
+    &*(address as *const T)
+}
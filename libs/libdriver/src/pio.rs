@@ -0,0 +1,133 @@
+//! Io-port register access
+//!
+//! Legacy devices, and devices which expose their registers through an index/data pair of io-ports
+//! (eg: the AHCI `IDP`), are driven with `in` and `out` instructions.
+//! The io-ports must have been granted to the process (see `Process::grant_io_ports` and `Device::claim`),
+//! else the access faults.
+//!
+//! Port accesses are compiler barriers: memory written before them (eg: DMA descriptors) is visible to the device.
+//!
+//! ```ignore
+//! let idp = unsafe { IndexData::new(resource.start as u16) };
+//! let version = idp.read(0x10);
+//! ```
+
+use core::{arch::asm, marker::PhantomData};
+
+/// Integer type that can be transferred through an io-port
+pub trait PortValue: Copy + private::Sealed {
+    /// # Safety
+    ///
+    /// The io-port must be granted to the process, and reading it must not break the device state.
+    unsafe fn read_from(port: u16) -> Self;
+
+    /// # Safety
+    ///
+    /// The io-port must be granted to the process, and writing it must not break the device state.
+    unsafe fn write_to(port: u16, value: Self);
+}
+
+macro_rules! port_value {
+    ($type:ty, $reg:tt) => {
+        impl private::Sealed for $type {}
+
+        impl PortValue for $type {
+            unsafe fn read_from(port: u16) -> Self {
+                let value: $type;
+                asm!(
+                    concat!("in ", $reg, ", dx"),
+                    out($reg) value,
+                    in("dx") port,
+                    options(nostack, preserves_flags)
+                );
+                value
+            }
+
+            unsafe fn write_to(port: u16, value: Self) {
+                asm!(
+                    concat!("out dx, ", $reg),
+                    in("dx") port,
+                    in($reg) value,
+                    options(nostack, preserves_flags)
+                );
+            }
+        }
+    };
+}
+
+port_value!(u8, "al");
+port_value!(u16, "ax");
+port_value!(u32, "eax");
+
+mod private {
+    pub trait Sealed {}
+}
+
+/// Io-port
+#[derive(Debug, Clone, Copy)]
+pub struct IoPort<T: PortValue> {
+    port: u16,
+    _type: PhantomData<T>,
+}
+
+impl<T: PortValue> IoPort<T> {
+    /// # Safety
+    ///
+    /// The io-port must be granted to the process, and only accessed in ways the device supports.
+    pub const unsafe fn new(port: u16) -> Self {
+        Self {
+            port,
+            _type: PhantomData,
+        }
+    }
+
+    /// Read the port
+    pub fn read(&self) -> T {
+        unsafe { T::read_from(self.port) }
+    }
+
+    /// Write the port
+    pub fn write(&self, value: T) {
+        unsafe { T::write_to(self.port, value) }
+    }
+}
+
+/// Index/data pair: a 32 bits register block accessed by writing the register offset to the index port,
+/// then accessing the data port (at index + 4)
+///
+/// Note: an access takes two port operations, the caller must serialize the accesses of its threads.
+#[derive(Debug)]
+pub struct IndexData {
+    index: IoPort<u32>,
+    data: IoPort<u32>,
+}
+
+impl IndexData {
+    /// # Safety
+    ///
+    /// The 8 io-ports from `base` must be granted to the process, and be an index/data pair.
+    pub const unsafe fn new(base: u16) -> Self {
+        Self {
+            index: IoPort::new(base),
+            data: IoPort::new(base + 4),
+        }
+    }
+
+    /// Read the register at `offset`
+    pub fn read(&self, offset: u32) -> u32 {
+        self.index.write(offset);
+        self.data.read()
+    }
+
+    /// Write the register at `offset`
+    pub fn write(&self, offset: u32, value: u32) {
+        self.index.write(offset);
+        self.data.write(value);
+    }
+
+    /// Read the register at `offset`, update its value, and write it back
+    pub fn modify<F: FnOnce(u32) -> u32>(&self, offset: u32, f: F) {
+        let value = self.read(offset);
+        self.write(offset, f(value));
+    }
+}
@@ -0,0 +1,58 @@
+//! Device power hooks, called around system sleep states
+//!
+//! Before a sleep state, each driver quiesces its devices: it stops issuing requests, waits for the in-flight ones,
+//! masks the device interrupts and saves the device state it needs (the device may lose power).
+//! After resume, it restores this state and restarts the device.
+//!
+//! ```ignore
+//! let mut devices: [&mut dyn PowerHooks; 2] = [&mut bus, &mut disk];
+//!
+//! power::quiesce_all(&mut devices)?;
+//! Power::suspend(SleepMode::S3)?; // from a privileged thread
+//! power::resume_all(&mut devices);
+//! ```
+
+/// Reason why a device refused to be quiesced
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PowerError {
+    /// The device has work in progress which cannot be interrupted: try again later
+    Busy,
+
+    /// The device could not be stopped
+    Failed,
+}
+
+/// Quiesce/resume hooks of a device
+pub trait PowerHooks {
+    /// Stop the device before a sleep state, and save its state
+    ///
+    /// On error, the device must still be operational.
+    fn quiesce(&mut self) -> Result<(), PowerError>;
+
+    /// Restore the device state after a sleep state, and restart it
+    fn resume(&mut self);
+}
+
+/// Quiesce devices, in reverse order (devices are expected to be given parents first, eg: bus then disks)
+///
+/// If a device fails, the already quiesced ones are resumed, and the index of the failing device is returned with its error.
+pub fn quiesce_all(devices: &mut [&mut dyn PowerHooks]) -> Result<(), (usize, PowerError)> {
+    for index in (0..devices.len()).rev() {
+        if let Err(err) = devices[index].quiesce() {
+            for device in devices[index + 1..].iter_mut() {
+                device.resume();
+            }
+
+            return Err((index, err));
+        }
+    }
+
+    Ok(())
+}
+
+/// Resume devices, in order (parents first)
+pub fn resume_all(devices: &mut [&mut dyn PowerHooks]) {
+    for device in devices.iter_mut() {
+        device.resume();
+    }
+}
@@ -0,0 +1,101 @@
+//! 16550 UART (serial port)
+//!
+//! The 8 io-ports of the UART must be granted to the process (eg: `libruntime::manifest_ioports!(0x2F8..0x300)` for COM2).
+//! Interrupts are not routed to userland yet: the line status is polled.
+//!
+//! ```ignore
+//! let uart = unsafe { Uart::new(COM2) };
+//! uart.init();
+//! uart.send(b'A');
+//! ```
+
+use crate::pio::IoPort;
+
+/// Base io-port of the first serial port
+pub const COM1: u16 = 0x3F8;
+
+/// Base io-port of the second serial port
+pub const COM2: u16 = 0x2F8;
+
+/// Number of io-ports of an UART
+pub const PORT_COUNT: u16 = 8;
+
+// Registers, relative to the base (DLAB is the divisor latch access bit of LCR)
+const DATA: u16 = 0; // DLAB=0: receive/transmit buffer, DLAB=1: divisor low byte
+const INTERRUPT_ENABLE: u16 = 1; // DLAB=1: divisor high byte
+const FIFO_CONTROL: u16 = 2;
+const LINE_CONTROL: u16 = 3;
+const MODEM_CONTROL: u16 = 4;
+const LINE_STATUS: u16 = 5;
+
+const LCR_DLAB: u8 = 1 << 7;
+/// 8 data bits, no parity, one stop bit
+const LCR_8N1: u8 = 0x03;
+/// Enable and clear the FIFOs, interrupt at 14 bytes
+const FCR_ENABLE: u8 = 0xC7;
+/// DTR, RTS and OUT2
+const MCR_READY: u8 = 0x0B;
+
+const LSR_DATA_READY: u8 = 1 << 0;
+const LSR_TRANSMIT_EMPTY: u8 = 1 << 5;
+
+/// Divisor of the 115200 bauds base clock: 38400 bauds, like the kernel serial ports
+const DIVISOR: u16 = 3;
+
+/// 16550 UART
+#[derive(Debug)]
+pub struct Uart {
+    data: IoPort<u8>,
+    interrupt_enable: IoPort<u8>,
+    fifo_control: IoPort<u8>,
+    line_control: IoPort<u8>,
+    modem_control: IoPort<u8>,
+    line_status: IoPort<u8>,
+}
+
+impl Uart {
+    /// # Safety
+    ///
+    /// The `PORT_COUNT` io-ports from `base` must be granted to the process, and be an UART not used by anyone else.
+    pub const unsafe fn new(base: u16) -> Self {
+        Self {
+            data: IoPort::new(base + DATA),
+            interrupt_enable: IoPort::new(base + INTERRUPT_ENABLE),
+            fifo_control: IoPort::new(base + FIFO_CONTROL),
+            line_control: IoPort::new(base + LINE_CONTROL),
+            modem_control: IoPort::new(base + MODEM_CONTROL),
+            line_status: IoPort::new(base + LINE_STATUS),
+        }
+    }
+
+    /// Setup the line (38400 bauds, 8N1, FIFOs enabled, interrupts disabled)
+    pub fn init(&self) {
+        self.interrupt_enable.write(0);
+
+        self.line_control.write(LCR_DLAB);
+        self.data.write(DIVISOR as u8);
+        self.interrupt_enable.write((DIVISOR >> 8) as u8);
+        self.line_control.write(LCR_8N1);
+
+        self.fifo_control.write(FCR_ENABLE);
+        self.modem_control.write(MCR_READY);
+    }
+
+    /// Send a byte, waiting for the transmitter to be ready
+    pub fn send(&self, byte: u8) {
+        while self.line_status.read() & LSR_TRANSMIT_EMPTY == 0 {
+            core::hint::spin_loop();
+        }
+
+        self.data.write(byte);
+    }
+
+    /// Get the next received byte, if any
+    pub fn try_receive(&self) -> Option<u8> {
+        if self.line_status.read() & LSR_DATA_READY == 0 {
+            return None;
+        }
+
+        Some(self.data.read())
+    }
+}
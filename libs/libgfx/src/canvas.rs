@@ -0,0 +1,171 @@
+use crate::{color::Color, font, rect::Rect};
+
+/// Read-only pixels buffer, source of blits
+#[derive(Debug, Clone, Copy)]
+pub struct Image<'a> {
+    pixels: &'a [u32],
+    width: usize,
+    height: usize,
+    /// Number of pixels between the starts of two rows
+    stride: usize,
+}
+
+impl<'a> Image<'a> {
+    pub fn new(pixels: &'a [u32], width: usize, height: usize, stride: usize) -> Self {
+        assert!(stride >= width);
+        assert!(height == 0 || pixels.len() >= (height - 1) * stride + width);
+
+        Self {
+            pixels,
+            width,
+            height,
+            stride,
+        }
+    }
+
+    pub fn width(&self) -> usize {
+        self.width
+    }
+
+    pub fn height(&self) -> usize {
+        self.height
+    }
+
+    fn row(&self, y: usize) -> &'a [u32] {
+        &self.pixels[y * self.stride..y * self.stride + self.width]
+    }
+}
+
+/// Pixels buffer to draw on
+#[derive(Debug)]
+pub struct Canvas<'a> {
+    pixels: &'a mut [u32],
+    width: usize,
+    height: usize,
+    /// Number of pixels between the starts of two rows
+    stride: usize,
+}
+
+impl<'a> Canvas<'a> {
+    pub fn new(pixels: &'a mut [u32], width: usize, height: usize, stride: usize) -> Self {
+        assert!(stride >= width);
+        assert!(height == 0 || pixels.len() >= (height - 1) * stride + width);
+
+        Self {
+            pixels,
+            width,
+            height,
+            stride,
+        }
+    }
+
+    pub fn width(&self) -> usize {
+        self.width
+    }
+
+    pub fn height(&self) -> usize {
+        self.height
+    }
+
+    /// Get the whole canvas area
+    pub fn bounds(&self) -> Rect {
+        Rect::new(0, 0, self.width as u32, self.height as u32)
+    }
+
+    /// View the canvas as an image, to blit it somewhere else
+    pub fn as_image(&self) -> Image<'_> {
+        Image::new(self.pixels, self.width, self.height, self.stride)
+    }
+
+    /// Get a pixel, None if out of the canvas
+    pub fn pixel(&self, x: i32, y: i32) -> Option<Color> {
+        let index = self.index(x, y)?;
+        Some(Color(self.pixels[index]))
+    }
+
+    /// Set a pixel, ignored if out of the canvas
+    pub fn set_pixel(&mut self, x: i32, y: i32, color: Color) {
+        if let Some(index) = self.index(x, y) {
+            self.pixels[index] = color.0;
+        }
+    }
+
+    pub fn clear(&mut self, color: Color) {
+        self.fill_rect(self.bounds(), color);
+    }
+
+    pub fn fill_rect(&mut self, rect: Rect, color: Color) {
+        let rect = rect.intersect(&self.bounds());
+
+        for y in rect.y..rect.bottom() {
+            let start = y as usize * self.stride + rect.x as usize;
+            self.pixels[start..start + rect.width as usize].fill(color.0);
+        }
+    }
+
+    /// Draw the outline of a rectangle (1 pixel)
+    pub fn draw_rect(&mut self, rect: Rect, color: Color) {
+        if rect.is_empty() {
+            return;
+        }
+
+        self.fill_rect(Rect::new(rect.x, rect.y, rect.width, 1), color);
+        self.fill_rect(Rect::new(rect.x, rect.bottom() - 1, rect.width, 1), color);
+        self.fill_rect(Rect::new(rect.x, rect.y, 1, rect.height), color);
+        self.fill_rect(Rect::new(rect.right() - 1, rect.y, 1, rect.height), color);
+    }
+
+    /// Copy an image at (`x`, `y`)
+    pub fn blit(&mut self, x: i32, y: i32, image: &Image) {
+        let target = Rect::new(x, y, image.width as u32, image.height as u32);
+        let clipped = target.intersect(&self.bounds());
+
+        let src_x = (clipped.x - x) as usize;
+        let src_y = (clipped.y - y) as usize;
+        let width = clipped.width as usize;
+
+        for row in 0..clipped.height as usize {
+            let src = &image.row(src_y + row)[src_x..src_x + width];
+            let start = (clipped.y as usize + row) * self.stride + clipped.x as usize;
+            self.pixels[start..start + width].copy_from_slice(src);
+        }
+    }
+
+    /// Draw a character at (`x`, `y`) (top-left corner), blended over the existing pixels
+    pub fn draw_char(&mut self, x: i32, y: i32, c: char, color: Color) {
+        for (row, line) in font::raster(c).iter().enumerate() {
+            for (column, &intensity) in line.iter().enumerate() {
+                if intensity == 0 {
+                    continue;
+                }
+
+                let (px, py) = (x + column as i32, y + row as i32);
+                if let Some(background) = self.pixel(px, py) {
+                    self.set_pixel(px, py, color.blend(background, intensity));
+                }
+            }
+        }
+    }
+
+    /// Draw a text on one line at (`x`, `y`) (top-left corner)
+    ///
+    /// Returns the x position after the text.
+    pub fn draw_text(&mut self, x: i32, y: i32, text: &str, color: Color) -> i32 {
+        let mut x = x;
+
+        for c in text.chars() {
+            self.draw_char(x, y, c, color);
+            x += font::CHAR_WIDTH as i32;
+        }
+
+        x
+    }
+
+    fn index(&self, x: i32, y: i32) -> Option<usize> {
+        if x < 0 || y < 0 || x as usize >= self.width || y as usize >= self.height {
+            return None;
+        }
+
+        Some(y as usize * self.stride + x as usize)
+    }
+}
@@ -0,0 +1,41 @@
+/// Color of a pixel: `0x00RRGGBB`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Color(pub u32);
+
+impl Color {
+    pub const BLACK: Self = Self(0x000000);
+    pub const WHITE: Self = Self(0xFFFFFF);
+    pub const RED: Self = Self(0xFF0000);
+    pub const GREEN: Self = Self(0x00FF00);
+    pub const BLUE: Self = Self(0x0000FF);
+
+    pub const fn rgb(r: u8, g: u8, b: u8) -> Self {
+        Self(((r as u32) << 16) | ((g as u32) << 8) | b as u32)
+    }
+
+    pub const fn r(self) -> u8 {
+        (self.0 >> 16) as u8
+    }
+
+    pub const fn g(self) -> u8 {
+        (self.0 >> 8) as u8
+    }
+
+    pub const fn b(self) -> u8 {
+        self.0 as u8
+    }
+
+    /// Mix `self` over `background`, with `alpha` (0: background only, 255: `self` only)
+    pub fn blend(self, background: Self, alpha: u8) -> Self {
+        let mix = |fg: u8, bg: u8| {
+            let alpha = alpha as u32;
+            ((fg as u32 * alpha + bg as u32 * (255 - alpha)) / 255) as u8
+        };
+
+        Self::rgb(
+            mix(self.r(), background.r()),
+            mix(self.g(), background.g()),
+            mix(self.b(), background.b()),
+        )
+    }
+}
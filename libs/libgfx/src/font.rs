@@ -0,0 +1,27 @@
+//! Embedded bitmap font (Noto Sans Mono, 16 pixels high)
+
+use noto_sans_mono_bitmap::{get_raster, get_raster_width, FontWeight, RasterHeight};
+
+const WEIGHT: FontWeight = FontWeight::Regular;
+const HEIGHT: RasterHeight = RasterHeight::Size16;
+
+/// Width of a character, in pixels (the font is monospace)
+pub const CHAR_WIDTH: usize = get_raster_width(WEIGHT, HEIGHT);
+
+/// Height of a line, in pixels
+pub const LINE_HEIGHT: usize = HEIGHT.val();
+
+/// Get the raster of a character: rows of intensities (0 to 255)
+///
+/// Characters missing from the font are drawn as `?`.
+pub fn raster(c: char) -> &'static [&'static [u8]] {
+    get_raster(c, WEIGHT, HEIGHT)
+        .or_else(|| get_raster('?', WEIGHT, HEIGHT))
+        .expect("missing '?' raster")
+        .raster()
+}
+
+/// Get the width of a text, in pixels
+pub fn text_width(text: &str) -> usize {
+    text.chars().count() * CHAR_WIDTH
+}
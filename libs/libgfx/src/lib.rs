@@ -0,0 +1,27 @@
+#![no_std]
+
+//! Software drawing primitives
+//!
+//! Draws on 32 bits pixels buffers (`0x00RRGGBB`), typically display-server surfaces:
+//! rectangles, blits and text with an embedded bitmap font. All drawing is clipped to the canvas.
+//!
+//! ```ignore
+//! let surface = display.create_surface(640, 480, &events)?;
+//! let mut mapped = MappedSurface::map(&surface)?;
+//! let mut canvas = mapped.canvas();
+//!
+//! canvas.clear(Color::BLACK);
+//! canvas.fill_rect(Rect::new(10, 10, 100, 20), Color::rgb(0, 0, 128));
+//! canvas.draw_text(14, 12, "Hello", Color::WHITE);
+//! ```
+
+mod canvas;
+mod color;
+pub mod font;
+mod rect;
+mod surface;
+
+pub use canvas::{Canvas, Image};
+pub use color::Color;
+pub use rect::Rect;
+pub use surface::MappedSurface;
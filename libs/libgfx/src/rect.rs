@@ -0,0 +1,48 @@
+/// Rectangle, in pixels
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Rect {
+    pub x: i32,
+    pub y: i32,
+    pub width: u32,
+    pub height: u32,
+}
+
+impl Rect {
+    pub const fn new(x: i32, y: i32, width: u32, height: u32) -> Self {
+        Self {
+            x,
+            y,
+            width,
+            height,
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.width == 0 || self.height == 0
+    }
+
+    /// Right edge (excluded)
+    pub fn right(&self) -> i32 {
+        self.x.saturating_add(self.width as i32)
+    }
+
+    /// Bottom edge (excluded)
+    pub fn bottom(&self) -> i32 {
+        self.y.saturating_add(self.height as i32)
+    }
+
+    /// Get the intersection of two rectangles, empty if they do not overlap
+    pub fn intersect(&self, other: &Rect) -> Rect {
+        let x = self.x.max(other.x);
+        let y = self.y.max(other.y);
+        let right = self.right().min(other.right());
+        let bottom = self.bottom().min(other.bottom());
+
+        Rect {
+            x,
+            y,
+            width: (right - x).max(0) as u32,
+            height: (bottom - y).max(0) as u32,
+        }
+    }
+}
@@ -0,0 +1,48 @@
+use core::slice;
+
+use libruntime::{
+    display::Surface,
+    kobject::{Error, Mapping, Permissions, Process},
+};
+
+use crate::canvas::Canvas;
+
+/// Display surface mapped in the process, to draw on it
+#[derive(Debug)]
+pub struct MappedSurface<'a> {
+    mapping: Mapping<'a>,
+    width: usize,
+    height: usize,
+}
+
+impl MappedSurface<'static> {
+    /// Map the pixels of a surface
+    pub fn map(surface: &Surface) -> Result<Self, Error> {
+        let size = libruntime::display::surface_memory_size(surface.width, surface.height);
+
+        let mapping = Process::current().map_mem(
+            None,
+            size,
+            Permissions::READ | Permissions::WRITE,
+            &surface.memory,
+            0,
+        )?;
+
+        Ok(Self {
+            mapping,
+            width: surface.width as usize,
+            height: surface.height as usize,
+        })
+    }
+}
+
+impl MappedSurface<'_> {
+    /// Get a canvas to draw on the surface
+    pub fn canvas(&mut self) -> Canvas<'_> {
+        let pixels = unsafe {
+            slice::from_raw_parts_mut(self.mapping.address() as *mut u32, self.width * self.height)
+        };
+
+        Canvas::new(pixels, self.width, self.height, self.width)
+    }
+}
@@ -0,0 +1,513 @@
+#![no_std]
+
+//! Key/value block
+//!
+//! Compact binary list of key/value pairs (eg: process environment, spawn parameters), passed in messages or memory objects.
+//!
+//! Layout (little endian):
+//! - header: magic (`KVBK`), version (u16), header size (u16), entry count (u32), entries size (u32)
+//! - entries, each one aligned on 8 bytes: entry size (u32, padding included), key length (u16), value type (u16),
+//!   value length (u32), then the key (UTF-8) and the value
+//!
+//! The format is append-friendly and forward compatible:
+//! - new header fields go after the current ones: readers skip them using the header size
+//! - new value types can be added: readers get them as `Value::Unknown`, and skip them using the entry size
+//! - entries can be appended to an existing block without rebuilding it (see `KVBlockBuilder::from_block`)
+//!
+//! The whole block is validated when it is parsed, so iteration never reads out of bounds.
+
+extern crate alloc;
+
+use core::{fmt, str};
+
+use alloc::vec::Vec;
+
+/// Magic of a block
+pub const MAGIC: [u8; 4] = *b"KVBK";
+
+/// Current version of the format
+///
+/// Only incompatible changes bump it: readers reject blocks with a newer version.
+pub const VERSION: u16 = 1;
+
+const HEADER_SIZE: usize = 16;
+const ENTRY_HEADER_SIZE: usize = 12;
+const ENTRY_ALIGN: usize = 8;
+
+/// Type of a value
+#[repr(u16)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ValueType {
+    /// UTF-8 string
+    Str = 1,
+    /// Raw bytes
+    Bytes,
+    /// Little endian u64
+    U64,
+}
+
+/// Value of an entry
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Value<'a> {
+    Str(&'a str),
+    Bytes(&'a [u8]),
+    U64(u64),
+    /// Value of a type added after this version of the library
+    Unknown(u16, &'a [u8]),
+}
+
+impl<'a> Value<'a> {
+    /// Get the value if it is a string
+    pub fn as_str(&self) -> Option<&'a str> {
+        match *self {
+            Value::Str(value) => Some(value),
+            _ => None,
+        }
+    }
+
+    /// Get the value if it is raw bytes
+    pub fn as_bytes(&self) -> Option<&'a [u8]> {
+        match *self {
+            Value::Bytes(value) => Some(value),
+            _ => None,
+        }
+    }
+
+    /// Get the value if it is an integer
+    pub fn as_u64(&self) -> Option<u64> {
+        match *self {
+            Value::U64(value) => Some(value),
+            _ => None,
+        }
+    }
+}
+
+/// Entry of a block
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Entry<'a> {
+    pub key: &'a str,
+    pub value: Value<'a>,
+}
+
+/// Error while parsing a block
+#[derive(Debug, Clone, Copy)]
+pub enum KVBlockError {
+    Truncated,
+    BadMagic,
+    UnsupportedVersion(u16),
+    /// The entry at this offset (from the start of the block) is corrupted
+    BadEntry(usize),
+    /// The entry count of the header does not match the entries
+    BadEntryCount,
+}
+
+impl fmt::Display for KVBlockError {
+    fn fmt(&self, formatter: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match *self {
+            KVBlockError::Truncated => write!(formatter, "truncated kv block"),
+            KVBlockError::BadMagic => write!(formatter, "bad kv block magic"),
+            KVBlockError::UnsupportedVersion(version) => {
+                write!(formatter, "unsupported kv block version: {}", version)
+            }
+            KVBlockError::BadEntry(offset) => {
+                write!(formatter, "bad kv block entry at offset {}", offset)
+            }
+            KVBlockError::BadEntryCount => write!(formatter, "bad kv block entry count"),
+        }
+    }
+}
+
+/// Validated block, borrowed from its data
+#[derive(Debug, Clone, Copy)]
+pub struct KVBlock<'a> {
+    entries: &'a [u8],
+    count: usize,
+}
+
+impl<'a> KVBlock<'a> {
+    /// Parse and validate a block
+    pub fn parse(data: &'a [u8]) -> Result<Self, KVBlockError> {
+        if data.len() < HEADER_SIZE {
+            return Err(KVBlockError::Truncated);
+        }
+
+        if data[0..4] != MAGIC {
+            return Err(KVBlockError::BadMagic);
+        }
+
+        let version = read_u16(data, 4);
+        if version > VERSION {
+            return Err(KVBlockError::UnsupportedVersion(version));
+        }
+
+        let header_size = read_u16(data, 6) as usize;
+        let count = read_u32(data, 8) as usize;
+        let entries_size = read_u32(data, 12) as usize;
+
+        if header_size < HEADER_SIZE || header_size % ENTRY_ALIGN != 0 {
+            return Err(KVBlockError::BadEntry(0));
+        }
+
+        let entries = data
+            .get(header_size..)
+            .and_then(|entries| entries.get(..entries_size))
+            .ok_or(KVBlockError::Truncated)?;
+
+        let block = Self { entries, count };
+
+        // Validate all the entries once, so that iteration cannot fail
+        let mut offset = 0;
+        let mut actual_count = 0;
+        while offset < entries.len() {
+            match parse_entry(entries, offset) {
+                Some((_, size)) => offset += size,
+                None => return Err(KVBlockError::BadEntry(header_size + offset)),
+            }
+            actual_count += 1;
+        }
+
+        if actual_count != count {
+            return Err(KVBlockError::BadEntryCount);
+        }
+
+        Ok(block)
+    }
+
+    /// Get the number of entries
+    pub fn len(&self) -> usize {
+        self.count
+    }
+
+    /// Test if the block has no entry
+    pub fn is_empty(&self) -> bool {
+        self.count == 0
+    }
+
+    /// Iterate over the entries, in insertion order
+    pub fn iter(&self) -> KVBlockIter<'a> {
+        KVBlockIter {
+            entries: self.entries,
+            offset: 0,
+        }
+    }
+
+    /// Get the value of the last entry with this key
+    pub fn get(&self, key: &str) -> Option<Value<'a>> {
+        self.iter()
+            .filter(|entry| entry.key == key)
+            .last()
+            .map(|entry| entry.value)
+    }
+}
+
+/// Iterator over the entries of a block
+#[derive(Debug, Clone)]
+pub struct KVBlockIter<'a> {
+    entries: &'a [u8],
+    offset: usize,
+}
+
+impl<'a> Iterator for KVBlockIter<'a> {
+    type Item = Entry<'a>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        // The block has been validated by `KVBlock::parse`
+        let (entry, size) = parse_entry(self.entries, self.offset)?;
+        self.offset += size;
+        Some(entry)
+    }
+}
+
+/// Parse the entry at `offset`, returns it with its size
+fn parse_entry(entries: &[u8], offset: usize) -> Option<(Entry<'_>, usize)> {
+    let header = entries.get(offset..offset.checked_add(ENTRY_HEADER_SIZE)?)?;
+
+    let size = read_u32(header, 0) as usize;
+    let key_len = read_u16(header, 4) as usize;
+    let value_type = read_u16(header, 6);
+    let value_len = read_u32(header, 8) as usize;
+
+    if size % ENTRY_ALIGN != 0 || size < ENTRY_HEADER_SIZE + key_len + value_len {
+        return None;
+    }
+
+    let data = entries.get(offset + ENTRY_HEADER_SIZE..offset.checked_add(size)?)?;
+    let key = str::from_utf8(&data[..key_len]).ok()?;
+    let value = &data[key_len..key_len + value_len];
+
+    let value = match value_type {
+        1 => Value::Str(str::from_utf8(value).ok()?),
+        2 => Value::Bytes(value),
+        3 => Value::U64(u64::from_le_bytes(value.try_into().ok()?)),
+        other => Value::Unknown(other, value),
+    };
+
+    Some((Entry { key, value }, size))
+}
+
+/// Builder of a block
+#[derive(Debug, Clone)]
+pub struct KVBlockBuilder {
+    data: Vec<u8>,
+    count: u32,
+}
+
+impl KVBlockBuilder {
+    /// New empty block
+    pub fn new() -> Self {
+        let mut data = Vec::with_capacity(HEADER_SIZE);
+        data.extend_from_slice(&MAGIC);
+        data.extend_from_slice(&VERSION.to_le_bytes());
+        data.extend_from_slice(&(HEADER_SIZE as u16).to_le_bytes());
+        data.extend_from_slice(&0u32.to_le_bytes());
+        data.extend_from_slice(&0u32.to_le_bytes());
+
+        Self { data, count: 0 }
+    }
+
+    /// Append entries to an existing block: its entries are copied as is, unknown value types included
+    pub fn from_block(block: &KVBlock) -> Self {
+        let mut builder = Self::new();
+        builder.data.extend_from_slice(block.entries);
+        builder.count = block.count as u32;
+        builder
+    }
+
+    /// Append an entry
+    ///
+    /// Keys can be repeated: `KVBlock::get` returns the last value.
+    pub fn push(&mut self, key: &str, value: Value) -> &mut Self {
+        let integer;
+        let (value_type, value) = match value {
+            Value::Str(value) => (ValueType::Str as u16, value.as_bytes()),
+            Value::Bytes(value) => (ValueType::Bytes as u16, value),
+            Value::U64(value) => {
+                integer = value.to_le_bytes();
+                (ValueType::U64 as u16, &integer[..])
+            }
+            Value::Unknown(r#type, value) => (r#type, value),
+        };
+        assert!(key.len() <= u16::MAX as usize);
+
+        let size = (ENTRY_HEADER_SIZE + key.len() + value.len()).next_multiple_of(ENTRY_ALIGN);
+
+        self.data.extend_from_slice(&(size as u32).to_le_bytes());
+        self.data
+            .extend_from_slice(&(key.len() as u16).to_le_bytes());
+        self.data.extend_from_slice(&value_type.to_le_bytes());
+        self.data
+            .extend_from_slice(&(value.len() as u32).to_le_bytes());
+        self.data.extend_from_slice(key.as_bytes());
+        self.data.extend_from_slice(value);
+        self.data
+            .resize(self.data.len().next_multiple_of(ENTRY_ALIGN), 0);
+
+        self.count += 1;
+        self
+    }
+
+    /// Append a string entry
+    pub fn push_str(&mut self, key: &str, value: &str) -> &mut Self {
+        self.push(key, Value::Str(value))
+    }
+
+    /// Get the block data
+    pub fn build(mut self) -> Vec<u8> {
+        let entries_size = (self.data.len() - HEADER_SIZE) as u32;
+        self.data[8..12].copy_from_slice(&self.count.to_le_bytes());
+        self.data[12..16].copy_from_slice(&entries_size.to_le_bytes());
+        self.data
+    }
+}
+
+impl Default for KVBlockBuilder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+fn read_u16(data: &[u8], offset: usize) -> u16 {
+    u16::from_le_bytes(data[offset..offset + 2].try_into().unwrap())
+}
+
+fn read_u32(data: &[u8], offset: usize) -> u32 {
+    u32::from_le_bytes(data[offset..offset + 4].try_into().unwrap())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sample() -> Vec<u8> {
+        let mut builder = KVBlockBuilder::new();
+        builder
+            .push_str("name", "init")
+            .push("pid", Value::U64(1))
+            .push("data", Value::Bytes(&[1, 2, 3]))
+            .push("later", Value::Unknown(42, b"opaque"));
+        builder.build()
+    }
+
+    #[test]
+    fn round_trip() {
+        let data = sample();
+        assert_eq!(data.len() % ENTRY_ALIGN, 0);
+
+        let block = KVBlock::parse(&data).unwrap();
+        assert_eq!(block.len(), 4);
+
+        let entries: Vec<Entry> = block.iter().collect();
+        assert_eq!(
+            entries,
+            [
+                Entry {
+                    key: "name",
+                    value: Value::Str("init")
+                },
+                Entry {
+                    key: "pid",
+                    value: Value::U64(1)
+                },
+                Entry {
+                    key: "data",
+                    value: Value::Bytes(&[1, 2, 3])
+                },
+                Entry {
+                    key: "later",
+                    value: Value::Unknown(42, b"opaque")
+                },
+            ]
+        );
+
+        assert_eq!(block.get("pid").and_then(|value| value.as_u64()), Some(1));
+        assert_eq!(block.get("missing"), None);
+    }
+
+    #[test]
+    fn empty() {
+        let data = KVBlockBuilder::new().build();
+        assert_eq!(data.len(), HEADER_SIZE);
+
+        let block = KVBlock::parse(&data).unwrap();
+        assert!(block.is_empty());
+        assert_eq!(block.iter().next(), None);
+    }
+
+    #[test]
+    fn truncated() {
+        let data = sample();
+
+        for len in 0..data.len() {
+            assert!(
+                matches!(KVBlock::parse(&data[..len]), Err(KVBlockError::Truncated)),
+                "length {}",
+                len
+            );
+        }
+    }
+
+    #[test]
+    fn trailing_data_ignored() {
+        let mut data = sample();
+        data.extend_from_slice(&[0xff; 24]);
+
+        assert_eq!(KVBlock::parse(&data).unwrap().len(), 4);
+    }
+
+    #[test]
+    fn duplicate_keys() {
+        let mut builder = KVBlockBuilder::new();
+        builder
+            .push_str("key", "first")
+            .push_str("other", "value")
+            .push_str("key", "second");
+        let data = builder.build();
+
+        let block = KVBlock::parse(&data).unwrap();
+        assert_eq!(block.len(), 3);
+        assert_eq!(block.get("key"), Some(Value::Str("second")));
+
+        let values: Vec<&str> = block
+            .iter()
+            .filter(|entry| entry.key == "key")
+            .filter_map(|entry| entry.value.as_str())
+            .collect();
+        assert_eq!(values, ["first", "second"]);
+    }
+
+    #[test]
+    fn append_to_block() {
+        let data = sample();
+        let block = KVBlock::parse(&data).unwrap();
+
+        let mut builder = KVBlockBuilder::from_block(&block);
+        builder.push_str("name", "renamed");
+        let data = builder.build();
+
+        let block = KVBlock::parse(&data).unwrap();
+        assert_eq!(block.len(), 5);
+        assert_eq!(block.get("name"), Some(Value::Str("renamed")));
+        assert_eq!(block.get("later"), Some(Value::Unknown(42, b"opaque")));
+    }
+
+    #[test]
+    fn larger_header_skipped() {
+        let data = sample();
+
+        // A newer minor version with an extra header field
+        let mut extended = Vec::from(&data[..HEADER_SIZE]);
+        extended[6..8].copy_from_slice(&((HEADER_SIZE + 8) as u16).to_le_bytes());
+        extended.extend_from_slice(&[0xaa; 8]);
+        extended.extend_from_slice(&data[HEADER_SIZE..]);
+
+        assert_eq!(KVBlock::parse(&extended).unwrap().len(), 4);
+    }
+
+    #[test]
+    fn bad_header() {
+        let mut data = sample();
+        data[0] = b'X';
+        assert!(matches!(KVBlock::parse(&data), Err(KVBlockError::BadMagic)));
+
+        let mut data = sample();
+        data[4..6].copy_from_slice(&(VERSION + 1).to_le_bytes());
+        assert!(matches!(
+            KVBlock::parse(&data),
+            Err(KVBlockError::UnsupportedVersion(version)) if version == VERSION + 1
+        ));
+
+        let mut data = sample();
+        data[8..12].copy_from_slice(&5u32.to_le_bytes());
+        assert!(matches!(
+            KVBlock::parse(&data),
+            Err(KVBlockError::BadEntryCount)
+        ));
+    }
+
+    #[test]
+    fn bad_entry() {
+        // Entry size smaller than its key and value
+        let mut data = sample();
+        data[HEADER_SIZE..HEADER_SIZE + 4].copy_from_slice(&8u32.to_le_bytes());
+        assert!(matches!(
+            KVBlock::parse(&data),
+            Err(KVBlockError::BadEntry(offset)) if offset == HEADER_SIZE
+        ));
+
+        // Entry past the end of the entries
+        let mut data = sample();
+        data[HEADER_SIZE..HEADER_SIZE + 4].copy_from_slice(&1024u32.to_le_bytes());
+        assert!(matches!(
+            KVBlock::parse(&data),
+            Err(KVBlockError::BadEntry(_))
+        ));
+
+        // Key not UTF-8
+        let mut data = sample();
+        data[HEADER_SIZE + ENTRY_HEADER_SIZE] = 0xff;
+        assert!(matches!(
+            KVBlock::parse(&data),
+            Err(KVBlockError::BadEntry(_))
+        ));
+    }
+}
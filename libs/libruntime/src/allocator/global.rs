@@ -4,7 +4,8 @@ use spin::Mutex;
 
 use super::Dlmalloc;
 
-#[global_allocator]
+// Host unit tests use the std one: this one needs the kernel
+#[cfg_attr(not(test), global_allocator)]
 pub static ALLOC: GlobalDlmalloc = GlobalDlmalloc::new();
 
 /// An instance of a "global allocator" backed by `Dlmalloc`
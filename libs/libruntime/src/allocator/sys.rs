@@ -1,4 +1,4 @@
-use libsyscalls::{memory_object, process, Error, Permissions};
+use libsyscalls::{memory_object, process, Error, MemoryObjectFlags, Permissions};
 use log::{error, trace};
 
 use crate::kobject;
@@ -19,7 +19,7 @@ impl System {
 
         let self_proc = process::open_self()?;
 
-        let mobj = memory_object::create(size)?;
+        let mobj = memory_object::create(size, MemoryObjectFlags::NONE)?;
         let addr = process::mmap(
             &self_proc,
             None,
@@ -29,6 +29,9 @@ impl System {
             0,
         )?;
 
+        // Label is only informative: do not fail the allocation on error
+        let _ = process::mname(&self_proc, &(addr..(addr + size)), Some("heap"));
+
         Ok(addr as *mut u8)
     }
 
@@ -0,0 +1,217 @@
+//! Block device protocol and client
+//!
+//! Storage drivers (eg: `servers/ahci-server`) register one port per disk, named `blockdev-<driver><index>` (eg: `blockdev-ahci0`).
+//!
+//! Data is exchanged in a memory object given by the client in handle 1: the driver programs the DMA transfers
+//! directly on its pages, so there is no copy. A transfer is at most `MAX_TRANSFER_SIZE` bytes, and the memory object
+//! must be large enough for `count` blocks.
+//!
+//! Requests are served in order, one at a time per disk.
+
+use core::mem;
+
+use alloc::{format, string::String};
+
+use crate::failure;
+use crate::kobject::{
+    Error, Handle, MemoryObject, Message, Port, PortReceiver, PortSender, PAGE_SIZE,
+};
+
+/// Prefix of the names of the block device ports
+pub const PORT_NAME_PREFIX: &str = "blockdev-";
+
+/// Maximum size of a transfer
+pub const MAX_TRANSFER_SIZE: usize = 128 * PAGE_SIZE;
+
+/// Get the name of the port of a disk
+pub fn port_name(driver: &str, index: usize) -> String {
+    format!("{}{}{}", PORT_NAME_PREFIX, driver, index)
+}
+
+/// Type of the requests to a block device
+#[repr(u64)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RequestType {
+    /// Get the geometry of the disk: the reply carries the block count and size
+    Info = 1,
+    /// Read `count` blocks from `lba` into the memory object in handle 1
+    Read,
+    /// Write `count` blocks at `lba` from the memory object in handle 1
+    Write,
+    /// Flush the write cache of the disk
+    Flush,
+}
+
+impl TryFrom<u64> for RequestType {
+    type Error = Error;
+
+    fn try_from(value: u64) -> Result<Self, Self::Error> {
+        match value {
+            1 => Ok(Self::Info),
+            2 => Ok(Self::Read),
+            3 => Ok(Self::Write),
+            4 => Ok(Self::Flush),
+            _ => Err(Error::InvalidArgument),
+        }
+    }
+}
+
+/// Request to a block device
+///
+/// Handle 0 is the port to send the reply to, handle 1 the data memory object for `Read` and `Write`.
+#[derive(Debug, Clone, Copy)]
+#[repr(C)]
+pub struct Request {
+    pub r#type: u64,
+    /// First block of the transfer
+    pub lba: u64,
+    /// Number of blocks of the transfer
+    pub count: u64,
+}
+
+impl Request {
+    pub fn new(r#type: RequestType) -> Self {
+        Self {
+            r#type: r#type as u64,
+            lba: 0,
+            count: 0,
+        }
+    }
+}
+
+/// Geometry of a disk
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[repr(C)]
+pub struct BlockDeviceInfo {
+    pub block_count: u64,
+    /// Size of a block, in bytes
+    pub block_size: u64,
+}
+
+impl BlockDeviceInfo {
+    /// Size of the disk, in bytes
+    pub fn size(&self) -> u64 {
+        self.block_count * self.block_size
+    }
+}
+
+/// Reply of a block device
+#[derive(Debug, Clone, Copy)]
+#[repr(C)]
+pub struct Reply {
+    /// 0 on success, else the error code
+    pub status: u64,
+    /// Set by `Info`
+    pub info: BlockDeviceInfo,
+}
+
+impl Reply {
+    /// Build the reply of a request result
+    pub fn new(result: Result<BlockDeviceInfo, Error>) -> Self {
+        match result {
+            Ok(info) => Self { status: 0, info },
+            Err(err) => Self {
+                status: err as u64,
+                info: BlockDeviceInfo::default(),
+            },
+        }
+    }
+
+    /// Get the result of the request
+    pub fn result(&self) -> Result<BlockDeviceInfo, Error> {
+        match self.status {
+            0 => Ok(self.info),
+            status if status <= Error::LAST as u64 => {
+                // Note: safe since it is in the range of error codes
+                Err(unsafe { mem::transmute::<usize, Error>(status as usize) })
+            }
+            _ => Err(Error::InvalidArgument),
+        }
+    }
+}
+
+/// Connection to a block device
+#[derive(Debug)]
+pub struct BlockDevice {
+    server: PortSender,
+    reply_receiver: PortReceiver,
+    reply_sender: PortSender,
+    info: BlockDeviceInfo,
+}
+
+impl BlockDevice {
+    /// Open the block device served on `port_name` (see `port_name`)
+    pub fn open(port_name: &str) -> Result<Self, Error> {
+        let server = Port::open(port_name)?;
+        let (reply_receiver, reply_sender) = Port::create(None)?;
+
+        let mut device = Self {
+            server,
+            reply_receiver,
+            reply_sender,
+            info: BlockDeviceInfo::default(),
+        };
+
+        device.info = device.call(&Request::new(RequestType::Info), Handle::invalid())?;
+
+        Ok(device)
+    }
+
+    /// Get the geometry of the disk
+    pub fn info(&self) -> BlockDeviceInfo {
+        self.info
+    }
+
+    /// Get the size of the memory object needed to transfer `count` blocks
+    pub fn buffer_size(&self, count: u64) -> usize {
+        (count * self.info.block_size).next_multiple_of(PAGE_SIZE as u64) as usize
+    }
+
+    /// Read `count` blocks from `lba` into a new memory object
+    pub fn read(&self, lba: u64, count: u64) -> Result<MemoryObject, Error> {
+        let buffer = MemoryObject::create(self.buffer_size(count))?;
+        self.read_into(lba, count, &buffer)?;
+        Ok(buffer)
+    }
+
+    /// Read `count` blocks from `lba` into `buffer`
+    pub fn read_into(&self, lba: u64, count: u64, buffer: &MemoryObject) -> Result<(), Error> {
+        let mut request = Request::new(RequestType::Read);
+        request.lba = lba;
+        request.count = count;
+
+        self.call(&request, buffer.clone().into_handle())?;
+        Ok(())
+    }
+
+    /// Write `count` blocks at `lba` from `buffer`
+    pub fn write(&self, lba: u64, count: u64, buffer: &MemoryObject) -> Result<(), Error> {
+        let mut request = Request::new(RequestType::Write);
+        request.lba = lba;
+        request.count = count;
+
+        self.call(&request, buffer.clone().into_handle())?;
+        Ok(())
+    }
+
+    /// Flush the write cache of the disk
+    pub fn flush(&self) -> Result<(), Error> {
+        self.call(&Request::new(RequestType::Flush), Handle::invalid())?;
+        Ok(())
+    }
+
+    fn call(&self, request: &Request, handle: Handle) -> Result<BlockDeviceInfo, Error> {
+        let mut handles = [self.reply_sender.clone().into_handle(), handle];
+
+        let mut message = unsafe { Message::new(request, &mut handles) };
+        self.server.send(&mut message)?;
+
+        let reply = self.reply_receiver.blocking_receive()?;
+        failure::check_reply(&reply)?;
+        unsafe { reply.data::<Reply>() }.result()
+    }
+}
+
+// Make sure the protocol fits in messages
+const _: () = assert!(mem::size_of::<Request>() <= Message::DATA_SIZE);
+const _: () = assert!(mem::size_of::<Reply>() <= Message::DATA_SIZE);
@@ -0,0 +1,154 @@
+//! Boot profile protocol and client
+//!
+//! The boot profile selects the set of services started by init:
+//! - `minimal`: only the services needed for a shell
+//! - `full`: the whole system
+//! - `test`: the test harness: init runs the tests, then the machine is powered off
+//!
+//! Init serves the selected profile on its port, so that services and tools can adapt to it.
+
+use core::{fmt, mem};
+
+use crate::failure;
+use crate::kobject::{Error, Message, Port};
+
+/// Name of the port of init serving the profile
+pub const SERVER_PORT_NAME: &str = "boot-profile";
+
+/// Set of services started at boot
+#[repr(u64)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Profile {
+    Minimal = 1,
+    Full,
+    Test,
+}
+
+impl Profile {
+    /// Profile used when the boot parameters do not give one
+    pub const DEFAULT: Self = Self::Full;
+
+    /// Parse a profile from its name in the boot parameters
+    pub fn parse(name: &str) -> Option<Self> {
+        match name.trim() {
+            "minimal" => Some(Self::Minimal),
+            "full" => Some(Self::Full),
+            "test" => Some(Self::Test),
+            _ => None,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::Minimal => "minimal",
+            Self::Full => "full",
+            Self::Test => "test",
+        }
+    }
+}
+
+impl fmt::Display for Profile {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+impl TryFrom<u64> for Profile {
+    type Error = Error;
+
+    fn try_from(value: u64) -> Result<Self, Self::Error> {
+        match value {
+            1 => Ok(Self::Minimal),
+            2 => Ok(Self::Full),
+            3 => Ok(Self::Test),
+            _ => Err(Error::InvalidArgument),
+        }
+    }
+}
+
+/// Type of the requests to the server
+#[repr(u64)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RequestType {
+    /// Get the profile selected at boot
+    Get = 1,
+}
+
+impl TryFrom<u64> for RequestType {
+    type Error = Error;
+
+    fn try_from(value: u64) -> Result<Self, Self::Error> {
+        match value {
+            1 => Ok(Self::Get),
+            _ => Err(Error::InvalidArgument),
+        }
+    }
+}
+
+/// Request to the server
+///
+/// Handle 0 is the port to send the reply to.
+#[derive(Debug, Clone, Copy)]
+#[repr(C)]
+pub struct Request {
+    pub r#type: u64,
+}
+
+/// Reply of the server
+#[derive(Debug, Clone, Copy)]
+#[repr(C)]
+pub struct Reply {
+    /// 0 on success, else the error code
+    pub status: u64,
+    /// `Profile` value, on success
+    pub profile: u64,
+}
+
+impl Reply {
+    /// Build the reply of a request result
+    pub fn new(result: Result<Profile, Error>) -> Self {
+        match result {
+            Ok(profile) => Self {
+                status: 0,
+                profile: profile as u64,
+            },
+            Err(err) => Self {
+                status: err as u64,
+                profile: 0,
+            },
+        }
+    }
+
+    /// Get the result of the request
+    pub fn result(&self) -> Result<Profile, Error> {
+        match self.status {
+            0 => Profile::try_from(self.profile),
+            status if status <= Error::LAST as u64 => {
+                // Note: safe since it is in the range of error codes
+                Err(unsafe { mem::transmute::<usize, Error>(status as usize) })
+            }
+            _ => Err(Error::InvalidArgument),
+        }
+    }
+}
+
+/// Get the profile selected at boot, from init
+pub fn current() -> Result<Profile, Error> {
+    let server = Port::open(SERVER_PORT_NAME)?;
+    let (reply_receiver, reply_sender) = Port::create(None)?;
+
+    let request = Request {
+        r#type: RequestType::Get as u64,
+    };
+    let mut handles = [reply_sender.into_handle()];
+    let mut message = unsafe { Message::new(&request, &mut handles) };
+    server.send(&mut message)?;
+
+    let reply = reply_receiver.blocking_receive()?;
+    failure::check_reply(&reply)?;
+    unsafe { reply.data::<Reply>() }.result()
+}
+
+// Make sure the protocol fits in messages
+const _: () = assert!(mem::size_of::<Request>() <= Message::DATA_SIZE);
+const _: () = assert!(mem::size_of::<Reply>() <= Message::DATA_SIZE);
@@ -0,0 +1,213 @@
+//! Checkpoint server protocol and client
+//!
+//! The checkpoint server (`servers/checkpoint-server`) stores checkpoints of processes in vfs files, and
+//! restores them into new processes (eg: fast startup of test fixtures). See `debug::Checkpoint` for the
+//! format and its limits.
+//!
+//! A process can be stored by its creator, or by the spawner of the server (init). Checkpoints can be restored
+//! by anyone: the restored process is created by the server, and its handle is sent with the reply.
+
+use core::mem;
+
+use crate::failure;
+use crate::kobject::{Error, Handle, Message, Port, PortReceiver, PortSender, Process};
+
+/// Name of the port of the server
+pub const SERVER_PORT_NAME: &str = "checkpoint-server";
+
+/// Maximum length of the path of a checkpoint file, in bytes
+pub const PATH_SIZE: usize = 48;
+
+/// Type of the requests to the server
+#[repr(u64)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RequestType {
+    /// Suspend the process, store its checkpoint in the file, and let it run again
+    Store = 1,
+    /// Restore the checkpoint of the file into a new process, and run it
+    Restore,
+}
+
+impl TryFrom<u64> for RequestType {
+    type Error = Error;
+
+    fn try_from(value: u64) -> Result<Self, Self::Error> {
+        match value {
+            1 => Ok(Self::Store),
+            2 => Ok(Self::Restore),
+            _ => Err(Error::InvalidArgument),
+        }
+    }
+}
+
+/// Request to the server
+///
+/// Handle 0 is the port to send the reply to.
+#[derive(Debug, Clone, Copy)]
+#[repr(C)]
+pub struct Request {
+    pub r#type: u64,
+    /// Used by `Store`: pid of the process
+    pub pid: u64,
+    /// Path of the checkpoint file, padded with zeroes
+    pub path: [u8; PATH_SIZE],
+}
+
+impl Request {
+    pub const fn new(r#type: RequestType) -> Self {
+        Self {
+            r#type: r#type as u64,
+            pid: 0,
+            path: [0; PATH_SIZE],
+        }
+    }
+
+    /// Set the path of the request
+    ///
+    /// Fails with `Error::InvalidArgument` if it is empty or longer than `PATH_SIZE`
+    pub fn with_path(mut self, path: &str) -> Result<Self, Error> {
+        if path.is_empty() || path.len() > PATH_SIZE {
+            return Err(Error::InvalidArgument);
+        }
+
+        self.path[..path.len()].copy_from_slice(path.as_bytes());
+        Ok(self)
+    }
+
+    /// Get the path of the request
+    pub fn path(&self) -> Result<&str, Error> {
+        let len = self
+            .path
+            .iter()
+            .position(|&byte| byte == 0)
+            .unwrap_or(PATH_SIZE);
+
+        match core::str::from_utf8(&self.path[..len]) {
+            Ok("") | Err(_) => Err(Error::InvalidArgument),
+            Ok(path) => Ok(path),
+        }
+    }
+}
+
+/// Reply of the server
+///
+/// Replies to `Restore` carry the restored process as handle 0.
+#[derive(Debug, Clone, Copy)]
+#[repr(C)]
+pub struct Reply {
+    /// 0 on success, else the error code
+    pub status: u64,
+    /// Size of the checkpoint file in bytes
+    pub size: u64,
+    /// Used by `Restore`: number of handles which could not be opened again in the restored process
+    pub lost_handles: u64,
+}
+
+impl Reply {
+    /// Build the reply of a request result: the size of the file, and the number of lost handles
+    pub fn new(result: Result<(usize, usize), Error>) -> Self {
+        match result {
+            Ok((size, lost_handles)) => Self {
+                status: 0,
+                size: size as u64,
+                lost_handles: lost_handles as u64,
+            },
+            Err(err) => Self {
+                status: err as u64,
+                size: 0,
+                lost_handles: 0,
+            },
+        }
+    }
+
+    /// Get the result of the request
+    pub fn result(&self) -> Result<(usize, usize), Error> {
+        match self.status {
+            0 => Ok((self.size as usize, self.lost_handles as usize)),
+            status if status <= Error::LAST as u64 => {
+                // Note: safe since it is in the range of error codes
+                Err(unsafe { mem::transmute::<usize, Error>(status as usize) })
+            }
+            _ => Err(Error::InvalidArgument),
+        }
+    }
+}
+
+/// Process restored by the server
+#[derive(Debug)]
+pub struct RestoredProcess {
+    /// The restored process, running
+    pub process: Process,
+    /// Number of handles which could not be opened again: their values are invalid in the restored process
+    pub lost_handles: usize,
+}
+
+/// Connection to the checkpoint server
+#[derive(Debug)]
+pub struct CheckpointServer {
+    server: PortSender,
+    reply_receiver: PortReceiver,
+    reply_sender: PortSender,
+}
+
+impl CheckpointServer {
+    /// Connect to the server
+    pub fn connect() -> Result<Self, Error> {
+        Self::connect_to(SERVER_PORT_NAME)
+    }
+
+    /// Connect to a server listening on another port (eg: a private instance in a test)
+    pub fn connect_to(port_name: &str) -> Result<Self, Error> {
+        let server = Port::open(port_name)?;
+        let (reply_receiver, reply_sender) = Port::create(None)?;
+
+        Ok(Self {
+            server,
+            reply_receiver,
+            reply_sender,
+        })
+    }
+
+    /// Store the checkpoint of the process in the file, returns its size in bytes
+    ///
+    /// The process is suspended while it is captured. A file at the same path is replaced.
+    /// Fails with `Error::NotSupported` if the caller did not create the process,
+    /// and with `Error::ObjectNotReady` if a thread of the process is blocked in a syscall.
+    pub fn store(&self, pid: u64, path: &str) -> Result<usize, Error> {
+        let mut request = Request::new(RequestType::Store).with_path(path)?;
+        request.pid = pid;
+
+        let ((size, _), _) = self.call(request)?;
+        Ok(size)
+    }
+
+    /// Restore the checkpoint of the file into a new process, which is running when this returns
+    pub fn restore(&self, path: &str) -> Result<RestoredProcess, Error> {
+        let request = Request::new(RequestType::Restore).with_path(path)?;
+
+        let ((_, lost_handles), mut reply) = self.call(request)?;
+        let process =
+            Process::from_handle(reply.take_handle(0)).map_err(|_| Error::InvalidArgument)?;
+
+        Ok(RestoredProcess {
+            process,
+            lost_handles,
+        })
+    }
+
+    fn call(&self, request: Request) -> Result<((usize, usize), Message), Error> {
+        let mut handles = [self.reply_sender.clone().into_handle(), Handle::invalid()];
+
+        let mut message = unsafe { Message::new(&request, &mut handles) };
+        self.server.send(&mut message)?;
+
+        let reply = self.reply_receiver.blocking_receive()?;
+        failure::check_reply(&reply)?;
+        let result = unsafe { reply.data::<Reply>() }.result()?;
+        Ok((result, reply))
+    }
+}
+
+// Make sure the protocol fits in messages
+const _: () = assert!(mem::size_of::<Request>() <= Message::DATA_SIZE);
+const _: () = assert!(mem::size_of::<Reply>() <= Message::DATA_SIZE);
@@ -0,0 +1,304 @@
+//! Clipboard protocol and client
+//!
+//! The clipboard server (`servers/clipboard`) holds one typed payload at a time, stored in a memory object.
+//!
+//! Ownership of the payload is transferred:
+//! - `set` gives the memory object to the server: the client must not modify it afterwards
+//! - `get` shares a read-only handle of the memory object (mapping it writable fails)
+//! - `take` moves the payload out of the clipboard, which becomes empty
+//!
+//! Each new content gets a new serial, so that clients can detect changes.
+
+use core::{mem, slice};
+
+use alloc::vec::Vec;
+
+use crate::failure;
+use crate::kobject::{
+    Error, Handle, MemoryObject, Message, Permissions, Port, PortReceiver, PortSender, Process,
+    PAGE_SIZE,
+};
+
+/// Name of the port of the server
+pub const SERVER_PORT_NAME: &str = "clipboard";
+
+/// Maximum length of a payload type (MIME type, eg: `text/plain`)
+pub const MIME_TYPE_LEN: usize = 32;
+
+/// Type of the requests to the server
+#[repr(u64)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RequestType {
+    /// Replace the content with the memory object in handle 1
+    Set = 1,
+    /// Get the content, shared
+    Get,
+    /// Get the content, and empty the clipboard
+    Take,
+    /// Empty the clipboard
+    Clear,
+    /// Get the type, size and serial of the content, without the memory object
+    Info,
+}
+
+impl TryFrom<u64> for RequestType {
+    type Error = Error;
+
+    fn try_from(value: u64) -> Result<Self, Self::Error> {
+        match value {
+            1 => Ok(Self::Set),
+            2 => Ok(Self::Get),
+            3 => Ok(Self::Take),
+            4 => Ok(Self::Clear),
+            5 => Ok(Self::Info),
+            _ => Err(Error::InvalidArgument),
+        }
+    }
+}
+
+/// Description of a payload
+#[derive(Debug, Clone, Copy)]
+#[repr(C)]
+pub struct PayloadInfo {
+    /// Size of the payload in bytes (the memory object may be larger)
+    pub size: u64,
+    /// Serial of the content, incremented on each `Set`
+    pub serial: u64,
+    pub mime_type_len: u64,
+    pub mime_type: [u8; MIME_TYPE_LEN],
+}
+
+impl PayloadInfo {
+    pub fn new(mime_type: &str, size: usize) -> Result<Self, Error> {
+        if mime_type.is_empty() || mime_type.len() > MIME_TYPE_LEN {
+            return Err(Error::InvalidArgument);
+        }
+
+        let mut info = Self {
+            size: size as u64,
+            serial: 0,
+            mime_type_len: mime_type.len() as u64,
+            mime_type: [0; MIME_TYPE_LEN],
+        };
+
+        info.mime_type[..mime_type.len()].copy_from_slice(mime_type.as_bytes());
+
+        Ok(info)
+    }
+
+    /// Get the payload type
+    pub fn mime_type(&self) -> Result<&str, Error> {
+        let len = self.mime_type_len as usize;
+        if len == 0 || len > MIME_TYPE_LEN {
+            return Err(Error::InvalidArgument);
+        }
+
+        core::str::from_utf8(&self.mime_type[..len]).map_err(|_| Error::InvalidArgument)
+    }
+}
+
+/// Request to the server
+///
+/// Handle 0 is the port to send the reply to. `Set` requests carry the payload memory object as handle 1.
+#[derive(Debug, Clone, Copy)]
+#[repr(C)]
+pub struct Request {
+    pub r#type: u64,
+    /// Used by `Set`
+    pub info: PayloadInfo,
+}
+
+/// Reply of the server
+///
+/// Replies to `Get` and `Take` carry the payload memory object as handle 0.
+#[derive(Debug, Clone, Copy)]
+#[repr(C)]
+pub struct Reply {
+    /// 0 on success, else the error code
+    pub status: u64,
+    /// Description of the content, on success (except for `Clear`)
+    pub info: PayloadInfo,
+}
+
+impl Reply {
+    /// Build the reply of a request result
+    pub fn new(result: Result<PayloadInfo, Error>) -> Self {
+        match result {
+            Ok(info) => Self { status: 0, info },
+            Err(err) => Self {
+                status: err as u64,
+                info: PayloadInfo {
+                    size: 0,
+                    serial: 0,
+                    mime_type_len: 0,
+                    mime_type: [0; MIME_TYPE_LEN],
+                },
+            },
+        }
+    }
+
+    /// Get the result of the request
+    pub fn result(&self) -> Result<PayloadInfo, Error> {
+        match self.status {
+            0 => Ok(self.info),
+            status if status <= Error::LAST as u64 => {
+                // Note: safe since it is in the range of error codes
+                Err(unsafe { mem::transmute::<usize, Error>(status as usize) })
+            }
+            _ => Err(Error::InvalidArgument),
+        }
+    }
+}
+
+/// Content of the clipboard
+#[derive(Debug)]
+pub struct Payload {
+    pub info: PayloadInfo,
+    pub object: MemoryObject,
+}
+
+impl Payload {
+    /// Copy the payload data
+    pub fn read(&self) -> Result<Vec<u8>, Error> {
+        let len = self.info.size as usize;
+        if len == 0 {
+            return Ok(Vec::new());
+        }
+
+        let size = len.next_multiple_of(PAGE_SIZE);
+        let mapping = Process::current().map_mem(None, size, Permissions::READ, &self.object, 0)?;
+
+        let data = unsafe { slice::from_raw_parts(mapping.address() as *const u8, len) };
+        Ok(Vec::from(data))
+    }
+}
+
+/// Connection to the clipboard server
+#[derive(Debug)]
+pub struct Clipboard {
+    server: PortSender,
+    reply_receiver: PortReceiver,
+    reply_sender: PortSender,
+}
+
+impl Clipboard {
+    /// Connect to the server
+    pub fn connect() -> Result<Self, Error> {
+        Self::connect_to(SERVER_PORT_NAME)
+    }
+
+    /// Connect to a server listening on another port (eg: a private instance in a test)
+    pub fn connect_to(port_name: &str) -> Result<Self, Error> {
+        let server = Port::open(port_name)?;
+        let (reply_receiver, reply_sender) = Port::create(None)?;
+
+        Ok(Self {
+            server,
+            reply_receiver,
+            reply_sender,
+        })
+    }
+
+    /// Copy `data` into a new memory object, and set it as the content
+    ///
+    /// Returns the serial of the new content.
+    pub fn set(&self, mime_type: &str, data: &[u8]) -> Result<u64, Error> {
+        let size = data.len().max(1).next_multiple_of(PAGE_SIZE);
+        let object = MemoryObject::create(size)?;
+
+        {
+            let mapping = Process::current().map_mem(
+                None,
+                size,
+                Permissions::READ | Permissions::WRITE,
+                &object,
+                0,
+            )?;
+
+            let dest =
+                unsafe { slice::from_raw_parts_mut(mapping.address() as *mut u8, data.len()) };
+            dest.copy_from_slice(data);
+        }
+
+        self.set_object(mime_type, object, data.len())
+    }
+
+    /// Set a memory object as the content, without copy
+    ///
+    /// Ownership is transferred to the server: the object must not be modified afterwards.
+    /// Returns the serial of the new content.
+    pub fn set_object(
+        &self,
+        mime_type: &str,
+        object: MemoryObject,
+        size: usize,
+    ) -> Result<u64, Error> {
+        let request = Request {
+            r#type: RequestType::Set as u64,
+            info: PayloadInfo::new(mime_type, size)?,
+        };
+
+        let (info, _) = self.call(&request, object.into_handle())?;
+        Ok(info.serial)
+    }
+
+    /// Get the content, shared with the clipboard
+    ///
+    /// Returns `ObjectNotFound` if the clipboard is empty.
+    pub fn get(&self) -> Result<Payload, Error> {
+        self.call_payload(RequestType::Get)
+    }
+
+    /// Get the content, and empty the clipboard
+    ///
+    /// Returns `ObjectNotFound` if the clipboard is empty.
+    pub fn take(&self) -> Result<Payload, Error> {
+        self.call_payload(RequestType::Take)
+    }
+
+    /// Empty the clipboard
+    pub fn clear(&self) -> Result<(), Error> {
+        self.call(&Self::request(RequestType::Clear), Handle::invalid())?;
+        Ok(())
+    }
+
+    /// Get the type, size and serial of the content
+    ///
+    /// Returns `ObjectNotFound` if the clipboard is empty.
+    pub fn info(&self) -> Result<PayloadInfo, Error> {
+        let (info, _) = self.call(&Self::request(RequestType::Info), Handle::invalid())?;
+        Ok(info)
+    }
+
+    fn request(r#type: RequestType) -> Request {
+        Request {
+            r#type: r#type as u64,
+            info: Reply::new(Err(Error::ObjectNotFound)).info,
+        }
+    }
+
+    fn call_payload(&self, r#type: RequestType) -> Result<Payload, Error> {
+        let (info, mut reply) = self.call(&Self::request(r#type), Handle::invalid())?;
+
+        let object =
+            MemoryObject::from_handle(reply.take_handle(0)).map_err(|_| Error::InvalidArgument)?;
+
+        Ok(Payload { info, object })
+    }
+
+    fn call(&self, request: &Request, object: Handle) -> Result<(PayloadInfo, Message), Error> {
+        let mut handles = [self.reply_sender.clone().into_handle(), object];
+
+        let mut message = unsafe { Message::new(request, &mut handles) };
+        self.server.send(&mut message)?;
+
+        let reply = self.reply_receiver.blocking_receive()?;
+        failure::check_reply(&reply)?;
+        let info = unsafe { reply.data::<Reply>() }.result()?;
+        Ok((info, reply))
+    }
+}
+
+// Make sure the protocol fits in messages
+const _: () = assert!(mem::size_of::<Request>() <= Message::DATA_SIZE);
+const _: () = assert!(mem::size_of::<Reply>() <= Message::DATA_SIZE);
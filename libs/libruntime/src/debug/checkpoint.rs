@@ -0,0 +1,628 @@
+//! Checkpoint/restore of processes (eg: fast startup of test fixtures)
+//!
+//! A checkpoint of a suspended process holds its address space (mappings, their names and their memory),
+//! the contexts of its threads, and its handle table. `to_bytes` and `parse` convert it to a file,
+//! `restore` builds a new process from it, with the same addresses and handle values.
+//!
+//! Limits:
+//! - threads blocked in a syscall have their state in the kernel: a checkpoint with such threads cannot be restored
+//! - processes, threads and port senders are opened again (by id, the own process and threads of the checkpoint map to
+//!   the restored ones); the other handles (port receivers, memory objects, listeners, timers, grants) are lost
+//! - memory is restored private: memory objects shared with other processes are copied
+//! - stacks are restored fully committed, unreadable memory is restored zero-filled
+
+use core::mem;
+
+use alloc::{collections::BTreeMap, string::String, vec, vec::Vec};
+use libsyscalls::thread;
+
+use crate::kobject::{
+    Error, Handle, HandleInfo, HandleType, KObject, MappingInfo, MemoryObject, Permissions, Port,
+    Process, Thread, ThreadContext, ThreadContextRegister, ThreadPriority, ThreadState,
+    ThreadSupervisor, TypedHandle,
+};
+
+/// Extension of checkpoint files
+pub const CHECKPOINT_EXTENSION: &str = ".ckpt";
+
+/// Magic of checkpoint files, with the format version
+const MAGIC: &[u8; 8] = b"MTICKPT1";
+
+const MAPPING_HAS_MEMORY_OBJECT: u64 = 1 << 0;
+const MAPPING_GROWS_DOWN: u64 = 1 << 1;
+const MAPPING_HAS_DATA: u64 = 1 << 2;
+
+/// Number of registers of a thread context
+const REG_COUNT: usize = 19;
+
+/// Checkpoint of a mapping of a suspended process
+#[derive(Debug, Clone)]
+pub struct MappingCheckpoint {
+    pub info: MappingInfo,
+    /// Memory content, none for reservations and unreadable memory
+    pub data: Option<Vec<u8>>,
+}
+
+/// Checkpoint of a thread of a suspended process
+#[derive(Debug)]
+pub struct ThreadCheckpoint {
+    pub tid: u64,
+    pub name: Option<String>,
+    pub priority: ThreadPriority,
+    pub privileged: bool,
+    pub state: ThreadState,
+    /// None if the thread is blocked in a syscall: its state is in the kernel
+    pub context: Option<ThreadContext>,
+}
+
+/// Checkpoint of a suspended process: its mappings and their memory, the contexts of its threads, and its handles
+#[derive(Debug)]
+pub struct Checkpoint {
+    pub pid: u64,
+    pub name: String,
+    pub mappings: Vec<MappingCheckpoint>,
+    pub threads: Vec<ThreadCheckpoint>,
+    pub handles: Vec<HandleInfo>,
+}
+
+/// Processes and threads restored so far, by their captured ids
+///
+/// Handles to them are installed to the restored objects, the others are opened again by id.
+#[derive(Debug, Default)]
+pub(super) struct RestoredObjects {
+    processes: BTreeMap<u64, Handle>,
+    threads: BTreeMap<u64, Handle>,
+}
+
+impl RestoredObjects {
+    /// Open the object of a captured handle again, if possible
+    fn reopen(&self, info: &HandleInfo) -> Option<Handle> {
+        match info.r#type {
+            HandleType::Process => match self.processes.get(&info.object_id) {
+                Some(handle) => Some(handle.clone()),
+                None => Process::open(info.object_id)
+                    .ok()
+                    .map(|process| unsafe { process.handle() }.as_handle().clone()),
+            },
+            HandleType::Thread => match self.threads.get(&info.object_id) {
+                Some(handle) => Some(handle.clone()),
+                None => Thread::open(info.object_id)
+                    .ok()
+                    .map(|thread| unsafe { thread.handle() }.as_handle().clone()),
+            },
+            HandleType::PortSender => Port::open_id(info.object_id)
+                .ok()
+                .map(|port| port.into_handle()),
+            _ => None,
+        }
+    }
+}
+
+/// Process restored from a checkpoint
+#[derive(Debug)]
+pub struct Restored {
+    /// The restored process, still suspended: resume it to run it
+    pub process: Process,
+    /// Handles which could not be opened again: their values are invalid in the restored process
+    pub lost_handles: Vec<HandleInfo>,
+}
+
+impl Checkpoint {
+    /// Capture the checkpoint of the process, which must be suspended (see `Process::suspend`)
+    ///
+    /// Note: the caller must have the `DEBUG` sandbox right (see `Process::read_memory`).
+    pub fn capture(process: &Process) -> Result<Self, Error> {
+        let info = process.info();
+        if !info.suspended {
+            return Err(Error::InvalidArgument);
+        }
+
+        let mut mappings = Vec::new();
+
+        for mapping in process.mappings()?.iter() {
+            let data = if mapping.has_memory_object && mapping.perms.contains(Permissions::READ) {
+                let mut data = vec![0; mapping.size];
+                process.read_memory(mapping.address, &mut data)?;
+                Some(data)
+            } else {
+                None
+            };
+
+            mappings.push(MappingCheckpoint {
+                info: *mapping,
+                data,
+            });
+        }
+
+        let mut threads = Vec::new();
+
+        for &tid in process.threads()?.iter() {
+            let thread = Thread::open(tid)?;
+            let info = thread.info();
+
+            let context = if info.state == ThreadState::Waiting {
+                None
+            } else {
+                Some(thread.context()?)
+            };
+
+            threads.push(ThreadCheckpoint {
+                tid,
+                name: thread.name().ok().filter(|name| !name.is_empty()),
+                priority: info.priority,
+                privileged: info.privileged,
+                state: info.state,
+                context,
+            });
+        }
+
+        Ok(Self {
+            pid: info.pid,
+            name: process.name()?,
+            mappings,
+            threads,
+            handles: process.handles()?.into_vec(),
+        })
+    }
+
+    /// Restore the checkpoint in a new process
+    ///
+    /// The process is returned suspended. Fails with `Error::ObjectNotReady` if a thread was blocked in a syscall.
+    /// Note: the caller must be allowed to create processes, and have the `DEBUG` sandbox right (to set the thread contexts).
+    pub fn restore(&self) -> Result<Restored, Error> {
+        let mut objects = RestoredObjects::default();
+        let process = self.restore_objects(&mut objects)?;
+        let lost_handles = self.restore_handles(&process, &objects)?;
+
+        Ok(Restored {
+            process,
+            lost_handles,
+        })
+    }
+
+    /// Whether the checkpoint can be restored: no thread was blocked in a syscall
+    pub fn is_restorable(&self) -> bool {
+        self.threads.iter().all(|thread| thread.context.is_some())
+    }
+
+    /// Restore the process, its mappings and its threads, and record them in `objects`
+    pub(super) fn restore_objects(&self, objects: &mut RestoredObjects) -> Result<Process, Error> {
+        if !self.is_restorable() {
+            return Err(Error::ObjectNotReady);
+        }
+
+        let process = Process::create(&self.name)?;
+
+        // No thread yet: the ones created below are not scheduled until the process is resumed
+        process.suspend()?;
+
+        for mapping in self.mappings.iter() {
+            restore_mapping(&process, mapping)?;
+        }
+
+        objects
+            .processes
+            .insert(self.pid, unsafe { process.handle() }.as_handle().clone());
+
+        for checkpoint in self.threads.iter() {
+            let thread = restore_thread(&process, checkpoint)?;
+            objects.threads.insert(
+                checkpoint.tid,
+                unsafe { thread.handle() }.as_handle().clone(),
+            );
+        }
+
+        Ok(process)
+    }
+
+    /// Install the handles of the checkpoint in the restored process, and get the ones which could not be opened again
+    pub(super) fn restore_handles(
+        &self,
+        process: &Process,
+        objects: &RestoredObjects,
+    ) -> Result<Vec<HandleInfo>, Error> {
+        let mut lost_handles = Vec::new();
+
+        for info in self.handles.iter() {
+            match objects.reopen(info) {
+                Some(source) => process.install_handle(info.handle, &source)?,
+                None => lost_handles.push(*info),
+            }
+        }
+
+        Ok(lost_handles)
+    }
+
+    /// Serialize the checkpoint, to store it in a file
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut writer = Writer(Vec::new());
+
+        writer.bytes(MAGIC);
+        writer.u64(self.pid);
+        writer.str(&self.name);
+
+        writer.u64(self.mappings.len() as u64);
+        for mapping in self.mappings.iter() {
+            let info = &mapping.info;
+            let mut flags = 0;
+            if info.has_memory_object {
+                flags |= MAPPING_HAS_MEMORY_OBJECT;
+            }
+            if info.grows_down {
+                flags |= MAPPING_GROWS_DOWN;
+            }
+            if mapping.data.is_some() {
+                flags |= MAPPING_HAS_DATA;
+            }
+
+            writer.u64(info.address as u64);
+            writer.u64(info.size as u64);
+            writer.u64(info.perms.bits());
+            writer.u64(flags);
+            writer.str(info.name().unwrap_or(""));
+            if let Some(data) = &mapping.data {
+                writer.bytes(data);
+            }
+        }
+
+        writer.u64(self.threads.len() as u64);
+        for thread in self.threads.iter() {
+            writer.u64(thread.tid);
+            writer.str(thread.name.as_deref().unwrap_or(""));
+            writer.u64(thread.priority as u64);
+            writer.u64(thread.privileged as u64);
+            writer.u64(thread.state as u64);
+            writer.u64(thread.context.is_some() as u64);
+            if let Some(context) = &thread.context {
+                for value in registers_of(context) {
+                    writer.u64(value as u64);
+                }
+            }
+        }
+
+        writer.u64(self.handles.len() as u64);
+        for handle in self.handles.iter() {
+            writer.u64(handle.handle);
+            writer.u64(handle.r#type as u64);
+            writer.u64(handle.object_id);
+        }
+
+        writer.0
+    }
+
+    /// Read a checkpoint file
+    ///
+    /// Fails with `Error::InvalidArgument` if the data is not a valid checkpoint.
+    pub fn parse(data: &[u8]) -> Result<Self, Error> {
+        let mut reader = Reader::new(data);
+
+        if reader.bytes(MAGIC.len())? != MAGIC {
+            return Err(Error::InvalidArgument);
+        }
+
+        let pid = reader.u64()?;
+        let name = String::from(reader.str()?);
+
+        let mut mappings = Vec::new();
+        for _ in 0..reader.u64()? {
+            let address = reader.u64()? as usize;
+            let size = reader.u64()? as usize;
+            let perms = Permissions::from_bits(reader.u64()?).ok_or(Error::InvalidArgument)?;
+            let flags = reader.u64()?;
+            let name = reader.str()?;
+            let data = if flags & MAPPING_HAS_DATA != 0 {
+                Some(Vec::from(reader.bytes(size)?))
+            } else {
+                None
+            };
+
+            let info = MappingInfo::new(
+                address,
+                size,
+                perms,
+                flags & MAPPING_HAS_MEMORY_OBJECT != 0,
+                flags & MAPPING_GROWS_DOWN != 0,
+                (!name.is_empty()).then_some(name),
+            );
+
+            mappings.push(MappingCheckpoint { info, data });
+        }
+
+        let mut threads = Vec::new();
+        for _ in 0..reader.u64()? {
+            let tid = reader.u64()?;
+            let name = reader.str()?;
+            let priority = priority_of(reader.u64()?)?;
+            let privileged = reader.u64()? != 0;
+            let state = state_of(reader.u64()?)?;
+            let context = if reader.u64()? != 0 {
+                let mut registers = [0; REG_COUNT];
+                for register in registers.iter_mut() {
+                    *register = reader.u64()? as usize;
+                }
+                Some(context_of(&registers))
+            } else {
+                None
+            };
+
+            threads.push(ThreadCheckpoint {
+                tid,
+                name: (!name.is_empty()).then(|| String::from(name)),
+                priority,
+                privileged,
+                state,
+                context,
+            });
+        }
+
+        let mut handles = Vec::new();
+        for _ in 0..reader.u64()? {
+            handles.push(HandleInfo {
+                handle: reader.u64()?,
+                r#type: handle_type_of(reader.u64()?)?,
+                object_id: reader.u64()?,
+            });
+        }
+
+        Ok(Self {
+            pid,
+            name,
+            mappings,
+            threads,
+            handles,
+        })
+    }
+}
+
+fn restore_mapping(process: &Process, mapping: &MappingCheckpoint) -> Result<(), Error> {
+    let info = &mapping.info;
+    let range = info.address..(info.address + info.size);
+
+    if !info.has_memory_object && !info.grows_down {
+        // Reservation (eg: guard page)
+        process
+            .map_reserve(Some(info.address), info.size)?
+            .into_raw();
+    } else {
+        // The kernel only grows the stacks it created: commit them whole
+        let perms = if info.grows_down {
+            Permissions::READ | Permissions::WRITE
+        } else {
+            info.perms
+        };
+
+        let mobj = MemoryObject::create(info.size)?;
+
+        if let Some(data) = &mapping.data {
+            let local = Process::current().map_mem(
+                None,
+                info.size,
+                Permissions::READ | Permissions::WRITE,
+                &mobj,
+                0,
+            )?;
+            let buffer = unsafe { local.as_buffer_mut() }.expect("could not access mapping");
+            buffer[..data.len()].copy_from_slice(data);
+        }
+
+        // Owned by the new process from now on
+        process
+            .map_mem(Some(info.address), info.size, perms, &mobj, 0)?
+            .into_raw();
+    }
+
+    if let Some(name) = info.name() {
+        process.name_mem(&range, Some(name))?;
+    }
+
+    Ok(())
+}
+
+fn restore_thread(process: &Process, checkpoint: &ThreadCheckpoint) -> Result<Thread, Error> {
+    let context = checkpoint.context.as_ref().expect("thread without context");
+
+    // The entry point is overwritten with the whole context below, before the thread runs
+    let entry_point =
+        unsafe { mem::transmute::<usize, extern "C" fn(usize) -> !>(context.instruction_pointer) };
+
+    let handle = thread::create(
+        checkpoint.name.as_deref(),
+        unsafe { process.handle() },
+        checkpoint.privileged,
+        checkpoint.priority,
+        entry_point,
+        context.rsp,
+        context.rdi,
+        context.tls,
+    )?;
+
+    let thread = Thread::from_handle(handle.into_handle()).map_err(|_| Error::InvalidArgument)?;
+
+    let registers = [
+        (ThreadContextRegister::Rax, context.rax),
+        (ThreadContextRegister::Rcx, context.rcx),
+        (ThreadContextRegister::Rdx, context.rdx),
+        (ThreadContextRegister::Rbx, context.rbx),
+        (ThreadContextRegister::Rsi, context.rsi),
+        (ThreadContextRegister::Rdi, context.rdi),
+        (ThreadContextRegister::Rsp, context.rsp),
+        (ThreadContextRegister::Rbp, context.rbp),
+        (ThreadContextRegister::R8, context.r8),
+        (ThreadContextRegister::R9, context.r9),
+        (ThreadContextRegister::R10, context.r10),
+        (ThreadContextRegister::R11, context.r11),
+        (ThreadContextRegister::R12, context.r12),
+        (ThreadContextRegister::R13, context.r13),
+        (ThreadContextRegister::R14, context.r14),
+        (ThreadContextRegister::R15, context.r15),
+        (
+            ThreadContextRegister::InstructionPointer,
+            context.instruction_pointer,
+        ),
+        (ThreadContextRegister::CpuFlags, context.cpu_flags),
+        (ThreadContextRegister::TLS, context.tls),
+    ];
+
+    ThreadSupervisor::new(&thread).update_context(&registers)?;
+
+    Ok(thread)
+}
+
+/// Get the registers of a context, in declaration order
+fn registers_of(context: &ThreadContext) -> [usize; REG_COUNT] {
+    [
+        context.rax,
+        context.rcx,
+        context.rdx,
+        context.rbx,
+        context.rsi,
+        context.rdi,
+        context.rsp,
+        context.rbp,
+        context.r8,
+        context.r9,
+        context.r10,
+        context.r11,
+        context.r12,
+        context.r13,
+        context.r14,
+        context.r15,
+        context.instruction_pointer,
+        context.cpu_flags,
+        context.tls,
+    ]
+}
+
+/// Build a context from registers in declaration order
+fn context_of(registers: &[usize; REG_COUNT]) -> ThreadContext {
+    let [rax, rcx, rdx, rbx, rsi, rdi, rsp, rbp, r8, r9, r10, r11, r12, r13, r14, r15, instruction_pointer, cpu_flags, tls] =
+        *registers;
+
+    ThreadContext {
+        rax,
+        rcx,
+        rdx,
+        rbx,
+        rsi,
+        rdi,
+        rsp,
+        rbp,
+        r8,
+        r9,
+        r10,
+        r11,
+        r12,
+        r13,
+        r14,
+        r15,
+        instruction_pointer,
+        cpu_flags,
+        tls,
+    }
+}
+
+fn priority_of(value: u64) -> Result<ThreadPriority, Error> {
+    const PRIORITIES: [ThreadPriority; 7] = [
+        ThreadPriority::Idle,
+        ThreadPriority::Lowest,
+        ThreadPriority::BelowNormal,
+        ThreadPriority::Normal,
+        ThreadPriority::AboveNormal,
+        ThreadPriority::Highest,
+        ThreadPriority::TimeCritical,
+    ];
+
+    PRIORITIES
+        .into_iter()
+        .find(|&priority| priority as u64 == value)
+        .ok_or(Error::InvalidArgument)
+}
+
+fn state_of(value: u64) -> Result<ThreadState, Error> {
+    const STATES: [ThreadState; 6] = [
+        ThreadState::Executing,
+        ThreadState::Ready,
+        ThreadState::Waiting,
+        ThreadState::Error,
+        ThreadState::Terminated,
+        ThreadState::Suspended,
+    ];
+
+    STATES
+        .into_iter()
+        .find(|&state| state as u64 == value)
+        .ok_or(Error::InvalidArgument)
+}
+
+fn handle_type_of(value: u64) -> Result<HandleType, Error> {
+    const TYPES: [HandleType; 12] = [
+        HandleType::Invalid,
+        HandleType::MemoryObject,
+        HandleType::Process,
+        HandleType::Thread,
+        HandleType::PortSender,
+        HandleType::PortReceiver,
+        HandleType::ProcessListener,
+        HandleType::ThreadListener,
+        HandleType::Timer,
+        HandleType::PortListener,
+        HandleType::Grant,
+        HandleType::Trampoline,
+    ];
+
+    TYPES
+        .into_iter()
+        .find(|&r#type| r#type as u64 == value)
+        .ok_or(Error::InvalidArgument)
+}
+
+/// Little endian writer of checkpoint files
+pub(super) struct Writer(pub Vec<u8>);
+
+impl Writer {
+    pub fn u64(&mut self, value: u64) {
+        self.0.extend_from_slice(&value.to_le_bytes());
+    }
+
+    pub fn bytes(&mut self, value: &[u8]) {
+        self.0.extend_from_slice(value);
+    }
+
+    fn str(&mut self, value: &str) {
+        self.u64(value.len() as u64);
+        self.bytes(value.as_bytes());
+    }
+}
+
+/// Little endian reader of checkpoint files
+pub(super) struct Reader<'a> {
+    data: &'a [u8],
+    offset: usize,
+}
+
+impl<'a> Reader<'a> {
+    pub fn new(data: &'a [u8]) -> Self {
+        Self { data, offset: 0 }
+    }
+
+    pub fn bytes(&mut self, len: usize) -> Result<&'a [u8], Error> {
+        let end = self.offset.checked_add(len).ok_or(Error::InvalidArgument)?;
+        let bytes = self
+            .data
+            .get(self.offset..end)
+            .ok_or(Error::InvalidArgument)?;
+
+        self.offset = end;
+        Ok(bytes)
+    }
+
+    pub fn u64(&mut self) -> Result<u64, Error> {
+        let bytes = self.bytes(8)?;
+        Ok(u64::from_le_bytes(bytes.try_into().unwrap()))
+    }
+
+    fn str(&mut self) -> Result<&'a str, Error> {
+        let len = self.u64()? as usize;
+        core::str::from_utf8(self.bytes(len)?).map_err(|_| Error::InvalidArgument)
+    }
+}
@@ -0,0 +1,855 @@
+//! Crash dumps: ELF core files of processes
+//!
+//! A dump is a standard ELF core file (`ET_CORE`), which host gdb can read (`gdb <binary> <core>`):
+//! - a `PT_NOTE` segment with `NT_PRPSINFO` (pid, name), one `NT_PRSTATUS` per thread (tid, registers, signal),
+//!   `NT_FILE` (mappings named after their object, see `loader`), and a `MTI` note with the exception of the crashed thread
+//! - one `PT_LOAD` segment per mapping, with its content if it is readable
+//!
+//! Threads blocked in a syscall have their state in the kernel: they are not in the dump.
+//!
+//! `CoreDump` reads a dump back: threads and their registers, mapped regions, memory,
+//! and backtraces (frame pointers walk, symbolized with the binaries given to a `Symbolizer`).
+
+use core::{fmt, mem::size_of, str};
+
+use alloc::{
+    collections::BTreeMap,
+    format,
+    string::{String, ToString},
+    vec,
+    vec::Vec,
+};
+use log::debug;
+
+use super::debugsym::Finder;
+use crate::kobject::{Error, Exception, Permissions, Process, Thread, ThreadContext, PAGE_SIZE};
+
+/// Directory of the vfs where crash dumps are stored
+pub const CRASH_DIR: &str = "/crash";
+
+/// Extension of crash dump files
+pub const CORE_EXTENSION: &str = ".core";
+
+/// Maximum number of frames of a backtrace
+const MAX_FRAMES: usize = 64;
+
+// ELF layout
+const ELF_HEADER_SIZE: usize = 64;
+const PROGRAM_HEADER_SIZE: usize = 56;
+const ET_CORE: u16 = 4;
+const EM_X86_64: u16 = 62;
+const PT_LOAD: u32 = 1;
+const PT_NOTE: u32 = 4;
+const PF_X: u32 = 1;
+const PF_W: u32 = 2;
+const PF_R: u32 = 4;
+
+// Notes
+const NOTE_CORE: &str = "CORE";
+const NOTE_MTI: &str = "MTI";
+const NT_PRSTATUS: u32 = 1;
+const NT_PRPSINFO: u32 = 3;
+const NT_FILE: u32 = 0x4649_4C45;
+const NT_MTI_EXCEPTION: u32 = 1;
+
+// `elf_prstatus` and `elf_prpsinfo` of x86_64
+const PRSTATUS_SIZE: usize = 336;
+const PRSTATUS_SIGNAL: usize = 0;
+const PRSTATUS_CURSIG: usize = 12;
+const PRSTATUS_PID: usize = 32;
+const PRSTATUS_REGS: usize = 112;
+const PRPSINFO_SIZE: usize = 136;
+const PRPSINFO_PID: usize = 24;
+const PRPSINFO_FNAME: usize = 40;
+const PRPSINFO_FNAME_LEN: usize = 16;
+const PRPSINFO_ARGS: usize = 56;
+const PRPSINFO_ARGS_LEN: usize = 80;
+
+// Index of the registers in `user_regs_struct`
+const REG_R15: usize = 0;
+const REG_R14: usize = 1;
+const REG_R13: usize = 2;
+const REG_R12: usize = 3;
+const REG_RBP: usize = 4;
+const REG_RBX: usize = 5;
+const REG_R11: usize = 6;
+const REG_R10: usize = 7;
+const REG_R9: usize = 8;
+const REG_R8: usize = 9;
+const REG_RAX: usize = 10;
+const REG_RCX: usize = 11;
+const REG_RDX: usize = 12;
+const REG_RSI: usize = 13;
+const REG_RDI: usize = 14;
+const REG_RIP: usize = 16;
+const REG_EFLAGS: usize = 18;
+const REG_RSP: usize = 19;
+const REG_FS_BASE: usize = 21;
+const REG_COUNT: usize = 27;
+
+// Signals reported to gdb
+const SIGILL: u32 = 4;
+const SIGTRAP: u32 = 5;
+const SIGABRT: u32 = 6;
+const SIGFPE: u32 = 8;
+const SIGSEGV: u32 = 11;
+
+/// Capture the dump of a process
+///
+/// The process should be suspended (see `Process::suspend`), so that the dump is consistent.
+/// `crashed` is the thread which triggered the dump, and its exception.
+/// Note: the caller needs the `DEBUG` sandbox right to read the memory of other processes.
+pub fn capture_core(
+    process: &Process,
+    crashed: Option<(u64, Exception)>,
+) -> Result<Vec<u8>, Error> {
+    let mut notes = Vec::new();
+
+    let name = process.name()?;
+    let mut prpsinfo = [0u8; PRPSINFO_SIZE];
+    put_u32(&mut prpsinfo, PRPSINFO_PID, process.pid() as u32);
+    put_str(&mut prpsinfo, PRPSINFO_FNAME, PRPSINFO_FNAME_LEN, &name);
+    put_str(&mut prpsinfo, PRPSINFO_ARGS, PRPSINFO_ARGS_LEN, &name);
+    push_note(&mut notes, NOTE_CORE, NT_PRPSINFO, &prpsinfo);
+
+    // The crashed thread first: gdb selects it
+    let mut tids = Vec::from(&*process.threads()?);
+    if let Some((tid, _)) = crashed {
+        tids.sort_by_key(|&other| other != tid);
+    }
+
+    for tid in tids {
+        let context = match Thread::open(tid).and_then(|thread| thread.context()) {
+            Ok(context) => context,
+            Err(err) => {
+                debug!("coredump: skipping thread {}: {:?}", tid, err);
+                continue;
+            }
+        };
+
+        let signal = match crashed {
+            Some((crashed_tid, exception)) if crashed_tid == tid => exception_signal(&exception),
+            _ => 0,
+        };
+
+        let mut prstatus = [0u8; PRSTATUS_SIZE];
+        put_u32(&mut prstatus, PRSTATUS_SIGNAL, signal);
+        prstatus[PRSTATUS_CURSIG..PRSTATUS_CURSIG + 2]
+            .copy_from_slice(&(signal as u16).to_le_bytes());
+        put_u32(&mut prstatus, PRSTATUS_PID, tid as u32);
+        for (index, value) in registers_of(&context).iter().enumerate() {
+            put_u64(&mut prstatus, PRSTATUS_REGS + index * 8, *value);
+        }
+        push_note(&mut notes, NOTE_CORE, NT_PRSTATUS, &prstatus);
+    }
+
+    if let Some((tid, exception)) = crashed {
+        let mut desc = Vec::from(tid.to_le_bytes());
+        desc.extend_from_slice(format!("{:?}", exception).as_bytes());
+        push_note(&mut notes, NOTE_MTI, NT_MTI_EXCEPTION, &desc);
+    }
+
+    // Mappings content
+    let mappings = process.mappings()?;
+    let mut regions = Vec::new();
+    let mut files = Vec::new();
+
+    for mapping in mappings.iter() {
+        let mut data = Vec::new();
+
+        if mapping.has_memory_object && mapping.perms.contains(Permissions::READ) {
+            data.resize(mapping.size, 0);
+            if let Err(err) = process.read_memory(mapping.address, &mut data) {
+                debug!(
+                    "coredump: could not read mapping at {:#x}: {:?}",
+                    mapping.address, err
+                );
+                data.clear();
+            }
+        }
+
+        let name = mapping_name(&mapping.name);
+        if !name.is_empty() {
+            files.push((mapping.address, mapping.size, name));
+        }
+
+        regions.push((mapping.address, mapping.size, mapping.perms, data));
+    }
+
+    let mut file_note = Vec::new();
+    file_note.extend_from_slice(&(files.len() as u64).to_le_bytes());
+    file_note.extend_from_slice(&(PAGE_SIZE as u64).to_le_bytes());
+    for (address, size, _) in files.iter() {
+        file_note.extend_from_slice(&(*address as u64).to_le_bytes());
+        file_note.extend_from_slice(&((address + size) as u64).to_le_bytes());
+        file_note.extend_from_slice(&0u64.to_le_bytes());
+    }
+    for (_, _, name) in files.iter() {
+        file_note.extend_from_slice(name.as_bytes());
+        file_note.push(0);
+    }
+    push_note(&mut notes, NOTE_CORE, NT_FILE, &file_note);
+
+    // Layout: ELF header, program headers, notes, then the content of the regions
+    let phnum = 1 + regions.len();
+    let notes_offset = ELF_HEADER_SIZE + phnum * PROGRAM_HEADER_SIZE;
+    let mut data_offset = align8(notes_offset + notes.len());
+
+    let mut core = vec![0u8; data_offset];
+    write_elf_header(&mut core, phnum as u16);
+
+    let mut header = ProgramHeader {
+        r#type: PT_NOTE,
+        flags: PF_R,
+        offset: notes_offset,
+        vaddr: 0,
+        filesz: notes.len(),
+        memsz: 0,
+        align: 4,
+    };
+    header.write(&mut core, ELF_HEADER_SIZE);
+    core[notes_offset..notes_offset + notes.len()].copy_from_slice(&notes);
+
+    for (index, (address, size, perms, data)) in regions.iter().enumerate() {
+        header = ProgramHeader {
+            r#type: PT_LOAD,
+            flags: flags_of(*perms),
+            offset: data_offset,
+            vaddr: *address,
+            filesz: data.len(),
+            memsz: *size,
+            align: PAGE_SIZE,
+        };
+        header.write(
+            &mut core,
+            ELF_HEADER_SIZE + (index + 1) * PROGRAM_HEADER_SIZE,
+        );
+
+        core.extend_from_slice(data);
+        data_offset += data.len();
+    }
+
+    Ok(core)
+}
+
+/// Thread in a dump
+#[derive(Debug)]
+pub struct DumpedThread {
+    pub tid: u64,
+    /// Signal reported to gdb (0 if the thread did not crash)
+    pub signal: u32,
+    pub context: ThreadContext,
+}
+
+/// Mapped region in a dump
+#[derive(Debug, Clone)]
+pub struct Region {
+    pub address: usize,
+    pub size: usize,
+    pub perms: Permissions,
+    /// Name of the mapping, set by the loader to the name of the object (eg: `libruntime.so`)
+    pub name: Option<String>,
+    /// Part of the dump with the content of the region (empty if it was not readable)
+    data: core::ops::Range<usize>,
+}
+
+impl Region {
+    /// Check if the content of the region is in the dump
+    pub fn has_content(&self) -> bool {
+        !self.data.is_empty()
+    }
+}
+
+/// Crash dump, read from an ELF core file
+#[derive(Debug)]
+pub struct CoreDump<'a> {
+    data: &'a [u8],
+    pid: u64,
+    name: String,
+    /// Crashed thread and its exception
+    exception: Option<(u64, String)>,
+    threads: Vec<DumpedThread>,
+    regions: Vec<Region>,
+}
+
+impl<'a> CoreDump<'a> {
+    /// Parse a dump (see `capture_core`)
+    ///
+    /// Fails with `Error::InvalidArgument` if the data is not an x86_64 ELF core file.
+    pub fn parse(data: &'a [u8]) -> Result<Self, Error> {
+        if data.len() < ELF_HEADER_SIZE
+            || data[0..4] != [0x7F, b'E', b'L', b'F']
+            || data[4] != 2
+            || data[5] != 1
+            || get_u16(data, 16)? != ET_CORE
+            || get_u16(data, 18)? != EM_X86_64
+        {
+            return Err(Error::InvalidArgument);
+        }
+
+        let phoff = get_u64(data, 32)? as usize;
+        let phnum = get_u16(data, 56)? as usize;
+
+        let mut dump = Self {
+            data,
+            pid: 0,
+            name: String::new(),
+            exception: None,
+            threads: Vec::new(),
+            regions: Vec::new(),
+        };
+
+        let mut files = BTreeMap::new();
+
+        for index in 0..phnum {
+            let header = ProgramHeader::read(data, phoff + index * PROGRAM_HEADER_SIZE)?;
+            let content = header.offset..header.offset + header.filesz;
+            if content.end > data.len() {
+                return Err(Error::InvalidArgument);
+            }
+
+            match header.r#type {
+                PT_NOTE => dump.parse_notes(&data[content], &mut files)?,
+                PT_LOAD => dump.regions.push(Region {
+                    address: header.vaddr,
+                    size: header.memsz,
+                    perms: perms_of(header.flags),
+                    name: None,
+                    data: content,
+                }),
+                _ => {}
+            }
+        }
+
+        for region in dump.regions.iter_mut() {
+            region.name = files.get(&region.address).cloned();
+        }
+
+        Ok(dump)
+    }
+
+    fn parse_notes(
+        &mut self,
+        notes: &[u8],
+        files: &mut BTreeMap<usize, String>,
+    ) -> Result<(), Error> {
+        let mut offset = 0;
+
+        while offset + 12 <= notes.len() {
+            let namesz = get_u32(notes, offset)? as usize;
+            let descsz = get_u32(notes, offset + 4)? as usize;
+            let r#type = get_u32(notes, offset + 8)?;
+
+            let name_start = offset + 12;
+            let desc_start = name_start + align4(namesz);
+            let desc = notes
+                .get(desc_start..desc_start + descsz)
+                .ok_or(Error::InvalidArgument)?;
+            let name = notes
+                .get(name_start..name_start + namesz)
+                .ok_or(Error::InvalidArgument)?;
+            let name = c_str(name);
+
+            match (name, r#type) {
+                (NOTE_CORE, NT_PRPSINFO) => {
+                    self.pid = get_u32(desc, PRPSINFO_PID)? as u64;
+                    self.name = c_str(
+                        desc.get(PRPSINFO_ARGS..PRPSINFO_ARGS + PRPSINFO_ARGS_LEN)
+                            .ok_or(Error::InvalidArgument)?,
+                    )
+                    .to_string();
+                }
+                (NOTE_CORE, NT_PRSTATUS) => {
+                    let mut registers = [0u64; REG_COUNT];
+                    for (index, register) in registers.iter_mut().enumerate() {
+                        *register = get_u64(desc, PRSTATUS_REGS + index * 8)?;
+                    }
+
+                    self.threads.push(DumpedThread {
+                        tid: get_u32(desc, PRSTATUS_PID)? as u64,
+                        signal: get_u32(desc, PRSTATUS_SIGNAL)?,
+                        context: context_of(&registers),
+                    });
+                }
+                (NOTE_CORE, NT_FILE) => {
+                    let count = get_u64(desc, 0)? as usize;
+                    let mut names = desc
+                        .get(16 + count * 24..)
+                        .ok_or(Error::InvalidArgument)?
+                        .split(|&byte| byte == 0);
+
+                    for index in 0..count {
+                        let start = get_u64(desc, 16 + index * 24)? as usize;
+                        let name = names.next().ok_or(Error::InvalidArgument)?;
+                        let name = str::from_utf8(name).map_err(|_| Error::InvalidArgument)?;
+                        files.insert(start, name.to_string());
+                    }
+                }
+                (NOTE_MTI, NT_MTI_EXCEPTION) => {
+                    let tid = get_u64(desc, 0)?;
+                    let text = str::from_utf8(&desc[8..]).map_err(|_| Error::InvalidArgument)?;
+                    self.exception = Some((tid, text.to_string()));
+                }
+                _ => {}
+            }
+
+            offset = desc_start + align4(descsz);
+        }
+
+        Ok(())
+    }
+
+    /// Get the pid of the dumped process
+    pub fn pid(&self) -> u64 {
+        self.pid
+    }
+
+    /// Get the name of the dumped process
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Get the crashed thread, and a description of its exception
+    pub fn exception(&self) -> Option<(u64, &str)> {
+        self.exception
+            .as_ref()
+            .map(|(tid, text)| (*tid, text.as_str()))
+    }
+
+    /// Get the threads of the dump, the crashed one first
+    pub fn threads(&self) -> &[DumpedThread] {
+        &self.threads
+    }
+
+    /// Get the mapped regions of the dump
+    pub fn regions(&self) -> &[Region] {
+        &self.regions
+    }
+
+    /// Read the memory of the process, if it is in the dump
+    pub fn read(&self, address: usize, len: usize) -> Option<&'a [u8]> {
+        let region = self.regions.iter().find(|region| {
+            address >= region.address && address - region.address < region.data.len()
+        })?;
+
+        let start = region.data.start + (address - region.address);
+        let end = start.checked_add(len)?;
+        if end > region.data.end {
+            return None;
+        }
+
+        Some(&self.data[start..end])
+    }
+
+    fn read_u64(&self, address: usize) -> Option<usize> {
+        let bytes = self.read(address, size_of::<u64>())?;
+        Some(u64::from_le_bytes(bytes.try_into().ok()?) as usize)
+    }
+
+    /// Get the return addresses of a thread, from its instruction pointer (frame pointers walk)
+    pub fn backtrace(&self, thread: &DumpedThread) -> Vec<usize> {
+        let mut frames = vec![thread.context.instruction_pointer];
+        let mut rbp = thread.context.rbp;
+
+        while rbp != 0 && frames.len() < MAX_FRAMES {
+            let (Some(next), Some(rip)) = (self.read_u64(rbp), self.read_u64(rbp + 8)) else {
+                break;
+            };
+
+            if rip == 0 {
+                break;
+            }
+
+            frames.push(rip);
+
+            // Frames are higher up the stack
+            if next <= rbp {
+                break;
+            }
+            rbp = next;
+        }
+
+        frames
+    }
+
+    /// Find the object an address belongs to: its name and the offset of the address in it
+    pub fn object_of(&self, address: usize) -> Option<(&str, usize)> {
+        let name = self
+            .regions
+            .iter()
+            .find(|region| address >= region.address && address - region.address < region.size)?
+            .name
+            .as_deref()?;
+
+        // The object base is the start of its first mapping
+        let base = self
+            .regions
+            .iter()
+            .filter(|region| region.name.as_deref() == Some(name))
+            .map(|region| region.address)
+            .min()?;
+
+        Some((name, address - base))
+    }
+
+    /// Get a report of the dump: crashed thread, registers and backtraces of the threads, mapped regions
+    pub fn report<'r>(&'r self, symbolizer: &'r Symbolizer<'r>) -> Report<'r, 'a> {
+        Report {
+            dump: self,
+            symbolizer,
+        }
+    }
+}
+
+/// Symbolizer of backtraces, with the binaries of the objects (by name, eg: `libruntime.so`)
+///
+/// Objects without binary are printed as `name+offset`, which can be symbolized on the host (eg: `addr2line`).
+#[derive(Default)]
+pub struct Symbolizer<'a> {
+    finders: BTreeMap<String, Finder<'a>>,
+}
+
+impl<'a> Symbolizer<'a> {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add the binary of an object
+    pub fn add_binary(&mut self, name: &str, binary: &'a [u8]) {
+        self.finders.insert(name.to_string(), Finder::load(binary));
+    }
+
+    /// Get the function and source location of an offset in an object
+    pub fn symbolize(&self, name: &str, offset: usize) -> Option<String> {
+        let frame = self.finders.get(name)?.find_frame(offset)?;
+
+        let function = frame
+            .function
+            .as_ref()
+            .and_then(|function| function.demangle().ok().map(|name| name.to_string()))
+            .unwrap_or_else(|| String::from("??"));
+
+        Some(match frame.location {
+            Some(location) => format!(
+                "{} at {}:{}",
+                function,
+                location.file.unwrap_or("??"),
+                location.line.unwrap_or(0)
+            ),
+            None => function,
+        })
+    }
+}
+
+impl fmt::Debug for Symbolizer<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_list().entries(self.finders.keys()).finish()
+    }
+}
+
+/// Report of a dump, see `CoreDump::report`
+pub struct Report<'r, 'a> {
+    dump: &'r CoreDump<'a>,
+    symbolizer: &'r Symbolizer<'r>,
+}
+
+impl fmt::Display for Report<'_, '_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let dump = self.dump;
+
+        writeln!(f, "process {} '{}'", dump.pid, dump.name)?;
+        if let Some((tid, exception)) = dump.exception() {
+            writeln!(f, "thread {} crashed: {}", tid, exception)?;
+        }
+
+        for thread in dump.threads.iter() {
+            let context = &thread.context;
+
+            writeln!(f)?;
+            writeln!(f, "thread {}:", thread.tid)?;
+            writeln!(
+                f,
+                "  rax={:#018x} rbx={:#018x} rcx={:#018x} rdx={:#018x}",
+                context.rax, context.rbx, context.rcx, context.rdx
+            )?;
+            writeln!(
+                f,
+                "  rsi={:#018x} rdi={:#018x} rbp={:#018x} rsp={:#018x}",
+                context.rsi, context.rdi, context.rbp, context.rsp
+            )?;
+            writeln!(
+                f,
+                "  r8 ={:#018x} r9 ={:#018x} r10={:#018x} r11={:#018x}",
+                context.r8, context.r9, context.r10, context.r11
+            )?;
+            writeln!(
+                f,
+                "  r12={:#018x} r13={:#018x} r14={:#018x} r15={:#018x}",
+                context.r12, context.r13, context.r14, context.r15
+            )?;
+            writeln!(
+                f,
+                "  rip={:#018x} flags={:#018x} fs={:#018x}",
+                context.instruction_pointer, context.cpu_flags, context.tls
+            )?;
+
+            for (index, address) in dump.backtrace(thread).iter().enumerate() {
+                write!(f, "  #{:<2} {:#018x}", index, address)?;
+
+                if let Some((name, offset)) = dump.object_of(*address) {
+                    write!(f, " {}+{:#x}", name, offset)?;
+
+                    // Return addresses are after the call instruction
+                    let lookup = if index == 0 { offset } else { offset - 1 };
+                    if let Some(symbol) = self.symbolizer.symbolize(name, lookup) {
+                        write!(f, " {}", symbol)?;
+                    }
+                }
+
+                writeln!(f)?;
+            }
+        }
+
+        writeln!(f)?;
+        writeln!(f, "regions:")?;
+        for region in dump.regions.iter() {
+            writeln!(
+                f,
+                "  {:#018x}-{:#018x} {}{}{} {}{}",
+                region.address,
+                region.address + region.size,
+                if region.perms.contains(Permissions::READ) {
+                    'r'
+                } else {
+                    '-'
+                },
+                if region.perms.contains(Permissions::WRITE) {
+                    'w'
+                } else {
+                    '-'
+                },
+                if region.perms.contains(Permissions::EXECUTE) {
+                    'x'
+                } else {
+                    '-'
+                },
+                region.name.as_deref().unwrap_or(""),
+                if region.has_content() {
+                    ""
+                } else {
+                    " (no content)"
+                }
+            )?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Program header of an ELF64 file
+struct ProgramHeader {
+    r#type: u32,
+    flags: u32,
+    offset: usize,
+    vaddr: usize,
+    filesz: usize,
+    memsz: usize,
+    align: usize,
+}
+
+impl ProgramHeader {
+    fn write(&self, data: &mut [u8], offset: usize) {
+        put_u32(data, offset, self.r#type);
+        put_u32(data, offset + 4, self.flags);
+        put_u64(data, offset + 8, self.offset as u64);
+        put_u64(data, offset + 16, self.vaddr as u64);
+        put_u64(data, offset + 24, 0);
+        put_u64(data, offset + 32, self.filesz as u64);
+        put_u64(data, offset + 40, self.memsz as u64);
+        put_u64(data, offset + 48, self.align as u64);
+    }
+
+    fn read(data: &[u8], offset: usize) -> Result<Self, Error> {
+        Ok(Self {
+            r#type: get_u32(data, offset)?,
+            flags: get_u32(data, offset + 4)?,
+            offset: get_u64(data, offset + 8)? as usize,
+            vaddr: get_u64(data, offset + 16)? as usize,
+            filesz: get_u64(data, offset + 32)? as usize,
+            memsz: get_u64(data, offset + 40)? as usize,
+            align: get_u64(data, offset + 48)? as usize,
+        })
+    }
+}
+
+fn write_elf_header(data: &mut [u8], phnum: u16) {
+    // Magic, 64 bits, little endian, version 1, System V ABI
+    data[0..7].copy_from_slice(&[0x7F, b'E', b'L', b'F', 2, 1, 1]);
+    data[16..18].copy_from_slice(&ET_CORE.to_le_bytes());
+    data[18..20].copy_from_slice(&EM_X86_64.to_le_bytes());
+    put_u32(data, 20, 1);
+    put_u64(data, 32, ELF_HEADER_SIZE as u64);
+    data[52..54].copy_from_slice(&(ELF_HEADER_SIZE as u16).to_le_bytes());
+    data[54..56].copy_from_slice(&(PROGRAM_HEADER_SIZE as u16).to_le_bytes());
+    data[56..58].copy_from_slice(&phnum.to_le_bytes());
+}
+
+fn push_note(notes: &mut Vec<u8>, name: &str, r#type: u32, desc: &[u8]) {
+    notes.extend_from_slice(&(name.len() as u32 + 1).to_le_bytes());
+    notes.extend_from_slice(&(desc.len() as u32).to_le_bytes());
+    notes.extend_from_slice(&r#type.to_le_bytes());
+
+    notes.extend_from_slice(name.as_bytes());
+    notes.resize(align4(notes.len() + 1), 0);
+
+    notes.extend_from_slice(desc);
+    notes.resize(align4(notes.len()), 0);
+}
+
+/// Get the registers of a context, in `user_regs_struct` order
+fn registers_of(context: &ThreadContext) -> [u64; REG_COUNT] {
+    let mut registers = [0u64; REG_COUNT];
+
+    registers[REG_R15] = context.r15 as u64;
+    registers[REG_R14] = context.r14 as u64;
+    registers[REG_R13] = context.r13 as u64;
+    registers[REG_R12] = context.r12 as u64;
+    registers[REG_RBP] = context.rbp as u64;
+    registers[REG_RBX] = context.rbx as u64;
+    registers[REG_R11] = context.r11 as u64;
+    registers[REG_R10] = context.r10 as u64;
+    registers[REG_R9] = context.r9 as u64;
+    registers[REG_R8] = context.r8 as u64;
+    registers[REG_RAX] = context.rax as u64;
+    registers[REG_RCX] = context.rcx as u64;
+    registers[REG_RDX] = context.rdx as u64;
+    registers[REG_RSI] = context.rsi as u64;
+    registers[REG_RDI] = context.rdi as u64;
+    registers[REG_RIP] = context.instruction_pointer as u64;
+    registers[REG_EFLAGS] = context.cpu_flags as u64;
+    registers[REG_RSP] = context.rsp as u64;
+    registers[REG_FS_BASE] = context.tls as u64;
+
+    registers
+}
+
+/// Build a context from registers in `user_regs_struct` order
+fn context_of(registers: &[u64; REG_COUNT]) -> ThreadContext {
+    ThreadContext {
+        rax: registers[REG_RAX] as usize,
+        rcx: registers[REG_RCX] as usize,
+        rdx: registers[REG_RDX] as usize,
+        rbx: registers[REG_RBX] as usize,
+        rsi: registers[REG_RSI] as usize,
+        rdi: registers[REG_RDI] as usize,
+        rsp: registers[REG_RSP] as usize,
+        rbp: registers[REG_RBP] as usize,
+        r8: registers[REG_R8] as usize,
+        r9: registers[REG_R9] as usize,
+        r10: registers[REG_R10] as usize,
+        r11: registers[REG_R11] as usize,
+        r12: registers[REG_R12] as usize,
+        r13: registers[REG_R13] as usize,
+        r14: registers[REG_R14] as usize,
+        r15: registers[REG_R15] as usize,
+        instruction_pointer: registers[REG_RIP] as usize,
+        cpu_flags: registers[REG_EFLAGS] as usize,
+        tls: registers[REG_FS_BASE] as usize,
+    }
+}
+
+/// Get the signal gdb shows for an exception (in crash dumps, and in the stop replies of `gdbstub-server`)
+pub fn exception_signal(exception: &Exception) -> u32 {
+    match exception {
+        Exception::DivideError | Exception::X87FloatingPoint | Exception::SimdFloatingPoint => {
+            SIGFPE
+        }
+        Exception::Debug(_) | Exception::Breakpoint => SIGTRAP,
+        Exception::InvalidOpcode => SIGILL,
+        Exception::PageFault(_, _)
+        | Exception::GeneralProtectionFault(_)
+        | Exception::StackSegmentFault(_) => SIGSEGV,
+        _ => SIGABRT,
+    }
+}
+
+fn flags_of(perms: Permissions) -> u32 {
+    let mut flags = 0;
+    if perms.contains(Permissions::READ) {
+        flags |= PF_R;
+    }
+    if perms.contains(Permissions::WRITE) {
+        flags |= PF_W;
+    }
+    if perms.contains(Permissions::EXECUTE) {
+        flags |= PF_X;
+    }
+    flags
+}
+
+fn perms_of(flags: u32) -> Permissions {
+    let mut perms = Permissions::NONE;
+    if flags & PF_R != 0 {
+        perms |= Permissions::READ;
+    }
+    if flags & PF_W != 0 {
+        perms |= Permissions::WRITE;
+    }
+    if flags & PF_X != 0 {
+        perms |= Permissions::EXECUTE;
+    }
+    perms
+}
+
+fn mapping_name(name: &[u8]) -> String {
+    String::from_utf8_lossy(c_bytes(name)).into_owned()
+}
+
+fn c_bytes(data: &[u8]) -> &[u8] {
+    let len = data
+        .iter()
+        .position(|&byte| byte == 0)
+        .unwrap_or(data.len());
+    &data[..len]
+}
+
+fn c_str(data: &[u8]) -> &str {
+    str::from_utf8(c_bytes(data)).unwrap_or("")
+}
+
+fn put_str(data: &mut [u8], offset: usize, len: usize, value: &str) {
+    // Keep a terminating zero
+    let bytes = &value.as_bytes()[..value.len().min(len - 1)];
+    data[offset..offset + bytes.len()].copy_from_slice(bytes);
+}
+
+fn put_u32(data: &mut [u8], offset: usize, value: u32) {
+    data[offset..offset + 4].copy_from_slice(&value.to_le_bytes());
+}
+
+fn put_u64(data: &mut [u8], offset: usize, value: u64) {
+    data[offset..offset + 8].copy_from_slice(&value.to_le_bytes());
+}
+
+fn get_u16(data: &[u8], offset: usize) -> Result<u16, Error> {
+    let bytes = data.get(offset..offset + 2).ok_or(Error::InvalidArgument)?;
+    Ok(u16::from_le_bytes(bytes.try_into().unwrap()))
+}
+
+fn get_u32(data: &[u8], offset: usize) -> Result<u32, Error> {
+    let bytes = data.get(offset..offset + 4).ok_or(Error::InvalidArgument)?;
+    Ok(u32::from_le_bytes(bytes.try_into().unwrap()))
+}
+
+fn get_u64(data: &[u8], offset: usize) -> Result<u64, Error> {
+    let bytes = data.get(offset..offset + 8).ok_or(Error::InvalidArgument)?;
+    Ok(u64::from_le_bytes(bytes.try_into().unwrap()))
+}
+
+fn align4(value: usize) -> usize {
+    value.next_multiple_of(4)
+}
+
+fn align8(value: usize) -> usize {
+    value.next_multiple_of(8)
+}
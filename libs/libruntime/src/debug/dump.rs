@@ -0,0 +1,89 @@
+use alloc::vec::Vec;
+use log::info;
+
+use crate::kobject::{Error, ThreadState};
+
+use super::{snapshot_threads, NameCache, StackFrame};
+
+/// Log the state of all the other threads of the current process, to find what it is stuck on
+///
+/// Threads waiting in a syscall are at a safepoint: their stacktrace is logged.
+/// For the other ones, only the instruction pointer is.
+///
+/// Returns the number of threads dumped.
+///
+/// Note: this is a debug tool, stacks are walked while their threads may be woken up.
+pub fn dump_threads() -> Result<usize, Error> {
+    let snapshots = snapshot_threads()?;
+
+    let tids: Vec<u64> = snapshots.iter().map(|snapshot| snapshot.tid).collect();
+    let mut names = NameCache::new();
+    // Names are only informative
+    let _ = names.resolve_threads(&tids);
+
+    info!("dump of {} threads", snapshots.len());
+
+    for snapshot in snapshots.iter() {
+        info!(
+            "thread {} ({}): {:?}",
+            snapshot.tid,
+            names.thread_name(snapshot.tid).unwrap_or("<unnamed>"),
+            snapshot.state
+        );
+
+        if snapshot.state == ThreadState::Waiting {
+            let stacktrace = unsafe { snapshot.stacktrace() };
+            for frame in stacktrace.iter() {
+                info!("  at {}", frame);
+            }
+        } else {
+            info!(
+                "  at {}",
+                StackFrame::new(snapshot.context.instruction_pointer)
+            );
+        }
+    }
+
+    Ok(snapshots.len())
+}
+
+/// Log the contention statistics of the named locks, most contended first, then the locks currently held
+#[cfg(feature = "lock-stats")]
+pub fn dump_locks() {
+    let mut stats = crate::sync::lock_stats();
+    stats.sort_by(|a, b| b.total_wait_ticks.cmp(&a.total_wait_ticks));
+
+    info!("dump of {} locks", stats.len());
+
+    for lock in stats.iter() {
+        info!(
+            "lock {}: acquisitions={} contentions={} wait(total={} max={}) hold(total={} max={})",
+            lock.name,
+            lock.acquisitions,
+            lock.contentions,
+            lock.total_wait_ticks,
+            lock.max_wait_ticks,
+            lock.total_hold_ticks,
+            lock.max_hold_ticks
+        );
+    }
+
+    dump_held_locks();
+}
+
+/// Log the locks currently held, by thread, returns their number
+#[cfg(feature = "lock-stats")]
+pub fn dump_held_locks() -> usize {
+    let held = crate::sync::held_locks();
+
+    info!("{} locks held", held.len());
+
+    for lock in held.iter() {
+        info!(
+            "lock {} held by thread {} for {} ticks",
+            lock.name, lock.tid, lock.held_ticks
+        );
+    }
+
+    held.len()
+}
@@ -0,0 +1,86 @@
+//! Memory breakdown of a process, by mapping label
+//!
+//! Labels are grouped by kind: "stack:12" and "stack:13" are both accounted as "stack".
+
+use core::fmt;
+
+use alloc::{collections::BTreeMap, string::String};
+
+use crate::kobject::{Error, Process};
+
+/// Group for mappings without label
+const UNNAMED: &str = "<unnamed>";
+
+/// Memory usage of one label kind
+#[derive(Debug, Default)]
+pub struct MemoryUsage {
+    /// Number of mappings
+    pub count: usize,
+
+    /// Size backed by memory objects
+    pub mapped: usize,
+
+    /// Size only reserved (no memory behind)
+    pub reserved: usize,
+}
+
+/// Memory usage of a process, by label kind
+#[derive(Debug)]
+pub struct MemoryBreakdown {
+    pub pid: u64,
+    pub usages: BTreeMap<String, MemoryUsage>,
+}
+
+impl MemoryBreakdown {
+    /// Build the breakdown from the process mappings list
+    pub fn of(process: &Process) -> Result<Self, Error> {
+        let mut usages: BTreeMap<String, MemoryUsage> = BTreeMap::new();
+
+        for mapping in process.mappings()?.iter() {
+            let kind = match mapping.name() {
+                Some(name) => name.split(':').next().unwrap_or(name),
+                None => UNNAMED,
+            };
+
+            let usage = usages.entry(String::from(kind)).or_default();
+
+            usage.count += 1;
+            if mapping.has_memory_object {
+                usage.mapped += mapping.size;
+            } else {
+                usage.reserved += mapping.size;
+            }
+        }
+
+        Ok(Self {
+            pid: process.pid(),
+            usages,
+        })
+    }
+
+    /// Get the total size backed by memory objects
+    pub fn total_mapped(&self) -> usize {
+        self.usages.values().map(|usage| usage.mapped).sum()
+    }
+}
+
+impl fmt::Display for MemoryBreakdown {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(
+            f,
+            "Memory of process {} (mapped={}):",
+            self.pid,
+            self.total_mapped()
+        )?;
+
+        for (kind, usage) in self.usages.iter() {
+            writeln!(
+                f,
+                "  {}: count={} mapped={} reserved={}",
+                kind, usage.count, usage.mapped, usage.reserved
+            )?;
+        }
+
+        Ok(())
+    }
+}
@@ -1,6 +1,29 @@
+mod checkpoint;
+mod coredump;
 mod debugsym;
+mod dump;
+mod memory;
+mod names;
 mod panic;
+mod snapshot;
 mod stacktrace;
+mod threads;
+mod watch;
 
+pub use checkpoint::{
+    Checkpoint, MappingCheckpoint, Restored, ThreadCheckpoint, CHECKPOINT_EXTENSION,
+};
+pub use coredump::{
+    capture_core, exception_signal, CoreDump, DumpedThread, Region, Report, Symbolizer,
+    CORE_EXTENSION, CRASH_DIR,
+};
 pub use debugsym::{find_location_info, init_memory_binary, LocationInfo};
+#[cfg(feature = "lock-stats")]
+pub use dump::{dump_held_locks, dump_locks};
+pub use dump::dump_threads;
+pub use memory::{MemoryBreakdown, MemoryUsage};
+pub use names::NameCache;
+pub use snapshot::Snapshot;
 pub use stacktrace::{StackFrame, StackTrace};
+pub use threads::{snapshot_threads, ThreadSnapshot};
+pub use watch::{Accessor, WatchpointHit, WatchpointHunter, WatchpointReport};
@@ -0,0 +1,86 @@
+use alloc::{collections::BTreeMap, string::String, vec::Vec};
+
+use crate::kobject::{Error, NameEntry, Process, Thread};
+
+/// Cache of thread and process names, so that tooling output (traces, profiles) can show names instead of ids
+///
+/// Misses are resolved in batch, with one syscall per call.
+/// Entries must be forgotten when the object is renamed or terminated.
+#[derive(Debug, Default)]
+pub struct NameCache {
+    threads: BTreeMap<u64, Option<String>>,
+    processes: BTreeMap<u64, Option<String>>,
+}
+
+impl NameCache {
+    /// Create a new empty cache
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Resolve the names of the given threads which are not in cache yet
+    pub fn resolve_threads(&mut self, tids: &[u64]) -> Result<(), Error> {
+        let missing = missing_ids(&self.threads, tids);
+        if missing.is_empty() {
+            return Ok(());
+        }
+
+        insert_entries(&mut self.threads, Thread::names(&missing)?);
+        Ok(())
+    }
+
+    /// Resolve the names of the given processes which are not in cache yet
+    pub fn resolve_processes(&mut self, pids: &[u64]) -> Result<(), Error> {
+        let missing = missing_ids(&self.processes, pids);
+        if missing.is_empty() {
+            return Ok(());
+        }
+
+        insert_entries(&mut self.processes, Process::names(&missing)?);
+        Ok(())
+    }
+
+    /// Get the name of a thread, resolving it if needed
+    pub fn thread_name(&mut self, tid: u64) -> Option<&str> {
+        // On error, the name is not cached and will be looked up again later
+        self.resolve_threads(&[tid]).ok()?;
+        self.threads.get(&tid)?.as_deref()
+    }
+
+    /// Get the name of a process, resolving it if needed
+    pub fn process_name(&mut self, pid: u64) -> Option<&str> {
+        self.resolve_processes(&[pid]).ok()?;
+        self.processes.get(&pid)?.as_deref()
+    }
+
+    /// Forget a thread name (eg: on rename or termination)
+    pub fn forget_thread(&mut self, tid: u64) {
+        self.threads.remove(&tid);
+    }
+
+    /// Forget a process name (eg: on rename or termination)
+    pub fn forget_process(&mut self, pid: u64) {
+        self.processes.remove(&pid);
+    }
+}
+
+fn missing_ids(cache: &BTreeMap<u64, Option<String>>, ids: &[u64]) -> Vec<u64> {
+    let mut missing: Vec<u64> = ids
+        .iter()
+        .copied()
+        .filter(|id| !cache.contains_key(id))
+        .collect();
+
+    missing.sort_unstable();
+    missing.dedup();
+    missing
+}
+
+fn insert_entries(cache: &mut BTreeMap<u64, Option<String>>, entries: Vec<NameEntry>) {
+    for entry in entries.iter() {
+        // Objects that do not exist (anymore) are not cached
+        if entry.found {
+            cache.insert(entry.id, entry.name().map(String::from));
+        }
+    }
+}
@@ -1,20 +1,48 @@
-use core::{fmt, hint::unreachable_unchecked, panic::PanicInfo};
+use core::{
+    fmt,
+    hint::unreachable_unchecked,
+    panic::PanicInfo,
+    sync::atomic::{AtomicBool, Ordering},
+};
 
 use libsyscalls::process;
-use log::error;
+use log::Level;
 
 use super::StackTrace;
+use crate::{failure, logging};
 
-#[panic_handler]
+static PANICKING: AtomicBool = AtomicBool::new(false);
+
+// Host unit tests use the std one
+#[cfg_attr(not(test), panic_handler)]
 fn panic(info: &PanicInfo) -> ! {
     do_panic(info);
 }
 
 fn do_panic(info: &PanicInfo) -> ! {
-    // TODO: check for re-entrancy (if the panic handler panics)
+    if PANICKING.swap(true, Ordering::Relaxed) {
+        // The panic handler panicked (eg: heap corrupted): only report the message, without allocating.
+        logging::log_no_alloc(
+            Level::Error,
+            format_args!("PANIC in panic handler: {}", info.message()),
+        );
+    } else {
+        // Capture allocates, but formatting does not: the report is not lost if it is too large.
+        let stacktrace = StackTrace::capture();
+        let backtrace_id = failure::backtrace_id(&stacktrace);
+        logging::log_no_alloc(
+            Level::Error,
+            format_args!(
+                "PANIC (backtrace id={backtrace_id:016X}): {}",
+                PanicDisplay::new(info, stacktrace)
+            ),
+        );
+
+        // The client of the request being processed would else only see the server exit
+        failure::report_panic(format_args!("{}", info.message()), backtrace_id);
+    }
 
-    let stacktrace = StackTrace::capture();
-    error!("PANIC: {}", PanicDisplay::new(info, stacktrace));
+    logging::flush();
 
     // Note: in case we failed exit, we cannot do much more.
     let _ = process::exit();
@@ -57,22 +85,7 @@ impl fmt::Display for PanicDisplay<'_> {
                 }
             }
 
-            formatter.write_str("  at ")?;
-            if let Some(info) = frame.location() {
-                if let Some(function) = info.function_name() {
-                    formatter.write_str(&function)?;
-                } else {
-                    formatter.write_str("???")?;
-                }
-                if let Some(location) = info.source_location() {
-                    formatter.write_str(" - ")?;
-                    location.fmt(formatter)?;
-                }
-            } else {
-                formatter.write_fmt(format_args!("0x{0:016X}", frame.address()))?;
-            }
-
-            formatter.write_str("\n")?;
+            formatter.write_fmt(format_args!("  at {}\n", frame))?;
         }
 
         Ok(())
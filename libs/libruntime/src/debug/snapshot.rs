@@ -0,0 +1,175 @@
+//! Snapshot/restore of the whole userland (eg: time-travel debugging of hard-to-reproduce races)
+//!
+//! A snapshot freezes all userland processes but the caller, captures a checkpoint of each (see `Checkpoint`),
+//! and lets them run again. It can be stored in a memory object (to keep it across a run, or to dump it to a
+//! host file from QEmu), and restored later: processes are restored together, so that handles from one
+//! restored process to another (processes, threads) refer to the restored objects.
+//!
+//! Limits are the ones of checkpoints, and:
+//! - the caller is not part of the snapshot
+//! - restored processes have new pids and tids: only handles follow them
+//! - port senders still refer to the ports of the running processes (ports are not restored)
+
+use alloc::vec::Vec;
+
+use super::checkpoint::{Reader, RestoredObjects, Writer};
+use super::{Checkpoint, Restored};
+use crate::kobject::{Error, MemoryObject, Permissions, Process, PAGE_SIZE};
+
+/// Magic of snapshots, with the format version
+const MAGIC: &[u8; 8] = b"MTISNAP1";
+
+/// Snapshot of the userland: checkpoints of all processes
+#[derive(Debug)]
+pub struct Snapshot {
+    pub processes: Vec<Checkpoint>,
+}
+
+impl Snapshot {
+    /// Freeze all userland processes but the current one, capture them, and let them run again
+    ///
+    /// Processes which cannot be suspended (eg: exiting) are not part of the snapshot.
+    /// Note: the caller must have the `DEBUG` sandbox right.
+    pub fn capture() -> Result<Self, Error> {
+        let current = Process::current().pid();
+        let pids: Vec<u64> = Process::list()?
+            .iter()
+            .copied()
+            .filter(|&pid| pid != current)
+            .collect();
+
+        Self::capture_pids(&pids)
+    }
+
+    /// Freeze the given processes, capture them, and let them run again
+    ///
+    /// All processes are suspended before the first capture, so that the snapshot is consistent.
+    /// Processes already suspended are captured, and left suspended.
+    pub fn capture_pids(pids: &[u64]) -> Result<Self, Error> {
+        let mut frozen = Vec::new();
+        let mut suspended = Vec::new();
+
+        for &pid in pids {
+            let Ok(process) = Process::open(pid) else {
+                continue;
+            };
+
+            if process.info().suspended {
+                frozen.push(process);
+            } else if process.suspend().is_ok() {
+                suspended.push(frozen.len());
+                frozen.push(process);
+            }
+        }
+
+        let processes: Result<Vec<_>, _> = frozen.iter().map(Checkpoint::capture).collect();
+
+        for &index in suspended.iter() {
+            // May have exited meanwhile
+            let _ = frozen[index].resume();
+        }
+
+        Ok(Self {
+            processes: processes?,
+        })
+    }
+
+    /// Whether the snapshot can be restored: no thread was blocked in a syscall
+    pub fn is_restorable(&self) -> bool {
+        self.processes.iter().all(Checkpoint::is_restorable)
+    }
+
+    /// Restore all processes of the snapshot, in its order
+    ///
+    /// The processes are returned suspended: resume them to run them.
+    /// Fails with `Error::ObjectNotReady` if a thread was blocked in a syscall, before creating any process.
+    pub fn restore(&self) -> Result<Vec<Restored>, Error> {
+        if !self.is_restorable() {
+            return Err(Error::ObjectNotReady);
+        }
+
+        // All objects first, so that handles between restored processes can be installed
+        let mut objects = RestoredObjects::default();
+        let mut processes = Vec::new();
+
+        for checkpoint in self.processes.iter() {
+            processes.push(checkpoint.restore_objects(&mut objects)?);
+        }
+
+        let mut restored = Vec::new();
+
+        for (checkpoint, process) in self.processes.iter().zip(processes) {
+            let lost_handles = checkpoint.restore_handles(&process, &objects)?;
+            restored.push(Restored {
+                process,
+                lost_handles,
+            });
+        }
+
+        Ok(restored)
+    }
+
+    /// Serialize the snapshot
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut writer = Writer(Vec::new());
+
+        writer.bytes(MAGIC);
+        writer.u64(self.processes.len() as u64);
+
+        for checkpoint in self.processes.iter() {
+            let data = checkpoint.to_bytes();
+            writer.u64(data.len() as u64);
+            writer.bytes(&data);
+        }
+
+        writer.0
+    }
+
+    /// Read a serialized snapshot
+    ///
+    /// Data after the snapshot is ignored. Fails with `Error::InvalidArgument` if the data is not a valid snapshot.
+    pub fn parse(data: &[u8]) -> Result<Self, Error> {
+        let mut reader = Reader::new(data);
+
+        if reader.bytes(MAGIC.len())? != MAGIC {
+            return Err(Error::InvalidArgument);
+        }
+
+        let mut processes = Vec::new();
+        for _ in 0..reader.u64()? {
+            let len = reader.u64()? as usize;
+            processes.push(Checkpoint::parse(reader.bytes(len)?)?);
+        }
+
+        Ok(Self { processes })
+    }
+
+    /// Store the snapshot in a new memory object
+    ///
+    /// The object starts with the serialized snapshot, and is padded with zeroes to a page boundary.
+    pub fn to_memory_object(&self) -> Result<MemoryObject, Error> {
+        let data = self.to_bytes();
+        let size = data.len().next_multiple_of(PAGE_SIZE);
+        let mobj = MemoryObject::create(size)?;
+
+        let mapping = Process::current().map_mem(
+            None,
+            size,
+            Permissions::READ | Permissions::WRITE,
+            &mobj,
+            0,
+        )?;
+        let buffer = unsafe { mapping.as_buffer_mut() }.expect("could not access mapping");
+        buffer[..data.len()].copy_from_slice(&data);
+
+        Ok(mobj)
+    }
+
+    /// Read a snapshot stored in a memory object of `size` bytes (see `to_memory_object`)
+    pub fn from_memory_object(mobj: &MemoryObject, size: usize) -> Result<Self, Error> {
+        let mapping = Process::current().map_mem(None, size, Permissions::READ, mobj, 0)?;
+        let buffer = unsafe { mapping.as_buffer() }.expect("could not access mapping");
+
+        Self::parse(buffer)
+    }
+}
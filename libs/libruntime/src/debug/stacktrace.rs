@@ -1,7 +1,8 @@
 use alloc::{boxed::Box, vec::Vec};
-use core::{arch::asm, ops::Index};
+use core::{arch::asm, fmt, ops::Index};
 
 use super::{find_location_info, LocationInfo};
+use crate::kobject::ThreadContext;
 
 // from https://wiki.osdev.org/Stack_Trace
 
@@ -68,7 +69,28 @@ impl StackTrace {
         Self(frames.into_boxed_slice())
     }
 
-    // TODO: capture from thread context (thread in error state)
+    /// Capture the stacktrace of another thread of the current process, from its context
+    ///
+    /// # Safety
+    ///
+    /// The thread stack is walked in place: the thread must not run during the capture,
+    /// and its frame pointers must be valid (eg: it is blocked in a syscall)
+    pub unsafe fn from_context(context: &ThreadContext) -> Self {
+        let mut frames = Vec::new();
+
+        // The instruction pointer is the next instruction to execute, not a return address
+        frames.push(StackFrame(context.instruction_pointer));
+
+        let mut walker = &*(context.rbp as *const FrameWalker);
+
+        while walker.valid() {
+            frames.push(StackFrame(walker.rip() - 1));
+
+            walker = walker.next()
+        }
+
+        Self(frames.into_boxed_slice())
+    }
 
     /// Iterate over frames
     pub fn iter(&self) -> core::slice::Iter<'_, StackFrame> {
@@ -101,6 +123,10 @@ impl Index<usize> for StackTrace {
 pub struct StackFrame(usize);
 
 impl StackFrame {
+    pub(crate) fn new(address: usize) -> Self {
+        Self(address)
+    }
+
     /// Get the address this frame represents
     pub fn address(&self) -> usize {
         self.0
@@ -111,3 +137,24 @@ impl StackFrame {
         find_location_info(self.address())
     }
 }
+
+/// Display the function and source location of the frame, or its address if there is no debug information
+impl fmt::Display for StackFrame {
+    fn fmt(&self, formatter: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if let Some(info) = self.location() {
+            if let Some(function) = info.function_name() {
+                formatter.write_str(function)?;
+            } else {
+                formatter.write_str("???")?;
+            }
+            if let Some(location) = info.source_location() {
+                formatter.write_str(" - ")?;
+                location.fmt(formatter)?;
+            }
+        } else {
+            formatter.write_fmt(format_args!("0x{0:016X}", self.address()))?;
+        }
+
+        Ok(())
+    }
+}
@@ -0,0 +1,55 @@
+use alloc::vec::Vec;
+
+use crate::kobject::{Error, Process, Thread, ThreadContext, ThreadState};
+
+use super::StackTrace;
+
+/// Snapshot of a thread of the current process, for in-process profilers and deadlock dumps
+#[derive(Debug)]
+pub struct ThreadSnapshot {
+    pub tid: u64,
+    pub state: ThreadState,
+    pub context: ThreadContext,
+}
+
+impl ThreadSnapshot {
+    /// Capture the stacktrace of the thread from its context
+    ///
+    /// # Safety
+    ///
+    /// The thread must not have run since the snapshot, and must be at a safepoint:
+    /// blocked in a syscall (eg: waiting on a port), so that its frame pointers are valid.
+    pub unsafe fn stacktrace(&self) -> StackTrace {
+        StackTrace::from_context(&self.context)
+    }
+}
+
+/// Capture a snapshot of the contexts of all the other threads of the current process
+///
+/// Threads which terminate during the capture are skipped.
+pub fn snapshot_threads() -> Result<Vec<ThreadSnapshot>, Error> {
+    let current_tid = Thread::current_tid();
+    let mut snapshots = Vec::new();
+
+    for &tid in Process::current().threads()?.iter() {
+        if tid == current_tid {
+            continue;
+        }
+
+        let Ok(thread) = Thread::open(tid) else {
+            continue;
+        };
+
+        let Ok(context) = thread.context() else {
+            continue;
+        };
+
+        snapshots.push(ThreadSnapshot {
+            tid,
+            state: thread.info().state,
+            context,
+        });
+    }
+
+    Ok(snapshots)
+}
@@ -0,0 +1,278 @@
+//! Memory corruption hunting
+//!
+//! Sets hardware watchpoints on a memory range of a victim process, on all its threads (including the ones created later).
+//! Each access to the range puts the accessing thread into error state: the hunter collects its context, then resumes it.
+
+use core::fmt;
+
+use alloc::{collections::BTreeMap, vec::Vec};
+use log::debug;
+
+use crate::kobject::{
+    Error, Exception, Thread, ThreadContext, ThreadEventType, ThreadListener, ThreadListenerFilter,
+    ThreadSupervisor, WatchpointKind, WATCHPOINT_COUNT,
+};
+
+/// DR6 bits B0-B3: watchpoint triggered
+const WATCHPOINTS_STATUS_MASK: usize = 0xF;
+
+/// Watched memory, as set on one hardware watchpoint
+#[derive(Debug, Clone, Copy)]
+struct WatchedRange {
+    address: usize,
+    size: usize,
+}
+
+/// Access to the watched memory
+#[derive(Debug)]
+pub struct WatchpointHit {
+    /// Thread which accessed the memory
+    pub tid: u64,
+
+    /// Address of the watched range which has been accessed
+    pub address: usize,
+
+    /// Context of the thread just after the access.
+    ///
+    /// Note: data watchpoints are traps, so the instruction pointer is the one of the next instruction
+    pub context: ThreadContext,
+}
+
+/// Hunt accesses to a memory range in a victim process
+#[derive(Debug)]
+pub struct WatchpointHunter {
+    pid: u64,
+    kind: WatchpointKind,
+    ranges: Vec<WatchedRange>,
+    listener: ThreadListener,
+    threads: BTreeMap<u64, Thread>,
+    hits: Vec<WatchpointHit>,
+}
+
+impl WatchpointHunter {
+    /// Start watching `address..address+len` in the process `pid`
+    ///
+    /// The range is split into aligned watchpoints: it fails with InvalidArgument if it needs more than available.
+    pub fn new(pid: u64, address: usize, len: usize, kind: WatchpointKind) -> Result<Self, Error> {
+        let ranges = split_range(address, len)?;
+
+        // Create the listener first, so that no new thread is missed
+        let listener = ThreadListener::create(ThreadListenerFilter::Pids(&[pid]))?;
+
+        let mut hunter = Self {
+            pid,
+            kind,
+            ranges,
+            listener,
+            threads: BTreeMap::new(),
+            hits: Vec::new(),
+        };
+
+        for tid in Thread::list()?.iter() {
+            // Thread may have terminated since the list
+            let Ok(thread) = Thread::open(*tid) else {
+                continue;
+            };
+
+            if thread.pid() == pid {
+                hunter.watch(thread)?;
+            }
+        }
+
+        debug!(
+            "Watching 0x{:016X} (len={}) in process {} on {} threads",
+            address,
+            len,
+            pid,
+            hunter.threads.len()
+        );
+
+        Ok(hunter)
+    }
+
+    /// Get the pid of the victim process
+    pub fn pid(&self) -> u64 {
+        self.pid
+    }
+
+    /// Block until the next thread event of the victim process, and process it
+    ///
+    /// Returns true if the event was a watchpoint hit.
+    pub fn process_event(&mut self) -> Result<bool, Error> {
+        let event = self.listener.blocking_receive()?;
+
+        match event.r#type {
+            ThreadEventType::Created => {
+                // Thread may already be gone
+                if let Ok(thread) = Thread::open(event.tid) {
+                    self.watch(thread)?;
+                }
+                Ok(false)
+            }
+            ThreadEventType::Error => self.process_error(event.tid),
+            ThreadEventType::Terminated | ThreadEventType::Deleted => {
+                self.threads.remove(&event.tid);
+                Ok(false)
+            }
+            ThreadEventType::Resumed => Ok(false),
+        }
+    }
+
+    /// Get all the watchpoint hits collected so far
+    pub fn hits(&self) -> &[WatchpointHit] {
+        &self.hits
+    }
+
+    /// Build a report of the accesses, grouped by instruction pointer
+    pub fn report(&self) -> WatchpointReport {
+        let mut accessors: BTreeMap<usize, Accessor> = BTreeMap::new();
+
+        for hit in self.hits.iter() {
+            let accessor = accessors
+                .entry(hit.context.instruction_pointer)
+                .or_insert_with(|| Accessor {
+                    instruction_pointer: hit.context.instruction_pointer,
+                    count: 0,
+                    tids: Vec::new(),
+                });
+
+            accessor.count += 1;
+            if !accessor.tids.contains(&hit.tid) {
+                accessor.tids.push(hit.tid);
+            }
+        }
+
+        WatchpointReport {
+            pid: self.pid,
+            accessors: accessors.into_values().collect(),
+        }
+    }
+
+    fn watch(&mut self, thread: Thread) -> Result<(), Error> {
+        let supervisor = ThreadSupervisor::new(&thread);
+
+        for (index, range) in self.ranges.iter().enumerate() {
+            supervisor.set_watchpoint(index, range.address, range.size, self.kind)?;
+        }
+
+        self.threads.insert(thread.tid(), thread);
+        Ok(())
+    }
+
+    fn process_error(&mut self, tid: u64) -> Result<bool, Error> {
+        let Some(thread) = self.threads.get(&tid) else {
+            return Ok(false);
+        };
+
+        let supervisor = ThreadSupervisor::new(thread);
+
+        // Other errors are left to the regular supervisor of the process
+        let Exception::Debug(status) = supervisor.error_info()? else {
+            return Ok(false);
+        };
+
+        if status & WATCHPOINTS_STATUS_MASK == 0 {
+            return Ok(false);
+        }
+
+        let context = supervisor.context()?;
+
+        // Report the first triggered watchpoint
+        let index = (status & WATCHPOINTS_STATUS_MASK).trailing_zeros() as usize;
+        let address = self.ranges[index].address;
+
+        debug!(
+            "Watchpoint hit: tid={}, address=0x{:016X}, ip=0x{:016X}",
+            tid, address, context.instruction_pointer
+        );
+
+        self.hits.push(WatchpointHit {
+            tid,
+            address,
+            context,
+        });
+
+        supervisor.resume()?;
+
+        Ok(true)
+    }
+}
+
+impl Drop for WatchpointHunter {
+    fn drop(&mut self) {
+        for thread in self.threads.values() {
+            let supervisor = ThreadSupervisor::new(thread);
+
+            for index in 0..self.ranges.len() {
+                // Thread may have terminated meanwhile
+                let _ = supervisor.clear_watchpoint(index);
+            }
+        }
+    }
+}
+
+/// Code which accessed the watched memory
+#[derive(Debug)]
+pub struct Accessor {
+    /// Instruction pointer after the access
+    pub instruction_pointer: usize,
+
+    /// Number of accesses from this instruction
+    pub count: usize,
+
+    /// Threads which did the accesses
+    pub tids: Vec<u64>,
+}
+
+/// Report of all the accesses to the watched memory
+#[derive(Debug)]
+pub struct WatchpointReport {
+    pub pid: u64,
+    pub accessors: Vec<Accessor>,
+}
+
+impl fmt::Display for WatchpointReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "Watched memory accessors in process {}:", self.pid)?;
+
+        for accessor in self.accessors.iter() {
+            writeln!(
+                f,
+                "  ip=0x{:016X} count={} tids={:?}",
+                accessor.instruction_pointer, accessor.count, accessor.tids
+            )?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Split the range into naturally aligned chunks of 1, 2, 4 or 8 bytes
+fn split_range(address: usize, len: usize) -> Result<Vec<WatchedRange>, Error> {
+    if len == 0 {
+        return Err(Error::InvalidArgument);
+    }
+
+    let mut ranges = Vec::new();
+    let mut current = address;
+    let end = address.checked_add(len).ok_or(Error::InvalidArgument)?;
+
+    while current < end {
+        let mut size = 8;
+        while current % size != 0 || current + size > end {
+            size /= 2;
+        }
+
+        if ranges.len() == WATCHPOINT_COUNT {
+            return Err(Error::InvalidArgument);
+        }
+
+        ranges.push(WatchedRange {
+            address: current,
+            size,
+        });
+        current += size;
+    }
+
+    Ok(ranges)
+}
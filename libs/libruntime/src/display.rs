@@ -0,0 +1,303 @@
+//! Display server protocol and client
+//!
+//! The display server (`servers/display-server`) hands out surfaces, tracks which one has the focus,
+//! and routes input events to the focused surface.
+//!
+//! A surface is a memory object of 32 bits pixels (`width * height * 4` bytes, rounded to pages), drawn by its client,
+//! and an event port given by the client, where the server sends `SurfaceEvent` messages.
+//!
+//! Focus follows a stack: a surface gets the focus when it is created or when it asks for it,
+//! and when the focused surface is destroyed (or its event port is closed), the focus goes back to the previous one.
+//!
+//! Input sources (eg: console-server for the keyboard) post `InputEvent` with `RequestType::Input`.
+
+use core::mem;
+
+use crate::failure;
+use crate::kobject::{
+    Error, Handle, MemoryObject, Message, Port, PortReceiver, PortSender, PAGE_SIZE,
+};
+
+/// Name of the port of the server
+pub const SERVER_PORT_NAME: &str = "display-server";
+
+/// Bytes per pixel of surfaces
+pub const BYTES_PER_PIXEL: usize = 4;
+
+/// Maximum width and height of a surface
+pub const MAX_SURFACE_SIZE: u32 = 8192;
+
+/// Get the size of the memory object of a surface
+pub fn surface_memory_size(width: u32, height: u32) -> usize {
+    (width as usize * height as usize * BYTES_PER_PIXEL).next_multiple_of(PAGE_SIZE)
+}
+
+/// Type of the requests to the server
+#[repr(u64)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RequestType {
+    /// Create a surface, with the event port in handle 1. The reply carries the memory object in handle 0.
+    CreateSurface = 1,
+    /// Destroy a surface
+    DestroySurface,
+    /// Give the focus to a surface
+    Focus,
+    /// Post an input event, routed to the focused surface
+    Input,
+}
+
+impl TryFrom<u64> for RequestType {
+    type Error = Error;
+
+    fn try_from(value: u64) -> Result<Self, Self::Error> {
+        match value {
+            1 => Ok(Self::CreateSurface),
+            2 => Ok(Self::DestroySurface),
+            3 => Ok(Self::Focus),
+            4 => Ok(Self::Input),
+            _ => Err(Error::InvalidArgument),
+        }
+    }
+}
+
+/// Kind of input event
+#[repr(u64)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum InputKind {
+    /// `code` is the key code, `value` is 1 on press, 0 on release
+    Key = 1,
+    /// `code` is the button, `value` is 1 on press, 0 on release
+    PointerButton,
+    /// `code` is the x position, `value` the y position, relative to the surface
+    PointerMove,
+}
+
+/// Input event
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(C)]
+pub struct InputEvent {
+    /// `InputKind`
+    pub kind: u64,
+    pub code: u64,
+    pub value: u64,
+    /// Modifiers state (source specific)
+    pub modifiers: u64,
+}
+
+impl InputEvent {
+    pub fn kind(&self) -> Result<InputKind, Error> {
+        match self.kind {
+            1 => Ok(InputKind::Key),
+            2 => Ok(InputKind::PointerButton),
+            3 => Ok(InputKind::PointerMove),
+            _ => Err(Error::InvalidArgument),
+        }
+    }
+}
+
+/// Request to the server
+///
+/// Handle 0 is the port to send the reply to.
+#[derive(Debug, Clone, Copy)]
+#[repr(C)]
+pub struct Request {
+    pub r#type: u64,
+    /// Used by `DestroySurface` and `Focus`
+    pub surface: u64,
+    /// Used by `CreateSurface`
+    pub width: u32,
+    pub height: u32,
+    /// Used by `Input`
+    pub input: InputEvent,
+}
+
+impl Request {
+    pub fn new(r#type: RequestType) -> Self {
+        Self {
+            r#type: r#type as u64,
+            surface: 0,
+            width: 0,
+            height: 0,
+            input: InputEvent {
+                kind: 0,
+                code: 0,
+                value: 0,
+                modifiers: 0,
+            },
+        }
+    }
+}
+
+/// Reply of the server
+#[derive(Debug, Clone, Copy)]
+#[repr(C)]
+pub struct Reply {
+    /// 0 on success, else the error code
+    pub status: u64,
+    /// Id of the surface created, or which received the input
+    pub surface: u64,
+}
+
+impl Reply {
+    /// Build the reply of a request result
+    pub fn new(result: Result<u64, Error>) -> Self {
+        match result {
+            Ok(surface) => Self { status: 0, surface },
+            Err(err) => Self {
+                status: err as u64,
+                surface: 0,
+            },
+        }
+    }
+
+    /// Get the result of the request
+    pub fn result(&self) -> Result<u64, Error> {
+        match self.status {
+            0 => Ok(self.surface),
+            status if status <= Error::LAST as u64 => {
+                // Note: safe since it is in the range of error codes
+                Err(unsafe { mem::transmute::<usize, Error>(status as usize) })
+            }
+            _ => Err(Error::InvalidArgument),
+        }
+    }
+}
+
+/// Type of the events sent to the surface event port
+#[repr(u64)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SurfaceEventType {
+    FocusGained = 1,
+    FocusLost,
+    Input,
+}
+
+/// Event sent to the surface event port
+#[derive(Debug, Clone, Copy)]
+#[repr(C)]
+pub struct SurfaceEvent {
+    /// `SurfaceEventType`
+    pub r#type: u64,
+    pub surface: u64,
+    /// Used by `Input`
+    pub input: InputEvent,
+}
+
+impl SurfaceEvent {
+    pub fn r#type(&self) -> Result<SurfaceEventType, Error> {
+        match self.r#type {
+            1 => Ok(SurfaceEventType::FocusGained),
+            2 => Ok(SurfaceEventType::FocusLost),
+            3 => Ok(SurfaceEventType::Input),
+            _ => Err(Error::InvalidArgument),
+        }
+    }
+}
+
+/// Connection to the display server
+#[derive(Debug)]
+pub struct Display {
+    server: PortSender,
+    reply_receiver: PortReceiver,
+    reply_sender: PortSender,
+}
+
+/// Surface created by the display server
+#[derive(Debug)]
+pub struct Surface {
+    pub id: u64,
+    pub width: u32,
+    pub height: u32,
+    /// Pixels: `width * height` 32 bits values, row after row
+    pub memory: MemoryObject,
+}
+
+impl Display {
+    /// Connect to the server
+    pub fn connect() -> Result<Self, Error> {
+        Self::connect_to(SERVER_PORT_NAME)
+    }
+
+    /// Connect to a server listening on another port (eg: a private instance in a test)
+    pub fn connect_to(port_name: &str) -> Result<Self, Error> {
+        let server = Port::open(port_name)?;
+        let (reply_receiver, reply_sender) = Port::create(None)?;
+
+        Ok(Self {
+            server,
+            reply_receiver,
+            reply_sender,
+        })
+    }
+
+    /// Create a surface. Its events are sent to `events`.
+    ///
+    /// The new surface gets the focus.
+    pub fn create_surface(
+        &self,
+        width: u32,
+        height: u32,
+        events: &PortSender,
+    ) -> Result<Surface, Error> {
+        let mut request = Request::new(RequestType::CreateSurface);
+        request.width = width;
+        request.height = height;
+
+        let (id, mut reply) = self.call(&request, events.clone().into_handle())?;
+        let memory =
+            MemoryObject::from_handle(reply.take_handle(0)).map_err(|_| Error::InvalidArgument)?;
+
+        Ok(Surface {
+            id,
+            width,
+            height,
+            memory,
+        })
+    }
+
+    /// Destroy a surface
+    pub fn destroy_surface(&self, surface: Surface) -> Result<(), Error> {
+        let mut request = Request::new(RequestType::DestroySurface);
+        request.surface = surface.id;
+
+        self.call(&request, Handle::invalid())?;
+        Ok(())
+    }
+
+    /// Give the focus to a surface
+    pub fn focus(&self, surface: &Surface) -> Result<(), Error> {
+        let mut request = Request::new(RequestType::Focus);
+        request.surface = surface.id;
+
+        self.call(&request, Handle::invalid())?;
+        Ok(())
+    }
+
+    /// Post an input event (input sources only)
+    ///
+    /// Returns the id of the surface which received it, `ObjectNotFound` if no surface has the focus.
+    pub fn post_input(&self, input: InputEvent) -> Result<u64, Error> {
+        let mut request = Request::new(RequestType::Input);
+        request.input = input;
+
+        let (surface, _) = self.call(&request, Handle::invalid())?;
+        Ok(surface)
+    }
+
+    fn call(&self, request: &Request, handle: Handle) -> Result<(u64, Message), Error> {
+        let mut handles = [self.reply_sender.clone().into_handle(), handle];
+
+        let mut message = unsafe { Message::new(request, &mut handles) };
+        self.server.send(&mut message)?;
+
+        let reply = self.reply_receiver.blocking_receive()?;
+        failure::check_reply(&reply)?;
+        let value = unsafe { reply.data::<Reply>() }.result()?;
+        Ok((value, reply))
+    }
+}
+
+// Make sure the protocol fits in messages
+const _: () = assert!(mem::size_of::<Request>() <= Message::DATA_SIZE);
+const _: () = assert!(mem::size_of::<Reply>() <= Message::DATA_SIZE);
+const _: () = assert!(mem::size_of::<SurfaceEvent>() <= Message::DATA_SIZE);
@@ -0,0 +1,30 @@
+/// Declare the entry point of a userland binary.
+///
+/// This generates the `_start` symbol expected by the linker: it initializes the runtime,
+/// runs the given function, then terminates the runtime and exits the process.
+///
+/// Note: the binary must be `#![no_main]`.
+///
+/// ```ignore
+/// libruntime::entry!(main);
+///
+/// fn main() {
+///     info!("Hello, world!");
+/// }
+/// ```
+#[macro_export]
+macro_rules! entry {
+    ($main:path) => {
+        #[no_mangle]
+        pub extern "C" fn _start() -> ! {
+            // Enforce the signature of the entry function
+            let main: fn() = $main;
+
+            $crate::init();
+
+            main();
+
+            $crate::exit()
+        }
+    };
+}
@@ -0,0 +1,185 @@
+//! Error context chain
+//!
+//! Bare error variants (eg: `ObjectNotFound`) do not tell which operation failed on which object.
+//! Operations can be attached to errors while they propagate, as static strings with an optional value (eg: handle, pid):
+//!
+//! ```ignore
+//! fn process_name(pid: u64) -> Result<String, ErrorChain<Error>> {
+//!     let process = Process::open(pid).context_value("open process", pid)?;
+//!     process.name().context("get name")
+//! }
+//!
+//! process_name(12).context("dump processes")?;
+//! // on failure: "dump processes: open process (12): ObjectNotFound"
+//! ```
+//!
+//! It does not allocate, so it can be used on out-of-memory paths.
+//! It is also kept small (no more than 128 bytes for `kobject::Error`), so that it can be returned in results.
+
+use core::fmt;
+
+use crate::kobject;
+
+/// Maximum number of operations kept in a chain
+///
+/// If more are attached, the outermost ones are dropped.
+pub const MAX_FRAMES: usize = 4;
+
+/// Operation attached to an error
+///
+/// Whether the frame has a value is kept in the chain, to keep frames small.
+#[derive(Debug, Clone, Copy)]
+struct Frame {
+    operation: &'static str,
+    value: u64,
+}
+
+impl Frame {
+    const EMPTY: Self = Self {
+        operation: "",
+        value: 0,
+    };
+}
+
+/// Error with the chain of operations during which it occurred
+#[derive(Clone, Copy)]
+pub struct ErrorChain<E> {
+    error: E,
+    frames: [Frame; MAX_FRAMES],
+    len: u8,
+    /// Bit `n` is set if frame `n` has a value
+    valued: u8,
+    dropped: u8,
+}
+
+impl<E> ErrorChain<E> {
+    /// Create a chain without operation
+    pub const fn new(error: E) -> Self {
+        Self {
+            error,
+            frames: [Frame::EMPTY; MAX_FRAMES],
+            len: 0,
+            valued: 0,
+            dropped: 0,
+        }
+    }
+
+    /// Get the source error
+    pub fn error(&self) -> &E {
+        &self.error
+    }
+
+    /// Get the source error, dropping the chain
+    pub fn into_error(self) -> E {
+        self.error
+    }
+
+    /// Get the attached operations, from the innermost to the outermost
+    pub fn operations(&self) -> impl DoubleEndedIterator<Item = (&'static str, Option<u64>)> + '_ {
+        self.frames[..self.len as usize]
+            .iter()
+            .enumerate()
+            .map(|(index, frame)| {
+                let value = (self.valued & (1 << index) != 0).then_some(frame.value);
+                (frame.operation, value)
+            })
+    }
+
+    fn push(mut self, operation: &'static str, value: Option<u64>) -> Self {
+        let index = self.len as usize;
+
+        if index == MAX_FRAMES {
+            self.dropped = self.dropped.saturating_add(1);
+        } else {
+            self.frames[index] = Frame {
+                operation,
+                value: value.unwrap_or(0),
+            };
+            if value.is_some() {
+                self.valued |= 1 << index;
+            }
+            self.len += 1;
+        }
+
+        self
+    }
+}
+
+impl<E: fmt::Debug> fmt::Display for ErrorChain<E> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.dropped > 0 {
+            write!(f, "...: ")?;
+        }
+
+        // Outermost operation first, as it is the one the caller knows about
+        for (operation, value) in self.operations().rev() {
+            write!(f, "{}", operation)?;
+
+            if let Some(value) = value {
+                write!(f, " ({})", value)?;
+            }
+
+            write!(f, ": ")?;
+        }
+
+        write!(f, "{:?}", self.error)
+    }
+}
+
+// Make sure chains stay small enough to be returned in results
+const _: () = assert!(core::mem::size_of::<ErrorChain<kobject::Error>>() <= 128);
+
+// Display the chain on `expect()`/`unwrap()`
+impl<E: fmt::Debug> fmt::Debug for ErrorChain<E> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Display::fmt(self, f)
+    }
+}
+
+/// Error which can start (or continue) a chain
+pub trait IntoErrorChain {
+    type Error;
+
+    fn into_chain(self) -> ErrorChain<Self::Error>;
+}
+
+impl IntoErrorChain for kobject::Error {
+    type Error = kobject::Error;
+
+    fn into_chain(self) -> ErrorChain<Self::Error> {
+        ErrorChain::new(self)
+    }
+}
+
+impl<E> IntoErrorChain for ErrorChain<E> {
+    type Error = E;
+
+    fn into_chain(self) -> ErrorChain<Self::Error> {
+        self
+    }
+}
+
+impl From<kobject::Error> for ErrorChain<kobject::Error> {
+    fn from(error: kobject::Error) -> Self {
+        Self::new(error)
+    }
+}
+
+/// Attach operations to errors of results
+pub trait ResultExt<T, E> {
+    /// Attach an operation to the error
+    fn context(self, operation: &'static str) -> Result<T, ErrorChain<E>>;
+
+    /// Attach an operation with the value it applies to (eg: handle, pid)
+    fn context_value(self, operation: &'static str, value: u64) -> Result<T, ErrorChain<E>>;
+}
+
+impl<T, E: IntoErrorChain> ResultExt<T, E::Error> for Result<T, E> {
+    fn context(self, operation: &'static str) -> Result<T, ErrorChain<E::Error>> {
+        self.map_err(|err| err.into_chain().push(operation, None))
+    }
+
+    fn context_value(self, operation: &'static str, value: u64) -> Result<T, ErrorChain<E::Error>> {
+        self.map_err(|err| err.into_chain().push(operation, Some(value)))
+    }
+}
@@ -0,0 +1,272 @@
+//! Event bus protocol and client
+//!
+//! The event bus server (`servers/event-bus`) manages named topics, so that loosely coupled components
+//! (service manager, metrics, shell notifications) do not need pairwise protocols.
+//!
+//! Each topic is a broadcast port named `event-bus:<topic>`, owned by the server:
+//! - publishers open it by name and send events to it directly (see `Publisher`)
+//! - subscribers open it by name and subscribe one of their ports to it (the kernel requires the receiver of the port)
+//!
+//! Events of a topic follow its schema: data item 0 is the event type of the topic,
+//! and the event data fits in the `data_capacity` first items.
+
+use core::mem;
+
+use alloc::{format, string::String};
+
+use crate::failure;
+use crate::kobject::{Error, Message, Port, PortReceiver, PortSender};
+
+/// Name of the port of the server
+pub const SERVER_PORT_NAME: &str = "event-bus";
+
+/// Prefix of the names of the topics ports
+pub const TOPIC_PORT_PREFIX: &str = "event-bus:";
+
+/// Maximum length of a topic name
+pub const TOPIC_NAME_LEN: usize = 24;
+
+/// Number of data items (u64) of a message, maximum `data_capacity` of a schema
+pub const DATA_ITEMS: usize = libsyscalls::Message::DATA_SIZE;
+
+/// Schema of the events of a topic
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(C)]
+pub struct TopicSchema {
+    /// Type of the events (data item 0)
+    pub event_type: u64,
+    /// Number of data items used by the events, including the event type
+    pub data_capacity: u64,
+}
+
+/// Type of the requests to the server
+#[repr(u64)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RequestType {
+    /// Create a topic, or check the schema if it already exists
+    CreateTopic = 1,
+    /// Get the schema of a topic
+    GetSchema,
+}
+
+impl TryFrom<u64> for RequestType {
+    type Error = Error;
+
+    fn try_from(value: u64) -> Result<Self, Self::Error> {
+        match value {
+            1 => Ok(Self::CreateTopic),
+            2 => Ok(Self::GetSchema),
+            _ => Err(Error::InvalidArgument),
+        }
+    }
+}
+
+/// Request to the server
+///
+/// Handle 0 is the port to send the reply to.
+#[derive(Debug, Clone, Copy)]
+#[repr(C)]
+pub struct Request {
+    pub r#type: u64,
+    /// Used by `CreateTopic`
+    pub schema: TopicSchema,
+    pub name_len: u64,
+    pub name: [u8; TOPIC_NAME_LEN],
+}
+
+impl Request {
+    /// Build a request for a topic
+    pub fn new(r#type: RequestType, topic: &str) -> Result<Self, Error> {
+        if topic.is_empty() || topic.len() > TOPIC_NAME_LEN {
+            return Err(Error::InvalidArgument);
+        }
+
+        let mut request = Self {
+            r#type: r#type as u64,
+            schema: TopicSchema {
+                event_type: 0,
+                data_capacity: 0,
+            },
+            name_len: topic.len() as u64,
+            name: [0; TOPIC_NAME_LEN],
+        };
+
+        request.name[..topic.len()].copy_from_slice(topic.as_bytes());
+
+        Ok(request)
+    }
+
+    /// Get the topic name
+    pub fn topic(&self) -> Result<&str, Error> {
+        let len = self.name_len as usize;
+        if len == 0 || len > TOPIC_NAME_LEN {
+            return Err(Error::InvalidArgument);
+        }
+
+        core::str::from_utf8(&self.name[..len]).map_err(|_| Error::InvalidArgument)
+    }
+}
+
+/// Reply of the server
+#[derive(Debug, Clone, Copy)]
+#[repr(C)]
+pub struct Reply {
+    /// 0 on success, else the error code
+    pub status: u64,
+    /// Schema of the topic, on success
+    pub schema: TopicSchema,
+}
+
+impl Reply {
+    /// Build the reply of a request result
+    pub fn new(result: Result<TopicSchema, Error>) -> Self {
+        match result {
+            Ok(schema) => Self { status: 0, schema },
+            Err(err) => Self {
+                status: err as u64,
+                schema: TopicSchema {
+                    event_type: 0,
+                    data_capacity: 0,
+                },
+            },
+        }
+    }
+
+    /// Get the result of the request
+    pub fn result(&self) -> Result<TopicSchema, Error> {
+        match self.status {
+            0 => Ok(self.schema),
+            status if status <= Error::LAST as u64 => {
+                // Note: safe since it is in the range of error codes
+                Err(unsafe { mem::transmute::<usize, Error>(status as usize) })
+            }
+            _ => Err(Error::InvalidArgument),
+        }
+    }
+}
+
+/// Get the name of the port of a topic
+pub fn topic_port_name(topic: &str) -> String {
+    format!("{}{}", TOPIC_PORT_PREFIX, topic)
+}
+
+/// Connection to the event bus server
+#[derive(Debug)]
+pub struct EventBus {
+    server: PortSender,
+    reply_receiver: PortReceiver,
+    reply_sender: PortSender,
+}
+
+impl EventBus {
+    /// Connect to the server
+    pub fn connect() -> Result<Self, Error> {
+        Self::connect_to(SERVER_PORT_NAME)
+    }
+
+    /// Connect to a server listening on another port (eg: a private instance in a test)
+    pub fn connect_to(port_name: &str) -> Result<Self, Error> {
+        let server = Port::open(port_name)?;
+        let (reply_receiver, reply_sender) = Port::create(None)?;
+
+        Ok(Self {
+            server,
+            reply_receiver,
+            reply_sender,
+        })
+    }
+
+    /// Create a topic
+    ///
+    /// If the topic already exists with the same schema, this is a success.
+    pub fn create_topic(&self, topic: &str, schema: TopicSchema) -> Result<(), Error> {
+        let mut request = Request::new(RequestType::CreateTopic, topic)?;
+        request.schema = schema;
+
+        self.call(&request)?;
+        Ok(())
+    }
+
+    /// Get the schema of a topic
+    pub fn schema(&self, topic: &str) -> Result<TopicSchema, Error> {
+        let request = Request::new(RequestType::GetSchema, topic)?;
+
+        self.call(&request)
+    }
+
+    /// Subscribe a port to a topic
+    ///
+    /// The subscriber misses events while it has `max_queued` messages (or more) waiting.
+    /// The subscription ends when the subscriber port is closed.
+    pub fn subscribe(
+        &self,
+        topic: &str,
+        subscriber: &PortReceiver,
+        max_queued: usize,
+    ) -> Result<TopicSchema, Error> {
+        let schema = self.schema(topic)?;
+        let port = Port::open(&topic_port_name(topic))?;
+        port.subscribe(subscriber, max_queued)?;
+
+        Ok(schema)
+    }
+
+    /// Unsubscribe a port from a topic
+    pub fn unsubscribe(&self, topic: &str, subscriber: &PortReceiver) -> Result<(), Error> {
+        let port = Port::open(&topic_port_name(topic))?;
+        port.unsubscribe(subscriber)
+    }
+
+    fn call(&self, request: &Request) -> Result<TopicSchema, Error> {
+        let mut handles = [self.reply_sender.clone().into_handle()];
+
+        let mut message = unsafe { Message::new(request, &mut handles) };
+        self.server.send(&mut message)?;
+
+        let reply = self.reply_receiver.blocking_receive()?;
+        failure::check_reply(&reply)?;
+        unsafe { reply.data::<Reply>() }.result()
+    }
+}
+
+/// Publisher of the events of a topic
+#[derive(Debug)]
+pub struct Publisher {
+    port: PortSender,
+    schema: TopicSchema,
+}
+
+impl Publisher {
+    /// Open a topic to publish events to it
+    pub fn open(bus: &EventBus, topic: &str) -> Result<Self, Error> {
+        let schema = bus.schema(topic)?;
+        let port = Port::open(&topic_port_name(topic))?;
+
+        Ok(Self { port, schema })
+    }
+
+    /// Get the schema of the topic
+    pub fn schema(&self) -> &TopicSchema {
+        &self.schema
+    }
+
+    /// Publish an event
+    ///
+    /// `data` are the data items following the event type, they must fit the schema of the topic
+    pub fn publish(&self, data: &[u64]) -> Result<(), Error> {
+        if data.len() + 1 > self.schema.data_capacity as usize {
+            return Err(Error::InvalidArgument);
+        }
+
+        let mut items = [0u64; DATA_ITEMS];
+        items[0] = self.schema.event_type;
+        items[1..=data.len()].copy_from_slice(data);
+
+        let mut message = unsafe { Message::new(&items, &mut []) };
+        self.port.send(&mut message)
+    }
+}
+
+// Make sure the protocol fits in messages
+const _: () = assert!(mem::size_of::<Request>() <= Message::DATA_SIZE);
+const _: () = assert!(mem::size_of::<Reply>() <= Message::DATA_SIZE);
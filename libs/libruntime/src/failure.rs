@@ -0,0 +1,206 @@
+//! Failure reports: a server thread which panics while processing a request replies with a structured report
+//!
+//! Without it, the client only sees its reply port closing (or waits forever), and the failure is lost in deep server chains.
+//!
+//! - the server marks the request it processes with `begin_request` (reply port and correlation id)
+//! - on panic, the panic handler sends a `FailureReport` to the reply port before the process exits
+//! - the client checks replies with `check_reply` before decoding them: it logs the report,
+//!   forwards it to the crash handler service (if one is registered), and gets `Error::ObjectClosed` (the server is gone)
+//!
+//! Replies of all the protocols begin with a `status` field (0 or an error code): reports use `FAILURE_STATUS`,
+//! which is not an error code, so that they can be detected whatever the protocol.
+//!
+//! The report only carries the beginning of the panic message:
+//! the full message and the stack trace are in the server log, with the same backtrace id.
+
+use core::{fmt, mem, str};
+
+use alloc::boxed::Box;
+use libsyscalls::thread;
+use log::error;
+use spin::Once;
+
+use crate::{
+    debug::StackTrace,
+    kobject::{Error, Message, Port, PortSender, Process, TlsAllocator, TlsSlot},
+};
+
+/// Name of the port of the crash handler service, to which clients forward the reports they receive
+pub const CRASH_HANDLER_PORT_NAME: &str = "crash-handler";
+
+/// Status of a failure report, in place of the status of the reply
+pub const FAILURE_STATUS: u64 = u64::MAX;
+
+/// Failure report, sent in place of the reply to a request
+#[derive(Debug, Clone, Copy)]
+#[repr(C)]
+pub struct FailureReport {
+    /// Always `FAILURE_STATUS`
+    pub status: u64,
+
+    /// Correlation id of the failed request
+    pub correlation: u64,
+
+    /// Hash of the stack trace, to match the report with the server log, and reports of the same failure together
+    pub backtrace_id: u64,
+
+    /// Process and thread which failed
+    pub pid: u64,
+    pub tid: u64,
+
+    /// Panic message, padded with zeros
+    pub message: [u8; Self::MESSAGE_LEN],
+}
+
+impl FailureReport {
+    /// Maximum length of the message (the remaining of the message data)
+    pub const MESSAGE_LEN: usize = 24;
+
+    /// Get the (possibly truncated) panic message
+    pub fn message(&self) -> &str {
+        let len = self
+            .message
+            .iter()
+            .position(|&byte| byte == 0)
+            .unwrap_or(Self::MESSAGE_LEN);
+
+        // The message may have been truncated in the middle of a character
+        match str::from_utf8(&self.message[..len]) {
+            Ok(message) => message,
+            Err(err) => str::from_utf8(&self.message[..err.valid_up_to()]).unwrap_or(""),
+        }
+    }
+}
+
+/// Request being processed by the current thread
+#[derive(Debug)]
+struct PendingRequest {
+    reply: PortSender,
+    correlation: u64,
+}
+
+/// Per thread: address of the `PendingRequest`, or 0
+static PENDING_REQUEST: Once<TlsSlot> = Once::new();
+
+/// Marks the request processed by the current thread, until dropped
+#[derive(Debug)]
+pub struct RequestGuard {
+    _pending: Box<PendingRequest>,
+}
+
+impl Drop for RequestGuard {
+    fn drop(&mut self) {
+        if let Some(slot) = PENDING_REQUEST.get() {
+            slot.set(0);
+        }
+    }
+}
+
+/// Mark the request processed by the current thread: if it panics before the guard is dropped,
+/// a `FailureReport` is sent to `reply` instead of the reply.
+pub fn begin_request(reply: &PortSender, correlation: u64) -> RequestGuard {
+    let slot = PENDING_REQUEST
+        .call_once(|| TlsAllocator::allocate().expect("Could not allocate TLS slot"));
+
+    let pending = Box::new(PendingRequest {
+        reply: reply.clone(),
+        correlation,
+    });
+
+    slot.set(&*pending as *const PendingRequest as usize);
+
+    RequestGuard { _pending: pending }
+}
+
+/// Compute the backtrace id of a stack trace (FNV-1a of the frame addresses)
+pub fn backtrace_id(stacktrace: &StackTrace) -> u64 {
+    const OFFSET_BASIS: u64 = 0xCBF2_9CE4_8422_2325;
+    const PRIME: u64 = 0x0000_0100_0000_01B3;
+
+    stacktrace.iter().fold(OFFSET_BASIS, |hash, frame| {
+        frame
+            .address()
+            .to_le_bytes()
+            .iter()
+            .fold(hash, |hash, &byte| (hash ^ byte as u64).wrapping_mul(PRIME))
+    })
+}
+
+/// Called by the panic handler: send a failure report for the request of the current thread, if any
+///
+/// Note: it does not allocate, the heap may be corrupted.
+pub(crate) fn report_panic(message: fmt::Arguments, backtrace_id: u64) {
+    let Some(slot) = PENDING_REQUEST.get() else {
+        return;
+    };
+
+    let pending = match slot.get() {
+        Some(0) | None => return,
+        Some(address) => unsafe { &*(address as *const PendingRequest) },
+    };
+
+    let mut report = FailureReport {
+        status: FAILURE_STATUS,
+        correlation: pending.correlation,
+        backtrace_id,
+        pid: Process::current().pid(),
+        tid: thread::get_ids().map_or(0, |ids| ids.tid),
+        message: [0; FailureReport::MESSAGE_LEN],
+    };
+
+    let mut writer = TruncatingWriter {
+        buffer: &mut report.message,
+        len: 0,
+    };
+    let _ = fmt::write(&mut writer, message);
+
+    let mut message = unsafe { Message::new(&report, &mut []) };
+    // Nothing more can be done if it fails
+    let _ = pending.reply.send(&mut message);
+}
+
+/// Check if a reply is a failure report
+///
+/// If it is, the report is logged and forwarded to the crash handler, and `Error::ObjectClosed` is returned.
+pub fn check_reply(reply: &Message) -> Result<(), Error> {
+    let report = unsafe { reply.data::<FailureReport>() };
+    if report.status != FAILURE_STATUS {
+        return Ok(());
+    }
+
+    error!(
+        "Request failed in server: pid={}, tid={}, correlation={}, backtrace id={:016X}: {}",
+        report.pid,
+        report.tid,
+        report.correlation,
+        report.backtrace_id,
+        report.message()
+    );
+
+    // Optional: the crash handler service may not be running
+    if let Ok(crash_handler) = Port::open(CRASH_HANDLER_PORT_NAME) {
+        let mut message = unsafe { Message::new(report, &mut []) };
+        message.set_correlation(report.correlation);
+        let _ = crash_handler.send(&mut message);
+    }
+
+    Err(Error::ObjectClosed)
+}
+
+/// Write into a fixed buffer, dropping what does not fit
+struct TruncatingWriter<'a> {
+    buffer: &'a mut [u8],
+    len: usize,
+}
+
+impl fmt::Write for TruncatingWriter<'_> {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        let count = s.len().min(self.buffer.len() - self.len);
+        self.buffer[self.len..self.len + count].copy_from_slice(&s.as_bytes()[..count]);
+        self.len += count;
+        Ok(())
+    }
+}
+
+// Make sure the report fits in messages
+const _: () = assert!(mem::size_of::<FailureReport>() <= Message::DATA_SIZE);
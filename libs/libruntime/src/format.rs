@@ -0,0 +1,106 @@
+//! Locale-independent formatting of sizes and durations, for logs and tools
+//!
+//! Values are scaled to the largest unit they reach, and shown with one decimal (the formatter precision overrides it):
+//!
+//! ```ignore
+//! info!("free={}", Bytes(12_900_000)); // "free=12.3 MiB"
+//! info!("latency={}", Nanos(1_234_567)); // "latency=1.2ms"
+//! info!("uptime={:.0}", Nanos::from(uptime)); // "uptime=1h02m03s"
+//! ```
+
+use core::{
+    fmt::{self, Display, Formatter},
+    time::Duration,
+};
+
+const MAX_PRECISION: usize = 3;
+
+/// Size in bytes, shown with binary prefixes (eg: "12.3 MiB")
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Bytes(pub u64);
+
+impl Display for Bytes {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        const UNITS: [&str; 6] = ["KiB", "MiB", "GiB", "TiB", "PiB", "EiB"];
+
+        if self.0 < 1024 {
+            return write!(f, "{} B", self.0);
+        }
+
+        let mut divisor = 1024;
+        let mut unit = 0;
+        while unit + 1 < UNITS.len() && self.0 / divisor >= 1024 {
+            divisor *= 1024;
+            unit += 1;
+        }
+
+        write_scaled(f, self.0, divisor, " ", UNITS[unit])
+    }
+}
+
+/// Duration in nanoseconds (eg: "1.2ms", "2m03s")
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Nanos(pub u64);
+
+impl From<Duration> for Nanos {
+    fn from(value: Duration) -> Self {
+        Self(value.as_nanos().min(u64::MAX as u128) as u64)
+    }
+}
+
+impl Display for Nanos {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        const MICRO: u64 = 1_000;
+        const MILLI: u64 = 1_000_000;
+        const SEC: u64 = 1_000_000_000;
+        const MIN: u64 = 60 * SEC;
+        const HOUR: u64 = 60 * MIN;
+        const DAY: u64 = 24 * HOUR;
+
+        let value = self.0;
+
+        match value {
+            0..MICRO => write!(f, "{value}ns"),
+            MICRO..MILLI => write_scaled(f, value, MICRO, "", "us"),
+            MILLI..SEC => write_scaled(f, value, MILLI, "", "ms"),
+            SEC..MIN => write_scaled(f, value, SEC, "", "s"),
+            // Above a minute, decimals are not meaningful anymore
+            MIN..HOUR => write!(f, "{}m{:02}s", value / MIN, (value % MIN) / SEC),
+            HOUR..DAY => write!(
+                f,
+                "{}h{:02}m{:02}s",
+                value / HOUR,
+                (value % HOUR) / MIN,
+                (value % MIN) / SEC
+            ),
+            _ => write!(
+                f,
+                "{}d{:02}h{:02}m",
+                value / DAY,
+                (value % DAY) / HOUR,
+                (value % HOUR) / MIN
+            ),
+        }
+    }
+}
+
+/// Write `value / divisor`, truncated to the formatter precision
+fn write_scaled(
+    f: &mut Formatter<'_>,
+    value: u64,
+    divisor: u64,
+    separator: &str,
+    unit: &str,
+) -> fmt::Result {
+    let precision = f.precision().unwrap_or(1).min(MAX_PRECISION);
+    let scale = 10u128.pow(precision as u32);
+
+    let scaled = value as u128 * scale / divisor as u128;
+    let (integer, fraction) = (scaled / scale, scaled % scale);
+
+    if precision == 0 {
+        write!(f, "{integer}{separator}{unit}")
+    } else {
+        write!(f, "{integer}.{fraction:0precision$}{separator}{unit}")
+    }
+}
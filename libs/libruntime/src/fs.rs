@@ -0,0 +1,671 @@
+//! Filesystem server protocol and client
+//!
+//! Filesystem servers (eg: `servers/memfs-server`) store the nodes: vfs-server resolves the paths and keeps the open handles
+//! on top of them (see `vfs`). Clients never talk to filesystem servers directly.
+//!
+//! Each `Attach` request creates (or opens) a filesystem instance, and returns its root directory.
+//! Nodes are identified by an id, unique in the server for their whole life.
+//!
+//! Names are passed as a key/value block in a memory object (handle 1), so is the data of `Write` requests (raw).
+//! Data read is replied in a memory object (handle 0).
+//!
+//! Nodes have timestamps, in nanoseconds since the Unix epoch (wall clock): `atime` (last read), `mtime` (last change
+//! of the data, or of the entries of a directory) and `ctime` (last change of the data or of the metadata).
+//! Servers may update `atime` lazily (eg: memfs only updates it if it is older than `mtime` or `ctime`, or than a day).
+//!
+//! Requests on the data of files (`Read`, `Write`, `Truncate`, `CopyRange`, `SetTimes`) carry the pid of the process
+//! on behalf of which vfs-server sends them (`Request::opener`), taken from the kernel-stamped sender of the client requests,
+//! so that servers can check permissions or audit.
+
+use core::{mem, slice, time::Duration};
+
+use alloc::{string::String, vec::Vec};
+
+use crate::failure;
+use crate::kobject::{
+    Error, Handle, MemoryObject, Message, Permissions, Port, PortReceiver, PortSender, Process,
+    PAGE_SIZE,
+};
+use crate::kvblock::{KVBlock, KVBlockBuilder, Value};
+
+/// Name of the port of the memory filesystem server
+pub const MEMFS_PORT_NAME: &str = "memfs";
+
+/// Maximum size of a read or write request, in bytes: larger ones are shortened
+pub const MAX_IO_SIZE: usize = 1024 * 1024;
+
+/// Maximum length of a node name, in bytes
+pub const NAME_MAX: usize = 255;
+
+/// Type of the requests to the server
+#[repr(u64)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RequestType {
+    /// Create a filesystem instance, get its root directory
+    Attach = 1,
+    /// Drop a filesystem instance and all its nodes
+    Detach,
+    /// Find a node by name in a directory
+    Lookup,
+    /// Create a node in a directory
+    Create,
+    /// Remove a file or an empty directory
+    Remove,
+    /// Move a node, possibly into another directory
+    Rename,
+    /// Read data from a file
+    Read,
+    /// Write data into a file, extending it if needed
+    Write,
+    /// Set the size of a file
+    Truncate,
+    /// Get the description of a node
+    GetAttr,
+    /// List the entries of a directory
+    List,
+    /// Copy data between two files of the same instance, without going through the client
+    CopyRange,
+    /// Set the access and modification times of a node (`utimens`-like)
+    SetTimes,
+}
+
+impl TryFrom<u64> for RequestType {
+    type Error = Error;
+
+    fn try_from(value: u64) -> Result<Self, Self::Error> {
+        match value {
+            1 => Ok(Self::Attach),
+            2 => Ok(Self::Detach),
+            3 => Ok(Self::Lookup),
+            4 => Ok(Self::Create),
+            5 => Ok(Self::Remove),
+            6 => Ok(Self::Rename),
+            7 => Ok(Self::Read),
+            8 => Ok(Self::Write),
+            9 => Ok(Self::Truncate),
+            10 => Ok(Self::GetAttr),
+            11 => Ok(Self::List),
+            12 => Ok(Self::CopyRange),
+            13 => Ok(Self::SetTimes),
+            _ => Err(Error::InvalidArgument),
+        }
+    }
+}
+
+/// Kind of a node
+#[repr(u64)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum NodeKind {
+    File = 1,
+    Directory,
+}
+
+impl NodeKind {
+    /// Name of the kind, used as key in directory listings
+    pub const fn name(&self) -> &'static str {
+        match self {
+            NodeKind::File => "file",
+            NodeKind::Directory => "directory",
+        }
+    }
+
+    fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "file" => Some(Self::File),
+            "directory" => Some(Self::Directory),
+            _ => None,
+        }
+    }
+}
+
+impl TryFrom<u64> for NodeKind {
+    type Error = Error;
+
+    fn try_from(value: u64) -> Result<Self, Self::Error> {
+        match value {
+            1 => Ok(Self::File),
+            2 => Ok(Self::Directory),
+            _ => Err(Error::InvalidArgument),
+        }
+    }
+}
+
+/// Description of a node
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(C)]
+pub struct NodeInfo {
+    pub id: u64,
+    pub kind: u64,
+    /// Size of the data in bytes (number of entries for directories)
+    pub size: u64,
+    /// Last access, in nanoseconds since the Unix epoch
+    pub atime: u64,
+    /// Last modification of the data, in nanoseconds since the Unix epoch
+    pub mtime: u64,
+    /// Last change of the data or the metadata, in nanoseconds since the Unix epoch
+    pub ctime: u64,
+}
+
+impl NodeInfo {
+    pub const EMPTY: Self = Self {
+        id: 0,
+        kind: 0,
+        size: 0,
+        atime: 0,
+        mtime: 0,
+        ctime: 0,
+    };
+
+    /// Get the kind of the node
+    pub fn kind(&self) -> Result<NodeKind, Error> {
+        NodeKind::try_from(self.kind)
+    }
+
+    pub fn is_directory(&self) -> bool {
+        self.kind == NodeKind::Directory as u64
+    }
+}
+
+/// New value of a timestamp, for `SetTimes`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TimeSpec {
+    /// Set it to the current wall clock
+    Now,
+    /// Keep it as is
+    Omit,
+    /// Set it to this time since the Unix epoch
+    At(Duration),
+}
+
+impl TimeSpec {
+    const RAW_NOW: u64 = u64::MAX;
+    const RAW_OMIT: u64 = u64::MAX - 1;
+
+    /// Encode it in a request field
+    pub fn to_raw(&self) -> u64 {
+        match self {
+            TimeSpec::Now => Self::RAW_NOW,
+            TimeSpec::Omit => Self::RAW_OMIT,
+            // Saturates far beyond any real date, below the special values
+            TimeSpec::At(time) => (time.as_nanos() as u64).min(Self::RAW_OMIT - 1),
+        }
+    }
+
+    /// Decode it from a request field
+    pub fn from_raw(raw: u64) -> Self {
+        match raw {
+            Self::RAW_NOW => TimeSpec::Now,
+            Self::RAW_OMIT => TimeSpec::Omit,
+            nanos => TimeSpec::At(Duration::from_nanos(nanos)),
+        }
+    }
+}
+
+/// Entry of a directory listing
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DirEntry {
+    pub name: String,
+    pub kind: NodeKind,
+}
+
+/// Encode directory entries as a key/value block: the key is the kind name, the value the entry name
+pub fn encode_entries<'a>(entries: impl Iterator<Item = (&'a str, NodeKind)>) -> Vec<u8> {
+    let mut builder = KVBlockBuilder::new();
+    for (name, kind) in entries {
+        builder.push_str(kind.name(), name);
+    }
+    builder.build()
+}
+
+/// Decode directory entries, entries of unknown kinds are skipped
+pub fn decode_entries(data: &[u8]) -> Result<Vec<DirEntry>, Error> {
+    let block = KVBlock::parse(data).map_err(|_| Error::InvalidArgument)?;
+
+    Ok(block
+        .iter()
+        .filter_map(|entry| {
+            let kind = NodeKind::from_name(entry.key)?;
+            let name = entry.value.as_str()?;
+            Some(DirEntry {
+                name: String::from(name),
+                kind,
+            })
+        })
+        .collect())
+}
+
+/// Check a node name: not empty, no `/`, not `.` or `..`
+pub fn check_name(name: &str) -> Result<(), Error> {
+    let valid = !name.is_empty()
+        && name.len() <= NAME_MAX
+        && name != "."
+        && name != ".."
+        && !name.contains(['/', '\0']);
+
+    if valid {
+        Ok(())
+    } else {
+        Err(Error::InvalidArgument)
+    }
+}
+
+/// Request to the server
+///
+/// Handle 0 is the port to send the reply to.
+/// Handle 1 is the arguments block (names) or the data of `Write`, of `buffer_size` bytes.
+#[derive(Debug, Clone, Copy)]
+#[repr(C)]
+pub struct Request {
+    pub r#type: u64,
+    /// Node the request is about (the directory for `Lookup`, `Create`, `Remove` and `Rename`, the source for `CopyRange`)
+    pub node: u64,
+    /// Used by `Read`, `Write` and `CopyRange`, and by `SetTimes` (access time, see `TimeSpec::to_raw`)
+    pub offset: u64,
+    /// Used by `Read` and `CopyRange` (size to read), `Truncate` (new size), `Create` (kind), `Rename` (1 to replace an existing file)
+    /// and `SetTimes` (modification time, see `TimeSpec::to_raw`)
+    pub size: u64,
+    /// Used by `Rename` (destination directory) and `CopyRange` (destination file)
+    pub other_node: u64,
+    /// Used by `CopyRange`: offset in the destination file
+    pub other_offset: u64,
+    /// Size of the content of handle 1, in bytes
+    pub buffer_size: u64,
+    /// Process on behalf of which the request is sent (0: unknown): the one which opened the node in vfs-server,
+    /// or the sender of the vfs request for operations without handle (truncation at open, batches)
+    pub opener: u64,
+}
+
+impl Request {
+    pub const fn new(r#type: RequestType, node: u64) -> Self {
+        Self {
+            r#type: r#type as u64,
+            node,
+            offset: 0,
+            size: 0,
+            other_node: 0,
+            other_offset: 0,
+            buffer_size: 0,
+            opener: 0,
+        }
+    }
+}
+
+/// Reply of the server
+///
+/// Replies to `Read` and `List` carry the data in a memory object as handle 0.
+#[derive(Debug, Clone, Copy)]
+#[repr(C)]
+pub struct Reply {
+    /// 0 on success, else the error code
+    pub status: u64,
+    /// Size of the data replied for `Read` and `List`, size written for `Write` and `CopyRange`
+    pub value: u64,
+    /// Node created, found or described (`Attach`, `Lookup`, `Create`, `GetAttr`), node read or written (`Read`, `Write`, `CopyRange`),
+    /// node changed (`SetTimes`)
+    pub info: NodeInfo,
+}
+
+impl Reply {
+    /// Build the reply of a request result
+    pub fn new(result: Result<(usize, NodeInfo), Error>) -> Self {
+        match result {
+            Ok((value, info)) => Self {
+                status: 0,
+                value: value as u64,
+                info,
+            },
+            Err(err) => Self {
+                status: err as u64,
+                value: 0,
+                info: NodeInfo::EMPTY,
+            },
+        }
+    }
+
+    /// Get the result of the request
+    pub fn result(&self) -> Result<(usize, NodeInfo), Error> {
+        match self.status {
+            0 => Ok((self.value as usize, self.info)),
+            status if status <= Error::LAST as u64 => {
+                // Note: safe since it is in the range of error codes
+                Err(unsafe { mem::transmute::<usize, Error>(status as usize) })
+            }
+            _ => Err(Error::InvalidArgument),
+        }
+    }
+}
+
+/// Copy data into a new memory object, to send it with a message
+pub fn copy_to_object(data: &[u8]) -> Result<MemoryObject, Error> {
+    let size = data.len().max(1).next_multiple_of(PAGE_SIZE);
+    let object = MemoryObject::create(size)?;
+
+    let mapping = Process::current().map_mem(
+        None,
+        size,
+        Permissions::READ | Permissions::WRITE,
+        &object,
+        0,
+    )?;
+
+    let dest = unsafe { slice::from_raw_parts_mut(mapping.address() as *mut u8, data.len()) };
+    dest.copy_from_slice(data);
+
+    Ok(object)
+}
+
+/// Copy the first `size` bytes of a memory object received with a message
+///
+/// Fails if the object is smaller than `size`.
+pub fn copy_from_object(object: &MemoryObject, size: usize) -> Result<Vec<u8>, Error> {
+    if size == 0 {
+        return Ok(Vec::new());
+    }
+
+    let mapping = Process::current().map_mem(
+        None,
+        size.next_multiple_of(PAGE_SIZE),
+        Permissions::READ,
+        object,
+        0,
+    )?;
+
+    let data = unsafe { slice::from_raw_parts(mapping.address() as *const u8, size) };
+    Ok(Vec::from(data))
+}
+
+/// Connection to a filesystem server
+#[derive(Debug)]
+pub struct Filesystem {
+    server: PortSender,
+    reply_receiver: PortReceiver,
+    reply_sender: PortSender,
+}
+
+impl Filesystem {
+    /// Connect to the server listening on `port_name`
+    pub fn connect(port_name: &str) -> Result<Self, Error> {
+        Self::from_port(Port::open(port_name)?)
+    }
+
+    /// Use an opened port of the server
+    pub fn from_port(server: PortSender) -> Result<Self, Error> {
+        let (reply_receiver, reply_sender) = Port::create(None)?;
+
+        Ok(Self {
+            server,
+            reply_receiver,
+            reply_sender,
+        })
+    }
+
+    /// Create a filesystem instance, returns its root directory
+    pub fn attach(&self) -> Result<NodeInfo, Error> {
+        let (_, info, _) = self.call(Request::new(RequestType::Attach, 0), None)?;
+        Ok(info)
+    }
+
+    /// Drop the filesystem instance of the root directory
+    pub fn detach(&self, root: u64) -> Result<(), Error> {
+        self.call(Request::new(RequestType::Detach, root), None)?;
+        Ok(())
+    }
+
+    /// Find a node by name in a directory
+    pub fn lookup(&self, directory: u64, name: &str) -> Result<NodeInfo, Error> {
+        let request = Request::new(RequestType::Lookup, directory);
+        let (_, info, _) = self.call_with_names(request, name, None)?;
+        Ok(info)
+    }
+
+    /// Create a node in a directory
+    ///
+    /// Fails with `Error::ObjectNameDuplicate` if the name exists.
+    pub fn create(&self, directory: u64, name: &str, kind: NodeKind) -> Result<NodeInfo, Error> {
+        let mut request = Request::new(RequestType::Create, directory);
+        request.size = kind as u64;
+        let (_, info, _) = self.call_with_names(request, name, None)?;
+        Ok(info)
+    }
+
+    /// Remove a file or an empty directory
+    pub fn remove(&self, directory: u64, name: &str) -> Result<(), Error> {
+        let request = Request::new(RequestType::Remove, directory);
+        self.call_with_names(request, name, None)?;
+        Ok(())
+    }
+
+    /// Move a node, possibly into another directory
+    ///
+    /// If `replace` is set, an existing file at the destination is replaced, else it fails with `Error::ObjectNameDuplicate`.
+    /// Directories are never replaced.
+    pub fn rename(
+        &self,
+        directory: u64,
+        name: &str,
+        new_directory: u64,
+        new_name: &str,
+        replace: bool,
+    ) -> Result<(), Error> {
+        let mut request = Request::new(RequestType::Rename, directory);
+        request.other_node = new_directory;
+        request.size = replace as u64;
+        self.call_with_names(request, name, Some(new_name))?;
+        Ok(())
+    }
+
+    /// Read data from a file on behalf of `opener`, returns less than `size` bytes at the end of the file
+    pub fn read(&self, node: u64, offset: u64, size: usize, opener: u64) -> Result<Vec<u8>, Error> {
+        let (size, object) = self.read_object(node, offset, size, opener)?;
+        match object {
+            Some(object) => copy_from_object(&object, size),
+            None => Ok(Vec::new()),
+        }
+    }
+
+    /// Read data from a file, returns the size read and the memory object holding it (`None` if nothing was read)
+    pub fn read_object(
+        &self,
+        node: u64,
+        offset: u64,
+        size: usize,
+        opener: u64,
+    ) -> Result<(usize, Option<MemoryObject>), Error> {
+        let (size, _, mut reply) =
+            self.call(Self::read_request(node, offset, size, opener), None)?;
+        if size == 0 {
+            return Ok((0, None));
+        }
+
+        let object =
+            MemoryObject::from_handle(reply.take_handle(0)).map_err(|_| Error::InvalidArgument)?;
+        Ok((size, Some(object)))
+    }
+
+    /// Send a read request without waiting for the reply, which is sent to `reply_port`
+    ///
+    /// The reply carries `correlation`, so that the caller can match it with its request.
+    pub fn send_read(
+        &self,
+        node: u64,
+        offset: u64,
+        size: usize,
+        opener: u64,
+        reply_port: &PortSender,
+        correlation: u64,
+    ) -> Result<(), Error> {
+        let request = Self::read_request(node, offset, size, opener);
+        let mut handles = [reply_port.clone().into_handle()];
+
+        let mut message = unsafe { Message::new(&request, &mut handles) };
+        message.set_correlation(correlation);
+        self.server.send(&mut message)
+    }
+
+    fn read_request(node: u64, offset: u64, size: usize, opener: u64) -> Request {
+        let mut request = Request::new(RequestType::Read, node);
+        request.offset = offset;
+        request.size = size.min(MAX_IO_SIZE) as u64;
+        request.opener = opener;
+        request
+    }
+
+    /// Write the first `size` bytes of a memory object into a file on behalf of `opener`, returns the size written
+    pub fn write_object(
+        &self,
+        node: u64,
+        offset: u64,
+        object: MemoryObject,
+        size: usize,
+        opener: u64,
+    ) -> Result<usize, Error> {
+        let mut request = Request::new(RequestType::Write, node);
+        request.offset = offset;
+        request.buffer_size = size.min(MAX_IO_SIZE) as u64;
+        request.opener = opener;
+
+        let (written, _, _) = self.call(request, Some(object.into_handle()))?;
+        Ok(written)
+    }
+
+    /// Write data into a file on behalf of `opener`, returns the size written
+    pub fn write(&self, node: u64, offset: u64, data: &[u8], opener: u64) -> Result<usize, Error> {
+        let data = &data[..data.len().min(MAX_IO_SIZE)];
+        self.write_object(node, offset, copy_to_object(data)?, data.len(), opener)
+    }
+
+    /// Set the size of a file: it is extended with zeroes or shortened
+    pub fn truncate(&self, node: u64, size: u64, opener: u64) -> Result<(), Error> {
+        let mut request = Request::new(RequestType::Truncate, node);
+        request.size = size;
+        request.opener = opener;
+        self.call(request, None)?;
+        Ok(())
+    }
+
+    /// Copy data from a file into another one (or the same one) of the instance, returns the size copied
+    ///
+    /// Copies less than `size` bytes at the end of the source file, or at most `MAX_IO_SIZE`.
+    /// The destination is extended if needed, like with `write`.
+    pub fn copy_range(
+        &self,
+        source: u64,
+        offset: u64,
+        dest: u64,
+        dest_offset: u64,
+        size: usize,
+        opener: u64,
+    ) -> Result<usize, Error> {
+        let mut request = Request::new(RequestType::CopyRange, source);
+        request.offset = offset;
+        request.size = size.min(MAX_IO_SIZE) as u64;
+        request.other_node = dest;
+        request.other_offset = dest_offset;
+        request.opener = opener;
+
+        let (copied, _, _) = self.call(request, None)?;
+        Ok(copied)
+    }
+
+    /// Set the access and modification times of a node, the change time is set to now
+    pub fn set_times(
+        &self,
+        node: u64,
+        atime: TimeSpec,
+        mtime: TimeSpec,
+        opener: u64,
+    ) -> Result<NodeInfo, Error> {
+        let mut request = Request::new(RequestType::SetTimes, node);
+        request.offset = atime.to_raw();
+        request.size = mtime.to_raw();
+        request.opener = opener;
+
+        let (_, info, _) = self.call(request, None)?;
+        Ok(info)
+    }
+
+    /// Get the description of a node
+    pub fn get_attr(&self, node: u64) -> Result<NodeInfo, Error> {
+        let (_, info, _) = self.call(Request::new(RequestType::GetAttr, node), None)?;
+        Ok(info)
+    }
+
+    /// List the entries of a directory
+    pub fn list(&self, directory: u64) -> Result<Vec<DirEntry>, Error> {
+        let (size, _, mut reply) = self.call(Request::new(RequestType::List, directory), None)?;
+        let object =
+            MemoryObject::from_handle(reply.take_handle(0)).map_err(|_| Error::InvalidArgument)?;
+
+        decode_entries(&copy_from_object(&object, size)?)
+    }
+
+    fn call_with_names(
+        &self,
+        mut request: Request,
+        name: &str,
+        new_name: Option<&str>,
+    ) -> Result<(usize, NodeInfo, Message), Error> {
+        let mut builder = KVBlockBuilder::new();
+        builder.push_str("name", name);
+        if let Some(new_name) = new_name {
+            builder.push_str("new_name", new_name);
+        }
+        let block = builder.build();
+
+        request.buffer_size = block.len() as u64;
+        self.call(request, Some(copy_to_object(&block)?.into_handle()))
+    }
+
+    fn call(
+        &self,
+        request: Request,
+        object: Option<Handle>,
+    ) -> Result<(usize, NodeInfo, Message), Error> {
+        let mut handles = [
+            self.reply_sender.clone().into_handle(),
+            object.unwrap_or_else(Handle::invalid),
+        ];
+
+        let mut message = unsafe { Message::new(&request, &mut handles) };
+        self.server.send(&mut message)?;
+
+        let reply = self.reply_receiver.blocking_receive()?;
+        failure::check_reply(&reply)?;
+        let (value, info) = unsafe { reply.data::<Reply>() }.result()?;
+        Ok((value, info, reply))
+    }
+}
+
+/// Server side: get the names of a request from its arguments block (handle 1)
+///
+/// Returns the name and the new name (`Rename` only)
+pub fn request_names(
+    request: &Request,
+    message: &mut Message,
+) -> Result<(String, Option<String>), Error> {
+    let object =
+        MemoryObject::from_handle(message.take_handle(1)).map_err(|_| Error::InvalidArgument)?;
+    let data = copy_from_object(&object, request.buffer_size as usize)?;
+    let block = KVBlock::parse(&data).map_err(|_| Error::InvalidArgument)?;
+
+    let name = block
+        .get("name")
+        .and_then(|value| value.as_str())
+        .ok_or(Error::InvalidArgument)?;
+    check_name(name)?;
+
+    let new_name = match block.get("new_name") {
+        Some(Value::Str(new_name)) => {
+            check_name(new_name)?;
+            Some(String::from(new_name))
+        }
+        Some(_) => return Err(Error::InvalidArgument),
+        None => None,
+    };
+
+    Ok((String::from(name), new_name))
+}
+
+// Make sure the protocol fits in messages
+const _: () = assert!(mem::size_of::<Request>() <= Message::DATA_SIZE);
+const _: () = assert!(mem::size_of::<Reply>() <= Message::DATA_SIZE);
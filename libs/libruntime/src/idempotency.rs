@@ -0,0 +1,99 @@
+//! At-most-once server operations
+//!
+//! A client that may retry a request with side effects (eg: create a process, mount a filesystem) after a timeout or a reconnection
+//! stamps it with an idempotency token, and keeps the same token for all the retries (see `Message::set_token`).
+//!
+//! The server records the results of its last requests by token, and answers a retry with the recorded result
+//! instead of processing it again. Results are only kept briefly: the cache is bounded, the oldest entries are evicted first.
+
+use core::sync::atomic::{AtomicU32, Ordering};
+
+use alloc::collections::VecDeque;
+
+use crate::kobject::Process;
+
+/// Token identifying a request and its retries
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct IdempotencyToken(u64);
+
+impl IdempotencyToken {
+    /// Generate a new token, unique in the system
+    ///
+    /// It is made of the pid of the client and a counter.
+    pub fn generate() -> Self {
+        static COUNTER: AtomicU32 = AtomicU32::new(1);
+
+        let counter = COUNTER.fetch_add(1, Ordering::Relaxed);
+        assert!(counter > 0, "counter wrapped");
+
+        Self((Process::current().pid() << 32) | counter as u64)
+    }
+
+    /// Get the token from its raw value (eg: received in a message), None if the request has no token
+    pub const fn from_u64(value: u64) -> Option<Self> {
+        if value == 0 {
+            None
+        } else {
+            Some(Self(value))
+        }
+    }
+
+    /// Get the raw value of the token
+    pub const fn as_u64(&self) -> u64 {
+        self.0
+    }
+}
+
+/// Results of the last requests, by token
+#[derive(Debug)]
+pub struct IdempotencyCache<T: Clone> {
+    capacity: usize,
+    entries: VecDeque<(IdempotencyToken, T)>,
+}
+
+impl<T: Clone> IdempotencyCache<T> {
+    /// Create a cache that keeps the results of the last `capacity` requests
+    pub fn new(capacity: usize) -> Self {
+        assert!(capacity > 0);
+
+        Self {
+            capacity,
+            entries: VecDeque::with_capacity(capacity),
+        }
+    }
+
+    /// Get the recorded result of a request, if it has already been processed
+    pub fn get(&self, token: IdempotencyToken) -> Option<&T> {
+        self.entries
+            .iter()
+            .find(|(entry_token, _)| *entry_token == token)
+            .map(|(_, result)| result)
+    }
+
+    /// Record the result of a request
+    pub fn record(&mut self, token: IdempotencyToken, result: T) {
+        if self.entries.len() == self.capacity {
+            self.entries.pop_front();
+        }
+
+        self.entries.push_back((token, result));
+    }
+
+    /// Process a request at most once
+    ///
+    /// If the request has already been processed, `process` is not called and the recorded result is returned.
+    /// Requests without token (0) are always processed.
+    pub fn process(&mut self, token: u64, process: impl FnOnce() -> T) -> T {
+        let Some(token) = IdempotencyToken::from_u64(token) else {
+            return process();
+        };
+
+        if let Some(result) = self.get(token) {
+            return result.clone();
+        }
+
+        let result = process();
+        self.record(token, result.clone());
+        result
+    }
+}
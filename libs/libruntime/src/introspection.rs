@@ -0,0 +1,222 @@
+//! Introspection of the resources held by servers on behalf of their clients
+//!
+//! For debugging leaks and for admin tooling: any server can be asked the list of the resources it holds
+//! (eg: surfaces in display-server, topics in event-bus), by client process.
+//!
+//! The request is shared by all the protocols: it is sent to the server port like other requests,
+//! with `INTROSPECT_REQUEST` as request type (out of the range of protocol request types),
+//! and the port to send the reply to as handle 0.
+//! The reply begins with a `status` like other replies, and carries the entries in a memory object as handle 0.
+//!
+//! Server side, resources are recorded in a `ResourceTracker`, which answers the request:
+//!
+//! ```ignore
+//! if let Some(request) = introspection::Request::from_message(&message) {
+//!     resources.reply(&request, &reply_port);
+//!     continue;
+//! }
+//! ```
+
+use core::{mem, slice, str};
+
+use alloc::{collections::BTreeMap, vec::Vec};
+
+use crate::{
+    failure,
+    kobject::{
+        Error, Handle, MemoryObject, Message, Permissions, Port, PortSender, Process, PAGE_SIZE,
+    },
+};
+
+/// Request type of introspection requests, in place of the protocol request type
+pub const INTROSPECT_REQUEST: u64 = 0x1_0000;
+
+/// Introspection request
+#[derive(Debug, Clone, Copy)]
+#[repr(C)]
+pub struct Request {
+    /// Always `INTROSPECT_REQUEST`
+    pub r#type: u64,
+
+    /// Only list the resources of this client (0 for all clients)
+    pub client_pid: u64,
+}
+
+impl Request {
+    /// Get the introspection request in a message received by a server, if it is one
+    pub fn from_message(message: &Message) -> Option<Self> {
+        let request = *unsafe { message.data::<Self>() };
+        (request.r#type == INTROSPECT_REQUEST).then_some(request)
+    }
+}
+
+/// Introspection reply
+///
+/// On success, handle 0 is a memory object with `count` entries.
+#[derive(Debug, Clone, Copy)]
+#[repr(C)]
+pub struct Reply {
+    /// 0 on success, else the error code
+    pub status: u64,
+    pub count: u64,
+}
+
+/// Resource held by a server on behalf of a client
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(C)]
+pub struct ResourceEntry {
+    /// Pid of the client process
+    pub client_pid: u64,
+
+    /// Id of the resource in the server
+    pub id: u64,
+
+    pub kind_len: u64,
+    pub kind: [u8; Self::KIND_LEN],
+}
+
+impl ResourceEntry {
+    /// Maximum length of the kind of a resource (eg: `surface`, `open-node`)
+    pub const KIND_LEN: usize = 24;
+
+    /// Get the kind of the resource
+    pub fn kind(&self) -> &str {
+        let len = (self.kind_len as usize).min(Self::KIND_LEN);
+        str::from_utf8(&self.kind[..len]).unwrap_or("")
+    }
+}
+
+/// Resources held by a server, by client
+#[derive(Debug, Default)]
+pub struct ResourceTracker {
+    /// (kind, id) -> client pid
+    resources: BTreeMap<(&'static str, u64), u64>,
+}
+
+impl ResourceTracker {
+    pub const fn new() -> Self {
+        Self {
+            resources: BTreeMap::new(),
+        }
+    }
+
+    /// Record that a resource is held for a client
+    pub fn add(&mut self, client_pid: u64, kind: &'static str, id: u64) {
+        assert!(kind.len() <= ResourceEntry::KIND_LEN);
+        self.resources.insert((kind, id), client_pid);
+    }
+
+    /// Record that a resource has been released
+    pub fn remove(&mut self, kind: &'static str, id: u64) {
+        self.resources.remove(&(kind, id));
+    }
+
+    /// List the resources, of one client or of all clients (`client_pid` = 0)
+    pub fn entries(&self, client_pid: u64) -> Vec<ResourceEntry> {
+        self.resources
+            .iter()
+            .filter(|(_, &pid)| client_pid == 0 || pid == client_pid)
+            .map(|(&(kind, id), &pid)| {
+                let mut entry = ResourceEntry {
+                    client_pid: pid,
+                    id,
+                    kind_len: kind.len() as u64,
+                    kind: [0; ResourceEntry::KIND_LEN],
+                };
+
+                entry.kind[..kind.len()].copy_from_slice(kind.as_bytes());
+                entry
+            })
+            .collect()
+    }
+
+    /// Answer an introspection request
+    pub fn reply(&self, request: &Request, reply_port: &PortSender) {
+        let entries = self.entries(request.client_pid);
+
+        let (reply, object) = match copy_entries(&entries) {
+            Ok(object) => (
+                Reply {
+                    status: 0,
+                    count: entries.len() as u64,
+                },
+                object.into_handle(),
+            ),
+            Err(err) => (
+                Reply {
+                    status: err as u64,
+                    count: 0,
+                },
+                Handle::invalid(),
+            ),
+        };
+
+        let mut handles = [object];
+        let mut message = unsafe { Message::new(&reply, &mut handles) };
+        // The client may be gone
+        let _ = reply_port.send(&mut message);
+    }
+}
+
+fn copy_entries(entries: &[ResourceEntry]) -> Result<MemoryObject, Error> {
+    let len = mem::size_of_val(entries);
+    let size = len.max(1).next_multiple_of(PAGE_SIZE);
+    let object = MemoryObject::create(size)?;
+
+    let mapping = Process::current().map_mem(
+        None,
+        size,
+        Permissions::READ | Permissions::WRITE,
+        &object,
+        0,
+    )?;
+
+    let dest = unsafe { slice::from_raw_parts_mut(mapping.address() as *mut u8, len) };
+    dest.copy_from_slice(unsafe { slice::from_raw_parts(entries.as_ptr() as *const u8, len) });
+
+    Ok(object)
+}
+
+/// Client side: list the resources held by the server on the named port, of one client or of all clients (`client_pid` = 0)
+pub fn list_resources(server_port: &str, client_pid: u64) -> Result<Vec<ResourceEntry>, Error> {
+    let server = Port::open(server_port)?;
+    let (reply_receiver, reply_sender) = Port::create(None)?;
+
+    let request = Request {
+        r#type: INTROSPECT_REQUEST,
+        client_pid,
+    };
+
+    let mut handles = [reply_sender.into_handle()];
+    let mut message = unsafe { Message::new(&request, &mut handles) };
+    server.send(&mut message)?;
+
+    let mut reply = reply_receiver.blocking_receive()?;
+    failure::check_reply(&reply)?;
+
+    let data = *unsafe { reply.data::<Reply>() };
+    match data.status {
+        0 => {}
+        status if status <= Error::LAST as u64 => {
+            // Note: safe since it is in the range of error codes
+            return Err(unsafe { mem::transmute::<usize, Error>(status as usize) });
+        }
+        _ => return Err(Error::InvalidArgument),
+    }
+
+    let count = data.count as usize;
+    let object =
+        MemoryObject::from_handle(reply.take_handle(0)).map_err(|_| Error::InvalidArgument)?;
+
+    let len = count * mem::size_of::<ResourceEntry>();
+    let size = len.max(1).next_multiple_of(PAGE_SIZE);
+    let mapping = Process::current().map_mem(None, size, Permissions::READ, &object, 0)?;
+
+    let entries =
+        unsafe { slice::from_raw_parts(mapping.address() as *const ResourceEntry, count) };
+    Ok(Vec::from(entries))
+}
+
+// Make sure the protocol fits in messages
+const _: () = assert!(mem::size_of::<Request>() <= Message::DATA_SIZE);
+const _: () = assert!(mem::size_of::<Reply>() <= Message::DATA_SIZE);
@@ -0,0 +1,50 @@
+use alloc::vec::Vec;
+use libsyscalls::audit;
+
+use super::*;
+
+/// Reader of the kernel audit log
+///
+/// The audit log is append-only: records are not consumed by readers, the oldest ones are dropped when it is full.
+#[derive(Debug, Default)]
+pub struct Audit {
+    /// Sequence number of the next record to read
+    cursor: u64,
+}
+
+impl Audit {
+    /// Create a reader, starting at the oldest records available
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Read the next records
+    ///
+    /// Returns an empty list if there are no new records.
+    /// If the reader is too late, the dropped records are skipped: their sequence numbers are missing.
+    ///
+    /// Note: only privileged threads can read the audit log
+    pub fn read(&mut self) -> Result<Vec<AuditRecord>, Error> {
+        let mut buffer = Vec::with_capacity(AuditRecord::BUFFER_SIZE);
+        buffer.resize(AuditRecord::BUFFER_SIZE, AuditRecord::default());
+
+        let count = audit::read(&mut self.cursor, &mut buffer)?.len();
+        buffer.truncate(count);
+
+        Ok(buffer)
+    }
+
+    /// Replace the audit rules: an operation is audited if at least one rule matches it
+    ///
+    /// Note: only privileged threads can set the rules
+    pub fn set_rules(rules: &[AuditRule]) -> Result<(), Error> {
+        audit::set_rules(rules)
+    }
+
+    /// Record a server operation (eg: mount), if a rule matches it
+    ///
+    /// The kernel records the calling process as the one performing the operation.
+    pub fn submit(r#type: AuditEventType, object: u64, name: &str) -> Result<(), Error> {
+        audit::submit(r#type, object, name)
+    }
+}
@@ -0,0 +1,54 @@
+use core::time::Duration;
+
+use libsyscalls::{stats, timer};
+
+use super::*;
+
+/// System clocks
+///
+/// Uptime is monotonic. The wall clock is read from the RTC at boot, and can be adjusted (eg: by a time service):
+/// adjustments move the boot time, so that the wall clock is always the boot time plus the uptime.
+pub struct Clock {
+    _priv: (),
+}
+
+impl Clock {
+    /// Get the time since boot (tick granularity)
+    pub fn uptime() -> Result<Duration, Error> {
+        Ok(Duration::from_nanos(stats::system_info()?.uptime))
+    }
+
+    /// Get the wall clock at boot, since the Unix epoch
+    pub fn boot_time() -> Result<Duration, Error> {
+        Ok(Duration::from_nanos(stats::system_info()?.boot_time))
+    }
+
+    /// Get the wall clock, since the Unix epoch (tick granularity)
+    pub fn wall() -> Result<Duration, Error> {
+        Ok(Duration::from_nanos(stats::system_info()?.wall_clock))
+    }
+
+    /// Adjust the wall clock
+    ///
+    /// Note: only privileged threads can adjust the wall clock
+    pub fn set_wall(value: Duration) -> Result<(), Error> {
+        timer::set_wall_clock(value.as_nanos() as u64)
+    }
+
+    /// Test if the kernel runs on a virtual clock (`virtual-clock` kernel feature)
+    ///
+    /// Time then stands still until a test harness advances it with `advance`,
+    /// so that timeouts (deadlines, retries, watchdogs) can be tested without real sleeps.
+    pub fn is_virtual() -> bool {
+        timer::stats().map_or(false, |stats| stats.virtual_clock)
+    }
+
+    /// Advance the virtual clock by `delay` (rounded up to ticks): the timers due on the way fire in deadline order
+    ///
+    /// Fails with `Error::NotSupported` on the real clock, and with `Error::InvalidArgument` above `MAX_ADVANCE_NS`.
+    ///
+    /// Note: only privileged threads can advance the clock
+    pub fn advance(delay: Duration) -> Result<(), Error> {
+        timer::advance(delay.as_nanos() as u64)
+    }
+}
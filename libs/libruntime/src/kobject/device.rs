@@ -0,0 +1,42 @@
+use alloc::{boxed::Box, vec::Vec};
+use libsyscalls::device;
+
+use super::*;
+
+/// Devices
+pub struct Device {
+    _priv: (),
+}
+
+impl Device {
+    /// List the devices discovered by the kernel
+    pub fn list() -> Result<Box<[DeviceInfo]>, Error> {
+        let mut size = 64;
+
+        // Device list is built at boot time, so it should not change between calls
+        loop {
+            let mut buffer = Vec::with_capacity(size);
+            buffer.resize(size, DeviceInfo::default());
+
+            let (_, new_size) = device::list(&mut buffer)?;
+
+            if new_size > size {
+                size = new_size;
+                continue;
+            }
+
+            buffer.truncate(new_size);
+
+            return Ok(buffer.into_boxed_slice());
+        }
+    }
+
+    /// Claim the PCI device at `address` (see `DeviceInfo::address`) to drive it
+    ///
+    /// The io-ports of the device are granted to the process, and bus mastering (DMA) is enabled.
+    /// It fails with `Error::ObjectNotReady` if another living process (or the kernel) drives it.
+    /// The process must have the `SandboxFlags::DEVICE` sandbox right (see `manifest_sandbox!`).
+    pub fn claim(address: u64) -> Result<(), Error> {
+        device::claim(address)
+    }
+}
@@ -0,0 +1,70 @@
+use core::ops::Range;
+
+use libsyscalls::grant;
+
+use super::*;
+
+/// Revocable access to a region of the memory of the current process
+///
+/// The owner creates it on one of its mappings, and sends it to other processes, which can map it.
+/// When the owner revokes it, the kernel unmaps it from all processes at once: further accesses fault.
+/// This is safer than sending the memory object itself for transient sharing (eg: a buffer lent for one request).
+///
+/// Clone duplicates the handle: the owner keeps one to revoke the grant after sending the other.
+#[derive(Debug, Clone)]
+pub struct Grant {
+    handle: GrantHandle,
+}
+
+impl KObject for Grant {
+    type Handle = GrantHandle;
+
+    unsafe fn handle(&self) -> &Self::Handle {
+        &self.handle
+    }
+}
+
+impl Grant {
+    /// Create a grant on a region of the current process
+    ///
+    /// The region must be part of one mapping of a memory object, and `perms` must be allowed by the mapping.
+    pub fn create(range: &Range<usize>, perms: Permissions) -> Result<Self, Error> {
+        let handle = grant::create(range.start, range.len(), perms)?;
+        Ok(Self { handle })
+    }
+
+    /// Map the granted region into the process
+    ///
+    /// After a revoke, the mapping is replaced by a reservation until it is dropped.
+    pub fn map<'a>(
+        &self,
+        process: &'a Process,
+        addr: Option<usize>,
+        perms: Permissions,
+    ) -> Result<Mapping<'a>, Error> {
+        let (addr, size) = grant::map(unsafe { process.handle() }, &self.handle, addr, perms)?;
+
+        Ok(unsafe { Mapping::from_raw(process, addr..(addr + size), perms) })
+    }
+
+    /// Revoke the grant: unmap it from all processes, and forbid new mappings
+    ///
+    /// Only the process which created the grant can revoke it.
+    pub fn revoke(&self) -> Result<(), Error> {
+        grant::revoke(&self.handle)
+    }
+
+    /// Get the handle, to send it in a message
+    pub fn into_handle(self) -> Handle {
+        self.handle.into_handle()
+    }
+
+    /// Build a grant from a handle received in a message
+    ///
+    /// On type mismatch, the handle is given back.
+    pub fn from_handle(handle: Handle) -> Result<Self, Handle> {
+        Ok(Self {
+            handle: GrantHandle::from_handle(handle)?,
+        })
+    }
+}
@@ -1,14 +1,28 @@
 use core::{
     fmt::Debug,
     mem::{self, size_of},
+    slice,
 };
 
-use alloc::vec::Vec;
+use alloc::{vec, vec::Vec};
 use bit_field::BitArray;
 use libsyscalls::ipc;
+use spin::Mutex;
+
+use crate::{idempotency::IdempotencyToken, retry::Backoff};
 
 type SysMessage = libsyscalls::Message;
 
+/// Data items of the payload header: message type, payload length
+const PAYLOAD_HEADER_ITEMS: usize = 2;
+const PAYLOAD_HEADER_SIZE: usize = PAYLOAD_HEADER_ITEMS * size_of::<u64>();
+
+/// Flag set on the payload length when the payload is spilled into a memory object
+const PAYLOAD_SPILLED: u64 = 1 << 63;
+
+/// Handle index of the memory object of a spilled payload
+const PAYLOAD_HANDLE_INDEX: usize = 0;
+
 use super::*;
 
 pub struct Port {
@@ -18,11 +32,87 @@ pub struct Port {
 impl Port {
     /// Create a new port
     pub fn create(name: Option<&str>) -> Result<(PortReceiver, PortSender), Error> {
-        let (receiver, sender) = ipc::create(name)?;
+        Self::create_inner(name, None, false)
+    }
+
+    /// Create a new port, whose messages only use the first `data_capacity` data items
+    ///
+    /// Note: `data_capacity` is in u64 items, from 1 (message type only) to `Message::DATA_SIZE / 8`
+    pub fn create_with_capacity(
+        name: Option<&str>,
+        data_capacity: usize,
+    ) -> Result<(PortReceiver, PortSender), Error> {
+        Self::create_inner(name, Some(data_capacity), false)
+    }
+
+    /// Open a port by its name
+    pub fn open(name: &str) -> Result<PortSender, Error> {
+        Self::open_inner(ipc::NameOrId::Name(name))
+    }
+
+    /// Open a port by its id
+    pub fn open_id(id: u64) -> Result<PortSender, Error> {
+        Self::open_inner(ipc::NameOrId::Id(id))
+    }
+
+    /// Open a port by its name, waiting for it to be created if needed
+    ///
+    /// Servers can be started in any order: their clients wait for them to come up.
+    pub fn wait_open(name: &str) -> Result<PortSender, Error> {
+        let listener = PortListener::create(PortListenerFilter::Name(name))?;
+
+        loop {
+            let event = listener.blocking_receive()?;
+            if event.r#type != PortEventType::Registered {
+                continue;
+            }
+
+            match Self::open_inner(ipc::NameOrId::Id(event.port_id)) {
+                Ok(port) => return Ok(port),
+                // Closed meanwhile, wait for the next one
+                Err(Error::ObjectNotFound) => continue,
+                Err(err) => return Err(err),
+            }
+        }
+    }
+
+    /// Open a port by its name, retrying with `backoff` while it does not exist
+    ///
+    /// Unlike `wait_open`, it gives up according to the backoff policy (eg: on timeout).
+    pub fn open_with_backoff(name: &str, backoff: &Backoff) -> Result<PortSender, Error> {
+        backoff.retry(|| Self::open(name))
+    }
+
+    fn open_inner(name_or_id: ipc::NameOrId) -> Result<PortSender, Error> {
+        let handle = ipc::open(name_or_id)?;
+
+        Ok(PortSender {
+            handle,
+            cached_data_capacity: Mutex::new(None),
+        })
+    }
+
+    /// Create a new broadcast port: messages sent to it are delivered to each of its subscribers
+    ///
+    /// Messages cannot carry handles.
+    /// The receiver does not get any message, it keeps the port open.
+    pub fn create_broadcast(name: Option<&str>) -> Result<(PortReceiver, PortSender), Error> {
+        Self::create_inner(name, None, true)
+    }
+
+    fn create_inner(
+        name: Option<&str>,
+        data_capacity: Option<usize>,
+        broadcast: bool,
+    ) -> Result<(PortReceiver, PortSender), Error> {
+        let (receiver, sender) = ipc::create(name, data_capacity, broadcast)?;
 
         Ok((
-            PortReceiver::from_handle(receiver),
-            PortSender::from_handle(sender),
+            PortReceiver { handle: receiver },
+            PortSender {
+                handle: sender,
+                cached_data_capacity: Mutex::new(data_capacity.or(Some(SysMessage::DATA_SIZE))),
+            },
         ))
     }
 }
@@ -30,18 +120,55 @@ impl Port {
 /// Port sender
 #[derive(Debug)]
 pub struct PortSender {
-    handle: Handle,
+    handle: PortSenderHandle,
+    cached_data_capacity: Mutex<Option<usize>>,
+}
+
+// Duplicate the handle
+impl Clone for PortSender {
+    fn clone(&self) -> Self {
+        Self {
+            handle: self.handle.clone(),
+            cached_data_capacity: Mutex::new(*self.cached_data_capacity.lock()),
+        }
+    }
 }
 
 impl KObject for PortSender {
-    unsafe fn handle(&self) -> &Handle {
+    type Handle = PortSenderHandle;
+
+    unsafe fn handle(&self) -> &Self::Handle {
         &self.handle
     }
 }
 
 impl PortSender {
-    fn from_handle(handle: Handle) -> Self {
-        Self { handle }
+    /// Build a port sender from a handle received in a message
+    ///
+    /// On type mismatch, the handle is given back.
+    pub fn from_handle(handle: Handle) -> Result<Self, Handle> {
+        Ok(Self {
+            handle: PortSenderHandle::from_handle(handle)?,
+            cached_data_capacity: Mutex::new(None),
+        })
+    }
+
+    /// Get the handle, to send it in a message
+    pub fn into_handle(self) -> Handle {
+        self.handle.into_handle()
+    }
+
+    /// Get the number of data items (u64) usable in messages sent to the port
+    pub fn data_capacity(&self) -> Result<usize, Error> {
+        let mut cached = self.cached_data_capacity.lock();
+
+        if let Some(value) = *cached {
+            return Ok(value);
+        }
+
+        let value = ipc::info(&self.handle)?.data_capacity;
+        *cached = Some(value);
+        Ok(value)
     }
 
     /// Send a message in the port
@@ -55,22 +182,39 @@ impl PortSender {
 
         Ok(())
     }
+
+    /// Subscribe a port to this broadcast port
+    ///
+    /// The subscriber misses broadcast messages while it has `max_queued` messages (or more) waiting:
+    /// the count of missed messages is `PortInfo::broadcast_dropped` of the subscriber.
+    /// The subscription ends when the subscriber port is closed.
+    /// The subscriber is given by its receiver: only the process receiving from a port can subscribe it.
+    pub fn subscribe(&self, subscriber: &PortReceiver, max_queued: usize) -> Result<(), Error> {
+        ipc::subscribe(&self.handle, &subscriber.handle, max_queued)
+    }
+
+    /// Unsubscribe a port from this broadcast port
+    pub fn unsubscribe(&self, subscriber: &PortReceiver) -> Result<(), Error> {
+        ipc::unsubscribe(&self.handle, &subscriber.handle)
+    }
 }
 
 /// Port receiver
 #[derive(Debug)]
 pub struct PortReceiver {
-    handle: Handle,
+    handle: PortReceiverHandle,
 }
 
 impl KObject for PortReceiver {
-    unsafe fn handle(&self) -> &Handle {
+    type Handle = PortReceiverHandle;
+
+    unsafe fn handle(&self) -> &Self::Handle {
         &self.handle
     }
 }
 
 impl KWaitable for PortReceiver {
-    unsafe fn waitable_handle(&self) -> &Handle {
+    unsafe fn waitable_handle(&self) -> &PortReceiverHandle {
         &self.handle
     }
 
@@ -87,8 +231,18 @@ impl KWaitable for PortReceiver {
 }
 
 impl PortReceiver {
-    fn from_handle(handle: Handle) -> Self {
-        Self { handle }
+    /// Build a port receiver from a handle received in a message
+    ///
+    /// On type mismatch, the handle is given back.
+    pub fn from_handle(handle: Handle) -> Result<Self, Handle> {
+        Ok(Self {
+            handle: PortReceiverHandle::from_handle(handle)?,
+        })
+    }
+
+    /// Get the handle, to send it in a message
+    pub fn into_handle(self) -> Handle {
+        self.handle.into_handle()
     }
 
     /// Receive a message from the port
@@ -100,27 +254,57 @@ impl PortReceiver {
         Ok(unsafe { Message::from_receive_syscall(msg) })
     }
 
+    /// Get the header of the next message, without receiving it
+    ///
+    /// It lets the caller check the message (type, sender, attached handles) before accepting its handles with `receive()`, or dropping it with `discard()`.
+    ///
+    /// Note: the call does not block, it returns ObjectNotReady if no message is waiting
+    pub fn peek(&self) -> Result<MessageHeader, Error> {
+        ipc::peek(&self.handle)
+    }
+
+    /// Drop the next message without receiving it: its handles are closed, they never enter the handle table
+    ///
+    /// Note: the call does not block, it returns ObjectNotReady if no message is waiting
+    pub fn discard(&self) -> Result<(), Error> {
+        ipc::discard(&self.handle)
+    }
+
     /// Block until a message is received
+    ///
+    /// Messages sent by other threads of the current process are handed off directly to the waiting thread.
     pub fn blocking_receive(&self) -> Result<Message, Error> {
-        loop {
-            self.wait()?;
-
-            match self.receive() {
-                Err(Error::ObjectNotReady) => {
-                    // retry
-                }
-                other => {
-                    return other;
-                }
-            }
-        }
+        let msg = ipc::blocking_receive(&self.handle)?;
+
+        Ok(unsafe { Message::from_receive_syscall(msg) })
+    }
+
+    /// Route messages whose type is in none of `ranges` to the `alternate` port
+    ///
+    /// The message type is the first data item of the message.
+    /// The alternate port is given by its receiver, and must have been created by the current process.
+    pub fn set_filter(
+        &self,
+        ranges: &[PortFilterRange],
+        alternate: &PortReceiver,
+    ) -> Result<(), Error> {
+        ipc::set_filter(&self.handle, ranges, Some(&alternate.handle))
+    }
+
+    /// Remove the filter of the port
+    pub fn clear_filter(&self) -> Result<(), Error> {
+        ipc::set_filter(&self.handle, &[], None)
     }
 }
 
 /// Trait to be implemented by all waitable objects
 pub trait KWaitable: Debug {
     /// Get the internal waitable handle of the object
-    unsafe fn waitable_handle(&self) -> &Handle;
+    ///
+    /// # Safety
+    ///
+    /// The handle must not be closed or moved out: it is still owned by the object.
+    unsafe fn waitable_handle(&self) -> &PortReceiverHandle;
 
     /// Wait until the object is ready
     fn wait(&self) -> Result<(), Error>;
@@ -236,6 +420,22 @@ pub struct Message {
     /// Set to invalid if no handle
     ///
     pub handles: [Handle; Self::HANDLE_COUNT],
+
+    /// Correlation id of the request the message belongs to
+    ///
+    /// If 0 when sending, the kernel stamps the message with the current correlation id of the thread.
+    correlation: u64,
+
+    /// Deadline of the request the message belongs to, in nanoseconds since boot (0 if none)
+    ///
+    /// If 0 when sending, the kernel stamps the message with the current deadline of the thread.
+    deadline: u64,
+
+    /// Idempotency token of the request (0 if none), see `idempotency`
+    token: u64,
+
+    /// PID of the sending process (0 if sent by the kernel), stamped by the kernel on receive
+    sender_pid: u64,
 }
 
 #[derive(Debug)]
@@ -252,6 +452,10 @@ impl Default for Message {
                 data: [0; Self::DATA_SIZE],
             },
             handles: [INVALID_HANDLE; Self::HANDLE_COUNT],
+            correlation: 0,
+            deadline: 0,
+            token: 0,
+            sender_pid: 0,
         }
     }
 }
@@ -291,6 +495,11 @@ impl Message {
             msg.handles[index] = Handle::from_raw(sys_handle);
         }
 
+        msg.correlation = sys_msg.correlation;
+        msg.deadline = sys_msg.deadline;
+        msg.token = sys_msg.token;
+        msg.sender_pid = sys_msg.sender_pid;
+
         msg
     }
 
@@ -324,6 +533,115 @@ impl Message {
         assert!(mem::align_of::<T>() <= 8);
     }
 
+    /// Construct a new message carrying a byte payload
+    ///
+    /// Layout: data item 0 is the message type, data item 1 the payload length.
+    /// The payload is inline after them if it fits the data capacity of the port, else it is spilled into a memory object sent as handle 0.
+    pub fn with_payload(port: &PortSender, r#type: u64, payload: &[u8]) -> Result<Self, Error> {
+        let capacity = port.data_capacity()?;
+        if capacity < PAYLOAD_HEADER_ITEMS {
+            return Err(Error::InvalidArgument);
+        }
+
+        let inline_size = (capacity - PAYLOAD_HEADER_ITEMS) * size_of::<u64>();
+        let mut msg = Self::default();
+
+        let header = unsafe { msg.data_mut::<[u64; PAYLOAD_HEADER_ITEMS]>() };
+        header[0] = r#type;
+        header[1] = payload.len() as u64;
+
+        if payload.len() <= inline_size {
+            msg.data.data[PAYLOAD_HEADER_SIZE..PAYLOAD_HEADER_SIZE + payload.len()]
+                .copy_from_slice(payload);
+            return Ok(msg);
+        }
+
+        header[1] |= PAYLOAD_SPILLED;
+
+        let size = payload.len().next_multiple_of(PAGE_SIZE);
+        let mobj = MemoryObject::create(size)?;
+
+        {
+            let mapping = Process::current().map_mem(
+                None,
+                size,
+                Permissions::READ | Permissions::WRITE,
+                &mobj,
+                0,
+            )?;
+
+            let dest = unsafe { slice::from_raw_parts_mut(mapping.address() as *mut u8, size) };
+            dest[..payload.len()].copy_from_slice(payload);
+        }
+
+        msg.handles[PAYLOAD_HANDLE_INDEX] = mobj.into_handle();
+
+        Ok(msg)
+    }
+
+    /// Get the byte payload of a message built with `with_payload`
+    ///
+    /// If the payload has been spilled, the memory object handle is taken from the message.
+    pub fn payload(&mut self) -> Result<Vec<u8>, Error> {
+        let header = unsafe { *self.data::<[u64; PAYLOAD_HEADER_ITEMS]>() };
+        let len = (header[1] & !PAYLOAD_SPILLED) as usize;
+
+        if header[1] & PAYLOAD_SPILLED == 0 {
+            let inline = &self.data.data[PAYLOAD_HEADER_SIZE..];
+            if len > inline.len() {
+                return Err(Error::InvalidArgument);
+            }
+
+            return Ok(Vec::from(&inline[..len]));
+        }
+
+        let mobj = MemoryObject::from_handle(self.take_handle(PAYLOAD_HANDLE_INDEX))
+            .map_err(|_| Error::InvalidArgument)?;
+
+        let size = len.next_multiple_of(PAGE_SIZE);
+        let mapping = Process::current().map_mem(None, size, Permissions::READ, &mobj, 0)?;
+
+        let mut payload = vec![0u8; len];
+        payload
+            .copy_from_slice(unsafe { slice::from_raw_parts(mapping.address() as *const u8, len) });
+
+        Ok(payload)
+    }
+
+    /// Get the correlation id of the message
+    pub fn correlation(&self) -> u64 {
+        self.correlation
+    }
+
+    /// Set the correlation id of the message, instead of the one of the sending thread
+    pub fn set_correlation(&mut self, correlation: u64) {
+        self.correlation = correlation;
+    }
+
+    /// Get the deadline of the message, in nanoseconds since boot (0 if none)
+    pub fn deadline(&self) -> u64 {
+        self.deadline
+    }
+
+    /// Get the idempotency token of the message (0 if none)
+    pub fn token(&self) -> u64 {
+        self.token
+    }
+
+    /// Set the idempotency token of the message
+    ///
+    /// Retries of the same request must use the same token.
+    pub fn set_token(&mut self, token: IdempotencyToken) {
+        self.token = token.as_u64();
+    }
+
+    /// Get the PID of the sending process (0 if sent by the kernel)
+    ///
+    /// It is stamped by the kernel: servers can trust it as the identity of their peer (eg: to check permissions or audit).
+    pub fn sender_pid(&self) -> u64 {
+        self.sender_pid
+    }
+
     /// Get the handle at index (index must be < 8)
     pub fn handle(&self, index: usize) -> &Handle {
         &self.handles[index]
@@ -342,6 +660,10 @@ impl Message {
             data: unsafe { mem::transmute(self.data.data) },
             handles: [unsafe { Handle::invalid().as_syscall_value() } as u64;
                 Message::HANDLE_COUNT],
+            correlation: self.correlation,
+            deadline: self.deadline,
+            token: self.token,
+            sender_pid: 0,
         };
 
         // pass handle values
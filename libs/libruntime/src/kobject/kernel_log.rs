@@ -0,0 +1,43 @@
+use libsyscalls::{log_read, log_relay, log_set_level};
+
+use super::*;
+
+/// Reader of the kernel log ring
+#[derive(Debug, Default)]
+pub struct KernelLog {
+    /// Position in the ring (number of bytes since boot)
+    cursor: u64,
+}
+
+impl KernelLog {
+    /// Create a reader, starting at the oldest records available
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Read the next records, as text
+    ///
+    /// Returns an empty slice if there are no new records.
+    /// If the reader is too late, the records overwritten in the meantime are skipped.
+    pub fn read<'a>(&mut self, buffer: &'a mut [u8]) -> Result<&'a [u8], Error> {
+        log_read(&mut self.cursor, buffer)
+    }
+
+    /// Log a record on behalf of a thread of another process (eg: buffered records drained by a log server)
+    ///
+    /// The record is attributed to the process, and counts in its rate limit.
+    /// Note: the caller must have the `DEBUG` sandbox right (see `Process::read_memory`).
+    pub fn relay(
+        process: &Process,
+        tid: u64,
+        level: log::Level,
+        message: &str,
+    ) -> Result<(), Error> {
+        log_relay(unsafe { process.handle() }, tid, level, message)
+    }
+
+    /// Set the level threshold of a kernel log sink
+    pub fn set_level(sink: LogSink, level: log::LevelFilter) -> Result<(), Error> {
+        log_set_level(sink, level)
+    }
+}
@@ -54,12 +54,12 @@ impl ThreadListenerFilterOwner {
 #[derive(Debug)]
 pub struct ThreadListener {
     filter: ThreadListenerFilterOwner,
-    _listener: Handle,
+    _listener: ThreadListenerHandle,
     reader: PortReceiver,
 }
 
 impl KWaitable for ThreadListener {
-    unsafe fn waitable_handle(&self) -> &Handle {
+    unsafe fn waitable_handle(&self) -> &PortReceiverHandle {
         self.reader.waitable_handle()
     }
 
@@ -150,12 +150,12 @@ impl ProcessListenerFilterOwner {
 #[derive(Debug)]
 pub struct ProcessListener {
     filter: ProcessListenerFilterOwner,
-    _listener: Handle,
+    _listener: ProcessListenerHandle,
     reader: PortReceiver,
 }
 
 impl KWaitable for ProcessListener {
-    unsafe fn waitable_handle(&self) -> &Handle {
+    unsafe fn waitable_handle(&self) -> &PortReceiverHandle {
         self.reader.waitable_handle()
     }
 
@@ -199,3 +199,76 @@ impl ProcessListener {
         self.filter.as_ref()
     }
 }
+
+/// Indicate the filter type on port listener
+#[derive(Debug)]
+pub enum PortListenerFilter<'a> {
+    /// All named ports
+    All,
+    /// Ports with this exact name
+    Name(&'a str),
+    /// Ports whose name starts with this prefix
+    Prefix(&'a str),
+}
+
+impl<'a> PortListenerFilter<'a> {
+    fn syscall_arg(&self) -> (&'a str, bool) {
+        match self {
+            PortListenerFilter::All => ("", true),
+            PortListenerFilter::Name(name) => (name, false),
+            PortListenerFilter::Prefix(prefix) => (prefix, true),
+        }
+    }
+}
+
+/// Port listener: get notified when named ports are registered or unregistered
+///
+/// On creation, a `Registered` event is received for each matching port that already exists,
+/// so that a client can wait for a server to come up without racing with it.
+///
+/// Note: since port listener is a complex object, it does not implement KObject directly
+#[derive(Debug)]
+pub struct PortListener {
+    _listener: PortListenerHandle,
+    reader: PortReceiver,
+}
+
+impl KWaitable for PortListener {
+    unsafe fn waitable_handle(&self) -> &PortReceiverHandle {
+        self.reader.waitable_handle()
+    }
+
+    fn wait(&self) -> Result<(), Error> {
+        self.reader.wait()
+    }
+}
+
+impl PortListener {
+    /// Create a new object which listen to port event.
+    pub fn create(filter: PortListenerFilter) -> Result<Self, Error> {
+        let (reader, sender) = Port::create(None)?;
+        let (name, is_prefix) = filter.syscall_arg();
+        let listener = listener::create_port(unsafe { sender.handle() }, name, is_prefix)?;
+
+        Ok(Self {
+            _listener: listener,
+            reader,
+        })
+    }
+
+    /// Receive a port event
+    ///
+    /// Note: the call does not block, it returns ObjectNotReady if no message is waiting
+    pub fn receive(&self) -> Result<PortEvent, Error> {
+        let msg = self.reader.receive()?;
+
+        Ok(unsafe { msg.data::<PortEvent>().clone() })
+    }
+
+    /// Block until a port event is received
+    pub fn blocking_receive(&self) -> Result<PortEvent, Error> {
+        let msg = self.reader.blocking_receive()?;
+
+        Ok(unsafe { msg.data::<PortEvent>().clone() })
+    }
+}
@@ -0,0 +1,42 @@
+use alloc::vec::Vec;
+use libsyscalls::measurement;
+
+use super::*;
+
+/// Kernel measurement log: digests of the components loaded since boot (ramdisk, binaries)
+///
+/// The log is append-only. Each entry extends a hash chain, so that the last chain value summarizes the whole log.
+#[derive(Debug)]
+pub struct MeasurementLog;
+
+impl MeasurementLog {
+    /// Measure a component before using it (eg: a binary before loading it)
+    pub fn measure(name: &str, data: &[u8]) -> Result<(), Error> {
+        measurement::extend(name, data)
+    }
+
+    /// Read the whole log
+    pub fn read() -> Result<Vec<Measurement>, Error> {
+        let mut buffer = Vec::with_capacity(Measurement::MAX_COUNT);
+        buffer.resize(Measurement::MAX_COUNT, Measurement::default());
+
+        let count = measurement::read(0, &mut buffer)?.len();
+        buffer.truncate(count);
+
+        Ok(buffer)
+    }
+
+    /// Check that the chain values of the entries match their digests
+    pub fn verify(entries: &[Measurement]) -> bool {
+        let mut chain = Measurement::INITIAL_CHAIN;
+
+        for entry in entries {
+            chain = Measurement::extend(&chain, &entry.digest);
+            if chain != entry.chain {
+                return false;
+            }
+        }
+
+        true
+    }
+}
@@ -12,4 +12,11 @@ impl Memory {
     pub fn stats() -> MemoryStats {
         memory::stats().expect("Could not get memory stats")
     }
+
+    /// Audit physical frames reference counts against mappings
+    ///
+    /// Note: only privileged threads can run the audit
+    pub fn audit_frames() -> Result<FrameAudit, Error> {
+        memory::audit_frames()
+    }
 }
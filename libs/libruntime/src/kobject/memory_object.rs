@@ -3,21 +3,82 @@ use libsyscalls::memory_object;
 use super::*;
 
 /// Memory object
-#[derive(Debug)]
+///
+/// Clone duplicates the handle: both refer to the same memory.
+#[derive(Debug, Clone)]
 pub struct MemoryObject {
-    handle: Handle,
+    handle: MemoryObjectHandle,
 }
 
 impl KObject for MemoryObject {
-    unsafe fn handle(&self) -> &Handle {
+    type Handle = MemoryObjectHandle;
+
+    unsafe fn handle(&self) -> &Self::Handle {
         &self.handle
     }
 }
 
 impl MemoryObject {
-    /// Create a new memory object of the specified size
+    /// Create a new memory object of the specified size, with zeroed content
     pub fn create(size: usize) -> Result<Self, Error> {
-        let handle = memory_object::create(size)?;
+        let handle = memory_object::create(size, MemoryObjectFlags::NONE)?;
+        Ok(Self { handle })
+    }
+
+    /// Create a new memory object of the specified size, without zeroing it
+    ///
+    /// Faster for objects that will be fully overwritten (eg: ELF segment staging).
+    /// The content is unspecified: it may contain data of freed memory, it must not be exposed before being overwritten.
+    /// Only privileged threads can create them: it fails with `Error::NotSupported` otherwise.
+    pub fn create_uninitialized(size: usize) -> Result<Self, Error> {
+        let handle = memory_object::create(size, MemoryObjectFlags::UNINITIALIZED)?;
+        Ok(Self { handle })
+    }
+
+    /// Send a `MemoryObjectEvent` with `cookie` to the port when the memory object is released
+    ///
+    /// The object is released when all handles (in all processes) have been closed and it is not mapped anymore.
+    /// Notes:
+    /// - the caller must close its own handles (including this one) for the notification to happen
+    /// - the notification is sent even if the port has no other sender anymore
+    pub fn notify_release(&self, port: &PortSender, cookie: u64) -> Result<(), Error> {
+        memory_object::notify_release(&self.handle, unsafe { port.handle() }, cookie)
+    }
+
+    /// Get a new handle to the memory object, which can only be mapped with `perms` (enforced by the kernel)
+    ///
+    /// The new handle cannot have more permissions than this one. Clones of it keep the restriction.
+    pub fn restrict(&self, perms: Permissions) -> Result<Self, Error> {
+        let handle = memory_object::restrict(&self.handle, perms)?;
         Ok(Self { handle })
     }
+
+    /// Get a new handle to the memory object, which can only be mapped read-only
+    ///
+    /// It lets a server share a buffer (eg: a result) without the receiver being able to modify it.
+    pub fn read_only(&self) -> Result<Self, Error> {
+        self.restrict(Permissions::READ)
+    }
+
+    /// Get the physical address of the page at `offset`, to program DMA transfers
+    ///
+    /// Pages of a memory object are not physically contiguous: DMA descriptors must be built per page.
+    /// Only privileged threads and processes which drive a device (see `Device::claim`) can query it.
+    pub fn physical_address(&self, offset: usize) -> Result<u64, Error> {
+        memory_object::physical_address(&self.handle, offset)
+    }
+
+    /// Get the handle, to send it in a message
+    pub fn into_handle(self) -> Handle {
+        self.handle.into_handle()
+    }
+
+    /// Build a memory object from a handle received in a message
+    ///
+    /// On type mismatch, the handle is given back.
+    pub fn from_handle(handle: Handle) -> Result<Self, Handle> {
+        Ok(Self {
+            handle: MemoryObjectHandle::from_handle(handle)?,
+        })
+    }
 }
@@ -1,33 +1,100 @@
 pub const PAGE_SIZE: usize = 4096;
 
+/// Number of ids fetched per syscall when listing objects
+const LIST_PAGE_SIZE: usize = 256;
+
+/// Fetch a whole id list by pages
+///
+/// `fetch_page(start, buffer)` fills the buffer with the page starting at `start`,
+/// and returns the page length and the `start` of the next page, if any.
+fn list_by_pages<F: FnMut(u64, &mut [u64]) -> Result<(usize, Option<u64>), Error>>(
+    mut fetch_page: F,
+) -> Result<Box<[u64]>, Error> {
+    let mut ids = Vec::new();
+    let mut buffer = [0u64; LIST_PAGE_SIZE];
+    let mut cursor = Some(0);
+
+    while let Some(start) = cursor {
+        let (len, next) = fetch_page(start, &mut buffer)?;
+
+        ids.extend_from_slice(&buffer[..len]);
+        cursor = next;
+    }
+
+    Ok(ids.into_boxed_slice())
+}
+
 use core::fmt::Debug;
+
+use alloc::{boxed::Box, vec::Vec};
 pub use libsyscalls::{
-    Error, Exception, Handle, KallocStats, KvmStats, MemoryStats, Permissions, PhysStats,
-    ProcessEvent, ProcessEventType, ProcessInfo, ThreadContext, ThreadContextRegister, ThreadEvent,
-    ThreadEventType, ThreadInfo, ThreadPriority,
+    AuditEventType, AuditRecord, AuditRule, CurrentIds, DeviceBus, DeviceInfo, DeviceResource,
+    DeviceResourceType, Error, Exception, FrameAudit, GrantHandle, Handle, HandleInfo, HandleType, IdleMethod,
+    IdleStats, KallocStats, KvmStats, LogSink, MappingInfo, Measurement, MemoryObjectEvent,
+    MemoryObjectEventType, MemoryObjectFlags, MemoryObjectHandle, MemoryStats, MessageHeader,
+    NameEntry, ObjectCounts, Permissions, PhysStats, PortEvent, PortEventType, PortFilterRange,
+    PortHandle, PortListenerHandle, PortReceiverHandle, PortSenderHandle, ProcessEvent,
+    ProcessEventType, ProcessHandle, ProcessInfo, ProcessListenerHandle, ReadyLatency,
+    SandboxFlags, SchedEvent, SchedEventType, SleepMode, SyscallLatency, SystemInfo, ThreadContext,
+    ThreadContextRegister, ThreadEvent, ThreadEventType, ThreadHandle, ThreadInfo,
+    ThreadListenerHandle, ThreadPriority, ThreadState, TimerEvent, TimerHandle, TimerStats,
+    TrampolineHandle, TypedHandle, WaitCause, WatchpointKind, MAX_ADVANCE_NS,
+    PROCESS_MEMORY_MAX_SIZE, TICK_NS, WATCHPOINT_COUNT,
 };
 
+mod audit;
+mod clock;
+mod device;
+mod grant;
 mod ipc;
+mod kernel_log;
 mod listener;
+mod measurement;
 mod memory;
 mod memory_object;
+mod power;
 mod process;
+mod stats;
+mod strings;
 mod thread;
+mod timer;
 mod tls;
+mod trampoline;
 
 /// Trait to be implemented by all kobjects
 pub trait KObject: Debug {
+    /// Type of the internal handle
+    type Handle: TypedHandle;
+
     /// Get the internal handle of the object
-    unsafe fn handle(&self) -> &Handle;
+    ///
+    /// # Safety
+    ///
+    /// The handle must not be closed or moved out: it is still owned by the object.
+    unsafe fn handle(&self) -> &Self::Handle;
 }
 
+pub use audit::Audit;
+pub use clock::Clock;
+pub use device::Device;
+pub use grant::Grant;
 pub use ipc::{KWaitable, Message, Port, PortReceiver, PortSender, Waiter};
-pub use listener::{ProcessListener, ProcessListenerFilter, ThreadListener, ThreadListenerFilter};
+pub use kernel_log::KernelLog;
+pub use listener::{
+    PortListener, PortListenerFilter, ProcessListener, ProcessListenerFilter, ThreadListener,
+    ThreadListenerFilter,
+};
+pub use measurement::MeasurementLog;
 pub use memory::Memory;
 pub use memory_object::MemoryObject;
+pub use power::Power;
 pub use process::{Mapping, Process};
+pub use stats::Stats;
+pub use strings::Strings;
 pub use thread::{Thread, ThreadOptions, ThreadSupervisor};
-pub use tls::{TlsAllocator, TlsSlot};
+pub use timer::Timer;
+pub use tls::{TlsAllocator, TlsSlot, TLS_SIZE};
+pub use trampoline::Trampoline;
 
 pub(crate) fn init() {
     thread::THREAD_GC.init();
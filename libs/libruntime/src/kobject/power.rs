@@ -0,0 +1,66 @@
+use alloc::vec::Vec;
+use libsyscalls::power;
+
+use super::*;
+
+/// System power management
+pub struct Power {
+    _priv: (),
+}
+
+impl Power {
+    /// Enter a sleep state, and return once the system is resumed
+    ///
+    /// Userland is frozen first: all the processes which can be suspended are, and only them are resumed after.
+    /// Drivers must have been quiesced by the caller before (see `libdriver::power`).
+    ///
+    /// Note: only privileged threads can suspend the machine
+    pub fn suspend(mode: SleepMode) -> Result<(), Error> {
+        let mut frozen = Vec::new();
+
+        for pid in Process::list()?.iter() {
+            // The current process, the idle process, already suspended or exited processes cannot be suspended
+            let Ok(process) = Process::open(*pid) else {
+                continue;
+            };
+
+            if process.suspend().is_ok() {
+                frozen.push(process);
+            }
+        }
+
+        let result = power::suspend(mode);
+
+        for process in frozen.iter() {
+            // The process may have been killed meanwhile
+            let _ = process.resume();
+        }
+
+        result
+    }
+}
+
+impl Power {
+    /// Power off the machine
+    ///
+    /// Returns only on error (eg: the machine does not support it).
+    ///
+    /// Note: only privileged threads can power off the machine
+    pub fn power_off() -> Result<(), Error> {
+        power::power_off()
+    }
+}
+
+impl Power {
+    /// Replace the running kernel by a new image (ELF file), started with the given ramdisk (kexec-lite)
+    ///
+    /// The whole system restarts from the new kernel boot, without going through the firmware: all processes are lost.
+    /// The memory map, the log levels, the serial port and the console are handed off to the new kernel.
+    ///
+    /// Returns only on error (eg: invalid image, not enough contiguous memory to load it).
+    ///
+    /// Note: only privileged threads can reload the kernel
+    pub fn reload(kernel: &[u8], ramdisk: &[u8]) -> Result<(), Error> {
+        power::reload(kernel, ramdisk)
+    }
+}
@@ -1,6 +1,10 @@
-use core::{ops::Range, slice};
+use core::{
+    ops::Range,
+    slice,
+    sync::atomic::{AtomicBool, Ordering},
+};
 
-use alloc::{boxed::Box, string::String, vec::Vec};
+use alloc::{boxed::Box, string::String, sync::Arc, vec::Vec};
 use libsyscalls::process;
 use spin::Mutex;
 
@@ -10,11 +14,13 @@ use super::*;
 #[derive(Debug)]
 pub struct Process {
     cached_pid: Mutex<Option<u64>>,
-    handle: Handle,
+    handle: ProcessHandle,
 }
 
 impl KObject for Process {
-    unsafe fn handle(&self) -> &Handle {
+    type Handle = ProcessHandle;
+
+    unsafe fn handle(&self) -> &Self::Handle {
         &self.handle
     }
 }
@@ -57,6 +63,16 @@ impl Process {
         })
     }
 
+    /// Build a process from a handle received in a message
+    ///
+    /// On type mismatch, the handle is given back.
+    pub fn from_handle(handle: Handle) -> Result<Self, Handle> {
+        Ok(Self {
+            cached_pid: Mutex::new(None),
+            handle: ProcessHandle::from_handle(handle)?,
+        })
+    }
+
     /// Get the process id
     pub fn pid(&self) -> u64 {
         if let Some(value) = *self.cached_pid.lock() {
@@ -83,17 +99,75 @@ impl Process {
         info
     }
 
-    /// List the process ids in the system
-    pub fn list() -> Result<Box<[u64]>, Error> {
-        let mut size = 1024;
+    /// Suspend the process: none of its threads runs until it is resumed
+    ///
+    /// Threads blocked in a syscall are suspended once it completes.
+    /// The context of suspended threads can be read and updated (see `Thread::context`).
+    /// The current process and the idle process cannot be suspended.
+    ///
+    /// Note: only the process which created it, debuggers (`DEBUG` sandbox right) or privileged threads can suspend and resume it
+    pub fn suspend(&self) -> Result<(), Error> {
+        process::suspend(&self.handle)
+    }
+
+    /// Resume the suspended process
+    pub fn resume(&self) -> Result<(), Error> {
+        process::resume(&self.handle)
+    }
+
+    /// Set the limits on the number of ports, timers and listeners the process can create
+    ///
+    /// This is up to the spawner: the current process cannot set its own limits.
+    /// New processes get `ObjectCounts::DEFAULT_LIMITS`.
+    ///
+    /// Note: only the process which created it or privileged threads can set the limits
+    pub fn set_object_limits(&self, limits: &ObjectCounts) -> Result<(), Error> {
+        process::set_object_limits(&self.handle, limits)
+    }
+
+    /// Set the sandbox rights of the process (eg: from the manifest of its binary)
+    ///
+    /// New processes get none, init has them all.
+    ///
+    /// Note: only the process which created it or privileged threads can set them, and not above their own rights
+    pub fn set_sandbox(&self, flags: SandboxFlags) -> Result<(), Error> {
+        process::set_sandbox(&self.handle, flags)
+    }
+
+    /// Grant access to a range of io-ports to the process: its threads can use `in`/`out` on them
+    ///
+    /// Note: only the process which created it or privileged threads can grant io-ports,
+    /// and they must hold the range themselves (except init, which delegates the io-ports of the machine)
+    pub fn grant_io_ports(&self, range: Range<u16>) -> Result<(), Error> {
+        process::grant_io_ports(&self.handle, range)
+    }
+
+    /// Read the memory of the process at `addr` into `buffer`
+    ///
+    /// Note: the caller must have the `DEBUG` sandbox right, unless it reads its own memory.
+    /// The whole range must be readable, else it fails with `Error::MemoryAccessDenied`.
+    pub fn read_memory(&self, addr: usize, buffer: &mut [u8]) -> Result<(), Error> {
+        process::memory_read(&self.handle, addr, buffer)
+    }
+
+    /// Write `data` to the memory of the process at `addr`
+    ///
+    /// Note: same rights than `read_memory`, and the whole range must be writable (code is never patched).
+    pub fn write_memory(&self, addr: usize, data: &[u8]) -> Result<(), Error> {
+        process::memory_write(&self.handle, addr, data)
+    }
+
+    /// List the handles opened by the process
+    ///
+    /// Note: same rights than `read_memory`.
+    pub fn handles(&self) -> Result<Box<[HandleInfo]>, Error> {
+        let mut size = 64;
 
-        // Event not atomic, let's consider that with doubling the required size between call,
-        // at some point we will be able to fetch list entirely
         loop {
             let mut buffer = Vec::with_capacity(size);
-            buffer.resize(size, 0);
+            buffer.resize(size, HandleInfo::default());
 
-            let (_, new_size) = process::list(&mut buffer)?;
+            let (_, new_size) = process::handles(&self.handle, &mut buffer)?;
 
             if new_size > size {
                 // Retry with 2x requested size
@@ -101,12 +175,63 @@ impl Process {
                 continue;
             }
 
-            buffer.resize(new_size, 0);
+            buffer.truncate(new_size);
 
             return Ok(buffer.into_boxed_slice());
         }
     }
 
+    /// Open the object of `source` in the process, with the handle value `value` (eg: restore of a checkpoint)
+    ///
+    /// Note: the caller must control the process (its creator), and `value` must not be used in it.
+    pub fn install_handle(&self, value: u64, source: &Handle) -> Result<(), Error> {
+        process::install_handle(&self.handle, value, source)
+    }
+
+    /// Get the number of ports, timers and listeners created by the process, and its limits
+    ///
+    /// Returns (usage, limits)
+    pub fn object_usage(&self) -> (ObjectCounts, ObjectCounts) {
+        process::object_usage(&self.handle).expect("Could not get process object usage")
+    }
+
+    /// List the process ids in the system
+    ///
+    /// Note: the list is fetched by pages, so it is not atomic.
+    pub fn list() -> Result<Box<[u64]>, Error> {
+        list_by_pages(|start, buffer| {
+            process::list(start, buffer).map(|(page, next)| (page.len(), next))
+        })
+    }
+
+    /// List the ids of the threads of the process
+    ///
+    /// Note: the list is fetched by pages, so it is not atomic.
+    pub fn threads(&self) -> Result<Box<[u64]>, Error> {
+        list_by_pages(|start, buffer| {
+            process::threads(&self.handle, start, buffer).map(|(page, next)| (page.len(), next))
+        })
+    }
+
+    /// List the ids of the ports created by the process
+    ///
+    /// Note: the list is fetched by pages, so it is not atomic.
+    pub fn ports(&self) -> Result<Box<[u64]>, Error> {
+        list_by_pages(|start, buffer| {
+            process::ports(&self.handle, start, buffer).map(|(page, next)| (page.len(), next))
+        })
+    }
+
+    /// Get the names of several processes in one call
+    pub fn names(pids: &[u64]) -> Result<Vec<NameEntry>, Error> {
+        let mut entries = Vec::with_capacity(pids.len());
+        entries.resize_with(pids.len(), || NameEntry::not_found(0));
+
+        process::names(pids, &mut entries)?;
+
+        Ok(entries)
+    }
+
     /// Set the name of the process
     pub fn set_name(&self, name: &str) -> Result<(), Error> {
         process::set_name(&self.handle, name)
@@ -136,13 +261,46 @@ impl Process {
     }
 
     /// Reserve an area in the process VM, but no not back it with memory
+    ///
+    /// Accesses to the area fault until parts of it are committed with `map_commit`.
+    /// Reserving at a given address fails with `Error::AddressInUse` if the area is already used.
     pub fn map_reserve(&self, addr: Option<usize>, size: usize) -> Result<Mapping, Error> {
-        let addr = process::mmap(&self.handle, addr, size, Permissions::NONE, None, 0)?;
+        let addr = process::mreserve(&self.handle, addr, size)?;
+
+        Ok(unsafe { Mapping::from_raw(self, addr..(addr + size), Permissions::NONE) })
+    }
+
+    /// Back a part of a reservation with a memory object
+    ///
+    /// The range must be fully covered by reservations (see `map_reserve`): committing never replaces memory in use.
+    /// It fails with `Error::InvalidArgument` otherwise.
+    pub fn map_commit(
+        &self,
+        range: &Range<usize>,
+        perms: Permissions,
+        mobj: &MemoryObject,
+        offset: usize,
+    ) -> Result<Mapping, Error> {
+        process::mcommit(&self.handle, range, perms, unsafe { mobj.handle() }, offset)?;
+
+        Ok(unsafe { Mapping::from_raw(self, range.clone(), perms) })
+    }
+
+    /// Map a stack of at most `max_size`, with `initial_size` committed at its top
+    ///
+    /// The kernel commits memory when the stack grows, a guard page at its bottom catches overflows.
+    /// The mapping covers the whole region, guard page included: the stack top is its end.
+    pub fn map_stack(&self, max_size: usize, initial_size: usize) -> Result<Mapping, Error> {
+        let addr = process::mmap_stack(&self.handle, max_size, initial_size)?;
+        let size = max_size + PAGE_SIZE;
 
-        Ok(unsafe { Mapping::unleak(self, addr..(addr + size), Permissions::NONE) })
+        Ok(unsafe { Mapping::from_raw(self, addr..(addr + size), Permissions::NONE) })
     }
 
     /// Map a memory object into the process VM
+    ///
+    /// Mapping at a given address fails with `Error::AddressInUse` if the area is already used (by a mapping or a reservation):
+    /// existing mappings are never replaced, they must be unmapped first.
     pub fn map_mem(
         &self,
         addr: Option<usize>,
@@ -160,38 +318,164 @@ impl Process {
             offset,
         )?;
 
-        Ok(unsafe { Mapping::unleak(self, addr..(addr + size), perms) })
+        Ok(unsafe { Mapping::from_raw(self, addr..(addr + size), perms) })
     }
 
     /// Unmap an area in the process VM
     pub fn unmap(&self, range: &Range<usize>) -> Result<(), Error> {
         process::munmap(&self.handle, range)
     }
+
+    /// Change the permissions of an area in the process VM
+    ///
+    /// The area must be fully mapped with memory, possibly by several mappings: it fails with `Error::AddressNotMapped`
+    /// if part of it is not mapped, and with `Error::AddressReserved` if part of it is a reservation.
+    /// Memory in use (eg: referenced by Rust objects) must keep its access.
+    pub fn protect(&self, range: &Range<usize>, perms: Permissions) -> Result<(), Error> {
+        process::mprotect(&self.handle, range, perms)
+    }
+
+    /// Set the name of an area in the process VM, or clear it if `name` is None
+    ///
+    /// The area must be fully mapped, reservations included: it fails with `Error::AddressNotMapped` otherwise.
+    pub fn name_mem(&self, range: &Range<usize>, name: Option<&str>) -> Result<(), Error> {
+        process::mname(&self.handle, range, name)
+    }
+
+    /// List the mappings of the process VM
+    pub fn mappings(&self) -> Result<Box<[MappingInfo]>, Error> {
+        let mut size = 64;
+
+        // Event not atomic, let's consider that with doubling the required size between call,
+        // at some point we will be able to fetch list entirely
+        loop {
+            let mut buffer = Vec::with_capacity(size);
+            buffer.resize(size, MappingInfo::default());
+
+            let (_, new_size) = process::mappings(&self.handle, &mut buffer)?;
+
+            if new_size > size {
+                // Retry with 2x requested size
+                size = new_size * 2;
+                continue;
+            }
+
+            buffer.truncate(new_size);
+
+            return Ok(buffer.into_boxed_slice());
+        }
+    }
+
+    /// List the mappings of the process VM which overlap the given range (reservations included)
+    ///
+    /// Since mapping at a fixed address fails if the area is already used, loaders can use it to report what is in the way.
+    pub fn mappings_in(&self, range: &Range<usize>) -> Result<Box<[MappingInfo]>, Error> {
+        let mut size = 8;
+
+        loop {
+            let mut buffer = Vec::with_capacity(size);
+            buffer.resize(size, MappingInfo::default());
+
+            let (_, new_size) = process::query_range(&self.handle, range, &mut buffer)?;
+
+            if new_size > size {
+                size = new_size * 2;
+                continue;
+            }
+
+            buffer.truncate(new_size);
+
+            return Ok(buffer.into_boxed_slice());
+        }
+    }
 }
 
 /// Mapping of memory
 ///
+/// A mapping is a reference-counted view: clones and sub-views (`view`) share the same underlying mapping,
+/// which is unmapped when the last of them is dropped.
+/// `into_raw` gives up the ownership (the memory stays mapped), `from_raw` takes it back.
+///
 /// Note: creating an overlapping mapping will not update this one. Care must be taken to arrange it properly.
+#[derive(Debug, Clone)]
 pub struct Mapping<'a> {
-    process: &'a Process,
+    inner: Arc<MappingInner<'a>>,
+    /// Part of the underlying mapping accessed by this view
     range: Range<usize>,
     perms: Permissions,
 }
 
+#[derive(Debug)]
+struct MappingInner<'a> {
+    process: &'a Process,
+    range: Range<usize>,
+    /// Set by `into_raw`: do not unmap on drop
+    leaked: AtomicBool,
+}
+
+impl Drop for MappingInner<'_> {
+    fn drop(&mut self) {
+        if !self.leaked.load(Ordering::Relaxed) {
+            self.process
+                .unmap(&self.range)
+                .expect("Could not free maping");
+        }
+    }
+}
+
 impl<'a> Mapping<'a> {
-    /// Rebuild a mapping previously leaked
+    /// Take the ownership of a mapping, previously released by `into_raw` or created without `Mapping` (eg: by the kernel)
     ///
     /// # Safety
     ///
-    /// The given arguments must be from a leaking mapping
-    pub unsafe fn unleak(process: &'a Process, range: Range<usize>, perms: Permissions) -> Self {
+    /// The range must be mapped in the process with the given permissions, and not owned by another `Mapping`
+    pub unsafe fn from_raw(process: &'a Process, range: Range<usize>, perms: Permissions) -> Self {
         Self {
-            process,
+            inner: Arc::new(MappingInner {
+                process,
+                range: range.clone(),
+                leaked: AtomicBool::new(false),
+            }),
             range,
             perms,
         }
     }
 
+    /// Give up the ownership of the mapping: it will not be unmapped when dropped (including by other views)
+    ///
+    /// Returns the range and permissions of this view, to rebuild it later with `from_raw`
+    pub fn into_raw(self) -> (Range<usize>, Permissions) {
+        self.inner.leaked.store(true, Ordering::Relaxed);
+        (self.range.clone(), self.perms)
+    }
+
+    /// Leak the mapping, consuming the object. The mapping is not freed.
+    pub fn leak(self) {
+        self.into_raw();
+    }
+
+    /// Get a view on a part of the mapping
+    ///
+    /// `offset` and `size` are relative to this view, and must be page aligned.
+    /// The view keeps the whole mapping alive.
+    pub fn view(&self, offset: usize, size: usize) -> Result<Self, Error> {
+        if offset % PAGE_SIZE != 0
+            || size % PAGE_SIZE != 0
+            || size == 0
+            || offset + size > self.len()
+        {
+            return Err(Error::InvalidArgument);
+        }
+
+        let start = self.range.start + offset;
+
+        Ok(Self {
+            inner: self.inner.clone(),
+            range: start..(start + size),
+            perms: self.perms,
+        })
+    }
+
     /// Is the mapping a reservation only?
     pub fn is_reservation(&self) -> bool {
         self.perms == Permissions::NONE
@@ -202,15 +486,14 @@ impl<'a> Mapping<'a> {
         self.perms
     }
 
-    /// Update the permissions of the mapping
-    ///
-    /// Note: since the underlying memory object is not changed, Permissions cannot be switch with NONE
+    /// Update the permissions of this view
     ///
-    /// # Safety
-    ///
-    /// Changing permissions on currently used mapping can result in page faults
-    pub unsafe fn update_permissions(&mut self, perms: Permissions) -> Result<(), Error> {
-        process::mprotect(&self.process.handle, &self.range, perms)?;
+    /// Notes:
+    /// - since the underlying memory object is not changed, Permissions cannot be switch with NONE
+    /// - other views on the same range are not updated
+    /// - buffers got from `as_buffer`/`as_buffer_mut` must not be used anymore if they lost their access
+    pub fn update_permissions(&mut self, perms: Permissions) -> Result<(), Error> {
+        self.inner.process.protect(&self.range, perms)?;
         self.perms = perms;
         Ok(())
     }
@@ -229,7 +512,7 @@ impl<'a> Mapping<'a> {
     ///
     /// # Safety
     ///
-    /// The slice remains valid as long as the mapping is not updated (eg: permissions)
+    /// The slice remains valid as long as the mapping is not updated (eg: permissions) and not dropped
     pub unsafe fn as_buffer(&self) -> Option<&'a [u8]> {
         if self.perms.contains(Permissions::READ) {
             Some(slice::from_raw_parts(
@@ -245,7 +528,7 @@ impl<'a> Mapping<'a> {
     ///
     /// # Safety
     ///
-    /// The slice remains valid as long as the mapping is not updated (eg: permissions)
+    /// The slice remains valid as long as the mapping is not updated (eg: permissions) and not dropped
     pub unsafe fn as_buffer_mut(&self) -> Option<&'a mut [u8]> {
         if self.perms.contains(Permissions::WRITE) {
             Some(slice::from_raw_parts_mut(
@@ -262,19 +545,8 @@ impl<'a> Mapping<'a> {
         self.range.len()
     }
 
-    /// Leak the mapping, consuming the object. The mapping is not freed.
-    pub fn leak(mut self) {
-        self.range = 0..0;
-    }
-}
-
-impl Drop for Mapping<'_> {
-    fn drop(&mut self) {
-        // Check not leaked
-        if self.range.len() > 0 {
-            self.process
-                .unmap(&self.range)
-                .expect("Could not free maping");
-        }
+    /// Label the mapping for memory investigations (eg: "heap", "stack:12")
+    pub fn set_name(&self, name: &str) -> Result<(), Error> {
+        self.inner.process.name_mem(&self.range, Some(name))
     }
 }
@@ -0,0 +1,58 @@
+use alloc::{boxed::Box, vec::Vec};
+use libsyscalls::stats;
+
+use super::*;
+
+/// Kernel statistics
+pub struct Stats {
+    _priv: (),
+}
+
+impl Stats {
+    /// Get an aggregate snapshot of the system state (uptime, memory, processes, threads, load)
+    pub fn system_info() -> Result<SystemInfo, Error> {
+        stats::system_info()
+    }
+
+    /// Get the idle policy (hlt or mwait) and the idle residency counters
+    pub fn idle() -> Result<IdleStats, Error> {
+        stats::idle_stats()
+    }
+
+    /// Get the time spent by threads in the ready list before being scheduled, by priority (highest first, idle excluded)
+    ///
+    /// Compared to syscall latencies, this tells scheduling delay apart from processing time.
+    pub fn ready_latencies() -> Result<Box<[ReadyLatency]>, Error> {
+        let mut buffer = Vec::new();
+        // One histogram per priority, idle excluded
+        buffer.resize(6, ReadyLatency::default());
+
+        let (list, _) = stats::ready_latencies(&mut buffer)?;
+        let len = list.len();
+        buffer.truncate(len);
+
+        Ok(buffer.into_boxed_slice())
+    }
+
+    /// Get latency histograms of syscalls which have been called at least once
+    pub fn syscall_latencies() -> Result<Box<[SyscallLatency]>, Error> {
+        let mut size = 32;
+
+        // New syscalls may be called between the calls, so retry until the buffer is large enough
+        loop {
+            let mut buffer = Vec::with_capacity(size);
+            buffer.resize(size, SyscallLatency::default());
+
+            let (_, new_size) = stats::syscall_latencies(&mut buffer)?;
+
+            if new_size > size {
+                size = new_size;
+                continue;
+            }
+
+            buffer.truncate(new_size);
+
+            return Ok(buffer.into_boxed_slice());
+        }
+    }
+}
@@ -0,0 +1,71 @@
+use alloc::{collections::BTreeMap, sync::Arc, vec::Vec};
+use libsyscalls::strings;
+use spin::Mutex;
+
+use super::*;
+
+/// Strings resolved from the kernel table, by id
+///
+/// An id always refers to the same string, so entries never need to be invalidated.
+static CACHE: Mutex<BTreeMap<u64, Arc<str>>> = Mutex::new(BTreeMap::new());
+
+/// Kernel strings table: process, thread and port names
+///
+/// Info structs (`ProcessInfo`, `ThreadInfo`, `PortInfo`) carry the id of the name.
+/// A rename gives a new id, so that a cached name is never stale.
+pub struct Strings {
+    _priv: (),
+}
+
+impl Strings {
+    /// Get the string of an id, from the cache or else from the kernel
+    ///
+    /// Returns `None` if the id is 0 (no name) or not in use anymore and not in cache.
+    pub fn get(id: u64) -> Option<Arc<str>> {
+        if id == 0 {
+            return None;
+        }
+
+        Self::resolve(&[id]).ok()?;
+        CACHE.lock().get(&id).cloned()
+    }
+
+    /// Resolve the given ids which are not in cache yet, with one syscall
+    pub fn resolve(ids: &[u64]) -> Result<(), Error> {
+        let mut missing: Vec<u64> = {
+            let cache = CACHE.lock();
+            ids.iter()
+                .copied()
+                .filter(|&id| id != 0 && !cache.contains_key(&id))
+                .collect()
+        };
+
+        if missing.is_empty() {
+            return Ok(());
+        }
+
+        missing.sort_unstable();
+        missing.dedup();
+
+        let entries = Self::lookup(&missing)?;
+
+        let mut cache = CACHE.lock();
+        for entry in entries.iter() {
+            if let Some(name) = entry.name() {
+                cache.insert(entry.id, Arc::from(name));
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Look up ids in the kernel table, without cache
+    pub fn lookup(ids: &[u64]) -> Result<Vec<NameEntry>, Error> {
+        let mut entries = Vec::with_capacity(ids.len());
+        entries.resize_with(ids.len(), || NameEntry::not_found(0));
+
+        strings::lookup(ids, &mut entries)?;
+
+        Ok(entries)
+    }
+}
@@ -1,25 +1,31 @@
-use core::{hint::unreachable_unchecked, mem, ops::Range};
+use core::{hint::unreachable_unchecked, mem, ops::Range, time::Duration};
 
 use alloc::{boxed::Box, collections::BTreeMap, format, string::String, sync::Arc, vec::Vec};
 use lazy_static::lazy_static;
-use libsyscalls::{thread, Handle};
+use libsyscalls::thread;
 use log::debug;
 use spin::Mutex;
 
 use super::{tls::TLS_SIZE, *};
 
+/// Default maximum size of a thread stack
 const STACK_SIZE: usize = PAGE_SIZE * 20;
 
+/// Part of the stack committed at thread creation, the rest is committed as the stack grows
+pub(super) const STACK_INITIAL_SIZE: usize = PAGE_SIZE * 4;
+
 /// Thread
 #[derive(Debug)]
 pub struct Thread {
     cached_tid: Mutex<Option<u64>>,
     cached_pid: Mutex<Option<u64>>,
-    handle: Handle,
+    handle: ThreadHandle,
 }
 
 impl KObject for Thread {
-    unsafe fn handle(&self) -> &Handle {
+    type Handle = ThreadHandle;
+
+    unsafe fn handle(&self) -> &Self::Handle {
         &self.handle
     }
 }
@@ -57,7 +63,9 @@ impl<'a> ThreadOptions<'a> {
         self
     }
 
-    /// Set the size of stack for the future thread
+    /// Set the maximum size of stack for the future thread
+    ///
+    /// Memory is committed as the stack grows.
     pub fn stack_size(&mut self, value: usize) -> &mut Self {
         self.stack_size = value;
         self
@@ -87,12 +95,19 @@ impl Thread {
         entry: Entry,
         options: ThreadOptions,
     ) -> Result<Self, Error> {
-        let stack = AllocWithGuards::new(options.stack_size)?;
+        // Privileged threads cannot fault to grow their stack: commit it all
+        let stack_initial_size = if options.privileged {
+            options.stack_size
+        } else {
+            STACK_INITIAL_SIZE.min(options.stack_size)
+        };
+
+        let stack = Process::current().map_stack(options.stack_size, stack_initial_size)?;
         let tls = AllocWithGuards::new(TLS_SIZE)?;
         let mut parameter = Box::new(ThreadParameter::new(entry));
 
         let arg = parameter.as_mut() as *mut _ as usize;
-        let stack_top_addr = stack.address() + options.stack_size;
+        let stack_top_addr = stack.range().end;
         let tls_addr = tls.address();
 
         let handle = thread::create(
@@ -106,7 +121,7 @@ impl Thread {
             tls_addr,
         )?;
 
-        let stack_reservation = stack.reservation().clone();
+        let stack_reservation = stack.range().clone();
         let tls_reservation = tls.reservation().clone();
 
         // Thread has been created properly, we can leak the allocation
@@ -120,15 +135,33 @@ impl Thread {
             handle,
         };
 
-        THREAD_GC.add_thread(ThreadGCData::new(
-            obj.tid(),
-            stack_reservation,
-            tls_reservation,
-        ));
+        let tid = obj.tid();
+
+        // Labels are only informative: the thread is usable even if they cannot be set
+        let _ = Self::set_stack_name(
+            &stack_reservation,
+            stack_initial_size,
+            &format!("stack:{}", tid),
+        );
+        let _ = AllocWithGuards::set_name(&tls_reservation, &format!("tls:{}", tid));
+
+        THREAD_GC.add_thread(ThreadGCData::new(tid, stack_reservation, tls_reservation));
 
         Ok(obj)
     }
 
+    /// Label the stack from its region: the growable part and the committed part (guard is not labelled)
+    fn set_stack_name(region: &Range<usize>, initial_size: usize, name: &str) -> Result<(), Error> {
+        let committed_start = region.end - initial_size;
+        let growable = (region.start + PAGE_SIZE)..committed_start;
+
+        if !growable.is_empty() {
+            Process::current().name_mem(&growable, Some(name))?;
+        }
+
+        Process::current().name_mem(&(committed_start..region.end), Some(name))
+    }
+
     extern "C" fn thread_entry(arg: usize) -> ! {
         {
             let parameter = unsafe { Box::from_raw(arg as *mut ThreadParameter) };
@@ -151,6 +184,49 @@ impl Thread {
         })
     }
 
+    /// Get the ids of the current thread and of its process
+    ///
+    /// Cheaper than `open_self`: no handle is opened.
+    pub fn current_ids() -> CurrentIds {
+        thread::get_ids().expect("Could not get current ids")
+    }
+
+    /// Get the id of the current thread
+    pub fn current_tid() -> u64 {
+        Self::current_ids().tid
+    }
+
+    /// Open the current thread
+    pub fn open_self() -> Result<Self, Error> {
+        let handle = thread::open_self()?;
+
+        Ok(Self {
+            cached_tid: Mutex::new(None),
+            cached_pid: Mutex::new(None),
+            handle,
+        })
+    }
+
+    /// Build a thread from a handle returned by the kernel
+    pub(super) fn from_thread_handle(handle: ThreadHandle) -> Self {
+        Self {
+            cached_tid: Mutex::new(None),
+            cached_pid: Mutex::new(None),
+            handle,
+        }
+    }
+
+    /// Build a thread from a handle received in a message
+    ///
+    /// On type mismatch, the handle is given back.
+    pub fn from_handle(handle: Handle) -> Result<Self, Handle> {
+        Ok(Self {
+            cached_tid: Mutex::new(None),
+            cached_pid: Mutex::new(None),
+            handle: ThreadHandle::from_handle(handle)?,
+        })
+    }
+
     /// Get the thread id
     pub fn tid(&self) -> u64 {
         if let Some(value) = *self.cached_tid.lock() {
@@ -178,6 +254,14 @@ impl Thread {
         thread::set_priority(&self.handle, priority)
     }
 
+    /// Get a snapshot of the context of another thread of the current process, or of a suspended thread
+    ///
+    /// Note: the context is the one saved the last time the thread entered the kernel (syscall, interrupt).
+    /// If the thread runs again after that, the snapshot is outdated.
+    pub fn context(&self) -> Result<ThreadContext, Error> {
+        thread::context(&self.handle)
+    }
+
     /// Get thread info
     pub fn info(&self) -> ThreadInfo {
         let info = thread::info(&self.handle).expect("Could not get thread info");
@@ -202,27 +286,63 @@ impl Thread {
     }
 
     /// List the thread ids in the system
+    ///
+    /// Note: the list is fetched by pages, so it is not atomic.
     pub fn list() -> Result<Box<[u64]>, Error> {
-        let mut size = 1024;
+        list_by_pages(|start, buffer| {
+            thread::list(start, buffer).map(|(page, next)| (page.len(), next))
+        })
+    }
 
-        // Event not atomic, let's consider that with doubling the required size between call,
-        // at some point we will be able to fetch list entirely
-        loop {
-            let mut buffer = Vec::with_capacity(size);
-            buffer.resize(size, 0);
+    /// Get the names of several threades in one call
+    pub fn names(tids: &[u64]) -> Result<Vec<NameEntry>, Error> {
+        let mut entries = Vec::with_capacity(tids.len());
+        entries.resize_with(tids.len(), || NameEntry::not_found(0));
 
-            let (_, new_size) = thread::list(&mut buffer)?;
+        thread::names(tids, &mut entries)?;
 
-            if new_size > size {
-                // Retry with 2x requested size
-                size = new_size * 2;
-                continue;
-            }
+        Ok(entries)
+    }
 
-            buffer.resize(new_size, 0);
+    /// Start a new request on the current thread
+    ///
+    /// The IPC messages sent by the thread are then stamped with a new correlation id, which is propagated by the servers
+    /// to the messages they send while handling them, and shown in log records.
+    pub fn new_correlation() -> Result<u64, Error> {
+        thread::new_correlation()
+    }
 
-            return Ok(buffer.into_boxed_slice());
-        }
+    /// Give a time budget to the request of the current thread
+    ///
+    /// The IPC messages sent by the thread are stamped with the resulting deadline, which is propagated by the servers
+    /// like the correlation id. `None` removes the deadline.
+    pub fn set_deadline(timeout: Option<Duration>) -> Result<(), Error> {
+        let timeout = timeout.map_or(0, |timeout| (timeout.as_nanos() as u64).max(1));
+        thread::set_deadline(timeout)
+    }
+
+    /// Check if the request of the current thread can still meet its deadline
+    ///
+    /// Servers call it after receiving a request: it fails with `Error::DeadlineExceeded` if the caller would not wait for the result anymore,
+    /// so that the request can be answered at once instead of doing useless work.
+    pub fn check_deadline() -> Result<(), Error> {
+        thread::check_deadline()
+    }
+
+    /// Enable or disable the scheduler tracing, for all threads
+    pub fn sched_trace_enable(enabled: bool) -> Result<(), Error> {
+        thread::sched_trace_enable(enabled)
+    }
+
+    /// Get the pending scheduler trace events, oldest first
+    pub fn sched_trace_read() -> Result<Vec<SchedEvent>, Error> {
+        let mut buffer = Vec::with_capacity(SchedEvent::BUFFER_SIZE);
+        buffer.resize(SchedEvent::BUFFER_SIZE, SchedEvent::default());
+
+        let count = thread::sched_trace_read(&mut buffer)?.len();
+        buffer.truncate(count);
+
+        Ok(buffer)
     }
 
     /// Set the name of the thread
@@ -263,7 +383,7 @@ impl Thread {
     }
 }
 
-struct AllocWithGuards<'a> {
+pub(super) struct AllocWithGuards<'a> {
     reservation: Mapping<'a>,
 }
 
@@ -276,9 +396,8 @@ impl AllocWithGuards<'_> {
 
         let mobj = MemoryObject::create(size)?;
 
-        let mapping = self_proc.map_mem(
-            Some(addr),
-            size,
+        let mapping = self_proc.map_commit(
+            &(addr..addr + size),
             Permissions::READ | Permissions::WRITE,
             &mobj,
             0,
@@ -305,6 +424,12 @@ impl AllocWithGuards<'_> {
     pub fn leak(self) {
         self.reservation.leak()
     }
+
+    /// Label a leaked allocation from its reservation (guards are not labelled)
+    pub fn set_name(reservation: &Range<usize>, name: &str) -> Result<(), Error> {
+        let range = (reservation.start + PAGE_SIZE)..(reservation.end - PAGE_SIZE);
+        Process::current().name_mem(&range, Some(name))
+    }
 }
 
 /// Supervisor for a thread
@@ -343,6 +468,24 @@ impl<'a> ThreadSupervisor<'a> {
     pub fn update_context(&self, regs: &[(ThreadContextRegister, usize)]) -> Result<(), Error> {
         thread::update_context(unsafe { &self.target.handle() }, regs)
     }
+
+    /// Set an hardware watchpoint on the thread
+    ///
+    /// `size` must be 1, 2, 4 or 8, and `address` must be aligned on it (1 for `WatchpointKind::Execute`).
+    pub fn set_watchpoint(
+        &self,
+        index: usize,
+        address: usize,
+        size: usize,
+        kind: WatchpointKind,
+    ) -> Result<(), Error> {
+        thread::set_watchpoint(unsafe { self.target.handle() }, index, address, size, kind)
+    }
+
+    /// Clear an hardware watchpoint of the thread
+    pub fn clear_watchpoint(&self, index: usize) -> Result<(), Error> {
+        thread::clear_watchpoint(unsafe { self.target.handle() }, index)
+    }
 }
 
 struct ThreadParameter {
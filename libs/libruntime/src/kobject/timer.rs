@@ -0,0 +1,78 @@
+use core::time::Duration;
+
+use libsyscalls::timer;
+
+use super::*;
+
+/// Timer
+///
+/// When it fires, a `TimerEvent` is received.
+/// The slack given when arming it lets the kernel fire it along with nearby timers,
+/// so that periodic timers (eg: heartbeats) cost fewer wakeups.
+///
+/// Note: since timer is a complex object, it does not implement KObject directly
+#[derive(Debug)]
+pub struct Timer {
+    handle: TimerHandle,
+    reader: PortReceiver,
+}
+
+impl KWaitable for Timer {
+    unsafe fn waitable_handle(&self) -> &PortReceiverHandle {
+        self.reader.waitable_handle()
+    }
+
+    fn wait(&self) -> Result<(), Error> {
+        self.reader.wait()
+    }
+}
+
+impl Timer {
+    /// Create a new timer, not armed
+    pub fn create() -> Result<Self, Error> {
+        let (reader, sender) = Port::create(None)?;
+        let handle = timer::create(unsafe { sender.handle() })?;
+
+        Ok(Self { handle, reader })
+    }
+
+    /// Arm the timer to fire after `delay`, or up to `slack` later
+    ///
+    /// If the timer is already armed, its deadline is replaced.
+    /// Note: timers fire on kernel ticks (see `TICK_NS`), a slack smaller than a tick is ignored.
+    pub fn arm(&self, delay: Duration, slack: Duration) -> Result<(), Error> {
+        timer::arm(
+            &self.handle,
+            delay.as_nanos() as u64,
+            slack.as_nanos() as u64,
+        )
+    }
+
+    /// Cancel the timer, if it is armed
+    ///
+    /// Note: an event already received is not discarded
+    pub fn cancel(&self) -> Result<(), Error> {
+        timer::cancel(&self.handle)
+    }
+
+    /// Receive a timer event
+    ///
+    /// Note: the call does not block, it returns ObjectNotReady if no message is waiting
+    pub fn receive(&self) -> Result<TimerEvent, Error> {
+        let msg = self.reader.receive()?;
+
+        Ok(unsafe { *msg.data::<TimerEvent>() })
+    }
+
+    /// Block until a timer event is received
+    pub fn blocking_receive(&self) -> Result<TimerEvent, Error> {
+        let msg = self.reader.blocking_receive()?;
+
+        Ok(unsafe { *msg.data::<TimerEvent>() })
+    }
+
+    /// Get timers statistics (wakeups, coalesced timers)
+    pub fn stats() -> Result<TimerStats, Error> {
+        timer::stats()
+    }
+}
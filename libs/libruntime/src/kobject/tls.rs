@@ -37,17 +37,18 @@ impl TlsAllocator {
     ///
     /// If the return value is `None`, there is no more slot available
     pub fn allocate() -> Option<TlsSlot> {
-        let mut data = Self::data();
+        let mut guard = Self::data();
+        let data = &mut *guard;
 
         for (index, allocated) in data.allocation_map.iter_mut().enumerate() {
             if !*allocated {
                 *allocated = true;
-            }
 
-            data.id_gen += 1;
-            let seq = data.id_gen;
+                data.id_gen += 1;
+                let seq = data.id_gen;
 
-            return Some(TlsSlot { index, seq });
+                return Some(TlsSlot { index, seq });
+            }
         }
 
         None
@@ -0,0 +1,73 @@
+use libsyscalls::trampoline;
+
+use super::{
+    thread::{AllocWithGuards, STACK_INITIAL_SIZE},
+    tls::TLS_SIZE,
+    *,
+};
+
+/// Entry point of the current process, authorized for threads created by other processes
+///
+/// The current process creates it and sends it to a server, which can then run threads in the current process
+/// (eg: to complete a blocking call) without being able to start code anywhere else in it.
+/// Everything the threads start with is fixed here: the server only chooses the argument given to `entry`.
+///
+/// The stack and the TLS are allocated once and reused: only one thread created from the trampoline can live at a time.
+/// `entry` must exit the thread when it is done.
+#[derive(Debug)]
+pub struct Trampoline {
+    handle: TrampolineHandle,
+}
+
+impl KObject for Trampoline {
+    type Handle = TrampolineHandle;
+
+    unsafe fn handle(&self) -> &Self::Handle {
+        &self.handle
+    }
+}
+
+impl Trampoline {
+    /// Create a trampoline on `entry`, for threads of the given priority, with stacks of up to `stack_size` bytes
+    pub fn create(
+        entry: extern "C" fn(usize) -> !,
+        priority: ThreadPriority,
+        stack_size: usize,
+    ) -> Result<Self, Error> {
+        let stack = Process::current().map_stack(stack_size, STACK_INITIAL_SIZE.min(stack_size))?;
+        let tls = AllocWithGuards::new(TLS_SIZE)?;
+
+        let handle = trampoline::create(priority, entry, stack.range().end, tls.address())?;
+
+        // Used by all the threads created from the trampoline, for the life of the process
+        let _ = Process::current().name_mem(stack.range(), Some("trampoline:stack"));
+        let _ = AllocWithGuards::set_name(tls.reservation(), "trampoline:tls");
+        stack.leak();
+        tls.leak();
+
+        Ok(Self { handle })
+    }
+
+    /// Create a thread in the process which created the trampoline, running its entry with `arg`
+    ///
+    /// Fails with `Error::ObjectNotReady` if the previous thread created from the trampoline is still alive.
+    pub fn spawn(&self, name: Option<&str>, arg: usize) -> Result<Thread, Error> {
+        let handle = trampoline::spawn(&self.handle, name, arg)?;
+
+        Ok(Thread::from_thread_handle(handle))
+    }
+
+    /// Get the handle, to send it in a message
+    pub fn into_handle(self) -> Handle {
+        self.handle.into_handle()
+    }
+
+    /// Build a trampoline from a handle received in a message
+    ///
+    /// On type mismatch, the handle is given back.
+    pub fn from_handle(handle: Handle) -> Result<Self, Handle> {
+        Ok(Self {
+            handle: TrampolineHandle::from_handle(handle)?,
+        })
+    }
+}
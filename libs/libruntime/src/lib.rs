@@ -12,10 +12,33 @@ use log::debug;
 extern crate alloc;
 
 mod allocator;
+pub mod blockdev;
+pub mod boot_profile;
+pub mod checkpoint_server;
+pub mod clipboard;
 pub mod debug;
+pub mod display;
+mod entry;
+pub mod error;
+pub mod event_bus;
+pub mod failure;
+pub mod format;
+pub mod fs;
+pub mod idempotency;
+pub mod introspection;
 pub mod kobject;
+pub mod loader;
+pub mod log_server;
 mod logging;
+pub mod manifest;
+pub mod process_server;
+pub mod retry;
+pub mod server;
+pub mod service;
 pub mod sync;
+pub mod vfs;
+
+pub use libkvblock as kvblock;
 
 pub fn init() {
     logging::init();
@@ -26,6 +49,7 @@ pub fn init() {
 
 pub fn terminate() {
     kobject::terminate();
+    logging::flush();
 }
 
 pub fn exit() -> ! {
@@ -0,0 +1,499 @@
+//! Loader: start a binary in a new process
+//!
+//! Binaries are position independent executables, dynamically linked against shared libraries (eg: `libruntime.so`).
+//! Each object is mapped at a free address of the new process: its segments are filled and relocated by the spawner,
+//! through local mappings of their memory objects, then committed in the new process with their permissions.
+//!
+//! Segment mappings are named after their object (eg: `libruntime.so`): the object base is the start of its first mapping.
+//!
+//! Symbols are resolved in load order, the binary first then its libraries, and all at load time (no lazy binding).
+//! Only the relocations emitted for our target are supported:
+//! `R_X86_64_RELATIVE`, `R_X86_64_64`, `R_X86_64_GLOB_DAT` and `R_X86_64_JUMP_SLOT`.
+//!
+//! Loading is done in two steps, so that the spawner can configure the process before it runs:
+//!
+//! ```ignore
+//! let image = Image::load("hello", binary, &[Library::new("libruntime.so", libruntime)])?;
+//! // eg: set object limits of image.process()
+//! let process = image.start()?;
+//! ```
+
+use core::{fmt, mem, ops::Range, ptr};
+
+use alloc::{
+    collections::BTreeMap,
+    string::{String, ToString},
+    vec::Vec,
+};
+use libsyscalls::thread;
+use log::debug;
+use xmas_elf::{
+    dynamic, header, program,
+    sections::{SectionData, ShType},
+    symbol_table::{Binding, DynEntry64, Entry, Type},
+    ElfFile,
+};
+
+use crate::kobject::{
+    Error, KObject, Mapping, MemoryObject, Permissions, Process, ThreadPriority, PAGE_SIZE,
+    TLS_SIZE,
+};
+
+/// Stack of the main thread of the new process
+const STACK_SIZE: usize = PAGE_SIZE * 20;
+const STACK_INITIAL_SIZE: usize = PAGE_SIZE * 4;
+
+const R_X86_64_NONE: u32 = 0;
+const R_X86_64_64: u32 = 1;
+const R_X86_64_GLOB_DAT: u32 = 6;
+const R_X86_64_JUMP_SLOT: u32 = 7;
+const R_X86_64_RELATIVE: u32 = 8;
+
+/// Shared library, given by the spawner to resolve the dependencies of the binary
+#[derive(Debug, Clone, Copy)]
+pub struct Library<'a> {
+    name: &'a str,
+    binary: &'a [u8],
+}
+
+impl<'a> Library<'a> {
+    /// Declare a library, with the name other objects use to depend on it (eg: `libruntime.so`)
+    pub const fn new(name: &'a str, binary: &'a [u8]) -> Self {
+        Self { name, binary }
+    }
+
+    pub const fn name(&self) -> &'a str {
+        self.name
+    }
+
+    pub const fn binary(&self) -> &'a [u8] {
+        self.binary
+    }
+}
+
+/// Binary loaded in a new process, not started yet
+#[derive(Debug)]
+pub struct Image {
+    process: Process,
+    entry_point: usize,
+}
+
+impl Image {
+    /// Create a new process, and load the binary and its dependencies in it
+    pub fn load(name: &str, binary: &[u8], libraries: &[Library]) -> Result<Self, LoaderError> {
+        let process = Process::create(name)?;
+
+        let mut objects = Vec::new();
+        objects.push(Object::load(&process, name, binary)?);
+
+        // Breadth first: dependencies of dependencies come after
+        let mut index = 0;
+        while index < objects.len() {
+            for needed in objects[index].needed()? {
+                if objects.iter().any(|object| object.name == needed) {
+                    continue;
+                }
+
+                let library = libraries
+                    .iter()
+                    .find(|library| library.name == needed)
+                    .ok_or_else(|| LoaderError::MissingLibrary(needed.to_string()))?;
+
+                objects.push(Object::load(&process, library.name, library.binary)?);
+            }
+
+            index += 1;
+        }
+
+        for object in objects.iter() {
+            object.relocate(&objects)?;
+        }
+
+        let entry_point = objects[0].base + objects[0].elf_file.header.pt2.entry_point() as usize;
+
+        for object in objects {
+            object.commit(&process)?;
+        }
+
+        Ok(Self {
+            process,
+            entry_point,
+        })
+    }
+
+    /// Get the new process
+    pub fn process(&self) -> &Process {
+        &self.process
+    }
+
+    /// Start the main thread of the process, at the entry point of the binary
+    pub fn start(self) -> Result<Process, LoaderError> {
+        let stack = self.process.map_stack(STACK_SIZE, STACK_INITIAL_SIZE)?;
+
+        let tls_mobj = MemoryObject::create(TLS_SIZE)?;
+        let tls = self.process.map_mem(
+            None,
+            TLS_SIZE,
+            Permissions::READ | Permissions::WRITE,
+            &tls_mobj,
+            0,
+        )?;
+
+        let entry_point =
+            unsafe { mem::transmute::<usize, extern "C" fn(usize) -> !>(self.entry_point) };
+
+        thread::create(
+            Some("main"),
+            unsafe { self.process.handle() },
+            false,
+            ThreadPriority::Normal,
+            entry_point,
+            stack.range().end,
+            0,
+            tls.address(),
+        )?;
+
+        // Owned by the new process from now on
+        stack.into_raw();
+        tls.into_raw();
+
+        Ok(self.process)
+    }
+}
+
+/// Object (binary or library) being loaded
+struct Object<'a> {
+    name: &'a str,
+    elf_file: ElfFile<'a>,
+    /// Load address in the new process
+    base: usize,
+    segments: Vec<Segment>,
+    /// Defined symbols, with their address in the new process
+    exports: BTreeMap<&'a str, usize>,
+}
+
+/// Loadable segment, filled through a local mapping
+struct Segment {
+    /// Page aligned, relative to the object base
+    range: Range<usize>,
+    perms: Permissions,
+    mobj: MemoryObject,
+    local: Mapping<'static>,
+}
+
+impl<'a> Object<'a> {
+    fn load(process: &Process, name: &'a str, binary: &'a [u8]) -> Result<Self, LoaderError> {
+        let elf_file = wrap_res(ElfFile::new(binary))?;
+
+        for program_header in elf_file.program_iter() {
+            wrap_res(program::sanity_check(program_header, &elf_file))?;
+        }
+
+        // Position independent executables are shared objects
+        match elf_file.header.pt2.type_().as_type() {
+            header::Type::SharedObject => (),
+            header::Type::Executable => Err(LoaderError::BadObjectType("executable"))?,
+            _ => Err(LoaderError::BadObjectType("not loadable"))?,
+        };
+
+        let mut segments: Vec<Segment> = Vec::new();
+
+        for program_header in elf_file.program_iter() {
+            if wrap_res(program_header.get_type())? != program::Type::Load {
+                continue;
+            }
+
+            let vaddr = program_header.virtual_addr() as usize;
+            let range = align_down(vaddr)..align_up(vaddr + program_header.mem_size() as usize);
+
+            if segments
+                .iter()
+                .any(|segment| segment.range.start < range.end && range.start < segment.range.end)
+            {
+                return Err(LoaderError::BadSegments);
+            }
+
+            let flags = program_header.flags();
+            let mut perms = Permissions::NONE;
+            if flags.is_read() {
+                perms |= Permissions::READ;
+            }
+            if flags.is_write() {
+                perms |= Permissions::WRITE;
+            }
+            if flags.is_execute() {
+                perms |= Permissions::EXECUTE;
+            }
+
+            let mobj = MemoryObject::create(range.len())?;
+            let local = Process::current().map_mem(
+                None,
+                range.len(),
+                Permissions::READ | Permissions::WRITE,
+                &mobj,
+                0,
+            )?;
+
+            let offset = program_header.offset() as usize;
+            let file_data = &binary[offset..offset + program_header.file_size() as usize];
+            let start = vaddr - range.start;
+            let buffer = unsafe { local.as_buffer_mut() }.expect("Could not get segment data");
+            buffer[start..start + file_data.len()].copy_from_slice(file_data);
+
+            segments.push(Segment {
+                range,
+                perms,
+                mobj,
+                local,
+            });
+        }
+
+        let span = segments
+            .iter()
+            .map(|segment| segment.range.end)
+            .max()
+            .ok_or(LoaderError::BadSegments)?;
+
+        // Only pick the address: segments are committed once relocated
+        let (reservation, _) = process.map_reserve(None, span)?.into_raw();
+
+        let mut object = Self {
+            name,
+            elf_file,
+            base: reservation.start,
+            segments,
+            exports: BTreeMap::new(),
+        };
+
+        object.build_exports()?;
+
+        debug!(
+            "Loaded '{}' at {:#x} ({} segments, {} exports)",
+            object.name,
+            object.base,
+            object.segments.len(),
+            object.exports.len()
+        );
+
+        Ok(object)
+    }
+
+    /// Get the names of the libraries this object depends on
+    fn needed(&self) -> Result<Vec<&'a str>, LoaderError> {
+        let Some(section) = self.elf_file.find_section_by_name(".dynamic") else {
+            return Ok(Vec::new());
+        };
+
+        let SectionData::Dynamic64(entries) = wrap_res(section.get_data(&self.elf_file))? else {
+            return Err(LoaderError::BadDynamicSection);
+        };
+
+        let mut needed = Vec::new();
+        for entry in entries {
+            match wrap_res(entry.get_tag())? {
+                dynamic::Tag::Null => break,
+                dynamic::Tag::Needed => {
+                    let index = wrap_res(entry.get_val())? as u32;
+                    needed.push(wrap_res(self.elf_file.get_dyn_string(index))?);
+                }
+                _ => {}
+            }
+        }
+
+        Ok(needed)
+    }
+
+    fn dynamic_symbols(&self) -> Result<&'a [DynEntry64], LoaderError> {
+        let Some(section) = self.elf_file.find_section_by_name(".dynsym") else {
+            return Ok(&[]);
+        };
+
+        match wrap_res(section.get_data(&self.elf_file))? {
+            SectionData::DynSymbolTable64(symbols) => Ok(symbols),
+            _ => Err(LoaderError::BadDynamicSection),
+        }
+    }
+
+    fn build_exports(&mut self) -> Result<(), LoaderError> {
+        for symbol in self.dynamic_symbols()? {
+            // Undefined
+            if symbol.shndx() == 0 {
+                continue;
+            }
+
+            if !matches!(
+                wrap_res(symbol.get_binding())?,
+                Binding::Global | Binding::Weak
+            ) || wrap_res(symbol.get_type())? == Type::Tls
+            {
+                continue;
+            }
+
+            let name = wrap_res(symbol.get_name(&self.elf_file))?;
+            self.exports
+                .insert(name, self.base + symbol.value() as usize);
+        }
+
+        Ok(())
+    }
+
+    /// Apply the relocations of the object, resolving symbols in `objects`
+    fn relocate(&self, objects: &[Object]) -> Result<(), LoaderError> {
+        let symbols = self.dynamic_symbols()?;
+
+        for section in self.elf_file.section_iter() {
+            if wrap_res(section.get_type())? != ShType::Rela {
+                continue;
+            }
+
+            let SectionData::Rela64(relocations) = wrap_res(section.get_data(&self.elf_file))?
+            else {
+                return Err(LoaderError::BadDynamicSection);
+            };
+
+            for relocation in relocations {
+                let addend = relocation.get_addend() as usize;
+
+                let value = match relocation.get_type() {
+                    R_X86_64_NONE => continue,
+                    R_X86_64_RELATIVE => self.base.wrapping_add(addend),
+                    R_X86_64_64 => self
+                        .resolve(objects, symbols, relocation.get_symbol_table_index())?
+                        .wrapping_add(addend),
+                    R_X86_64_GLOB_DAT | R_X86_64_JUMP_SLOT => {
+                        self.resolve(objects, symbols, relocation.get_symbol_table_index())?
+                    }
+                    other => return Err(LoaderError::UnsupportedRelocation(other)),
+                };
+
+                self.write(relocation.get_offset() as usize, value as u64)?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Get the address of a symbol: the first object which defines it wins
+    fn resolve(
+        &self,
+        objects: &[Object],
+        symbols: &[DynEntry64],
+        index: u32,
+    ) -> Result<usize, LoaderError> {
+        let symbol = symbols
+            .get(index as usize)
+            .ok_or(LoaderError::BadDynamicSection)?;
+        let name = wrap_res(symbol.get_name(&self.elf_file))?;
+
+        if let Some(address) = objects
+            .iter()
+            .find_map(|object| object.exports.get(name).copied())
+        {
+            return Ok(address);
+        }
+
+        // Unresolved weak symbols are null
+        if wrap_res(symbol.get_binding())? == Binding::Weak {
+            return Ok(0);
+        }
+
+        Err(LoaderError::MissingSymbol(name.to_string()))
+    }
+
+    /// Write a relocated value, at `offset` from the object base
+    fn write(&self, offset: usize, value: u64) -> Result<(), LoaderError> {
+        let end = offset + mem::size_of::<u64>();
+        let segment = self
+            .segments
+            .iter()
+            .find(|segment| segment.range.start <= offset && end <= segment.range.end)
+            .ok_or(LoaderError::BadRelocation)?;
+
+        let address = segment.local.address() + (offset - segment.range.start);
+        unsafe { ptr::write_unaligned(address as *mut u64, value) };
+
+        Ok(())
+    }
+
+    /// Map the segments in the new process, with their permissions
+    fn commit(self, process: &Process) -> Result<(), LoaderError> {
+        for segment in self.segments.iter() {
+            let range = (self.base + segment.range.start)..(self.base + segment.range.end);
+
+            // Owned by the new process from now on
+            process
+                .map_commit(&range, segment.perms, &segment.mobj, 0)?
+                .into_raw();
+
+            // Named after the object: debuggers and crash dumps find its load address from them
+            process.name_mem(&range, Some(self.name))?;
+        }
+
+        // Read-only after relocation (only whole pages, the end of the last one may be shared with data)
+        for program_header in self.elf_file.program_iter() {
+            if wrap_res(program_header.get_type())? != program::Type::GnuRelro {
+                continue;
+            }
+
+            let vaddr = program_header.virtual_addr() as usize;
+            let range = (self.base + align_up(vaddr))
+                ..(self.base + align_down(vaddr + program_header.mem_size() as usize));
+
+            if !range.is_empty() {
+                process.protect(&range, Permissions::READ)?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+fn align_down(value: usize) -> usize {
+    value / PAGE_SIZE * PAGE_SIZE
+}
+
+fn align_up(value: usize) -> usize {
+    value.div_ceil(PAGE_SIZE) * PAGE_SIZE
+}
+
+fn wrap_res<T>(res: Result<T, &'static str>) -> Result<T, LoaderError> {
+    res.map_err(LoaderError::ElfReaderError)
+}
+
+/// Error while loading a binary
+#[derive(Debug)]
+pub enum LoaderError {
+    ElfReaderError(&'static str),
+    BadObjectType(&'static str),
+    BadSegments,
+    BadDynamicSection,
+    BadRelocation,
+    UnsupportedRelocation(u32),
+    MissingLibrary(String),
+    MissingSymbol(String),
+    Kernel(Error),
+}
+
+impl From<Error> for LoaderError {
+    fn from(err: Error) -> Self {
+        LoaderError::Kernel(err)
+    }
+}
+
+impl fmt::Display for LoaderError {
+    fn fmt(&self, formatter: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            LoaderError::ElfReaderError(str) => write!(formatter, "elf reader error: {}", str),
+            LoaderError::BadObjectType(typ) => write!(formatter, "bad object type: '{}'", typ),
+            LoaderError::BadSegments => write!(formatter, "bad segments"),
+            LoaderError::BadDynamicSection => write!(formatter, "bad dynamic section"),
+            LoaderError::BadRelocation => write!(formatter, "relocation outside of segments"),
+            LoaderError::UnsupportedRelocation(typ) => {
+                write!(formatter, "unsupported relocation type: {}", typ)
+            }
+            LoaderError::MissingLibrary(name) => write!(formatter, "missing library: '{}'", name),
+            LoaderError::MissingSymbol(name) => write!(formatter, "missing symbol: '{}'", name),
+            LoaderError::Kernel(err) => write!(formatter, "kernel error: {:?}", err),
+        }
+    }
+}
@@ -0,0 +1,391 @@
+//! Log server protocol and client, and the shared-memory transport of log records
+//!
+//! Processes started once the log server (`servers/log-server`) is up buffer their records in a ring shared with
+//! the server, instead of sending each of them synchronously to the kernel. The server drains the rings:
+//! - periodically (`DRAIN_PERIOD`)
+//! - on `Flush` requests: processes flush before they exit
+//! - when a process terminates: the last records of a crashed process are not lost
+//! - on `Shutdown` requests: the spawner of the server (init) sends it before powering off, the server drains
+//!   all the rings before replying
+//!
+//! Records are relayed to the kernel log with `KernelLog::relay`: they are attributed to the process which wrote them.
+//!
+//! Fallbacks to the kernel log syscall:
+//! - a server which does not answer a flush in time (`FLUSH_BACKOFF`): the process drains its ring itself
+//! - a full ring: the process drains its ring itself before writing the record
+//! - records larger than `MAX_MESSAGE_SIZE`, and crash reports (see `logging::log_no_alloc`)
+
+use core::{
+    mem,
+    sync::atomic::{AtomicU64, Ordering},
+    time::Duration,
+};
+
+use log::Level;
+
+use crate::failure;
+use crate::kobject::{
+    Error, Handle, MemoryObject, Message, Port, PortReceiver, PortSender, PAGE_SIZE,
+};
+use crate::retry::Backoff;
+
+/// Name of the port of the server
+pub const SERVER_PORT_NAME: &str = "log-server";
+
+/// Size of the memory object holding a ring, header included
+pub const RING_SIZE: usize = 16 * PAGE_SIZE;
+
+/// Maximum size of the message of a record in a ring, in bytes
+pub const MAX_MESSAGE_SIZE: usize = 1024;
+
+/// Period of the drain of the rings by the server
+pub const DRAIN_PERIOD: Duration = Duration::from_millis(20);
+
+/// Wait for the reply to `Register` and `Flush`
+pub const FLUSH_BACKOFF: Backoff =
+    Backoff::new(Duration::from_millis(1), Duration::from_millis(20))
+        .timeout(Duration::from_millis(500));
+
+/// Wait for the reply to `Shutdown`: all the rings are drained
+pub const SHUTDOWN_BACKOFF: Backoff =
+    Backoff::new(Duration::from_millis(1), Duration::from_millis(50))
+        .timeout(Duration::from_secs(2));
+
+/// Offset of the records in a ring: the header has its own cache line
+const RING_DATA_OFFSET: usize = 64;
+
+/// Size of the header of a record: tid, level, message length
+const RECORD_HEADER_SIZE: usize = 16;
+
+/// Type of the requests to the server
+#[repr(u64)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RequestType {
+    /// Register the ring of the calling process, in handle 1
+    Register = 1,
+    /// Drain the ring of the calling process
+    Flush,
+    /// Drain all the rings (reserved to the spawner of the server)
+    Shutdown,
+}
+
+impl TryFrom<u64> for RequestType {
+    type Error = Error;
+
+    fn try_from(value: u64) -> Result<Self, Self::Error> {
+        match value {
+            1 => Ok(Self::Register),
+            2 => Ok(Self::Flush),
+            3 => Ok(Self::Shutdown),
+            _ => Err(Error::InvalidArgument),
+        }
+    }
+}
+
+/// Request to the server
+///
+/// Handle 0 is the port to send the reply to. `Register` requests carry the memory object of the ring as handle 1.
+#[derive(Debug, Clone, Copy)]
+#[repr(C)]
+pub struct Request {
+    pub r#type: u64,
+}
+
+/// Reply of the server
+#[derive(Debug, Clone, Copy)]
+#[repr(C)]
+pub struct Reply {
+    /// 0 on success, else the error code
+    pub status: u64,
+    /// Number of records drained, for `Flush` and `Shutdown`
+    pub drained: u64,
+}
+
+impl Reply {
+    /// Build the reply of a request result
+    pub fn new(result: Result<usize, Error>) -> Self {
+        match result {
+            Ok(drained) => Self {
+                status: 0,
+                drained: drained as u64,
+            },
+            Err(err) => Self {
+                status: err as u64,
+                drained: 0,
+            },
+        }
+    }
+
+    /// Get the result of the request
+    pub fn result(&self) -> Result<usize, Error> {
+        match self.status {
+            0 => Ok(self.drained as usize),
+            status if status <= Error::LAST as u64 => {
+                // Note: safe since it is in the range of error codes
+                Err(unsafe { mem::transmute::<usize, Error>(status as usize) })
+            }
+            _ => Err(Error::InvalidArgument),
+        }
+    }
+}
+
+/// Header of a ring, at the start of its memory object
+///
+/// Positions are numbers of bytes since the creation of the ring.
+#[repr(C)]
+struct RingHeader {
+    /// Position after the last record written, only moved by the process
+    write: AtomicU64,
+    /// Position of the next record to read, moved by the consumer which claims the record
+    read: AtomicU64,
+}
+
+/// Ring of log records, in a memory object shared by a process and the server
+///
+/// The process writes the records (its writers must be serialized), the server reads them.
+/// Records are claimed by moving the read position: the process can also read its own ring, when the server does
+/// not answer in time, without a record being relayed twice.
+///
+/// The content is not trusted by the server: invalid records make it skip all the pending data.
+#[derive(Debug)]
+pub struct Ring {
+    address: usize,
+    capacity: usize,
+}
+
+// Note: the data is only accessed through the atomic positions
+unsafe impl Send for Ring {}
+unsafe impl Sync for Ring {}
+
+/// Record read from a ring
+#[derive(Debug)]
+pub struct RingRecord<'a> {
+    pub tid: u64,
+    pub level: Level,
+    pub message: &'a str,
+}
+
+impl Ring {
+    /// Use the ring mapped at `address`, of `RING_SIZE` bytes
+    ///
+    /// # Safety
+    ///
+    /// The memory must stay mapped readable and writable for the lifetime of the ring.
+    pub unsafe fn from_address(address: usize) -> Self {
+        Self {
+            address,
+            capacity: RING_SIZE - RING_DATA_OFFSET,
+        }
+    }
+
+    fn header(&self) -> &RingHeader {
+        unsafe { &*(self.address as *const RingHeader) }
+    }
+
+    /// Write a record, returns false if it does not fit
+    ///
+    /// Writers must be serialized by the caller.
+    pub fn push(&self, tid: u64, level: Level, message: &str) -> bool {
+        if message.len() > MAX_MESSAGE_SIZE {
+            return false;
+        }
+
+        let header = self.header();
+        let size = record_size(message.len());
+        let write = header.write.load(Ordering::Relaxed);
+        let read = header.read.load(Ordering::Acquire);
+
+        let used = write.wrapping_sub(read) as usize;
+        if used > self.capacity || size > self.capacity - used {
+            return false;
+        }
+
+        let mut record_header = [0u8; RECORD_HEADER_SIZE];
+        record_header[..8].copy_from_slice(&tid.to_le_bytes());
+        record_header[8..12].copy_from_slice(&(level as u32).to_le_bytes());
+        record_header[12..].copy_from_slice(&(message.len() as u32).to_le_bytes());
+
+        self.copy_in(write, &record_header);
+        self.copy_in(write + RECORD_HEADER_SIZE as u64, message.as_bytes());
+
+        header.write.store(write + size as u64, Ordering::Release);
+        true
+    }
+
+    /// Claim the next record, and copy its message into `buffer`
+    ///
+    /// Returns None if the ring is empty.
+    pub fn pop<'a>(&self, buffer: &'a mut [u8; MAX_MESSAGE_SIZE]) -> Option<RingRecord<'a>> {
+        let header = self.header();
+
+        loop {
+            let read = header.read.load(Ordering::Acquire);
+            let write = header.write.load(Ordering::Acquire);
+            if read == write {
+                return None;
+            }
+
+            let mut record_header = [0u8; RECORD_HEADER_SIZE];
+            self.copy_out(read, &mut record_header);
+
+            let tid = u64::from_le_bytes(record_header[..8].try_into().unwrap());
+            let level = level_of(u32::from_le_bytes(record_header[8..12].try_into().unwrap()));
+            let len = u32::from_le_bytes(record_header[12..].try_into().unwrap()) as usize;
+            let available = write.wrapping_sub(read) as usize;
+
+            let valid = len <= MAX_MESSAGE_SIZE && record_size(len) <= available.min(self.capacity);
+            let Some(level) = level.filter(|_| valid) else {
+                // Corrupted: drop all the pending data
+                let _ =
+                    header
+                        .read
+                        .compare_exchange(read, write, Ordering::AcqRel, Ordering::Relaxed);
+                return None;
+            };
+
+            // Copy before claiming: the space can be reused by the writer once claimed
+            self.copy_out(read + RECORD_HEADER_SIZE as u64, &mut buffer[..len]);
+
+            if header
+                .read
+                .compare_exchange(
+                    read,
+                    read + record_size(len) as u64,
+                    Ordering::AcqRel,
+                    Ordering::Relaxed,
+                )
+                .is_err()
+            {
+                // Claimed by the other reader
+                continue;
+            }
+
+            let message = match core::str::from_utf8(&buffer[..len]) {
+                Ok(message) => message,
+                Err(err) => unsafe { core::str::from_utf8_unchecked(&buffer[..err.valid_up_to()]) },
+            };
+
+            return Some(RingRecord {
+                tid,
+                level,
+                message,
+            });
+        }
+    }
+
+    fn data(&self) -> *mut u8 {
+        (self.address + RING_DATA_OFFSET) as *mut u8
+    }
+
+    fn copy_in(&self, position: u64, bytes: &[u8]) {
+        let offset = (position % self.capacity as u64) as usize;
+        let first = bytes.len().min(self.capacity - offset);
+
+        unsafe {
+            core::ptr::copy_nonoverlapping(bytes.as_ptr(), self.data().add(offset), first);
+            core::ptr::copy_nonoverlapping(
+                bytes.as_ptr().add(first),
+                self.data(),
+                bytes.len() - first,
+            );
+        }
+    }
+
+    fn copy_out(&self, position: u64, bytes: &mut [u8]) {
+        let offset = (position % self.capacity as u64) as usize;
+        let first = bytes.len().min(self.capacity - offset);
+
+        unsafe {
+            core::ptr::copy_nonoverlapping(self.data().add(offset), bytes.as_mut_ptr(), first);
+            core::ptr::copy_nonoverlapping(
+                self.data(),
+                bytes.as_mut_ptr().add(first),
+                bytes.len() - first,
+            );
+        }
+    }
+}
+
+/// Size of a record in a ring, aligned on 8 bytes
+fn record_size(len: usize) -> usize {
+    RECORD_HEADER_SIZE + len.next_multiple_of(8)
+}
+
+fn level_of(value: u32) -> Option<Level> {
+    const LEVELS: [Level; 5] = [
+        Level::Error,
+        Level::Warn,
+        Level::Info,
+        Level::Debug,
+        Level::Trace,
+    ];
+
+    LEVELS.into_iter().find(|&level| level as u32 == value)
+}
+
+/// Connection to the log server
+#[derive(Debug)]
+pub struct LogServer {
+    server: PortSender,
+    reply_receiver: PortReceiver,
+    reply_sender: PortSender,
+}
+
+impl LogServer {
+    /// Connect to the server
+    pub fn connect() -> Result<Self, Error> {
+        let server = Port::open(SERVER_PORT_NAME)?;
+        let (reply_receiver, reply_sender) = Port::create(None)?;
+
+        Ok(Self {
+            server,
+            reply_receiver,
+            reply_sender,
+        })
+    }
+
+    /// Register the ring of the calling process, in a memory object of `RING_SIZE` bytes
+    pub fn register(&self, ring: MemoryObject) -> Result<(), Error> {
+        self.call(RequestType::Register, ring.into_handle(), &FLUSH_BACKOFF)?;
+        Ok(())
+    }
+
+    /// Let the server drain the ring of the calling process, returns the number of records drained
+    ///
+    /// Fails with `Error::DeadlineExceeded` if the server does not answer in time.
+    pub fn flush(&self) -> Result<usize, Error> {
+        self.call(RequestType::Flush, Handle::invalid(), &FLUSH_BACKOFF)
+    }
+
+    /// Let the server drain all the rings before the system shuts down, returns the number of records drained
+    ///
+    /// Note: only the process which spawned the server (init) can request it, others get `Error::NotSupported`
+    pub fn shutdown(&self) -> Result<usize, Error> {
+        self.call(RequestType::Shutdown, Handle::invalid(), &SHUTDOWN_BACKOFF)
+    }
+
+    fn call(&self, r#type: RequestType, object: Handle, backoff: &Backoff) -> Result<usize, Error> {
+        let request = Request {
+            r#type: r#type as u64,
+        };
+        let mut handles = [self.reply_sender.clone().into_handle(), object];
+
+        // Drop the late replies of the calls which timed out
+        while self.reply_receiver.receive().is_ok() {}
+
+        let mut message = unsafe { Message::new(&request, &mut handles) };
+        self.server.send(&mut message)?;
+
+        // Bounded: the caller falls back to the kernel log syscall
+        let reply = backoff.retry_if(
+            |err| matches!(err, Error::ObjectNotReady),
+            || self.reply_receiver.receive(),
+        )?;
+        failure::check_reply(&reply)?;
+        unsafe { reply.data::<Reply>() }.result()
+    }
+}
+
+// Make sure the protocol fits in messages
+const _: () = assert!(mem::size_of::<Request>() <= Message::DATA_SIZE);
+const _: () = assert!(mem::size_of::<Reply>() <= Message::DATA_SIZE);
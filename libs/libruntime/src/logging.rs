@@ -1,8 +1,75 @@
 use alloc::fmt::format;
-use log::{Metadata, Record};
+use core::fmt;
+use log::{Level, Metadata, Record};
+use spin::{Mutex, Once};
+
+use crate::kobject::{Error, KernelLog, MemoryObject, Permissions, Process, Thread};
+use crate::log_server::{LogServer, Ring, MAX_MESSAGE_SIZE, RING_SIZE};
 
 struct InitLogger;
 
+/// Ring shared with the log server (see `log_server`), if it was up when the process started
+struct Transport {
+    ring: Ring,
+    server: LogServer,
+    /// Serializes the writers of the ring
+    writers: Mutex<()>,
+}
+
+static TRANSPORT: Once<Transport> = Once::new();
+
+impl Transport {
+    fn connect() -> Result<Self, Error> {
+        let server = LogServer::connect()?;
+        let object = MemoryObject::create(RING_SIZE)?;
+        let mapping = Process::current().map_mem(
+            None,
+            RING_SIZE,
+            Permissions::READ | Permissions::WRITE,
+            &object,
+            0,
+        )?;
+        let ring = unsafe { Ring::from_address(mapping.address()) };
+
+        server.register(object)?;
+
+        // Shared with the server until the process exits
+        mapping.leak();
+
+        Ok(Self {
+            ring,
+            server,
+            writers: Mutex::new(()),
+        })
+    }
+
+    fn submit(&self, level: Level, message: &str) -> bool {
+        if message.len() > MAX_MESSAGE_SIZE {
+            return false;
+        }
+
+        let tid = Thread::current_tid();
+        let _writers = self.writers.lock();
+
+        if self.ring.push(tid, level, message) {
+            return true;
+        }
+
+        // Full: relay the pending records first, to keep the order
+        self.drain();
+        self.ring.push(tid, level, message)
+    }
+
+    /// Relay the pending records to the kernel ourselves, without allocating
+    fn drain(&self) {
+        let mut buffer = [0u8; MAX_MESSAGE_SIZE];
+
+        while let Some(record) = self.ring.pop(&mut buffer) {
+            let _ = KernelLog::relay(Process::current(), record.tid, record.level, record.message);
+        }
+    }
+}
+
 // https://stackoverflow.com/questions/50200268/how-can-i-use-the-format-macro-in-a-no-std-environment
 mod write_to {
     use core::cmp::min;
@@ -20,6 +87,18 @@ mod write_to {
             WriteTo { buffer, used: 0 }
         }
 
+        /// Get the written part, even if the buffer overflowed
+        pub fn truncated(self) -> &'a str {
+            let used = min(self.used, self.buffer.len());
+            let valid = &self.buffer[..used];
+
+            // The last char may have been cut in the middle
+            match core::str::from_utf8(valid) {
+                Ok(value) => value,
+                Err(err) => unsafe { core::str::from_utf8_unchecked(&valid[..err.valid_up_to()]) },
+            }
+        }
+
         pub fn as_str(self) -> Option<&'a str> {
             if self.used <= self.buffer.len() {
                 // only successful concats of str - must be a valid str.
@@ -71,7 +150,14 @@ impl log::Log for InitLogger {
         }
     }
 
-    fn flush(&self) {}
+    // Let the log server drain our ring, or drain it ourselves if it does not answer in time
+    fn flush(&self) {
+        if let Some(transport) = TRANSPORT.get() {
+            if transport.server.flush().is_err() {
+                transport.drain();
+            }
+        }
+    }
 }
 
 impl InitLogger {
@@ -93,6 +179,15 @@ impl InitLogger {
     }
 
     fn syscall(record: &Record, message: &str) {
+        if let Some(transport) = TRANSPORT.get() {
+            if transport.submit(record.level(), message) {
+                return;
+            }
+
+            // Larger than a ring record: keep the order
+            transport.drain();
+        }
+
         // If logging fails, there is not much we can do...
         let _ = libsyscalls::log(record.level(), message);
     }
@@ -100,8 +195,49 @@ impl InitLogger {
 
 static LOGGER: InitLogger = InitLogger;
 
+/// Flush pending log records
+///
+/// Called before the process exits, so that its last messages are not lost.
+/// The log server drains the ring of the process, with a bounded wait: after it, the process drains it itself.
+pub fn flush() {
+    log::logger().flush();
+}
+
+/// Log without allocating: the message is truncated if it does not fit in the buffer
+///
+/// Used on crash paths, where the heap may not be usable anymore.
+/// The records pending in the ring are relayed first, then the message goes directly to the kernel.
+pub fn log_no_alloc(level: Level, args: fmt::Arguments) {
+    const TRUNCATED_MARK: &str = " [truncated]";
+
+    if let Some(transport) = TRANSPORT.get() {
+        transport.drain();
+    }
+
+    let mut buf: [u8; 4096] = [0u8; 4096];
+    let mut writer = write_to::WriteTo::new(&mut buf[..4096 - TRUNCATED_MARK.len()]);
+    let complete = fmt::write(&mut writer, args).is_ok();
+    let message = writer.truncated();
+
+    if complete {
+        let _ = libsyscalls::log(level, message);
+        return;
+    }
+
+    // Append the mark after the message, in the space kept for it
+    let len = message.len();
+    buf[len..len + TRUNCATED_MARK.len()].copy_from_slice(TRUNCATED_MARK.as_bytes());
+    let message = unsafe { core::str::from_utf8_unchecked(&buf[..len + TRUNCATED_MARK.len()]) };
+    let _ = libsyscalls::log(level, message);
+}
+
 pub fn init() {
     // Note: if set logger fails, there is not much we can do since panic also use the logger
     let _ = log::set_logger(&LOGGER);
     log::set_max_level(log::LevelFilter::Debug); // Trace is very verbose
+
+    // Processes started before the log server log directly to the kernel
+    if let Ok(transport) = Transport::connect() {
+        TRANSPORT.call_once(|| transport);
+    }
 }
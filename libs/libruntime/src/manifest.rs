@@ -0,0 +1,447 @@
+//! Binary manifest
+//!
+//! A binary declares its requirements (services, io-ports, sandbox needs) as ELF notes in a dedicated section.
+//! The loader parses this section and checks the requirements against the spawner's rights before starting the binary.
+//!
+//! Each requirement is one note, with name `NOTE_NAME` and type `ManifestEntryType`.
+
+use core::{fmt, mem::size_of, ops::Range, str};
+
+/// Name of the ELF section which contains the manifest notes
+pub const SECTION_NAME: &str = ".note.mti_fun_os";
+
+/// Owner name of the manifest notes
+pub const NOTE_NAME: &[u8; NOTE_NAME_SIZE] = b"mti-fun-os\0\0";
+
+// "mti-fun-os\0" padded to 4 bytes
+const NOTE_NAME_SIZE: usize = 12;
+const NOTE_NAME_LEN: u32 = 11;
+
+const NOTE_ALIGN: usize = 4;
+
+/// Type of a manifest entry
+#[repr(u32)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ManifestEntryType {
+    RequiredService = 1,
+    IoPortRange,
+    Sandbox,
+}
+
+/// Sandbox needs of a binary: the rights the kernel checks (see `Process::set_sandbox`)
+pub use libsyscalls::SandboxFlags;
+
+/// Entry of a manifest
+#[derive(Debug, Clone)]
+pub enum ManifestEntry<'a> {
+    /// The binary needs the named service to be running
+    RequiredService(&'a str),
+    /// The binary needs access to the given io-port range
+    IoPortRange(Range<u16>),
+    /// The binary needs the given sandbox rights
+    Sandbox(SandboxFlags),
+}
+
+/// Error while parsing a manifest
+#[derive(Debug, Clone, Copy)]
+pub enum ManifestError {
+    Truncated,
+    BadEntryType(u32),
+    BadEntryData(ManifestEntryType),
+}
+
+impl fmt::Display for ManifestError {
+    fn fmt(&self, formatter: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match *self {
+            ManifestError::Truncated => write!(formatter, "truncated manifest"),
+            ManifestError::BadEntryType(typ) => {
+                write!(formatter, "bad manifest entry type: {}", typ)
+            }
+            ManifestError::BadEntryData(typ) => {
+                write!(formatter, "bad manifest entry data for {:?}", typ)
+            }
+        }
+    }
+}
+
+/// Raw ELF note, as emitted by the manifest macros
+///
+/// Note: `N` must be aligned on 4 bytes
+#[repr(C, align(4))]
+pub struct ManifestNote<const N: usize> {
+    namesz: u32,
+    descsz: u32,
+    r#type: u32,
+    name: [u8; NOTE_NAME_SIZE],
+    desc: [u8; N],
+}
+
+impl<const N: usize> ManifestNote<N> {
+    /// Build a note from its type and data
+    pub const fn new(r#type: ManifestEntryType, data: &[u8]) -> Self {
+        assert!(N % NOTE_ALIGN == 0);
+        assert!(data.len() <= N);
+
+        let mut desc = [0u8; N];
+        let mut index = 0;
+        while index < data.len() {
+            desc[index] = data[index];
+            index += 1;
+        }
+
+        Self {
+            namesz: NOTE_NAME_LEN,
+            descsz: data.len() as u32,
+            r#type: r#type as u32,
+            name: *NOTE_NAME,
+            desc,
+        }
+    }
+}
+
+/// Get the padded size of note data
+pub const fn padded_size(len: usize) -> usize {
+    (len + NOTE_ALIGN - 1) / NOTE_ALIGN * NOTE_ALIGN
+}
+
+/// Declare a service the binary needs
+///
+/// ```ignore
+/// libruntime::manifest_service!("vfs-server");
+/// ```
+#[macro_export]
+macro_rules! manifest_service {
+    ($name:expr) => {
+        const _: () = {
+            const DATA: &[u8] = $name.as_bytes();
+            const SIZE: usize = $crate::manifest::padded_size(DATA.len());
+
+            #[used]
+            #[link_section = ".note.mti_fun_os"]
+            static NOTE: $crate::manifest::ManifestNote<SIZE> = $crate::manifest::ManifestNote::new(
+                $crate::manifest::ManifestEntryType::RequiredService,
+                DATA,
+            );
+        };
+    };
+}
+
+/// Declare an io-port range the binary needs
+///
+/// ```ignore
+/// libruntime::manifest_ioports!(0x3F8..0x400);
+/// ```
+#[macro_export]
+macro_rules! manifest_ioports {
+    ($range:expr) => {
+        const _: () = {
+            const RANGE: core::ops::Range<u16> = $range;
+            const START: [u8; 2] = RANGE.start.to_le_bytes();
+            const END: [u8; 2] = RANGE.end.to_le_bytes();
+
+            #[used]
+            #[link_section = ".note.mti_fun_os"]
+            static NOTE: $crate::manifest::ManifestNote<4> = $crate::manifest::ManifestNote::new(
+                $crate::manifest::ManifestEntryType::IoPortRange,
+                &[START[0], START[1], END[0], END[1]],
+            );
+        };
+    };
+}
+
+/// Declare the sandbox rights the binary needs
+///
+/// ```ignore
+/// libruntime::manifest_sandbox!(libruntime::manifest::SandboxFlags::PROCESS_CREATE);
+/// ```
+#[macro_export]
+macro_rules! manifest_sandbox {
+    ($flags:expr) => {
+        const _: () = {
+            const FLAGS: [u8; 4] = $crate::manifest::SandboxFlags::bits(&$flags).to_le_bytes();
+
+            #[used]
+            #[link_section = ".note.mti_fun_os"]
+            static NOTE: $crate::manifest::ManifestNote<4> = $crate::manifest::ManifestNote::new(
+                $crate::manifest::ManifestEntryType::Sandbox,
+                &FLAGS,
+            );
+        };
+    };
+}
+
+/// Parse the manifest section data
+///
+/// Notes from other owners are skipped.
+pub fn parse(data: &[u8]) -> ManifestIter<'_> {
+    ManifestIter { data }
+}
+
+/// Iterator over manifest entries
+pub struct ManifestIter<'a> {
+    data: &'a [u8],
+}
+
+impl<'a> Iterator for ManifestIter<'a> {
+    type Item = Result<ManifestEntry<'a>, ManifestError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            if self.data.is_empty() {
+                return None;
+            }
+
+            match self.next_note() {
+                Ok(Some(entry)) => return Some(Ok(entry)),
+                Ok(None) => {
+                    // Note from another owner
+                }
+                Err(err) => {
+                    // Stop iteration on error
+                    self.data = &[];
+                    return Some(Err(err));
+                }
+            }
+        }
+    }
+}
+
+impl<'a> ManifestIter<'a> {
+    fn next_note(&mut self) -> Result<Option<ManifestEntry<'a>>, ManifestError> {
+        let namesz = self.read_u32()? as usize;
+        let descsz = self.read_u32()? as usize;
+        let r#type = self.read_u32()?;
+        let name = self.read_bytes(namesz)?;
+        let desc = self.read_bytes(descsz)?;
+
+        if name != &NOTE_NAME[..NOTE_NAME_LEN as usize] {
+            return Ok(None);
+        }
+
+        let entry = match r#type {
+            1 => {
+                let typ = ManifestEntryType::RequiredService;
+                let name = str::from_utf8(desc).map_err(|_| ManifestError::BadEntryData(typ))?;
+                ManifestEntry::RequiredService(name)
+            }
+            2 => {
+                let typ = ManifestEntryType::IoPortRange;
+                if desc.len() != 2 * size_of::<u16>() {
+                    return Err(ManifestError::BadEntryData(typ));
+                }
+
+                let start = u16::from_le_bytes([desc[0], desc[1]]);
+                let end = u16::from_le_bytes([desc[2], desc[3]]);
+                ManifestEntry::IoPortRange(start..end)
+            }
+            3 => {
+                let typ = ManifestEntryType::Sandbox;
+                let bits = desc
+                    .try_into()
+                    .map_err(|_| ManifestError::BadEntryData(typ))?;
+                ManifestEntry::Sandbox(SandboxFlags::from_bits(u32::from_le_bytes(bits)))
+            }
+            other => {
+                return Err(ManifestError::BadEntryType(other));
+            }
+        };
+
+        Ok(Some(entry))
+    }
+
+    fn read_u32(&mut self) -> Result<u32, ManifestError> {
+        let bytes = self.read_bytes(size_of::<u32>())?;
+        Ok(u32::from_le_bytes(bytes.try_into().unwrap()))
+    }
+
+    /// Read data, then skip padding
+    fn read_bytes(&mut self, len: usize) -> Result<&'a [u8], ManifestError> {
+        if self.data.len() < len {
+            return Err(ManifestError::Truncated);
+        }
+
+        let (value, remain) = self.data.split_at(len);
+        let padding = padded_size(len) - len;
+        self.data = remain.get(padding..).unwrap_or(&[]);
+
+        Ok(value)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate std;
+
+    use alloc::vec::Vec;
+
+    use super::*;
+
+    /// Build a raw note, padded like the linker emits it
+    fn note(r#type: u32, name: &[u8], desc: &[u8]) -> Vec<u8> {
+        let mut data = Vec::new();
+        data.extend_from_slice(&(name.len() as u32).to_le_bytes());
+        data.extend_from_slice(&(desc.len() as u32).to_le_bytes());
+        data.extend_from_slice(&r#type.to_le_bytes());
+        data.extend_from_slice(name);
+        data.resize(data.len() + padded_size(name.len()) - name.len(), 0);
+        data.extend_from_slice(desc);
+        data.resize(data.len() + padded_size(desc.len()) - desc.len(), 0);
+        data
+    }
+
+    fn our_note(r#type: u32, desc: &[u8]) -> Vec<u8> {
+        note(r#type, &NOTE_NAME[..NOTE_NAME_LEN as usize], desc)
+    }
+
+    fn note_bytes<const N: usize>(note: &ManifestNote<N>) -> &[u8] {
+        unsafe {
+            core::slice::from_raw_parts(note as *const _ as *const u8, size_of::<ManifestNote<N>>())
+        }
+    }
+
+    #[test]
+    fn parses_entries() {
+        let mut data = our_note(1, b"vfs-server");
+        data.extend(our_note(2, &[0xF8, 0x03, 0x00, 0x04]));
+        data.extend(our_note(3, &SandboxFlags::DEBUG.bits().to_le_bytes()));
+
+        let entries: Vec<_> = parse(&data).collect::<Result<_, _>>().unwrap();
+        assert_eq!(entries.len(), 3);
+        assert!(matches!(
+            entries[0],
+            ManifestEntry::RequiredService("vfs-server")
+        ));
+        assert!(
+            matches!(&entries[1], ManifestEntry::IoPortRange(range) if *range == (0x3F8..0x400))
+        );
+        assert!(matches!(
+            entries[2],
+            ManifestEntry::Sandbox(SandboxFlags::DEBUG)
+        ));
+    }
+
+    #[test]
+    fn parses_macro_notes() {
+        let service =
+            ManifestNote::<{ padded_size(3) }>::new(ManifestEntryType::RequiredService, b"foo");
+        let sandbox = ManifestNote::<4>::new(
+            ManifestEntryType::Sandbox,
+            &SandboxFlags::PROCESS_CREATE.bits().to_le_bytes(),
+        );
+
+        let mut data = Vec::from(note_bytes(&service));
+        data.extend_from_slice(note_bytes(&sandbox));
+
+        let entries: Vec<_> = parse(&data).collect::<Result<_, _>>().unwrap();
+        assert!(matches!(entries[0], ManifestEntry::RequiredService("foo")));
+        assert!(matches!(
+            entries[1],
+            ManifestEntry::Sandbox(SandboxFlags::PROCESS_CREATE)
+        ));
+    }
+
+    #[test]
+    fn skips_other_owners() {
+        let mut data = note(1, b"GNU\0", b"build-id");
+        data.extend(our_note(1, b"event-bus"));
+
+        let entries: Vec<_> = parse(&data).collect::<Result<_, _>>().unwrap();
+        assert_eq!(entries.len(), 1);
+        assert!(matches!(
+            entries[0],
+            ManifestEntry::RequiredService("event-bus")
+        ));
+    }
+
+    #[test]
+    fn empty_manifest() {
+        assert_eq!(parse(&[]).count(), 0);
+    }
+
+    #[test]
+    fn truncated_header() {
+        let data = our_note(1, b"vfs-server");
+
+        let mut iter = parse(&data[..8]);
+        assert!(matches!(iter.next(), Some(Err(ManifestError::Truncated))));
+        assert!(iter.next().is_none());
+    }
+
+    #[test]
+    fn truncated_desc() {
+        let data = our_note(1, b"vfs-server");
+
+        // The name is complete, the description is cut
+        let mut iter = parse(&data[..data.len() - 8]);
+        assert!(matches!(iter.next(), Some(Err(ManifestError::Truncated))));
+    }
+
+    #[test]
+    fn oversized_desc() {
+        let mut data = our_note(3, &[0; 4]);
+        // descsz larger than the section
+        data[4..8].copy_from_slice(&u32::MAX.to_le_bytes());
+
+        assert!(matches!(
+            parse(&data).next(),
+            Some(Err(ManifestError::Truncated))
+        ));
+    }
+
+    #[test]
+    fn bad_entry_type() {
+        let data = our_note(42, &[0; 4]);
+
+        assert!(matches!(
+            parse(&data).next(),
+            Some(Err(ManifestError::BadEntryType(42)))
+        ));
+    }
+
+    #[test]
+    fn bad_service_name() {
+        let data = our_note(1, &[0xFF, 0xFE]);
+
+        assert!(matches!(
+            parse(&data).next(),
+            Some(Err(ManifestError::BadEntryData(
+                ManifestEntryType::RequiredService
+            )))
+        ));
+    }
+
+    #[test]
+    fn bad_io_port_range_size() {
+        let data = our_note(2, &[0xF8, 0x03]);
+
+        assert!(matches!(
+            parse(&data).next(),
+            Some(Err(ManifestError::BadEntryData(
+                ManifestEntryType::IoPortRange
+            )))
+        ));
+    }
+
+    #[test]
+    fn bad_sandbox_size() {
+        let data = our_note(3, &[1, 0, 0, 0, 0, 0, 0, 0]);
+
+        assert!(matches!(
+            parse(&data).next(),
+            Some(Err(ManifestError::BadEntryData(ManifestEntryType::Sandbox)))
+        ));
+    }
+
+    #[test]
+    fn stops_after_error() {
+        let mut data = our_note(42, &[0; 4]);
+        data.extend(our_note(1, b"vfs-server"));
+
+        let mut iter = parse(&data);
+        assert!(matches!(
+            iter.next(),
+            Some(Err(ManifestError::BadEntryType(42)))
+        ));
+        assert!(iter.next().is_none());
+    }
+}
@@ -0,0 +1,409 @@
+//! Process server protocol and client
+//!
+//! The process server (`servers/process-server`) reaps the processes when they terminate:
+//! it keeps a record of each exited process (pid, name, timestamps, resource usage) in a bounded table,
+//! so that their parents or a shell can query how they went after the kernel deleted them.
+//!
+//! Records are dropped by the retention policy: the oldest ones go first when the table is full, or when they get too old.
+//!
+//! The records are sent as a key/value block, so that fields can be added without breaking the clients.
+//!
+//! The server also spawns processes from the binaries registered by init (eg: for `posix_spawn`).
+//! Binaries are registered by path: `/bin/<name>` for programs, `/lib/<name>` for the shared libraries they need.
+//! Init checks their signature and manifest before registering them: the server trusts them.
+
+use core::{mem, slice, time::Duration};
+
+use alloc::{string::String, vec::Vec};
+
+use crate::failure;
+use crate::kobject::{
+    Error, Handle, MemoryObject, Message, Permissions, Port, PortReceiver, PortSender, Process,
+    PAGE_SIZE,
+};
+use crate::kvblock::{KVBlock, KVBlockBuilder, Value};
+
+/// Name of the port of the server
+pub const SERVER_PORT_NAME: &str = "process-server";
+
+/// Key of the records in the block replied to `ListExited`: each value is the block of a record (bytes)
+pub const RECORD_KEY: &str = "process";
+
+/// Directory of the shared libraries in the binaries registry
+pub const LIBRARY_DIR: &str = "/lib/";
+
+/// Maximum length of the path of a binary, in bytes
+pub const PATH_SIZE: usize = 32;
+
+/// Type of the requests to the server
+#[repr(u64)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RequestType {
+    /// List the exited processes, oldest first
+    ListExited = 1,
+    /// Replace the retention policy of the exited processes table (reserved to the spawner of the server)
+    SetRetention,
+    /// Add a binary to the registry (reserved to the spawner of the server)
+    RegisterBinary,
+    /// Spawn a process from a registered binary
+    Spawn,
+    /// Set the exit status of the calling process, kept in its record once it exits
+    SetExitStatus,
+}
+
+impl TryFrom<u64> for RequestType {
+    type Error = Error;
+
+    fn try_from(value: u64) -> Result<Self, Self::Error> {
+        match value {
+            1 => Ok(Self::ListExited),
+            2 => Ok(Self::SetRetention),
+            3 => Ok(Self::RegisterBinary),
+            4 => Ok(Self::Spawn),
+            5 => Ok(Self::SetExitStatus),
+            _ => Err(Error::InvalidArgument),
+        }
+    }
+}
+
+/// Retention policy of the exited processes table
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(C)]
+pub struct Retention {
+    /// Maximum number of records: the oldest one is dropped to make room
+    pub max_entries: u64,
+    /// Maximum age of a record in nanoseconds, since the process exited (0: no limit)
+    pub max_age: u64,
+}
+
+impl Retention {
+    pub const fn new(max_entries: usize, max_age: Option<Duration>) -> Self {
+        Self {
+            max_entries: max_entries as u64,
+            max_age: match max_age {
+                Some(max_age) => max_age.as_nanos() as u64,
+                None => 0,
+            },
+        }
+    }
+
+    /// Get the maximum age of a record
+    pub fn max_age(&self) -> Option<Duration> {
+        match self.max_age {
+            0 => None,
+            max_age => Some(Duration::from_nanos(max_age)),
+        }
+    }
+}
+
+impl Default for Retention {
+    fn default() -> Self {
+        Self::new(64, Some(Duration::from_secs(10 * 60)))
+    }
+}
+
+/// Record of an exited process
+#[derive(Debug, Clone, Default)]
+pub struct ExitedProcess {
+    pub pid: u64,
+    pub name: String,
+    /// Time the process was created, in nanoseconds since boot (0 if it was created before the server started)
+    pub created: u64,
+    /// Time the process terminated, in nanoseconds since boot
+    pub terminated: u64,
+    /// Number of scheduler ticks run by the threads of the process
+    pub ticks: u64,
+    /// Number of threads which ran in the process
+    pub thread_count: u64,
+    /// Exit status set by the process (0 if it did not set one)
+    pub status: i64,
+}
+
+impl ExitedProcess {
+    /// Encode the record as a key/value block
+    pub fn to_block(&self) -> Vec<u8> {
+        let mut builder = KVBlockBuilder::new();
+        builder
+            .push("pid", Value::U64(self.pid))
+            .push_str("name", &self.name)
+            .push("created", Value::U64(self.created))
+            .push("terminated", Value::U64(self.terminated))
+            .push("ticks", Value::U64(self.ticks))
+            .push("threads", Value::U64(self.thread_count))
+            .push("status", Value::U64(self.status as u64));
+        builder.build()
+    }
+
+    /// Decode a record
+    ///
+    /// Missing fields are left to their default value, and unknown ones are ignored.
+    pub fn from_block(block: &KVBlock) -> Self {
+        let u64_field = |key| block.get(key).and_then(|value| value.as_u64()).unwrap_or(0);
+
+        Self {
+            pid: u64_field("pid"),
+            name: String::from(
+                block
+                    .get("name")
+                    .and_then(|value| value.as_str())
+                    .unwrap_or(""),
+            ),
+            created: u64_field("created"),
+            terminated: u64_field("terminated"),
+            ticks: u64_field("ticks"),
+            thread_count: u64_field("threads"),
+            status: u64_field("status") as i64,
+        }
+    }
+
+    /// Get the lifetime of the process (`None` if its creation time is unknown)
+    pub fn lifetime(&self) -> Option<Duration> {
+        match self.created {
+            0 => None,
+            created => Some(Duration::from_nanos(
+                self.terminated.saturating_sub(created),
+            )),
+        }
+    }
+}
+
+/// Request to the server
+///
+/// Handle 0 is the port to send the reply to.
+/// Handle 1 is the memory object holding the binary, for `RegisterBinary`.
+#[derive(Debug, Clone, Copy)]
+#[repr(C)]
+pub struct Request {
+    pub r#type: u64,
+    /// Used by `SetRetention`
+    pub retention: Retention,
+    /// Used by `RegisterBinary` (size of the binary in bytes) and `SetExitStatus` (status)
+    pub value: u64,
+    /// Used by `RegisterBinary` and `Spawn`: path of the binary, padded with zeroes
+    pub path: [u8; PATH_SIZE],
+}
+
+impl Request {
+    pub const fn new(r#type: RequestType) -> Self {
+        Self {
+            r#type: r#type as u64,
+            retention: Retention::new(0, None),
+            value: 0,
+            path: [0; PATH_SIZE],
+        }
+    }
+
+    /// Set the path of the request
+    ///
+    /// Fails with `Error::InvalidArgument` if it is empty or longer than `PATH_SIZE`
+    pub fn with_path(mut self, path: &str) -> Result<Self, Error> {
+        if path.is_empty() || path.len() > PATH_SIZE {
+            return Err(Error::InvalidArgument);
+        }
+
+        self.path[..path.len()].copy_from_slice(path.as_bytes());
+        Ok(self)
+    }
+
+    /// Get the path of the request
+    pub fn path(&self) -> Result<&str, Error> {
+        let len = self
+            .path
+            .iter()
+            .position(|&byte| byte == 0)
+            .unwrap_or(PATH_SIZE);
+
+        match core::str::from_utf8(&self.path[..len]) {
+            Ok("") | Err(_) => Err(Error::InvalidArgument),
+            Ok(path) => Ok(path),
+        }
+    }
+}
+
+/// Reply of the server
+///
+/// Replies to `ListExited` carry the records in a memory object as handle 0: a key/value block with one
+/// `RECORD_KEY` entry per record, oldest first.
+#[derive(Debug, Clone, Copy)]
+#[repr(C)]
+pub struct Reply {
+    /// 0 on success, else the error code
+    pub status: u64,
+    /// Size of the block in bytes for `ListExited` (0 if there is no record), pid of the new process for `Spawn`
+    pub size: u64,
+}
+
+impl Reply {
+    /// Build the reply of a request result
+    pub fn new(result: Result<usize, Error>) -> Self {
+        match result {
+            Ok(size) => Self {
+                status: 0,
+                size: size as u64,
+            },
+            Err(err) => Self {
+                status: err as u64,
+                size: 0,
+            },
+        }
+    }
+
+    /// Get the result of the request
+    pub fn result(&self) -> Result<usize, Error> {
+        match self.status {
+            0 => Ok(self.size as usize),
+            status if status <= Error::LAST as u64 => {
+                // Note: safe since it is in the range of error codes
+                Err(unsafe { mem::transmute::<usize, Error>(status as usize) })
+            }
+            _ => Err(Error::InvalidArgument),
+        }
+    }
+}
+
+/// Connection to the process server
+#[derive(Debug)]
+pub struct ProcessServer {
+    server: PortSender,
+    reply_receiver: PortReceiver,
+    reply_sender: PortSender,
+}
+
+impl ProcessServer {
+    /// Connect to the server
+    pub fn connect() -> Result<Self, Error> {
+        Self::connect_to(SERVER_PORT_NAME)
+    }
+
+    /// Connect to the server, waiting for it to come up if needed
+    pub fn wait_connect() -> Result<Self, Error> {
+        let server = Port::wait_open(SERVER_PORT_NAME)?;
+        let (reply_receiver, reply_sender) = Port::create(None)?;
+
+        Ok(Self {
+            server,
+            reply_receiver,
+            reply_sender,
+        })
+    }
+
+    /// Connect to a server listening on another port (eg: a private instance in a test)
+    pub fn connect_to(port_name: &str) -> Result<Self, Error> {
+        let server = Port::open(port_name)?;
+        let (reply_receiver, reply_sender) = Port::create(None)?;
+
+        Ok(Self {
+            server,
+            reply_receiver,
+            reply_sender,
+        })
+    }
+
+    /// List the exited processes still retained, oldest first
+    pub fn list_exited(&self) -> Result<Vec<ExitedProcess>, Error> {
+        let (size, mut reply) = self.call(Request::new(RequestType::ListExited), None)?;
+        if size == 0 {
+            return Ok(Vec::new());
+        }
+
+        let object =
+            MemoryObject::from_handle(reply.take_handle(0)).map_err(|_| Error::InvalidArgument)?;
+        // Mapping fails if the object is smaller than the block
+        let mapping = Process::current().map_mem(
+            None,
+            size.next_multiple_of(PAGE_SIZE),
+            Permissions::READ,
+            &object,
+            0,
+        )?;
+        let data = unsafe { slice::from_raw_parts(mapping.address() as *const u8, size) };
+
+        let block = KVBlock::parse(data).map_err(|_| Error::InvalidArgument)?;
+        let mut records = Vec::with_capacity(block.len());
+        for entry in block.iter().filter(|entry| entry.key == RECORD_KEY) {
+            let record = entry.value.as_bytes().ok_or(Error::InvalidArgument)?;
+            let record = KVBlock::parse(record).map_err(|_| Error::InvalidArgument)?;
+            records.push(ExitedProcess::from_block(&record));
+        }
+
+        Ok(records)
+    }
+
+    /// Replace the retention policy
+    ///
+    /// Records which do not fit the new policy are dropped immediately.
+    ///
+    /// Note: only the process which spawned the server (init) can set it, others get `Error::NotSupported`
+    pub fn set_retention(&self, retention: Retention) -> Result<(), Error> {
+        let mut request = Request::new(RequestType::SetRetention);
+        request.retention = retention;
+        self.call(request, None)?;
+        Ok(())
+    }
+
+    /// Add a binary to the registry, so that processes can be spawned from it
+    ///
+    /// The binary is copied into a memory object: the server keeps it, and the caller can drop its copy.
+    /// A binary registered at the same path is replaced.
+    ///
+    /// Note: only the process which spawned the server (init) can register binaries, others get `Error::NotSupported`
+    pub fn register_binary(&self, path: &str, binary: &[u8]) -> Result<(), Error> {
+        let mut request = Request::new(RequestType::RegisterBinary).with_path(path)?;
+        request.value = binary.len() as u64;
+
+        let size = binary.len().next_multiple_of(PAGE_SIZE);
+        let object = MemoryObject::create(size)?;
+        {
+            let mapping = Process::current().map_mem(
+                None,
+                size,
+                Permissions::READ | Permissions::WRITE,
+                &object,
+                0,
+            )?;
+
+            let dest =
+                unsafe { slice::from_raw_parts_mut(mapping.address() as *mut u8, binary.len()) };
+            dest.copy_from_slice(binary);
+        }
+
+        self.call(request, Some(object.into_handle()))?;
+        Ok(())
+    }
+
+    /// Spawn a process from a registered binary, returns its pid
+    ///
+    /// Fails with `Error::ObjectNotFound` if no binary is registered at this path
+    pub fn spawn(&self, path: &str) -> Result<u64, Error> {
+        let request = Request::new(RequestType::Spawn).with_path(path)?;
+        let (pid, _) = self.call(request, None)?;
+        Ok(pid as u64)
+    }
+
+    /// Set the exit status of the calling process, to be found in its record once it exits
+    pub fn set_exit_status(&self, status: i64) -> Result<(), Error> {
+        let mut request = Request::new(RequestType::SetExitStatus);
+        request.value = status as u64;
+        self.call(request, None)?;
+        Ok(())
+    }
+
+    fn call(&self, request: Request, object: Option<Handle>) -> Result<(usize, Message), Error> {
+        let mut handles = [
+            self.reply_sender.clone().into_handle(),
+            object.unwrap_or_else(Handle::invalid),
+        ];
+
+        let mut message = unsafe { Message::new(&request, &mut handles) };
+        self.server.send(&mut message)?;
+
+        let reply = self.reply_receiver.blocking_receive()?;
+        failure::check_reply(&reply)?;
+        let result = unsafe { reply.data::<Reply>() }.result()?;
+        Ok((result, reply))
+    }
+}
+
+// Make sure the protocol fits in messages
+const _: () = assert!(mem::size_of::<Request>() <= Message::DATA_SIZE);
+const _: () = assert!(mem::size_of::<Reply>() <= Message::DATA_SIZE);
@@ -0,0 +1,165 @@
+//! Retry with exponential backoff
+//!
+//! Callers waiting for something to come up (eg: a server port, a reconnection) retry the operation
+//! with growing delays, so that they do not spin while the system is busy starting.
+//! Delays are randomized (jitter), so that clients started together do not retry in lockstep.
+//!
+//! The retries stop on the first non transient error, when the backoff deadline passes,
+//! or when the request of the current thread cannot meet its deadline anymore (see `Thread::set_deadline`).
+//!
+//! Note: to wait for a server port at boot, `Port::wait_open` is better: it waits for the port registration instead of polling.
+//! Backoff is for what cannot be watched, or must not be waited for forever (eg: vfs-server connecting to the filesystem server
+//! of a mount, the vfs client reconnecting to a restarted server, polling the scheduler trace in init tests).
+
+use core::{
+    arch::x86_64::_rdtsc,
+    sync::atomic::{AtomicU64, Ordering},
+    time::Duration,
+};
+
+use log::debug;
+
+use crate::kobject::{Clock, Error, Thread, Timer};
+
+/// Backoff policy
+#[derive(Debug, Clone, Copy)]
+pub struct Backoff {
+    initial: Duration,
+    max: Duration,
+    factor: u32,
+    jitter_percent: u32,
+    timeout: Option<Duration>,
+}
+
+impl Default for Backoff {
+    fn default() -> Self {
+        Self::new(Duration::from_millis(10), Duration::from_secs(1))
+    }
+}
+
+impl Backoff {
+    /// New policy: the first delay is `initial`, then it doubles up to `max`, with 25% of jitter, without timeout
+    pub const fn new(initial: Duration, max: Duration) -> Self {
+        Self {
+            initial,
+            max,
+            factor: 2,
+            jitter_percent: 25,
+            timeout: None,
+        }
+    }
+
+    /// Set the multiplier applied to the delay after each attempt
+    pub const fn factor(mut self, factor: u32) -> Self {
+        self.factor = factor;
+        self
+    }
+
+    /// Set the jitter: each delay is randomly shortened by up to `percent` of its value
+    pub const fn jitter(mut self, percent: u32) -> Self {
+        self.jitter_percent = if percent > 100 { 100 } else { percent };
+        self
+    }
+
+    /// Give up with `Error::DeadlineExceeded` after `timeout`
+    pub const fn timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = Some(timeout);
+        self
+    }
+
+    /// Run `op` until it succeeds or fails with a non transient error (see `is_transient`)
+    pub fn retry<T>(&self, op: impl FnMut() -> Result<T, Error>) -> Result<T, Error> {
+        self.retry_if(is_transient, op)
+    }
+
+    /// Run `op` until it succeeds or fails with an error for which `transient` returns false
+    pub fn retry_if<T>(
+        &self,
+        transient: impl Fn(&Error) -> bool,
+        mut op: impl FnMut() -> Result<T, Error>,
+    ) -> Result<T, Error> {
+        let deadline = match self.timeout {
+            Some(timeout) => Some(Clock::uptime()? + timeout),
+            None => None,
+        };
+
+        let mut delay = self.initial;
+
+        loop {
+            let err = match op() {
+                Ok(value) => return Ok(value),
+                Err(err) if transient(&err) => err,
+                Err(err) => return Err(err),
+            };
+
+            // The caller of the request would not wait for the result anymore
+            Thread::check_deadline()?;
+
+            let mut wait = self.apply_jitter(delay);
+            if let Some(deadline) = deadline {
+                let now = Clock::uptime()?;
+                if now >= deadline {
+                    debug!("retry: giving up after timeout (last error: {err:?})");
+                    return Err(Error::DeadlineExceeded);
+                }
+
+                wait = wait.min(deadline - now);
+            }
+
+            sleep(wait)?;
+
+            delay = delay.saturating_mul(self.factor).min(self.max);
+        }
+    }
+
+    fn apply_jitter(&self, delay: Duration) -> Duration {
+        if self.jitter_percent == 0 {
+            return delay;
+        }
+
+        let range = delay.as_nanos() as u64 * self.jitter_percent as u64 / 100;
+        if range == 0 {
+            return delay;
+        }
+
+        delay - Duration::from_nanos(random() % (range + 1))
+    }
+}
+
+/// Errors which may go away by themselves: the object is not created yet, or not ready
+pub fn is_transient(err: &Error) -> bool {
+    matches!(err, Error::ObjectNotFound | Error::ObjectNotReady)
+}
+
+/// Block the current thread for `duration`
+pub fn sleep(duration: Duration) -> Result<(), Error> {
+    if duration.is_zero() {
+        return Ok(());
+    }
+
+    let timer = Timer::create()?;
+    timer.arm(duration, Duration::ZERO)?;
+    timer.blocking_receive()?;
+
+    Ok(())
+}
+
+static RANDOM_STATE: AtomicU64 = AtomicU64::new(0);
+
+/// Pseudo random value for the jitter
+///
+/// Note: there is no RNG service yet, so this is a xorshift seeded from the TSC.
+/// Quality does not matter here, clients only need to be spread apart.
+fn random() -> u64 {
+    let mut state = RANDOM_STATE.load(Ordering::Relaxed);
+    if state == 0 {
+        state = unsafe { _rdtsc() } | 1;
+    }
+
+    state ^= state << 13;
+    state ^= state >> 7;
+    state ^= state << 17;
+
+    RANDOM_STATE.store(state, Ordering::Relaxed);
+    state
+}
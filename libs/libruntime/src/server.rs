@@ -0,0 +1,490 @@
+//! Managed servers: the request loop of a server on its named port, with a debug endpoint installed automatically
+//!
+//! The framework receives the requests, takes their reply port (handle 0), marks them for failure reports
+//! (see `failure`) and records the request in progress, then gives them to the handler.
+//!
+//! ```ignore
+//! let server = ManagedServerBuilder::new(SERVER_PORT_NAME).build()?;
+//! let context = server.context();
+//! server.run(|request| { ... });
+//! ```
+//!
+//! Debug endpoint ("what is this server stuck on"): `build` starts a thread serving the port `server-debug:<name>`
+//! (see `debug_port_name`), so that it answers even if the request loop is stuck.
+//! On a dump request (`dump`), it logs:
+//! - the state of the threads of the server, with the stacktraces of the blocked ones (`debug::dump_threads`)
+//! - the locks held, by thread (`sync::held_locks`, only recorded with the `lock-stats` feature)
+//! - the pending tasks: the request in progress, and the tasks registered by the handler (`Context::begin_task`)
+//! - the client sessions opened by the handler (`Context::open_session`)
+
+use core::{
+    mem,
+    sync::atomic::{AtomicU64, Ordering},
+    time::Duration,
+};
+
+use alloc::{collections::BTreeMap, format, string::String, sync::Arc};
+use log::{info, warn};
+use spin::Mutex;
+
+use crate::{
+    debug, failure,
+    format::Nanos,
+    kobject::{
+        Clock, Error, Message, Port, PortReceiver, PortSender, Process, Thread, ThreadOptions,
+    },
+};
+
+/// Prefix of the names of the debug ports of the managed servers
+pub const DEBUG_PORT_PREFIX: &str = "server-debug:";
+
+/// Get the name of the debug port of a server
+pub fn debug_port_name(server: &str) -> String {
+    format!("{}{}", DEBUG_PORT_PREFIX, server)
+}
+
+/// Type of the requests of the debug endpoint
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(u64)]
+pub enum DebugRequestType {
+    /// Log the state of the server
+    Dump = 1,
+    /// Stop the debug thread, only accepted from the server process (when the server is dropped)
+    Stop,
+}
+
+impl TryFrom<u64> for DebugRequestType {
+    type Error = Error;
+
+    fn try_from(value: u64) -> Result<Self, Self::Error> {
+        match value {
+            1 => Ok(Self::Dump),
+            2 => Ok(Self::Stop),
+            _ => Err(Error::InvalidArgument),
+        }
+    }
+}
+
+/// Request to the debug endpoint, handle 0 is the port to send the reply to
+#[derive(Debug, Clone, Copy)]
+#[repr(C)]
+pub struct DebugRequest {
+    pub r#type: u64,
+}
+
+/// Reply of a dump request: the numbers of entries logged
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[repr(C)]
+pub struct DumpReply {
+    /// 0 on success, else the error code
+    pub status: u64,
+    pub threads: u64,
+    /// Always 0 without the `lock-stats` feature
+    pub held_locks: u64,
+    /// Pending tasks, including the request in progress
+    pub tasks: u64,
+    pub sessions: u64,
+}
+
+/// Client side: ask the named server to log its state, returns the numbers of entries logged
+pub fn dump(server: &str) -> Result<DumpReply, Error> {
+    let port = Port::open(&debug_port_name(server))?;
+    let (reply_receiver, reply_sender) = Port::create(None)?;
+
+    let request = DebugRequest {
+        r#type: DebugRequestType::Dump as u64,
+    };
+
+    let mut handles = [reply_sender.into_handle()];
+    let mut message = unsafe { Message::new(&request, &mut handles) };
+    port.send(&mut message)?;
+
+    let reply = reply_receiver.blocking_receive()?;
+    failure::check_reply(&reply)?;
+
+    let reply = *unsafe { reply.data::<DumpReply>() };
+    match reply.status {
+        0 => Ok(reply),
+        status if status <= Error::LAST as u64 => {
+            // Note: safe since it is in the range of error codes
+            Err(unsafe { mem::transmute::<usize, Error>(status as usize) })
+        }
+        _ => Err(Error::InvalidArgument),
+    }
+}
+
+/// Builder of a managed server
+#[derive(Debug)]
+pub struct ManagedServerBuilder<'a> {
+    name: &'a str,
+    data_capacity: Option<usize>,
+}
+
+impl<'a> ManagedServerBuilder<'a> {
+    /// Server on the port `name`
+    pub fn new(name: &'a str) -> Self {
+        Self {
+            name,
+            data_capacity: None,
+        }
+    }
+
+    /// Accept messages with payloads up to `value` bytes (see `Port::create_with_capacity`)
+    pub fn data_capacity(&mut self, value: usize) -> &mut Self {
+        self.data_capacity = Some(value);
+        self
+    }
+
+    /// Create the server port, and start the debug endpoint
+    pub fn build(&self) -> Result<ManagedServer, Error> {
+        let (receiver, sender) = match self.data_capacity {
+            Some(capacity) => Port::create_with_capacity(Some(self.name), capacity)?,
+            None => Port::create(Some(self.name))?,
+        };
+
+        let debug_name = debug_port_name(self.name);
+        let (debug_receiver, debug_sender) = Port::create(Some(&debug_name))?;
+
+        let context = Context {
+            state: Arc::new(State::default()),
+        };
+
+        let endpoint = DebugEndpoint {
+            name: String::from(self.name),
+            receiver: debug_receiver,
+            context: context.clone(),
+        };
+
+        let mut options = ThreadOptions::default();
+        options.name(&debug_name);
+        Thread::start(move || endpoint.run(), options)?;
+
+        Ok(ManagedServer {
+            receiver,
+            _sender: sender,
+            debug_sender,
+            context,
+        })
+    }
+}
+
+/// Request received by a managed server
+#[derive(Debug)]
+pub struct Request {
+    /// Handle 0 (the reply port) is taken
+    pub message: Message,
+    pub reply_port: PortSender,
+}
+
+impl Request {
+    pub fn sender_pid(&self) -> u64 {
+        self.message.sender_pid()
+    }
+}
+
+/// Managed server, see the module documentation
+#[derive(Debug)]
+pub struct ManagedServer {
+    receiver: PortReceiver,
+    // Keep the port open
+    _sender: PortSender,
+    debug_sender: PortSender,
+    context: Context,
+}
+
+impl ManagedServer {
+    /// Get the context of the server, to record sessions and tasks
+    pub fn context(&self) -> Context {
+        self.context.clone()
+    }
+
+    /// Process requests forever
+    pub fn run(&self, mut handler: impl FnMut(Request)) -> ! {
+        loop {
+            if let Err(err) = self.process_one(&mut handler) {
+                warn!("Could not receive request: {:?}", err);
+            }
+        }
+    }
+
+    /// Wait for a request and process it
+    ///
+    /// Requests without reply port are dropped.
+    pub fn process_one(&self, handler: &mut impl FnMut(Request)) -> Result<(), Error> {
+        let mut message = self.receiver.blocking_receive()?;
+
+        let reply_port = match PortSender::from_handle(message.take_handle(0)) {
+            Ok(port) => port,
+            Err(_) => {
+                warn!("Dropping request without reply port");
+                return Ok(());
+            }
+        };
+
+        // If processing panics, the client gets a failure report instead of the reply
+        let _request = failure::begin_request(&reply_port, message.correlation());
+
+        *self.context.state.current.lock() = Some(InProgress {
+            r#type: *unsafe { message.data::<u64>() },
+            sender_pid: message.sender_pid(),
+            correlation: message.correlation(),
+            since: uptime(),
+        });
+
+        handler(Request {
+            message,
+            reply_port,
+        });
+
+        *self.context.state.current.lock() = None;
+
+        Ok(())
+    }
+}
+
+impl Drop for ManagedServer {
+    fn drop(&mut self) {
+        let request = DebugRequest {
+            r#type: DebugRequestType::Stop as u64,
+        };
+
+        let mut message = unsafe { Message::new(&request, &mut []) };
+        if let Err(err) = self.debug_sender.send(&mut message) {
+            warn!("Could not stop debug endpoint: {:?}", err);
+        }
+    }
+}
+
+/// Context of a managed server: records the client sessions and the pending tasks shown by the debug endpoint
+#[derive(Debug, Clone)]
+pub struct Context {
+    state: Arc<State>,
+}
+
+impl Context {
+    /// Record a session opened by a client, returns its id
+    pub fn open_session(&self, client_pid: u64, description: String) -> u64 {
+        let id = self.state.next_id.fetch_add(1, Ordering::Relaxed);
+
+        self.state.sessions.lock().insert(
+            id,
+            Session {
+                client_pid,
+                description,
+                since: uptime(),
+            },
+        );
+
+        id
+    }
+
+    /// Record that a session has been closed
+    pub fn close_session(&self, id: u64) {
+        self.state.sessions.lock().remove(&id);
+    }
+
+    /// Record that all the sessions of a client have been closed (eg: the client is gone)
+    pub fn close_client_sessions(&self, client_pid: u64) {
+        self.state
+            .sessions
+            .lock()
+            .retain(|_, session| session.client_pid != client_pid);
+    }
+
+    /// Record a pending task (eg: a deferred reply), until the returned guard is dropped
+    pub fn begin_task(&self, description: String) -> Task {
+        let id = self.state.next_id.fetch_add(1, Ordering::Relaxed);
+
+        self.state.tasks.lock().insert(
+            id,
+            TaskEntry {
+                description,
+                since: uptime(),
+            },
+        );
+
+        Task {
+            state: self.state.clone(),
+            id,
+        }
+    }
+
+    /// Number of the open sessions
+    pub fn session_count(&self) -> usize {
+        self.state.sessions.lock().len()
+    }
+}
+
+/// Pending task, recorded until dropped
+#[derive(Debug)]
+pub struct Task {
+    state: Arc<State>,
+    id: u64,
+}
+
+impl Drop for Task {
+    fn drop(&mut self) {
+        self.state.tasks.lock().remove(&self.id);
+    }
+}
+
+/// State shared between the request loop and the debug endpoint
+///
+/// Note: spin locks, not `sync` ones, so that they do not show up in the dump
+#[derive(Debug, Default)]
+struct State {
+    current: Mutex<Option<InProgress>>,
+    tasks: Mutex<BTreeMap<u64, TaskEntry>>,
+    sessions: Mutex<BTreeMap<u64, Session>>,
+    next_id: AtomicU64,
+}
+
+#[derive(Debug)]
+struct InProgress {
+    /// First field of the request data, the request type of all the protocols
+    r#type: u64,
+    sender_pid: u64,
+    correlation: u64,
+    since: Duration,
+}
+
+#[derive(Debug)]
+struct TaskEntry {
+    description: String,
+    since: Duration,
+}
+
+#[derive(Debug)]
+struct Session {
+    client_pid: u64,
+    description: String,
+    since: Duration,
+}
+
+struct DebugEndpoint {
+    name: String,
+    receiver: PortReceiver,
+    context: Context,
+}
+
+impl DebugEndpoint {
+    fn run(self) {
+        loop {
+            let mut message = match self.receiver.blocking_receive() {
+                Ok(message) => message,
+                Err(err) => {
+                    warn!("Could not receive debug request: {:?}", err);
+                    continue;
+                }
+            };
+
+            let request = *unsafe { message.data::<DebugRequest>() };
+
+            match DebugRequestType::try_from(request.r#type) {
+                Ok(DebugRequestType::Dump) => {
+                    let Ok(reply_port) = PortSender::from_handle(message.take_handle(0)) else {
+                        warn!("Dropping debug request without reply port");
+                        continue;
+                    };
+
+                    let reply = match self.dump() {
+                        Ok(reply) => reply,
+                        Err(err) => DumpReply {
+                            status: err as u64,
+                            ..Default::default()
+                        },
+                    };
+
+                    let mut reply_message = unsafe { Message::new(&reply, &mut []) };
+                    // The client may be gone
+                    let _ = reply_port.send(&mut reply_message);
+                }
+                Ok(DebugRequestType::Stop) if message.sender_pid() == Process::current().pid() => {
+                    return;
+                }
+                _ => warn!(
+                    "Dropping invalid debug request from process {}",
+                    message.sender_pid()
+                ),
+            }
+        }
+    }
+
+    fn dump(&self) -> Result<DumpReply, Error> {
+        let state = &self.context.state;
+        let now = uptime();
+
+        info!("dump of server '{}'", self.name);
+
+        let threads = debug::dump_threads()?;
+        let held_locks = dump_held_locks();
+
+        let current = state.current.lock();
+        let tasks = state.tasks.lock();
+        let task_count = tasks.len() + current.is_some() as usize;
+        info!("{} pending tasks", task_count);
+
+        if let Some(request) = current.as_ref() {
+            info!(
+                "request type={} from process {} (cid={}), for {}",
+                request.r#type,
+                request.sender_pid,
+                request.correlation,
+                Nanos::from(now.saturating_sub(request.since))
+            );
+        }
+
+        for (id, task) in tasks.iter() {
+            info!(
+                "task {}: {}, for {}",
+                id,
+                task.description,
+                Nanos::from(now.saturating_sub(task.since))
+            );
+        }
+
+        drop(tasks);
+        drop(current);
+
+        let sessions = state.sessions.lock();
+        info!("{} client sessions", sessions.len());
+
+        for (id, session) in sessions.iter() {
+            info!(
+                "session {}: process {}, {}, open for {}",
+                id,
+                session.client_pid,
+                session.description,
+                Nanos::from(now.saturating_sub(session.since))
+            );
+        }
+
+        Ok(DumpReply {
+            status: 0,
+            threads: threads as u64,
+            held_locks: held_locks as u64,
+            tasks: task_count as u64,
+            sessions: sessions.len() as u64,
+        })
+    }
+}
+
+#[cfg(feature = "lock-stats")]
+fn dump_held_locks() -> usize {
+    debug::dump_held_locks()
+}
+
+#[cfg(not(feature = "lock-stats"))]
+fn dump_held_locks() -> usize {
+    info!("held locks not recorded (needs the lock-stats feature)");
+    0
+}
+
+fn uptime() -> Duration {
+    // Only informative
+    Clock::uptime().unwrap_or_default()
+}
+
+// Make sure the protocol fits in messages
+const _: () = assert!(mem::size_of::<DebugRequest>() <= Message::DATA_SIZE);
+const _: () = assert!(mem::size_of::<DumpReply>() <= Message::DATA_SIZE);
@@ -0,0 +1,104 @@
+//! Lazy service activation
+//!
+//! A service manager can register a rarely used service (eg: debugger, profiler) without starting it:
+//! it creates the named port of the service itself, and clients connect to it as usual.
+//! On the first message, the manager spawns the server and hands the receiving side of the port over to it.
+//! Messages already queued stay in the port, so the first connection is spliced over transparently.
+//!
+//! Hand over protocol:
+//! - the server creates a port named `service-activation:<name>` and waits on it (see `accept_activation`)
+//! - the manager opens it (waiting for its registration) and sends the receiver of the service port as handle 0
+
+use alloc::{format, string::String};
+
+use crate::kobject::{
+    Error, KWaitable, Message, Port, PortReceiver, PortReceiverHandle, PortSender,
+};
+
+/// Prefix of the names of the ports used to hand over the service ports
+pub const ACTIVATION_PORT_PREFIX: &str = "service-activation:";
+
+/// Get the name of the activation port of a service
+pub fn activation_port_name(service: &str) -> String {
+    format!("{}{}", ACTIVATION_PORT_PREFIX, service)
+}
+
+/// Service registered by the manager, whose server is not started yet
+#[derive(Debug)]
+pub struct Activatable {
+    name: String,
+    receiver: PortReceiver,
+    // Keep the port open until it is handed over
+    _sender: PortSender,
+}
+
+impl KWaitable for Activatable {
+    unsafe fn waitable_handle(&self) -> &PortReceiverHandle {
+        self.receiver.waitable_handle()
+    }
+
+    fn wait(&self) -> Result<(), Error> {
+        self.receiver.wait()
+    }
+}
+
+impl Activatable {
+    /// Register the service: create its port, so that clients can connect to it
+    pub fn register(name: &str) -> Result<Self, Error> {
+        let (receiver, sender) = Port::create(Some(name))?;
+
+        Ok(Self {
+            name: String::from(name),
+            receiver,
+            _sender: sender,
+        })
+    }
+
+    /// Get the name of the service
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Check if a client sent a message to the service, so that it must be started
+    pub fn requested(&self) -> Result<bool, Error> {
+        match self.receiver.peek() {
+            Ok(_) => Ok(true),
+            Err(Error::ObjectNotReady) => Ok(false),
+            Err(err) => Err(err),
+        }
+    }
+
+    /// Hand over the port of the service to its server, once it has been spawned
+    ///
+    /// Blocks until the server is ready to accept it.
+    pub fn activate(self) -> Result<(), Error> {
+        hand_over(&self.name, self.receiver)
+    }
+}
+
+/// Hand over a port to the server of a service, as its service port
+///
+/// Blocks until the server is ready to accept it. It lets a process interposed in front of the service
+/// (eg: `servers/trace-proxy`) give the server a port of its own instead of the one clients use.
+pub fn hand_over(service: &str, receiver: PortReceiver) -> Result<(), Error> {
+    let server = Port::wait_open(&activation_port_name(service))?;
+
+    let mut handles = [receiver.into_handle()];
+    let mut message = unsafe { Message::new(&0u64, &mut handles) };
+    server.send(&mut message)
+}
+
+/// Server side: get the port of the service, handed over by the manager
+///
+/// Messages sent by clients before the server started are received on it.
+pub fn accept_activation(service: &str) -> Result<PortReceiver, Error> {
+    let (receiver, _sender) = Port::create(Some(&activation_port_name(service)))?;
+
+    loop {
+        let mut message = receiver.blocking_receive()?;
+
+        if let Ok(port) = PortReceiver::from_handle(message.take_handle(0)) {
+            return Ok(port);
+        }
+    }
+}
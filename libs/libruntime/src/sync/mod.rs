@@ -1,3 +1,11 @@
+mod mutex;
 mod once_lock;
+mod registry;
+mod rw_lock;
 
+pub use mutex::{Mutex, MutexGuard};
 pub use once_lock::OnceLock;
+#[cfg(feature = "lock-stats")]
+pub use registry::{held_locks, lock_stats};
+pub use registry::{HeldLock, LockStats};
+pub use rw_lock::{RwLock, RwLockReadGuard, RwLockWriteGuard};
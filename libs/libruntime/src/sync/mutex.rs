@@ -0,0 +1,84 @@
+use core::{
+    fmt,
+    ops::{Deref, DerefMut},
+};
+
+use super::registry::HoldProbe;
+
+/// Named mutual exclusion lock
+///
+/// With the `lock-stats` feature, its contention statistics are recorded under its name.
+pub struct Mutex<T: ?Sized> {
+    name: &'static str,
+    inner: spin::Mutex<T>,
+}
+
+impl<T> Mutex<T> {
+    /// Create a new mutex
+    pub const fn new(name: &'static str, value: T) -> Self {
+        Self {
+            name,
+            inner: spin::Mutex::new(value),
+        }
+    }
+
+    /// Consume the mutex, returning its value
+    pub fn into_inner(self) -> T {
+        self.inner.into_inner()
+    }
+}
+
+impl<T: ?Sized> Mutex<T> {
+    /// Get the name of the lock
+    pub fn name(&self) -> &'static str {
+        self.name
+    }
+
+    /// Lock the mutex, spinning until it is available
+    pub fn lock(&self) -> MutexGuard<'_, T> {
+        let (inner, _probe) =
+            HoldProbe::acquire(self.name, || self.inner.try_lock(), || self.inner.lock());
+
+        MutexGuard { inner, _probe }
+    }
+
+    /// Try to lock the mutex, without waiting
+    pub fn try_lock(&self) -> Option<MutexGuard<'_, T>> {
+        let inner = self.inner.try_lock()?;
+
+        Some(MutexGuard {
+            inner,
+            _probe: HoldProbe::acquired(self.name, None),
+        })
+    }
+}
+
+impl<T: fmt::Debug> fmt::Debug for Mutex<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Mutex")
+            .field("name", &self.name)
+            .field("inner", &self.inner)
+            .finish()
+    }
+}
+
+/// Guard of a locked mutex, which unlocks it on drop
+pub struct MutexGuard<'a, T: ?Sized> {
+    // Note: dropped first, so that the hold time ends on unlock
+    inner: spin::MutexGuard<'a, T>,
+    _probe: HoldProbe,
+}
+
+impl<T: ?Sized> Deref for MutexGuard<'_, T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        &self.inner
+    }
+}
+
+impl<T: ?Sized> DerefMut for MutexGuard<'_, T> {
+    fn deref_mut(&mut self) -> &mut T {
+        &mut self.inner
+    }
+}
@@ -0,0 +1,180 @@
+//! Lock registry, recording contention statistics per named lock, and the locks currently held
+//!
+//! Only enabled with the `lock-stats` feature: without it, probes are no-ops.
+//!
+//! Note: recording allocates, so instrumented locks must not be used by the allocator.
+
+#[cfg(feature = "lock-stats")]
+use alloc::{collections::BTreeMap, vec::Vec};
+#[cfg(feature = "lock-stats")]
+use core::{
+    arch::x86_64::_rdtsc,
+    sync::atomic::{AtomicU64, Ordering},
+};
+
+#[cfg(feature = "lock-stats")]
+use crate::kobject::Thread;
+
+/// Contention statistics of a named lock
+///
+/// Times are in CPU ticks. All locks with the same name share their statistics.
+#[derive(Debug, Clone, Copy)]
+pub struct LockStats {
+    pub name: &'static str,
+    /// Number of acquisitions
+    pub acquisitions: u64,
+    /// Number of acquisitions which had to wait
+    pub contentions: u64,
+    pub total_wait_ticks: u64,
+    /// Longest wait of an acquisition
+    pub max_wait_ticks: u64,
+    pub total_hold_ticks: u64,
+    pub max_hold_ticks: u64,
+}
+
+impl LockStats {
+    #[cfg(feature = "lock-stats")]
+    const fn new(name: &'static str) -> Self {
+        Self {
+            name,
+            acquisitions: 0,
+            contentions: 0,
+            total_wait_ticks: 0,
+            max_wait_ticks: 0,
+            total_hold_ticks: 0,
+            max_hold_ticks: 0,
+        }
+    }
+}
+
+/// Lock currently held by a thread
+#[derive(Debug, Clone, Copy)]
+pub struct HeldLock {
+    pub name: &'static str,
+    /// Thread which acquired it
+    pub tid: u64,
+    /// Time since its acquisition, in CPU ticks
+    pub held_ticks: u64,
+}
+
+#[cfg(feature = "lock-stats")]
+static REGISTRY: spin::Mutex<BTreeMap<&'static str, LockStats>> = spin::Mutex::new(BTreeMap::new());
+
+/// Holds in progress, by hold id
+#[cfg(feature = "lock-stats")]
+static HELD: spin::Mutex<BTreeMap<u64, (&'static str, u64, u64)>> =
+    spin::Mutex::new(BTreeMap::new());
+
+#[cfg(feature = "lock-stats")]
+static NEXT_HOLD_ID: AtomicU64 = AtomicU64::new(1);
+
+/// Get the statistics of all the locks acquired so far, ordered by name
+#[cfg(feature = "lock-stats")]
+pub fn lock_stats() -> Vec<LockStats> {
+    REGISTRY.lock().values().copied().collect()
+}
+
+/// Get the locks currently held, oldest acquisition first
+#[cfg(feature = "lock-stats")]
+pub fn held_locks() -> Vec<HeldLock> {
+    let now = unsafe { _rdtsc() };
+
+    HELD.lock()
+        .values()
+        .map(|&(name, tid, acquired_at)| HeldLock {
+            name,
+            tid,
+            held_ticks: now - acquired_at,
+        })
+        .collect()
+}
+
+#[cfg(feature = "lock-stats")]
+fn update<F: FnOnce(&mut LockStats)>(name: &'static str, f: F) {
+    let mut registry = REGISTRY.lock();
+    f(registry.entry(name).or_insert(LockStats::new(name)));
+}
+
+/// Measure of a lock hold, from its acquisition to the drop of its guard
+pub(super) struct HoldProbe {
+    #[cfg(feature = "lock-stats")]
+    name: &'static str,
+    #[cfg(feature = "lock-stats")]
+    acquired_at: u64,
+    #[cfg(feature = "lock-stats")]
+    hold_id: u64,
+}
+
+impl HoldProbe {
+    /// Acquire a lock with `try_acquire`, falling back to `acquire` if it is contended
+    #[cfg(feature = "lock-stats")]
+    pub fn acquire<G>(
+        name: &'static str,
+        try_acquire: impl FnOnce() -> Option<G>,
+        acquire: impl FnOnce() -> G,
+    ) -> (G, Self) {
+        if let Some(guard) = try_acquire() {
+            return (guard, Self::acquired(name, None));
+        }
+
+        let begin = unsafe { _rdtsc() };
+        let guard = acquire();
+        let wait_ticks = unsafe { _rdtsc() } - begin;
+
+        (guard, Self::acquired(name, Some(wait_ticks)))
+    }
+
+    /// Acquire a lock with `acquire`
+    #[cfg(not(feature = "lock-stats"))]
+    pub fn acquire<G>(
+        name: &'static str,
+        _try_acquire: impl FnOnce() -> Option<G>,
+        acquire: impl FnOnce() -> G,
+    ) -> (G, Self) {
+        (acquire(), Self::acquired(name, None))
+    }
+
+    /// Start to measure the hold of a lock, acquired after `wait_ticks` if it was contended
+    #[cfg(feature = "lock-stats")]
+    pub fn acquired(name: &'static str, wait_ticks: Option<u64>) -> Self {
+        update(name, |stats| {
+            stats.acquisitions += 1;
+
+            if let Some(wait_ticks) = wait_ticks {
+                stats.contentions += 1;
+                stats.total_wait_ticks += wait_ticks;
+                stats.max_wait_ticks = stats.max_wait_ticks.max(wait_ticks);
+            }
+        });
+
+        let acquired_at = unsafe { _rdtsc() };
+        let hold_id = NEXT_HOLD_ID.fetch_add(1, Ordering::Relaxed);
+        HELD.lock()
+            .insert(hold_id, (name, Thread::current_tid(), acquired_at));
+
+        Self {
+            name,
+            acquired_at,
+            hold_id,
+        }
+    }
+
+    /// Start to measure the hold of a lock
+    #[cfg(not(feature = "lock-stats"))]
+    pub fn acquired(_name: &'static str, _wait_ticks: Option<u64>) -> Self {
+        Self {}
+    }
+}
+
+#[cfg(feature = "lock-stats")]
+impl Drop for HoldProbe {
+    fn drop(&mut self) {
+        let hold_ticks = unsafe { _rdtsc() } - self.acquired_at;
+        HELD.lock().remove(&self.hold_id);
+
+        update(self.name, |stats| {
+            stats.total_hold_ticks += hold_ticks;
+            stats.max_hold_ticks = stats.max_hold_ticks.max(hold_ticks);
+        });
+    }
+}
@@ -0,0 +1,98 @@
+use core::{
+    fmt,
+    ops::{Deref, DerefMut},
+};
+
+use super::registry::HoldProbe;
+
+/// Named reader-writer lock
+///
+/// With the `lock-stats` feature, its contention statistics are recorded under its name
+/// (readers and writers together).
+pub struct RwLock<T: ?Sized> {
+    name: &'static str,
+    inner: spin::RwLock<T>,
+}
+
+impl<T> RwLock<T> {
+    /// Create a new reader-writer lock
+    pub const fn new(name: &'static str, value: T) -> Self {
+        Self {
+            name,
+            inner: spin::RwLock::new(value),
+        }
+    }
+
+    /// Consume the lock, returning its value
+    pub fn into_inner(self) -> T {
+        self.inner.into_inner()
+    }
+}
+
+impl<T: ?Sized> RwLock<T> {
+    /// Get the name of the lock
+    pub fn name(&self) -> &'static str {
+        self.name
+    }
+
+    /// Lock for shared read access, spinning until it is available
+    pub fn read(&self) -> RwLockReadGuard<'_, T> {
+        let (inner, _probe) =
+            HoldProbe::acquire(self.name, || self.inner.try_read(), || self.inner.read());
+
+        RwLockReadGuard { inner, _probe }
+    }
+
+    /// Lock for exclusive write access, spinning until it is available
+    pub fn write(&self) -> RwLockWriteGuard<'_, T> {
+        let (inner, _probe) =
+            HoldProbe::acquire(self.name, || self.inner.try_write(), || self.inner.write());
+
+        RwLockWriteGuard { inner, _probe }
+    }
+}
+
+impl<T: fmt::Debug> fmt::Debug for RwLock<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("RwLock")
+            .field("name", &self.name)
+            .field("inner", &self.inner)
+            .finish()
+    }
+}
+
+/// Guard of a read-locked lock, which unlocks it on drop
+pub struct RwLockReadGuard<'a, T: ?Sized> {
+    // Note: dropped first, so that the hold time ends on unlock
+    inner: spin::RwLockReadGuard<'a, T>,
+    _probe: HoldProbe,
+}
+
+impl<T: ?Sized> Deref for RwLockReadGuard<'_, T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        &self.inner
+    }
+}
+
+/// Guard of a write-locked lock, which unlocks it on drop
+pub struct RwLockWriteGuard<'a, T: ?Sized> {
+    // Note: dropped first, so that the hold time ends on unlock
+    inner: spin::RwLockWriteGuard<'a, T>,
+    _probe: HoldProbe,
+}
+
+impl<T: ?Sized> Deref for RwLockWriteGuard<'_, T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        &self.inner
+    }
+}
+
+impl<T: ?Sized> DerefMut for RwLockWriteGuard<'_, T> {
+    fn deref_mut(&mut self) -> &mut T {
+        &mut self.inner
+    }
+}
@@ -0,0 +1,887 @@
+//! VFS protocol and client
+//!
+//! The vfs server (`servers/vfs-server`) gives a single tree of paths over the filesystem servers mounted in it (see `fs`).
+//! Clients open nodes by path and get a handle, then read and write them at explicit offsets (like `pread`/`pwrite`).
+//! Handles are private to the process which opened them (kernel-stamped sender): other processes get `Error::ObjectNotFound`.
+//! They are closed when it terminates. The server passes the opener on its requests to the filesystem servers (see `fs`).
+//!
+//! Paths are absolute, `/` separated; `.` components are ignored, `..` is not supported.
+//!
+//! Paths are passed as a key/value block in a memory object (handle 1), so is the data of `Write` requests (raw).
+//! Data read is replied in a memory object (handle 0).
+//!
+//! Read-ahead: the server detects sequential reads on each handle (a read starting where the previous one ended),
+//! and prefetches the next blocks from the filesystem server into its cache without blocking the reader.
+//! The window doubles on each sequential read, up to the maximum of the mount (`MountOptions::read_ahead`), and is reset on seek.
+//!
+//! `File::copy_range` copies between two files without going through the client: done by the filesystem server
+//! if both are on the same filesystem, else by vfs-server with a read and a write.
+//!
+//! Trash: on mounts with the `trash_expiry` option, `remove` moves the node into a hidden `.trash` directory
+//! at the root of the mount, from which it can be restored or purged until it expires.
+//! Open handles on a node in the trash keep working. The `.trash` name is reserved at the root of all the mounts.
+//!
+//! Batches: `Vfs::batch` applies a sequence of create, write and rename operations on a single mount.
+//! The server processes no other request meanwhile, and undoes the operations applied if one fails,
+//! so clients see either all of them or none (eg: a config written to a temporary file then renamed over the old one).
+//!
+//! Reconnection: if the port of the server is closed (eg: the server restarted), the client reopens it by name with a backoff
+//! (`RECONNECT_BACKOFF`) and sends the request again. Handles opened on the previous server are lost.
+//!
+//! Node timestamps are maintained by the filesystem servers (see `fs`). Reads served from the cache of vfs-server
+//! do not reach them, so do not update the access time.
+
+use core::{mem, ops::BitOr, time::Duration};
+
+use alloc::{string::String, vec, vec::Vec};
+use log::debug;
+use spin::Mutex;
+
+use crate::failure;
+use crate::fs::{copy_from_object, copy_to_object, MAX_IO_SIZE};
+use crate::kobject::{Error, Handle, MemoryObject, Message, Port, PortReceiver, PortSender};
+use crate::kvblock::{KVBlock, KVBlockBuilder, Value};
+use crate::retry::Backoff;
+
+pub use crate::fs::{DirEntry, NodeInfo, NodeKind, Reply, TimeSpec};
+
+/// Name of the port of the server
+pub const SERVER_PORT_NAME: &str = "vfs-server";
+
+/// Size of the blocks of the server cache, in bytes
+pub const BLOCK_SIZE: usize = 4096;
+
+/// Backoff of the client waiting for the server to come back when its port is closed
+pub const RECONNECT_BACKOFF: Backoff =
+    Backoff::new(Duration::from_millis(10), Duration::from_millis(500))
+        .timeout(Duration::from_secs(5));
+
+/// Name of the trash directory, at the root of the mounts
+pub const TRASH_DIR: &str = ".trash";
+
+/// Key of the entries in the block replied to `ListTrash`: each value is the block of an entry (bytes)
+pub const TRASH_ENTRY_KEY: &str = "entry";
+
+/// Key of the operations in the block of a `Batch` request: each value is the block of an operation (bytes)
+pub const BATCH_OPERATION_KEY: &str = "operation";
+
+/// Type of the requests to the server
+#[repr(u64)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RequestType {
+    /// Mount a filesystem server on a path (reserved to the spawner of the server)
+    Mount = 1,
+    /// Unmount the filesystem of a path (reserved to the spawner of the server)
+    Unmount,
+    /// Open a node by path, get a handle on it
+    Open,
+    /// Close a handle
+    Close,
+    /// Read data from an open file
+    Read,
+    /// Write data into an open file
+    Write,
+    /// Get the description of a node by path
+    Stat,
+    /// Get the description of an open node
+    HandleStat,
+    /// Set the size of an open file
+    Truncate,
+    /// Create a directory
+    Mkdir,
+    /// Remove a file or an empty directory
+    Remove,
+    /// Move a node, within a filesystem
+    Rename,
+    /// List the entries of a directory
+    List,
+    /// Get the cache statistics of the server
+    Statistics,
+    /// Copy data between two open files
+    CopyRange,
+    /// Set the access and modification times of an open node
+    SetTimes,
+    /// List the nodes in the trash of the mounts
+    ListTrash,
+    /// Move a node from the trash back to its path
+    Restore,
+    /// Remove nodes from the trash for good
+    Purge,
+    /// Apply a sequence of operations on a single mount, all of them or none
+    Batch,
+}
+
+impl TryFrom<u64> for RequestType {
+    type Error = Error;
+
+    fn try_from(value: u64) -> Result<Self, Self::Error> {
+        match value {
+            1 => Ok(Self::Mount),
+            2 => Ok(Self::Unmount),
+            3 => Ok(Self::Open),
+            4 => Ok(Self::Close),
+            5 => Ok(Self::Read),
+            6 => Ok(Self::Write),
+            7 => Ok(Self::Stat),
+            8 => Ok(Self::HandleStat),
+            9 => Ok(Self::Truncate),
+            10 => Ok(Self::Mkdir),
+            11 => Ok(Self::Remove),
+            12 => Ok(Self::Rename),
+            13 => Ok(Self::List),
+            14 => Ok(Self::Statistics),
+            15 => Ok(Self::CopyRange),
+            16 => Ok(Self::SetTimes),
+            17 => Ok(Self::ListTrash),
+            18 => Ok(Self::Restore),
+            19 => Ok(Self::Purge),
+            20 => Ok(Self::Batch),
+            _ => Err(Error::InvalidArgument),
+        }
+    }
+}
+
+/// Options of `Open`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct OpenFlags(u64);
+
+impl OpenFlags {
+    pub const NONE: Self = Self(0);
+    /// Create the file if it does not exist
+    pub const CREATE: Self = Self(1 << 0);
+    /// With `CREATE`: fail with `Error::ObjectNameDuplicate` if the file exists
+    pub const EXCLUSIVE: Self = Self(1 << 1);
+    /// Empty the file
+    pub const TRUNCATE: Self = Self(1 << 2);
+
+    pub const fn from_bits(bits: u64) -> Self {
+        Self(bits)
+    }
+
+    pub const fn bits(&self) -> u64 {
+        self.0
+    }
+
+    pub const fn contains(&self, other: Self) -> bool {
+        (self.0 & other.0) == other.0
+    }
+}
+
+impl BitOr for OpenFlags {
+    type Output = Self;
+
+    fn bitor(self, rhs: Self) -> Self {
+        Self(self.0 | rhs.0)
+    }
+}
+
+/// Options of a mount
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MountOptions {
+    /// Maximum read-ahead window, in blocks (0: read-ahead disabled)
+    pub read_ahead: usize,
+    /// Time removed nodes stay in the trash (`None`: no trash, nodes are removed right away)
+    pub trash_expiry: Option<Duration>,
+}
+
+impl Default for MountOptions {
+    fn default() -> Self {
+        Self {
+            read_ahead: 32,
+            trash_expiry: None,
+        }
+    }
+}
+
+/// Node in the trash
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TrashEntry {
+    pub id: u64,
+    /// Path of the node before it was removed
+    pub path: String,
+    pub kind: NodeKind,
+    /// Wall clock when the node was removed, in nanoseconds since the Unix epoch
+    pub deleted: u64,
+}
+
+impl TrashEntry {
+    /// Encode the entry as a key/value block
+    pub fn to_block(&self) -> Vec<u8> {
+        let mut builder = KVBlockBuilder::new();
+        builder
+            .push("id", Value::U64(self.id))
+            .push_str("path", &self.path)
+            .push("kind", Value::U64(self.kind as u64))
+            .push("deleted", Value::U64(self.deleted));
+        builder.build()
+    }
+
+    /// Decode an entry
+    pub fn from_block(block: &KVBlock) -> Result<Self, Error> {
+        let u64_field = |key| block.get(key).and_then(|value| value.as_u64()).unwrap_or(0);
+
+        Ok(Self {
+            id: u64_field("id"),
+            path: String::from(
+                block
+                    .get("path")
+                    .and_then(|value| value.as_str())
+                    .unwrap_or(""),
+            ),
+            kind: NodeKind::try_from(u64_field("kind"))?,
+            deleted: u64_field("deleted"),
+        })
+    }
+}
+
+/// Cache statistics of the server
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Statistics {
+    /// Reads served from the cache
+    pub hits: u64,
+    /// Reads sent to the filesystem servers
+    pub misses: u64,
+    /// Blocks prefetched by read-ahead
+    pub prefetched: u64,
+    /// Blocks in the cache
+    pub cached: u64,
+}
+
+impl Statistics {
+    /// Encode the statistics as a key/value block
+    pub fn to_block(&self) -> Vec<u8> {
+        let mut builder = KVBlockBuilder::new();
+        builder
+            .push("hits", Value::U64(self.hits))
+            .push("misses", Value::U64(self.misses))
+            .push("prefetched", Value::U64(self.prefetched))
+            .push("cached", Value::U64(self.cached));
+        builder.build()
+    }
+
+    /// Decode the statistics, missing fields are left to 0
+    pub fn from_block(block: &KVBlock) -> Self {
+        let field = |key| block.get(key).and_then(|value| value.as_u64()).unwrap_or(0);
+
+        Self {
+            hits: field("hits"),
+            misses: field("misses"),
+            prefetched: field("prefetched"),
+            cached: field("cached"),
+        }
+    }
+}
+
+/// Request to the server
+///
+/// Handle 0 is the port to send the reply to.
+/// Handle 1 is the arguments block (paths, mount options) or the data of `Write`, of `buffer_size` bytes.
+#[derive(Debug, Clone, Copy)]
+#[repr(C)]
+pub struct Request {
+    pub r#type: u64,
+    /// Open handle the request is about (the source for `CopyRange`), trash entry for `Restore` and `Purge` (0: all for `Purge`)
+    pub handle: u64,
+    /// Used by `Read`, `Write` and `CopyRange`, and by `SetTimes` (access time, see `TimeSpec::to_raw`)
+    pub offset: u64,
+    /// Used by `Read` and `CopyRange` (size to read), `Truncate` (new size) and `SetTimes` (modification time)
+    pub size: u64,
+    /// Used by `Open` (see `OpenFlags`)
+    pub flags: u64,
+    /// Size of the content of handle 1, in bytes
+    pub buffer_size: u64,
+    /// Used by `CopyRange`: destination handle
+    pub other_handle: u64,
+    /// Used by `CopyRange`: offset in the destination file
+    pub other_offset: u64,
+}
+
+impl Request {
+    pub const fn new(r#type: RequestType) -> Self {
+        Self {
+            r#type: r#type as u64,
+            handle: 0,
+            offset: 0,
+            size: 0,
+            flags: 0,
+            buffer_size: 0,
+            other_handle: 0,
+            other_offset: 0,
+        }
+    }
+}
+
+/// Operation of a batch
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum BatchOperation {
+    /// Create a node, fails with `Error::ObjectNameDuplicate` if the path exists
+    Create { path: String, kind: NodeKind },
+    /// Write data to a file
+    Write {
+        path: String,
+        offset: u64,
+        data: Vec<u8>,
+    },
+    /// Move a node, an existing file at the destination is replaced
+    Rename { from: String, to: String },
+}
+
+impl BatchOperation {
+    const CREATE: u64 = 1;
+    const WRITE: u64 = 2;
+    const RENAME: u64 = 3;
+
+    /// Paths the operation is about
+    pub fn paths(&self) -> Vec<&str> {
+        match self {
+            Self::Create { path, .. } | Self::Write { path, .. } => vec![path.as_str()],
+            Self::Rename { from, to } => vec![from.as_str(), to.as_str()],
+        }
+    }
+
+    /// Encode the operation as a key/value block
+    pub fn to_block(&self) -> Vec<u8> {
+        let mut builder = KVBlockBuilder::new();
+        match self {
+            Self::Create { path, kind } => {
+                builder
+                    .push("type", Value::U64(Self::CREATE))
+                    .push_str("path", path)
+                    .push("kind", Value::U64(*kind as u64));
+            }
+            Self::Write { path, offset, data } => {
+                builder
+                    .push("type", Value::U64(Self::WRITE))
+                    .push_str("path", path)
+                    .push("offset", Value::U64(*offset))
+                    .push("data", Value::Bytes(data));
+            }
+            Self::Rename { from, to } => {
+                builder
+                    .push("type", Value::U64(Self::RENAME))
+                    .push_str("path", from)
+                    .push_str("to", to);
+            }
+        }
+        builder.build()
+    }
+
+    /// Decode an operation
+    pub fn from_block(block: &KVBlock) -> Result<Self, Error> {
+        let string = |key| match block.get(key) {
+            Some(Value::Str(value)) => Ok(String::from(value)),
+            _ => Err(Error::InvalidArgument),
+        };
+        let u64_field = |key| {
+            block
+                .get(key)
+                .and_then(|value| value.as_u64())
+                .ok_or(Error::InvalidArgument)
+        };
+
+        match u64_field("type")? {
+            Self::CREATE => Ok(Self::Create {
+                path: string("path")?,
+                kind: NodeKind::try_from(u64_field("kind")?)?,
+            }),
+            Self::WRITE => Ok(Self::Write {
+                path: string("path")?,
+                offset: u64_field("offset")?,
+                data: Vec::from(
+                    block
+                        .get("data")
+                        .and_then(|value| value.as_bytes())
+                        .ok_or(Error::InvalidArgument)?,
+                ),
+            }),
+            Self::RENAME => Ok(Self::Rename {
+                from: string("path")?,
+                to: string("to")?,
+            }),
+            _ => Err(Error::InvalidArgument),
+        }
+    }
+}
+
+/// Arguments of a request, passed as a key/value block
+#[derive(Debug, Clone, Default)]
+pub struct Arguments {
+    pub path: Option<String>,
+    /// Destination path of `Rename`
+    pub to: Option<String>,
+    /// Port name of the filesystem server of `Mount`
+    pub fs: Option<String>,
+    pub options: Option<MountOptions>,
+}
+
+impl Arguments {
+    /// Encode the arguments as a key/value block
+    pub fn to_block(&self) -> Vec<u8> {
+        let mut builder = KVBlockBuilder::new();
+        if let Some(path) = &self.path {
+            builder.push_str("path", path);
+        }
+        if let Some(to) = &self.to {
+            builder.push_str("to", to);
+        }
+        if let Some(fs) = &self.fs {
+            builder.push_str("fs", fs);
+        }
+        if let Some(options) = &self.options {
+            builder.push("read_ahead", Value::U64(options.read_ahead as u64));
+            if let Some(expiry) = options.trash_expiry {
+                builder.push("trash_expiry", Value::U64(expiry.as_millis() as u64));
+            }
+        }
+        builder.build()
+    }
+
+    /// Decode the arguments
+    pub fn from_block(block: &KVBlock) -> Result<Self, Error> {
+        let string = |key| match block.get(key) {
+            Some(Value::Str(value)) => Ok(Some(String::from(value))),
+            Some(_) => Err(Error::InvalidArgument),
+            None => Ok(None),
+        };
+
+        let options = block.get("read_ahead").map(|value| MountOptions {
+            read_ahead: value.as_u64().unwrap_or(0) as usize,
+            trash_expiry: block
+                .get("trash_expiry")
+                .and_then(|value| value.as_u64())
+                .map(Duration::from_millis),
+        });
+
+        Ok(Self {
+            path: string("path")?,
+            to: string("to")?,
+            fs: string("fs")?,
+            options,
+        })
+    }
+
+    /// Get the path, fails with `Error::InvalidArgument` if it is missing
+    pub fn path(&self) -> Result<&str, Error> {
+        self.path.as_deref().ok_or(Error::InvalidArgument)
+    }
+
+    fn with_path(path: &str) -> Self {
+        Self {
+            path: Some(String::from(path)),
+            ..Default::default()
+        }
+    }
+}
+
+/// Server side: get the arguments of a request from its block (handle 1)
+pub fn request_arguments(request: &Request, message: &mut Message) -> Result<Arguments, Error> {
+    let object =
+        MemoryObject::from_handle(message.take_handle(1)).map_err(|_| Error::InvalidArgument)?;
+    let data = copy_from_object(&object, request.buffer_size as usize)?;
+    let block = KVBlock::parse(&data).map_err(|_| Error::InvalidArgument)?;
+
+    Arguments::from_block(&block)
+}
+
+/// Server side: get the operations of a `Batch` request from its block (handle 1)
+///
+/// Fails with `Error::InvalidArgument` if the block is larger than `MAX_IO_SIZE`.
+pub fn request_batch(
+    request: &Request,
+    message: &mut Message,
+) -> Result<Vec<BatchOperation>, Error> {
+    if request.buffer_size as usize > MAX_IO_SIZE {
+        return Err(Error::InvalidArgument);
+    }
+
+    let object =
+        MemoryObject::from_handle(message.take_handle(1)).map_err(|_| Error::InvalidArgument)?;
+    let data = copy_from_object(&object, request.buffer_size as usize)?;
+    let block = KVBlock::parse(&data).map_err(|_| Error::InvalidArgument)?;
+
+    block
+        .iter()
+        .filter(|entry| entry.key == BATCH_OPERATION_KEY)
+        .map(|entry| {
+            let operation = entry.value.as_bytes().ok_or(Error::InvalidArgument)?;
+            let operation = KVBlock::parse(operation).map_err(|_| Error::InvalidArgument)?;
+            BatchOperation::from_block(&operation)
+        })
+        .collect()
+}
+
+/// Connection to the vfs server
+#[derive(Debug)]
+pub struct Vfs {
+    /// Reopened if the server restarts
+    server: Mutex<PortSender>,
+    port_name: String,
+    reply_receiver: PortReceiver,
+    reply_sender: PortSender,
+}
+
+impl Vfs {
+    /// Connect to the server
+    pub fn connect() -> Result<Self, Error> {
+        Self::connect_to(SERVER_PORT_NAME)
+    }
+
+    /// Connect to the server, waiting for it to come up if needed
+    pub fn wait_connect() -> Result<Self, Error> {
+        Self::from_port(SERVER_PORT_NAME, Port::wait_open(SERVER_PORT_NAME)?)
+    }
+
+    /// Connect to a server listening on another port (eg: a private instance in a test)
+    pub fn connect_to(port_name: &str) -> Result<Self, Error> {
+        Self::from_port(port_name, Port::open(port_name)?)
+    }
+
+    fn from_port(port_name: &str, server: PortSender) -> Result<Self, Error> {
+        let (reply_receiver, reply_sender) = Port::create(None)?;
+
+        Ok(Self {
+            server: Mutex::new(server),
+            port_name: String::from(port_name),
+            reply_receiver,
+            reply_sender,
+        })
+    }
+
+    /// Mount the filesystem server listening on `fs_port_name` on `path`
+    ///
+    /// The server attaches a new instance of the filesystem. Mount points do not need to exist in the parent filesystem.
+    ///
+    /// Note: only the process which spawned the server (init) can mount filesystems, others get `Error::NotSupported`
+    pub fn mount(
+        &self,
+        path: &str,
+        fs_port_name: &str,
+        options: MountOptions,
+    ) -> Result<(), Error> {
+        let arguments = Arguments {
+            path: Some(String::from(path)),
+            fs: Some(String::from(fs_port_name)),
+            options: Some(options),
+            ..Default::default()
+        };
+
+        self.call_with_arguments(Request::new(RequestType::Mount), &arguments)?;
+        Ok(())
+    }
+
+    /// Unmount the filesystem mounted on `path`, and detach its instance
+    ///
+    /// Fails with `Error::ObjectNotReady` while handles are open on it.
+    ///
+    /// Note: only the process which spawned the server (init) can unmount filesystems, others get `Error::NotSupported`
+    pub fn unmount(&self, path: &str) -> Result<(), Error> {
+        self.call_with_arguments(
+            Request::new(RequestType::Unmount),
+            &Arguments::with_path(path),
+        )?;
+        Ok(())
+    }
+
+    /// Open a node by path
+    pub fn open(&self, path: &str, flags: OpenFlags) -> Result<File<'_>, Error> {
+        let mut request = Request::new(RequestType::Open);
+        request.flags = flags.bits();
+
+        let (handle, info, _) = self.call_with_arguments(request, &Arguments::with_path(path))?;
+
+        Ok(File {
+            vfs: self,
+            handle: handle as u64,
+            info,
+        })
+    }
+
+    /// Get the description of a node by path
+    pub fn stat(&self, path: &str) -> Result<NodeInfo, Error> {
+        let (_, info, _) =
+            self.call_with_arguments(Request::new(RequestType::Stat), &Arguments::with_path(path))?;
+        Ok(info)
+    }
+
+    /// Create a directory
+    pub fn mkdir(&self, path: &str) -> Result<NodeInfo, Error> {
+        let (_, info, _) = self.call_with_arguments(
+            Request::new(RequestType::Mkdir),
+            &Arguments::with_path(path),
+        )?;
+        Ok(info)
+    }
+
+    /// Remove a file or an empty directory
+    ///
+    /// On a mount with a trash, the node is moved into it. Else open handles on the node fail with `Error::ObjectNotFound` afterwards.
+    pub fn remove(&self, path: &str) -> Result<(), Error> {
+        self.call_with_arguments(
+            Request::new(RequestType::Remove),
+            &Arguments::with_path(path),
+        )?;
+        Ok(())
+    }
+
+    /// Move a node, within a filesystem (fails with `Error::InvalidArgument` across mounts)
+    ///
+    /// An existing file at the destination is replaced.
+    pub fn rename(&self, from: &str, to: &str) -> Result<(), Error> {
+        let arguments = Arguments {
+            path: Some(String::from(from)),
+            to: Some(String::from(to)),
+            ..Default::default()
+        };
+
+        self.call_with_arguments(Request::new(RequestType::Rename), &arguments)?;
+        Ok(())
+    }
+
+    /// List the entries of a directory
+    pub fn list(&self, path: &str) -> Result<Vec<DirEntry>, Error> {
+        let (size, _, mut reply) =
+            self.call_with_arguments(Request::new(RequestType::List), &Arguments::with_path(path))?;
+
+        let data = reply_data(&mut reply, size)?;
+        crate::fs::decode_entries(&data)
+    }
+
+    /// List the nodes in the trash of all the mounts
+    pub fn list_trash(&self) -> Result<Vec<TrashEntry>, Error> {
+        let (size, _, mut reply) = self.call(Request::new(RequestType::ListTrash), None)?;
+
+        let data = reply_data(&mut reply, size)?;
+        if data.is_empty() {
+            return Ok(Vec::new());
+        }
+
+        let block = KVBlock::parse(&data).map_err(|_| Error::InvalidArgument)?;
+        let mut entries = Vec::with_capacity(block.len());
+        for entry in block.iter().filter(|entry| entry.key == TRASH_ENTRY_KEY) {
+            let entry = entry.value.as_bytes().ok_or(Error::InvalidArgument)?;
+            let entry = KVBlock::parse(entry).map_err(|_| Error::InvalidArgument)?;
+            entries.push(TrashEntry::from_block(&entry)?);
+        }
+
+        Ok(entries)
+    }
+
+    /// Move a node from the trash back to its path
+    ///
+    /// Fails with `Error::ObjectNameDuplicate` if the path exists again, and with `Error::ObjectNotFound` if its directory does not.
+    pub fn restore(&self, id: u64) -> Result<NodeInfo, Error> {
+        let mut request = Request::new(RequestType::Restore);
+        request.handle = id;
+
+        let (_, info, _) = self.call(request, None)?;
+        Ok(info)
+    }
+
+    /// Remove a node from the trash for good (`None`: all of them)
+    pub fn purge(&self, id: Option<u64>) -> Result<(), Error> {
+        let mut request = Request::new(RequestType::Purge);
+        request.handle = id.unwrap_or(0);
+
+        self.call(request, None)?;
+        Ok(())
+    }
+
+    /// Apply operations on a single mount, all of them or none
+    ///
+    /// Fails with `Error::InvalidArgument` if the operations are on several mounts, or if their encoding is larger than `MAX_IO_SIZE`.
+    /// Else fails with the error of the first operation which fails, after the previous ones have been undone.
+    pub fn batch(&self, operations: &[BatchOperation]) -> Result<(), Error> {
+        let mut builder = KVBlockBuilder::new();
+        for operation in operations {
+            builder.push(BATCH_OPERATION_KEY, Value::Bytes(&operation.to_block()));
+        }
+        let block = builder.build();
+
+        if block.len() > MAX_IO_SIZE {
+            return Err(Error::InvalidArgument);
+        }
+
+        let mut request = Request::new(RequestType::Batch);
+        request.buffer_size = block.len() as u64;
+        self.call(request, Some(copy_to_object(&block)?.into_handle()))?;
+        Ok(())
+    }
+
+    /// Get the cache statistics of the server
+    pub fn statistics(&self) -> Result<Statistics, Error> {
+        let (size, _, mut reply) = self.call(Request::new(RequestType::Statistics), None)?;
+
+        let data = reply_data(&mut reply, size)?;
+        let block = KVBlock::parse(&data).map_err(|_| Error::InvalidArgument)?;
+        Ok(Statistics::from_block(&block))
+    }
+
+    fn call_with_arguments(
+        &self,
+        mut request: Request,
+        arguments: &Arguments,
+    ) -> Result<(usize, NodeInfo, Message), Error> {
+        let block = arguments.to_block();
+        request.buffer_size = block.len() as u64;
+        self.call(request, Some(copy_to_object(&block)?.into_handle()))
+    }
+
+    fn call(
+        &self,
+        request: Request,
+        object: Option<Handle>,
+    ) -> Result<(usize, NodeInfo, Message), Error> {
+        let mut handles = [
+            self.reply_sender.clone().into_handle(),
+            object.unwrap_or_else(Handle::invalid),
+        ];
+
+        let mut message = unsafe { Message::new(&request, &mut handles) };
+        self.send(&mut message)?;
+
+        let reply = self.reply_receiver.blocking_receive()?;
+        failure::check_reply(&reply)?;
+        let (value, info) = unsafe { reply.data::<Reply>() }.result()?;
+        Ok((value, info, reply))
+    }
+
+    /// Send a message to the server, reconnecting first if its port is closed
+    fn send(&self, message: &mut Message) -> Result<(), Error> {
+        let mut server = self.server.lock();
+
+        match server.send(message) {
+            // Not delivered: the message still has its handles
+            Err(Error::ObjectClosed) => {
+                debug!("Port '{}' closed, reconnecting", self.port_name);
+                *server = Port::open_with_backoff(&self.port_name, &RECONNECT_BACKOFF)?;
+                server.send(message)
+            }
+            result => result,
+        }
+    }
+}
+
+/// Get the data replied in a memory object (handle 0)
+fn reply_data(reply: &mut Message, size: usize) -> Result<Vec<u8>, Error> {
+    if size == 0 {
+        return Ok(Vec::new());
+    }
+
+    let object =
+        MemoryObject::from_handle(reply.take_handle(0)).map_err(|_| Error::InvalidArgument)?;
+    copy_from_object(&object, size)
+}
+
+/// Open node, closed on drop
+#[derive(Debug)]
+pub struct File<'a> {
+    vfs: &'a Vfs,
+    handle: u64,
+    info: NodeInfo,
+}
+
+impl File<'_> {
+    /// Get the handle of the node in the server
+    pub fn handle(&self) -> u64 {
+        self.handle
+    }
+
+    /// Get the description of the node when it was opened
+    pub fn info(&self) -> &NodeInfo {
+        &self.info
+    }
+
+    /// Read data at `offset` into `buffer`, returns the size read (less than the buffer size at the end of the file)
+    pub fn read_at(&self, offset: u64, buffer: &mut [u8]) -> Result<usize, Error> {
+        let mut request = self.request(RequestType::Read);
+        request.offset = offset;
+        request.size = buffer.len().min(MAX_IO_SIZE) as u64;
+
+        let (size, _, mut reply) = self.vfs.call(request, None)?;
+        let data = reply_data(&mut reply, size)?;
+        if data.len() > buffer.len() {
+            return Err(Error::InvalidArgument);
+        }
+
+        buffer[..data.len()].copy_from_slice(&data);
+        Ok(data.len())
+    }
+
+    /// Write data at `offset`, returns the size written
+    pub fn write_at(&self, offset: u64, data: &[u8]) -> Result<usize, Error> {
+        let data = &data[..data.len().min(MAX_IO_SIZE)];
+
+        let mut request = self.request(RequestType::Write);
+        request.offset = offset;
+        request.buffer_size = data.len() as u64;
+
+        let (written, _, _) = self
+            .vfs
+            .call(request, Some(copy_to_object(data)?.into_handle()))?;
+        Ok(written)
+    }
+
+    /// Get the current description of the node
+    pub fn stat(&self) -> Result<NodeInfo, Error> {
+        let (_, info, _) = self.vfs.call(self.request(RequestType::HandleStat), None)?;
+        Ok(info)
+    }
+
+    /// Copy data from this file at `offset` into `dest` at `dest_offset`, returns the size copied (like `copy_file_range`)
+    ///
+    /// Copies less than `size` bytes at the end of this file, or at most `fs::MAX_IO_SIZE`: loop for larger copies.
+    /// Within a filesystem, the data is copied by its server and never goes through the client.
+    pub fn copy_range(
+        &self,
+        offset: u64,
+        dest: &File,
+        dest_offset: u64,
+        size: usize,
+    ) -> Result<usize, Error> {
+        let mut request = self.request(RequestType::CopyRange);
+        request.offset = offset;
+        request.size = size.min(MAX_IO_SIZE) as u64;
+        request.other_handle = dest.handle;
+        request.other_offset = dest_offset;
+
+        let (copied, _, _) = self.vfs.call(request, None)?;
+        Ok(copied)
+    }
+
+    /// Set the access and modification times of the node (like `futimens`), returns its new description
+    ///
+    /// The change time is set to now.
+    pub fn set_times(&self, atime: TimeSpec, mtime: TimeSpec) -> Result<NodeInfo, Error> {
+        let mut request = self.request(RequestType::SetTimes);
+        request.offset = atime.to_raw();
+        request.size = mtime.to_raw();
+
+        let (_, info, _) = self.vfs.call(request, None)?;
+        Ok(info)
+    }
+
+    /// Set the size of the file: it is extended with zeroes or shortened
+    pub fn truncate(&self, size: u64) -> Result<(), Error> {
+        let mut request = self.request(RequestType::Truncate);
+        request.size = size;
+        self.vfs.call(request, None)?;
+        Ok(())
+    }
+
+    fn request(&self, r#type: RequestType) -> Request {
+        let mut request = Request::new(r#type);
+        request.handle = self.handle;
+        request
+    }
+}
+
+impl Drop for File<'_> {
+    fn drop(&mut self) {
+        // Nothing to do on failure: the handle is already gone
+        let _ = self.vfs.call(self.request(RequestType::Close), None);
+    }
+}
+
+// Make sure the protocol fits in messages
+const _: () = assert!(mem::size_of::<Request>() <= Message::DATA_SIZE);
+const _: () = assert!(mem::size_of::<Reply>() <= Message::DATA_SIZE);
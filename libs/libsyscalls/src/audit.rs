@@ -0,0 +1,55 @@
+use syscalls::{AuditEventType, AuditRecord, AuditRule, SyscallNumber};
+
+use super::{syscalls::*, sysret_to_result, SyscallResult};
+
+/// Replace the audit rules (at most `AuditRule::MAX_COUNT`, privileged threads only)
+pub fn set_rules(rules: &[AuditRule]) -> SyscallResult<()> {
+    let ret = unsafe {
+        syscall2(
+            SyscallNumber::AuditSetRules,
+            rules.as_ptr() as usize,
+            rules.len(),
+        )
+    };
+
+    sysret_to_result(ret)
+}
+
+/// Read the audit records from sequence number `cursor`, and move it after the records read
+///
+/// If the records at `cursor` have been dropped, reading starts from the oldest record available.
+/// Privileged threads only.
+pub fn read<'a>(
+    cursor: &mut u64,
+    array: &'a mut [AuditRecord],
+) -> SyscallResult<&'a [AuditRecord]> {
+    let mut count = array.len();
+
+    let ret = unsafe {
+        syscall3(
+            SyscallNumber::AuditRead,
+            cursor as *mut u64 as usize,
+            array.as_mut_ptr() as usize,
+            &mut count as *mut usize as usize,
+        )
+    };
+
+    sysret_to_result(ret)?;
+
+    Ok(&array[..count])
+}
+
+/// Submit the record of a server operation (the kernel fills the process and thread)
+pub fn submit(r#type: AuditEventType, object: u64, name: &str) -> SyscallResult<()> {
+    let ret = unsafe {
+        syscall4(
+            SyscallNumber::AuditSubmit,
+            r#type as usize,
+            object as usize,
+            name.as_ptr() as usize,
+            name.len(),
+        )
+    };
+
+    sysret_to_result(ret)
+}
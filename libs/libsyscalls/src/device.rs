@@ -0,0 +1,30 @@
+use syscalls::SyscallNumber;
+
+use super::{syscalls::*, sysret_to_result, DeviceInfo, SyscallList, SyscallResult};
+
+/// Get list of devices discovered by the kernel
+pub fn list(array: &mut [DeviceInfo]) -> SyscallResult<(&[DeviceInfo], usize)> {
+    let mut list = unsafe { SyscallList::new(array) };
+
+    let ret = unsafe {
+        syscall2(
+            SyscallNumber::DeviceList,
+            list.array_ptr_arg(),
+            list.count_ptr_arg(),
+        )
+    };
+
+    sysret_to_result(ret)?;
+
+    Ok(list.finalize())
+}
+
+/// Claim the PCI device at `address` to drive it
+///
+/// Its io-ports are granted to the process, and bus mastering is enabled.
+/// Note: the process must be privileged or have the `SandboxFlags::DEVICE` sandbox right
+pub fn claim(address: u64) -> SyscallResult<()> {
+    let ret = unsafe { syscall1(SyscallNumber::DeviceClaim, address as usize) };
+
+    sysret_to_result(ret)
+}
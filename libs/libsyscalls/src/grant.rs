@@ -0,0 +1,63 @@
+use syscalls::SyscallNumber;
+
+use super::{
+    syscalls::*, sysret_to_result, GrantHandle, Permissions, ProcessHandle, SyscallResult,
+};
+
+/// Create a grant on a region of the current process
+///
+/// The region must be part of one mapping of a memory object, and `perms` must be allowed by the mapping.
+pub fn create(addr: usize, size: usize, perms: Permissions) -> SyscallResult<GrantHandle> {
+    let mut new_handle = GrantHandle::invalid();
+    let ret = unsafe {
+        syscall4(
+            SyscallNumber::GrantCreate,
+            addr,
+            size,
+            perms.bits() as usize,
+            new_handle.as_syscall_ptr(),
+        )
+    };
+
+    sysret_to_result(ret)?;
+
+    Ok(new_handle)
+}
+
+/// Map the granted region into the process
+///
+/// Returns the address and the size of the mapping
+pub fn map(
+    process: &ProcessHandle,
+    grant: &GrantHandle,
+    addr: Option<usize>,
+    perms: Permissions,
+) -> SyscallResult<(usize, usize)> {
+    let mut addr = addr.unwrap_or(0);
+    let mut size: usize = 0;
+
+    // Note: addr is modified in-place
+    let addr_ptr = &mut addr as *mut _;
+    let size_ptr = &mut size as *mut _;
+    let ret = unsafe {
+        syscall5(
+            SyscallNumber::GrantMap,
+            process.as_syscall_value(),
+            grant.as_syscall_value(),
+            addr_ptr as usize,
+            perms.bits() as usize,
+            size_ptr as usize,
+        )
+    };
+
+    sysret_to_result(ret)?;
+
+    Ok((addr, size))
+}
+
+/// Revoke the grant: unmap it from all processes, and forbid new mappings (owner process only)
+pub fn revoke(grant: &GrantHandle) -> SyscallResult<()> {
+    let ret = unsafe { syscall1(SyscallNumber::GrantRevoke, grant.as_syscall_value()) };
+
+    sysret_to_result(ret)
+}
@@ -57,6 +57,165 @@ impl Clone for Handle {
     }
 }
 
+/// Handle to a known kernel object type
+///
+/// Typed handles prevent passing a handle of one type to a syscall expecting another one (eg: a thread handle to `mmap`).
+pub trait TypedHandle: Sized {
+    /// Type of the kernel object
+    const TYPE: HandleType;
+
+    /// Get the untyped handle
+    fn as_handle(&self) -> &Handle;
+
+    /// Get the untyped handle, consuming the typed one
+    fn into_handle(self) -> Handle;
+
+    /// Build a typed handle without checking its type
+    ///
+    /// # Safety
+    ///
+    /// The handle must be invalid or point to an object of type `TYPE`.
+    unsafe fn from_handle_unchecked(handle: Handle) -> Self;
+
+    /// Build a typed handle, checking its type with the kernel
+    ///
+    /// On type mismatch, the handle is given back.
+    fn from_handle(handle: Handle) -> Result<Self, Handle> {
+        if handle.r#type() == Self::TYPE {
+            Ok(unsafe { Self::from_handle_unchecked(handle) })
+        } else {
+            Err(handle)
+        }
+    }
+}
+
+/// Handle to a port, on either side
+pub trait PortHandle: TypedHandle {}
+
+macro_rules! typed_handle {
+    ($(#[$meta:meta])* $name:ident, $type:ident) => {
+        $(#[$meta])*
+        #[derive(Debug, Clone)]
+        #[repr(transparent)]
+        pub struct $name(Handle);
+
+        impl $name {
+            /// Construct a new invalid handle
+            pub const fn invalid() -> Self {
+                Self(Handle::invalid())
+            }
+
+            /// Indicate is the handle is valid
+            pub const fn valid(&self) -> bool {
+                self.0.valid()
+            }
+
+            /// Reserved for syscalls implementations
+            ///
+            /// # Safety
+            ///
+            /// The pointer must only be given to a syscall which writes a handle of this type.
+            pub unsafe fn as_syscall_ptr(&mut self) -> usize {
+                self.0.as_syscall_ptr()
+            }
+
+            /// Reserved for syscalls implementations
+            ///
+            /// # Safety
+            ///
+            /// The value must only be given to a syscall which expects a handle of this type.
+            pub const unsafe fn as_syscall_value(&self) -> usize {
+                self.0.as_syscall_value()
+            }
+        }
+
+        impl TypedHandle for $name {
+            const TYPE: HandleType = HandleType::$type;
+
+            fn as_handle(&self) -> &Handle {
+                &self.0
+            }
+
+            fn into_handle(self) -> Handle {
+                self.0
+            }
+
+            unsafe fn from_handle_unchecked(handle: Handle) -> Self {
+                Self(handle)
+            }
+        }
+    };
+}
+
+typed_handle!(
+    /// Handle to a memory object
+    MemoryObjectHandle,
+    MemoryObject
+);
+
+typed_handle!(
+    /// Handle to a process
+    ProcessHandle,
+    Process
+);
+
+typed_handle!(
+    /// Handle to a thread
+    ThreadHandle,
+    Thread
+);
+
+typed_handle!(
+    /// Handle to the sending side of a port
+    PortSenderHandle,
+    PortSender
+);
+
+typed_handle!(
+    /// Handle to the receiving side of a port
+    PortReceiverHandle,
+    PortReceiver
+);
+
+typed_handle!(
+    /// Handle to a process listener
+    ProcessListenerHandle,
+    ProcessListener
+);
+
+typed_handle!(
+    /// Handle to a thread listener
+    ThreadListenerHandle,
+    ThreadListener
+);
+
+typed_handle!(
+    /// Handle to a port listener
+    PortListenerHandle,
+    PortListener
+);
+
+typed_handle!(
+    /// Handle to a timer
+    TimerHandle,
+    Timer
+);
+
+typed_handle!(
+    /// Handle to a memory grant
+    GrantHandle,
+    Grant
+);
+
+typed_handle!(
+    /// Handle to a trampoline
+    TrampolineHandle,
+    Trampoline
+);
+
+impl PortHandle for PortSenderHandle {}
+impl PortHandle for PortReceiverHandle {}
+
 fn close(handle: &Handle) -> SyscallResult<()> {
     let ret = unsafe { syscall1(SyscallNumber::HandleClose, handle.as_syscall_value()) };
 
@@ -1,8 +1,8 @@
-use syscalls::{Message, PortInfo, SyscallNumber};
+use syscalls::{Message, MessageHeader, PortFilterRange, PortInfo, SyscallNumber};
 
 use super::{
-    ref_ptr, syscalls::*, sysret_to_result, Handle, SyscallInStr, SyscallList, SyscallOutPtr,
-    SyscallResult,
+    ref_ptr, syscalls::*, sysret_to_result, PortHandle, PortReceiverHandle, PortSenderHandle,
+    SyscallInStr, SyscallList, SyscallOutPtr, SyscallResult,
 };
 
 pub enum NameOrId<'a> {
@@ -10,8 +10,8 @@ pub enum NameOrId<'a> {
     Name(&'a str),
 }
 
-pub fn open(name_or_id: NameOrId) -> SyscallResult<Handle> {
-    let mut new_handle = Handle::invalid();
+pub fn open(name_or_id: NameOrId) -> SyscallResult<PortSenderHandle> {
+    let mut new_handle = PortSenderHandle::invalid();
 
     let mut arg_id: usize = 0;
     let mut arg_name_ptr: usize = 0;
@@ -46,18 +46,27 @@ pub fn open(name_or_id: NameOrId) -> SyscallResult<Handle> {
 }
 
 // return (receiver, sender)
-pub fn create(name: Option<&str>) -> SyscallResult<(Handle, Handle)> {
-    let mut new_receiver_handle = Handle::invalid();
-    let mut new_sender_handle = Handle::invalid();
+//
+// `data_capacity`: number of data items usable in messages, full message size if not set
+// `broadcast`: messages are delivered to the subscribers instead of the receiver
+pub fn create(
+    name: Option<&str>,
+    data_capacity: Option<usize>,
+    broadcast: bool,
+) -> SyscallResult<(PortReceiverHandle, PortSenderHandle)> {
+    let mut new_receiver_handle = PortReceiverHandle::invalid();
+    let mut new_sender_handle = PortSenderHandle::invalid();
     let name_reader = SyscallInStr::new(name.unwrap_or(""));
 
     let ret = unsafe {
-        syscall4(
+        syscall6(
             SyscallNumber::PortCreate,
             name_reader.ptr_arg(),
             name_reader.len_arg(),
             new_receiver_handle.as_syscall_ptr(),
             new_sender_handle.as_syscall_ptr(),
+            data_capacity.unwrap_or(0),
+            broadcast as usize,
         )
     };
 
@@ -67,7 +76,7 @@ pub fn create(name: Option<&str>) -> SyscallResult<(Handle, Handle)> {
 }
 
 /// Send a message to a port
-pub fn send(port: &Handle, msg: &Message) -> SyscallResult<()> {
+pub fn send(port: &PortSenderHandle, msg: &Message) -> SyscallResult<()> {
     let ret = unsafe {
         syscall2(
             SyscallNumber::PortSend,
@@ -81,8 +90,42 @@ pub fn send(port: &Handle, msg: &Message) -> SyscallResult<()> {
     Ok(())
 }
 
+/// Subscribe a port to a broadcast port
+///
+/// The subscriber misses broadcast messages while it has `max_queued` messages (or more) waiting.
+/// It is given by its receiver: only the process receiving from a port can subscribe it.
+pub fn subscribe(
+    port: &PortSenderHandle,
+    subscriber: &PortReceiverHandle,
+    max_queued: usize,
+) -> SyscallResult<()> {
+    let ret = unsafe {
+        syscall3(
+            SyscallNumber::PortSubscribe,
+            port.as_syscall_value(),
+            subscriber.as_syscall_value(),
+            max_queued,
+        )
+    };
+
+    sysret_to_result(ret)
+}
+
+/// Unsubscribe a port from a broadcast port
+pub fn unsubscribe(port: &PortSenderHandle, subscriber: &PortReceiverHandle) -> SyscallResult<()> {
+    let ret = unsafe {
+        syscall2(
+            SyscallNumber::PortUnsubscribe,
+            port.as_syscall_value(),
+            subscriber.as_syscall_value(),
+        )
+    };
+
+    sysret_to_result(ret)
+}
+
 /// Receive a message from a port
-pub fn receive(port: &Handle) -> SyscallResult<Message> {
+pub fn receive(port: &PortReceiverHandle) -> SyscallResult<Message> {
     let msg = SyscallOutPtr::new();
 
     let ret = unsafe {
@@ -98,6 +141,49 @@ pub fn receive(port: &Handle) -> SyscallResult<Message> {
     Ok(msg.take())
 }
 
+/// Receive a message from a port, waiting for one if there is none
+///
+/// A sender of the same process hands off its message directly to a waiting receiver.
+pub fn blocking_receive(port: &PortReceiverHandle) -> SyscallResult<Message> {
+    let msg = SyscallOutPtr::new();
+
+    let ret = unsafe {
+        syscall2(
+            SyscallNumber::PortBlockingReceive,
+            port.as_syscall_value(),
+            msg.ptr_arg(),
+        )
+    };
+
+    sysret_to_result(ret)?;
+
+    Ok(msg.take())
+}
+
+/// Get the header of the next message of a port, without receiving it
+pub fn peek(port: &PortReceiverHandle) -> SyscallResult<MessageHeader> {
+    let header = SyscallOutPtr::new();
+
+    let ret = unsafe {
+        syscall2(
+            SyscallNumber::PortPeek,
+            port.as_syscall_value(),
+            header.ptr_arg(),
+        )
+    };
+
+    sysret_to_result(ret)?;
+
+    Ok(header.take())
+}
+
+/// Drop the next message of a port without receiving it: its handles are closed
+pub fn discard(port: &PortReceiverHandle) -> SyscallResult<()> {
+    let ret = unsafe { syscall1(SyscallNumber::PortDiscard, port.as_syscall_value()) };
+
+    sysret_to_result(ret)
+}
+
 /// Wait for a port to be ready to receive a message
 ///
 /// Notes:
@@ -122,13 +208,13 @@ pub fn wait(ports: &[usize], ready_buffer: &mut [u8]) -> SyscallResult<()> {
 }
 
 /// Get info about the port (can use sender or receiver)
-pub fn info(port: &Handle) -> SyscallResult<PortInfo> {
+pub fn info(port: &impl PortHandle) -> SyscallResult<PortInfo> {
     let info = SyscallOutPtr::new();
 
     let ret = unsafe {
         syscall2(
             SyscallNumber::PortInfo,
-            port.as_syscall_value(),
+            port.as_handle().as_syscall_value(),
             info.ptr_arg(),
         )
     };
@@ -138,13 +224,16 @@ pub fn info(port: &Handle) -> SyscallResult<PortInfo> {
     Ok(info.take())
 }
 
-/// Get list of port ids living in the system
-pub fn list<'a>(array: &'a mut [u64]) -> SyscallResult<(&'a [u64], usize)> {
+/// Get a page of the port ids living in the system, ordered, starting at `start` (included)
+///
+/// Returns the page, and the `start` of the next page if there are more port ids than `array` can hold.
+pub fn list(start: u64, array: &mut [u64]) -> SyscallResult<(&[u64], Option<u64>)> {
     let mut list = unsafe { SyscallList::new(array) };
 
     let ret = unsafe {
-        syscall2(
+        syscall3(
             SyscallNumber::PortList,
+            start as usize,
             list.array_ptr_arg(),
             list.count_ptr_arg(),
         )
@@ -152,5 +241,32 @@ pub fn list<'a>(array: &'a mut [u64]) -> SyscallResult<(&'a [u64], usize)> {
 
     sysret_to_result(ret)?;
 
-    Ok(list.finalize())
+    Ok(list.finalize_page())
+}
+
+/// Set the filter of a port
+///
+/// Messages whose type is in none of `ranges` are routed to the `alternate` port.
+/// It is given by its receiver, and must have been created by the current process.
+///
+/// Note: empty ranges clear the filter
+pub fn set_filter(
+    port: &PortReceiverHandle,
+    ranges: &[PortFilterRange],
+    alternate: Option<&PortReceiverHandle>,
+) -> SyscallResult<()> {
+    let invalid = PortReceiverHandle::invalid();
+    let alternate = alternate.unwrap_or(&invalid);
+
+    let ret = unsafe {
+        syscall4(
+            SyscallNumber::PortSetFilter,
+            port.as_syscall_value(),
+            ranges.as_ptr() as usize,
+            ranges.len(),
+            alternate.as_syscall_value(),
+        )
+    };
+
+    sysret_to_result(ret)
 }
@@ -1,14 +1,23 @@
 #![no_std]
 
+pub mod audit;
+pub mod device;
+pub mod grant;
 mod handle;
 pub mod ipc;
 pub mod listener;
 mod logging;
+pub mod measurement;
 pub mod memory;
 pub mod memory_object;
+pub mod power;
 pub mod process;
+pub mod stats;
+pub mod strings;
 mod syscalls;
 pub mod thread;
+pub mod timer;
+pub mod trampoline;
 
 use core::{
     cmp::min,
@@ -20,9 +29,16 @@ pub use logging::*;
 
 use ::syscalls::SUCCESS;
 pub use ::syscalls::{
-    Error, Exception, HandleType, KallocStats, KvmStats, MemoryStats, Message, Permissions,
-    PhysStats, PortInfo, ProcessEvent, ProcessEventType, ProcessInfo, ThreadContext,
-    ThreadContextRegister, ThreadEvent, ThreadEventType, ThreadInfo, ThreadPriority, ThreadState,
+    AuditEventType, AuditRecord, AuditRule, CurrentIds, DeviceBus, DeviceInfo, DeviceResource,
+    DeviceResourceType, Error, Exception, FrameAudit, HandleInfo, HandleType, IdleMethod, IdleStats,
+    KallocStats, KvmStats, LogSink, MappingInfo, Measurement, MemoryObjectEvent,
+    MemoryObjectEventType, MemoryObjectFlags, MemoryStats, Message, MessageHeader, NameEntry,
+    ObjectCounts, Permissions, PhysStats, PortEvent, PortEventType, PortFilterRange, PortInfo,
+    ProcessEvent, ProcessEventType, ProcessInfo, ReadyLatency, SandboxFlags, SchedEvent,
+    SchedEventType, SleepMode, SyscallLatency, SystemInfo, ThreadContext, ThreadContextRegister,
+    ThreadEvent, ThreadEventType, ThreadInfo, ThreadPriority, ThreadState, TimerEvent, TimerStats,
+    WaitCause, WatchpointKind, MAPPING_BUDGET_SIZE, MAX_ADVANCE_NS, PROCESS_MEMORY_MAX_SIZE,
+    TICK_NS, WATCHPOINT_COUNT,
 };
 
 pub type SyscallResult<T> = Result<T, Error>;
@@ -96,6 +112,23 @@ impl<'a, T: Sized + Copy> SyscallList<'a, T> {
     }
 }
 
+impl<'a> SyscallList<'a, u64> {
+    /// Call after a paginated id list syscall to get the page and the cursor of the next one, if any
+    ///
+    /// Ids are ordered, so the next page starts after the last id of this one.
+    pub fn finalize_page<'b>(&mut self) -> (&'b [u64], Option<u64>) {
+        let (page, count) = self.finalize();
+
+        let next = if count > page.len() {
+            page.last().map(|id| id + 1)
+        } else {
+            None
+        };
+
+        (page, next)
+    }
+}
+
 struct SyscallOutPtr<T: Sized> {
     value: T,
 }
@@ -1,8 +1,14 @@
 use syscalls::SyscallNumber;
 
-use super::{slice_ptr, syscalls::*, sysret_to_result, Handle, SyscallResult};
+use super::{
+    slice_ptr, syscalls::*, sysret_to_result, PortListenerHandle, PortSenderHandle,
+    ProcessListenerHandle, SyscallInStr, SyscallResult, ThreadListenerHandle,
+};
 
-pub fn create_process(port: &Handle, pids: Option<&[u64]>) -> SyscallResult<Handle> {
+pub fn create_process(
+    port: &PortSenderHandle,
+    pids: Option<&[u64]>,
+) -> SyscallResult<ProcessListenerHandle> {
     let (pid_list_ptr, pid_list_size) = if let Some(list) = pids {
         assert!(list.len() > 0);
 
@@ -11,7 +17,7 @@ pub fn create_process(port: &Handle, pids: Option<&[u64]>) -> SyscallResult<Hand
         (0, 0)
     };
 
-    let mut new_handle = Handle::invalid();
+    let mut new_handle = ProcessListenerHandle::invalid();
     let ret = unsafe {
         syscall4(
             SyscallNumber::ListenerCreateProcess,
@@ -27,7 +33,11 @@ pub fn create_process(port: &Handle, pids: Option<&[u64]>) -> SyscallResult<Hand
     Ok(new_handle)
 }
 
-pub fn create_thread(port: &Handle, ids: Option<&[u64]>, is_pids: bool) -> SyscallResult<Handle> {
+pub fn create_thread(
+    port: &PortSenderHandle,
+    ids: Option<&[u64]>,
+    is_pids: bool,
+) -> SyscallResult<ThreadListenerHandle> {
     let (id_list_ptr, id_list_size) = if let Some(list) = ids {
         assert!(list.len() > 0);
 
@@ -38,7 +48,7 @@ pub fn create_thread(port: &Handle, ids: Option<&[u64]>, is_pids: bool) -> Sysca
 
     let is_pids = if is_pids { 1 } else { 0 };
 
-    let mut new_handle = Handle::invalid();
+    let mut new_handle = ThreadListenerHandle::invalid();
     let ret = unsafe {
         syscall5(
             SyscallNumber::ListenerCreateThread,
@@ -54,3 +64,31 @@ pub fn create_thread(port: &Handle, ids: Option<&[u64]>, is_pids: bool) -> Sysca
 
     Ok(new_handle)
 }
+
+/// Listen to the registration of port names
+///
+/// An empty name with `is_prefix` matches all named ports.
+pub fn create_port(
+    port: &PortSenderHandle,
+    name: &str,
+    is_prefix: bool,
+) -> SyscallResult<PortListenerHandle> {
+    let name_reader = SyscallInStr::new(name);
+    let is_prefix = if is_prefix { 1 } else { 0 };
+
+    let mut new_handle = PortListenerHandle::invalid();
+    let ret = unsafe {
+        syscall5(
+            SyscallNumber::ListenerCreatePort,
+            port.as_syscall_value(),
+            name_reader.ptr_arg(),
+            name_reader.len_arg(),
+            is_prefix,
+            new_handle.as_syscall_ptr(),
+        )
+    };
+
+    sysret_to_result(ret)?;
+
+    Ok(new_handle)
+}
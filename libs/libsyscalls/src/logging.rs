@@ -1,5 +1,5 @@
-use super::{syscalls::*, sysret_to_result, SyscallResult};
-use syscalls::SyscallNumber;
+use super::{syscalls::*, sysret_to_result, ProcessHandle, SyscallResult};
+use syscalls::{LogSink, SyscallNumber};
 
 pub fn log(level: log::Level, message: &str) -> SyscallResult<()> {
     let ret = unsafe {
@@ -13,3 +13,51 @@ pub fn log(level: log::Level, message: &str) -> SyscallResult<()> {
 
     sysret_to_result(ret)
 }
+
+/// Log a record on behalf of a thread of another process, which the caller can inspect
+pub fn log_relay(
+    process: &ProcessHandle,
+    tid: u64,
+    level: log::Level,
+    message: &str,
+) -> SyscallResult<()> {
+    let ret = unsafe {
+        syscall5(
+            SyscallNumber::LogRelay,
+            process.as_syscall_value(),
+            tid as usize,
+            level as usize,
+            message.as_ptr() as usize,
+            message.len(),
+        )
+    };
+
+    sysret_to_result(ret)
+}
+
+/// Read the kernel log ring from `cursor` (number of bytes since boot), and move it after the data read
+///
+/// If the data at `cursor` has been overwritten, reading starts from the oldest data available.
+pub fn log_read<'a>(cursor: &mut u64, buffer: &'a mut [u8]) -> SyscallResult<&'a [u8]> {
+    let mut len = buffer.len();
+
+    let ret = unsafe {
+        syscall3(
+            SyscallNumber::LogRead,
+            cursor as *mut u64 as usize,
+            buffer.as_mut_ptr() as usize,
+            &mut len as *mut usize as usize,
+        )
+    };
+
+    sysret_to_result(ret)?;
+
+    Ok(&buffer[..len])
+}
+
+/// Set the level threshold of a kernel log sink
+pub fn log_set_level(sink: LogSink, level: log::LevelFilter) -> SyscallResult<()> {
+    let ret = unsafe { syscall2(SyscallNumber::LogSetLevel, sink as usize, level as usize) };
+
+    sysret_to_result(ret)
+}
@@ -0,0 +1,36 @@
+use syscalls::{Measurement, SyscallNumber};
+
+use super::{syscalls::*, sysret_to_result, SyscallResult};
+
+/// Measure a component before using it: the kernel computes the digest of `data` and appends it to the log
+pub fn extend(name: &str, data: &[u8]) -> SyscallResult<()> {
+    let ret = unsafe {
+        syscall4(
+            SyscallNumber::MeasurementExtend,
+            name.as_ptr() as usize,
+            name.len(),
+            data.as_ptr() as usize,
+            data.len(),
+        )
+    };
+
+    sysret_to_result(ret)
+}
+
+/// Read the log entries from index `start`
+pub fn read(start: usize, array: &mut [Measurement]) -> SyscallResult<&[Measurement]> {
+    let mut count = array.len();
+
+    let ret = unsafe {
+        syscall3(
+            SyscallNumber::MeasurementRead,
+            start,
+            array.as_mut_ptr() as usize,
+            &mut count as *mut usize as usize,
+        )
+    };
+
+    sysret_to_result(ret)?;
+
+    Ok(&array[..count])
+}
@@ -1,6 +1,6 @@
 use syscalls::SyscallNumber;
 
-use super::{syscalls::*, sysret_to_result, MemoryStats, SyscallOutPtr, SyscallResult};
+use super::{syscalls::*, sysret_to_result, FrameAudit, MemoryStats, SyscallOutPtr, SyscallResult};
 
 /// Get info about the process
 pub fn stats() -> SyscallResult<MemoryStats> {
@@ -12,3 +12,14 @@ pub fn stats() -> SyscallResult<MemoryStats> {
 
     Ok(stats.take())
 }
+
+/// Cross-check physical frames reference counts against mappings (privileged threads only)
+pub fn audit_frames() -> SyscallResult<FrameAudit> {
+    let audit = SyscallOutPtr::new();
+
+    let ret = unsafe { syscall1(SyscallNumber::MemoryAuditFrames, audit.ptr_arg()) };
+
+    sysret_to_result(ret)?;
+
+    Ok(audit.take())
+}
@@ -1,13 +1,14 @@
-use syscalls::SyscallNumber;
+use syscalls::{MemoryObjectFlags, Permissions, SyscallNumber};
 
-use super::{syscalls::*, sysret_to_result, Handle, SyscallResult};
+use super::{syscalls::*, sysret_to_result, MemoryObjectHandle, PortSenderHandle, SyscallResult};
 
-pub fn create(size: usize) -> SyscallResult<Handle> {
-    let mut new_handle = Handle::invalid();
+pub fn create(size: usize, flags: MemoryObjectFlags) -> SyscallResult<MemoryObjectHandle> {
+    let mut new_handle = MemoryObjectHandle::invalid();
     let ret = unsafe {
-        syscall2(
+        syscall3(
             SyscallNumber::MemoryObjectCreate,
             size,
+            flags.bits() as usize,
             new_handle.as_syscall_ptr(),
         )
     };
@@ -16,3 +17,62 @@ pub fn create(size: usize) -> SyscallResult<Handle> {
 
     Ok(new_handle)
 }
+
+/// Send a `MemoryObjectEvent` to the port when the memory object is released (no more handle nor mapping)
+pub fn notify_release(
+    memory_object: &MemoryObjectHandle,
+    port: &PortSenderHandle,
+    cookie: u64,
+) -> SyscallResult<()> {
+    let ret = unsafe {
+        syscall3(
+            SyscallNumber::MemoryObjectNotifyRelease,
+            memory_object.as_syscall_value(),
+            port.as_syscall_value(),
+            cookie as usize,
+        )
+    };
+
+    sysret_to_result(ret)
+}
+
+/// Open a new handle to the memory object, which can only map it with `perms`
+///
+/// The new handle cannot have more permissions than `memory_object`.
+pub fn restrict(
+    memory_object: &MemoryObjectHandle,
+    perms: Permissions,
+) -> SyscallResult<MemoryObjectHandle> {
+    let mut new_handle = MemoryObjectHandle::invalid();
+    let ret = unsafe {
+        syscall3(
+            SyscallNumber::MemoryObjectRestrict,
+            memory_object.as_syscall_value(),
+            perms.bits() as usize,
+            new_handle.as_syscall_ptr(),
+        )
+    };
+
+    sysret_to_result(ret)?;
+
+    Ok(new_handle)
+}
+
+/// Get the physical address of the page at `offset` in the memory object, to program DMA transfers
+///
+/// Note: only privileged threads and processes which drive a device can query it
+pub fn physical_address(memory_object: &MemoryObjectHandle, offset: usize) -> SyscallResult<u64> {
+    let mut address = 0u64;
+    let ret = unsafe {
+        syscall3(
+            SyscallNumber::MemoryObjectPhysicalAddress,
+            memory_object.as_syscall_value(),
+            offset,
+            &mut address as *mut _ as usize,
+        )
+    };
+
+    sysret_to_result(ret)?;
+
+    Ok(address)
+}
@@ -0,0 +1,42 @@
+use syscalls::SyscallNumber;
+
+use super::{syscalls::*, sysret_to_result, SleepMode, SyscallResult};
+
+/// Enter a sleep state, and return once the system is resumed
+///
+/// Note: only privileged threads can suspend the machine
+pub fn suspend(mode: SleepMode) -> SyscallResult<()> {
+    let ret = unsafe { syscall1(SyscallNumber::SystemSuspend, mode as usize) };
+
+    sysret_to_result(ret)
+}
+
+/// Power off the machine
+///
+/// Returns only on error.
+///
+/// Note: only privileged threads can power off the machine
+pub fn power_off() -> SyscallResult<()> {
+    let ret = unsafe { syscall0(SyscallNumber::SystemPowerOff) };
+
+    sysret_to_result(ret)
+}
+
+/// Load a new kernel image (ELF file) with its ramdisk, and jump to it
+///
+/// Returns only on error.
+///
+/// Note: only privileged threads can reload the kernel
+pub fn reload(kernel: &[u8], ramdisk: &[u8]) -> SyscallResult<()> {
+    let ret = unsafe {
+        syscall4(
+            SyscallNumber::KernelReload,
+            kernel.as_ptr() as usize,
+            kernel.len(),
+            ramdisk.as_ptr() as usize,
+            ramdisk.len(),
+        )
+    };
+
+    sysret_to_result(ret)
+}
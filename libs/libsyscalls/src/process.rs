@@ -1,14 +1,15 @@
 use core::ops::Range;
 
-use syscalls::SyscallNumber;
+use syscalls::{HandleInfo, MappingInfo, NameEntry, SandboxFlags, SyscallNumber};
 
 use super::{
-    syscalls::*, sysret_to_result, Handle, Permissions, ProcessInfo, SyscallInStr, SyscallList,
-    SyscallOutPtr, SyscallResult,
+    syscalls::*, sysret_to_result, Error, Handle, MemoryObjectHandle, ObjectCounts, Permissions,
+    ProcessHandle, ProcessInfo, SyscallInStr, SyscallList, SyscallOutPtr, SyscallResult,
+    MAPPING_BUDGET_SIZE, PROCESS_MEMORY_MAX_SIZE,
 };
 
-pub fn open_self() -> SyscallResult<Handle> {
-    let mut new_handle = Handle::invalid();
+pub fn open_self() -> SyscallResult<ProcessHandle> {
+    let mut new_handle = ProcessHandle::invalid();
     let ret = unsafe { syscall1(SyscallNumber::ProcessOpenSelf, new_handle.as_syscall_ptr()) };
 
     sysret_to_result(ret)?;
@@ -16,8 +17,8 @@ pub fn open_self() -> SyscallResult<Handle> {
     Ok(new_handle)
 }
 
-pub fn open(pid: u64) -> SyscallResult<Handle> {
-    let mut new_handle = Handle::invalid();
+pub fn open(pid: u64) -> SyscallResult<ProcessHandle> {
+    let mut new_handle = ProcessHandle::invalid();
     let ret = unsafe {
         syscall2(
             SyscallNumber::ProcessOpen,
@@ -31,8 +32,8 @@ pub fn open(pid: u64) -> SyscallResult<Handle> {
     Ok(new_handle)
 }
 
-pub fn create(name: &str) -> SyscallResult<Handle> {
-    let mut new_handle = Handle::invalid();
+pub fn create(name: &str) -> SyscallResult<ProcessHandle> {
+    let mut new_handle = ProcessHandle::invalid();
     let name_reader = SyscallInStr::new(name);
     let ret = unsafe {
         syscall3(
@@ -52,13 +53,13 @@ pub fn create(name: &str) -> SyscallResult<Handle> {
 ///
 /// Notes:
 /// - If `addr` is not set, an address where the mapping can fit will be found.
-/// - If `addr` is set, this function cannot overwrite part of an existing mapping. Call unmap() before.
+/// - If `addr` is set, the area must be free: this function never overwrites part of an existing mapping. Call unmap() before.
 pub fn mmap(
-    process: &Handle,
+    process: &ProcessHandle,
     addr: Option<usize>,
     size: usize,
     perms: Permissions,
-    memory_object: Option<&Handle>,
+    memory_object: Option<&MemoryObjectHandle>,
     offset: usize,
 ) -> SyscallResult<usize> {
     let mut addr = if let Some(value) = addr { value } else { 0 };
@@ -66,7 +67,10 @@ pub fn mmap(
     let (memory_object, offset) = if let Some(handle) = memory_object {
         (unsafe { handle.as_syscall_value() }, offset)
     } else {
-        (unsafe { Handle::invalid().as_syscall_value() }, 0)
+        (
+            unsafe { MemoryObjectHandle::invalid().as_syscall_value() },
+            0,
+        )
     };
 
     // Note: addr is modified in-place
@@ -88,43 +92,217 @@ pub fn mmap(
     Ok(addr)
 }
 
+/// Reserve an area of the process VM, without backing it with memory
+///
+/// Unlike `mmap`, reserving at a given address fails if the area is already used.
+pub fn mreserve(process: &ProcessHandle, addr: Option<usize>, size: usize) -> SyscallResult<usize> {
+    let mut addr = addr.unwrap_or(0);
+
+    // Note: addr is modified in-place
+    let ret = unsafe {
+        syscall3(
+            SyscallNumber::ProcessMReserve,
+            process.as_syscall_value(),
+            &mut addr as *mut _ as usize,
+            size,
+        )
+    };
+
+    sysret_to_result(ret)?;
+
+    Ok(addr)
+}
+
+/// Back a part of a reservation with a memory object
+///
+/// The area must be fully covered by reservations.
+pub fn mcommit(
+    process: &ProcessHandle,
+    range: &Range<usize>,
+    perms: Permissions,
+    memory_object: &MemoryObjectHandle,
+    offset: usize,
+) -> SyscallResult<()> {
+    let ret = unsafe {
+        syscall6(
+            SyscallNumber::ProcessMCommit,
+            process.as_syscall_value(),
+            range.start,
+            range.len(),
+            perms.bits() as usize,
+            memory_object.as_syscall_value(),
+            offset,
+        )
+    };
+
+    sysret_to_result(ret)
+}
+
+/// Map a stack of at most `max_size`, with `initial_size` committed at its top
+///
+/// The region starts with a guard page: its size is `max_size + PAGE_SIZE`. The stack grows on page faults.
+/// Returns the start of the region.
+pub fn mmap_stack(
+    process: &ProcessHandle,
+    max_size: usize,
+    initial_size: usize,
+) -> SyscallResult<usize> {
+    let mut addr: usize = 0;
+
+    let ret = unsafe {
+        syscall4(
+            SyscallNumber::ProcessMMapStack,
+            process.as_syscall_value(),
+            max_size,
+            initial_size,
+            &mut addr as *mut _ as usize,
+        )
+    };
+
+    sysret_to_result(ret)?;
+
+    Ok(addr)
+}
+
 /// Unmap the address space from addr to addr+size.
 ///
 /// Notes:
 /// - It may contains multiple mappings,
 /// - addr or addr+size may be in the middle of a mapping
 /// - part of the specified area my not be mapped. In consequence, calling unmap() on an unmapped area is a successful noop.
+/// - large areas are processed in several syscalls (see `MAPPING_BUDGET_SIZE`)
+///
+pub fn munmap(process: &ProcessHandle, range: &Range<usize>) -> SyscallResult<()> {
+    by_budget(range, |start, size| {
+        let ret = unsafe {
+            syscall3(
+                SyscallNumber::ProcessMUnmap,
+                process.as_syscall_value(),
+                start,
+                size,
+            )
+        };
+
+        sysret_to_result(ret)
+    })
+}
+
+/// Change the permissions for the given memory region
+///
+/// Notes:
+/// - It may contains multiple mappings, but must be fully mapped with memory (no reservation)
+/// - The mappings at its edges may be larger than the given region. They will be split.
+/// - large regions are processed in several syscalls (see `MAPPING_BUDGET_SIZE`)
+pub fn mprotect(
+    process: &ProcessHandle,
+    range: &Range<usize>,
+    perms: Permissions,
+) -> SyscallResult<()> {
+    by_budget(range, |start, size| {
+        let ret = unsafe {
+            syscall4(
+                SyscallNumber::ProcessMProtect,
+                process.as_syscall_value(),
+                start,
+                size,
+                perms.bits() as usize,
+            )
+        };
+
+        sysret_to_result(ret)
+    })
+}
+
+/// Set the name of the given memory region, or clear it if `name` is None
 ///
-pub fn munmap(process: &Handle, range: &Range<usize>) -> SyscallResult<()> {
+/// Notes:
+/// - It may contains multiple mappings (reservations included), but must be fully mapped
+/// - The mappings at its edges may be larger than the given region. They will be split.
+/// - Names longer than MappingInfo::NAME_LEN are rejected
+/// - large regions are processed in several syscalls (see `MAPPING_BUDGET_SIZE`)
+pub fn mname(
+    process: &ProcessHandle,
+    range: &Range<usize>,
+    name: Option<&str>,
+) -> SyscallResult<()> {
+    let name_reader = SyscallInStr::new(name.unwrap_or(""));
+
+    by_budget(range, |start, size| {
+        let ret = unsafe {
+            syscall5(
+                SyscallNumber::ProcessMName,
+                process.as_syscall_value(),
+                start,
+                size,
+                name_reader.ptr_arg(),
+                name_reader.len_arg(),
+            )
+        };
+
+        sysret_to_result(ret)
+    })
+}
+
+/// Call the range syscall until the kernel has processed the whole range
+///
+/// The kernel processes at most `MAPPING_BUDGET_SIZE` per call, and returns `Error::Partial` if there is more.
+fn by_budget<F: FnMut(usize, usize) -> SyscallResult<()>>(
+    range: &Range<usize>,
+    mut syscall: F,
+) -> SyscallResult<()> {
+    let mut start = range.start;
+
+    loop {
+        match syscall(start, range.end - start) {
+            Err(Error::Partial) => start += MAPPING_BUDGET_SIZE,
+            result => return result,
+        }
+    }
+}
+
+/// Get list of mappings of the process, ordered by address
+pub fn mappings<'a>(
+    process: &ProcessHandle,
+    array: &'a mut [MappingInfo],
+) -> SyscallResult<(&'a [MappingInfo], usize)> {
+    let mut list = unsafe { SyscallList::new(array) };
+
     let ret = unsafe {
         syscall3(
-            SyscallNumber::ProcessMUnmap,
+            SyscallNumber::ProcessMappings,
             process.as_syscall_value(),
-            range.start as usize,
-            range.len(),
+            list.array_ptr_arg(),
+            list.count_ptr_arg(),
         )
     };
 
-    sysret_to_result(ret)
+    sysret_to_result(ret)?;
+
+    Ok(list.finalize())
 }
 
-/// Change the permissions for the given memory region
-///
-/// Notes:
-/// - It can only contains one mapping
-/// - The mapping may be larger than the given region. It will be split.
-pub fn mprotect(process: &Handle, range: &Range<usize>, perms: Permissions) -> SyscallResult<()> {
+/// Get the mappings of the process which overlap the given range (reservations included), ordered by address
+pub fn query_range<'a>(
+    process: &ProcessHandle,
+    range: &Range<usize>,
+    array: &'a mut [MappingInfo],
+) -> SyscallResult<(&'a [MappingInfo], usize)> {
+    let mut list = unsafe { SyscallList::new(array) };
+
     let ret = unsafe {
-        syscall4(
-            SyscallNumber::ProcessMProtect,
+        syscall5(
+            SyscallNumber::ProcessQueryRange,
             process.as_syscall_value(),
-            range.start as usize,
+            range.start,
             range.len(),
-            perms.bits() as usize,
+            list.array_ptr_arg(),
+            list.count_ptr_arg(),
         )
     };
 
-    sysret_to_result(ret)
+    sysret_to_result(ret)?;
+
+    Ok(list.finalize())
 }
 
 pub fn exit() -> SyscallResult<()> {
@@ -133,14 +311,95 @@ pub fn exit() -> SyscallResult<()> {
     sysret_to_result(ret)
 }
 
-pub fn kill(process: &Handle) -> SyscallResult<()> {
+pub fn kill(process: &ProcessHandle) -> SyscallResult<()> {
     let ret = unsafe { syscall1(SyscallNumber::ProcessKill, process.as_syscall_value()) };
 
     sysret_to_result(ret)
 }
 
+/// Suspend all the threads of the process, until it is resumed
+pub fn suspend(process: &ProcessHandle) -> SyscallResult<()> {
+    let ret = unsafe { syscall1(SyscallNumber::ProcessSuspend, process.as_syscall_value()) };
+
+    sysret_to_result(ret)
+}
+
+/// Resume a suspended process
+pub fn resume(process: &ProcessHandle) -> SyscallResult<()> {
+    let ret = unsafe { syscall1(SyscallNumber::ProcessResume, process.as_syscall_value()) };
+
+    sysret_to_result(ret)
+}
+
+/// Set the limits on the number of kernel objects the process can create
+///
+/// Note: a process cannot set its own limits, only its creator or privileged threads can
+pub fn set_object_limits(process: &ProcessHandle, limits: &ObjectCounts) -> SyscallResult<()> {
+    let ret = unsafe {
+        syscall2(
+            SyscallNumber::ProcessSetObjectLimits,
+            process.as_syscall_value(),
+            limits as *const _ as usize,
+        )
+    };
+
+    sysret_to_result(ret)
+}
+
+/// Set the sandbox rights of the process
+///
+/// Note: only its creator or privileged threads can set them, within the rights of the caller
+pub fn set_sandbox(process: &ProcessHandle, flags: SandboxFlags) -> SyscallResult<()> {
+    let ret = unsafe {
+        syscall2(
+            SyscallNumber::ProcessSetSandbox,
+            process.as_syscall_value(),
+            flags.bits() as usize,
+        )
+    };
+
+    sysret_to_result(ret)
+}
+
+/// Grant a range of io-ports to the process
+///
+/// Note: only its creator or privileged threads can grant them, and the caller must hold the range itself (except init)
+pub fn grant_io_ports(process: &ProcessHandle, range: Range<u16>) -> SyscallResult<()> {
+    let ret = unsafe {
+        syscall3(
+            SyscallNumber::ProcessGrantIoPorts,
+            process.as_syscall_value(),
+            range.start as usize,
+            range.end as usize,
+        )
+    };
+
+    sysret_to_result(ret)
+}
+
+/// Get the number of kernel objects created by the process, and its limits
+///
+/// Returns (usage, limits)
+pub fn object_usage(process: &ProcessHandle) -> SyscallResult<(ObjectCounts, ObjectCounts)> {
+    let usage = SyscallOutPtr::new();
+    let limits = SyscallOutPtr::new();
+
+    let ret = unsafe {
+        syscall3(
+            SyscallNumber::ProcessObjectUsage,
+            process.as_syscall_value(),
+            usage.ptr_arg(),
+            limits.ptr_arg(),
+        )
+    };
+
+    sysret_to_result(ret)?;
+
+    Ok((usage.take(), limits.take()))
+}
+
 /// Get info about the process
-pub fn info(process: &Handle) -> SyscallResult<ProcessInfo> {
+pub fn info(process: &ProcessHandle) -> SyscallResult<ProcessInfo> {
     let info = SyscallOutPtr::new();
 
     let ret = unsafe {
@@ -156,13 +415,16 @@ pub fn info(process: &Handle) -> SyscallResult<ProcessInfo> {
     Ok(info.take())
 }
 
-/// Get list of pids living in the system
-pub fn list<'a>(array: &'a mut [u64]) -> SyscallResult<(&'a [u64], usize)> {
+/// Get a page of the pids living in the system, ordered, starting at `start` (included)
+///
+/// Returns the page, and the `start` of the next page if there are more pids than `array` can hold.
+pub fn list(start: u64, array: &mut [u64]) -> SyscallResult<(&[u64], Option<u64>)> {
     let mut list = unsafe { SyscallList::new(array) };
 
     let ret = unsafe {
-        syscall2(
+        syscall3(
             SyscallNumber::ProcessList,
+            start as usize,
             list.array_ptr_arg(),
             list.count_ptr_arg(),
         )
@@ -170,11 +432,61 @@ pub fn list<'a>(array: &'a mut [u64]) -> SyscallResult<(&'a [u64], usize)> {
 
     sysret_to_result(ret)?;
 
-    Ok(list.finalize())
+    Ok(list.finalize_page())
+}
+
+/// Get a page of the tids of the threads of the process, ordered, starting at `start` (included)
+///
+/// Returns the page, and the `start` of the next page if there are more tids than `array` can hold.
+pub fn threads<'a>(
+    process: &ProcessHandle,
+    start: u64,
+    array: &'a mut [u64],
+) -> SyscallResult<(&'a [u64], Option<u64>)> {
+    let mut list = unsafe { SyscallList::new(array) };
+
+    let ret = unsafe {
+        syscall4(
+            SyscallNumber::ProcessThreads,
+            process.as_syscall_value(),
+            start as usize,
+            list.array_ptr_arg(),
+            list.count_ptr_arg(),
+        )
+    };
+
+    sysret_to_result(ret)?;
+
+    Ok(list.finalize_page())
+}
+
+/// Get a page of the ids of the ports created by the process, ordered, starting at `start` (included)
+///
+/// Returns the page, and the `start` of the next page if there are more port ids than `array` can hold.
+pub fn ports<'a>(
+    process: &ProcessHandle,
+    start: u64,
+    array: &'a mut [u64],
+) -> SyscallResult<(&'a [u64], Option<u64>)> {
+    let mut list = unsafe { SyscallList::new(array) };
+
+    let ret = unsafe {
+        syscall4(
+            SyscallNumber::ProcessPorts,
+            process.as_syscall_value(),
+            start as usize,
+            list.array_ptr_arg(),
+            list.count_ptr_arg(),
+        )
+    };
+
+    sysret_to_result(ret)?;
+
+    Ok(list.finalize_page())
 }
 
 /// Set the process name
-pub fn set_name(process: &Handle, name: &str) -> SyscallResult<()> {
+pub fn set_name(process: &ProcessHandle, name: &str) -> SyscallResult<()> {
     let name_reader = SyscallInStr::new(name);
     let ret = unsafe {
         syscall3(
@@ -192,7 +504,7 @@ pub fn set_name(process: &Handle, name: &str) -> SyscallResult<()> {
 ///
 /// This can be useful is name is longer than 128 (truncated in info)
 pub fn get_name<'a>(
-    process: &Handle,
+    process: &ProcessHandle,
     name_buffer: &'a mut [u8],
 ) -> SyscallResult<(&'a [u8], usize)> {
     let mut list = unsafe { SyscallList::new(name_buffer) };
@@ -210,3 +522,104 @@ pub fn get_name<'a>(
 
     Ok(list.finalize())
 }
+
+/// Get the names of several processes in one call
+///
+/// Note: `entries` must have the same length as `pids`
+pub fn names(pids: &[u64], entries: &mut [NameEntry]) -> SyscallResult<()> {
+    assert!(pids.len() == entries.len());
+
+    let ret = unsafe {
+        syscall3(
+            SyscallNumber::ProcessNames,
+            pids.as_ptr() as usize,
+            entries.as_mut_ptr() as usize,
+            pids.len(),
+        )
+    };
+
+    sysret_to_result(ret)
+}
+
+/// Read the memory of the process at `addr` into `buffer`
+///
+/// Large buffers are read in several syscalls (see `PROCESS_MEMORY_MAX_SIZE`).
+/// Note: the caller must be privileged or have the `DEBUG` sandbox right, unless it reads its own memory
+pub fn memory_read(process: &ProcessHandle, addr: usize, buffer: &mut [u8]) -> SyscallResult<()> {
+    for (index, chunk) in buffer.chunks_mut(PROCESS_MEMORY_MAX_SIZE).enumerate() {
+        let ret = unsafe {
+            syscall4(
+                SyscallNumber::ProcessMemoryRead,
+                process.as_syscall_value(),
+                addr + index * PROCESS_MEMORY_MAX_SIZE,
+                chunk.as_mut_ptr() as usize,
+                chunk.len(),
+            )
+        };
+
+        sysret_to_result(ret)?;
+    }
+
+    Ok(())
+}
+
+/// Write `data` to the memory of the process at `addr`
+///
+/// Large buffers are written in several syscalls (see `PROCESS_MEMORY_MAX_SIZE`).
+/// Note: same rights than `memory_read`, and the target memory must be writable
+pub fn memory_write(process: &ProcessHandle, addr: usize, data: &[u8]) -> SyscallResult<()> {
+    for (index, chunk) in data.chunks(PROCESS_MEMORY_MAX_SIZE).enumerate() {
+        let ret = unsafe {
+            syscall4(
+                SyscallNumber::ProcessMemoryWrite,
+                process.as_syscall_value(),
+                addr + index * PROCESS_MEMORY_MAX_SIZE,
+                chunk.as_ptr() as usize,
+                chunk.len(),
+            )
+        };
+
+        sysret_to_result(ret)?;
+    }
+
+    Ok(())
+}
+
+/// Get list of handles opened by the process
+///
+/// Note: same rights than `memory_read`
+pub fn handles<'a>(
+    process: &ProcessHandle,
+    array: &'a mut [HandleInfo],
+) -> SyscallResult<(&'a [HandleInfo], usize)> {
+    let mut list = unsafe { SyscallList::new(array) };
+
+    let ret = unsafe {
+        syscall3(
+            SyscallNumber::ProcessHandles,
+            process.as_syscall_value(),
+            list.array_ptr_arg(),
+            list.count_ptr_arg(),
+        )
+    };
+
+    sysret_to_result(ret)?;
+
+    Ok(list.finalize())
+}
+
+/// Open the object of `source` in the process, with the handle value `value`
+///
+/// Note: the caller must control the process (creator or privileged), and `value` must not be used in it
+pub fn install_handle(process: &ProcessHandle, value: u64, source: &Handle) -> SyscallResult<()> {
+    let ret = unsafe {
+        syscall3(
+            SyscallNumber::ProcessInstallHandle,
+            process.as_syscall_value(),
+            value as usize,
+            source.as_syscall_value(),
+        )
+    };
+
+    sysret_to_result(ret)
+}
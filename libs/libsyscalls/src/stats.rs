@@ -0,0 +1,64 @@
+use syscalls::SyscallNumber;
+
+use super::{
+    syscalls::*, sysret_to_result, IdleStats, ReadyLatency, SyscallLatency, SyscallList,
+    SyscallOutPtr, SyscallResult, SystemInfo,
+};
+
+/// Get latency histograms of syscalls which have been called at least once
+pub fn syscall_latencies(
+    array: &mut [SyscallLatency],
+) -> SyscallResult<(&[SyscallLatency], usize)> {
+    let mut list = unsafe { SyscallList::new(array) };
+
+    let ret = unsafe {
+        syscall2(
+            SyscallNumber::SyscallStats,
+            list.array_ptr_arg(),
+            list.count_ptr_arg(),
+        )
+    };
+
+    sysret_to_result(ret)?;
+
+    Ok(list.finalize())
+}
+
+/// Get an aggregate snapshot of the system state
+pub fn system_info() -> SyscallResult<SystemInfo> {
+    let info = SyscallOutPtr::new();
+
+    let ret = unsafe { syscall1(SyscallNumber::SystemInfo, info.ptr_arg()) };
+
+    sysret_to_result(ret)?;
+
+    Ok(info.take())
+}
+
+/// Get the idle policy and residency counters
+pub fn idle_stats() -> SyscallResult<IdleStats> {
+    let stats = SyscallOutPtr::new();
+
+    let ret = unsafe { syscall1(SyscallNumber::IdleStats, stats.ptr_arg()) };
+
+    sysret_to_result(ret)?;
+
+    Ok(stats.take())
+}
+
+/// Get the time spent by threads in the ready list before being scheduled, by priority (highest first, idle excluded)
+pub fn ready_latencies(array: &mut [ReadyLatency]) -> SyscallResult<(&[ReadyLatency], usize)> {
+    let mut list = unsafe { SyscallList::new(array) };
+
+    let ret = unsafe {
+        syscall2(
+            SyscallNumber::ReadyLatencyStats,
+            list.array_ptr_arg(),
+            list.count_ptr_arg(),
+        )
+    };
+
+    sysret_to_result(ret)?;
+
+    Ok(list.finalize())
+}
@@ -0,0 +1,21 @@
+use syscalls::SyscallNumber;
+
+use super::{syscalls::*, sysret_to_result, NameEntry, SyscallResult};
+
+/// Resolve ids of the kernel strings table (process, thread and port names) in batch
+///
+/// `entries[i]` receives the string of `ids[i]`, or a not found entry if the id is not in use anymore.
+pub fn lookup(ids: &[u64], entries: &mut [NameEntry]) -> SyscallResult<()> {
+    assert!(ids.len() == entries.len());
+
+    let ret = unsafe {
+        syscall3(
+            SyscallNumber::StringLookup,
+            ids.as_ptr() as usize,
+            entries.as_mut_ptr() as usize,
+            ids.len(),
+        )
+    };
+
+    sysret_to_result(ret)
+}
@@ -1,16 +1,17 @@
 use syscalls::{
-    Exception, SyscallNumber, ThreadContext, ThreadContextRegister, ThreadCreationParameters,
-    ThreadInfo, ThreadPriority,
+    CurrentIds, Exception, NameEntry, SchedEvent, SyscallNumber, ThreadContext,
+    ThreadContextRegister, ThreadCreationParameters, ThreadInfo, ThreadPriority, WatchpointKind,
 };
 
 use crate::SyscallInStr;
 
 use super::{
-    ref_ptr, syscalls::*, sysret_to_result, Handle, SyscallList, SyscallOutPtr, SyscallResult,
+    ref_ptr, syscalls::*, sysret_to_result, ProcessHandle, SyscallList, SyscallOutPtr,
+    SyscallResult, ThreadHandle,
 };
 
-pub fn open_self() -> SyscallResult<Handle> {
-    let mut new_handle = Handle::invalid();
+pub fn open_self() -> SyscallResult<ThreadHandle> {
+    let mut new_handle = ThreadHandle::invalid();
     let ret = unsafe { syscall1(SyscallNumber::ThreadOpenSelf, new_handle.as_syscall_ptr()) };
 
     sysret_to_result(ret)?;
@@ -18,8 +19,19 @@ pub fn open_self() -> SyscallResult<Handle> {
     Ok(new_handle)
 }
 
-pub fn open(tid: u64) -> SyscallResult<Handle> {
-    let mut new_handle = Handle::invalid();
+/// Get the ids of the current thread and of its process, without opening handles
+pub fn get_ids() -> SyscallResult<CurrentIds> {
+    let ids = SyscallOutPtr::new();
+
+    let ret = unsafe { syscall1(SyscallNumber::ThreadGetIds, ids.ptr_arg()) };
+
+    sysret_to_result(ret)?;
+
+    Ok(ids.take())
+}
+
+pub fn open(tid: u64) -> SyscallResult<ThreadHandle> {
+    let mut new_handle = ThreadHandle::invalid();
     let ret = unsafe {
         syscall2(
             SyscallNumber::ThreadOpen,
@@ -35,15 +47,15 @@ pub fn open(tid: u64) -> SyscallResult<Handle> {
 
 pub fn create(
     name: Option<&str>,
-    process: &Handle,
+    process: &ProcessHandle,
     privileged: bool,
     priority: ThreadPriority,
     entry_point: extern "C" fn(usize) -> !,
     stack_top: usize,
     arg: usize,
     tls: usize,
-) -> SyscallResult<Handle> {
-    let mut new_handle = Handle::invalid();
+) -> SyscallResult<ThreadHandle> {
+    let mut new_handle = ThreadHandle::invalid();
     let name_reader = name.map(SyscallInStr::new);
 
     let params = ThreadCreationParameters {
@@ -81,13 +93,13 @@ pub fn exit() -> SyscallResult<()> {
     sysret_to_result(ret)
 }
 
-pub fn kill(thread: &Handle) -> SyscallResult<()> {
+pub fn kill(thread: &ThreadHandle) -> SyscallResult<()> {
     let ret = unsafe { syscall1(SyscallNumber::ThreadKill, thread.as_syscall_value()) };
 
     sysret_to_result(ret)
 }
 
-pub fn set_priority(thread: &Handle, priority: ThreadPriority) -> SyscallResult<()> {
+pub fn set_priority(thread: &ThreadHandle, priority: ThreadPriority) -> SyscallResult<()> {
     let ret = unsafe {
         syscall2(
             SyscallNumber::ThreadSetPriority,
@@ -100,7 +112,7 @@ pub fn set_priority(thread: &Handle, priority: ThreadPriority) -> SyscallResult<
 }
 
 /// Get info about the thread
-pub fn info(thread: &Handle) -> SyscallResult<ThreadInfo> {
+pub fn info(thread: &ThreadHandle) -> SyscallResult<ThreadInfo> {
     let info = SyscallOutPtr::new();
 
     let ret = unsafe {
@@ -116,13 +128,16 @@ pub fn info(thread: &Handle) -> SyscallResult<ThreadInfo> {
     Ok(info.take())
 }
 
-/// Get list of tids living in the system
-pub fn list<'a>(array: &'a mut [u64]) -> SyscallResult<(&'a [u64], usize)> {
+/// Get a page of the tids living in the system, ordered, starting at `start` (included)
+///
+/// Returns the page, and the `start` of the next page if there are more tids than `array` can hold.
+pub fn list(start: u64, array: &mut [u64]) -> SyscallResult<(&[u64], Option<u64>)> {
     let mut list = unsafe { SyscallList::new(array) };
 
     let ret = unsafe {
-        syscall2(
+        syscall3(
             SyscallNumber::ThreadList,
+            start as usize,
             list.array_ptr_arg(),
             list.count_ptr_arg(),
         )
@@ -130,11 +145,11 @@ pub fn list<'a>(array: &'a mut [u64]) -> SyscallResult<(&'a [u64], usize)> {
 
     sysret_to_result(ret)?;
 
-    Ok(list.finalize())
+    Ok(list.finalize_page())
 }
 
 /// Set the thread name
-pub fn set_name(thread: &Handle, name: &str) -> SyscallResult<()> {
+pub fn set_name(thread: &ThreadHandle, name: &str) -> SyscallResult<()> {
     let name_reader = SyscallInStr::new(name);
     let ret = unsafe {
         syscall3(
@@ -152,7 +167,7 @@ pub fn set_name(thread: &Handle, name: &str) -> SyscallResult<()> {
 ///
 /// This can be useful is name is longer than 128 (truncated in info)
 pub fn get_name<'a>(
-    thread: &Handle,
+    thread: &ThreadHandle,
     name_buffer: &'a mut [u8],
 ) -> SyscallResult<(&'a [u8], usize)> {
     let mut list = unsafe { SyscallList::new(name_buffer) };
@@ -173,7 +188,7 @@ pub fn get_name<'a>(
 /// Get the error info of the thread
 ///
 /// Note: the thread must be in error state
-pub fn error_info(thread: &Handle) -> SyscallResult<Exception> {
+pub fn error_info(thread: &ThreadHandle) -> SyscallResult<Exception> {
     let error = SyscallOutPtr::new();
 
     let ret = unsafe {
@@ -191,8 +206,8 @@ pub fn error_info(thread: &Handle) -> SyscallResult<Exception> {
 
 /// Get the context of the thread
 ///
-/// Note: the thread must be in error state
-pub fn context(thread: &Handle) -> SyscallResult<ThreadContext> {
+/// Note: the thread must be in error state, or be another thread of the current process
+pub fn context(thread: &ThreadHandle) -> SyscallResult<ThreadContext> {
     let context = SyscallOutPtr::new();
 
     let ret = unsafe {
@@ -212,7 +227,7 @@ pub fn context(thread: &Handle) -> SyscallResult<ThreadContext> {
 ///
 /// Note: the thread must be in error state
 pub fn update_context(
-    thread: &Handle,
+    thread: &ThreadHandle,
     regs: &[(ThreadContextRegister, usize)],
 ) -> SyscallResult<()> {
     let size = regs.len();
@@ -234,8 +249,119 @@ pub fn update_context(
 /// Resume the execution of the thread
 ///
 /// Note: the thread must be in error state
-pub fn resume(thread: &Handle) -> SyscallResult<()> {
+pub fn resume(thread: &ThreadHandle) -> SyscallResult<()> {
     let ret = unsafe { syscall1(SyscallNumber::ThreadResume, thread.as_syscall_value()) };
 
     sysret_to_result(ret)
 }
+
+/// Set an hardware watchpoint on the thread
+///
+/// Note: cannot be used on the current thread
+pub fn set_watchpoint(
+    thread: &ThreadHandle,
+    index: usize,
+    address: usize,
+    size: usize,
+    kind: WatchpointKind,
+) -> SyscallResult<()> {
+    let ret = unsafe {
+        syscall5(
+            SyscallNumber::ThreadSetWatchpoint,
+            thread.as_syscall_value(),
+            index,
+            address,
+            size,
+            kind as usize,
+        )
+    };
+
+    sysret_to_result(ret)
+}
+
+/// Clear an hardware watchpoint of the thread
+///
+/// Note: cannot be used on the current thread
+pub fn clear_watchpoint(thread: &ThreadHandle, index: usize) -> SyscallResult<()> {
+    let ret = unsafe {
+        syscall2(
+            SyscallNumber::ThreadClearWatchpoint,
+            thread.as_syscall_value(),
+            index,
+        )
+    };
+
+    sysret_to_result(ret)
+}
+
+/// Enable or disable the scheduler tracing (pending events are dropped on disable)
+pub fn sched_trace_enable(enabled: bool) -> SyscallResult<()> {
+    let ret = unsafe { syscall1(SyscallNumber::ThreadSchedTraceEnable, enabled as usize) };
+
+    sysret_to_result(ret)
+}
+
+/// Move the oldest scheduler trace events into `array`
+pub fn sched_trace_read(array: &mut [SchedEvent]) -> SyscallResult<&[SchedEvent]> {
+    let mut list = unsafe { SyscallList::new(array) };
+
+    let ret = unsafe {
+        syscall2(
+            SyscallNumber::ThreadSchedTraceRead,
+            list.array_ptr_arg(),
+            list.count_ptr_arg(),
+        )
+    };
+
+    sysret_to_result(ret)?;
+
+    let (events, _) = list.finalize();
+    Ok(events)
+}
+
+/// Get the names of several threades in one call
+///
+/// Note: `entries` must have the same length as `tids`
+pub fn names(tids: &[u64], entries: &mut [NameEntry]) -> SyscallResult<()> {
+    assert!(tids.len() == entries.len());
+
+    let ret = unsafe {
+        syscall3(
+            SyscallNumber::ThreadNames,
+            tids.as_ptr() as usize,
+            entries.as_mut_ptr() as usize,
+            tids.len(),
+        )
+    };
+
+    sysret_to_result(ret)
+}
+
+/// Start a new request on the current thread: generate a new correlation id and set it as current
+///
+/// The messages sent by the thread are stamped with it, until it receives a message carrying another one.
+pub fn new_correlation() -> SyscallResult<u64> {
+    let correlation = SyscallOutPtr::new();
+
+    let ret = unsafe { syscall1(SyscallNumber::ThreadNewCorrelation, correlation.ptr_arg()) };
+
+    sysret_to_result(ret)?;
+
+    Ok(correlation.take())
+}
+
+/// Set the deadline of the current thread, `timeout` nanoseconds from now (0 to clear it)
+///
+/// The messages sent by the thread are stamped with it, until it receives a message carrying another one.
+pub fn set_deadline(timeout: u64) -> SyscallResult<()> {
+    let ret = unsafe { syscall1(SyscallNumber::ThreadSetDeadline, timeout as usize) };
+
+    sysret_to_result(ret)
+}
+
+/// Check the deadline of the current thread, fails with `Error::DeadlineExceeded` if it has passed
+pub fn check_deadline() -> SyscallResult<()> {
+    let ret = unsafe { syscall0(SyscallNumber::ThreadCheckDeadline) };
+
+    sysret_to_result(ret)
+}
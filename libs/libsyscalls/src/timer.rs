@@ -0,0 +1,76 @@
+use syscalls::SyscallNumber;
+
+use super::{
+    syscalls::*, sysret_to_result, PortSenderHandle, SyscallOutPtr, SyscallResult, TimerHandle,
+    TimerStats,
+};
+
+/// Create a timer, which sends a `TimerEvent` to the port when it fires
+pub fn create(port: &PortSenderHandle) -> SyscallResult<TimerHandle> {
+    let mut new_handle = TimerHandle::invalid();
+    let ret = unsafe {
+        syscall2(
+            SyscallNumber::TimerCreate,
+            port.as_syscall_value(),
+            new_handle.as_syscall_ptr(),
+        )
+    };
+
+    sysret_to_result(ret)?;
+
+    Ok(new_handle)
+}
+
+/// Arm the timer to fire after `delay` nanoseconds, or up to `slack` nanoseconds later
+pub fn arm(timer: &TimerHandle, delay: u64, slack: u64) -> SyscallResult<()> {
+    let ret = unsafe {
+        syscall3(
+            SyscallNumber::TimerArm,
+            timer.as_syscall_value(),
+            delay as usize,
+            slack as usize,
+        )
+    };
+
+    sysret_to_result(ret)
+}
+
+/// Cancel the timer, if it is armed
+pub fn cancel(timer: &TimerHandle) -> SyscallResult<()> {
+    let ret = unsafe { syscall1(SyscallNumber::TimerCancel, timer.as_syscall_value()) };
+
+    sysret_to_result(ret)
+}
+
+/// Get timers statistics
+pub fn stats() -> SyscallResult<TimerStats> {
+    let stats = SyscallOutPtr::new();
+
+    let ret = unsafe { syscall1(SyscallNumber::TimerStats, stats.ptr_arg()) };
+
+    sysret_to_result(ret)?;
+
+    Ok(stats.take())
+}
+
+/// Adjust the wall clock, in nanoseconds since the Unix epoch
+///
+/// Uptime is not affected: the boot time moves instead.
+///
+/// Note: only privileged threads can adjust the wall clock
+pub fn set_wall_clock(value: u64) -> SyscallResult<()> {
+    let ret = unsafe { syscall1(SyscallNumber::ClockSetWall, value as usize) };
+
+    sysret_to_result(ret)
+}
+
+/// Advance the virtual clock by `delay` nanoseconds (rounded up to ticks), firing the timers due on the way
+///
+/// Fails with `NotSupported` if the kernel runs on the real clock (`virtual-clock` kernel feature disabled).
+///
+/// Note: only privileged threads can advance the clock, by at most `MAX_ADVANCE_NS`
+pub fn advance(delay: u64) -> SyscallResult<()> {
+    let ret = unsafe { syscall1(SyscallNumber::ClockAdvance, delay as usize) };
+
+    sysret_to_result(ret)
+}
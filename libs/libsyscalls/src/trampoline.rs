@@ -0,0 +1,69 @@
+use syscalls::{SyscallNumber, ThreadPriority, TrampolineParameters};
+
+use super::{
+    ref_ptr, syscalls::*, sysret_to_result, SyscallInStr, SyscallResult, ThreadHandle,
+    TrampolineHandle,
+};
+
+/// Create a trampoline: an entry point of the current process, authorized for threads created by other processes
+///
+/// Threads created from it start at `entry_point` with `stack_top`, `tls` and `priority`:
+/// the process which spawns them only chooses their argument.
+pub fn create(
+    priority: ThreadPriority,
+    entry_point: extern "C" fn(usize) -> !,
+    stack_top: usize,
+    tls: usize,
+) -> SyscallResult<TrampolineHandle> {
+    let mut new_handle = TrampolineHandle::invalid();
+
+    let params = TrampolineParameters {
+        priority,
+        entry_point: entry_point as usize,
+        stack_top,
+        tls,
+    };
+
+    let ret = unsafe {
+        syscall2(
+            SyscallNumber::TrampolineCreate,
+            ref_ptr(&params),
+            new_handle.as_syscall_ptr(),
+        )
+    };
+
+    sysret_to_result(ret)?;
+
+    Ok(new_handle)
+}
+
+/// Create a thread in the process which created the trampoline, starting at its entry point with `arg`
+///
+/// Fails with `ObjectNotReady` if the previous thread created from the trampoline is still alive.
+pub fn spawn(
+    trampoline: &TrampolineHandle,
+    name: Option<&str>,
+    arg: usize,
+) -> SyscallResult<ThreadHandle> {
+    let mut new_handle = ThreadHandle::invalid();
+    let name_reader = name.map(SyscallInStr::new);
+
+    let (ptr, len) = name_reader.as_ref().map_or((0, 0), |reader| unsafe {
+        (reader.ptr_arg(), reader.len_arg())
+    });
+
+    let ret = unsafe {
+        syscall5(
+            SyscallNumber::TrampolineSpawn,
+            trampoline.as_syscall_value(),
+            ptr,
+            len,
+            arg,
+            new_handle.as_syscall_ptr(),
+        )
+    };
+
+    sysret_to_result(ret)?;
+
+    Ok(new_handle)
+}
@@ -0,0 +1,79 @@
+/// Color of a cell (ANSI palette)
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Color {
+    /// Default foreground or background of the host
+    #[default]
+    Default,
+    Black,
+    Red,
+    Green,
+    Yellow,
+    Blue,
+    Magenta,
+    Cyan,
+    White,
+    BrightBlack,
+    BrightRed,
+    BrightGreen,
+    BrightYellow,
+    BrightBlue,
+    BrightMagenta,
+    BrightCyan,
+    BrightWhite,
+}
+
+impl Color {
+    /// Get a color from its ANSI index (0 to 15)
+    pub fn from_index(index: u16) -> Self {
+        const COLORS: [Color; 16] = [
+            Color::Black,
+            Color::Red,
+            Color::Green,
+            Color::Yellow,
+            Color::Blue,
+            Color::Magenta,
+            Color::Cyan,
+            Color::White,
+            Color::BrightBlack,
+            Color::BrightRed,
+            Color::BrightGreen,
+            Color::BrightYellow,
+            Color::BrightBlue,
+            Color::BrightMagenta,
+            Color::BrightCyan,
+            Color::BrightWhite,
+        ];
+
+        COLORS
+            .get(index as usize)
+            .copied()
+            .unwrap_or(Color::Default)
+    }
+}
+
+/// Display attributes of a cell
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Attributes {
+    pub foreground: Color,
+    pub background: Color,
+    pub bold: bool,
+    pub underline: bool,
+    /// Foreground and background are swapped
+    pub reverse: bool,
+}
+
+/// Character cell of the grid
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Cell {
+    pub c: char,
+    pub attrs: Attributes,
+}
+
+impl Default for Cell {
+    fn default() -> Self {
+        Self {
+            c: ' ',
+            attrs: Attributes::default(),
+        }
+    }
+}
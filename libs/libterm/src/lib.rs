@@ -0,0 +1,26 @@
+#![no_std]
+
+//! Terminal emulation core
+//!
+//! Interprets the output stream of a program (VT100 subset) into a grid of cells, with scrollback and selection.
+//! Rendering and input are left to the host (eg: a terminal program drawing on a display surface).
+//!
+//! ```ignore
+//! let mut terminal = Terminal::new(80, 25, 1000);
+//! terminal.write(b"\x1b[1;31mhello\x1b[0m world\r\n");
+//!
+//! for row in 0..terminal.rows() {
+//!     for cell in terminal.visible_line(row) {
+//!         draw(cell.c, cell.attrs);
+//!     }
+//! }
+//! ```
+
+extern crate alloc;
+
+mod cell;
+mod parser;
+mod terminal;
+
+pub use cell::{Attributes, Cell, Color};
+pub use terminal::{Position, Terminal};
@@ -0,0 +1,177 @@
+/// Max number of CSI parameters kept, others are ignored
+const MAX_PARAMS: usize = 16;
+
+/// Escape sequence element decoded from the stream
+#[derive(Debug, PartialEq, Eq)]
+pub enum Action<'a> {
+    /// Printable character
+    Print(char),
+    /// C0 control (eg: `\n`, `\r`, `\x08`)
+    Execute(u8),
+    /// `ESC [ params final`. Missing parameters are 0.
+    Csi {
+        params: &'a [u16],
+        /// `ESC [ ?` (DEC private mode)
+        private: bool,
+        action: u8,
+    },
+    /// `ESC final`
+    Esc(u8),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum State {
+    Ground,
+    Escape,
+    Csi,
+    /// Unsupported sequence (eg: OSC, DCS): ignore until its end
+    String,
+}
+
+/// Incremental parser of a VT100 stream
+///
+/// Bytes can be fed in any chunks: a sequence (or UTF-8 character) can be split across calls.
+#[derive(Debug)]
+pub struct Parser {
+    state: State,
+    params: [u16; MAX_PARAMS],
+    param_count: usize,
+    private: bool,
+    utf8: [u8; 4],
+    utf8_len: usize,
+    utf8_expected: usize,
+}
+
+impl Parser {
+    pub const fn new() -> Self {
+        Self {
+            state: State::Ground,
+            params: [0; MAX_PARAMS],
+            param_count: 0,
+            private: false,
+            utf8: [0; 4],
+            utf8_len: 0,
+            utf8_expected: 0,
+        }
+    }
+
+    pub fn advance(&mut self, byte: u8, perform: &mut impl FnMut(Action)) {
+        match self.state {
+            State::Ground => self.ground(byte, perform),
+            State::Escape => self.escape(byte, perform),
+            State::Csi => self.csi(byte, perform),
+            State::String => {
+                // Terminated by BEL or ST (ESC \)
+                match byte {
+                    0x07 => self.state = State::Ground,
+                    0x1B => self.state = State::Escape,
+                    _ => {}
+                }
+            }
+        }
+    }
+
+    fn ground(&mut self, byte: u8, perform: &mut impl FnMut(Action)) {
+        if self.utf8_expected > 0 {
+            if byte & 0xC0 == 0x80 {
+                self.utf8[self.utf8_len] = byte;
+                self.utf8_len += 1;
+
+                if self.utf8_len == self.utf8_expected {
+                    self.utf8_expected = 0;
+                    let c = core::str::from_utf8(&self.utf8[..self.utf8_len])
+                        .ok()
+                        .and_then(|s| s.chars().next())
+                        .unwrap_or(char::REPLACEMENT_CHARACTER);
+                    perform(Action::Print(c));
+                }
+
+                return;
+            }
+
+            // Truncated character
+            self.utf8_expected = 0;
+            perform(Action::Print(char::REPLACEMENT_CHARACTER));
+        }
+
+        match byte {
+            0x1B => {
+                self.state = State::Escape;
+            }
+            0x00..=0x1F | 0x7F => perform(Action::Execute(byte)),
+            0x20..=0x7E => perform(Action::Print(byte as char)),
+            _ => {
+                let expected = match byte {
+                    0xC0..=0xDF => 2,
+                    0xE0..=0xEF => 3,
+                    0xF0..=0xF7 => 4,
+                    _ => {
+                        perform(Action::Print(char::REPLACEMENT_CHARACTER));
+                        return;
+                    }
+                };
+
+                self.utf8[0] = byte;
+                self.utf8_len = 1;
+                self.utf8_expected = expected;
+            }
+        }
+    }
+
+    fn escape(&mut self, byte: u8, perform: &mut impl FnMut(Action)) {
+        match byte {
+            b'[' => {
+                self.params = [0; MAX_PARAMS];
+                self.param_count = 0;
+                self.private = false;
+                self.state = State::Csi;
+            }
+            b']' | b'P' | b'_' | b'^' => self.state = State::String,
+            // String terminator, after an ignored string
+            b'\\' => self.state = State::Ground,
+            _ => {
+                self.state = State::Ground;
+                perform(Action::Esc(byte));
+            }
+        }
+    }
+
+    fn csi(&mut self, byte: u8, perform: &mut impl FnMut(Action)) {
+        match byte {
+            b'0'..=b'9' => {
+                if self.param_count == 0 {
+                    self.param_count = 1;
+                }
+
+                if let Some(param) = self.params.get_mut(self.param_count - 1) {
+                    *param = param
+                        .saturating_mul(10)
+                        .saturating_add((byte - b'0') as u16);
+                }
+            }
+            b';' => {
+                if self.param_count == 0 {
+                    self.param_count = 1;
+                }
+
+                self.param_count += 1;
+            }
+            b'?' => self.private = true,
+            0x40..=0x7E => {
+                self.state = State::Ground;
+
+                let count = self.param_count.min(MAX_PARAMS);
+                perform(Action::Csi {
+                    params: &self.params[..count],
+                    private: self.private,
+                    action: byte,
+                });
+            }
+            // Controls are executed in the middle of sequences
+            0x00..=0x1A | 0x1C..=0x1F => perform(Action::Execute(byte)),
+            0x1B => self.state = State::Escape,
+            // Intermediate bytes: not supported, kept in the sequence
+            _ => {}
+        }
+    }
+}
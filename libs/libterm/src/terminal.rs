@@ -0,0 +1,611 @@
+use alloc::{collections::VecDeque, string::String, vec, vec::Vec};
+use core::{cmp::Ordering, mem};
+
+use crate::{
+    cell::{Attributes, Cell, Color},
+    parser::{Action, Parser},
+};
+
+const TAB_WIDTH: usize = 8;
+
+/// Position in the terminal lines, scrollback included
+///
+/// Lines are numbered from the oldest line kept in the scrollback.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Position {
+    pub line: usize,
+    pub column: usize,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct SavedCursor {
+    column: usize,
+    row: usize,
+    attrs: Attributes,
+}
+
+/// Terminal state: screen grid, scrollback, cursor and selection
+#[derive(Debug)]
+pub struct Terminal {
+    columns: usize,
+    rows: usize,
+    scrollback_limit: usize,
+    /// Scrollback lines, followed by the `rows` lines of the screen
+    lines: VecDeque<Vec<Cell>>,
+    /// Cursor position on the screen
+    column: usize,
+    row: usize,
+    /// The cursor is past the last column: the next printed character wraps to the next line
+    wrap_pending: bool,
+    cursor_visible: bool,
+    attrs: Attributes,
+    saved_cursor: SavedCursor,
+    parser: Parser,
+    /// Number of lines the view is scrolled back from the screen
+    view_offset: usize,
+    /// Anchor and end of the selection
+    selection: Option<(Position, Position)>,
+}
+
+impl Terminal {
+    /// Create a terminal of `columns` x `rows` characters, keeping up to `scrollback_limit` lines scrolled out
+    pub fn new(columns: usize, rows: usize, scrollback_limit: usize) -> Self {
+        assert!(columns > 0 && rows > 0);
+
+        let mut lines = VecDeque::new();
+        lines.resize(rows, vec![Cell::default(); columns]);
+
+        Self {
+            columns,
+            rows,
+            scrollback_limit,
+            lines,
+            column: 0,
+            row: 0,
+            wrap_pending: false,
+            cursor_visible: true,
+            attrs: Attributes::default(),
+            saved_cursor: SavedCursor {
+                column: 0,
+                row: 0,
+                attrs: Attributes::default(),
+            },
+            parser: Parser::new(),
+            view_offset: 0,
+            selection: None,
+        }
+    }
+
+    pub fn columns(&self) -> usize {
+        self.columns
+    }
+
+    pub fn rows(&self) -> usize {
+        self.rows
+    }
+
+    /// Number of lines in the scrollback
+    pub fn scrollback_len(&self) -> usize {
+        self.lines.len() - self.rows
+    }
+
+    /// Get the cursor position on the view (column, row), if it is visible
+    pub fn cursor(&self) -> Option<(usize, usize)> {
+        if !self.cursor_visible || self.row + self.view_offset >= self.rows {
+            return None;
+        }
+
+        Some((self.column, self.row + self.view_offset))
+    }
+
+    /// Feed the output of the hosted program
+    pub fn write(&mut self, data: &[u8]) {
+        let mut parser = mem::replace(&mut self.parser, Parser::new());
+
+        for &byte in data {
+            parser.advance(byte, &mut |action| self.perform(action));
+        }
+
+        self.parser = parser;
+    }
+
+    /// Get a line of the view
+    ///
+    /// The view is the screen, or a part of the scrollback if it has been scrolled back.
+    pub fn visible_line(&self, row: usize) -> &[Cell] {
+        &self.lines[self.view_line(row)]
+    }
+
+    /// Get the position of a cell of the view (eg: to start a selection from a mouse click)
+    pub fn visible_position(&self, column: usize, row: usize) -> Position {
+        Position {
+            line: self.view_line(row),
+            column: column.min(self.columns - 1),
+        }
+    }
+
+    /// Scroll the view back (positive `delta`) into the scrollback, or forward (negative `delta`)
+    pub fn scroll_view(&mut self, delta: isize) {
+        let offset = self.view_offset as isize + delta;
+        self.view_offset = offset.clamp(0, self.scrollback_len() as isize) as usize;
+    }
+
+    /// Scroll the view back to the screen
+    pub fn reset_view(&mut self) {
+        self.view_offset = 0;
+    }
+
+    /// Resize the terminal
+    ///
+    /// Lines are truncated or extended, they are not re-wrapped.
+    pub fn resize(&mut self, columns: usize, rows: usize) {
+        assert!(columns > 0 && rows > 0);
+
+        for line in self.lines.iter_mut() {
+            line.resize(columns, Cell::default());
+        }
+
+        match rows.cmp(&self.rows) {
+            Ordering::Greater => {
+                // Extend the screen at the bottom
+                for _ in self.rows..rows {
+                    self.lines.push_back(vec![Cell::default(); columns]);
+                }
+            }
+            Ordering::Less => {
+                // Remove empty lines below the cursor, then push the top of the screen into the scrollback
+                let mut remove = self.rows - rows;
+                let mut last_row = self.rows - 1;
+
+                while remove > 0 && self.row < last_row && self.is_blank(self.lines.len() - 1) {
+                    self.lines.pop_back();
+                    last_row -= 1;
+                    remove -= 1;
+                }
+
+                // The cursor line must stay on the screen: if there are not enough lines above it, the bottom of the screen is lost
+                for _ in self.row..remove {
+                    self.lines.pop_back();
+                }
+
+                self.row = self.row.saturating_sub(remove);
+            }
+            Ordering::Equal => {}
+        }
+
+        self.columns = columns;
+        self.rows = rows;
+        self.column = self.column.min(columns - 1);
+        self.row = self.row.min(rows - 1);
+        self.wrap_pending = false;
+        self.view_offset = 0;
+        self.selection = None;
+        self.trim_scrollback();
+    }
+
+    /// Set the selection, from `anchor` to `end` (in any order)
+    pub fn select(&mut self, anchor: Position, end: Position) {
+        self.selection = Some((anchor, end));
+    }
+
+    pub fn clear_selection(&mut self) {
+        self.selection = None;
+    }
+
+    /// Check if a cell is selected
+    pub fn is_selected(&self, position: Position) -> bool {
+        self.selection_range()
+            .map_or(false, |(start, end)| start <= position && position <= end)
+    }
+
+    /// Get the text of the selection
+    ///
+    /// Trailing spaces of lines are removed, lines are separated by `\n`.
+    pub fn selected_text(&self) -> Option<String> {
+        let (start, end) = self.selection_range()?;
+        let mut text = String::new();
+
+        for line_index in start.line..=end.line {
+            let line = &self.lines[line_index];
+
+            let first = if line_index == start.line {
+                start.column
+            } else {
+                0
+            };
+
+            let last = if line_index == end.line {
+                end.column.min(self.columns - 1)
+            } else {
+                self.columns - 1
+            };
+
+            let content: String = line[first..=last].iter().map(|cell| cell.c).collect();
+            text.push_str(content.trim_end());
+
+            if line_index != end.line {
+                text.push('\n');
+            }
+        }
+
+        Some(text)
+    }
+
+    fn selection_range(&self) -> Option<(Position, Position)> {
+        let (anchor, end) = self.selection?;
+        Some((anchor.min(end), anchor.max(end)))
+    }
+
+    fn view_line(&self, row: usize) -> usize {
+        assert!(row < self.rows);
+        self.lines.len() - self.rows - self.view_offset + row
+    }
+
+    fn screen_line(&mut self, row: usize) -> &mut Vec<Cell> {
+        let index = self.lines.len() - self.rows + row;
+        &mut self.lines[index]
+    }
+
+    fn is_blank(&self, index: usize) -> bool {
+        self.lines[index]
+            .iter()
+            .all(|cell| *cell == Cell::default())
+    }
+
+    fn blank(&self) -> Cell {
+        Cell {
+            c: ' ',
+            attrs: Attributes {
+                background: self.attrs.background,
+                ..Attributes::default()
+            },
+        }
+    }
+
+    fn perform(&mut self, action: Action) {
+        match action {
+            Action::Print(c) => self.print(c),
+            Action::Execute(byte) => self.execute(byte),
+            Action::Esc(byte) => self.esc(byte),
+            Action::Csi {
+                params,
+                private,
+                action,
+            } => self.csi(params, private, action),
+        }
+    }
+
+    fn print(&mut self, c: char) {
+        if self.wrap_pending {
+            self.wrap_pending = false;
+            self.column = 0;
+            self.line_feed();
+        }
+
+        let attrs = self.attrs;
+        let column = self.column;
+        self.screen_line(self.row)[column] = Cell { c, attrs };
+
+        if self.column + 1 < self.columns {
+            self.column += 1;
+        } else {
+            self.wrap_pending = true;
+        }
+    }
+
+    fn execute(&mut self, byte: u8) {
+        match byte {
+            b'\n' | 0x0B | 0x0C => self.line_feed(),
+            b'\r' => self.carriage_return(),
+            0x08 => self.move_to(self.column.saturating_sub(1), self.row),
+            b'\t' => {
+                let column = (self.column / TAB_WIDTH + 1) * TAB_WIDTH;
+                self.move_to(column.min(self.columns - 1), self.row);
+            }
+            // BEL, and other controls: ignored
+            _ => {}
+        }
+    }
+
+    fn esc(&mut self, byte: u8) {
+        match byte {
+            b'7' => self.save_cursor(),
+            b'8' => self.restore_cursor(),
+            b'D' => self.line_feed(),
+            b'E' => {
+                self.carriage_return();
+                self.line_feed();
+            }
+            b'M' => self.reverse_line_feed(),
+            b'c' => self.reset(),
+            _ => {}
+        }
+    }
+
+    fn csi(&mut self, params: &[u16], private: bool, action: u8) {
+        // Get a parameter, with a default value if it is missing or 0
+        let param = |index: usize, default: usize| match params.get(index) {
+            Some(&value) if value > 0 => value as usize,
+            _ => default,
+        };
+
+        if private {
+            // DECTCEM: cursor visibility. Other private modes are ignored.
+            if params.contains(&25) {
+                match action {
+                    b'h' => self.cursor_visible = true,
+                    b'l' => self.cursor_visible = false,
+                    _ => {}
+                }
+            }
+
+            return;
+        }
+
+        match action {
+            b'A' => self.move_to(self.column, self.row.saturating_sub(param(0, 1))),
+            b'B' => self.move_to(self.column, self.row + param(0, 1)),
+            b'C' => self.move_to(self.column + param(0, 1), self.row),
+            b'D' => self.move_to(self.column.saturating_sub(param(0, 1)), self.row),
+            b'E' => self.move_to(0, self.row + param(0, 1)),
+            b'F' => self.move_to(0, self.row.saturating_sub(param(0, 1))),
+            b'G' => self.move_to(param(0, 1) - 1, self.row),
+            b'd' => self.move_to(self.column, param(0, 1) - 1),
+            b'H' | b'f' => self.move_to(param(1, 1) - 1, param(0, 1) - 1),
+            b'J' => self.erase_display(params.first().copied().unwrap_or(0)),
+            b'K' => self.erase_line(params.first().copied().unwrap_or(0)),
+            b'X' => {
+                let blank = self.blank();
+                let end = (self.column + param(0, 1)).min(self.columns);
+                let column = self.column;
+                self.screen_line(self.row)[column..end].fill(blank);
+            }
+            b'm' => self.select_graphic_rendition(params),
+            b's' => self.save_cursor(),
+            b'u' => self.restore_cursor(),
+            _ => {}
+        }
+    }
+
+    fn select_graphic_rendition(&mut self, params: &[u16]) {
+        // `ESC [ m` is a reset
+        if params.is_empty() {
+            self.attrs = Attributes::default();
+            return;
+        }
+
+        for &param in params {
+            match param {
+                0 => self.attrs = Attributes::default(),
+                1 => self.attrs.bold = true,
+                4 => self.attrs.underline = true,
+                7 => self.attrs.reverse = true,
+                22 => self.attrs.bold = false,
+                24 => self.attrs.underline = false,
+                27 => self.attrs.reverse = false,
+                30..=37 => self.attrs.foreground = Color::from_index(param - 30),
+                39 => self.attrs.foreground = Color::Default,
+                40..=47 => self.attrs.background = Color::from_index(param - 40),
+                49 => self.attrs.background = Color::Default,
+                90..=97 => self.attrs.foreground = Color::from_index(param - 90 + 8),
+                100..=107 => self.attrs.background = Color::from_index(param - 100 + 8),
+                _ => {}
+            }
+        }
+    }
+
+    fn move_to(&mut self, column: usize, row: usize) {
+        self.column = column.min(self.columns - 1);
+        self.row = row.min(self.rows - 1);
+        self.wrap_pending = false;
+    }
+
+    fn carriage_return(&mut self) {
+        self.column = 0;
+        self.wrap_pending = false;
+    }
+
+    fn line_feed(&mut self) {
+        self.wrap_pending = false;
+
+        if self.row + 1 < self.rows {
+            self.row += 1;
+            return;
+        }
+
+        // Scroll up: the top line of the screen goes into the scrollback
+        self.lines.push_back(vec![self.blank(); self.columns]);
+
+        if self.view_offset > 0 {
+            // Keep the view on the same lines
+            self.view_offset += 1;
+        }
+
+        self.trim_scrollback();
+    }
+
+    fn reverse_line_feed(&mut self) {
+        self.wrap_pending = false;
+
+        if self.row > 0 {
+            self.row -= 1;
+            return;
+        }
+
+        // Scroll down: the bottom line of the screen is lost
+        let top = self.lines.len() - self.rows;
+        self.lines.pop_back();
+        self.lines.insert(top, vec![self.blank(); self.columns]);
+    }
+
+    fn trim_scrollback(&mut self) {
+        while self.scrollback_len() > self.scrollback_limit {
+            self.lines.pop_front();
+
+            // Positions are relative to the oldest line
+            self.selection = self.selection.and_then(|(anchor, end)| {
+                if anchor.line == 0 || end.line == 0 {
+                    return None;
+                }
+
+                let shift = |position: Position| Position {
+                    line: position.line - 1,
+                    column: position.column,
+                };
+                Some((shift(anchor), shift(end)))
+            });
+        }
+
+        self.view_offset = self.view_offset.min(self.scrollback_len());
+    }
+
+    fn erase_display(&mut self, mode: u16) {
+        let blank = self.blank();
+
+        match mode {
+            // From cursor to end of screen
+            0 => {
+                self.erase_line(0);
+                for row in self.row + 1..self.rows {
+                    self.screen_line(row).fill(blank);
+                }
+            }
+            // From start of screen to cursor
+            1 => {
+                self.erase_line(1);
+                for row in 0..self.row {
+                    self.screen_line(row).fill(blank);
+                }
+            }
+            // Whole screen
+            2 | 3 => {
+                for row in 0..self.rows {
+                    self.screen_line(row).fill(blank);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    fn erase_line(&mut self, mode: u16) {
+        let blank = self.blank();
+        let column = self.column;
+        let line = self.screen_line(self.row);
+
+        match mode {
+            0 => line[column..].fill(blank),
+            1 => line[..=column].fill(blank),
+            2 => line.fill(blank),
+            _ => {}
+        }
+    }
+
+    fn save_cursor(&mut self) {
+        self.saved_cursor = SavedCursor {
+            column: self.column,
+            row: self.row,
+            attrs: self.attrs,
+        };
+    }
+
+    fn restore_cursor(&mut self) {
+        let saved = self.saved_cursor;
+        self.move_to(saved.column, saved.row);
+        self.attrs = saved.attrs;
+    }
+
+    fn reset(&mut self) {
+        *self = Self::new(self.columns, self.rows, self.scrollback_limit);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn line_text(terminal: &Terminal, row: usize) -> String {
+        let text: String = terminal
+            .visible_line(row)
+            .iter()
+            .map(|cell| cell.c)
+            .collect();
+        String::from(text.trim_end())
+    }
+
+    fn screen_text(terminal: &Terminal) -> Vec<String> {
+        (0..terminal.rows())
+            .map(|row| line_text(terminal, row))
+            .collect()
+    }
+
+    /// 10x5 terminal, with a line on each row
+    fn full_terminal() -> Terminal {
+        let mut terminal = Terminal::new(10, 5, 100);
+        terminal.write(b"l0\r\nl1\r\nl2\r\nl3\r\nl4");
+        terminal
+    }
+
+    #[test]
+    fn shrink_with_cursor_at_top() {
+        let mut terminal = full_terminal();
+        terminal.write(b"\x1b[H");
+
+        terminal.resize(10, 2);
+
+        assert_eq!(terminal.cursor(), Some((0, 0)));
+        assert_eq!(screen_text(&terminal), ["l0", "l1"]);
+        assert_eq!(terminal.scrollback_len(), 0);
+    }
+
+    #[test]
+    fn shrink_with_content_below_cursor() {
+        let mut terminal = full_terminal();
+        terminal.write(b"\x1b[2;1H");
+
+        terminal.resize(10, 2);
+
+        // One line above the cursor goes to the scrollback, the rest is lost at the bottom
+        assert_eq!(terminal.cursor(), Some((0, 0)));
+        assert_eq!(screen_text(&terminal), ["l1", "l2"]);
+        assert_eq!(terminal.scrollback_len(), 1);
+        assert_eq!(line_text(&terminal, 0), "l1");
+
+        terminal.scroll_view(1);
+        assert_eq!(line_text(&terminal, 0), "l0");
+    }
+
+    #[test]
+    fn shrink_with_cursor_at_bottom() {
+        let mut terminal = full_terminal();
+
+        terminal.resize(10, 2);
+
+        assert_eq!(terminal.cursor(), Some((2, 1)));
+        assert_eq!(screen_text(&terminal), ["l3", "l4"]);
+        assert_eq!(terminal.scrollback_len(), 3);
+    }
+
+    #[test]
+    fn shrink_removes_blank_lines_below_cursor() {
+        let mut terminal = Terminal::new(10, 5, 100);
+        terminal.write(b"l0\r\nl1");
+
+        terminal.resize(10, 2);
+
+        assert_eq!(terminal.cursor(), Some((2, 1)));
+        assert_eq!(screen_text(&terminal), ["l0", "l1"]);
+        assert_eq!(terminal.scrollback_len(), 0);
+    }
+
+    #[test]
+    fn shrink_then_write() {
+        let mut terminal = full_terminal();
+        terminal.write(b"\x1b[H");
+
+        terminal.resize(10, 2);
+        terminal.write(b"\r\n\r\nnew");
+
+        assert_eq!(terminal.cursor(), Some((3, 1)));
+        assert_eq!(screen_text(&terminal), ["l1", "new"]);
+    }
+}
@@ -0,0 +1,213 @@
+//! Test fixtures: throwaway servers for integration tests
+//!
+//! A test binary spawns private instances of the servers it needs, as threads of its own process,
+//! each one listening on a private port name. Client libraries connect to them with `connect_to(name)`
+//! instead of the system instances booted by init, so that tests are isolated from each other and from the system.
+//!
+//! ```ignore
+//! let mut fixture = Fixture::new();
+//! let clipboard = fixture.spawn("clipboard", |ports| {
+//!     while let Some(message) = ports.next() {
+//!         // process the request
+//!     }
+//! })?;
+//! let client = Clipboard::connect_to(clipboard.name())?;
+//! // ...
+//! drop(fixture); // stops the servers, in reverse order
+//! ```
+
+#![no_std]
+
+extern crate alloc;
+
+pub mod transcript;
+
+use core::sync::atomic::{AtomicU64, Ordering};
+
+use alloc::{format, string::String, vec::Vec};
+use libruntime::kobject::{
+    Error, Message, Port, PortReceiver, PortSender, Process, Thread, ThreadEventType,
+    ThreadListener, ThreadListenerFilter, ThreadOptions, ThreadState, Waiter,
+};
+use log::{debug, warn};
+
+static NEXT_INSTANCE: AtomicU64 = AtomicU64::new(1);
+
+/// Get a port name private to this process, for an instance of a server
+///
+/// Format: `test:<pid>:<instance>:<name>`
+pub fn private_name(name: &str) -> String {
+    format!(
+        "test:{}:{}:{}",
+        Process::current().pid(),
+        NEXT_INSTANCE.fetch_add(1, Ordering::Relaxed),
+        name
+    )
+}
+
+/// Ports given to the body of a test server
+#[derive(Debug)]
+pub struct ServerPorts {
+    /// Requests of the clients, on the private port of the server
+    pub requests: PortReceiver,
+    shutdown: PortReceiver,
+}
+
+impl ServerPorts {
+    /// Block until the next request, returns `None` when the server must stop
+    pub fn next(&self) -> Option<Message> {
+        loop {
+            let mut waiter = Waiter::new(&[&self.requests, &self.shutdown]);
+            if let Err(err) = waiter.wait() {
+                warn!("Test server: could not wait: {:?}", err);
+                return None;
+            }
+
+            if waiter.is_ready(1) {
+                return None;
+            }
+
+            match self.requests.receive() {
+                Ok(message) => return Some(message),
+                Err(Error::ObjectNotReady) => continue,
+                Err(err) => {
+                    warn!("Test server: could not receive: {:?}", err);
+                    return None;
+                }
+            }
+        }
+    }
+}
+
+/// Server instance running in a thread of the test process
+///
+/// It is stopped when dropped: the body gets `None` from `ServerPorts::next`, and the drop waits for the thread to terminate.
+#[derive(Debug)]
+pub struct TestServer {
+    name: String,
+    thread: Thread,
+    shutdown: Option<PortSender>,
+}
+
+impl TestServer {
+    /// Start a server: `body` runs in a new thread, with the ports of the server
+    pub fn spawn<Body: FnOnce(ServerPorts) + 'static>(
+        name: &str,
+        body: Body,
+    ) -> Result<Self, Error> {
+        let port_name = private_name(name);
+        let (requests, _) = Port::create(Some(&port_name))?;
+        let (shutdown_receiver, shutdown) = Port::create(None)?;
+
+        let ports = ServerPorts {
+            requests,
+            shutdown: shutdown_receiver,
+        };
+
+        let mut options = ThreadOptions::default();
+        options.name(name);
+        let thread = Thread::start(move || body(ports), options)?;
+
+        debug!("Test server '{}' started (tid={})", port_name, thread.tid());
+
+        Ok(Self {
+            name: port_name,
+            thread,
+            shutdown: Some(shutdown),
+        })
+    }
+
+    /// Get the private port name of the server, for the clients to connect to it
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Open the port of the server
+    pub fn connect(&self) -> Result<PortSender, Error> {
+        Port::open(&self.name)
+    }
+
+    /// Stop the server, and wait for its thread to terminate
+    pub fn stop(mut self) -> Result<(), Error> {
+        self.shutdown()
+    }
+
+    fn terminated(&self) -> bool {
+        self.thread.info().state == ThreadState::Terminated
+    }
+
+    fn shutdown(&mut self) -> Result<(), Error> {
+        let Some(shutdown) = self.shutdown.take() else {
+            return Ok(());
+        };
+
+        // Listen before sending the shutdown, so that the termination event cannot be missed
+        let tid = self.thread.tid();
+        let listener = ThreadListener::create(ThreadListenerFilter::Tids(&[tid]))?;
+
+        let mut message = unsafe { Message::new(&0u64, &mut []) };
+        if let Err(err) = shutdown.send(&mut message) {
+            // The server may have terminated by itself: its shutdown port is then closed
+            if !self.terminated() {
+                return Err(err);
+            }
+        }
+
+        // It may also have terminated before the listener was created
+        while !self.terminated() {
+            let event = listener.blocking_receive()?;
+            if event.tid == tid && event.r#type == ThreadEventType::Terminated {
+                break;
+            }
+        }
+
+        debug!("Test server '{}' stopped", self.name);
+        Ok(())
+    }
+}
+
+impl Drop for TestServer {
+    fn drop(&mut self) {
+        if let Err(err) = self.shutdown() {
+            warn!("Could not stop test server '{}': {:?}", self.name, err);
+        }
+    }
+}
+
+/// Set of test servers, torn down together in reverse order of creation
+///
+/// Servers started later may depend on the earlier ones (eg: a vfs on top of a memfs), so they are stopped first.
+#[derive(Debug, Default)]
+pub struct Fixture {
+    servers: Vec<TestServer>,
+}
+
+impl Fixture {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Start a server in the fixture (see `TestServer::spawn`)
+    pub fn spawn<Body: FnOnce(ServerPorts) + 'static>(
+        &mut self,
+        name: &str,
+        body: Body,
+    ) -> Result<&TestServer, Error> {
+        let server = TestServer::spawn(name, body)?;
+        self.servers.push(server);
+        Ok(self.servers.last().unwrap())
+    }
+
+    /// Stop all the servers
+    pub fn teardown(&mut self) {
+        while let Some(server) = self.servers.pop() {
+            drop(server);
+        }
+    }
+}
+
+impl Drop for Fixture {
+    fn drop(&mut self) {
+        self.teardown();
+    }
+}
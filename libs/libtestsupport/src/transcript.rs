@@ -0,0 +1,392 @@
+//! Golden transcripts of IPC protocols
+//!
+//! A transcript is the canonical record of a session between clients and a server: the requests in order, each one with its reply.
+//! It is recorded once against a known good server (eg: by `servers/trace-proxy`), committed with the tests,
+//! then replayed against the new implementation after a protocol refactoring: each reply must match the recorded one.
+//!
+//! Only the deterministic parts of the messages are kept: the data, and the types of the handles.
+//! Correlation ids, deadlines, idempotency tokens, sender pids and timestamps differ from one run to the other, so they are dropped.
+//!
+//! Text format, one message per line (data words in hex, trailing zero words omitted):
+//!
+//! ```text
+//! # comment
+//! > #0 data=2 handles=0:PortSender
+//! < #0 data=0,5,1 handles=0:MemoryObject
+//! ```
+//!
+//! `>` is a request, `<` the reply to the request with the same exchange number.
+//! Replies may come in any order (eg: extracted from the log of a proxy), they are matched by number.
+
+use core::fmt;
+
+use alloc::{boxed::Box, string::String, vec::Vec};
+use libruntime::kobject::{Error, HandleType, Message, Port, PortSender, Timer, Waiter};
+
+/// Data items of a message
+pub const DATA_ITEMS: usize = Message::DATA_SIZE / 8;
+
+const HANDLE_TYPES: [HandleType; 12] = [
+    HandleType::Invalid,
+    HandleType::MemoryObject,
+    HandleType::Process,
+    HandleType::Thread,
+    HandleType::PortSender,
+    HandleType::PortReceiver,
+    HandleType::ProcessListener,
+    HandleType::ThreadListener,
+    HandleType::Timer,
+    HandleType::PortListener,
+    HandleType::Grant,
+    HandleType::Trampoline,
+];
+
+/// Canonical record of a message
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Record {
+    pub data: [u64; DATA_ITEMS],
+    pub handles: [HandleType; Message::HANDLE_COUNT],
+}
+
+impl Record {
+    /// Record a message
+    pub fn new(message: &Message) -> Self {
+        let data = unsafe { *message.data::<[u64; DATA_ITEMS]>() };
+
+        let mut handles = [HandleType::Invalid; Message::HANDLE_COUNT];
+        for (index, handle) in message.handles.iter().enumerate() {
+            handles[index] = handle.r#type();
+        }
+
+        Self { data, handles }
+    }
+
+    fn parse(text: &str) -> Option<Self> {
+        let mut record = Self {
+            data: [0; DATA_ITEMS],
+            handles: [HandleType::Invalid; Message::HANDLE_COUNT],
+        };
+
+        for field in text.split_whitespace() {
+            if let Some(data) = field.strip_prefix("data=") {
+                for (index, word) in data.split(',').filter(|word| !word.is_empty()).enumerate() {
+                    *record.data.get_mut(index)? = u64::from_str_radix(word, 16).ok()?;
+                }
+            } else if let Some(handles) = field.strip_prefix("handles=") {
+                for handle in handles.split(',').filter(|handle| !handle.is_empty()) {
+                    let (index, name) = handle.split_once(':')?;
+                    let index: usize = index.parse().ok()?;
+                    let r#type = HANDLE_TYPES
+                        .iter()
+                        .find(|r#type| handle_type_name(**r#type) == name)?;
+                    *record.handles.get_mut(index)? = *r#type;
+                }
+            } else {
+                return None;
+            }
+        }
+
+        Some(record)
+    }
+}
+
+impl fmt::Display for Record {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let len = self
+            .data
+            .iter()
+            .rposition(|&word| word != 0)
+            .map_or(0, |index| index + 1);
+
+        write!(f, "data=")?;
+        for (index, word) in self.data[..len].iter().enumerate() {
+            if index > 0 {
+                write!(f, ",")?;
+            }
+            write!(f, "{:x}", word)?;
+        }
+
+        write!(f, " handles=")?;
+        let mut first = true;
+        for (index, r#type) in self.handles.iter().enumerate() {
+            if *r#type != HandleType::Invalid {
+                if !first {
+                    write!(f, ",")?;
+                }
+                first = false;
+                write!(f, "{}:{}", index, handle_type_name(*r#type))?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+fn handle_type_name(r#type: HandleType) -> &'static str {
+    match r#type {
+        HandleType::Invalid => "Invalid",
+        HandleType::MemoryObject => "MemoryObject",
+        HandleType::Process => "Process",
+        HandleType::Thread => "Thread",
+        HandleType::PortSender => "PortSender",
+        HandleType::PortReceiver => "PortReceiver",
+        HandleType::ProcessListener => "ProcessListener",
+        HandleType::ThreadListener => "ThreadListener",
+        HandleType::Timer => "Timer",
+        HandleType::PortListener => "PortListener",
+        HandleType::Grant => "Grant",
+        HandleType::Trampoline => "Trampoline",
+    }
+}
+
+/// Request and its reply (if any: some requests are one-way)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Exchange {
+    pub request: Record,
+    pub reply: Option<Record>,
+}
+
+/// Error while parsing a transcript
+#[derive(Debug, Clone, Copy)]
+pub enum TranscriptError {
+    /// The line (1-based) is malformed
+    BadLine(usize),
+    /// The reply at this line (1-based) does not match a previous request, or the request already has a reply
+    UnmatchedReply(usize),
+}
+
+impl fmt::Display for TranscriptError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match *self {
+            TranscriptError::BadLine(line) => write!(f, "bad transcript line {}", line),
+            TranscriptError::UnmatchedReply(line) => {
+                write!(f, "unmatched transcript reply at line {}", line)
+            }
+        }
+    }
+}
+
+/// Recorded session
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Transcript {
+    exchanges: Vec<Exchange>,
+}
+
+impl Transcript {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Parse the text format
+    ///
+    /// Request numbers are informative only: requests are numbered by their order in the text, and replies refer to these numbers.
+    pub fn parse(text: &str) -> Result<Self, TranscriptError> {
+        let mut transcript = Self::new();
+
+        for (index, line) in text.lines().enumerate() {
+            let line_number = index + 1;
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+
+            let bad_line = TranscriptError::BadLine(line_number);
+            let (is_request, rest) = match (line.strip_prefix('>'), line.strip_prefix('<')) {
+                (Some(rest), _) => (true, rest),
+                (_, Some(rest)) => (false, rest),
+                _ => return Err(bad_line),
+            };
+            let (number, record) = rest.trim_start().split_once(' ').ok_or(bad_line)?;
+            let number: usize = number
+                .strip_prefix('#')
+                .and_then(|number| number.parse().ok())
+                .ok_or(bad_line)?;
+            let record = Record::parse(record).ok_or(bad_line)?;
+
+            if is_request {
+                transcript.record_request(record);
+            } else {
+                let exchange = transcript
+                    .exchanges
+                    .get_mut(number)
+                    .filter(|exchange| exchange.reply.is_none())
+                    .ok_or(TranscriptError::UnmatchedReply(line_number))?;
+                exchange.reply = Some(record);
+            }
+        }
+
+        Ok(transcript)
+    }
+
+    /// Add a request, returns its exchange number
+    pub fn record_request(&mut self, request: Record) -> usize {
+        self.exchanges.push(Exchange {
+            request,
+            reply: None,
+        });
+        self.exchanges.len() - 1
+    }
+
+    /// Set the reply of a request
+    pub fn record_reply(&mut self, number: usize, reply: Record) {
+        self.exchanges[number].reply = Some(reply);
+    }
+
+    pub fn exchanges(&self) -> &[Exchange] {
+        &self.exchanges
+    }
+
+    /// Get the text format
+    pub fn to_text(&self) -> String {
+        use core::fmt::Write;
+
+        let mut text = String::new();
+        for (number, exchange) in self.exchanges.iter().enumerate() {
+            writeln!(text, "{}", request_line(number, &exchange.request)).unwrap();
+            if let Some(reply) = &exchange.reply {
+                writeln!(text, "{}", reply_line(number, reply)).unwrap();
+            }
+        }
+        text
+    }
+
+    /// Replay the requests against a server, and check its replies against the recorded ones
+    ///
+    /// Handle 0 of the requests (the reply port, by convention of all protocols) is replaced with a fresh reply port.
+    /// Other handles cannot be reproduced from a transcript: sessions which send them cannot be replayed.
+    ///
+    /// The replay stops at the first mismatch, since the state of the server differs from the recorded session afterwards.
+    pub fn replay(&self, server: &PortSender, options: &ReplayOptions) -> Result<(), ReplayError> {
+        for (number, exchange) in self.exchanges.iter().enumerate() {
+            let actual = replay_exchange(number, exchange, server, options)?;
+
+            if actual != exchange.reply {
+                return Err(ReplayError::Mismatch {
+                    number,
+                    expected: Box::new(exchange.reply),
+                    actual: Box::new(actual),
+                });
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Get the text line of a request
+pub fn request_line(number: usize, record: &Record) -> String {
+    alloc::format!("> #{} {}", number, record)
+}
+
+/// Get the text line of a reply
+pub fn reply_line(number: usize, record: &Record) -> String {
+    alloc::format!("< #{} {}", number, record)
+}
+
+/// Options of a replay
+#[derive(Debug, Clone, Copy)]
+pub struct ReplayOptions {
+    /// Time to wait for each reply
+    ///
+    /// For requests recorded without reply, this is also the time during which no reply must come.
+    pub reply_timeout: core::time::Duration,
+}
+
+impl Default for ReplayOptions {
+    fn default() -> Self {
+        Self {
+            reply_timeout: core::time::Duration::from_secs(1),
+        }
+    }
+}
+
+/// Error of a replay
+#[derive(Debug)]
+pub enum ReplayError {
+    Ipc(Error),
+    /// The request carries a handle (other than the reply port) which cannot be reproduced
+    UnsupportedHandle {
+        number: usize,
+        index: usize,
+    },
+    /// The reply differs from the recorded one (`None`: no reply)
+    ///
+    /// Records are boxed to keep the error small.
+    Mismatch {
+        number: usize,
+        expected: Box<Option<Record>>,
+        actual: Box<Option<Record>>,
+    },
+}
+
+impl From<Error> for ReplayError {
+    fn from(err: Error) -> Self {
+        ReplayError::Ipc(err)
+    }
+}
+
+impl fmt::Display for ReplayError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ReplayError::Ipc(err) => write!(f, "ipc error: {:?}", err),
+            ReplayError::UnsupportedHandle { number, index } => write!(
+                f,
+                "request #{}: handle {} cannot be replayed",
+                number, index
+            ),
+            ReplayError::Mismatch {
+                number,
+                expected,
+                actual,
+            } => {
+                write!(f, "reply #{}: expected ", number)?;
+                match expected.as_ref() {
+                    Some(record) => write!(f, "'{}'", record)?,
+                    None => write!(f, "no reply")?,
+                }
+                write!(f, ", got ")?;
+                match actual.as_ref() {
+                    Some(record) => write!(f, "'{}'", record),
+                    None => write!(f, "no reply"),
+                }
+            }
+        }
+    }
+}
+
+fn replay_exchange(
+    number: usize,
+    exchange: &Exchange,
+    server: &PortSender,
+    options: &ReplayOptions,
+) -> Result<Option<Record>, ReplayError> {
+    let (reply_receiver, reply_sender) = Port::create(None)?;
+    let mut reply_sender = Some(reply_sender);
+
+    let mut message = unsafe { Message::new(&exchange.request.data, &mut []) };
+
+    for (index, r#type) in exchange.request.handles.iter().enumerate() {
+        match (index, r#type) {
+            (_, HandleType::Invalid) => {}
+            (0, HandleType::PortSender) => {
+                message.handles[0] = reply_sender.take().unwrap().into_handle();
+            }
+            _ => return Err(ReplayError::UnsupportedHandle { number, index }),
+        }
+    }
+
+    server.send(&mut message)?;
+
+    let timer = Timer::create()?;
+    timer.arm(options.reply_timeout, core::time::Duration::ZERO)?;
+
+    let mut waiter = Waiter::new(&[&reply_receiver, &timer]);
+    waiter.wait()?;
+
+    if !waiter.is_ready(0) {
+        return Ok(None);
+    }
+
+    let reply = reply_receiver.receive()?;
+    Ok(Some(Record::new(&reply)))
+}
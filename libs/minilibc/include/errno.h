@@ -0,0 +1,17 @@
+#pragma once
+
+#define EPERM 1
+#define ENOENT 2
+#define EBADF 9
+#define EAGAIN 11
+#define ENOMEM 12
+#define EACCES 13
+#define EFAULT 14
+#define EEXIST 17
+#define EINVAL 22
+#define EMFILE 24
+#define ENOSYS 38
+#define ETIMEDOUT 110
+
+int *__errno_location(void);
+#define errno (*__errno_location())
@@ -0,0 +1,8 @@
+#pragma once
+
+typedef int pid_t;
+typedef struct posix_spawn_file_actions posix_spawn_file_actions_t;
+typedef struct posix_spawnattr posix_spawnattr_t;
+
+int posix_spawn(pid_t *pid, const char *path, const posix_spawn_file_actions_t *file_actions,
+                const posix_spawnattr_t *attrp, char *const argv[], char *const envp[]);
@@ -0,0 +1,13 @@
+#pragma once
+
+#include <stddef.h>
+
+void *malloc(size_t size);
+void *calloc(size_t count, size_t size);
+void *realloc(void *ptr, size_t size);
+void free(void *ptr);
+
+_Noreturn void exit(int status);
+_Noreturn void abort(void);
+char *getenv(const char *name);
+int atoi(const char *s);
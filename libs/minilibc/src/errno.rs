@@ -0,0 +1,62 @@
+use alloc::boxed::Box;
+use core::ffi::c_int;
+
+use libruntime::{
+    kobject::{TlsAllocator, TlsSlot},
+    sync::OnceLock,
+};
+
+pub const EPERM: c_int = 1;
+pub const ENOENT: c_int = 2;
+pub const EBADF: c_int = 9;
+pub const ENOMEM: c_int = 12;
+pub const EACCES: c_int = 13;
+pub const EFAULT: c_int = 14;
+pub const EEXIST: c_int = 17;
+pub const EINVAL: c_int = 22;
+pub const EMFILE: c_int = 24;
+pub const EAGAIN: c_int = 11;
+pub const ENOSYS: c_int = 38;
+pub const ETIMEDOUT: c_int = 110;
+
+/// Per-thread pointer to the errno value
+static ERRNO: OnceLock<TlsSlot> = OnceLock::new();
+
+/// Note: the value is allocated on first use by each thread, and is not freed when the thread exits.
+#[no_mangle]
+pub extern "C" fn __errno_location() -> *mut c_int {
+    let slot = ERRNO.get_or_init(|| TlsAllocator::allocate().expect("No TLS slot left for errno"));
+
+    if let Some(value) = slot.get() {
+        return value as *mut c_int;
+    }
+
+    let value = Box::into_raw(Box::new(0));
+    slot.set(value as usize);
+    value
+}
+
+/// Set errno value
+pub fn set_errno(value: c_int) {
+    unsafe { *__errno_location() = value };
+}
+
+/// Convert a syscall error into errno value
+pub fn from_error(err: libsyscalls::Error) -> c_int {
+    match err {
+        libsyscalls::Error::InvalidArgument => EINVAL,
+        libsyscalls::Error::OutOfMemory => ENOMEM,
+        libsyscalls::Error::NotSupported => ENOSYS,
+        libsyscalls::Error::MemoryAccessDenied => EFAULT,
+        libsyscalls::Error::ObjectNotFound => ENOENT,
+        libsyscalls::Error::ObjectNameDuplicate => EEXIST,
+        libsyscalls::Error::ObjectClosed => EBADF,
+        libsyscalls::Error::ObjectNotReady => EAGAIN,
+        libsyscalls::Error::Partial => EAGAIN,
+        libsyscalls::Error::DeadlineExceeded => ETIMEDOUT,
+        libsyscalls::Error::QuotaExceeded => EMFILE,
+        libsyscalls::Error::AddressInUse => EEXIST,
+        libsyscalls::Error::AddressNotMapped => ENOMEM,
+        libsyscalls::Error::AddressReserved => EACCES,
+    }
+}
@@ -0,0 +1,19 @@
+#![no_std]
+
+//! Minimal C runtime, to port small C programs and test suites.
+//!
+//! Only a small subset of the C library is provided. The matching headers are in the `include` directory.
+//!
+//! Note: memcpy, memmove, memset, memcmp, bcmp and strlen are already provided by compiler_builtins.
+
+extern crate alloc;
+extern crate libruntime;
+
+mod errno;
+mod malloc;
+mod start;
+mod stdlib;
+mod string;
+mod unistd;
+
+pub use errno::*;
@@ -0,0 +1,109 @@
+use core::{
+    alloc::Layout,
+    ffi::c_void,
+    mem::size_of,
+    ptr::{self, null_mut},
+};
+
+use alloc::alloc::{alloc, alloc_zeroed, dealloc, realloc as rust_realloc};
+
+use crate::errno::{set_errno, ENOMEM};
+
+// C allocations do not carry their size on free, so keep it in a header just before the returned pointer.
+// The header is as big as the alignment to keep the returned pointer properly aligned.
+const ALIGN: usize = 16;
+const HEADER_SIZE: usize = ALIGN;
+
+const _: () = assert!(size_of::<usize>() <= HEADER_SIZE);
+
+fn layout(size: usize) -> Option<Layout> {
+    let total = size.checked_add(HEADER_SIZE)?;
+    Layout::from_size_align(total, ALIGN).ok()
+}
+
+unsafe fn from_header(header: *mut u8, size: usize) -> *mut c_void {
+    if header.is_null() {
+        set_errno(ENOMEM);
+        return null_mut();
+    }
+
+    *(header as *mut usize) = size;
+    header.add(HEADER_SIZE) as *mut c_void
+}
+
+unsafe fn to_header(ptr: *mut c_void) -> (*mut u8, usize) {
+    let header = (ptr as *mut u8).sub(HEADER_SIZE);
+    let size = *(header as *const usize);
+    (header, size)
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn malloc(size: usize) -> *mut c_void {
+    let Some(layout) = layout(size) else {
+        set_errno(ENOMEM);
+        return null_mut();
+    };
+
+    from_header(alloc(layout), size)
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn calloc(count: usize, size: usize) -> *mut c_void {
+    let Some(size) = count.checked_mul(size) else {
+        set_errno(ENOMEM);
+        return null_mut();
+    };
+
+    let Some(layout) = layout(size) else {
+        set_errno(ENOMEM);
+        return null_mut();
+    };
+
+    from_header(alloc_zeroed(layout), size)
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn realloc(ptr: *mut c_void, size: usize) -> *mut c_void {
+    if ptr.is_null() {
+        return malloc(size);
+    }
+
+    if size == 0 {
+        free(ptr);
+        return null_mut();
+    }
+
+    let (header, old_size) = to_header(ptr);
+    let Some(new_layout) = layout(size) else {
+        set_errno(ENOMEM);
+        return null_mut();
+    };
+
+    let new_header = rust_realloc(
+        header,
+        layout(old_size).unwrap_unchecked(),
+        new_layout.size(),
+    );
+
+    // On failure, the old block is left untouched
+    from_header(new_header, size)
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn free(ptr: *mut c_void) {
+    if ptr.is_null() {
+        return;
+    }
+
+    let (header, size) = to_header(ptr);
+    dealloc(header, layout(size).unwrap_unchecked());
+}
+
+/// Duplicate a buffer into a new malloc'ed one
+pub(crate) unsafe fn dup(src: *const u8, len: usize) -> *mut u8 {
+    let dest = malloc(len) as *mut u8;
+    if !dest.is_null() {
+        ptr::copy_nonoverlapping(src, dest, len);
+    }
+    dest
+}
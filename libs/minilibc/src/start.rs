@@ -0,0 +1,18 @@
+use core::ffi::{c_char, c_int};
+
+extern "C" {
+    fn main(argc: c_int, argv: *const *const c_char) -> c_int;
+}
+
+/// C programs entry point
+#[no_mangle]
+pub extern "C" fn _start() -> ! {
+    libruntime::init();
+
+    // TODO: pass arguments from process-server
+    let argv: [*const c_char; 1] = [core::ptr::null()];
+
+    let status = unsafe { main(0, argv.as_ptr()) };
+
+    crate::stdlib::exit(status)
+}
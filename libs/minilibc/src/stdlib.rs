@@ -0,0 +1,99 @@
+use core::ffi::{c_char, c_int, CStr};
+
+use libruntime::process_server::ProcessServer;
+
+use crate::errno::{from_error, EINVAL};
+
+#[no_mangle]
+pub extern "C" fn exit(status: c_int) -> ! {
+    crate::unistd::flush();
+
+    // Best effort: the process exits anyway if the server is not there
+    if let Ok(server) = ProcessServer::connect() {
+        let _ = server.set_exit_status(status as i64);
+    }
+
+    libruntime::exit()
+}
+
+#[no_mangle]
+pub extern "C" fn abort() -> ! {
+    panic!("abort() called");
+}
+
+#[no_mangle]
+pub extern "C" fn getenv(_name: *const c_char) -> *mut c_char {
+    // No environment yet
+    core::ptr::null_mut()
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn atoi(s: *const c_char) -> c_int {
+    let mut ptr = s;
+    let mut value: c_int = 0;
+    let mut negative = false;
+
+    while matches!(*ptr as u8, b' ' | b'\t' | b'\n' | b'\r' | b'\x0b' | b'\x0c') {
+        ptr = ptr.add(1);
+    }
+
+    match *ptr as u8 {
+        b'-' => {
+            negative = true;
+            ptr = ptr.add(1);
+        }
+        b'+' => {
+            ptr = ptr.add(1);
+        }
+        _ => {}
+    }
+
+    while (*ptr as u8).is_ascii_digit() {
+        value = value
+            .wrapping_mul(10)
+            .wrapping_add((*ptr as u8 - b'0') as c_int);
+        ptr = ptr.add(1);
+    }
+
+    if negative {
+        -value
+    } else {
+        value
+    }
+}
+
+/// Spawn a new process, from a binary registered in process-server (eg: `/bin/c-smoke`)
+///
+/// Returns 0 on success, else the error number (errno is not set).
+///
+/// Note: file actions and attributes are not supported, and must be null.
+/// Arguments and environment are not passed to the new process yet: they are ignored.
+#[no_mangle]
+pub unsafe extern "C" fn posix_spawn(
+    pid: *mut c_int,
+    path: *const c_char,
+    file_actions: *const u8,
+    attrp: *const u8,
+    _argv: *const *const c_char,
+    _envp: *const *const c_char,
+) -> c_int {
+    if path.is_null() || !file_actions.is_null() || !attrp.is_null() {
+        return EINVAL;
+    }
+
+    let Ok(path) = CStr::from_ptr(path).to_str() else {
+        return EINVAL;
+    };
+
+    let result = ProcessServer::connect().and_then(|server| server.spawn(path));
+
+    match result {
+        Ok(new_pid) => {
+            if !pid.is_null() {
+                *pid = new_pid as c_int;
+            }
+            0
+        }
+        Err(err) => from_error(err),
+    }
+}
@@ -0,0 +1,105 @@
+use core::ffi::{c_char, c_int};
+
+use crate::malloc;
+
+extern "C" {
+    // Provided by compiler_builtins
+    fn strlen(s: *const c_char) -> usize;
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn strnlen(s: *const c_char, maxlen: usize) -> usize {
+    let mut len = 0;
+    while len < maxlen && *s.add(len) != 0 {
+        len += 1;
+    }
+    len
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn strcmp(s1: *const c_char, s2: *const c_char) -> c_int {
+    strncmp(s1, s2, usize::MAX)
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn strncmp(s1: *const c_char, s2: *const c_char, n: usize) -> c_int {
+    for index in 0..n {
+        let c1 = *s1.add(index) as u8;
+        let c2 = *s2.add(index) as u8;
+
+        if c1 != c2 {
+            return c1 as c_int - c2 as c_int;
+        }
+
+        if c1 == 0 {
+            break;
+        }
+    }
+
+    0
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn strcpy(dest: *mut c_char, src: *const c_char) -> *mut c_char {
+    let len = strlen(src);
+    // Also copy the terminating null
+    core::ptr::copy_nonoverlapping(src, dest, len + 1);
+    dest
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn strncpy(dest: *mut c_char, src: *const c_char, n: usize) -> *mut c_char {
+    let len = strnlen(src, n);
+    core::ptr::copy_nonoverlapping(src, dest, len);
+    // Pad with nulls
+    core::ptr::write_bytes(dest.add(len), 0, n - len);
+    dest
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn strcat(dest: *mut c_char, src: *const c_char) -> *mut c_char {
+    strcpy(dest.add(strlen(dest)), src);
+    dest
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn strchr(s: *const c_char, c: c_int) -> *mut c_char {
+    let c = c as c_char;
+    let mut ptr = s;
+
+    loop {
+        if *ptr == c {
+            return ptr as *mut _;
+        }
+
+        if *ptr == 0 {
+            return core::ptr::null_mut();
+        }
+
+        ptr = ptr.add(1);
+    }
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn strrchr(s: *const c_char, c: c_int) -> *mut c_char {
+    let c = c as c_char;
+    let mut ptr = s;
+    let mut found = core::ptr::null_mut();
+
+    loop {
+        if *ptr == c {
+            found = ptr as *mut _;
+        }
+
+        if *ptr == 0 {
+            return found;
+        }
+
+        ptr = ptr.add(1);
+    }
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn strdup(s: *const c_char) -> *mut c_char {
+    malloc::dup(s as *const u8, strlen(s) + 1) as *mut _
+}
@@ -0,0 +1,109 @@
+use alloc::{string::String, vec::Vec};
+use core::{
+    ffi::{c_char, c_int, c_void},
+    fmt::Write,
+    slice,
+};
+
+use libruntime::sync::Mutex;
+use log::Level;
+
+use crate::errno::{from_error, set_errno, EBADF, ENOSYS};
+
+pub const STDIN_FILENO: c_int = 0;
+pub const STDOUT_FILENO: c_int = 1;
+pub const STDERR_FILENO: c_int = 2;
+
+/// Pending output of stdout and stderr, until the end of the line
+static STDOUT: Mutex<Vec<u8>> = Mutex::new("minilibc::stdout", Vec::new());
+static STDERR: Mutex<Vec<u8>> = Mutex::new("minilibc::stderr", Vec::new());
+
+/// Note: stdout and stderr are redirected to the kernel log, until we have a proper console.
+///
+/// The output is logged line by line, and bytes that are not valid UTF-8 are escaped as `\xNN`.
+#[no_mangle]
+pub unsafe extern "C" fn write(fd: c_int, buf: *const c_void, count: usize) -> isize {
+    let (stream, level) = match fd {
+        STDOUT_FILENO => (&STDOUT, Level::Info),
+        STDERR_FILENO => (&STDERR, Level::Error),
+        _ => {
+            // TODO: vfs-server
+            set_errno(EBADF);
+            return -1;
+        }
+    };
+
+    // buf may be null then: slice::from_raw_parts does not accept it
+    if count == 0 {
+        return 0;
+    }
+
+    let data = slice::from_raw_parts(buf as *const u8, count);
+    let mut pending = stream.lock();
+    pending.extend_from_slice(data);
+
+    while let Some(end) = pending.iter().position(|&byte| byte == b'\n') {
+        // Logging adds its own line break
+        let result = log_line(level, &pending[..end]);
+        pending.drain(..=end);
+
+        if let Err(err) = result {
+            set_errno(from_error(err));
+            return -1;
+        }
+    }
+
+    count as isize
+}
+
+/// Log the unterminated output of stdout and stderr
+pub fn flush() {
+    for (stream, level) in [(&STDOUT, Level::Info), (&STDERR, Level::Error)] {
+        let mut pending = stream.lock();
+        if !pending.is_empty() {
+            // Nowhere to report the error
+            let _ = log_line(level, &pending);
+            pending.clear();
+        }
+    }
+}
+
+fn log_line(level: Level, line: &[u8]) -> Result<(), libsyscalls::Error> {
+    let mut message = String::with_capacity(line.len());
+
+    for chunk in line.utf8_chunks() {
+        message.push_str(chunk.valid());
+
+        for byte in chunk.invalid() {
+            let _ = write!(message, "\\x{byte:02x}");
+        }
+    }
+
+    libsyscalls::log(level, &message)
+}
+
+// TODO: vfs-server
+#[no_mangle]
+pub unsafe extern "C" fn read(_fd: c_int, _buf: *mut c_void, _count: usize) -> isize {
+    set_errno(ENOSYS);
+    -1
+}
+
+// TODO: vfs-server
+#[no_mangle]
+pub unsafe extern "C" fn open(_path: *const c_char, _flags: c_int, _mode: c_int) -> c_int {
+    set_errno(ENOSYS);
+    -1
+}
+
+// TODO: vfs-server
+#[no_mangle]
+pub unsafe extern "C" fn close(fd: c_int) -> c_int {
+    match fd {
+        STDIN_FILENO | STDOUT_FILENO | STDERR_FILENO => 0,
+        _ => {
+            set_errno(EBADF);
+            -1
+        }
+    }
+}
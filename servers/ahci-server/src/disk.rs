@@ -0,0 +1,428 @@
+//! SATA disk attached to a port of the HBA
+//!
+//! Commands are issued one at a time, in slot 0 of the command list, and their completion is polled.
+//! Data is transferred by DMA directly to or from the memory object of the client, one PRDT entry per page.
+
+use core::ptr;
+
+use alloc::{rc::Rc, string::String};
+use libdriver::mmio::Field;
+use libruntime::{
+    blockdev::{BlockDeviceInfo, MAX_TRANSFER_SIZE},
+    kobject::{Error, Mapping, MemoryObject, Permissions, Process, PAGE_SIZE},
+};
+use log::{debug, warn};
+
+use crate::hba::{self, port, Hba};
+
+// Layout of the DMA memory of a port
+const COMMAND_LIST_OFFSET: usize = 0; // 32 headers of 32 bytes, 1K aligned
+const RECEIVED_FIS_OFFSET: usize = 0x400; // 256 bytes, 256 bytes aligned
+const COMMAND_TABLE_OFFSET: usize = PAGE_SIZE; // 128 bytes aligned
+const IDENTIFY_OFFSET: usize = 2 * PAGE_SIZE;
+const DMA_SIZE: usize = 3 * PAGE_SIZE;
+
+// Command table
+const PRDT_OFFSET: usize = 0x80;
+const PRDT_ENTRY_SIZE: usize = 16;
+
+// The PRDT of the largest transfer (one entry per page) fits in the command table page
+const _: () = assert!(PRDT_OFFSET + MAX_TRANSFER_SIZE / PAGE_SIZE * PRDT_ENTRY_SIZE <= PAGE_SIZE);
+
+// Command header
+const HEADER_CFL: Field<u32> = Field::new(0, 5);
+const HEADER_WRITE: Field<u32> = Field::new(6, 1);
+const HEADER_PRDTL: Field<u32> = Field::new(16, 16);
+
+// Register host to device FIS
+const FIS_TYPE_REG_H2D: u8 = 0x27;
+const FIS_REG_H2D_DWORDS: u32 = 5;
+const FIS_COMMAND: u8 = 0x80;
+const DEVICE_LBA: u8 = 1 << 6;
+
+// ATA commands
+const ATA_IDENTIFY_DEVICE: u8 = 0xEC;
+const ATA_READ_DMA_EXT: u8 = 0x25;
+const ATA_WRITE_DMA_EXT: u8 = 0x35;
+const ATA_FLUSH_CACHE_EXT: u8 = 0xEA;
+
+/// Size of the data of IDENTIFY DEVICE
+const IDENTIFY_SIZE: usize = 512;
+
+/// Direction of the data of a command
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Direction {
+    None,
+    FromDevice,
+    ToDevice,
+}
+
+/// Data buffer of a command
+struct Transfer<'a> {
+    buffer: &'a MemoryObject,
+    offset: usize,
+    size: usize,
+    direction: Direction,
+}
+
+/// SATA disk
+#[derive(Debug)]
+pub struct Disk {
+    hba: Rc<Hba>,
+    port: u32,
+    /// Command list, received FIS, command table and identify data
+    memory: MemoryObject,
+    mapping: Mapping<'static>,
+    info: BlockDeviceInfo,
+    model: String,
+}
+
+impl Disk {
+    /// Start the port, and identify its disk
+    ///
+    /// Returns `None` if no SATA disk is attached to the port.
+    pub fn probe(hba: Rc<Hba>, port: u32) -> Result<Option<Self>, Error> {
+        let status = hba.port_read(port, port::SSTS);
+        if port::SSTS_DET.get(status) != port::DET_PRESENT {
+            return Ok(None);
+        }
+
+        let signature = hba.port_read(port, port::SIG);
+        if signature != port::SIG_ATA {
+            debug!(
+                "port {}: unsupported device (signature={:#010x})",
+                port, signature
+            );
+            return Ok(None);
+        }
+
+        let memory = MemoryObject::create(DMA_SIZE)?;
+        let mapping = Process::current().map_mem(
+            None,
+            DMA_SIZE,
+            Permissions::READ | Permissions::WRITE,
+            &memory,
+            0,
+        )?;
+
+        let mut disk = Self {
+            hba,
+            port,
+            memory,
+            mapping,
+            info: BlockDeviceInfo::default(),
+            model: String::new(),
+        };
+
+        disk.start()?;
+        disk.identify()?;
+
+        Ok(Some(disk))
+    }
+
+    /// Get the geometry of the disk
+    pub fn info(&self) -> BlockDeviceInfo {
+        self.info
+    }
+
+    /// Get the model name reported by the disk
+    pub fn model(&self) -> &str {
+        &self.model
+    }
+
+    /// Read `count` blocks from `lba` into `buffer`
+    pub fn read(&self, lba: u64, count: u64, buffer: &MemoryObject) -> Result<(), Error> {
+        let size = self.check_range(lba, count)?;
+
+        self.execute(
+            ATA_READ_DMA_EXT,
+            lba,
+            count as u16,
+            Some(Transfer {
+                buffer,
+                offset: 0,
+                size,
+                direction: Direction::FromDevice,
+            }),
+        )
+    }
+
+    /// Write `count` blocks at `lba` from `buffer`
+    pub fn write(&self, lba: u64, count: u64, buffer: &MemoryObject) -> Result<(), Error> {
+        let size = self.check_range(lba, count)?;
+
+        self.execute(
+            ATA_WRITE_DMA_EXT,
+            lba,
+            count as u16,
+            Some(Transfer {
+                buffer,
+                offset: 0,
+                size,
+                direction: Direction::ToDevice,
+            }),
+        )
+    }
+
+    /// Flush the write cache of the disk
+    pub fn flush(&self) -> Result<(), Error> {
+        self.execute(ATA_FLUSH_CACHE_EXT, 0, 0, None)
+    }
+
+    /// Check that the transfer is within the disk and the transfer limit, returns its size in bytes
+    fn check_range(&self, lba: u64, count: u64) -> Result<usize, Error> {
+        let end = lba.checked_add(count).ok_or(Error::InvalidArgument)?;
+        if count == 0 || end > self.info.block_count {
+            return Err(Error::InvalidArgument);
+        }
+
+        let size = count * self.info.block_size;
+        if size > MAX_TRANSFER_SIZE as u64 {
+            return Err(Error::InvalidArgument);
+        }
+
+        Ok(size as usize)
+    }
+
+    /// Set the DMA memory of the port, and start its command engine (AHCI 1.3.1, section 10.1.2)
+    fn start(&self) -> Result<(), Error> {
+        self.stop()?;
+
+        let base = self.dma_address(&self.memory, 0)?;
+        let command_list = base + COMMAND_LIST_OFFSET as u64;
+        let received_fis = base + RECEIVED_FIS_OFFSET as u64;
+
+        self.hba
+            .port_write(self.port, port::CLB, command_list as u32);
+        self.hba
+            .port_write(self.port, port::CLBU, (command_list >> 32) as u32);
+        self.hba
+            .port_write(self.port, port::FB, received_fis as u32);
+        self.hba
+            .port_write(self.port, port::FBU, (received_fis >> 32) as u32);
+
+        // Completions are polled
+        self.hba.port_write(self.port, port::IE, 0);
+        self.hba.port_write(self.port, port::SERR, u32::MAX);
+        self.hba.port_write(self.port, port::IS, u32::MAX);
+
+        self.hba
+            .port_write_field(self.port, port::CMD, port::CMD_FRE, 1);
+        self.wait_idle()?;
+        self.hba
+            .port_write_field(self.port, port::CMD, port::CMD_ST, 1);
+
+        Ok(())
+    }
+
+    /// Stop the command engine and the FIS receive of the port
+    fn stop(&self) -> Result<(), Error> {
+        self.hba
+            .port_write_field(self.port, port::CMD, port::CMD_ST, 0);
+        hba::poll(|| {
+            let cmd = self.hba.port_read(self.port, port::CMD);
+            Ok(port::CMD_CR.get(cmd) == 0)
+        })?;
+
+        self.hba
+            .port_write_field(self.port, port::CMD, port::CMD_FRE, 0);
+        hba::poll(|| {
+            let cmd = self.hba.port_read(self.port, port::CMD);
+            Ok(port::CMD_FR.get(cmd) == 0)
+        })
+    }
+
+    /// Wait until the disk can accept a command
+    fn wait_idle(&self) -> Result<(), Error> {
+        hba::poll(|| {
+            let tfd = self.hba.port_read(self.port, port::TFD);
+            Ok(port::TFD_BSY.get(tfd) == 0 && port::TFD_DRQ.get(tfd) == 0)
+        })
+    }
+
+    fn identify(&mut self) -> Result<(), Error> {
+        self.execute(
+            ATA_IDENTIFY_DEVICE,
+            0,
+            0,
+            Some(Transfer {
+                buffer: &self.memory,
+                offset: IDENTIFY_OFFSET,
+                size: IDENTIFY_SIZE,
+                direction: Direction::FromDevice,
+            }),
+        )?;
+
+        let word = |index: usize| self.read_u16(IDENTIFY_OFFSET + index * 2);
+
+        // Word 83 bit 10: 48 bits addresses
+        if word(83) & (1 << 10) == 0 {
+            warn!("port {}: disk does not support LBA48", self.port);
+            return Err(Error::NotSupported);
+        }
+
+        let block_count = (0..4).fold(0u64, |value, index| {
+            value | (word(100 + index) as u64) << (16 * index)
+        });
+
+        // Word 106: bit 14 set and bit 15 clear if valid, bit 12 set if the logical sector is larger than 256 words
+        let sector_info = word(106);
+        let block_size = if sector_info & 0xC000 == 0x4000 && sector_info & (1 << 12) != 0 {
+            (word(117) as u64 | (word(118) as u64) << 16) * 2
+        } else {
+            512
+        };
+
+        // Words 27-46: model, 2 characters per word, first one in the high byte
+        let mut model = String::new();
+        for index in 27..47 {
+            let [high, low] = word(index).to_be_bytes();
+            model.push(high as char);
+            model.push(low as char);
+        }
+
+        self.model = String::from(model.trim());
+        self.info = BlockDeviceInfo {
+            block_count,
+            block_size,
+        };
+
+        Ok(())
+    }
+
+    /// Execute an ATA command in slot 0, and wait for its completion
+    fn execute(
+        &self,
+        command: u8,
+        lba: u64,
+        count: u16,
+        transfer: Option<Transfer>,
+    ) -> Result<(), Error> {
+        self.wait_idle()?;
+
+        // Command table: FIS, then PRDT
+        self.fill(COMMAND_TABLE_OFFSET, PRDT_OFFSET, 0);
+
+        let fis = COMMAND_TABLE_OFFSET;
+        let lba_bytes = lba.to_le_bytes();
+        self.write_u8(fis, FIS_TYPE_REG_H2D);
+        self.write_u8(fis + 1, FIS_COMMAND);
+        self.write_u8(fis + 2, command);
+        self.write_u8(fis + 4, lba_bytes[0]);
+        self.write_u8(fis + 5, lba_bytes[1]);
+        self.write_u8(fis + 6, lba_bytes[2]);
+        self.write_u8(fis + 7, DEVICE_LBA);
+        self.write_u8(fis + 8, lba_bytes[3]);
+        self.write_u8(fis + 9, lba_bytes[4]);
+        self.write_u8(fis + 10, lba_bytes[5]);
+        self.write_u8(fis + 12, count as u8);
+        self.write_u8(fis + 13, (count >> 8) as u8);
+
+        let mut entries = 0;
+        let mut direction = Direction::None;
+
+        if let Some(transfer) = transfer {
+            direction = transfer.direction;
+
+            let mut done = 0;
+            while done < transfer.size {
+                let size = (transfer.size - done).min(PAGE_SIZE);
+                let address = self.dma_address(transfer.buffer, transfer.offset + done)?;
+
+                let entry = COMMAND_TABLE_OFFSET + PRDT_OFFSET + entries * PRDT_ENTRY_SIZE;
+                self.write_u32(entry, address as u32);
+                self.write_u32(entry + 4, (address >> 32) as u32);
+                self.write_u32(entry + 8, 0);
+                // Byte count, minus one
+                self.write_u32(entry + 12, (size - 1) as u32);
+
+                entries += 1;
+                done += size;
+            }
+        }
+
+        // Command header of slot 0
+        let table = self.dma_address(&self.memory, 0)? + COMMAND_TABLE_OFFSET as u64;
+        let mut flags = HEADER_CFL.set(0, FIS_REG_H2D_DWORDS);
+        flags = HEADER_WRITE.set(flags, (direction == Direction::ToDevice) as u32);
+        flags = HEADER_PRDTL.set(flags, entries as u32);
+
+        let header = COMMAND_LIST_OFFSET;
+        self.write_u32(header, flags);
+        self.write_u32(header + 4, 0);
+        self.write_u32(header + 8, table as u32);
+        self.write_u32(header + 12, (table >> 32) as u32);
+
+        // Note: io-port accesses are compiler barriers, the descriptors are written before the command is issued
+        self.hba.port_write(self.port, port::IS, u32::MAX);
+        self.hba.port_write(self.port, port::CI, 1);
+
+        let result = hba::poll(|| {
+            let status = self.hba.port_read(self.port, port::IS);
+            if port::IS_TFES.get(status) != 0 {
+                return Err(Error::InvalidArgument);
+            }
+
+            Ok(self.hba.port_read(self.port, port::CI) & 1 == 0)
+        });
+
+        let tfd = self.hba.port_read(self.port, port::TFD);
+        if result.is_err() || port::TFD_ERR.get(tfd) != 0 {
+            warn!(
+                "port {}: command {:#04x} failed (lba={}, count={}, tfd={:#x}, error={:#x})",
+                self.port,
+                command,
+                lba,
+                count,
+                tfd,
+                port::TFD_ERROR.get(tfd)
+            );
+
+            // The port must be restarted to clear the error
+            self.start()?;
+            return Err(result.err().unwrap_or(Error::InvalidArgument));
+        }
+
+        Ok(())
+    }
+
+    /// Get the DMA address of the page at `offset` of a memory object
+    fn dma_address(&self, buffer: &MemoryObject, offset: usize) -> Result<u64, Error> {
+        let address = buffer.physical_address(offset)?;
+
+        if address > u32::MAX as u64 && !self.hba.supports_64bit() {
+            return Err(Error::NotSupported);
+        }
+
+        Ok(address)
+    }
+
+    fn fill(&self, offset: usize, size: usize, value: u8) {
+        assert!(offset + size <= DMA_SIZE);
+        unsafe { ptr::write_bytes((self.mapping.address() + offset) as *mut u8, value, size) };
+    }
+
+    fn write_u8(&self, offset: usize, value: u8) {
+        assert!(offset < DMA_SIZE);
+        unsafe { ptr::write_volatile((self.mapping.address() + offset) as *mut u8, value) };
+    }
+
+    fn write_u32(&self, offset: usize, value: u32) {
+        assert!(offset + 4 <= DMA_SIZE);
+        unsafe { ptr::write_volatile((self.mapping.address() + offset) as *mut u32, value) };
+    }
+
+    fn read_u16(&self, offset: usize) -> u16 {
+        assert!(offset + 2 <= DMA_SIZE);
+        unsafe { ptr::read_volatile((self.mapping.address() + offset) as *const u16) }
+    }
+}
+
+impl Drop for Disk {
+    fn drop(&mut self) {
+        // The HBA must not access the memory anymore once it is released
+        if let Err(err) = self.stop() {
+            warn!("port {}: could not stop: {:?}", self.port, err);
+        }
+    }
+}
@@ -0,0 +1,149 @@
+//! Host bus adapter registers (AHCI 1.3.1, section 3)
+//!
+//! The registers are reached through the index/data pair (`IDP`, section 3.2.1 of the Intel ICH documentation),
+//! which lets the driver use io-ports instead of mapping the `ABAR` memory.
+
+use core::time::Duration;
+
+use libdriver::{mmio::Field, pio::IndexData};
+use libruntime::{kobject::Error, retry::Backoff};
+
+// Generic host control
+const CAP: u32 = 0x00;
+const GHC: u32 = 0x04;
+const IS: u32 = 0x08;
+const PI: u32 = 0x0C;
+const VS: u32 = 0x10;
+
+const CAP_S64A: Field<u32> = Field::new(31, 1);
+const GHC_AE: Field<u32> = Field::new(31, 1);
+const GHC_HR: Field<u32> = Field::new(0, 1);
+const VS_MAJOR: Field<u32> = Field::new(16, 16);
+const VS_MINOR: Field<u32> = Field::new(0, 16);
+
+const PORT_BASE: u32 = 0x100;
+const PORT_SIZE: u32 = 0x80;
+
+/// Maximum number of ports of a HBA
+pub const MAX_PORTS: u32 = 32;
+
+/// Registers of a port, relative to its base
+pub mod port {
+    use libdriver::mmio::Field;
+
+    pub const CLB: u32 = 0x00;
+    pub const CLBU: u32 = 0x04;
+    pub const FB: u32 = 0x08;
+    pub const FBU: u32 = 0x0C;
+    pub const IS: u32 = 0x10;
+    pub const IE: u32 = 0x14;
+    pub const CMD: u32 = 0x18;
+    pub const TFD: u32 = 0x20;
+    pub const SIG: u32 = 0x24;
+    pub const SSTS: u32 = 0x28;
+    pub const SERR: u32 = 0x30;
+    pub const CI: u32 = 0x38;
+
+    pub const IS_TFES: Field<u32> = Field::new(30, 1);
+
+    pub const CMD_ST: Field<u32> = Field::new(0, 1);
+    pub const CMD_FRE: Field<u32> = Field::new(4, 1);
+    pub const CMD_FR: Field<u32> = Field::new(14, 1);
+    pub const CMD_CR: Field<u32> = Field::new(15, 1);
+
+    pub const TFD_ERR: Field<u32> = Field::new(0, 1);
+    pub const TFD_DRQ: Field<u32> = Field::new(3, 1);
+    pub const TFD_BSY: Field<u32> = Field::new(7, 1);
+    pub const TFD_ERROR: Field<u32> = Field::new(8, 8);
+
+    pub const SSTS_DET: Field<u32> = Field::new(0, 4);
+
+    /// Device detected and communication established
+    pub const DET_PRESENT: u32 = 3;
+
+    /// Signature of a SATA disk (ATAPI devices are not supported)
+    pub const SIG_ATA: u32 = 0x0000_0101;
+}
+
+/// Time the HBA and the disks have to complete resets and commands
+const TIMEOUT: Duration = Duration::from_secs(1);
+
+/// Interrupts are not routed to userland yet: completions are polled, quickly at first
+const POLL_BACKOFF: Backoff = Backoff::new(Duration::from_micros(10), Duration::from_millis(10))
+    .jitter(0)
+    .timeout(TIMEOUT);
+
+/// Poll until `done` returns true, or fail with `Error::DeadlineExceeded` after `TIMEOUT`
+pub fn poll(mut done: impl FnMut() -> Result<bool, Error>) -> Result<(), Error> {
+    POLL_BACKOFF.retry_if(
+        |err| matches!(err, Error::ObjectNotReady),
+        || match done()? {
+            true => Ok(()),
+            false => Err(Error::ObjectNotReady),
+        },
+    )
+}
+
+/// Host bus adapter
+#[derive(Debug)]
+pub struct Hba {
+    registers: IndexData,
+}
+
+impl Hba {
+    pub fn new(registers: IndexData) -> Self {
+        Self { registers }
+    }
+
+    /// Reset the HBA, and switch it to AHCI mode
+    pub fn reset(&self) -> Result<(), Error> {
+        self.registers.modify(GHC, |value| GHC_AE.set(value, 1));
+        self.registers.modify(GHC, |value| GHC_HR.set(value, 1));
+        poll(|| Ok(GHC_HR.get(self.registers.read(GHC)) == 0))?;
+
+        // The reset clears AE. Interrupts stay disabled (GHC.IE is cleared by the reset).
+        self.registers.modify(GHC, |value| GHC_AE.set(value, 1));
+        self.registers.write(IS, u32::MAX);
+
+        Ok(())
+    }
+
+    /// Get the version of the AHCI specification implemented (major, minor)
+    pub fn version(&self) -> (u32, u32) {
+        let value = self.registers.read(VS);
+        (VS_MAJOR.get(value), VS_MINOR.get(value))
+    }
+
+    /// Get the bitmap of the implemented ports
+    pub fn implemented_ports(&self) -> u32 {
+        self.registers.read(PI)
+    }
+
+    /// Check if the HBA can use 64 bits DMA addresses
+    pub fn supports_64bit(&self) -> bool {
+        CAP_S64A.get(self.registers.read(CAP)) == 1
+    }
+
+    /// Read a register of a port (see `port`)
+    pub fn port_read(&self, index: u32, register: u32) -> u32 {
+        self.registers.read(port_offset(index, register))
+    }
+
+    /// Write a register of a port (see `port`)
+    pub fn port_write(&self, index: u32, register: u32, value: u32) {
+        self.registers.write(port_offset(index, register), value)
+    }
+
+    /// Update a field of a register of a port (see `port`), keeping the other bits
+    pub fn port_write_field(&self, index: u32, register: u32, field: Field<u32>, value: u32) {
+        self.registers
+            .modify(port_offset(index, register), |current| {
+                field.set(current, value)
+            })
+    }
+}
+
+fn port_offset(index: u32, register: u32) -> u32 {
+    assert!(index < MAX_PORTS);
+    PORT_BASE + index * PORT_SIZE + register
+}
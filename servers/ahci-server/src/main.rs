@@ -0,0 +1,205 @@
+#![no_std]
+#![no_main]
+
+// AHCI storage driver: serves the SATA disks of the first AHCI controller as block devices (see `libruntime::blockdev`)
+//
+// The controller registers are reached through its index/data pair of io-ports, granted by `Device::claim`.
+// Interrupts are not routed to userland yet: completions are polled.
+
+extern crate alloc;
+extern crate libruntime;
+
+mod disk;
+mod hba;
+
+use alloc::{rc::Rc, vec::Vec};
+use libdriver::pio::IndexData;
+use libruntime::{
+    blockdev::{self, BlockDeviceInfo, Reply, Request, RequestType},
+    failure,
+    kobject::{
+        Device, DeviceBus, DeviceInfo, DeviceResourceType, Error, Handle, MemoryObject, Message,
+        Port, PortReceiver, PortSender, Waiter,
+    },
+    manifest::SandboxFlags,
+};
+use log::{error, info, warn};
+
+use disk::Disk;
+use hba::Hba;
+
+libruntime::entry!(main);
+
+libruntime::manifest_sandbox!(SandboxFlags::DEVICE);
+
+/// Driver name, in the block device port names
+const DRIVER_NAME: &str = "ahci";
+
+// PCI class of AHCI controllers: mass storage, SATA, AHCI 1.0
+const CLASS_STORAGE: u8 = 0x01;
+const SUBCLASS_SATA: u8 = 0x06;
+const PROG_IF_AHCI: u8 = 0x01;
+
+/// Size of the index/data pair io-ports
+const IDP_SIZE: u64 = 8;
+
+/// Disk served on its port
+struct Served {
+    disk: Disk,
+    receiver: PortReceiver,
+    // Keep the port open
+    _sender: PortSender,
+}
+
+fn main() {
+    let disks = match init() {
+        Ok(disks) => disks,
+        Err(err) => {
+            error!("Could not initialize the AHCI controller: {:?}", err);
+            return;
+        }
+    };
+
+    if disks.is_empty() {
+        info!("No disk attached");
+        return;
+    }
+
+    serve(disks);
+}
+
+fn init() -> Result<Vec<Served>, Error> {
+    let devices = Device::list()?;
+    let controller = devices
+        .iter()
+        .find(|device| is_ahci(device))
+        .ok_or(Error::ObjectNotFound)?;
+
+    Device::claim(controller.address)?;
+
+    // The memory BAR (ABAR) cannot be mapped by userland yet: the index/data pair is the io-port BAR
+    let idp = controller
+        .resources
+        .iter()
+        .find(|resource| resource.r#type == DeviceResourceType::IoPort && resource.len >= IDP_SIZE)
+        .ok_or(Error::NotSupported)?;
+
+    let hba = Rc::new(Hba::new(unsafe { IndexData::new(idp.start as u16) }));
+    hba.reset()?;
+
+    let (major, minor) = hba.version();
+    info!(
+        "AHCI {}.{} controller {:04X}:{:04X} at {:06X}",
+        major, minor, controller.vendor_id, controller.device_id, controller.address
+    );
+
+    let mut disks = Vec::new();
+    let ports = hba.implemented_ports();
+
+    for port in (0..hba::MAX_PORTS).filter(|port| ports & (1 << port) != 0) {
+        let disk = match Disk::probe(hba.clone(), port) {
+            Ok(Some(disk)) => disk,
+            Ok(None) => continue,
+            Err(err) => {
+                warn!("port {}: could not start: {:?}", port, err);
+                continue;
+            }
+        };
+
+        let name = blockdev::port_name(DRIVER_NAME, disks.len());
+        let (receiver, sender) = Port::create(Some(&name))?;
+
+        let info = disk.info();
+        info!(
+            "port {}: '{}', {} blocks of {} bytes, served on '{}'",
+            port,
+            disk.model(),
+            info.block_count,
+            info.block_size,
+            name
+        );
+
+        disks.push(Served {
+            disk,
+            receiver,
+            _sender: sender,
+        });
+    }
+
+    Ok(disks)
+}
+
+fn is_ahci(device: &DeviceInfo) -> bool {
+    device.bus == DeviceBus::Pci
+        && device.class == CLASS_STORAGE
+        && device.subclass == SUBCLASS_SATA
+        && device.prog_if == PROG_IF_AHCI
+}
+
+fn serve(disks: Vec<Served>) -> ! {
+    loop {
+        let ready: Vec<usize> = {
+            let receivers: Vec<_> = disks.iter().map(|served| &served.receiver as _).collect();
+            let mut waiter = Waiter::new(&receivers);
+            if let Err(err) = waiter.wait() {
+                warn!("Could not wait: {:?}", err);
+                continue;
+            }
+
+            (0..disks.len())
+                .filter(|&index| waiter.is_ready(index))
+                .collect()
+        };
+
+        for index in ready {
+            let served = &disks[index];
+            while let Ok(message) = served.receiver.receive() {
+                process_message(&served.disk, message);
+            }
+        }
+    }
+}
+
+fn process_message(disk: &Disk, mut message: Message) {
+    let reply_port = match PortSender::from_handle(message.take_handle(0)) {
+        Ok(port) => port,
+        Err(_) => {
+            warn!("Dropping request without reply port");
+            return;
+        }
+    };
+
+    // If processing panics, the client gets a failure report instead of the reply
+    let _request = failure::begin_request(&reply_port, message.correlation());
+
+    let request = *unsafe { message.data::<Request>() };
+    let result = process_request(disk, &request, &mut message);
+
+    let reply = Reply::new(result);
+    let mut handles = [Handle::invalid()];
+    let mut reply_message = unsafe { Message::new(&reply, &mut handles) };
+    if let Err(err) = reply_port.send(&mut reply_message) {
+        warn!("Could not send reply: {:?}", err);
+    }
+}
+
+fn process_request(
+    disk: &Disk,
+    request: &Request,
+    message: &mut Message,
+) -> Result<BlockDeviceInfo, Error> {
+    let r#type = RequestType::try_from(request.r#type)?;
+
+    match r#type {
+        RequestType::Info => return Ok(disk.info()),
+        RequestType::Read => disk.read(request.lba, request.count, &buffer(message)?)?,
+        RequestType::Write => disk.write(request.lba, request.count, &buffer(message)?)?,
+        RequestType::Flush => disk.flush()?,
+    }
+
+    Ok(BlockDeviceInfo::default())
+}
+
+fn buffer(message: &mut Message) -> Result<MemoryObject, Error> {
+    MemoryObject::from_handle(message.take_handle(1)).map_err(|_| Error::InvalidArgument)
+}
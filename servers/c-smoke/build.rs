@@ -0,0 +1,23 @@
+// Compile the C program against the minilibc headers
+//
+// Userland has no C toolchain: the host compiler builds freestanding position independent code,
+// which is linked into the binary by rustc like the Rust code.
+
+fn main() {
+    println!("cargo:rerun-if-changed=src/smoke.c");
+    println!("cargo:rerun-if-changed=../../libs/minilibc/include");
+
+    cc::Build::new()
+        // Our target is not known by cc: build for the bare metal one, which has the same ABI
+        .target("x86_64-unknown-none")
+        .compiler("cc")
+        .file("src/smoke.c")
+        .include("../../libs/minilibc/include")
+        .flag("-ffreestanding")
+        .flag("-fPIC")
+        .flag("-fno-stack-protector")
+        .flag("-mno-red-zone")
+        .flag("-mgeneral-regs-only")
+        .warnings_into_errors(true)
+        .compile("smoke");
+}
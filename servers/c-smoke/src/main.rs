@@ -0,0 +1,9 @@
+//! C smoke program: `main` is written in C (`src/smoke.c`), and runs on minilibc
+//!
+//! It is registered by init in process-server as `/bin/c-smoke`, and spawned by the `spawn::c_smoke` test,
+//! which checks its exit status.
+
+#![no_std]
+#![no_main]
+
+extern crate minilibc;
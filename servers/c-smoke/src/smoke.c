@@ -0,0 +1,51 @@
+// Smoke test of minilibc: exits with 0 if all the checks pass, else with the number of the failed check
+
+#include <errno.h>
+#include <spawn.h>
+#include <stddef.h>
+#include <stdlib.h>
+#include <string.h>
+#include <unistd.h>
+
+static void log_line(const char *line)
+{
+    write(STDOUT_FILENO, line, strlen(line));
+    write(STDOUT_FILENO, "\n", 1);
+}
+
+static int check(int number, int ok, const char *description)
+{
+    if (!ok) {
+        log_line("c-smoke: check failed:");
+        log_line(description);
+        exit(number);
+    }
+
+    return ok;
+}
+
+int main(int argc, char *argv[])
+{
+    (void)argc;
+    (void)argv;
+
+    log_line("c-smoke: started");
+
+    char *copy = strdup("hello");
+    check(1, copy != NULL && strcmp(copy, "hello") == 0, "strdup");
+    free(copy);
+
+    check(2, atoi(" -42") == -42, "atoi");
+
+    pid_t pid = -1;
+    check(3, posix_spawn(&pid, "/bin/missing", NULL, NULL, NULL, NULL) == ENOENT,
+          "posix_spawn of a missing binary");
+    check(4, pid == -1, "posix_spawn left the pid untouched on failure");
+
+    posix_spawn_file_actions_t *file_actions = (posix_spawn_file_actions_t *)&pid;
+    check(5, posix_spawn(&pid, "/bin/c-smoke", file_actions, NULL, NULL, NULL) == EINVAL,
+          "posix_spawn with file actions");
+
+    log_line("c-smoke: all checks passed");
+    return 0;
+}
@@ -0,0 +1,177 @@
+#![no_std]
+#![no_main]
+
+// Checkpoints of processes (CRIU-lite): stores them in vfs files, and restores them into new processes
+//
+// See `libruntime::checkpoint_server` for the protocol, and `libruntime::debug::Checkpoint` for the format.
+// The server needs the `DEBUG` sandbox right, to read the memory of the processes and to set the thread contexts,
+// and the `PROCESS_CREATE` one to restore them.
+// It is a managed server (see `libruntime::server`): `server::dump("checkpoint-server")` logs what it is doing.
+
+extern crate alloc;
+extern crate libruntime;
+
+use alloc::{vec, vec::Vec};
+use libruntime::{
+    checkpoint_server::{Reply, Request, RequestType, SERVER_PORT_NAME},
+    debug::Checkpoint,
+    kobject::{Error, Handle, KObject, Message, Process, TypedHandle},
+    manifest::SandboxFlags,
+    server::ManagedServerBuilder,
+    vfs::{OpenFlags, Vfs},
+};
+use log::{debug, error, info, warn};
+
+libruntime::entry!(main);
+
+libruntime::manifest_sandbox!(SandboxFlags::DEBUG.union(SandboxFlags::PROCESS_CREATE));
+
+struct Server {
+    vfs: Vfs,
+    /// Pid of the process which spawned the server: it can store any process
+    spawner: u64,
+}
+
+fn main() {
+    let vfs = match Vfs::wait_connect() {
+        Ok(vfs) => vfs,
+        Err(err) => {
+            error!("Could not connect to vfs-server: {:?}", err);
+            return;
+        }
+    };
+
+    let managed = ManagedServerBuilder::new(SERVER_PORT_NAME)
+        .build()
+        .expect("Could not create server port");
+
+    let server = Server {
+        vfs,
+        spawner: Process::current().info().creator_pid,
+    };
+
+    info!("Checkpoint server ready on port '{}'", SERVER_PORT_NAME);
+
+    managed.run(|request| {
+        let data = *unsafe { request.message.data::<Request>() };
+        let (result, process) = server.process_request(&data, request.sender_pid());
+
+        let reply = Reply::new(result);
+        let mut handles = [process.map_or(Handle::invalid(), |process| {
+            unsafe { process.handle() }.as_handle().clone()
+        })];
+        let mut reply_message = unsafe { Message::new(&reply, &mut handles) };
+        if let Err(err) = request.reply_port.send(&mut reply_message) {
+            warn!("Could not send reply: {:?}", err);
+        }
+    });
+}
+
+impl Server {
+    /// Process a request, returns the result and the restored process to send with the reply
+    fn process_request(
+        &self,
+        request: &Request,
+        sender_pid: u64,
+    ) -> (Result<(usize, usize), Error>, Option<Process>) {
+        let result =
+            RequestType::try_from(request.r#type).and_then(|r#type| Ok((r#type, request.path()?)));
+
+        match result {
+            Ok((RequestType::Store, path)) => (
+                self.store(request.pid, path, sender_pid)
+                    .map(|size| (size, 0)),
+                None,
+            ),
+            Ok((RequestType::Restore, path)) => match self.restore(path) {
+                Ok((process, size, lost_handles)) => (Ok((size, lost_handles)), Some(process)),
+                Err(err) => (Err(err), None),
+            },
+            Err(err) => (Err(err), None),
+        }
+    }
+
+    fn store(&self, pid: u64, path: &str, sender_pid: u64) -> Result<usize, Error> {
+        let process = Process::open(pid)?;
+
+        if sender_pid != process.info().creator_pid && sender_pid != self.spawner {
+            return Err(Error::NotSupported);
+        }
+
+        // Left suspended if it already was
+        let suspended = !process.info().suspended;
+        if suspended {
+            process.suspend()?;
+        }
+        let checkpoint = Checkpoint::capture(&process);
+        if suspended {
+            process.resume()?;
+        }
+        let checkpoint = checkpoint?;
+
+        // Restore would fail: do not store an unusable file
+        if !checkpoint.is_restorable() {
+            return Err(Error::ObjectNotReady);
+        }
+
+        let data = checkpoint.to_bytes();
+        write_file(&self.vfs, path, &data)?;
+
+        debug!(
+            "Process {} ({}) stored in {} ({} bytes)",
+            checkpoint.name,
+            pid,
+            path,
+            data.len()
+        );
+
+        Ok(data.len())
+    }
+
+    fn restore(&self, path: &str) -> Result<(Process, usize, usize), Error> {
+        let data = read_file(&self.vfs, path)?;
+        let checkpoint = Checkpoint::parse(&data)?;
+
+        let restored = checkpoint.restore()?;
+        restored.process.resume()?;
+
+        debug!(
+            "Process {} restored from {} as {} ({} handles lost)",
+            checkpoint.name,
+            path,
+            restored.process.pid(),
+            restored.lost_handles.len()
+        );
+
+        Ok((restored.process, data.len(), restored.lost_handles.len()))
+    }
+}
+
+fn read_file(vfs: &Vfs, path: &str) -> Result<Vec<u8>, Error> {
+    let file = vfs.open(path, OpenFlags::NONE)?;
+
+    let mut data = vec![0; file.info().size as usize];
+
+    let mut offset = 0;
+    while offset < data.len() {
+        let read = file.read_at(offset as u64, &mut data[offset..])?;
+        if read == 0 {
+            break;
+        }
+        offset += read;
+    }
+
+    data.truncate(offset);
+    Ok(data)
+}
+
+fn write_file(vfs: &Vfs, path: &str, data: &[u8]) -> Result<(), Error> {
+    let file = vfs.open(path, OpenFlags::CREATE | OpenFlags::TRUNCATE)?;
+
+    let mut offset = 0;
+    while offset < data.len() {
+        offset += file.write_at(offset as u64, &data[offset..])?;
+    }
+
+    Ok(())
+}
@@ -0,0 +1,161 @@
+#![no_std]
+#![no_main]
+
+extern crate alloc;
+extern crate libruntime;
+
+use core::mem;
+
+use libruntime::{
+    clipboard::{PayloadInfo, Reply, Request, RequestType, SERVER_PORT_NAME},
+    failure,
+    introspection::{self, ResourceTracker},
+    kobject::{Error, Handle, MemoryObject, Message, Port, PortSender, Thread},
+};
+use log::{debug, info, warn};
+
+libruntime::entry!(main);
+
+/// Content of the clipboard
+struct Content {
+    info: PayloadInfo,
+    object: MemoryObject,
+}
+
+struct Clipboard {
+    content: Option<Content>,
+    serial: u64,
+    /// Content, by client which set it
+    resources: ResourceTracker,
+}
+
+fn main() {
+    let (receiver, _sender) =
+        Port::create(Some(SERVER_PORT_NAME)).expect("Could not create server port");
+
+    info!("Clipboard ready on port '{}'", SERVER_PORT_NAME);
+
+    let mut clipboard = Clipboard {
+        content: None,
+        serial: 0,
+        resources: ResourceTracker::new(),
+    };
+
+    loop {
+        let mut message = match receiver.blocking_receive() {
+            Ok(message) => message,
+            Err(err) => {
+                warn!("Could not receive request: {:?}", err);
+                continue;
+            }
+        };
+
+        let reply_port = match PortSender::from_handle(message.take_handle(0)) {
+            Ok(port) => port,
+            Err(_) => {
+                warn!("Dropping request without reply port");
+                continue;
+            }
+        };
+
+        if let Some(request) = introspection::Request::from_message(&message) {
+            clipboard.resources.reply(&request, &reply_port);
+            continue;
+        }
+
+        // If processing panics, the client gets a failure report instead of the reply
+        let _request = failure::begin_request(&reply_port, message.correlation());
+
+        let request = *unsafe { message.data::<Request>() };
+        // Shed requests whose caller gave up waiting
+        let (result, object) = match Thread::check_deadline() {
+            Ok(()) => clipboard.process_request(&request, &mut message),
+            Err(err) => (Err(err), None),
+        };
+
+        let reply = Reply::new(result);
+        let mut handles = [object.map_or(Handle::invalid(), MemoryObject::into_handle)];
+        let mut reply_message = unsafe { Message::new(&reply, &mut handles) };
+        if let Err(err) = reply_port.send(&mut reply_message) {
+            warn!("Could not send reply: {:?}", err);
+        }
+    }
+}
+
+impl Clipboard {
+    /// Process a request, returns the result and the memory object to send with the reply
+    fn process_request(
+        &mut self,
+        request: &Request,
+        message: &mut Message,
+    ) -> (Result<PayloadInfo, Error>, Option<MemoryObject>) {
+        let r#type = match RequestType::try_from(request.r#type) {
+            Ok(r#type) => r#type,
+            Err(err) => return (Err(err), None),
+        };
+
+        match r#type {
+            RequestType::Set => (self.set(request, message), None),
+            RequestType::Get => match &self.content {
+                // Shared: the client must not be able to modify the content of the other clients
+                Some(content) => match content.object.read_only() {
+                    Ok(object) => (Ok(content.info), Some(object)),
+                    Err(err) => (Err(err), None),
+                },
+                None => (Err(Error::ObjectNotFound), None),
+            },
+            RequestType::Take => match self.replace_content(None, 0) {
+                Some(content) => {
+                    debug!("Content {} taken", content.info.serial);
+                    (Ok(content.info), Some(content.object))
+                }
+                None => (Err(Error::ObjectNotFound), None),
+            },
+            RequestType::Clear => {
+                self.replace_content(None, 0);
+                (Ok(request.info), None)
+            }
+            RequestType::Info => match &self.content {
+                Some(content) => (Ok(content.info), None),
+                None => (Err(Error::ObjectNotFound), None),
+            },
+        }
+    }
+
+    fn set(&mut self, request: &Request, message: &mut Message) -> Result<PayloadInfo, Error> {
+        // Validate the type
+        request.info.mime_type()?;
+
+        let object = MemoryObject::from_handle(message.take_handle(1))
+            .map_err(|_| Error::InvalidArgument)?;
+
+        self.serial += 1;
+
+        let mut info = request.info;
+        info.serial = self.serial;
+
+        debug!(
+            "Content {} set ({}, {} bytes)",
+            info.serial,
+            info.mime_type()?,
+            info.size
+        );
+
+        self.replace_content(Some(Content { info, object }), message.sender_pid());
+        Ok(info)
+    }
+
+    /// Replace the content, returns the previous one
+    fn replace_content(&mut self, content: Option<Content>, owner_pid: u64) -> Option<Content> {
+        if let Some(previous) = &self.content {
+            self.resources.remove("content", previous.info.serial);
+        }
+
+        if let Some(content) = &content {
+            self.resources
+                .add(owner_pid, "content", content.info.serial);
+        }
+
+        mem::replace(&mut self.content, content)
+    }
+}
@@ -0,0 +1,223 @@
+#![no_std]
+#![no_main]
+
+// Crash dumps: stores an ELF core file of each process which gets a thread in error, and prints reports
+//
+// Dumps are written in `/crash/<pid>-<name>.core` (see `libruntime::debug::capture_core`): they are standard
+// ELF core files, to read with host gdb (`gdb <binary> <core>`) once copied out of the vfs.
+// At start, the dumps already stored are listed, and the report of the latest one is printed.
+//
+// Backtraces are symbolized with the binaries found in the vfs (`/bin/<name>`, `/lib/<name>`),
+// else they print `object+offset`.
+
+extern crate alloc;
+extern crate libruntime;
+
+use alloc::{collections::BTreeSet, format, string::String, vec, vec::Vec};
+use libruntime::{
+    debug::{capture_core, CoreDump, Symbolizer, CORE_EXTENSION, CRASH_DIR},
+    fs::{NodeInfo, NodeKind},
+    kobject::{
+        Error, Exception, Process, Thread, ThreadEventType, ThreadListener, ThreadListenerFilter,
+        ThreadSupervisor,
+    },
+    manifest::SandboxFlags,
+    process_server::LIBRARY_DIR,
+    vfs::{OpenFlags, Vfs},
+};
+use log::{error, info, warn};
+
+libruntime::entry!(main);
+
+libruntime::manifest_sandbox!(SandboxFlags::DEBUG);
+
+/// Directory of the programs binaries, used to symbolize backtraces
+const BINARY_DIR: &str = "/bin/";
+
+fn main() {
+    let vfs = match Vfs::wait_connect() {
+        Ok(vfs) => vfs,
+        Err(err) => {
+            error!("Could not connect to vfs-server: {:?}", err);
+            return;
+        }
+    };
+
+    match vfs.mkdir(CRASH_DIR) {
+        Ok(_) | Err(Error::ObjectNameDuplicate) => {}
+        Err(err) => {
+            error!("Could not create '{}': {:?}", CRASH_DIR, err);
+            return;
+        }
+    }
+
+    if let Err(err) = list_dumps(&vfs) {
+        warn!("Could not list the stored dumps: {:?}", err);
+    }
+
+    let listener = match ThreadListener::create(ThreadListenerFilter::All) {
+        Ok(listener) => listener,
+        Err(err) => {
+            error!("Could not create thread listener: {:?}", err);
+            return;
+        }
+    };
+
+    info!("Watching crashes, dumps stored in '{}'", CRASH_DIR);
+
+    loop {
+        let event = match listener.blocking_receive() {
+            Ok(event) => event,
+            Err(err) => {
+                warn!("Could not receive thread event: {:?}", err);
+                continue;
+            }
+        };
+
+        if event.r#type == ThreadEventType::Error {
+            if let Err(err) = dump_crash(&vfs, event.tid) {
+                warn!(
+                    "Could not dump the crash of thread {}: {:?}",
+                    event.tid, err
+                );
+            }
+        }
+    }
+}
+
+/// List the stored dumps, and print the report of the latest one
+fn list_dumps(vfs: &Vfs) -> Result<(), Error> {
+    let mut latest: Option<(String, NodeInfo)> = None;
+
+    for entry in vfs.list(CRASH_DIR)? {
+        if entry.kind != NodeKind::File || !entry.name.ends_with(CORE_EXTENSION) {
+            continue;
+        }
+
+        let path = format!("{}/{}", CRASH_DIR, entry.name);
+        let node = vfs.stat(&path)?;
+        info!("{}: {} bytes", path, node.size);
+
+        if latest
+            .as_ref()
+            .map_or(true, |(_, latest)| node.mtime > latest.mtime)
+        {
+            latest = Some((path, node));
+        }
+    }
+
+    if let Some((path, _)) = latest {
+        info!("Latest dump: {}", path);
+        print_report(vfs, &read_file(vfs, &path)?)?;
+    }
+
+    Ok(())
+}
+
+/// Dump the process of a thread in error, store it and print its report
+fn dump_crash(vfs: &Vfs, tid: u64) -> Result<(), Error> {
+    let thread = Thread::open(tid)?;
+    let pid = thread.info().pid;
+
+    // Our own crashes cannot be dumped by ourselves
+    if pid == Process::current().pid() {
+        return Ok(());
+    }
+
+    let exception: Exception = ThreadSupervisor::new(&thread).error_info()?;
+
+    // Breakpoints, watchpoints and single-steps are handled by debuggers (eg: gdbstub-server)
+    if let Exception::Debug(_) = exception {
+        return Ok(());
+    }
+
+    let process = Process::open(pid)?;
+
+    // Stop the other threads while capturing, if we are allowed to (only the creator of the process is)
+    let suspended = process.suspend().is_ok();
+    let core = capture_core(&process, Some((tid, exception)));
+    if suspended {
+        process.resume()?;
+    }
+    let core = core?;
+
+    let path = format!(
+        "{}/{}-{}{}",
+        CRASH_DIR,
+        pid,
+        process.name()?,
+        CORE_EXTENSION
+    );
+    write_file(vfs, &path, &core)?;
+    info!(
+        "Thread {} crashed ({:?}), dump stored in {} ({} bytes)",
+        tid,
+        exception,
+        path,
+        core.len()
+    );
+
+    print_report(vfs, &core)
+}
+
+fn print_report(vfs: &Vfs, core: &[u8]) -> Result<(), Error> {
+    let dump = CoreDump::parse(core)?;
+
+    // Load the binaries of the mapped objects first: the symbolizer borrows them
+    let names: BTreeSet<&str> = dump
+        .regions()
+        .iter()
+        .filter_map(|region| region.name.as_deref())
+        .collect();
+
+    let binaries: Vec<(&str, Vec<u8>)> = names
+        .into_iter()
+        .filter_map(|name| Some((name, find_binary(vfs, name)?)))
+        .collect();
+
+    let mut symbolizer = Symbolizer::new();
+    for (name, binary) in binaries.iter() {
+        symbolizer.add_binary(name, binary);
+    }
+
+    for line in format!("{}", dump.report(&symbolizer)).lines() {
+        info!("{}", line);
+    }
+
+    Ok(())
+}
+
+fn find_binary(vfs: &Vfs, name: &str) -> Option<Vec<u8>> {
+    [BINARY_DIR, LIBRARY_DIR]
+        .iter()
+        .find_map(|dir| read_file(vfs, &format!("{}{}", dir, name)).ok())
+}
+
+fn read_file(vfs: &Vfs, path: &str) -> Result<Vec<u8>, Error> {
+    let file = vfs.open(path, OpenFlags::NONE)?;
+
+    let mut data = vec![0; file.info().size as usize];
+
+    let mut offset = 0;
+    while offset < data.len() {
+        let read = file.read_at(offset as u64, &mut data[offset..])?;
+        if read == 0 {
+            break;
+        }
+        offset += read;
+    }
+
+    data.truncate(offset);
+    Ok(data)
+}
+
+fn write_file(vfs: &Vfs, path: &str, data: &[u8]) -> Result<(), Error> {
+    let file = vfs.open(path, OpenFlags::CREATE | OpenFlags::TRUNCATE)?;
+
+    let mut offset = 0;
+    while offset < data.len() {
+        offset += file.write_at(offset as u64, &data[offset..])?;
+    }
+
+    Ok(())
+}
@@ -0,0 +1,238 @@
+#![no_std]
+#![no_main]
+
+extern crate alloc;
+extern crate libruntime;
+
+use alloc::{collections::BTreeMap, vec::Vec};
+use libruntime::{
+    display::{
+        surface_memory_size, InputEvent, Reply, Request, RequestType, SurfaceEvent,
+        SurfaceEventType, MAX_SURFACE_SIZE, SERVER_PORT_NAME,
+    },
+    failure,
+    introspection::{self, ResourceTracker},
+    kobject::{Error, Handle, MemoryObject, Message, Port, PortSender},
+};
+use log::{debug, info, warn};
+
+libruntime::entry!(main);
+
+/// Surface managed by the server
+struct Surface {
+    // Keep the pixels, for composition
+    _memory: MemoryObject,
+    events: PortSender,
+}
+
+struct Server {
+    surfaces: BTreeMap<u64, Surface>,
+    /// Surfaces ids, the focused one last
+    focus_stack: Vec<u64>,
+    next_id: u64,
+    /// Surfaces, by client
+    resources: ResourceTracker,
+}
+
+fn main() {
+    let (receiver, _sender) =
+        Port::create(Some(SERVER_PORT_NAME)).expect("Could not create server port");
+
+    info!("Display server ready on port '{}'", SERVER_PORT_NAME);
+
+    let mut server = Server {
+        surfaces: BTreeMap::new(),
+        focus_stack: Vec::new(),
+        next_id: 1,
+        resources: ResourceTracker::new(),
+    };
+
+    loop {
+        let mut message = match receiver.blocking_receive() {
+            Ok(message) => message,
+            Err(err) => {
+                warn!("Could not receive request: {:?}", err);
+                continue;
+            }
+        };
+
+        let reply_port = match PortSender::from_handle(message.take_handle(0)) {
+            Ok(port) => port,
+            Err(_) => {
+                warn!("Dropping request without reply port");
+                continue;
+            }
+        };
+
+        if let Some(request) = introspection::Request::from_message(&message) {
+            server.resources.reply(&request, &reply_port);
+            continue;
+        }
+
+        // If processing panics, the client gets a failure report instead of the reply
+        let _request = failure::begin_request(&reply_port, message.correlation());
+
+        let request = *unsafe { message.data::<Request>() };
+        let (result, memory) = server.process_request(&request, &mut message);
+
+        let reply = Reply::new(result);
+        let mut handles = [memory.map_or(Handle::invalid(), MemoryObject::into_handle)];
+        let mut reply_message = unsafe { Message::new(&reply, &mut handles) };
+        if let Err(err) = reply_port.send(&mut reply_message) {
+            warn!("Could not send reply: {:?}", err);
+        }
+    }
+}
+
+impl Server {
+    /// Process a request, returns the result and the memory object to send with the reply
+    fn process_request(
+        &mut self,
+        request: &Request,
+        message: &mut Message,
+    ) -> (Result<u64, Error>, Option<MemoryObject>) {
+        let r#type = match RequestType::try_from(request.r#type) {
+            Ok(r#type) => r#type,
+            Err(err) => return (Err(err), None),
+        };
+
+        match r#type {
+            RequestType::CreateSurface => match self.create_surface(request, message) {
+                Ok((id, memory)) => (Ok(id), Some(memory)),
+                Err(err) => (Err(err), None),
+            },
+            RequestType::DestroySurface => (self.destroy_surface(request.surface), None),
+            RequestType::Focus => (self.focus(request.surface), None),
+            RequestType::Input => (self.route_input(&request.input), None),
+        }
+    }
+
+    fn create_surface(
+        &mut self,
+        request: &Request,
+        message: &mut Message,
+    ) -> Result<(u64, MemoryObject), Error> {
+        if request.width == 0
+            || request.height == 0
+            || request.width > MAX_SURFACE_SIZE
+            || request.height > MAX_SURFACE_SIZE
+        {
+            return Err(Error::InvalidArgument);
+        }
+
+        let events =
+            PortSender::from_handle(message.take_handle(1)).map_err(|_| Error::InvalidArgument)?;
+
+        let memory = MemoryObject::create(surface_memory_size(request.width, request.height))?;
+
+        let id = self.next_id;
+        self.next_id += 1;
+
+        self.surfaces.insert(
+            id,
+            Surface {
+                _memory: memory.clone(),
+                events,
+            },
+        );
+        self.resources.add(message.sender_pid(), "surface", id);
+
+        debug!(
+            "Surface {} created ({}x{})",
+            id, request.width, request.height
+        );
+
+        self.focus(id)?;
+        Ok((id, memory))
+    }
+
+    fn destroy_surface(&mut self, id: u64) -> Result<u64, Error> {
+        if self.surfaces.remove(&id).is_none() {
+            return Err(Error::ObjectNotFound);
+        }
+        self.resources.remove("surface", id);
+
+        let was_focused = self.focused() == Some(id);
+        self.focus_stack.retain(|&value| value != id);
+
+        debug!("Surface {} destroyed", id);
+
+        if was_focused {
+            self.notify_focused(SurfaceEventType::FocusGained);
+        }
+
+        Ok(id)
+    }
+
+    fn focus(&mut self, id: u64) -> Result<u64, Error> {
+        if !self.surfaces.contains_key(&id) {
+            return Err(Error::ObjectNotFound);
+        }
+
+        if self.focused() == Some(id) {
+            return Ok(id);
+        }
+
+        self.notify_focused(SurfaceEventType::FocusLost);
+
+        self.focus_stack.retain(|&value| value != id);
+        self.focus_stack.push(id);
+
+        self.notify_focused(SurfaceEventType::FocusGained);
+        Ok(id)
+    }
+
+    fn route_input(&mut self, input: &InputEvent) -> Result<u64, Error> {
+        input.kind()?;
+
+        let id = self.focused().ok_or(Error::ObjectNotFound)?;
+        self.send_event(id, SurfaceEventType::Input, *input);
+
+        // The surface may have been dropped while sending
+        self.focused().ok_or(Error::ObjectNotFound)
+    }
+
+    fn focused(&self) -> Option<u64> {
+        self.focus_stack.last().copied()
+    }
+
+    /// Send a focus event to the focused surface
+    fn notify_focused(&mut self, r#type: SurfaceEventType) {
+        if let Some(id) = self.focused() {
+            let input = InputEvent {
+                kind: 0,
+                code: 0,
+                value: 0,
+                modifiers: 0,
+            };
+
+            self.send_event(id, r#type, input);
+        }
+    }
+
+    fn send_event(&mut self, id: u64, r#type: SurfaceEventType, input: InputEvent) {
+        let Some(surface) = self.surfaces.get(&id) else {
+            return;
+        };
+
+        let event = SurfaceEvent {
+            r#type: r#type as u64,
+            surface: id,
+            input,
+        };
+
+        let mut message = unsafe { Message::new(&event, &mut []) };
+
+        match surface.events.send(&mut message) {
+            Ok(()) => {}
+            Err(Error::ObjectClosed) => {
+                // The client is gone: drop its surface, the focus goes to the previous one
+                info!("Surface {} event port closed, dropping it", id);
+                let _ = self.destroy_surface(id);
+            }
+            Err(err) => {
+                warn!("Could not send event to surface {}: {:?}", id, err);
+            }
+        }
+    }
+}
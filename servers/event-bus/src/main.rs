@@ -0,0 +1,133 @@
+#![no_std]
+#![no_main]
+
+extern crate alloc;
+extern crate libruntime;
+
+use alloc::{collections::BTreeMap, string::String};
+use libruntime::{
+    event_bus::{
+        topic_port_name, Reply, Request, RequestType, TopicSchema, DATA_ITEMS, SERVER_PORT_NAME,
+    },
+    failure,
+    introspection::{self, ResourceTracker},
+    kobject::{Error, Message, Port, PortReceiver, PortSender},
+};
+use log::{info, warn};
+
+libruntime::entry!(main);
+
+/// Topic managed by the server
+struct Topic {
+    schema: TopicSchema,
+    // Keep the broadcast port open
+    _receiver: PortReceiver,
+}
+
+fn main() {
+    let (receiver, _sender) =
+        Port::create(Some(SERVER_PORT_NAME)).expect("Could not create server port");
+
+    info!("Event bus ready on port '{}'", SERVER_PORT_NAME);
+
+    let mut topics = BTreeMap::new();
+    // Topics, by client which created them
+    let mut resources = ResourceTracker::new();
+
+    loop {
+        let mut message = match receiver.blocking_receive() {
+            Ok(message) => message,
+            Err(err) => {
+                warn!("Could not receive request: {:?}", err);
+                continue;
+            }
+        };
+
+        let reply_port = match PortSender::from_handle(message.take_handle(0)) {
+            Ok(port) => port,
+            Err(_) => {
+                warn!("Dropping request without reply port");
+                continue;
+            }
+        };
+
+        if let Some(request) = introspection::Request::from_message(&message) {
+            resources.reply(&request, &reply_port);
+            continue;
+        }
+
+        // If processing panics, the client gets a failure report instead of the reply
+        let _request = failure::begin_request(&reply_port, message.correlation());
+
+        let request = *unsafe { message.data::<Request>() };
+        let result = process_request(&mut topics, &mut resources, &request, &message);
+
+        let reply = Reply::new(result);
+        let mut reply_message = unsafe { Message::new(&reply, &mut []) };
+        if let Err(err) = reply_port.send(&mut reply_message) {
+            warn!("Could not send reply: {:?}", err);
+        }
+    }
+}
+
+fn process_request(
+    topics: &mut BTreeMap<String, Topic>,
+    resources: &mut ResourceTracker,
+    request: &Request,
+    message: &Message,
+) -> Result<TopicSchema, Error> {
+    let name = request.topic()?;
+
+    match RequestType::try_from(request.r#type)? {
+        RequestType::CreateTopic => {
+            let created = !topics.contains_key(name);
+            let schema = create_topic(topics, name, request.schema)?;
+
+            // Topics are never destroyed: their ids are their creation order
+            if created {
+                resources.add(message.sender_pid(), "topic", topics.len() as u64);
+            }
+
+            Ok(schema)
+        }
+        RequestType::GetSchema => Ok(get_topic(topics, name)?.schema),
+    }
+}
+
+fn create_topic(
+    topics: &mut BTreeMap<String, Topic>,
+    name: &str,
+    schema: TopicSchema,
+) -> Result<TopicSchema, Error> {
+    if schema.data_capacity == 0 || schema.data_capacity as usize > DATA_ITEMS {
+        return Err(Error::InvalidArgument);
+    }
+
+    if let Some(topic) = topics.get(name) {
+        return if topic.schema == schema {
+            Ok(schema)
+        } else {
+            Err(Error::ObjectNameDuplicate)
+        };
+    }
+
+    let (receiver, _sender) = Port::create_broadcast(Some(&topic_port_name(name)))?;
+
+    topics.insert(
+        String::from(name),
+        Topic {
+            schema,
+            _receiver: receiver,
+        },
+    );
+
+    info!(
+        "Created topic '{}' (event type {}, {} data items)",
+        name, schema.event_type, schema.data_capacity
+    );
+    Ok(schema)
+}
+
+fn get_topic<'a>(topics: &'a BTreeMap<String, Topic>, name: &str) -> Result<&'a Topic, Error> {
+    topics.get(name).ok_or(Error::ObjectNotFound)
+}
@@ -0,0 +1,503 @@
+#![no_std]
+#![no_main]
+
+// GDB remote stub for userland processes, over COM2
+//
+// gdb connects in extended mode, and attaches to a process by its pid:
+// `target extended-remote <COM2 chardev>`, then `attach <pid>`.
+// Note: the kernel stub (`gdbstub` feature) also uses COM2, they cannot be used together.
+//
+// Supported: threads, registers, memory, hardware breakpoints and watchpoints (4 per process), continue,
+// single-step, interrupt (Ctrl-C) and detach. Processes cannot be killed, `kill` detaches.
+
+extern crate alloc;
+extern crate libruntime;
+
+mod packet;
+mod target;
+
+use alloc::{vec, vec::Vec};
+use libdriver::uart::{Uart, COM2, PORT_COUNT};
+use libruntime::{
+    debug::exception_signal,
+    kobject::{Error, Exception, ThreadContextRegister, WatchpointKind, PROCESS_MEMORY_MAX_SIZE},
+    manifest::SandboxFlags,
+};
+use log::{info, warn};
+
+use packet::Connection;
+use target::{Breakpoint, Stop, Target, WATCHPOINTS_STATUS_MASK};
+
+libruntime::entry!(main);
+
+libruntime::manifest_ioports!(COM2..(COM2 + PORT_COUNT));
+libruntime::manifest_sandbox!(SandboxFlags::DEBUG);
+
+const OK: &[u8] = b"OK";
+/// ENOENT
+const ERROR_NOT_FOUND: &[u8] = b"E02";
+/// EFAULT
+const ERROR_FAULT: &[u8] = b"E0e";
+/// EINVAL
+const ERROR_INVALID: &[u8] = b"E16";
+/// ENOSPC
+const ERROR_NO_SPACE: &[u8] = b"E1c";
+
+const SIGINT: u32 = 2;
+const SIGTRAP: u32 = 5;
+
+/// Registers in gdb amd64 order: rax, rbx, rcx, rdx, rsi, rdi, rbp, rsp, r8-r15, rip (64 bits), then eflags (32 bits)
+const REGISTERS: [ThreadContextRegister; 17] = [
+    ThreadContextRegister::Rax,
+    ThreadContextRegister::Rbx,
+    ThreadContextRegister::Rcx,
+    ThreadContextRegister::Rdx,
+    ThreadContextRegister::Rsi,
+    ThreadContextRegister::Rdi,
+    ThreadContextRegister::Rbp,
+    ThreadContextRegister::Rsp,
+    ThreadContextRegister::R8,
+    ThreadContextRegister::R9,
+    ThreadContextRegister::R10,
+    ThreadContextRegister::R11,
+    ThreadContextRegister::R12,
+    ThreadContextRegister::R13,
+    ThreadContextRegister::R14,
+    ThreadContextRegister::R15,
+    ThreadContextRegister::InstructionPointer,
+];
+
+enum Action {
+    Reply(Vec<u8>),
+    /// Resume the process, with the selected thread single-stepping if set, and reply when it stops
+    Resume(bool),
+    /// No reply (kill)
+    None,
+}
+
+struct Stub {
+    target: Option<Target>,
+    /// Thread selected for registers and single-step
+    current: u64,
+    last_stop: Option<Stop>,
+}
+
+fn main() {
+    let connection = Connection::new(unsafe { Uart::new(COM2) });
+    let mut stub = Stub {
+        target: None,
+        current: 0,
+        last_stop: None,
+    };
+
+    info!("GDB stub: waiting for gdb on COM2 ('target extended-remote', then 'attach <pid>')");
+
+    loop {
+        let command = connection.receive();
+
+        match stub.process(&command) {
+            Action::Reply(reply) => connection.send(&reply),
+            Action::Resume(step) => {
+                let reply = stub.run(step, || connection.interrupted());
+                connection.send(&reply);
+            }
+            Action::None => {}
+        }
+    }
+}
+
+impl Stub {
+    fn process(&mut self, command: &[u8]) -> Action {
+        let Some((&name, args)) = command.split_first() else {
+            return Action::Reply(Vec::new());
+        };
+
+        // Commands without process
+        match name {
+            b'!' => return Action::Reply(Vec::from(OK)),
+            b'?' => return Action::Reply(self.stop_reply()),
+            b'v' if args.starts_with(b"Attach;") => return self.attach(&args[7..]),
+            b'q' if args.starts_with(b"Supported") => {
+                return Action::Reply(Vec::from(b"PacketSize=1000"))
+            }
+            b'q' if args.starts_with(b"Attached") => return Action::Reply(Vec::from(b"1")),
+            _ => {}
+        }
+
+        if self.target.is_none() {
+            return match name {
+                b'D' | b'k' => Action::Reply(Vec::from(OK)),
+                b'g' | b'G' | b'm' | b'M' | b'Z' | b'z' | b'c' | b's' | b'H' | b'T' => {
+                    Action::Reply(Vec::from(ERROR_NOT_FOUND))
+                }
+                _ => Action::Reply(Vec::new()),
+            };
+        }
+
+        match name {
+            b'g' => Action::Reply(self.read_registers()),
+            b'G' => reply_status(self.write_registers(args)),
+            b'm' => Action::Reply(self.read_memory(args)),
+            b'M' => reply_status(self.write_memory(args)),
+            b'Z' => self.insert_breakpoint(args),
+            b'z' => self.remove_breakpoint(args),
+            b'c' => self.resume(args, false),
+            b's' => self.resume(args, true),
+            b'H' => self.select_thread(args),
+            b'T' => self.thread_alive(args),
+            b'q' if args == b"fThreadInfo" => Action::Reply(self.thread_list()),
+            b'q' if args == b"sThreadInfo" => Action::Reply(Vec::from(b"l")),
+            b'q' if args == b"C" => {
+                let mut reply = Vec::from(b"QC");
+                packet::push_hex(&mut reply, self.current);
+                Action::Reply(reply)
+            }
+            b'D' => {
+                self.detach();
+                Action::Reply(Vec::from(OK))
+            }
+            b'k' => {
+                self.detach();
+                Action::None
+            }
+            _ => {
+                // Unsupported
+                Action::Reply(Vec::new())
+            }
+        }
+    }
+
+    fn target(&self) -> &Target {
+        self.target.as_ref().expect("no target")
+    }
+
+    fn target_mut(&mut self) -> &mut Target {
+        self.target.as_mut().expect("no target")
+    }
+
+    // vAttach;pid
+    fn attach(&mut self, args: &[u8]) -> Action {
+        let Some(pid) = packet::parse_hex(args) else {
+            return Action::Reply(Vec::from(ERROR_INVALID));
+        };
+
+        self.detach();
+
+        match Target::attach(pid) {
+            Ok((target, stop)) => {
+                info!("Attached to process {}", pid);
+                self.target = Some(target);
+                self.set_stop(stop);
+                Action::Reply(self.stop_reply())
+            }
+            Err(err) => {
+                warn!("Could not attach to process {}: {:?}", pid, err);
+                Action::Reply(Vec::from(ERROR_NOT_FOUND))
+            }
+        }
+    }
+
+    fn detach(&mut self) {
+        if let Some(target) = self.target.take() {
+            info!("Detached from process {}", target.pid());
+            target.detach();
+        }
+
+        self.last_stop = None;
+    }
+
+    /// Resume the process until it stops, and get the stop reply
+    fn run(&mut self, step: bool, interrupted: impl Fn() -> bool) -> Vec<u8> {
+        let current = self.current;
+        let target = self.target_mut();
+
+        if let Err(err) = target.resume(step.then_some(current)) {
+            warn!("Could not resume process {}: {:?}", target.pid(), err);
+            return Vec::from(ERROR_INVALID);
+        }
+
+        match target.wait(interrupted) {
+            Ok(stop) => self.set_stop(stop),
+            Err(err) => {
+                warn!("Could not wait for process {}: {:?}", target.pid(), err);
+                self.set_stop(Stop::Exited);
+            }
+        }
+
+        self.stop_reply()
+    }
+
+    fn set_stop(&mut self, stop: Stop) {
+        match stop {
+            Stop::Attached(tid)
+            | Stop::Interrupted(tid)
+            | Stop::Exception(tid, _)
+            | Stop::Step(tid) => self.current = tid,
+            Stop::Exited => {
+                if let Some(target) = self.target.take() {
+                    info!("Process {} exited", target.pid());
+                }
+            }
+        }
+
+        self.last_stop = Some(stop);
+    }
+
+    /// Stop reply: `T<signal>thread:<tid>;[watch:<address>;]`, or `W00` if the process exited
+    fn stop_reply(&self) -> Vec<u8> {
+        let (tid, signal, exception) = match self.last_stop {
+            None | Some(Stop::Exited) => return Vec::from(b"W00"),
+            Some(Stop::Attached(tid) | Stop::Step(tid)) => (tid, SIGTRAP, None),
+            Some(Stop::Interrupted(tid)) => (tid, SIGINT, None),
+            Some(Stop::Exception(tid, exception)) => {
+                (tid, exception_signal(&exception), Some(exception))
+            }
+        };
+
+        let mut reply = Vec::from(b"T");
+        packet::push_hex_byte(&mut reply, signal as u8);
+        reply.extend_from_slice(b"thread:");
+        packet::push_hex(&mut reply, tid);
+        reply.push(b';');
+
+        // Watchpoints: tell gdb which one triggered
+        if let Some(Exception::Debug(status)) = exception {
+            let index = (status & WATCHPOINTS_STATUS_MASK).trailing_zeros() as usize;
+            let breakpoint = self
+                .target
+                .as_ref()
+                .and_then(|target| target.breakpoint(index));
+
+            let name: Option<&[u8]> = match breakpoint.map(|breakpoint| breakpoint.kind) {
+                Some(WatchpointKind::Write) => Some(b"watch"),
+                Some(WatchpointKind::ReadWrite) => Some(b"awatch"),
+                _ => None,
+            };
+
+            if let (Some(name), Some(breakpoint)) = (name, breakpoint) {
+                reply.extend_from_slice(name);
+                reply.push(b':');
+                packet::push_hex(&mut reply, breakpoint.address as u64);
+                reply.push(b';');
+            }
+        }
+
+        reply
+    }
+
+    // Hg<tid> / Hc<tid>
+    fn select_thread(&mut self, args: &[u8]) -> Action {
+        let Some(tid) = args.get(1..) else {
+            return Action::Reply(Vec::from(ERROR_INVALID));
+        };
+
+        // 0: any thread, -1: all threads
+        if tid == b"0" || tid == b"-1" {
+            return Action::Reply(Vec::from(OK));
+        }
+
+        match packet::parse_hex(tid) {
+            Some(tid) if self.target().has_thread(tid) => {
+                self.current = tid;
+                Action::Reply(Vec::from(OK))
+            }
+            _ => Action::Reply(Vec::from(ERROR_NOT_FOUND)),
+        }
+    }
+
+    // T<tid>
+    fn thread_alive(&self, args: &[u8]) -> Action {
+        match packet::parse_hex(args) {
+            Some(tid) if self.target().has_thread(tid) => Action::Reply(Vec::from(OK)),
+            _ => Action::Reply(Vec::from(ERROR_NOT_FOUND)),
+        }
+    }
+
+    fn thread_list(&self) -> Vec<u8> {
+        let mut reply = Vec::from(b"m");
+
+        for (index, tid) in self.target().threads().enumerate() {
+            if index > 0 {
+                reply.push(b',');
+            }
+            packet::push_hex(&mut reply, tid);
+        }
+
+        reply
+    }
+
+    fn read_registers(&self) -> Vec<u8> {
+        let mut reply = Vec::new();
+
+        let Ok(context) = self.target().context(self.current) else {
+            // Blocked in a syscall: registers are unavailable
+            reply.resize((REGISTERS.len() * 8 + 4) * 2, b'x');
+            return reply;
+        };
+
+        let values = [
+            context.rax,
+            context.rbx,
+            context.rcx,
+            context.rdx,
+            context.rsi,
+            context.rdi,
+            context.rbp,
+            context.rsp,
+            context.r8,
+            context.r9,
+            context.r10,
+            context.r11,
+            context.r12,
+            context.r13,
+            context.r14,
+            context.r15,
+            context.instruction_pointer,
+        ];
+
+        for value in values {
+            packet::push_hex_le(&mut reply, value as u64, 8);
+        }
+
+        packet::push_hex_le(&mut reply, context.cpu_flags as u64, 4);
+
+        reply
+    }
+
+    fn write_registers(&self, args: &[u8]) -> Option<()> {
+        const REG_DIGITS: usize = 16;
+        const FLAGS_DIGITS: usize = 8;
+
+        let mut registers = Vec::with_capacity(REGISTERS.len() + 1);
+        for (index, register) in REGISTERS.iter().enumerate() {
+            let start = index * REG_DIGITS;
+            let value = packet::parse_hex_le(args.get(start..start + REG_DIGITS)?)?;
+            registers.push((*register, value as usize));
+        }
+
+        let start = REGISTERS.len() * REG_DIGITS;
+        if let Some(flags) = args.get(start..start + FLAGS_DIGITS) {
+            let flags = packet::parse_hex_le(flags)?;
+            registers.push((ThreadContextRegister::CpuFlags, flags as usize));
+        }
+
+        self.target().update_context(self.current, &registers).ok()
+    }
+
+    // m addr,length
+    fn read_memory(&self, args: &[u8]) -> Vec<u8> {
+        let Some((address, len)) = parse_address_len(args) else {
+            return Vec::from(ERROR_INVALID);
+        };
+
+        let mut data = vec![0; len.min(PROCESS_MEMORY_MAX_SIZE)];
+
+        if self.target().read_memory(address, &mut data).is_err() {
+            return Vec::from(ERROR_FAULT);
+        }
+
+        let mut reply = Vec::with_capacity(data.len() * 2);
+        for byte in data {
+            packet::push_hex_byte(&mut reply, byte);
+        }
+
+        reply
+    }
+
+    // M addr,length:XX...
+    fn write_memory(&self, args: &[u8]) -> Option<()> {
+        let separator = args.iter().position(|&byte| byte == b':')?;
+        let (address, len) = parse_address_len(&args[..separator])?;
+        let data = &args[separator + 1..];
+
+        if data.len() != len * 2 {
+            return None;
+        }
+
+        let mut bytes = Vec::with_capacity(len);
+        for digits in data.chunks(2) {
+            bytes.push(packet::parse_hex(digits)? as u8);
+        }
+
+        self.target().write_memory(address, &bytes).ok()
+    }
+
+    // Z<type>,addr,kind
+    fn insert_breakpoint(&mut self, args: &[u8]) -> Action {
+        let Some(breakpoint) = parse_breakpoint(args) else {
+            return Action::Reply(Vec::new());
+        };
+
+        match self.target_mut().insert_breakpoint(breakpoint) {
+            Ok(()) => Action::Reply(Vec::from(OK)),
+            Err(Error::ObjectNotReady) => Action::Reply(Vec::from(ERROR_NO_SPACE)),
+            Err(_) => Action::Reply(Vec::from(ERROR_INVALID)),
+        }
+    }
+
+    // z<type>,addr,kind
+    fn remove_breakpoint(&mut self, args: &[u8]) -> Action {
+        let Some(breakpoint) = parse_breakpoint(args) else {
+            return Action::Reply(Vec::new());
+        };
+
+        self.target_mut().remove_breakpoint(breakpoint);
+        Action::Reply(Vec::from(OK))
+    }
+
+    // c [addr] / s [addr]
+    fn resume(&mut self, args: &[u8], step: bool) -> Action {
+        if !args.is_empty() {
+            let Some(address) = packet::parse_hex(args) else {
+                return Action::Reply(Vec::from(ERROR_INVALID));
+            };
+
+            let registers = [(ThreadContextRegister::InstructionPointer, address as usize)];
+            if self
+                .target()
+                .update_context(self.current, &registers)
+                .is_err()
+            {
+                return Action::Reply(Vec::from(ERROR_INVALID));
+            }
+        }
+
+        Action::Resume(step)
+    }
+}
+
+fn reply_status(status: Option<()>) -> Action {
+    match status {
+        Some(()) => Action::Reply(Vec::from(OK)),
+        None => Action::Reply(Vec::from(ERROR_INVALID)),
+    }
+}
+
+fn parse_address_len(args: &[u8]) -> Option<(usize, usize)> {
+    let separator = args.iter().position(|&byte| byte == b',')?;
+    let address = packet::parse_hex(&args[..separator])? as usize;
+    let len = packet::parse_hex(&args[separator + 1..])? as usize;
+
+    Some((address, len))
+}
+
+// type,addr,kind: 0 and 1 are breakpoints (both hardware), 2 write watchpoints, 4 access watchpoints
+fn parse_breakpoint(args: &[u8]) -> Option<Breakpoint> {
+    let (&r#type, args) = args.split_first()?;
+    let args = args.strip_prefix(b",")?;
+    let (address, size) = parse_address_len(args)?;
+
+    let (kind, size) = match r#type {
+        b'0' | b'1' => (WatchpointKind::Execute, 1),
+        b'2' => (WatchpointKind::Write, size),
+        b'4' => (WatchpointKind::ReadWrite, size),
+        // Read watchpoints are not supported by the hardware
+        _ => return None,
+    };
+
+    Some(Breakpoint {
+        address,
+        size,
+        kind,
+    })
+}
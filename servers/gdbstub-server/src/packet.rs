@@ -0,0 +1,172 @@
+//! GDB remote serial protocol framing, over the serial port
+//!
+//! Same framing as the kernel stub (`kernel/src/gdbstub/packet.rs`), but the serial port is polled from userland:
+//! the line is checked every `IDLE_POLL_PERIOD` between packets, and busy-polled within a packet
+//! so that the receive FIFO does not overflow.
+
+use core::{hint, time::Duration};
+
+use alloc::vec::Vec;
+use libdriver::uart::Uart;
+use libruntime::retry;
+
+const PACKET_START: u8 = b'$';
+const PACKET_END: u8 = b'#';
+const ACK: u8 = b'+';
+const NACK: u8 = b'-';
+/// Sent by gdb outside of packets to stop the target (Ctrl-C)
+const INTERRUPT: u8 = 0x03;
+
+/// Period at which the line is checked when no packet is being received
+const IDLE_POLL_PERIOD: Duration = Duration::from_millis(5);
+
+/// Connection to gdb
+#[derive(Debug)]
+pub struct Connection {
+    uart: Uart,
+}
+
+impl Connection {
+    pub fn new(uart: Uart) -> Self {
+        uart.init();
+        Self { uart }
+    }
+
+    /// Block until a valid packet is received, and acknowledge it
+    pub fn receive(&self) -> Vec<u8> {
+        loop {
+            // Skip everything until packet start (acks, interrupt requests)
+            while self.wait_byte() != PACKET_START {}
+
+            let mut data = Vec::new();
+            let mut checksum: u8 = 0;
+
+            loop {
+                let byte = self.spin_byte();
+                if byte == PACKET_END {
+                    break;
+                }
+
+                checksum = checksum.wrapping_add(byte);
+                data.push(byte);
+            }
+
+            let expected = [self.spin_byte(), self.spin_byte()];
+
+            if parse_hex(&expected) == Some(checksum as u64) {
+                self.uart.send(ACK);
+                return data;
+            }
+
+            self.uart.send(NACK);
+        }
+    }
+
+    /// Send a packet, until it is acknowledged
+    pub fn send(&self, data: &[u8]) {
+        let checksum = data.iter().fold(0u8, |acc, byte| acc.wrapping_add(*byte));
+
+        loop {
+            self.uart.send(PACKET_START);
+            for byte in data {
+                self.uart.send(*byte);
+            }
+            self.uart.send(PACKET_END);
+            self.uart.send(hex_digit(checksum >> 4));
+            self.uart.send(hex_digit(checksum & 0xF));
+
+            if self.wait_byte() == ACK {
+                return;
+            }
+        }
+    }
+
+    /// Check, without blocking, if gdb asked to stop the target
+    pub fn interrupted(&self) -> bool {
+        let mut interrupted = false;
+        while let Some(byte) = self.uart.try_receive() {
+            interrupted |= byte == INTERRUPT;
+        }
+
+        interrupted
+    }
+
+    /// Sleep between polls until a byte is received
+    fn wait_byte(&self) -> u8 {
+        loop {
+            if let Some(byte) = self.uart.try_receive() {
+                return byte;
+            }
+
+            let _ = retry::sleep(IDLE_POLL_PERIOD);
+        }
+    }
+
+    fn spin_byte(&self) -> u8 {
+        loop {
+            if let Some(byte) = self.uart.try_receive() {
+                return byte;
+            }
+
+            hint::spin_loop();
+        }
+    }
+}
+
+/// Append the value as little-endian hex bytes, as used by registers and memory
+pub fn push_hex_le(buffer: &mut Vec<u8>, value: u64, size: usize) {
+    for byte in value.to_le_bytes().iter().take(size) {
+        push_hex_byte(buffer, *byte);
+    }
+}
+
+pub fn push_hex_byte(buffer: &mut Vec<u8>, byte: u8) {
+    buffer.push(hex_digit(byte >> 4));
+    buffer.push(hex_digit(byte & 0xF));
+}
+
+/// Append a big-endian hex number, as used by thread ids and addresses
+pub fn push_hex(buffer: &mut Vec<u8>, value: u64) {
+    let digits = (64 - value.leading_zeros()).div_ceil(4).max(1);
+    for index in (0..digits).rev() {
+        buffer.push(hex_digit(((value >> (index * 4)) & 0xF) as u8));
+    }
+}
+
+/// Parse a big-endian hex number, as used by addresses and lengths
+pub fn parse_hex(data: &[u8]) -> Option<u64> {
+    if data.is_empty() || data.len() > 16 {
+        return None;
+    }
+
+    data.iter().try_fold(0u64, |acc, digit| {
+        Some((acc << 4) | hex_value(*digit)? as u64)
+    })
+}
+
+/// Parse little-endian hex bytes, as used by registers
+pub fn parse_hex_le(data: &[u8]) -> Option<u64> {
+    if data.len() % 2 != 0 || data.len() > 16 {
+        return None;
+    }
+
+    let mut value = 0u64;
+    for (index, byte) in data.chunks(2).enumerate() {
+        value |= (parse_hex(byte)?) << (index * 8);
+    }
+
+    Some(value)
+}
+
+fn hex_digit(value: u8) -> u8 {
+    b"0123456789abcdef"[value as usize]
+}
+
+fn hex_value(digit: u8) -> Option<u8> {
+    match digit {
+        b'0'..=b'9' => Some(digit - b'0'),
+        b'a'..=b'f' => Some(digit - b'a' + 10),
+        b'A'..=b'F' => Some(digit - b'A' + 10),
+        _ => None,
+    }
+}
@@ -0,0 +1,418 @@
+//! Process being debugged
+//!
+//! While gdb has control, the process is suspended (see `Process::suspend`): the contexts of its threads can be read
+//! and updated. Threads blocked in a syscall are only suspended once it completes: their registers are not available.
+//!
+//! Code pages are not writable, and may be shared with other processes: breakpoints are hardware ones
+//! (`WatchpointKind::Execute`), sharing the `WATCHPOINT_COUNT` debug registers with watchpoints.
+//! They are set on all the threads of the process, including the ones created later.
+//!
+//! Execute breakpoints stop the thread before the instruction: to resume it, the thread single-steps over it
+//! with its breakpoints cleared (trap flag), then they are set again.
+
+use core::time::Duration;
+
+use alloc::{collections::BTreeMap, vec::Vec};
+use libruntime::{
+    kobject::{
+        Error, Exception, Process, Thread, ThreadContext, ThreadContextRegister, ThreadEvent,
+        ThreadEventType, ThreadListener, ThreadListenerFilter, ThreadState, ThreadSupervisor,
+        WatchpointKind, WATCHPOINT_COUNT,
+    },
+    retry,
+};
+use log::{debug, warn};
+
+/// DR6 bits B0-B3: watchpoint triggered
+pub const WATCHPOINTS_STATUS_MASK: usize = 0xF;
+
+/// DR6 bit BS: single-step
+const SINGLE_STEP_STATUS: usize = 1 << 14;
+
+/// RFLAGS trap flag: single-step
+const TRAP_FLAG: usize = 1 << 8;
+
+/// Period at which thread events and gdb interrupts are checked while the process runs
+const RUNNING_POLL_PERIOD: Duration = Duration::from_millis(10);
+
+/// Hardware breakpoint or watchpoint
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Breakpoint {
+    pub address: usize,
+    pub size: usize,
+    pub kind: WatchpointKind,
+}
+
+/// Reason why the process stopped
+#[derive(Debug, Clone, Copy)]
+pub enum Stop {
+    /// gdb attached to the process
+    Attached(u64),
+    /// gdb interrupted the process (Ctrl-C)
+    Interrupted(u64),
+    /// A thread got an exception (breakpoints and watchpoints are `Exception::Debug`)
+    Exception(u64, Exception),
+    /// A single-step asked by gdb completed
+    Step(u64),
+    /// All the threads terminated
+    Exited,
+}
+
+/// Attached process
+#[derive(Debug)]
+pub struct Target {
+    process: Process,
+    listener: ThreadListener,
+    threads: BTreeMap<u64, Thread>,
+    breakpoints: [Option<Breakpoint>; WATCHPOINT_COUNT],
+    /// Threads single-stepping, with their execute breakpoints cleared: true if gdb asked for the step,
+    /// false if the thread steps over a breakpoint
+    stepping: BTreeMap<u64, bool>,
+}
+
+impl Target {
+    /// Attach to the process, and stop it
+    pub fn attach(pid: u64) -> Result<(Self, Stop), Error> {
+        let process = Process::open(pid)?;
+
+        // Listen first, not to miss threads created meanwhile
+        let listener = ThreadListener::create(ThreadListenerFilter::Pids(&[pid]))?;
+        process.suspend()?;
+
+        let mut target = Self {
+            process,
+            listener,
+            threads: BTreeMap::new(),
+            breakpoints: [None; WATCHPOINT_COUNT],
+            stepping: BTreeMap::new(),
+        };
+
+        for &tid in target.process.threads()?.iter() {
+            target.add_thread(tid);
+        }
+
+        let stop = Stop::Attached(target.first_thread().ok_or(Error::ObjectNotFound)?);
+        Ok((target, stop))
+    }
+
+    pub fn pid(&self) -> u64 {
+        self.process.pid()
+    }
+
+    /// Get the tids of the threads
+    pub fn threads(&self) -> impl Iterator<Item = u64> + '_ {
+        self.threads.keys().copied()
+    }
+
+    pub fn has_thread(&self, tid: u64) -> bool {
+        self.threads.contains_key(&tid)
+    }
+
+    pub fn first_thread(&self) -> Option<u64> {
+        self.threads.keys().next().copied()
+    }
+
+    /// Get the registers of a thread
+    pub fn context(&self, tid: u64) -> Result<ThreadContext, Error> {
+        self.thread(tid)?.context()
+    }
+
+    /// Update registers of a thread
+    pub fn update_context(
+        &self,
+        tid: u64,
+        registers: &[(ThreadContextRegister, usize)],
+    ) -> Result<(), Error> {
+        ThreadSupervisor::new(self.thread(tid)?).update_context(registers)
+    }
+
+    pub fn read_memory(&self, address: usize, buffer: &mut [u8]) -> Result<(), Error> {
+        self.process.read_memory(address, buffer)
+    }
+
+    pub fn write_memory(&self, address: usize, data: &[u8]) -> Result<(), Error> {
+        self.process.write_memory(address, data)
+    }
+
+    /// Get the breakpoint of a debug register
+    pub fn breakpoint(&self, index: usize) -> Option<Breakpoint> {
+        self.breakpoints.get(index).copied().flatten()
+    }
+
+    /// Set a breakpoint on all the threads
+    ///
+    /// Fails with `Error::ObjectNotReady` if all the debug registers are used.
+    pub fn insert_breakpoint(&mut self, breakpoint: Breakpoint) -> Result<(), Error> {
+        if self.breakpoints.contains(&Some(breakpoint)) {
+            return Ok(());
+        }
+
+        let index = self
+            .breakpoints
+            .iter()
+            .position(Option::is_none)
+            .ok_or(Error::ObjectNotReady)?;
+
+        self.breakpoints[index] = Some(breakpoint);
+
+        let result = self
+            .threads
+            .iter()
+            .try_for_each(|(&tid, thread)| self.apply_breakpoint(tid, thread, index));
+
+        if result.is_err() {
+            self.remove_breakpoint(breakpoint);
+        }
+
+        result
+    }
+
+    /// Clear a breakpoint on all the threads
+    pub fn remove_breakpoint(&mut self, breakpoint: Breakpoint) {
+        let Some(index) = self
+            .breakpoints
+            .iter()
+            .position(|slot| *slot == Some(breakpoint))
+        else {
+            return;
+        };
+
+        self.breakpoints[index] = None;
+
+        for thread in self.threads.values() {
+            // Thread may have terminated meanwhile
+            let _ = ThreadSupervisor::new(thread).clear_watchpoint(index);
+        }
+    }
+
+    /// Resume the process, with one thread single-stepping if `step` is set
+    ///
+    /// Threads in error for another reason than the debugger (eg: page fault) stay stopped.
+    pub fn resume(&mut self, step: Option<u64>) -> Result<(), Error> {
+        // A faulting thread would fault again: it cannot be stepped
+        if let Some(tid) = step {
+            if !matches!(
+                self.stop_exception(tid)?,
+                None | Some(Exception::Debug(_) | Exception::Breakpoint)
+            ) {
+                return Err(Error::InvalidArgument);
+            }
+        }
+
+        let tids: Vec<u64> = self.threads.keys().copied().collect();
+
+        for tid in tids {
+            let requested = step == Some(tid);
+            let thread = self.thread(tid)?;
+
+            if thread.info().state != ThreadState::Error {
+                if requested {
+                    self.start_step(tid, true)?;
+                }
+                continue;
+            }
+
+            let supervisor = ThreadSupervisor::new(thread);
+            let exception = supervisor.error_info()?;
+            if !matches!(exception, Exception::Debug(_) | Exception::Breakpoint) {
+                continue;
+            }
+
+            let address = supervisor.context()?.instruction_pointer;
+            if requested || self.is_execute_breakpoint(address) {
+                self.start_step(tid, requested)?;
+            }
+
+            ThreadSupervisor::new(self.thread(tid)?).resume()?;
+        }
+
+        self.process.resume()
+    }
+
+    /// Let the process run until it stops, or until `interrupted` returns true
+    pub fn wait(&mut self, interrupted: impl Fn() -> bool) -> Result<Stop, Error> {
+        loop {
+            if interrupted() {
+                let tid = self.first_thread().ok_or(Error::ObjectNotFound)?;
+                self.process.suspend()?;
+                return Ok(Stop::Interrupted(tid));
+            }
+
+            match self.listener.receive() {
+                Ok(event) => {
+                    if let Some(stop) = self.process_event(&event)? {
+                        if !matches!(stop, Stop::Exited) {
+                            self.process.suspend()?;
+                        }
+                        return Ok(stop);
+                    }
+                }
+                Err(Error::ObjectNotReady) => {
+                    let _ = retry::sleep(RUNNING_POLL_PERIOD);
+                }
+                Err(err) => return Err(err),
+            }
+        }
+    }
+
+    /// Clear the breakpoints, and let the process run
+    pub fn detach(mut self) {
+        for index in 0..WATCHPOINT_COUNT {
+            if let Some(breakpoint) = self.breakpoints[index] {
+                self.remove_breakpoint(breakpoint);
+            }
+        }
+
+        for (&tid, thread) in self.threads.iter() {
+            let supervisor = ThreadSupervisor::new(thread);
+
+            if self.stepping.contains_key(&tid) {
+                if let Ok(context) = supervisor.context() {
+                    let flags = context.cpu_flags & !TRAP_FLAG;
+                    let _ = supervisor.update_context(&[(ThreadContextRegister::CpuFlags, flags)]);
+                }
+            }
+
+            if matches!(
+                supervisor.error_info(),
+                Ok(Exception::Debug(_) | Exception::Breakpoint)
+            ) {
+                let _ = supervisor.resume();
+            }
+        }
+
+        if let Err(err) = self.process.resume() {
+            warn!("Could not resume process {}: {:?}", self.pid(), err);
+        }
+    }
+
+    fn process_event(&mut self, event: &ThreadEvent) -> Result<Option<Stop>, Error> {
+        match event.r#type {
+            ThreadEventType::Created => {
+                self.add_thread(event.tid);
+                Ok(None)
+            }
+            ThreadEventType::Terminated | ThreadEventType::Deleted => {
+                self.threads.remove(&event.tid);
+                self.stepping.remove(&event.tid);
+
+                Ok(self.threads.is_empty().then_some(Stop::Exited))
+            }
+            ThreadEventType::Error => self.process_error(event.tid),
+            ThreadEventType::Resumed => Ok(None),
+        }
+    }
+
+    fn process_error(&mut self, tid: u64) -> Result<Option<Stop>, Error> {
+        if !self.threads.contains_key(&tid) {
+            self.add_thread(tid);
+        }
+
+        let exception = ThreadSupervisor::new(self.thread(tid)?).error_info()?;
+
+        if let Exception::Debug(status) = exception {
+            if status & SINGLE_STEP_STATUS != 0 {
+                if let Some(requested) = self.stepping.remove(&tid) {
+                    self.finish_step(tid)?;
+
+                    if requested {
+                        return Ok(Some(Stop::Step(tid)));
+                    }
+
+                    // Stepped over a breakpoint: continue
+                    ThreadSupervisor::new(self.thread(tid)?).resume()?;
+                    return Ok(None);
+                }
+            }
+        }
+
+        Ok(Some(Stop::Exception(tid, exception)))
+    }
+
+    fn add_thread(&mut self, tid: u64) {
+        let thread = match Thread::open(tid) {
+            Ok(thread) => thread,
+            Err(err) => {
+                // Thread may have terminated meanwhile
+                debug!("Could not open thread {}: {:?}", tid, err);
+                return;
+            }
+        };
+
+        for index in 0..WATCHPOINT_COUNT {
+            if let Err(err) = self.apply_breakpoint(tid, &thread, index) {
+                warn!("Could not set breakpoint on thread {}: {:?}", tid, err);
+            }
+        }
+
+        self.threads.insert(tid, thread);
+    }
+
+    fn apply_breakpoint(&self, tid: u64, thread: &Thread, index: usize) -> Result<(), Error> {
+        let Some(breakpoint) = self.breakpoints[index] else {
+            return Ok(());
+        };
+
+        // Execute breakpoints are cleared while the thread steps over them
+        if breakpoint.kind == WatchpointKind::Execute && self.stepping.contains_key(&tid) {
+            return Ok(());
+        }
+
+        ThreadSupervisor::new(thread).set_watchpoint(
+            index,
+            breakpoint.address,
+            breakpoint.size,
+            breakpoint.kind,
+        )
+    }
+
+    fn is_execute_breakpoint(&self, address: usize) -> bool {
+        self.breakpoints.iter().flatten().any(|breakpoint| {
+            breakpoint.kind == WatchpointKind::Execute && breakpoint.address == address
+        })
+    }
+
+    fn start_step(&mut self, tid: u64, requested: bool) -> Result<(), Error> {
+        let supervisor = ThreadSupervisor::new(self.thread(tid)?);
+
+        let flags = supervisor.context()?.cpu_flags | TRAP_FLAG;
+        supervisor.update_context(&[(ThreadContextRegister::CpuFlags, flags)])?;
+
+        for (index, breakpoint) in self.breakpoints.iter().enumerate() {
+            if breakpoint.is_some_and(|breakpoint| breakpoint.kind == WatchpointKind::Execute) {
+                supervisor.clear_watchpoint(index)?;
+            }
+        }
+
+        self.stepping.insert(tid, requested);
+        Ok(())
+    }
+
+    fn finish_step(&self, tid: u64) -> Result<(), Error> {
+        let thread = self.thread(tid)?;
+        let supervisor = ThreadSupervisor::new(thread);
+
+        let flags = supervisor.context()?.cpu_flags & !TRAP_FLAG;
+        supervisor.update_context(&[(ThreadContextRegister::CpuFlags, flags)])?;
+
+        for index in 0..WATCHPOINT_COUNT {
+            self.apply_breakpoint(tid, thread, index)?;
+        }
+
+        Ok(())
+    }
+
+    /// Get the exception of a thread, if it is in error
+    fn stop_exception(&self, tid: u64) -> Result<Option<Exception>, Error> {
+        let thread = self.thread(tid)?;
+        if thread.info().state != ThreadState::Error {
+            return Ok(None);
+        }
+
+        ThreadSupervisor::new(thread).error_info().map(Some)
+    }
+
+    fn thread(&self, tid: u64) -> Result<&Thread, Error> {
+        self.threads.get(&tid).ok_or(Error::ObjectNotFound)
+    }
+}
@@ -0,0 +1,208 @@
+#![no_std]
+#![no_main]
+
+// Log server: drains the log rings of the processes to the kernel log
+//
+// See `libruntime::log_server` for the transport and the protocol.
+// The server needs the `DEBUG` sandbox right, to relay the records on behalf of the processes.
+// It logs directly to the kernel itself: its port does not exist yet when it starts.
+
+extern crate alloc;
+extern crate libruntime;
+
+use alloc::collections::BTreeMap;
+use libruntime::{
+    failure,
+    kobject::{
+        Error, KernelLog, Mapping, MemoryObject, Message, Permissions, Port, PortReceiver,
+        PortSender, Process, ProcessEventType, ProcessListener, ProcessListenerFilter, Timer,
+        Waiter,
+    },
+    log_server::{
+        Reply, Request, RequestType, Ring, DRAIN_PERIOD, MAX_MESSAGE_SIZE, RING_SIZE,
+        SERVER_PORT_NAME,
+    },
+    manifest::SandboxFlags,
+};
+use log::{debug, info, warn};
+
+libruntime::entry!(main);
+
+libruntime::manifest_sandbox!(SandboxFlags::DEBUG);
+
+/// Process which registered its ring
+struct Client {
+    process: Process,
+    ring: Ring,
+    /// Keeps the ring mapped
+    _mapping: Mapping<'static>,
+}
+
+struct Server {
+    clients: BTreeMap<u64, Client>,
+    /// Pid of the process which spawned the server: it can request the shutdown drain
+    spawner: u64,
+}
+
+fn main() {
+    let (receiver, _sender) =
+        Port::create(Some(SERVER_PORT_NAME)).expect("Could not create server port");
+    let processes = ProcessListener::create(ProcessListenerFilter::All)
+        .expect("Could not create process listener");
+    let timer = Timer::create().expect("Could not create drain timer");
+    timer
+        .arm(DRAIN_PERIOD, DRAIN_PERIOD / 2)
+        .expect("Could not arm drain timer");
+
+    let mut server = Server {
+        clients: BTreeMap::new(),
+        spawner: Process::current().info().creator_pid,
+    };
+
+    info!("Log server ready on port '{}'", SERVER_PORT_NAME);
+
+    loop {
+        let mut waiter = Waiter::new(&[&receiver, &processes, &timer]);
+        if let Err(err) = waiter.wait() {
+            warn!("Could not wait: {:?}", err);
+            continue;
+        }
+
+        if waiter.is_ready(0) {
+            server.process_message(&receiver);
+        }
+
+        // Terminated processes: drain their last records, and release their ring
+        if waiter.is_ready(1) {
+            while let Ok(event) = processes.receive() {
+                if event.r#type == ProcessEventType::Terminated {
+                    if let Some(client) = server.clients.remove(&event.pid) {
+                        drain(&client);
+                    }
+                }
+            }
+        }
+
+        if waiter.is_ready(2) {
+            while timer.receive().is_ok() {}
+            server.drain_all();
+
+            if let Err(err) = timer.arm(DRAIN_PERIOD, DRAIN_PERIOD / 2) {
+                warn!("Could not arm drain timer: {:?}", err);
+            }
+        }
+    }
+}
+
+impl Server {
+    fn process_message(&mut self, receiver: &PortReceiver) {
+        let mut message = match receiver.receive() {
+            Ok(message) => message,
+            Err(Error::ObjectNotReady) => return,
+            Err(err) => {
+                warn!("Could not receive request: {:?}", err);
+                return;
+            }
+        };
+
+        let reply_port = match PortSender::from_handle(message.take_handle(0)) {
+            Ok(port) => port,
+            Err(_) => {
+                warn!("Dropping request without reply port");
+                return;
+            }
+        };
+
+        // If processing panics, the client gets a failure report instead of the reply
+        let _request = failure::begin_request(&reply_port, message.correlation());
+
+        let request = *unsafe { message.data::<Request>() };
+        let result = self.process_request(&request, &mut message);
+
+        let reply = Reply::new(result);
+        let mut reply_message = unsafe { Message::new(&reply, &mut []) };
+        if let Err(err) = reply_port.send(&mut reply_message) {
+            warn!("Could not send reply: {:?}", err);
+        }
+    }
+
+    /// Process a request, returns the number of records drained
+    fn process_request(
+        &mut self,
+        request: &Request,
+        message: &mut Message,
+    ) -> Result<usize, Error> {
+        let sender_pid = message.sender_pid();
+
+        match RequestType::try_from(request.r#type)? {
+            RequestType::Register => {
+                let object = MemoryObject::from_handle(message.take_handle(1))
+                    .map_err(|_| Error::InvalidArgument)?;
+                self.register(sender_pid, &object)?;
+                Ok(0)
+            }
+            RequestType::Flush => Ok(self.clients.get(&sender_pid).map_or(0, drain)),
+            RequestType::Shutdown => {
+                if sender_pid != self.spawner {
+                    return Err(Error::NotSupported);
+                }
+
+                let drained = self.drain_all();
+                info!(
+                    "Shutdown: {} records drained from {} processes",
+                    drained,
+                    self.clients.len()
+                );
+                Ok(drained)
+            }
+        }
+    }
+
+    fn register(&mut self, pid: u64, object: &MemoryObject) -> Result<(), Error> {
+        let process = Process::open(pid)?;
+
+        // Fails if the object is smaller than a ring
+        let mapping = Process::current().map_mem(
+            None,
+            RING_SIZE,
+            Permissions::READ | Permissions::WRITE,
+            object,
+            0,
+        )?;
+        let ring = unsafe { Ring::from_address(mapping.address()) };
+
+        debug!("Ring of process {} registered", pid);
+
+        // A process registers once: a new ring replaces the previous one
+        if let Some(previous) = self.clients.insert(
+            pid,
+            Client {
+                process,
+                ring,
+                _mapping: mapping,
+            },
+        ) {
+            drain(&previous);
+        }
+
+        Ok(())
+    }
+
+    fn drain_all(&self) -> usize {
+        self.clients.values().map(drain).sum()
+    }
+}
+
+/// Relay the pending records of a client, returns their number
+fn drain(client: &Client) -> usize {
+    let mut buffer = [0u8; MAX_MESSAGE_SIZE];
+    let mut count = 0;
+
+    while let Some(record) = client.ring.pop(&mut buffer) {
+        // Dropped by the rate limit of the process, or the process is gone: nothing more to do
+        let _ = KernelLog::relay(&client.process, record.tid, record.level, record.message);
+        count += 1;
+    }
+
+    count
+}
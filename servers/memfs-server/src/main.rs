@@ -0,0 +1,530 @@
+#![no_std]
+#![no_main]
+
+extern crate alloc;
+extern crate libruntime;
+
+use alloc::{collections::BTreeMap, string::String, vec::Vec};
+use core::time::Duration;
+
+use libruntime::{
+    failure,
+    fs::{self, NodeInfo, NodeKind, Reply, Request, RequestType, TimeSpec, MEMFS_PORT_NAME},
+    kobject::{Clock, Error, Handle, MemoryObject, Message, Port, PortSender, Thread},
+};
+use log::{debug, info, warn};
+
+libruntime::entry!(main);
+
+/// Maximum size of a file, so that a write at a large offset cannot exhaust the memory
+const MAX_FILE_SIZE: u64 = 64 * 1024 * 1024;
+
+/// Access times more recent than this are not updated, unless the node changed since (relatime)
+const RELATIME_INTERVAL: Duration = Duration::from_secs(24 * 60 * 60);
+
+struct Node {
+    /// Root directory of the filesystem instance of the node
+    instance: u64,
+    /// Directory holding the node (itself for a root directory)
+    parent: u64,
+    kind: NodeKind,
+    data: Vec<u8>,
+    children: BTreeMap<String, u64>,
+    atime: u64,
+    mtime: u64,
+    ctime: u64,
+}
+
+impl Node {
+    fn new(instance: u64, parent: u64, kind: NodeKind, now: u64) -> Self {
+        Self {
+            instance,
+            parent,
+            kind,
+            data: Vec::new(),
+            children: BTreeMap::new(),
+            atime: now,
+            mtime: now,
+            ctime: now,
+        }
+    }
+
+    fn info(&self, id: u64) -> NodeInfo {
+        NodeInfo {
+            id,
+            kind: self.kind as u64,
+            size: match self.kind {
+                NodeKind::File => self.data.len() as u64,
+                NodeKind::Directory => self.children.len() as u64,
+            },
+            atime: self.atime,
+            mtime: self.mtime,
+            ctime: self.ctime,
+        }
+    }
+
+    /// The data (or the entries) changed
+    fn modified(&mut self, now: u64) {
+        self.mtime = now;
+        self.ctime = now;
+    }
+
+    /// The data (or the entries) has been read
+    ///
+    /// Relatime: the access time is only updated if the node changed since the last access, or once a day,
+    /// so that reads do not turn into metadata writes on disk filesystems.
+    fn accessed(&mut self, now: u64) {
+        let stale = self.atime <= self.mtime
+            || self.atime <= self.ctime
+            || now.saturating_sub(self.atime) >= RELATIME_INTERVAL.as_nanos() as u64;
+
+        if stale {
+            self.atime = now;
+        }
+    }
+}
+
+/// Current wall clock, in nanoseconds since the Unix epoch
+fn now() -> u64 {
+    Clock::wall().map_or(0, |time| time.as_nanos() as u64)
+}
+
+/// Filesystem instances, each one attached by a vfs-server
+struct MemFs {
+    nodes: BTreeMap<u64, Node>,
+    next_id: u64,
+    /// Process which attached each instance, by root directory: the only one which can use it
+    instances: BTreeMap<u64, u64>,
+}
+
+fn main() {
+    let (receiver, _sender) =
+        Port::create(Some(MEMFS_PORT_NAME)).expect("Could not create server port");
+
+    info!("Memfs ready on port '{}'", MEMFS_PORT_NAME);
+
+    let mut memfs = MemFs {
+        nodes: BTreeMap::new(),
+        next_id: 1,
+        instances: BTreeMap::new(),
+    };
+
+    loop {
+        let mut message = match receiver.blocking_receive() {
+            Ok(message) => message,
+            Err(err) => {
+                warn!("Could not receive request: {:?}", err);
+                continue;
+            }
+        };
+
+        let reply_port = match PortSender::from_handle(message.take_handle(0)) {
+            Ok(port) => port,
+            Err(_) => {
+                warn!("Dropping request without reply port");
+                continue;
+            }
+        };
+
+        // If processing panics, the client gets a failure report instead of the reply
+        let _request = failure::begin_request(&reply_port, message.correlation());
+
+        let request = *unsafe { message.data::<Request>() };
+        // Shed requests whose caller gave up waiting
+        let (result, object) = match Thread::check_deadline() {
+            Ok(()) => memfs.process_request(&request, &mut message),
+            Err(err) => (Err(err), None),
+        };
+
+        let reply = Reply::new(result);
+        let mut handles = [object.map_or(Handle::invalid(), MemoryObject::into_handle)];
+        let mut reply_message = unsafe { Message::new(&reply, &mut handles) };
+        if let Err(err) = reply_port.send(&mut reply_message) {
+            warn!("Could not send reply: {:?}", err);
+        }
+    }
+}
+
+impl MemFs {
+    /// Process a request, returns the result and the memory object to send with the reply
+    fn process_request(
+        &mut self,
+        request: &Request,
+        message: &mut Message,
+    ) -> (Result<(usize, NodeInfo), Error>, Option<MemoryObject>) {
+        let r#type = match RequestType::try_from(request.r#type) {
+            Ok(r#type) => r#type,
+            Err(err) => return (Err(err), None),
+        };
+
+        let sender = message.sender_pid();
+
+        if r#type == RequestType::Attach {
+            return (Ok((0, self.attach(sender))), None);
+        }
+
+        // Only the process which attached the instance can use it
+        if let Err(err) = self.check_instance(request.node, sender) {
+            return (Err(err), None);
+        }
+
+        // No permissions in memfs: the process the request is made for is only traced
+        if request.opener != 0 {
+            debug!(
+                "{:?} on node {} for process {}",
+                r#type, request.node, request.opener
+            );
+        }
+
+        match r#type {
+            RequestType::Attach => unreachable!(),
+            RequestType::Detach => (
+                self.detach(request.node).map(|_| (0, NodeInfo::EMPTY)),
+                None,
+            ),
+            RequestType::Lookup => (self.lookup(request, message).map(|info| (0, info)), None),
+            RequestType::Create => (self.create(request, message).map(|info| (0, info)), None),
+            RequestType::Remove => (
+                self.remove(request, message).map(|_| (0, NodeInfo::EMPTY)),
+                None,
+            ),
+            RequestType::Rename => (
+                self.rename(request, message).map(|_| (0, NodeInfo::EMPTY)),
+                None,
+            ),
+            RequestType::Read => match self.read(request) {
+                Ok((info, data)) if data.is_empty() => (Ok((0, info)), None),
+                Ok((info, data)) => match fs::copy_to_object(&data) {
+                    Ok(object) => (Ok((data.len(), info)), Some(object)),
+                    Err(err) => (Err(err), None),
+                },
+                Err(err) => (Err(err), None),
+            },
+            RequestType::Write => (self.write(request, message), None),
+            RequestType::CopyRange => (self.copy_range(request), None),
+            RequestType::Truncate => (self.truncate(request).map(|info| (0, info)), None),
+            RequestType::SetTimes => (self.set_times(request).map(|info| (0, info)), None),
+            RequestType::GetAttr => (
+                self.node(request.node)
+                    .map(|node| (0, node.info(request.node))),
+                None,
+            ),
+            RequestType::List => match self.list(request.node) {
+                Ok(block) => match fs::copy_to_object(&block) {
+                    Ok(object) => (Ok((block.len(), NodeInfo::EMPTY)), Some(object)),
+                    Err(err) => (Err(err), None),
+                },
+                Err(err) => (Err(err), None),
+            },
+        }
+    }
+
+    fn attach(&mut self, sender: u64) -> NodeInfo {
+        let root = self.allocate_id();
+        self.nodes
+            .insert(root, Node::new(root, root, NodeKind::Directory, now()));
+        self.instances.insert(root, sender);
+
+        debug!("Instance {} attached by process {}", root, sender);
+        self.nodes[&root].info(root)
+    }
+
+    fn detach(&mut self, root: u64) -> Result<(), Error> {
+        if self.instances.remove(&root).is_none() {
+            // Not a root directory
+            return Err(Error::InvalidArgument);
+        }
+
+        self.nodes.retain(|_, node| node.instance != root);
+
+        debug!("Instance {} detached", root);
+        Ok(())
+    }
+
+    fn check_instance(&self, id: u64, sender: u64) -> Result<(), Error> {
+        let node = self.node(id)?;
+        match self.instances.get(&node.instance) {
+            Some(&owner) if owner == sender => Ok(()),
+            _ => Err(Error::ObjectNotFound),
+        }
+    }
+
+    fn allocate_id(&mut self) -> u64 {
+        let id = self.next_id;
+        self.next_id += 1;
+        id
+    }
+
+    fn node(&self, id: u64) -> Result<&Node, Error> {
+        self.nodes.get(&id).ok_or(Error::ObjectNotFound)
+    }
+
+    fn node_mut(&mut self, id: u64) -> Result<&mut Node, Error> {
+        self.nodes.get_mut(&id).ok_or(Error::ObjectNotFound)
+    }
+
+    fn directory(&self, id: u64) -> Result<&Node, Error> {
+        let node = self.node(id)?;
+        if node.kind != NodeKind::Directory {
+            return Err(Error::InvalidArgument);
+        }
+
+        Ok(node)
+    }
+
+    fn file_mut(&mut self, id: u64) -> Result<&mut Node, Error> {
+        let node = self.node_mut(id)?;
+        if node.kind != NodeKind::File {
+            return Err(Error::InvalidArgument);
+        }
+
+        Ok(node)
+    }
+
+    fn lookup(&self, request: &Request, message: &mut Message) -> Result<NodeInfo, Error> {
+        let (name, _) = fs::request_names(request, message)?;
+        let directory = self.directory(request.node)?;
+
+        let &id = directory.children.get(&name).ok_or(Error::ObjectNotFound)?;
+        Ok(self.nodes[&id].info(id))
+    }
+
+    fn create(&mut self, request: &Request, message: &mut Message) -> Result<NodeInfo, Error> {
+        let (name, _) = fs::request_names(request, message)?;
+        let kind = NodeKind::try_from(request.size)?;
+        let directory = self.directory(request.node)?;
+
+        if directory.children.contains_key(&name) {
+            return Err(Error::ObjectNameDuplicate);
+        }
+
+        let instance = directory.instance;
+        let id = self.allocate_id();
+        let now = now();
+        self.nodes
+            .insert(id, Node::new(instance, request.node, kind, now));
+
+        let directory = self.node_mut(request.node)?;
+        directory.children.insert(name, id);
+        directory.modified(now);
+
+        Ok(self.nodes[&id].info(id))
+    }
+
+    fn remove(&mut self, request: &Request, message: &mut Message) -> Result<(), Error> {
+        let (name, _) = fs::request_names(request, message)?;
+        let directory = self.directory(request.node)?;
+
+        let &id = directory.children.get(&name).ok_or(Error::ObjectNotFound)?;
+        if !self.nodes[&id].children.is_empty() {
+            // Directory not empty
+            return Err(Error::InvalidArgument);
+        }
+
+        let directory = self.node_mut(request.node)?;
+        directory.children.remove(&name);
+        directory.modified(now());
+
+        self.nodes.remove(&id);
+        Ok(())
+    }
+
+    fn rename(&mut self, request: &Request, message: &mut Message) -> Result<(), Error> {
+        let (name, new_name) = fs::request_names(request, message)?;
+        let new_name = new_name.ok_or(Error::InvalidArgument)?;
+        let replace = request.size != 0;
+
+        let directory = self.directory(request.node)?;
+        let &id = directory.children.get(&name).ok_or(Error::ObjectNotFound)?;
+        let new_directory = self.directory(request.other_node)?;
+
+        if new_directory.instance != directory.instance {
+            return Err(Error::InvalidArgument);
+        }
+
+        // A directory cannot move into itself
+        let mut ancestor = request.other_node;
+        loop {
+            if ancestor == id {
+                return Err(Error::InvalidArgument);
+            }
+
+            let parent = self.nodes[&ancestor].parent;
+            if parent == ancestor {
+                break;
+            }
+            ancestor = parent;
+        }
+
+        if let Some(&existing) = new_directory.children.get(&new_name) {
+            if existing == id {
+                return Ok(());
+            }
+
+            let both_files = self.nodes[&existing].kind == NodeKind::File
+                && self.nodes[&id].kind == NodeKind::File;
+            if !replace || !both_files {
+                return Err(Error::ObjectNameDuplicate);
+            }
+
+            self.nodes.remove(&existing);
+        }
+
+        let now = now();
+
+        let directory = self.node_mut(request.node)?;
+        directory.children.remove(&name);
+        directory.modified(now);
+
+        let new_directory = self.node_mut(request.other_node)?;
+        new_directory.children.insert(new_name, id);
+        new_directory.modified(now);
+
+        let node = self.node_mut(id)?;
+        node.parent = request.other_node;
+        node.ctime = now;
+
+        Ok(())
+    }
+
+    fn read(&mut self, request: &Request) -> Result<(NodeInfo, Vec<u8>), Error> {
+        let node = self.file_mut(request.node)?;
+
+        let start = (request.offset as usize).min(node.data.len());
+        let end = start
+            .saturating_add(request.size.min(fs::MAX_IO_SIZE as u64) as usize)
+            .min(node.data.len());
+        let data = Vec::from(&node.data[start..end]);
+        node.accessed(now());
+
+        Ok((node.info(request.node), data))
+    }
+
+    fn write(
+        &mut self,
+        request: &Request,
+        message: &mut Message,
+    ) -> Result<(usize, NodeInfo), Error> {
+        let size = request.buffer_size.min(fs::MAX_IO_SIZE as u64);
+        let end = request
+            .offset
+            .checked_add(size)
+            .ok_or(Error::InvalidArgument)?;
+        if end > MAX_FILE_SIZE {
+            return Err(Error::InvalidArgument);
+        }
+
+        let object = MemoryObject::from_handle(message.take_handle(1))
+            .map_err(|_| Error::InvalidArgument)?;
+        let data = fs::copy_from_object(&object, size as usize)?;
+
+        let node = self.file_mut(request.node)?;
+        let (start, end) = (request.offset as usize, end as usize);
+        if node.data.len() < end {
+            node.data.resize(end, 0);
+        }
+        node.data[start..end].copy_from_slice(&data);
+        node.modified(now());
+
+        Ok((data.len(), node.info(request.node)))
+    }
+
+    /// Copy within the memory of the server: the data never goes through vfs-server
+    fn copy_range(&mut self, request: &Request) -> Result<(usize, NodeInfo), Error> {
+        if self.node(request.other_node)?.instance != self.node(request.node)?.instance {
+            return Err(Error::InvalidArgument);
+        }
+
+        let source = self.file_mut(request.node)?;
+        let start = (request.offset as usize).min(source.data.len());
+        let end = start
+            .saturating_add(request.size.min(fs::MAX_IO_SIZE as u64) as usize)
+            .min(source.data.len());
+        let size = (end - start) as u64;
+        if size == 0 {
+            // Nothing copied: the destination is not extended
+            let dest = self.file_mut(request.other_node)?;
+            return Ok((0, dest.info(request.other_node)));
+        }
+
+        let dest_end = request
+            .other_offset
+            .checked_add(size)
+            .ok_or(Error::InvalidArgument)?;
+        if dest_end > MAX_FILE_SIZE {
+            return Err(Error::InvalidArgument);
+        }
+
+        let (dest_start, dest_end) = (request.other_offset as usize, dest_end as usize);
+        let now = now();
+
+        if request.node == request.other_node {
+            let node = self.file_mut(request.node)?;
+            if node.data.len() < dest_end {
+                node.data.resize(dest_end, 0);
+            }
+            node.data.copy_within(start..end, dest_start);
+            node.accessed(now);
+            node.modified(now);
+        } else {
+            // Note: through a temporary buffer, both nodes cannot be borrowed at once
+            let source = self.file_mut(request.node)?;
+            let data = Vec::from(&source.data[start..end]);
+            source.accessed(now);
+
+            let dest = self.file_mut(request.other_node)?;
+            if dest.data.len() < dest_end {
+                dest.data.resize(dest_end, 0);
+            }
+            dest.data[dest_start..dest_end].copy_from_slice(&data);
+            dest.modified(now);
+        }
+
+        let dest = self.node(request.other_node)?;
+        Ok((size as usize, dest.info(request.other_node)))
+    }
+
+    fn truncate(&mut self, request: &Request) -> Result<NodeInfo, Error> {
+        if request.size > MAX_FILE_SIZE {
+            return Err(Error::InvalidArgument);
+        }
+
+        let node = self.file_mut(request.node)?;
+        node.data.resize(request.size as usize, 0);
+        node.modified(now());
+
+        Ok(node.info(request.node))
+    }
+
+    /// Explicit times, like `utimens`: the change time is always set to now
+    fn set_times(&mut self, request: &Request) -> Result<NodeInfo, Error> {
+        let now = now();
+        let node = self.node_mut(request.node)?;
+
+        let time = |spec, current| match spec {
+            TimeSpec::Now => now,
+            TimeSpec::Omit => current,
+            TimeSpec::At(time) => time.as_nanos() as u64,
+        };
+
+        node.atime = time(TimeSpec::from_raw(request.offset), node.atime);
+        node.mtime = time(TimeSpec::from_raw(request.size), node.mtime);
+        node.ctime = now;
+
+        Ok(node.info(request.node))
+    }
+
+    fn list(&mut self, id: u64) -> Result<Vec<u8>, Error> {
+        let directory = self.directory(id)?;
+
+        let block = fs::encode_entries(
+            directory
+                .children
+                .iter()
+                .map(|(name, child)| (name.as_str(), self.nodes[child].kind)),
+        );
+
+        self.node_mut(id)?.accessed(now());
+        Ok(block)
+    }
+}
@@ -1,18 +1,467 @@
 #![no_std]
 #![no_main]
-#![feature(naked_functions)]
-#![feature(used_with_arg)]
 
 extern crate alloc;
 extern crate libruntime;
 
-use log::info;
+use core::{slice, time::Duration};
 
-#[no_mangle]
-pub extern "C" fn _start() -> ! {
-    libruntime::init();
+use alloc::{
+    collections::{BTreeMap, VecDeque},
+    string::{String, ToString},
+    vec::Vec,
+};
+use libruntime::{
+    failure,
+    kobject::{
+        Clock, Error, Handle, Mapping, MemoryObject, Message, Permissions, Port, PortReceiver,
+        PortSender, Process, ProcessEventType, ProcessListener, ProcessListenerFilter, Thread,
+        ThreadEventType, ThreadListener, ThreadListenerFilter, Waiter, PAGE_SIZE,
+    },
+    kvblock::{KVBlockBuilder, Value},
+    loader::{Image, Library},
+    manifest::SandboxFlags,
+    process_server::{
+        ExitedProcess, Reply, Request, RequestType, Retention, LIBRARY_DIR, RECORD_KEY,
+        SERVER_PORT_NAME,
+    },
+};
+use log::{debug, info, warn};
 
-    info!("Hello, world!");
+libruntime::entry!(main);
 
-    libruntime::exit()
+libruntime::manifest_sandbox!(SandboxFlags::PROCESS_CREATE);
+
+/// Process being watched until it terminates
+///
+/// The server holds a handle on it, so that its info can still be read once it terminates.
+struct LiveProcess {
+    process: Process,
+    /// 0 if unknown
+    created: u64,
+    ticks: u64,
+    thread_count: u64,
+    status: i64,
+}
+
+/// Binary registered by init, which processes can be spawned from
+struct Binary {
+    object: MemoryObject,
+    size: usize,
+}
+
+impl Binary {
+    /// Map the binary read-only in the server
+    fn map(&self) -> Result<Mapping<'static>, Error> {
+        Process::current().map_mem(
+            None,
+            self.size.next_multiple_of(PAGE_SIZE),
+            Permissions::READ,
+            &self.object,
+            0,
+        )
+    }
+}
+
+/// Reaper of the processes, and table of the exited ones
+struct Reaper {
+    live: BTreeMap<u64, LiveProcess>,
+    /// Threads being watched until they terminate, to sum their ticks into their process
+    threads: BTreeMap<u64, Thread>,
+    /// Oldest first
+    exited: VecDeque<ExitedProcess>,
+    retention: Retention,
+    /// Pid of the process which spawned the server (init): the only one which can change the retention policy
+    /// and register binaries
+    spawner: u64,
+    /// Registered binaries, by path
+    binaries: BTreeMap<String, Binary>,
+}
+
+fn main() {
+    let (receiver, _sender) =
+        Port::create(Some(SERVER_PORT_NAME)).expect("Could not create server port");
+
+    // Listen first, so that no process is missed between the listing and the events
+    let processes = ProcessListener::create(ProcessListenerFilter::All)
+        .expect("Could not create process listener");
+    let threads = ThreadListener::create(ThreadListenerFilter::All)
+        .expect("Could not create thread listener");
+
+    let spawner = Process::current().info().creator_pid;
+    let mut reaper = Reaper::new(Retention::default(), spawner);
+    reaper.adopt_existing();
+
+    info!("Process server ready on port '{}'", SERVER_PORT_NAME);
+
+    loop {
+        let mut waiter = Waiter::new(&[&receiver, &processes, &threads]);
+        if let Err(err) = waiter.wait() {
+            warn!("Could not wait: {:?}", err);
+            continue;
+        }
+
+        // Thread events first: the ticks of the last threads go into the record of their process
+        if waiter.is_ready(2) {
+            while let Ok(event) = threads.receive() {
+                match event.r#type {
+                    ThreadEventType::Created => reaper.thread_created(event.tid),
+                    ThreadEventType::Terminated => reaper.thread_terminated(event.tid),
+                    _ => {}
+                }
+            }
+        }
+
+        if waiter.is_ready(1) {
+            while let Ok(event) = processes.receive() {
+                match event.r#type {
+                    ProcessEventType::Created => reaper.process_created(event.pid),
+                    ProcessEventType::Terminated => reaper.process_terminated(event.pid),
+                    _ => {}
+                }
+            }
+        }
+
+        if waiter.is_ready(0) {
+            process_message(&receiver, &mut reaper);
+        }
+    }
+}
+
+fn process_message(receiver: &PortReceiver, reaper: &mut Reaper) {
+    let mut message = match receiver.receive() {
+        Ok(message) => message,
+        Err(Error::ObjectNotReady) => return,
+        Err(err) => {
+            warn!("Could not receive request: {:?}", err);
+            return;
+        }
+    };
+
+    let reply_port = match PortSender::from_handle(message.take_handle(0)) {
+        Ok(port) => port,
+        Err(_) => {
+            warn!("Dropping request without reply port");
+            return;
+        }
+    };
+
+    // If processing panics, the client gets a failure report instead of the reply
+    let _request = failure::begin_request(&reply_port, message.correlation());
+
+    let request = *unsafe { message.data::<Request>() };
+    let binary = message.take_handle(1);
+    // Shed requests whose caller gave up waiting
+    let (result, object) = match Thread::check_deadline() {
+        Ok(()) => reaper.process_request(&request, binary, message.sender_pid()),
+        Err(err) => (Err(err), None),
+    };
+
+    let reply = Reply::new(result);
+    let mut handles = [object.map_or(Handle::invalid(), MemoryObject::into_handle)];
+    let mut reply_message = unsafe { Message::new(&reply, &mut handles) };
+    if let Err(err) = reply_port.send(&mut reply_message) {
+        warn!("Could not send reply: {:?}", err);
+    }
+}
+
+impl Reaper {
+    fn new(retention: Retention, spawner: u64) -> Self {
+        Self {
+            live: BTreeMap::new(),
+            threads: BTreeMap::new(),
+            exited: VecDeque::new(),
+            retention,
+            spawner,
+            binaries: BTreeMap::new(),
+        }
+    }
+
+    /// Watch the processes and threads which exist before the server started
+    fn adopt_existing(&mut self) {
+        match Process::list() {
+            Ok(pids) => {
+                for &pid in pids.iter() {
+                    self.watch_process(pid, 0);
+                }
+            }
+            Err(err) => warn!("Could not list processes: {:?}", err),
+        }
+
+        match Thread::list() {
+            Ok(tids) => {
+                for &tid in tids.iter() {
+                    self.thread_created(tid);
+                }
+            }
+            Err(err) => warn!("Could not list threads: {:?}", err),
+        }
+    }
+
+    fn process_created(&mut self, pid: u64) {
+        self.watch_process(pid, now());
+    }
+
+    fn watch_process(&mut self, pid: u64, created: u64) {
+        if self.live.contains_key(&pid) {
+            return;
+        }
+
+        // It may already be gone
+        let Ok(process) = Process::open(pid) else {
+            return;
+        };
+
+        self.live.insert(
+            pid,
+            LiveProcess {
+                process,
+                created,
+                ticks: 0,
+                thread_count: 0,
+                status: 0,
+            },
+        );
+    }
+
+    fn thread_created(&mut self, tid: u64) {
+        if self.threads.contains_key(&tid) {
+            return;
+        }
+
+        let Ok(thread) = Thread::open(tid) else {
+            return;
+        };
+
+        // Thread events are processed first: the process created event may not be processed yet
+        let pid = thread.pid();
+        self.watch_process(pid, now());
+        if let Some(live) = self.live.get_mut(&pid) {
+            live.thread_count += 1;
+        }
+
+        self.threads.insert(tid, thread);
+    }
+
+    fn thread_terminated(&mut self, tid: u64) {
+        let Some(thread) = self.threads.remove(&tid) else {
+            return;
+        };
+
+        let info = thread.info();
+        let ticks = info.ticks as u64;
+
+        if let Some(live) = self.live.get_mut(&info.pid) {
+            live.ticks += ticks;
+        } else if let Some(record) = self.exited.iter_mut().rev().find(|r| r.pid == info.pid) {
+            // The process terminated event came first
+            record.ticks += ticks;
+        }
+    }
+
+    /// Reap a process: move it to the exited table, and release its handle
+    fn process_terminated(&mut self, pid: u64) {
+        let Some(live) = self.live.remove(&pid) else {
+            return;
+        };
+
+        let record = ExitedProcess {
+            pid,
+            name: live.process.name().unwrap_or_default(),
+            created: live.created,
+            terminated: now(),
+            ticks: live.ticks,
+            thread_count: live.thread_count,
+            status: live.status,
+        };
+
+        debug!(
+            "Process {} ({}) reaped: {} threads, {} ticks",
+            pid, record.name, record.thread_count, record.ticks
+        );
+
+        self.exited.push_back(record);
+        self.expire();
+    }
+
+    /// Drop the records which do not fit the retention policy
+    fn expire(&mut self) {
+        while self.exited.len() as u64 > self.retention.max_entries {
+            self.exited.pop_front();
+        }
+
+        if let Some(max_age) = self.retention.max_age() {
+            let now = Duration::from_nanos(now());
+            while let Some(record) = self.exited.front() {
+                if now.saturating_sub(Duration::from_nanos(record.terminated)) <= max_age {
+                    break;
+                }
+
+                self.exited.pop_front();
+            }
+        }
+    }
+
+    /// Process a request, returns the result and the memory object to send with the reply
+    fn process_request(
+        &mut self,
+        request: &Request,
+        binary: Handle,
+        sender_pid: u64,
+    ) -> (Result<usize, Error>, Option<MemoryObject>) {
+        let r#type = match RequestType::try_from(request.r#type) {
+            Ok(r#type) => r#type,
+            Err(err) => return (Err(err), None),
+        };
+
+        match r#type {
+            RequestType::ListExited => {
+                self.expire();
+                match self.list_exited() {
+                    Ok(Some((size, object))) => (Ok(size), Some(object)),
+                    Ok(None) => (Ok(0), None),
+                    Err(err) => (Err(err), None),
+                }
+            }
+            RequestType::SetRetention => {
+                if sender_pid != self.spawner {
+                    warn!(
+                        "Process {} is not allowed to set the retention policy",
+                        sender_pid
+                    );
+                    return (Err(Error::NotSupported), None);
+                }
+
+                debug!("Retention set to {:?}", request.retention);
+                self.retention = request.retention;
+                self.expire();
+                (Ok(0), None)
+            }
+            RequestType::RegisterBinary => {
+                if sender_pid != self.spawner {
+                    warn!("Process {} is not allowed to register binaries", sender_pid);
+                    return (Err(Error::NotSupported), None);
+                }
+
+                (self.register_binary(request, binary).map(|_| 0), None)
+            }
+            RequestType::Spawn => (self.spawn(request).map(|pid| pid as usize), None),
+            RequestType::SetExitStatus => {
+                if let Some(live) = self.live.get_mut(&sender_pid) {
+                    live.status = request.value as i64;
+                }
+
+                (Ok(0), None)
+            }
+        }
+    }
+
+    fn register_binary(&mut self, request: &Request, handle: Handle) -> Result<(), Error> {
+        let path = request.path()?;
+        let object = MemoryObject::from_handle(handle).map_err(|_| Error::InvalidArgument)?;
+        let binary = Binary {
+            object,
+            size: request.value as usize,
+        };
+
+        // Mapping fails if the object is smaller than the binary
+        binary.map()?;
+
+        debug!("Binary '{}' registered ({} bytes)", path, binary.size);
+        self.binaries.insert(path.to_string(), binary);
+        Ok(())
+    }
+
+    /// Load a registered binary in a new process, and start it
+    fn spawn(&self, request: &Request) -> Result<u64, Error> {
+        let path = request.path()?;
+        let binary = self.binaries.get(path).ok_or(Error::ObjectNotFound)?;
+        let name = path.rsplit('/').next().unwrap_or(path);
+
+        let binary_mapping = binary.map()?;
+        let mut library_mappings = Vec::new();
+        for (library_path, library) in self.binaries.range(LIBRARY_DIR.to_string()..) {
+            let Some(library_name) = library_path.strip_prefix(LIBRARY_DIR) else {
+                break;
+            };
+
+            library_mappings.push((library_name, library.size, library.map()?));
+        }
+
+        let libraries: Vec<Library> = library_mappings
+            .iter()
+            .map(|(name, size, mapping)| {
+                Library::new(name, unsafe { mapping_data(mapping, *size) })
+            })
+            .collect();
+
+        let image = Image::load(
+            name,
+            unsafe { mapping_data(&binary_mapping, binary.size) },
+            &libraries,
+        )
+        .map_err(|err| {
+            warn!("Could not load '{}': {}", path, err);
+            match err {
+                libruntime::loader::LoaderError::Kernel(err) => err,
+                _ => Error::InvalidArgument,
+            }
+        })?;
+
+        let process = image.start().map_err(|err| {
+            warn!("Could not start '{}': {}", path, err);
+            Error::InvalidArgument
+        })?;
+
+        let pid = process.pid();
+        debug!("Process {} spawned from '{}'", pid, path);
+        Ok(pid)
+    }
+
+    /// Encode the exited table into a memory object, returns it with the size of the block (`None` if it is empty)
+    fn list_exited(&self) -> Result<Option<(usize, MemoryObject)>, Error> {
+        if self.exited.is_empty() {
+            return Ok(None);
+        }
+
+        let mut builder = KVBlockBuilder::new();
+        for record in self.exited.iter() {
+            builder.push(RECORD_KEY, Value::Bytes(&record.to_block()));
+        }
+        let block = builder.build();
+
+        let size = block.len().next_multiple_of(PAGE_SIZE);
+        let object = MemoryObject::create(size)?;
+
+        {
+            let mapping = Process::current().map_mem(
+                None,
+                size,
+                Permissions::READ | Permissions::WRITE,
+                &object,
+                0,
+            )?;
+
+            let dest =
+                unsafe { slice::from_raw_parts_mut(mapping.address() as *mut u8, block.len()) };
+            dest.copy_from_slice(&block);
+        }
+
+        // Clients must not be able to alter the records they read
+        Ok(Some((block.len(), object.read_only()?)))
+    }
+}
+
+/// Get the content of a binary mapping
+///
+/// # Safety
+///
+/// `size` must be within the mapping
+unsafe fn mapping_data<'a>(mapping: &'a Mapping, size: usize) -> &'a [u8] {
+    slice::from_raw_parts(mapping.address() as *const u8, size)
+}
+
+/// Get the current time, in nanoseconds since boot
+fn now() -> u64 {
+    Clock::uptime().unwrap_or(Duration::ZERO).as_nanos() as u64
 }
@@ -0,0 +1,276 @@
+#![no_std]
+#![no_main]
+
+// Terminal program: draws a `libterm` terminal on a display-server surface
+//
+// Key events are taken as characters (`code` is the Unicode scalar value, as posted by the input sources).
+// Until console-server streams exist to host the shell, typed lines are echoed locally.
+// Pointer: button 1 selects text (copied to the clipboard on release), buttons 4 and 5 scroll the view.
+
+extern crate alloc;
+extern crate libruntime;
+
+use alloc::vec::Vec;
+use libgfx::{font, Canvas, Color, MappedSurface, Rect};
+use libruntime::{
+    clipboard::Clipboard,
+    display::{Display, InputEvent, InputKind, SurfaceEvent, SurfaceEventType},
+    kobject::Port,
+};
+use libterm::{Attributes, Position, Terminal};
+use log::{info, warn};
+
+libruntime::entry!(main);
+
+const WIDTH: u32 = 640;
+const HEIGHT: u32 = 400;
+const SCROLLBACK: usize = 1000;
+
+const BUTTON_SELECT: u64 = 1;
+const BUTTON_WHEEL_UP: u64 = 4;
+const BUTTON_WHEEL_DOWN: u64 = 5;
+const WHEEL_LINES: isize = 3;
+
+const DEFAULT_FOREGROUND: Color = Color::rgb(0xC0, 0xC0, 0xC0);
+const DEFAULT_BACKGROUND: Color = Color::BLACK;
+const SELECTION_BACKGROUND: Color = Color::rgb(0x30, 0x50, 0x90);
+
+struct TerminalWindow {
+    terminal: Terminal,
+    surface: MappedSurface<'static>,
+    /// Pointer position, in cells
+    pointer: (usize, usize),
+    /// Anchor of the selection in progress
+    selecting: Option<Position>,
+    /// Line typed, not yet submitted
+    line: Vec<u8>,
+}
+
+fn main() {
+    let display = Display::connect().expect("Could not connect to display server");
+    let (events, events_sender) = Port::create(None).expect("Could not create event port");
+
+    let surface = display
+        .create_surface(WIDTH, HEIGHT, &events_sender)
+        .expect("Could not create surface");
+    let mapped = MappedSurface::map(&surface).expect("Could not map surface");
+
+    let columns = WIDTH as usize / font::CHAR_WIDTH;
+    let rows = HEIGHT as usize / font::LINE_HEIGHT;
+    info!("Terminal ready ({}x{})", columns, rows);
+
+    let mut window = TerminalWindow {
+        terminal: Terminal::new(columns, rows, SCROLLBACK),
+        surface: mapped,
+        pointer: (0, 0),
+        selecting: None,
+        line: Vec::new(),
+    };
+
+    window
+        .terminal
+        .write(b"mti-fun-os terminal\r\n(no shell hosted yet: lines are echoed)\r\n> ");
+    window.draw();
+
+    loop {
+        let message = match events.blocking_receive() {
+            Ok(message) => message,
+            Err(err) => {
+                warn!("Could not receive event: {:?}", err);
+                continue;
+            }
+        };
+
+        let event = *unsafe { message.data::<SurfaceEvent>() };
+        match event.r#type() {
+            Ok(SurfaceEventType::Input) => window.input(&event.input),
+            Ok(SurfaceEventType::FocusGained) | Ok(SurfaceEventType::FocusLost) => {}
+            Err(_) => {
+                warn!("Dropping unknown event type {}", event.r#type);
+                continue;
+            }
+        }
+
+        window.draw();
+    }
+}
+
+impl TerminalWindow {
+    fn input(&mut self, input: &InputEvent) {
+        match input.kind() {
+            Ok(InputKind::Key) if input.value == 1 => self.key(input.code),
+            Ok(InputKind::Key) => {}
+            Ok(InputKind::PointerButton) => self.pointer_button(input.code, input.value == 1),
+            Ok(InputKind::PointerMove) => self.pointer_move(input.code, input.value),
+            Err(_) => warn!("Dropping unknown input kind {}", input.kind),
+        }
+    }
+
+    fn key(&mut self, code: u64) {
+        let Some(c) = u32::try_from(code).ok().and_then(char::from_u32) else {
+            return;
+        };
+
+        self.terminal.reset_view();
+
+        match c {
+            '\r' | '\n' => {
+                // Echo the line, as the hosted program would
+                let line = core::mem::take(&mut self.line);
+                self.terminal.write(b"\r\n");
+                self.terminal.write(&line);
+                self.terminal.write(b"\r\n> ");
+            }
+            '\x08' | '\x7f' => {
+                if self.line.pop().is_some() {
+                    self.terminal.write(b"\x08 \x08");
+                }
+            }
+            c if !c.is_control() => {
+                let mut buffer = [0u8; 4];
+                let encoded = c.encode_utf8(&mut buffer).as_bytes();
+                self.line.extend_from_slice(encoded);
+                self.terminal.write(encoded);
+            }
+            _ => {}
+        }
+    }
+
+    fn pointer_button(&mut self, button: u64, pressed: bool) {
+        let (column, row) = self.pointer;
+
+        match (button, pressed) {
+            (BUTTON_SELECT, true) => {
+                let anchor = self.terminal.visible_position(column, row);
+                self.terminal.clear_selection();
+                self.selecting = Some(anchor);
+            }
+            (BUTTON_SELECT, false) => {
+                self.selecting = None;
+                self.copy_selection();
+            }
+            (BUTTON_WHEEL_UP, true) => self.terminal.scroll_view(WHEEL_LINES),
+            (BUTTON_WHEEL_DOWN, true) => self.terminal.scroll_view(-WHEEL_LINES),
+            _ => {}
+        }
+    }
+
+    fn pointer_move(&mut self, x: u64, y: u64) {
+        let column = (x as usize / font::CHAR_WIDTH).min(self.terminal.columns() - 1);
+        let row = (y as usize / font::LINE_HEIGHT).min(self.terminal.rows() - 1);
+        self.pointer = (column, row);
+
+        if let Some(anchor) = self.selecting {
+            let end = self.terminal.visible_position(column, row);
+            self.terminal.select(anchor, end);
+        }
+    }
+
+    fn copy_selection(&self) {
+        let Some(text) = self.terminal.selected_text() else {
+            return;
+        };
+
+        let result =
+            Clipboard::connect().and_then(|clipboard| clipboard.set("text/plain", text.as_bytes()));
+        if let Err(err) = result {
+            warn!("Could not copy selection to clipboard: {:?}", err);
+        }
+    }
+
+    fn draw(&mut self) {
+        let terminal = &self.terminal;
+        let mut canvas = self.surface.canvas();
+        canvas.clear(DEFAULT_BACKGROUND);
+
+        for row in 0..terminal.rows() {
+            for (column, cell) in terminal.visible_line(row).iter().enumerate() {
+                let selected = terminal.is_selected(terminal.visible_position(column, row));
+                let cursor = terminal.cursor() == Some((column, row));
+                draw_cell(
+                    &mut canvas,
+                    column,
+                    row,
+                    cell.c,
+                    &cell.attrs,
+                    selected,
+                    cursor,
+                );
+            }
+        }
+    }
+}
+
+fn draw_cell(
+    canvas: &mut Canvas,
+    column: usize,
+    row: usize,
+    c: char,
+    attrs: &Attributes,
+    selected: bool,
+    cursor: bool,
+) {
+    let mut foreground = color(attrs.foreground, DEFAULT_FOREGROUND, attrs.bold);
+    let mut background = color(attrs.background, DEFAULT_BACKGROUND, false);
+
+    if attrs.reverse != cursor {
+        (foreground, background) = (background, foreground);
+    }
+    if selected {
+        background = SELECTION_BACKGROUND;
+    }
+
+    let x = (column * font::CHAR_WIDTH) as i32;
+    let y = (row * font::LINE_HEIGHT) as i32;
+    let width = font::CHAR_WIDTH as u32;
+    let height = font::LINE_HEIGHT as u32;
+
+    if background != DEFAULT_BACKGROUND {
+        canvas.fill_rect(Rect::new(x, y, width, height), background);
+    }
+
+    if c != ' ' {
+        canvas.draw_char(x, y, c, foreground);
+    }
+
+    if attrs.underline {
+        canvas.fill_rect(Rect::new(x, y + height as i32 - 1, width, 1), foreground);
+    }
+}
+
+/// Get the color of a cell, `bold` selects the bright variant of the ANSI colors
+fn color(color: libterm::Color, default: Color, bold: bool) -> Color {
+    use libterm::Color as Ansi;
+
+    let color = match (color, bold) {
+        (Ansi::Black, true) => Ansi::BrightBlack,
+        (Ansi::Red, true) => Ansi::BrightRed,
+        (Ansi::Green, true) => Ansi::BrightGreen,
+        (Ansi::Yellow, true) => Ansi::BrightYellow,
+        (Ansi::Blue, true) => Ansi::BrightBlue,
+        (Ansi::Magenta, true) => Ansi::BrightMagenta,
+        (Ansi::Cyan, true) => Ansi::BrightCyan,
+        (Ansi::White, true) => Ansi::BrightWhite,
+        (color, _) => color,
+    };
+
+    match color {
+        Ansi::Default => default,
+        Ansi::Black => Color::rgb(0x00, 0x00, 0x00),
+        Ansi::Red => Color::rgb(0xAA, 0x00, 0x00),
+        Ansi::Green => Color::rgb(0x00, 0xAA, 0x00),
+        Ansi::Yellow => Color::rgb(0xAA, 0x55, 0x00),
+        Ansi::Blue => Color::rgb(0x00, 0x00, 0xAA),
+        Ansi::Magenta => Color::rgb(0xAA, 0x00, 0xAA),
+        Ansi::Cyan => Color::rgb(0x00, 0xAA, 0xAA),
+        Ansi::White => Color::rgb(0xAA, 0xAA, 0xAA),
+        Ansi::BrightBlack => Color::rgb(0x55, 0x55, 0x55),
+        Ansi::BrightRed => Color::rgb(0xFF, 0x55, 0x55),
+        Ansi::BrightGreen => Color::rgb(0x55, 0xFF, 0x55),
+        Ansi::BrightYellow => Color::rgb(0xFF, 0xFF, 0x55),
+        Ansi::BrightBlue => Color::rgb(0x55, 0x55, 0xFF),
+        Ansi::BrightMagenta => Color::rgb(0xFF, 0x55, 0xFF),
+        Ansi::BrightCyan => Color::rgb(0x55, 0xFF, 0xFF),
+        Ansi::BrightWhite => Color::rgb(0xFF, 0xFF, 0xFF),
+    }
+}
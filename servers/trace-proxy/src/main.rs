@@ -0,0 +1,192 @@
+//! Tracing proxy: interposed between the clients of a service and its server, it records all the messages
+//!
+//! It is used to debug protocol mismatches (eg: between libruntime versions) without modifying the clients or the server.
+//!
+//! The proxy relies on lazy service activation (see `libruntime::service`):
+//! - the service manager registers the service, and hands its port over to the proxy (started first, as the service server)
+//! - the proxy hands a port of its own over to the real server, and forwards the messages between them
+//!
+//! Requests are forwarded as is (correlation, deadline and idempotency token are kept).
+//! The reply port (handle 0, by convention of all protocols) is replaced with a relay port, so that the reply is recorded too.
+//! Other handles are forwarded untouched: only their types are recorded.
+//!
+//! Records are written to the log (the serial log file on the host), one line per message, prefixed with `trace:`.
+//! Each message is also written in the canonical transcript format (see `libtestsupport::transcript`), prefixed with `transcript:`:
+//! those lines make a golden transcript of the session, to replay against another implementation of the server.
+
+#![no_std]
+#![no_main]
+
+extern crate alloc;
+extern crate libruntime;
+
+use core::time::Duration;
+
+use alloc::{format, string::String, vec::Vec};
+use libruntime::{
+    kobject::{Clock, Error, KWaitable, Message, Port, PortReceiver, PortSender, Waiter},
+    service,
+};
+use libtestsupport::transcript::{self, Record};
+use log::{info, warn};
+
+libruntime::entry!(main);
+
+/// Name of the service to trace, given at build time (there are no process arguments yet)
+const TARGET: &str = match option_env!("TRACE_PROXY_TARGET") {
+    Some(target) => target,
+    None => "clipboard",
+};
+
+/// Data items recorded per message
+const DATA_ITEMS: usize = Message::DATA_SIZE / 8;
+
+/// Direction of a recorded message
+#[derive(Debug, Clone, Copy)]
+enum Direction {
+    /// From a client to the server
+    Request,
+    /// From the server to the reply port of a client
+    Reply,
+}
+
+/// Reply port given to the server in place of the reply port of the client
+struct Relay {
+    receiver: PortReceiver,
+    client: PortSender,
+    /// Number of the exchange in the transcript
+    number: usize,
+}
+
+fn main() {
+    let clients = service::accept_activation(TARGET).expect("Could not accept service port");
+
+    let (upstream_receiver, upstream) = Port::create(None).expect("Could not create port");
+    service::hand_over(TARGET, upstream_receiver).expect("Could not hand over port to server");
+
+    info!("Tracing service '{}'", TARGET);
+
+    let mut relays: Vec<Relay> = Vec::new();
+    let mut exchanges = 0;
+
+    loop {
+        let ready = match wait(&clients, &relays) {
+            Ok(ready) => ready,
+            Err(err) => {
+                warn!("Could not wait for messages: {:?}", err);
+                continue;
+            }
+        };
+
+        if ready.clients {
+            if let Err(err) = forward_request(&clients, &upstream, &mut relays, &mut exchanges) {
+                warn!("Could not forward request: {:?}", err);
+            }
+        }
+
+        // Replies are one-shot: the relay is dropped once its reply is forwarded
+        for index in ready.relays.into_iter().rev() {
+            let relay = relays.swap_remove(index);
+            if let Err(err) = forward_reply(&relay) {
+                warn!("Could not forward reply: {:?}", err);
+            }
+        }
+    }
+}
+
+struct Ready {
+    clients: bool,
+    /// Indexes of the ready relays, in increasing order
+    relays: Vec<usize>,
+}
+
+fn wait(clients: &PortReceiver, relays: &[Relay]) -> Result<Ready, Error> {
+    let mut waitables: Vec<&dyn KWaitable> = Vec::with_capacity(relays.len() + 1);
+    waitables.push(clients);
+    waitables.extend(relays.iter().map(|relay| &relay.receiver as &dyn KWaitable));
+
+    let mut waiter = Waiter::new(&waitables);
+    waiter.wait()?;
+
+    Ok(Ready {
+        clients: waiter.is_ready(0),
+        relays: (0..relays.len())
+            .filter(|&index| waiter.is_ready(index + 1))
+            .collect(),
+    })
+}
+
+fn forward_request(
+    clients: &PortReceiver,
+    upstream: &PortSender,
+    relays: &mut Vec<Relay>,
+    exchanges: &mut usize,
+) -> Result<(), Error> {
+    let mut message = match clients.receive() {
+        Ok(message) => message,
+        Err(Error::ObjectNotReady) => return Ok(()),
+        Err(err) => return Err(err),
+    };
+
+    let number = *exchanges;
+    *exchanges += 1;
+    record(Direction::Request, number, &message);
+
+    if let Ok(client) = PortSender::from_handle(message.take_handle(0)) {
+        let (receiver, sender) = Port::create(None)?;
+        message.handles[0] = sender.into_handle();
+        relays.push(Relay {
+            receiver,
+            client,
+            number,
+        });
+    }
+
+    upstream.send(&mut message)
+}
+
+fn forward_reply(relay: &Relay) -> Result<(), Error> {
+    let mut message = match relay.receiver.receive() {
+        Ok(message) => message,
+        Err(Error::ObjectNotReady) => return Ok(()),
+        Err(err) => return Err(err),
+    };
+
+    record(Direction::Reply, relay.number, &message);
+
+    relay.client.send(&mut message)
+}
+
+fn record(direction: Direction, number: usize, message: &Message) {
+    let timestamp = Clock::uptime().unwrap_or(Duration::ZERO);
+    let data = unsafe { message.data::<[u64; DATA_ITEMS]>() };
+
+    let mut handles = String::new();
+    for (index, handle) in message.handles.iter().enumerate() {
+        if handle.valid() {
+            if !handles.is_empty() {
+                handles.push(',');
+            }
+            handles.push_str(&format!("{}:{:?}", index, handle.r#type()));
+        }
+    }
+
+    info!(
+        "trace: t={}ns {:?} pid={} correlation={} deadline={} token={} data={:x?} handles=[{}]",
+        timestamp.as_nanos(),
+        direction,
+        message.sender_pid(),
+        message.correlation(),
+        message.deadline(),
+        message.token(),
+        data,
+        handles
+    );
+
+    let record = Record::new(message);
+    let line = match direction {
+        Direction::Request => transcript::request_line(number, &record),
+        Direction::Reply => transcript::reply_line(number, &record),
+    };
+    info!("transcript: {}", line);
+}
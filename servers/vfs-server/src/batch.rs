@@ -0,0 +1,242 @@
+// Batches of operations on a single mount
+//
+// vfs-server processes one request at a time, so no client sees the intermediate states of a batch.
+// Each operation applied records how to undo it: if one fails, the log is undone in reverse order.
+// A file replaced by a rename is set aside in the trash directory of the mount, and only removed once the batch succeeded.
+
+use alloc::{format, string::String, vec::Vec};
+use libruntime::{fs::NodeInfo, kobject::Error, vfs::BatchOperation};
+use log::warn;
+
+use crate::{
+    cache::Cache,
+    mounts::{Mount, Mounts},
+};
+
+enum Undo {
+    /// Remove a created node
+    Remove { parent: u64, name: String },
+    /// Put back the data overwritten in a file, then its size
+    Write {
+        node: u64,
+        offset: u64,
+        data: Vec<u8>,
+        size: u64,
+    },
+    /// Move a renamed node back
+    Rename {
+        parent: u64,
+        name: String,
+        new_parent: u64,
+        new_name: String,
+    },
+    /// Move back a file replaced by a rename
+    Unstash {
+        stash: String,
+        node: u64,
+        parent: u64,
+        name: String,
+    },
+}
+
+struct Batch<'a> {
+    mounts: &'a Mounts,
+    mount_id: u64,
+    mount: &'a Mount,
+    cache: &'a mut Cache,
+    /// Sender of the batch
+    opener: u64,
+    undo: Vec<Undo>,
+}
+
+/// Apply the operations of `sender`, all on the same mount, or none of them
+pub fn apply(
+    mounts: &Mounts,
+    cache: &mut Cache,
+    operations: &[BatchOperation],
+    sender: u64,
+) -> Result<(), Error> {
+    // Nothing is applied if an operation is on another mount
+    let mut mount_id = None;
+    for path in operations.iter().flat_map(BatchOperation::paths) {
+        let location = mounts.resolve(path)?;
+        if *mount_id.get_or_insert(location.mount) != location.mount {
+            return Err(Error::InvalidArgument);
+        }
+    }
+
+    let Some(mount_id) = mount_id else {
+        // Empty batch
+        return Ok(());
+    };
+
+    let mut batch = Batch {
+        mounts,
+        mount_id,
+        mount: mounts.get(mount_id)?,
+        cache,
+        opener: sender,
+        undo: Vec::new(),
+    };
+
+    for operation in operations {
+        if let Err(err) = batch.apply(operation) {
+            batch.rollback();
+            return Err(err);
+        }
+    }
+
+    batch.commit();
+    Ok(())
+}
+
+impl Batch<'_> {
+    fn apply(&mut self, operation: &BatchOperation) -> Result<(), Error> {
+        let fs = &self.mount.fs;
+
+        match operation {
+            BatchOperation::Create { path, kind } => {
+                let location = self.mounts.resolve(path)?;
+                let (parent, name) = self.mounts.lookup_parent(&location)?;
+
+                fs.create(parent, name, *kind)?;
+                self.undo.push(Undo::Remove {
+                    parent,
+                    name: String::from(name),
+                });
+            }
+
+            BatchOperation::Write { path, offset, data } => {
+                let info = self.mounts.lookup(&self.mounts.resolve(path)?)?;
+                if info.is_directory() {
+                    return Err(Error::InvalidArgument);
+                }
+
+                // Recorded first: undoing a write which did not happen changes nothing
+                let old = fs.read(info.id, *offset, data.len(), self.opener)?;
+                self.undo.push(Undo::Write {
+                    node: info.id,
+                    offset: *offset,
+                    data: old,
+                    size: info.size,
+                });
+
+                self.cache.invalidate(self.mount_id, info.id);
+                fs.write(info.id, *offset, data, self.opener)?;
+            }
+
+            BatchOperation::Rename { from, to } => {
+                let from = self.mounts.resolve(from)?;
+                let to = self.mounts.resolve(to)?;
+                let (parent, name) = self.mounts.lookup_parent(&from)?;
+                let (new_parent, new_name) = self.mounts.lookup_parent(&to)?;
+                let source = self.mounts.lookup(&from)?;
+
+                if let Ok(replaced) = self.mounts.lookup(&to) {
+                    if replaced.id == source.id {
+                        return Ok(());
+                    }
+
+                    // Only a file replaces a file
+                    if replaced.is_directory() || source.is_directory() {
+                        return Err(Error::ObjectNameDuplicate);
+                    }
+
+                    self.stash(&replaced, new_parent, new_name)?;
+                }
+
+                fs.rename(parent, name, new_parent, new_name, false)?;
+                self.undo.push(Undo::Rename {
+                    parent,
+                    name: String::from(name),
+                    new_parent,
+                    new_name: String::from(new_name),
+                });
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Set a file aside in the trash directory, until the batch succeeds
+    fn stash(&mut self, info: &NodeInfo, parent: u64, name: &str) -> Result<(), Error> {
+        let trash_dir = self.mount.trash_dir()?;
+        let stash = format!("batch-{}", info.id);
+
+        self.mount
+            .fs
+            .rename(parent, name, trash_dir, &stash, false)?;
+        self.undo.push(Undo::Unstash {
+            stash,
+            node: info.id,
+            parent,
+            name: String::from(name),
+        });
+
+        Ok(())
+    }
+
+    /// Remove the files replaced by renames
+    fn commit(self) {
+        for undo in self.undo.iter() {
+            let Undo::Unstash { stash, node, .. } = undo else {
+                continue;
+            };
+
+            let result = self
+                .mount
+                .trash_dir()
+                .and_then(|trash_dir| self.mount.fs.remove(trash_dir, stash));
+            if let Err(err) = result {
+                warn!("Could not remove replaced file '{}': {:?}", stash, err);
+            }
+
+            self.cache.invalidate(self.mount_id, *node);
+        }
+    }
+
+    /// Undo the operations applied, last first
+    fn rollback(mut self) {
+        let fs = &self.mount.fs;
+
+        while let Some(undo) = self.undo.pop() {
+            let result = match &undo {
+                Undo::Remove { parent, name } => fs.remove(*parent, name),
+                Undo::Write {
+                    node,
+                    offset,
+                    data,
+                    size,
+                } => {
+                    self.cache.invalidate(self.mount_id, *node);
+
+                    let restored = if data.is_empty() {
+                        Ok(0)
+                    } else {
+                        fs.write(*node, *offset, data, self.opener)
+                    };
+                    restored.and_then(|_| fs.truncate(*node, *size, self.opener))
+                }
+                Undo::Rename {
+                    parent,
+                    name,
+                    new_parent,
+                    new_name,
+                } => fs.rename(*new_parent, new_name, *parent, name, false),
+                Undo::Unstash {
+                    stash,
+                    parent,
+                    name,
+                    ..
+                } => self
+                    .mount
+                    .trash_dir()
+                    .and_then(|trash_dir| fs.rename(trash_dir, stash, *parent, name, false)),
+            };
+
+            if let Err(err) = result {
+                warn!("Could not undo batch operation: {:?}", err);
+            }
+        }
+    }
+}
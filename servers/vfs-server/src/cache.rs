@@ -0,0 +1,358 @@
+// Block cache, filled by read-ahead
+//
+// Prefetches are sent to the filesystem servers without waiting: their replies come back on the prefetch port,
+// processed by the main loop (or right away by a read which needs a block being prefetched).
+// Each prefetch carries a correlation id of its own, so that its reply can be matched whatever the order.
+//
+// Filesystem servers only change the data on requests from vfs-server, so the cache only needs to be invalidated
+// when vfs-server sends them a change. A change bumps the generation of the node: prefetches started before it are dropped.
+
+use core::ops::Range;
+
+use alloc::{
+    collections::{BTreeMap, VecDeque},
+    vec::Vec,
+};
+use libruntime::{
+    fs::{self, Filesystem, Reply},
+    kobject::{Error, MemoryObject, Message, Port, PortReceiver, PortSender},
+    vfs::{Statistics, BLOCK_SIZE},
+};
+use log::{debug, warn};
+
+/// Maximum number of blocks in the cache
+const CAPACITY: usize = 1024;
+
+/// Window of the first sequential read, in blocks
+const READ_AHEAD_MIN: usize = 2;
+
+/// Correlation ids of the prefetches have the high bit set, so that they are not mistaken with the kernel ones in traces
+const PREFETCH_CORRELATION_BASE: u64 = 1 << 63;
+
+/// Block of a node of a mount
+type BlockKey = (u64, u64, u64);
+
+/// Node of a mount
+pub type NodeKey = (u64, u64);
+
+/// Read-ahead state of an open handle
+#[derive(Debug)]
+pub struct ReadAhead {
+    /// Offset where the last read ended
+    next_offset: u64,
+    /// Current window, in blocks (0: not sequential)
+    window: usize,
+    /// Maximum window, from the options of the mount (0: read-ahead disabled)
+    max_window: usize,
+}
+
+impl ReadAhead {
+    pub fn new(max_window: usize) -> Self {
+        Self {
+            next_offset: 0,
+            window: 0,
+            max_window,
+        }
+    }
+
+    /// Update the state with a new read, returns true if it is sequential
+    fn update(&mut self, offset: u64) -> bool {
+        let max_window = self.max_window;
+        let sequential = offset == self.next_offset;
+
+        self.window = if !sequential {
+            // Seek: start over
+            0
+        } else if self.window == 0 {
+            READ_AHEAD_MIN.min(max_window)
+        } else {
+            (self.window * 2).min(max_window)
+        };
+
+        sequential
+    }
+}
+
+struct Prefetch {
+    mount: u64,
+    node: u64,
+    blocks: Range<u64>,
+    generation: u64,
+}
+
+pub struct Cache {
+    /// Data of the blocks, with their insertion serial: a block shorter than `BLOCK_SIZE` is the end of its file
+    blocks: BTreeMap<BlockKey, (u64, Vec<u8>)>,
+    /// Insertion order, for eviction
+    order: VecDeque<(u64, BlockKey)>,
+    next_serial: u64,
+    /// Generation of the data of each node, bumped on each change
+    generations: BTreeMap<(u64, u64), u64>,
+    /// Prefetches waiting for their reply, by correlation id
+    in_flight: BTreeMap<u64, Prefetch>,
+    next_correlation: u64,
+    prefetch_receiver: PortReceiver,
+    prefetch_sender: PortSender,
+    statistics: Statistics,
+}
+
+impl Cache {
+    pub fn new() -> Result<Self, Error> {
+        let (prefetch_receiver, prefetch_sender) = Port::create(None)?;
+
+        Ok(Self {
+            blocks: BTreeMap::new(),
+            order: VecDeque::new(),
+            next_serial: 0,
+            generations: BTreeMap::new(),
+            in_flight: BTreeMap::new(),
+            next_correlation: PREFETCH_CORRELATION_BASE,
+            prefetch_receiver,
+            prefetch_sender,
+            statistics: Statistics::default(),
+        })
+    }
+
+    /// Port receiving the replies of the prefetches
+    pub fn prefetch_port(&self) -> &PortReceiver {
+        &self.prefetch_receiver
+    }
+
+    pub fn statistics(&self) -> Statistics {
+        Statistics {
+            cached: self.blocks.len() as u64,
+            ..self.statistics
+        }
+    }
+
+    /// Read data of a node on behalf of `opener`: from the cache if all its blocks are there, else from the filesystem server
+    ///
+    /// Then prefetch the next blocks if the read is sequential.
+    pub fn read(
+        &mut self,
+        fs: &Filesystem,
+        (mount, node): NodeKey,
+        opener: u64,
+        offset: u64,
+        size: usize,
+        read_ahead: &mut ReadAhead,
+    ) -> Result<Vec<u8>, Error> {
+        let sequential = read_ahead.update(offset);
+
+        let data = match self.read_cached(mount, node, offset, size)? {
+            Some(data) => {
+                self.statistics.hits += 1;
+                data
+            }
+            None => {
+                self.statistics.misses += 1;
+                fs.read(node, offset, size, opener)?
+            }
+        };
+
+        let end = offset + data.len() as u64;
+        read_ahead.next_offset = end;
+
+        // A short read is the end of the file: nothing more to prefetch
+        if sequential && read_ahead.window > 0 && data.len() == size {
+            let first = end / BLOCK_SIZE as u64;
+            let blocks = first..first + read_ahead.window as u64;
+            self.prefetch(fs, (mount, node), opener, blocks);
+        }
+
+        Ok(data)
+    }
+
+    /// Assemble data from the cached blocks, `None` if a block is missing
+    fn read_cached(
+        &mut self,
+        mount: u64,
+        node: u64,
+        offset: u64,
+        size: usize,
+    ) -> Result<Option<Vec<u8>>, Error> {
+        if size == 0 {
+            return Ok(Some(Vec::new()));
+        }
+
+        let end = offset + size as u64;
+        let blocks = offset / BLOCK_SIZE as u64..end.div_ceil(BLOCK_SIZE as u64);
+
+        // Blocks on their way are about to be there
+        self.wait_prefetches(mount, node, &blocks)?;
+
+        let mut data = Vec::with_capacity(size);
+        for index in blocks {
+            let Some((_, block)) = self.blocks.get(&(mount, node, index)) else {
+                return Ok(None);
+            };
+
+            let block_offset = index * BLOCK_SIZE as u64;
+            let start = offset.saturating_sub(block_offset) as usize;
+            let stop = ((end - block_offset) as usize).min(block.len());
+            if start < stop {
+                data.extend_from_slice(&block[start..stop]);
+            }
+
+            if block.len() < BLOCK_SIZE {
+                // End of the file
+                break;
+            }
+        }
+
+        Ok(Some(data))
+    }
+
+    /// Send a prefetch for the blocks which are neither cached nor on their way, from the first one of them
+    fn prefetch(
+        &mut self,
+        fs: &Filesystem,
+        (mount, node): NodeKey,
+        opener: u64,
+        blocks: Range<u64>,
+    ) {
+        let Some(first) = blocks.clone().find(|&index| {
+            !self.is_cached(mount, node, index) && !self.is_in_flight(mount, node, index)
+        }) else {
+            return;
+        };
+
+        let blocks = first..blocks.end;
+        let correlation = self.next_correlation;
+        let offset = first * BLOCK_SIZE as u64;
+        let size = (blocks.end - first) as usize * BLOCK_SIZE;
+
+        if let Err(err) = fs.send_read(
+            node,
+            offset,
+            size,
+            opener,
+            &self.prefetch_sender,
+            correlation,
+        ) {
+            warn!("Could not send prefetch: {:?}", err);
+            return;
+        }
+
+        self.next_correlation += 1;
+        self.in_flight.insert(
+            correlation,
+            Prefetch {
+                mount,
+                node,
+                blocks,
+                generation: self.generation(mount, node),
+            },
+        );
+    }
+
+    /// Block until no prefetch of these blocks is on its way
+    fn wait_prefetches(&mut self, mount: u64, node: u64, blocks: &Range<u64>) -> Result<(), Error> {
+        while self.in_flight.values().any(|prefetch| {
+            prefetch.mount == mount
+                && prefetch.node == node
+                && prefetch.blocks.start < blocks.end
+                && blocks.start < prefetch.blocks.end
+        }) {
+            let message = self.prefetch_receiver.blocking_receive()?;
+            self.prefetch_done(message);
+        }
+
+        Ok(())
+    }
+
+    /// Process the prefetch replies received
+    pub fn process_prefetches(&mut self) {
+        while let Ok(message) = self.prefetch_receiver.receive() {
+            self.prefetch_done(message);
+        }
+    }
+
+    fn prefetch_done(&mut self, mut message: Message) {
+        let Some(prefetch) = self.in_flight.remove(&message.correlation()) else {
+            warn!("Dropping unexpected prefetch reply");
+            return;
+        };
+
+        // Changed since the prefetch has been sent
+        if prefetch.generation != self.generation(prefetch.mount, prefetch.node) {
+            return;
+        }
+
+        let size = match unsafe { message.data::<Reply>() }.result() {
+            Ok((size, _)) => size,
+            Err(err) => {
+                debug!("Prefetch failed: {:?}", err);
+                return;
+            }
+        };
+
+        let data = match MemoryObject::from_handle(message.take_handle(0)) {
+            Ok(object) => fs::copy_from_object(&object, size),
+            Err(_) if size == 0 => Ok(Vec::new()),
+            Err(_) => Err(Error::InvalidArgument),
+        };
+        let Ok(data) = data else {
+            warn!("Dropping malformed prefetch reply");
+            return;
+        };
+
+        for (chunk, index) in data.chunks(BLOCK_SIZE).zip(prefetch.blocks) {
+            self.insert((prefetch.mount, prefetch.node, index), Vec::from(chunk));
+            self.statistics.prefetched += 1;
+        }
+    }
+
+    fn insert(&mut self, key: BlockKey, data: Vec<u8>) {
+        let serial = self.next_serial;
+        self.next_serial += 1;
+
+        self.blocks.insert(key, (serial, data));
+        self.order.push_back((serial, key));
+
+        while self.blocks.len() > CAPACITY {
+            let Some((serial, key)) = self.order.pop_front() else {
+                break;
+            };
+
+            // Skip the entries of blocks replaced or dropped since
+            if self
+                .blocks
+                .get(&key)
+                .is_some_and(|(block_serial, _)| *block_serial == serial)
+            {
+                self.blocks.remove(&key);
+            }
+        }
+    }
+
+    fn is_cached(&self, mount: u64, node: u64, index: u64) -> bool {
+        self.blocks.contains_key(&(mount, node, index))
+    }
+
+    fn is_in_flight(&self, mount: u64, node: u64, index: u64) -> bool {
+        self.in_flight.values().any(|prefetch| {
+            prefetch.mount == mount && prefetch.node == node && prefetch.blocks.contains(&index)
+        })
+    }
+
+    fn generation(&self, mount: u64, node: u64) -> u64 {
+        self.generations.get(&(mount, node)).copied().unwrap_or(0)
+    }
+
+    /// Drop the blocks of a node, before its data changes
+    pub fn invalidate(&mut self, mount: u64, node: u64) {
+        *self.generations.entry((mount, node)).or_insert(0) += 1;
+        self.blocks
+            .retain(|&(block_mount, block_node, _), _| (block_mount, block_node) != (mount, node));
+    }
+
+    /// Drop the blocks of a mount, when it is unmounted
+    pub fn invalidate_mount(&mut self, mount: u64) {
+        self.blocks
+            .retain(|&(block_mount, _, _), _| block_mount != mount);
+        self.generations
+            .retain(|&(block_mount, _), _| block_mount != mount);
+        self.in_flight.retain(|_, prefetch| prefetch.mount != mount);
+    }
+}
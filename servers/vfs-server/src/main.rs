@@ -1,18 +1,838 @@
 #![no_std]
 #![no_main]
-#![feature(naked_functions)]
-#![feature(used_with_arg)]
 
 extern crate alloc;
 extern crate libruntime;
 
-use log::info;
+mod batch;
+mod cache;
+mod mounts;
+mod trash;
 
-#[no_mangle]
-pub extern "C" fn _start() -> ! {
-    libruntime::init();
+use core::time::Duration;
 
-    info!("Hello, world!");
+use alloc::{
+    collections::BTreeMap,
+    string::{String, ToString},
+    vec,
+    vec::Vec,
+};
+use cache::{Cache, ReadAhead};
+use libruntime::{
+    failure,
+    fs::{self, Filesystem, NodeInfo, NodeKind},
+    introspection::{self, ResourceTracker},
+    kobject::{
+        Audit, AuditEventType, Clock, Error, Handle, MemoryObject, Message, Port, PortReceiver,
+        PortSender, Process, ProcessEventType, ProcessListener, ProcessListenerFilter, Thread,
+        Timer, Waiter,
+    },
+    kvblock::{KVBlockBuilder, Value},
+    retry::Backoff,
+    vfs::{
+        self, OpenFlags, Reply, Request, RequestType, TimeSpec, TrashEntry, SERVER_PORT_NAME,
+        TRASH_DIR, TRASH_ENTRY_KEY,
+    },
+};
+use log::{debug, info, warn};
+use mounts::{Location, Mount, Mounts};
+use trash::{Trash, Trashed};
 
-    libruntime::exit()
+libruntime::entry!(main);
+
+/// Wait for the filesystem server of a mount: it may be started along with vfs-server.
+/// Bounded, since no other request is processed meanwhile.
+const FS_CONNECT_BACKOFF: Backoff =
+    Backoff::new(Duration::from_millis(10), Duration::from_millis(200))
+        .timeout(Duration::from_secs(2));
+
+/// Slack of the trash expiry timer: expiry does not need to be precise
+const EXPIRY_SLACK: Duration = Duration::from_millis(100);
+
+/// Node opened by a client
+struct OpenedNode {
+    mount: u64,
+    node: u64,
+    kind: NodeKind,
+    read_ahead: ReadAhead,
+    /// Process which opened the node (kernel-stamped sender): the only one which can use the handle
+    opener: u64,
+}
+
+struct Server {
+    mounts: Mounts,
+    handles: BTreeMap<u64, OpenedNode>,
+    next_handle: u64,
+    cache: Cache,
+    /// Process which spawned the server (init): the only one which can mount filesystems
+    spawner: u64,
+    /// Open handles, by client which opened them
+    resources: ResourceTracker,
+    trash: Trash,
+    /// Fires on the next expiry of the trash
+    expiry_timer: Timer,
+    /// Uptime the expiry timer is armed on
+    armed_expiry: Option<Duration>,
+}
+
+fn main() {
+    let (receiver, _sender) =
+        Port::create(Some(SERVER_PORT_NAME)).expect("Could not create server port");
+
+    let mut server = Server {
+        mounts: Mounts::new(),
+        handles: BTreeMap::new(),
+        next_handle: 1,
+        cache: Cache::new().expect("Could not create cache"),
+        spawner: Process::current().info().creator_pid,
+        resources: ResourceTracker::new(),
+        trash: Trash::new(),
+        expiry_timer: Timer::create().expect("Could not create expiry timer"),
+        armed_expiry: None,
+    };
+
+    // Handles of processes which terminate are closed
+    let processes = ProcessListener::create(ProcessListenerFilter::All)
+        .expect("Could not create process listener");
+
+    info!("VFS server ready on port '{}'", SERVER_PORT_NAME);
+
+    loop {
+        let (request_ready, prefetch_ready, expiry_ready, processes_ready) = {
+            let mut waiter = Waiter::new(&[
+                &receiver,
+                server.cache.prefetch_port(),
+                &server.expiry_timer,
+                &processes,
+            ]);
+            if let Err(err) = waiter.wait() {
+                warn!("Could not wait: {:?}", err);
+                continue;
+            }
+
+            (
+                waiter.is_ready(0),
+                waiter.is_ready(1),
+                waiter.is_ready(2),
+                waiter.is_ready(3),
+            )
+        };
+
+        if processes_ready {
+            while let Ok(event) = processes.receive() {
+                if event.r#type == ProcessEventType::Terminated {
+                    server.process_terminated(event.pid);
+                }
+            }
+        }
+
+        // Prefetches first: the request may be about their blocks
+        if prefetch_ready {
+            server.cache.process_prefetches();
+        }
+
+        if expiry_ready {
+            server.expire_trash();
+        }
+
+        if request_ready {
+            process_message(&receiver, &mut server);
+        }
+
+        server.arm_expiry();
+    }
+}
+
+fn process_message(receiver: &PortReceiver, server: &mut Server) {
+    let mut message = match receiver.receive() {
+        Ok(message) => message,
+        Err(Error::ObjectNotReady) => return,
+        Err(err) => {
+            warn!("Could not receive request: {:?}", err);
+            return;
+        }
+    };
+
+    let reply_port = match PortSender::from_handle(message.take_handle(0)) {
+        Ok(port) => port,
+        Err(_) => {
+            warn!("Dropping request without reply port");
+            return;
+        }
+    };
+
+    if let Some(request) = introspection::Request::from_message(&message) {
+        server.resources.reply(&request, &reply_port);
+        return;
+    }
+
+    // The thread takes the correlation of each message it receives, including prefetch replies
+    let correlation = message.correlation();
+
+    // If processing panics, the client gets a failure report instead of the reply
+    let _request = failure::begin_request(&reply_port, correlation);
+
+    let request = *unsafe { message.data::<Request>() };
+    // Shed requests whose caller gave up waiting
+    let (result, object) = match Thread::check_deadline() {
+        Ok(()) => server.process_request(&request, &mut message),
+        Err(err) => (Err(err), None),
+    };
+
+    let reply = Reply::new(result);
+    let mut handles = [object.map_or(Handle::invalid(), MemoryObject::into_handle)];
+    let mut reply_message = unsafe { Message::new(&reply, &mut handles) };
+    reply_message.set_correlation(correlation);
+    if let Err(err) = reply_port.send(&mut reply_message) {
+        warn!("Could not send reply: {:?}", err);
+    }
+}
+
+impl Server {
+    /// Process a request, returns the result and the memory object to send with the reply
+    fn process_request(
+        &mut self,
+        request: &Request,
+        message: &mut Message,
+    ) -> (Result<(usize, NodeInfo), Error>, Option<MemoryObject>) {
+        let r#type = match RequestType::try_from(request.r#type) {
+            Ok(r#type) => r#type,
+            Err(err) => return (Err(err), None),
+        };
+
+        let sender = message.sender_pid();
+
+        if let Err(err) = self.check_handles(r#type, request, sender) {
+            return (Err(err), None);
+        }
+
+        match r#type {
+            RequestType::Mount => (
+                self.mount(request, message, sender).map(|info| (0, info)),
+                None,
+            ),
+            RequestType::Unmount => (
+                self.unmount(request, message, sender)
+                    .map(|_| (0, NodeInfo::EMPTY)),
+                None,
+            ),
+            RequestType::Open => (self.open(request, message, sender), None),
+            RequestType::Close => (
+                self.close(request.handle).map(|_| (0, NodeInfo::EMPTY)),
+                None,
+            ),
+            RequestType::Read => match self.read(request) {
+                Ok(data) if data.is_empty() => (Ok((0, NodeInfo::EMPTY)), None),
+                Ok(data) => reply_with_data(&data),
+                Err(err) => (Err(err), None),
+            },
+            RequestType::Write => (
+                self.write(request, message)
+                    .map(|size| (size, NodeInfo::EMPTY)),
+                None,
+            ),
+            RequestType::Stat => (self.stat(request, message).map(|info| (0, info)), None),
+            RequestType::SetTimes => (self.set_times(request).map(|info| (0, info)), None),
+            RequestType::HandleStat => {
+                (self.handle_stat(request.handle).map(|info| (0, info)), None)
+            }
+            RequestType::Truncate => (self.truncate(request).map(|_| (0, NodeInfo::EMPTY)), None),
+            RequestType::Mkdir => (self.mkdir(request, message).map(|info| (0, info)), None),
+            RequestType::Remove => (
+                self.remove(request, message).map(|_| (0, NodeInfo::EMPTY)),
+                None,
+            ),
+            RequestType::Rename => (
+                self.rename(request, message).map(|_| (0, NodeInfo::EMPTY)),
+                None,
+            ),
+            RequestType::List => match self.list(request, message) {
+                Ok(block) => reply_with_data(&block),
+                Err(err) => (Err(err), None),
+            },
+            RequestType::Statistics => reply_with_data(&self.cache.statistics().to_block()),
+            RequestType::CopyRange => (
+                self.copy_range(request).map(|size| (size, NodeInfo::EMPTY)),
+                None,
+            ),
+            RequestType::ListTrash => match self.list_trash() {
+                block if block.is_empty() => (Ok((0, NodeInfo::EMPTY)), None),
+                block => reply_with_data(&block),
+            },
+            RequestType::Restore => (self.restore(request.handle).map(|info| (0, info)), None),
+            RequestType::Purge => (
+                self.purge(request.handle).map(|_| (0, NodeInfo::EMPTY)),
+                None,
+            ),
+            RequestType::Batch => (
+                self.batch(request, message, sender)
+                    .map(|_| (0, NodeInfo::EMPTY)),
+                None,
+            ),
+        }
+    }
+
+    /// Check that the handles of a request were opened by its sender: handles are private to their opener
+    ///
+    /// Fails with `Error::ObjectNotFound` otherwise, as if they did not exist.
+    fn check_handles(
+        &self,
+        r#type: RequestType,
+        request: &Request,
+        sender: u64,
+    ) -> Result<(), Error> {
+        let handles: &[u64] = match r#type {
+            RequestType::Close
+            | RequestType::Read
+            | RequestType::Write
+            | RequestType::HandleStat
+            | RequestType::SetTimes
+            | RequestType::Truncate => &[request.handle],
+            RequestType::CopyRange => &[request.handle, request.other_handle],
+            _ => &[],
+        };
+
+        for handle in handles {
+            let opened = self.handles.get(handle).ok_or(Error::ObjectNotFound)?;
+            if opened.opener != sender {
+                warn!(
+                    "Process {} used handle {} of process {}",
+                    sender, handle, opened.opener
+                );
+                return Err(Error::ObjectNotFound);
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Close the handles of a process which terminated
+    fn process_terminated(&mut self, pid: u64) {
+        let handles: Vec<u64> = self
+            .handles
+            .iter()
+            .filter(|(_, opened)| opened.opener == pid)
+            .map(|(&handle, _)| handle)
+            .collect();
+
+        for &handle in handles.iter() {
+            self.handles.remove(&handle);
+            self.resources.remove("handle", handle);
+        }
+
+        if !handles.is_empty() {
+            debug!("Closed {} handles of process {}", handles.len(), pid);
+        }
+    }
+
+    fn check_spawner(&self, sender: u64, operation: &str) -> Result<(), Error> {
+        if sender != self.spawner {
+            warn!("Process {} is not allowed to {}", sender, operation);
+            return Err(Error::NotSupported);
+        }
+
+        Ok(())
+    }
+
+    fn mount(
+        &mut self,
+        request: &Request,
+        message: &mut Message,
+        sender: u64,
+    ) -> Result<NodeInfo, Error> {
+        self.check_spawner(sender, "mount filesystems")?;
+
+        let arguments = vfs::request_arguments(request, message)?;
+        let path = arguments.path()?;
+        let fs_name = arguments.fs.as_deref().ok_or(Error::InvalidArgument)?;
+        let components = mounts::split_path(path)?;
+
+        if self.mounts.find_exact(path).is_ok() {
+            return Err(Error::ObjectNameDuplicate);
+        }
+
+        let fs = Filesystem::from_port(Port::open_with_backoff(fs_name, &FS_CONNECT_BACKOFF)?)?;
+        let root = fs.attach()?;
+
+        let mount = Mount {
+            path: components.into_iter().map(String::from).collect(),
+            fs_name: String::from(fs_name),
+            fs,
+            root: root.id,
+            options: arguments.options.unwrap_or_default(),
+        };
+
+        let id = self.mounts.add(mount)?;
+
+        if let Err(err) = Audit::submit(AuditEventType::Mount, id, path) {
+            warn!("Could not audit mount: {:?}", err);
+        }
+
+        info!("Mounted '{}' on '{}'", fs_name, path);
+        Ok(root)
+    }
+
+    fn unmount(
+        &mut self,
+        request: &Request,
+        message: &mut Message,
+        sender: u64,
+    ) -> Result<(), Error> {
+        self.check_spawner(sender, "unmount filesystems")?;
+
+        let arguments = vfs::request_arguments(request, message)?;
+        let path = arguments.path()?;
+        let id = self.mounts.find_exact(path)?;
+
+        if self.handles.values().any(|opened| opened.mount == id) {
+            return Err(Error::ObjectNotReady);
+        }
+
+        let mount = self.mounts.remove(id).ok_or(Error::ObjectNotFound)?;
+        self.cache.invalidate_mount(id);
+        self.trash.remove_mount(id);
+
+        if let Err(err) = mount.fs.detach(mount.root) {
+            warn!(
+                "Could not detach instance of '{}': {:?}",
+                mount.fs_name, err
+            );
+        }
+
+        if let Err(err) = Audit::submit(AuditEventType::Unmount, id, path) {
+            warn!("Could not audit unmount: {:?}", err);
+        }
+
+        info!("Unmounted '{}'", path);
+        Ok(())
+    }
+
+    fn open(
+        &mut self,
+        request: &Request,
+        message: &mut Message,
+        sender: u64,
+    ) -> Result<(usize, NodeInfo), Error> {
+        let flags = OpenFlags::from_bits(request.flags);
+        let arguments = vfs::request_arguments(request, message)?;
+        let location = self.mounts.resolve(arguments.path()?)?;
+        let mount = self.mounts.get(location.mount)?;
+
+        let mut info = match self.mounts.lookup(&location) {
+            Ok(_) if flags.contains(OpenFlags::CREATE | OpenFlags::EXCLUSIVE) => {
+                return Err(Error::ObjectNameDuplicate);
+            }
+            Ok(info) => info,
+            Err(Error::ObjectNotFound) if flags.contains(OpenFlags::CREATE) => {
+                let (parent, name) = self.mounts.lookup_parent(&location)?;
+                mount.fs.create(parent, name, NodeKind::File)?
+            }
+            Err(err) => return Err(err),
+        };
+
+        let kind = info.kind()?;
+
+        if flags.contains(OpenFlags::TRUNCATE) {
+            if kind != NodeKind::File {
+                return Err(Error::InvalidArgument);
+            }
+
+            self.cache.invalidate(location.mount, info.id);
+            mount.fs.truncate(info.id, 0, sender)?;
+            info.size = 0;
+        }
+
+        let handle = self.next_handle;
+        self.next_handle += 1;
+
+        self.handles.insert(
+            handle,
+            OpenedNode {
+                mount: location.mount,
+                node: info.id,
+                kind,
+                read_ahead: ReadAhead::new(mount.options.read_ahead),
+                opener: sender,
+            },
+        );
+        self.resources.add(sender, "handle", handle);
+
+        debug!("Handle {} opened by process {}", handle, sender);
+        Ok((handle as usize, info))
+    }
+
+    fn close(&mut self, handle: u64) -> Result<(), Error> {
+        self.handles.remove(&handle).ok_or(Error::ObjectNotFound)?;
+        self.resources.remove("handle", handle);
+        Ok(())
+    }
+
+    /// Get an open file and the filesystem server it is on
+    fn file(&self, handle: u64) -> Result<(&OpenedNode, &Mount), Error> {
+        let opened = self.handles.get(&handle).ok_or(Error::ObjectNotFound)?;
+        if opened.kind != NodeKind::File {
+            return Err(Error::InvalidArgument);
+        }
+
+        let mount = self.mounts.get(opened.mount)?;
+        Ok((opened, mount))
+    }
+
+    fn read(&mut self, request: &Request) -> Result<Vec<u8>, Error> {
+        let opened = self
+            .handles
+            .get_mut(&request.handle)
+            .ok_or(Error::ObjectNotFound)?;
+        if opened.kind != NodeKind::File {
+            return Err(Error::InvalidArgument);
+        }
+
+        let mount = self.mounts.get(opened.mount)?;
+        self.cache.read(
+            &mount.fs,
+            (opened.mount, opened.node),
+            opened.opener,
+            request.offset,
+            request.size.min(fs::MAX_IO_SIZE as u64) as usize,
+            &mut opened.read_ahead,
+        )
+    }
+
+    fn write(&mut self, request: &Request, message: &mut Message) -> Result<usize, Error> {
+        let object = MemoryObject::from_handle(message.take_handle(1))
+            .map_err(|_| Error::InvalidArgument)?;
+
+        let (opened, mount) = self.file(request.handle)?;
+        let (mount_id, node) = (opened.mount, opened.node);
+        let written = mount.fs.write_object(
+            node,
+            request.offset,
+            object,
+            request.buffer_size as usize,
+            opened.opener,
+        );
+
+        // Even on failure: the write may be partial
+        self.cache.invalidate(mount_id, node);
+        written
+    }
+
+    fn copy_range(&mut self, request: &Request) -> Result<usize, Error> {
+        let (source, source_mount) = self.file(request.handle)?;
+        let (dest, dest_mount) = self.file(request.other_handle)?;
+        let (dest_mount_id, dest_node) = (dest.mount, dest.node);
+        let size = request.size.min(fs::MAX_IO_SIZE as u64) as usize;
+
+        let copied = if source.mount == dest.mount {
+            source_mount.fs.copy_range(
+                source.node,
+                request.offset,
+                dest.node,
+                request.other_offset,
+                size,
+                source.opener,
+            )
+        } else {
+            // Across filesystems: through vfs-server
+            source_mount
+                .fs
+                .read(source.node, request.offset, size, source.opener)
+                .and_then(|data| {
+                    // Nothing read: the destination must not be extended
+                    if data.is_empty() {
+                        return Ok(0);
+                    }
+                    dest_mount
+                        .fs
+                        .write(dest.node, request.other_offset, &data, dest.opener)
+                })
+        };
+
+        // Even on failure: the copy may be partial
+        self.cache.invalidate(dest_mount_id, dest_node);
+        copied
+    }
+
+    fn stat(&self, request: &Request, message: &mut Message) -> Result<NodeInfo, Error> {
+        let arguments = vfs::request_arguments(request, message)?;
+        let location = self.mounts.resolve(arguments.path()?)?;
+        self.mounts.lookup(&location)
+    }
+
+    fn handle_stat(&self, handle: u64) -> Result<NodeInfo, Error> {
+        let opened = self.handles.get(&handle).ok_or(Error::ObjectNotFound)?;
+        let mount = self.mounts.get(opened.mount)?;
+        mount.fs.get_attr(opened.node)
+    }
+
+    fn set_times(&self, request: &Request) -> Result<NodeInfo, Error> {
+        let opened = self
+            .handles
+            .get(&request.handle)
+            .ok_or(Error::ObjectNotFound)?;
+        let mount = self.mounts.get(opened.mount)?;
+
+        mount.fs.set_times(
+            opened.node,
+            TimeSpec::from_raw(request.offset),
+            TimeSpec::from_raw(request.size),
+            opened.opener,
+        )
+    }
+
+    fn truncate(&mut self, request: &Request) -> Result<(), Error> {
+        let (opened, mount) = self.file(request.handle)?;
+        let (mount_id, node) = (opened.mount, opened.node);
+        let result = mount.fs.truncate(node, request.size, opened.opener);
+
+        self.cache.invalidate(mount_id, node);
+        result
+    }
+
+    fn mkdir(&self, request: &Request, message: &mut Message) -> Result<NodeInfo, Error> {
+        let arguments = vfs::request_arguments(request, message)?;
+        let location = self.mounts.resolve(arguments.path()?)?;
+        let (parent, name) = self.mounts.lookup_parent(&location)?;
+
+        let mount = self.mounts.get(location.mount)?;
+        mount.fs.create(parent, name, NodeKind::Directory)
+    }
+
+    fn remove(&mut self, request: &Request, message: &mut Message) -> Result<(), Error> {
+        let arguments = vfs::request_arguments(request, message)?;
+        let location = self.mounts.resolve(arguments.path()?)?;
+        let (parent, name) = self.mounts.lookup_parent(&location)?;
+        let info = self.mounts.lookup(&location)?;
+
+        let mount = self.mounts.get(location.mount)?;
+        if let Some(expiry) = mount.options.trash_expiry {
+            return self.move_to_trash(&location, parent, name, &info, expiry);
+        }
+
+        mount.fs.remove(parent, name)?;
+
+        self.cache.invalidate(location.mount, info.id);
+        Ok(())
+    }
+
+    fn move_to_trash(
+        &mut self,
+        location: &Location,
+        parent: u64,
+        name: &str,
+        info: &NodeInfo,
+        expiry: Duration,
+    ) -> Result<(), Error> {
+        let mount = self.mounts.get(location.mount)?;
+
+        // Same rule as a removal: only empty directories
+        if info.is_directory() && !mount.fs.list(info.id)?.is_empty() {
+            return Err(Error::InvalidArgument);
+        }
+
+        let kind = NodeKind::try_from(info.kind)?;
+        let deleted = Clock::wall()?.as_nanos() as u64;
+        let expires = Clock::uptime()? + expiry;
+        let trash_dir = mount.trash_dir()?;
+
+        let id = self.trash.allocate_id();
+        mount
+            .fs
+            .rename(parent, name, trash_dir, &id.to_string(), false)?;
+
+        let path = mount
+            .path
+            .iter()
+            .map(String::as_str)
+            .chain(location.components.iter().copied())
+            .fold(String::new(), |mut path, component| {
+                path.push('/');
+                path.push_str(component);
+                path
+            });
+
+        self.trash.add(Trashed {
+            entry: TrashEntry {
+                id,
+                path,
+                kind,
+                deleted,
+            },
+            mount: location.mount,
+            components: location
+                .components
+                .iter()
+                .copied()
+                .map(String::from)
+                .collect(),
+            expires,
+        });
+
+        Ok(())
+    }
+
+    fn batch(
+        &mut self,
+        request: &Request,
+        message: &mut Message,
+        sender: u64,
+    ) -> Result<(), Error> {
+        let operations = vfs::request_batch(request, message)?;
+        batch::apply(&self.mounts, &mut self.cache, &operations, sender)
+    }
+
+    /// Encode the entries of the trash (empty if there is none)
+    fn list_trash(&self) -> Vec<u8> {
+        if self.trash.entries().next().is_none() {
+            return Vec::new();
+        }
+
+        let mut builder = KVBlockBuilder::new();
+        for entry in self.trash.entries() {
+            builder.push(TRASH_ENTRY_KEY, Value::Bytes(&entry.to_block()));
+        }
+
+        builder.build()
+    }
+
+    fn restore(&mut self, id: u64) -> Result<NodeInfo, Error> {
+        let trashed = self.trash.get(id)?;
+        let location = Location {
+            mount: trashed.mount,
+            components: trashed.components.iter().map(String::as_str).collect(),
+        };
+        let (parent, name) = self.mounts.lookup_parent(&location)?;
+
+        let mount = self.mounts.get(trashed.mount)?;
+        let trash_dir = mount.trash_dir()?;
+        mount
+            .fs
+            .rename(trash_dir, &id.to_string(), parent, name, false)?;
+        let info = mount.fs.lookup(parent, name)?;
+
+        self.trash.take(id);
+        Ok(info)
+    }
+
+    /// Purge an entry of the trash, or all of them (id 0)
+    fn purge(&mut self, id: u64) -> Result<(), Error> {
+        let ids = if id == 0 {
+            self.trash.ids()
+        } else {
+            self.trash.get(id)?;
+            vec![id]
+        };
+
+        for id in ids {
+            self.purge_entry(id)?;
+        }
+
+        Ok(())
+    }
+
+    fn purge_entry(&mut self, id: u64) -> Result<(), Error> {
+        let trashed = self.trash.get(id)?;
+        let mount_id = trashed.mount;
+        let mount = self.mounts.get(mount_id)?;
+        let trash_dir = mount.trash_dir()?;
+        let name = id.to_string();
+
+        let info = mount.fs.lookup(trash_dir, &name)?;
+        mount.fs.remove(trash_dir, &name)?;
+
+        self.trash.take(id);
+        self.cache.invalidate(mount_id, info.id);
+        Ok(())
+    }
+
+    /// Purge the expired entries of the trash, when the expiry timer fires
+    fn expire_trash(&mut self) {
+        while self.expiry_timer.receive().is_ok() {}
+        self.armed_expiry = None;
+
+        let now = match Clock::uptime() {
+            Ok(now) => now,
+            Err(err) => {
+                warn!("Could not get uptime: {:?}", err);
+                return;
+            }
+        };
+
+        for id in self.trash.expired(now) {
+            if let Err(err) = self.purge_entry(id) {
+                // Do not retry it forever
+                warn!("Could not purge trash entry {}: {:?}", id, err);
+                self.trash.take(id);
+            }
+        }
+    }
+
+    /// Arm the expiry timer on the next expiry of the trash, if it changed
+    fn arm_expiry(&mut self) {
+        let next = self.trash.next_expiry();
+        if next == self.armed_expiry {
+            return;
+        }
+
+        let result = match next {
+            Some(expires) => Clock::uptime().and_then(|now| {
+                self.expiry_timer
+                    .arm(expires.saturating_sub(now), EXPIRY_SLACK)
+            }),
+            None => self.expiry_timer.cancel(),
+        };
+
+        match result {
+            Ok(()) => self.armed_expiry = next,
+            Err(err) => warn!("Could not arm expiry timer: {:?}", err),
+        }
+    }
+
+    fn rename(&mut self, request: &Request, message: &mut Message) -> Result<(), Error> {
+        let arguments = vfs::request_arguments(request, message)?;
+        let to = arguments.to.as_deref().ok_or(Error::InvalidArgument)?;
+        let from = self.mounts.resolve(arguments.path()?)?;
+        let to = self.mounts.resolve(to)?;
+
+        if from.mount != to.mount {
+            return Err(Error::InvalidArgument);
+        }
+
+        let (parent, name) = self.mounts.lookup_parent(&from)?;
+        let (new_parent, new_name) = self.mounts.lookup_parent(&to)?;
+
+        // A replaced file is gone
+        if let Ok(replaced) = self.mounts.lookup(&to) {
+            self.cache.invalidate(to.mount, replaced.id);
+        }
+
+        let mount = self.mounts.get(from.mount)?;
+        mount.fs.rename(parent, name, new_parent, new_name, true)
+    }
+
+    fn list(&self, request: &Request, message: &mut Message) -> Result<Vec<u8>, Error> {
+        let arguments = vfs::request_arguments(request, message)?;
+        let location = self.mounts.resolve(arguments.path()?)?;
+        let info = self.mounts.lookup(&location)?;
+
+        let mount = self.mounts.get(location.mount)?;
+        let entries = mount.fs.list(info.id)?;
+
+        // The trash is hidden
+        let is_root = info.id == mount.root;
+        Ok(fs::encode_entries(
+            entries
+                .iter()
+                .filter(|entry| !is_root || entry.name != TRASH_DIR)
+                .map(|entry| (entry.name.as_str(), entry.kind)),
+        ))
+    }
+}
+
+/// Reply data in a memory object
+fn reply_with_data(data: &[u8]) -> (Result<(usize, NodeInfo), Error>, Option<MemoryObject>) {
+    match fs::copy_to_object(data) {
+        Ok(object) => (Ok((data.len(), NodeInfo::EMPTY)), Some(object)),
+        Err(err) => (Err(err), None),
+    }
 }
@@ -0,0 +1,170 @@
+use alloc::{collections::BTreeMap, string::String, vec::Vec};
+use libruntime::{
+    fs::{self, Filesystem, NodeInfo, NodeKind},
+    kobject::Error,
+    vfs::{MountOptions, TRASH_DIR},
+};
+
+/// Filesystem mounted on a path
+pub struct Mount {
+    /// Components of the mount point
+    pub path: Vec<String>,
+    /// Port name of the filesystem server
+    pub fs_name: String,
+    pub fs: Filesystem,
+    /// Root directory of the filesystem instance
+    pub root: u64,
+    pub options: MountOptions,
+}
+
+impl Mount {
+    /// Directory of the trash, created on first use
+    pub fn trash_dir(&self) -> Result<u64, Error> {
+        match self.fs.lookup(self.root, TRASH_DIR) {
+            Ok(info) => Ok(info.id),
+            Err(Error::ObjectNotFound) => Ok(self
+                .fs
+                .create(self.root, TRASH_DIR, NodeKind::Directory)?
+                .id),
+            Err(err) => Err(err),
+        }
+    }
+}
+
+/// Path resolved to a mount
+#[derive(Debug)]
+pub struct Location<'a> {
+    pub mount: u64,
+    /// Components of the path inside the mount
+    pub components: Vec<&'a str>,
+}
+
+/// Table of the mounts
+pub struct Mounts {
+    mounts: BTreeMap<u64, Mount>,
+    next_id: u64,
+}
+
+impl Mounts {
+    pub fn new() -> Self {
+        Self {
+            mounts: BTreeMap::new(),
+            next_id: 1,
+        }
+    }
+
+    pub fn get(&self, id: u64) -> Result<&Mount, Error> {
+        self.mounts.get(&id).ok_or(Error::ObjectNotFound)
+    }
+
+    /// Add a mount, fails with `Error::ObjectNameDuplicate` if the path is already a mount point
+    pub fn add(&mut self, mount: Mount) -> Result<u64, Error> {
+        if self.mounts.values().any(|other| other.path == mount.path) {
+            return Err(Error::ObjectNameDuplicate);
+        }
+
+        let id = self.next_id;
+        self.next_id += 1;
+        self.mounts.insert(id, mount);
+        Ok(id)
+    }
+
+    pub fn remove(&mut self, id: u64) -> Option<Mount> {
+        self.mounts.remove(&id)
+    }
+
+    /// Find the mount whose point is exactly the path
+    pub fn find_exact(&self, path: &str) -> Result<u64, Error> {
+        let components = split_path(path)?;
+
+        self.mounts
+            .iter()
+            .find(|(_, mount)| mount.path == components)
+            .map(|(&id, _)| id)
+            .ok_or(Error::ObjectNotFound)
+    }
+
+    /// Resolve a path to the mount with the longest matching mount point
+    ///
+    /// Fails with `Error::InvalidArgument` for paths in the trash: its name is reserved at the root of the mounts.
+    pub fn resolve<'a>(&self, path: &'a str) -> Result<Location<'a>, Error> {
+        let components = split_path(path)?;
+
+        let (&id, mount) = self
+            .mounts
+            .iter()
+            .filter(|(_, mount)| is_under(&mount.path, &components))
+            .max_by_key(|(_, mount)| mount.path.len())
+            .ok_or(Error::ObjectNotFound)?;
+
+        if components.get(mount.path.len()) == Some(&TRASH_DIR) {
+            return Err(Error::InvalidArgument);
+        }
+
+        Ok(Location {
+            mount: id,
+            components: components[mount.path.len()..].to_vec(),
+        })
+    }
+
+    /// Find the node of a location, walking its directories from the root of the mount
+    pub fn lookup(&self, location: &Location) -> Result<NodeInfo, Error> {
+        let mount = self.get(location.mount)?;
+        let mut info = mount.fs.get_attr(mount.root)?;
+
+        for component in location.components.iter() {
+            info = mount.fs.lookup(info.id, component)?;
+        }
+
+        Ok(info)
+    }
+
+    /// Find the directory holding a location, returns it with the name of the location in it
+    ///
+    /// Fails with `Error::InvalidArgument` for a mount point: it has no parent in its filesystem.
+    pub fn lookup_parent<'a>(&self, location: &Location<'a>) -> Result<(u64, &'a str), Error> {
+        let (&name, parent_components) = location
+            .components
+            .split_last()
+            .ok_or(Error::InvalidArgument)?;
+
+        let parent = self.lookup(&Location {
+            mount: location.mount,
+            components: parent_components.to_vec(),
+        })?;
+
+        if !parent.is_directory() {
+            return Err(Error::InvalidArgument);
+        }
+
+        Ok((parent.id, name))
+    }
+}
+
+/// Check if a path is the mount point or under it
+fn is_under(mount_path: &[String], components: &[&str]) -> bool {
+    mount_path.len() <= components.len()
+        && mount_path
+            .iter()
+            .zip(components)
+            .all(|(mount_component, component)| mount_component == component)
+}
+
+/// Split an absolute path in components, skipping empty and `.` ones
+pub fn split_path(path: &str) -> Result<Vec<&str>, Error> {
+    if !path.starts_with('/') {
+        return Err(Error::InvalidArgument);
+    }
+
+    let mut components = Vec::new();
+    for component in path.split('/') {
+        if component.is_empty() || component == "." {
+            continue;
+        }
+
+        fs::check_name(component)?;
+        components.push(component);
+    }
+
+    Ok(components)
+}
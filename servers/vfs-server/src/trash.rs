@@ -0,0 +1,80 @@
+// Trash of the mounts with the `trash_expiry` option
+//
+// A removed node is renamed into the `.trash` directory at the root of its mount, named after its trash id:
+// its node id does not change, so open handles and cached blocks stay valid until it is purged.
+// Entries only live in the server: on unmount they are dropped along with the filesystem instance.
+
+use core::time::Duration;
+
+use alloc::{collections::BTreeMap, string::String, vec::Vec};
+use libruntime::{kobject::Error, vfs::TrashEntry};
+
+/// Node in the trash
+pub struct Trashed {
+    pub entry: TrashEntry,
+    pub mount: u64,
+    /// Components of the original path inside the mount
+    pub components: Vec<String>,
+    /// Uptime when the node is purged
+    pub expires: Duration,
+}
+
+pub struct Trash {
+    entries: BTreeMap<u64, Trashed>,
+    next_id: u64,
+}
+
+impl Trash {
+    pub fn new() -> Self {
+        Self {
+            entries: BTreeMap::new(),
+            next_id: 1,
+        }
+    }
+
+    /// Reserve the id of a new entry
+    pub fn allocate_id(&mut self) -> u64 {
+        let id = self.next_id;
+        self.next_id += 1;
+        id
+    }
+
+    pub fn add(&mut self, trashed: Trashed) {
+        self.entries.insert(trashed.entry.id, trashed);
+    }
+
+    pub fn get(&self, id: u64) -> Result<&Trashed, Error> {
+        self.entries.get(&id).ok_or(Error::ObjectNotFound)
+    }
+
+    pub fn take(&mut self, id: u64) -> Option<Trashed> {
+        self.entries.remove(&id)
+    }
+
+    pub fn ids(&self) -> Vec<u64> {
+        self.entries.keys().copied().collect()
+    }
+
+    pub fn entries(&self) -> impl Iterator<Item = &TrashEntry> {
+        self.entries.values().map(|trashed| &trashed.entry)
+    }
+
+    /// Drop the entries of a mount, when it is unmounted
+    pub fn remove_mount(&mut self, mount: u64) {
+        self.entries.retain(|_, trashed| trashed.mount != mount);
+    }
+
+    /// Ids of the entries expired at `now` (uptime)
+    pub fn expired(&self, now: Duration) -> Vec<u64> {
+        self.entries
+            .values()
+            .filter(|trashed| trashed.expires <= now)
+            .map(|trashed| trashed.entry.id)
+            .collect()
+    }
+
+    /// Uptime of the next expiry, if any
+    pub fn next_expiry(&self) -> Option<Duration> {
+        self.entries.values().map(|trashed| trashed.expires).min()
+    }
+}
@@ -7,6 +7,8 @@ fn main() {
     let uefi = true;
 
     let mut cmd = std::process::Command::new("qemu-system-x86_64");
+    // Modern chipset: legacy interrupts are routed through the IO APIC (with ACPI overrides)
+    cmd.arg("-machine").arg("q35");
     if uefi {
         cmd.arg("-bios").arg(ovmf_prebuilt::ovmf_pure_efi());
         cmd.arg("-drive")
@@ -21,7 +23,10 @@ fn main() {
         .arg("-monitor")
         .arg("stdio")
         .arg("-serial")
-        .arg("file:serial.log");
+        .arg("file:serial.log")
+        // COM2: kernel GDB stub (kernel `gdbstub` feature)
+        .arg("-serial")
+        .arg("tcp::1234,server,nowait");
     let mut child = cmd.spawn().unwrap();
     child.wait().unwrap();
 }
@@ -0,0 +1,679 @@
+//! ABI conformance between the kernel and userland
+//!
+//! Both sides share the types of this crate, but they are built for different targets
+//! (`x86_64-unknown-none` for the kernel, `x86_64-mti_fun_os` for userland).
+//! The layouts below are pinned, and checked at compile time by each build:
+//! if a target lays out a type differently, or if a type is changed without updating this table, the build fails.
+//!
+//! Types exchanged through syscalls or messages must be listed here.
+//! When an ABI change is intended, update the table in the same change (kernel and userland must then be rebuilt together).
+
+use core::mem::{align_of, offset_of, size_of};
+
+use crate::*;
+
+/// Check the size and alignment of a type, and the offsets of its fields
+macro_rules! layout {
+    ($type:ty, size = $size:expr, align = $align:expr $(, $field:ident = $offset:expr)* $(,)?) => {
+        const _: () = {
+            assert!(
+                size_of::<$type>() == $size,
+                concat!("ABI change: size of ", stringify!($type))
+            );
+            assert!(
+                align_of::<$type>() == $align,
+                concat!("ABI change: alignment of ", stringify!($type))
+            );
+            $(
+                assert!(
+                    offset_of!($type, $field) == $offset,
+                    concat!("ABI change: offset of ", stringify!($type), "::", stringify!($field))
+                );
+            )*
+        };
+    };
+}
+
+/// Check the size of an enum, and the values of its variants
+macro_rules! values {
+    ($type:ident, size = $size:expr $(, $variant:ident = $value:expr)* $(,)?) => {
+        const _: () = {
+            assert!(
+                size_of::<$type>() == $size,
+                concat!("ABI change: size of ", stringify!($type))
+            );
+            $(
+                assert!(
+                    $type::$variant as u64 == $value,
+                    concat!("ABI change: value of ", stringify!($type), "::", stringify!($variant))
+                );
+            )*
+        };
+    };
+}
+
+values!(
+    SyscallNumber,
+    size = 8,
+    Log = 1,
+    LogRead = 2,
+    LogSetLevel = 3,
+    HandleClose = 4,
+    HandleDuplicate = 5,
+    HandleType = 6,
+    ProcessOpenSelf = 7,
+    ProcessOpen = 8,
+    ProcessCreate = 9,
+    ProcessMMap = 10,
+    ProcessMUnmap = 11,
+    ProcessMProtect = 12,
+    ProcessMName = 13,
+    ProcessMappings = 14,
+    ProcessExit = 15,
+    ProcessKill = 16,
+    ProcessSuspend = 17,
+    ProcessResume = 18,
+    ProcessInfo = 19,
+    ProcessList = 20,
+    ProcessSetName = 21,
+    ProcessGetName = 22,
+    ProcessNames = 23,
+    ProcessThreads = 24,
+    ProcessPorts = 25,
+    ThreadOpenSelf = 26,
+    ThreadOpen = 27,
+    ThreadCreate = 28,
+    ThreadExit = 29,
+    ThreadKill = 30,
+    ThreadSetPriority = 31,
+    ThreadInfo = 32,
+    ThreadList = 33,
+    ThreadSetName = 34,
+    ThreadGetName = 35,
+    ThreadNames = 36,
+    ThreadErrorInfo = 37,
+    ThreadContext = 38,
+    ThreadUpdateContext = 39,
+    ThreadResume = 40,
+    ThreadSetWatchpoint = 41,
+    ThreadClearWatchpoint = 42,
+    ThreadSchedTraceEnable = 43,
+    ThreadSchedTraceRead = 44,
+    MemoryObjectCreate = 45,
+    MemoryObjectNotifyRelease = 46,
+    PortCreate = 47,
+    PortOpen = 48,
+    PortSend = 49,
+    PortReceive = 50,
+    PortWait = 51,
+    PortInfo = 52,
+    PortList = 53,
+    PortSetFilter = 54,
+    PortPeek = 55,
+    PortDiscard = 56,
+    PortSubscribe = 57,
+    PortUnsubscribe = 58,
+    ListenerCreateProcess = 59,
+    ListenerCreateThread = 60,
+    ListenerCreatePort = 61,
+    TimerCreate = 62,
+    TimerArm = 63,
+    TimerCancel = 64,
+    TimerStats = 65,
+    InitSetup = 66,
+    MemoryStats = 67,
+    MemoryAuditFrames = 68,
+    SyscallStats = 69,
+    SystemInfo = 70,
+    DeviceList = 71,
+    GrantCreate = 72,
+    GrantMap = 73,
+    GrantRevoke = 74,
+    ProcessQueryRange = 75,
+    ThreadNewCorrelation = 76,
+    ThreadSetDeadline = 77,
+    ThreadCheckDeadline = 78,
+    ProcessMMapStack = 79,
+    ProcessMReserve = 80,
+    ProcessMCommit = 81,
+    AuditSetRules = 82,
+    AuditRead = 83,
+    AuditSubmit = 84,
+    MeasurementExtend = 85,
+    MeasurementRead = 86,
+    IdleStats = 87,
+    SystemSuspend = 88,
+    ClockSetWall = 89,
+    ProcessSetObjectLimits = 90,
+    ProcessObjectUsage = 91,
+    ReadyLatencyStats = 92,
+    TrampolineCreate = 93,
+    TrampolineSpawn = 94,
+    ClockAdvance = 95,
+    MemoryObjectRestrict = 96,
+    StringLookup = 97,
+    ThreadGetIds = 98,
+    SystemPowerOff = 99,
+    PortBlockingReceive = 100,
+    ProcessGrantIoPorts = 101,
+    ProcessSetSandbox = 102,
+    DeviceClaim = 103,
+    MemoryObjectPhysicalAddress = 104,
+    ProcessMemoryRead = 105,
+    ProcessMemoryWrite = 106,
+    ProcessHandles = 107,
+    ProcessInstallHandle = 108,
+    LogRelay = 109,
+    KernelReload = 110,
+);
+
+values!(
+    Error,
+    size = 8,
+    InvalidArgument = 1,
+    OutOfMemory = 2,
+    NotSupported = 3,
+    MemoryAccessDenied = 4,
+    ObjectNotFound = 5,
+    ObjectNameDuplicate = 6,
+    ObjectClosed = 7,
+    ObjectNotReady = 8,
+    Partial = 9,
+    DeadlineExceeded = 10,
+    QuotaExceeded = 11,
+    AddressInUse = 12,
+    AddressNotMapped = 13,
+    AddressReserved = 14,
+);
+
+values!(
+    HandleType,
+    size = 8,
+    Invalid = 0,
+    MemoryObject = 1,
+    Process = 2,
+    Thread = 3,
+    PortSender = 4,
+    PortReceiver = 5,
+    ProcessListener = 6,
+    ThreadListener = 7,
+    Timer = 8,
+    PortListener = 9,
+    Grant = 10,
+    Trampoline = 11,
+);
+
+layout!(
+    HandleInfo,
+    size = 24,
+    align = 8,
+    handle = 0,
+    r#type = 8,
+    object_id = 16,
+);
+
+layout!(
+    Message,
+    size = 128,
+    align = 8,
+    data = 0,
+    handles = 64,
+    correlation = 96,
+    deadline = 104,
+    token = 112,
+    sender_pid = 120,
+);
+
+layout!(
+    MessageHeader,
+    size = 128,
+    align = 8,
+    data = 0,
+    handle_types = 64,
+    sender_pid = 96,
+    correlation = 104,
+    deadline = 112,
+    token = 120,
+);
+
+layout!(PortFilterRange, size = 16, align = 8, start = 0, end = 8);
+
+layout!(
+    PortInfo,
+    size = 208,
+    align = 8,
+    id = 0,
+    name = 8,
+    closed = 136,
+    owner_pid = 144,
+    broadcast = 152,
+    subscriber_count = 160,
+    broadcast_dropped = 168,
+    data_capacity = 176,
+    message_queue_count = 184,
+    waiting_receiver_count = 192,
+    name_id = 200,
+);
+
+layout!(ProcessEvent, size = 16, align = 8, pid = 0, r#type = 8);
+
+values!(
+    ProcessEventType,
+    size = 8,
+    Created = 1,
+    Terminated = 2,
+    Deleted = 3,
+);
+
+layout!(
+    MemoryObjectEvent,
+    size = 16,
+    align = 8,
+    cookie = 0,
+    r#type = 8,
+);
+
+values!(MemoryObjectEventType, size = 8, Released = 1);
+
+layout!(ThreadEvent, size = 16, align = 8, tid = 0, r#type = 8);
+
+values!(
+    ThreadEventType,
+    size = 8,
+    Created = 1,
+    Error = 2,
+    Resumed = 3,
+    Terminated = 4,
+    Deleted = 5,
+);
+
+layout!(PortEvent, size = 16, align = 8, port_id = 0, r#type = 8);
+
+values!(PortEventType, size = 8, Registered = 1, Unregistered = 2);
+
+values!(LogSink, size = 8, Serial = 1, Ring = 2, Console = 3);
+
+layout!(
+    PhysStats,
+    size = 40,
+    align = 8,
+    total = 0,
+    free = 8,
+    zeroed = 16,
+    zeroed_hits = 24,
+    zeroed_misses = 32,
+);
+
+layout!(KvmStats, size = 16, align = 8, used = 0, total = 8);
+
+layout!(
+    KallocStats,
+    size = 32,
+    align = 8,
+    slabs_user = 0,
+    slabs_allocated = 8,
+    kvm_user = 16,
+    kvm_allocated = 24,
+);
+
+layout!(
+    FrameAudit,
+    size = 48,
+    align = 8,
+    used_frames = 0,
+    mapped_frames = 8,
+    list_errors = 16,
+    free_mapped_frames = 24,
+    underreferenced_frames = 32,
+    mismatched_pages = 40,
+);
+
+layout!(
+    MemoryStats,
+    size = 88,
+    align = 8,
+    phys = 0,
+    kvm = 40,
+    kalloc = 56,
+);
+
+layout!(
+    NameEntry,
+    size = 144,
+    align = 8,
+    id = 0,
+    found = 8,
+    name = 9,
+);
+
+layout!(
+    ProcessInfo,
+    size = 192,
+    align = 8,
+    pid = 0,
+    name = 8,
+    thread_count = 136,
+    mapping_count = 144,
+    handle_count = 152,
+    terminated = 160,
+    suspended = 161,
+    name_id = 168,
+    creator_pid = 176,
+    sandbox = 184,
+);
+
+layout!(SandboxFlags, size = 4, align = 4);
+
+layout!(
+    ObjectCounts,
+    size = 24,
+    align = 8,
+    ports = 0,
+    timers = 8,
+    listeners = 16,
+);
+
+layout!(
+    MappingInfo,
+    size = 64,
+    align = 8,
+    address = 0,
+    size = 8,
+    perms = 16,
+    has_memory_object = 24,
+    grows_down = 25,
+    name = 26,
+);
+
+layout!(
+    ramdisk::RamdiskTrailer,
+    size = 24,
+    align = 8,
+    magic = 0,
+    data_len = 8,
+    checksum = 16,
+);
+
+values!(
+    SchedEventType,
+    size = 8,
+    Switch = 1,
+    Sleep = 2,
+    Wake = 3,
+    DirectedYield = 4,
+);
+
+values!(
+    AuditEventType,
+    size = 8,
+    ProcessCreate = 1,
+    PortRegister = 2,
+    IoPortGrant = 3,
+    Mount = 4,
+    Unmount = 5,
+    ClockSet = 6,
+    DeviceClaim = 7,
+);
+
+layout!(AuditRule, size = 16, align = 8, events = 0, pid = 8);
+
+layout!(
+    AuditRecord,
+    size = 80,
+    align = 8,
+    seq = 0,
+    timestamp = 8,
+    r#type = 16,
+    pid = 24,
+    tid = 32,
+    object = 40,
+    name = 48,
+);
+
+layout!(
+    Measurement,
+    size = 112,
+    align = 8,
+    index = 0,
+    pid = 8,
+    digest = 16,
+    chain = 48,
+    name = 80,
+);
+
+values!(WaitCause, size = 8, None = 0, Port = 1);
+
+layout!(
+    SchedEvent,
+    size = 48,
+    align = 8,
+    timestamp = 0,
+    r#type = 8,
+    tid = 16,
+    other_tid = 24,
+    cause = 32,
+    cause_id = 40,
+);
+
+layout!(
+    SyscallLatency,
+    size = 344,
+    align = 8,
+    number = 0,
+    count = 8,
+    total_ticks = 16,
+    buckets = 24,
+);
+
+layout!(
+    ReadyLatency,
+    size = 344,
+    align = 8,
+    priority = 0,
+    count = 8,
+    total_ticks = 16,
+    buckets = 24,
+);
+
+layout!(
+    SystemInfo,
+    size = 72,
+    align = 8,
+    uptime = 0,
+    memory_total = 8,
+    memory_free = 16,
+    processes = 24,
+    threads = 32,
+    runnable = 40,
+    load = 48,
+    boot_time = 56,
+    wall_clock = 64,
+);
+
+values!(IdleMethod, size = 8, Hlt = 1, Mwait = 2);
+
+values!(SleepMode, size = 8, Test = 1, S3 = 2);
+
+layout!(
+    IdleStats,
+    size = 40,
+    align = 8,
+    method = 0,
+    mwait_hint = 8,
+    entries = 16,
+    residency_ticks = 24,
+    total_ticks = 32,
+);
+
+layout!(
+    ThreadCreationParameters,
+    size = 56,
+    align = 8,
+    process_handle = 0,
+    privileged = 8,
+    priority = 16,
+    entry_point = 24,
+    stack_top = 32,
+    arg = 40,
+    tls = 48,
+);
+
+layout!(
+    TrampolineParameters,
+    size = 32,
+    align = 8,
+    priority = 0,
+    entry_point = 8,
+    stack_top = 16,
+    tls = 24,
+);
+
+values!(
+    ThreadPriority,
+    size = 8,
+    Idle = 1,
+    Lowest = 2,
+    BelowNormal = 3,
+    Normal = 4,
+    AboveNormal = 5,
+    Highest = 6,
+    TimeCritical = 7,
+);
+
+values!(
+    ThreadState,
+    size = 8,
+    Executing = 1,
+    Ready = 2,
+    Waiting = 3,
+    Error = 4,
+    Terminated = 5,
+    Suspended = 6,
+);
+
+layout!(
+    ThreadInfo,
+    size = 184,
+    align = 8,
+    tid = 0,
+    pid = 8,
+    name = 16,
+    priority = 144,
+    privileged = 152,
+    state = 160,
+    ticks = 168,
+    name_id = 176,
+);
+
+layout!(CurrentIds, size = 16, align = 8, pid = 0, tid = 8);
+
+values!(Exception, size = 24);
+
+layout!(
+    ThreadContext,
+    size = 152,
+    align = 8,
+    rax = 0,
+    rcx = 8,
+    rdx = 16,
+    rbx = 24,
+    rsi = 32,
+    rdi = 40,
+    rsp = 48,
+    rbp = 56,
+    r8 = 64,
+    r9 = 72,
+    r10 = 80,
+    r11 = 88,
+    r12 = 96,
+    r13 = 104,
+    r14 = 112,
+    r15 = 120,
+    instruction_pointer = 128,
+    cpu_flags = 136,
+    tls = 144,
+);
+
+values!(
+    ThreadContextRegister,
+    size = 8,
+    Rax = 1,
+    Rcx = 2,
+    Rdx = 3,
+    Rbx = 4,
+    Rsi = 5,
+    Rdi = 6,
+    Rsp = 7,
+    Rbp = 8,
+    R8 = 9,
+    R9 = 10,
+    R10 = 11,
+    R11 = 12,
+    R12 = 13,
+    R13 = 14,
+    R14 = 15,
+    R15 = 16,
+    InstructionPointer = 17,
+    CpuFlags = 18,
+    TLS = 19,
+);
+
+values!(
+    WatchpointKind,
+    size = 8,
+    Write = 1,
+    ReadWrite = 2,
+    Execute = 3
+);
+
+layout!(TimerEvent, size = 16, align = 8, deadline = 0, fired = 8);
+
+layout!(
+    TimerStats,
+    size = 72,
+    align = 8,
+    ticks = 0,
+    wakeups = 8,
+    fired = 16,
+    coalesced = 24,
+    armed = 32,
+    timers = 40,
+    processing_ticks = 48,
+    processing_max_ticks = 56,
+    virtual_clock = 64,
+);
+
+layout!(
+    DeviceInfo,
+    size = 176,
+    align = 8,
+    bus = 0,
+    address = 8,
+    vendor_id = 16,
+    device_id = 18,
+    class = 20,
+    subclass = 21,
+    prog_if = 22,
+    resources = 24,
+    claimed_by = 168,
+);
+
+values!(DeviceBus, size = 8, Platform = 1, Pci = 2);
+
+layout!(
+    DeviceResource,
+    size = 24,
+    align = 8,
+    r#type = 0,
+    start = 8,
+    len = 16,
+);
+
+values!(
+    DeviceResourceType,
+    size = 8,
+    None = 0,
+    IoPort = 1,
+    Memory = 2,
+    Irq = 3,
+);
@@ -0,0 +1,144 @@
+use core::str;
+
+/// Type of audited operation
+#[repr(u64)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AuditEventType {
+    /// A process has been created: `object` is its pid, `name` its name
+    ProcessCreate = 1,
+
+    /// A named port has been created: `object` is the port id, `name` the port name
+    PortRegister,
+
+    /// Io-ports have been granted to a process: `object` is the pid of the process, `name` describes the range
+    IoPortGrant,
+
+    /// A filesystem has been mounted (submitted by servers): `name` is the mount point
+    Mount,
+
+    /// A filesystem has been unmounted (submitted by servers): `name` is the mount point
+    Unmount,
+
+    /// The wall clock has been adjusted: `object` is the new wall clock, in seconds since the Unix epoch
+    ClockSet,
+
+    /// A device has been claimed by a driver: `object` is the address of the device on its bus
+    DeviceClaim,
+}
+
+impl AuditEventType {
+    /// Get the bit of the type in `AuditRule::events`
+    pub const fn mask(self) -> u64 {
+        1 << (self as u64)
+    }
+
+    /// Test if the operation is done by servers, so that they can submit records of this type
+    ///
+    /// Other types are only recorded by the kernel, so that their records can be trusted.
+    pub const fn is_server_operation(self) -> bool {
+        matches!(self, Self::Mount | Self::Unmount)
+    }
+}
+
+/// Rule selecting the operations to audit
+///
+/// An operation is audited if at least one rule matches it.
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+pub struct AuditRule {
+    /// Mask of audited operation types (see `AuditEventType::mask`)
+    pub events: u64,
+
+    /// Only audit operations performed by this process (0 for any process)
+    pub pid: u64,
+}
+
+impl AuditRule {
+    /// Maximum number of rules
+    pub const MAX_COUNT: usize = 8;
+
+    /// Test if the rule matches an operation performed by `pid`
+    pub const fn matches(&self, r#type: AuditEventType, pid: u64) -> bool {
+        (self.events & r#type.mask()) != 0 && (self.pid == 0 || self.pid == pid)
+    }
+}
+
+/// Audit record
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+pub struct AuditRecord {
+    /// Sequence number, incremented for each record: a gap means that records have been dropped
+    pub seq: u64,
+
+    /// Nanoseconds since boot (tick granularity)
+    pub timestamp: u64,
+
+    pub r#type: AuditEventType,
+
+    /// Process performing the operation
+    pub pid: u64,
+
+    /// Thread performing the operation
+    pub tid: u64,
+
+    /// Object of the operation (depends on the type)
+    pub object: u64,
+
+    pub name: [u8; Self::NAME_LEN], // if name len == 0 then there is no name
+}
+
+impl AuditRecord {
+    pub const NAME_LEN: usize = 32;
+
+    /// Maximum number of records kept by the kernel: oldest records are dropped first
+    pub const BUFFER_SIZE: usize = 1024;
+
+    /// Build a record, truncating the name if needed
+    pub fn new(
+        seq: u64,
+        timestamp: u64,
+        r#type: AuditEventType,
+        pid: u64,
+        tid: u64,
+        object: u64,
+        name: Option<&str>,
+    ) -> Self {
+        let mut record = Self {
+            seq,
+            timestamp,
+            r#type,
+            pid,
+            tid,
+            object,
+            name: [0; Self::NAME_LEN],
+        };
+
+        if let Some(name) = name {
+            let len = name.len().min(Self::NAME_LEN);
+            record.name[..len].copy_from_slice(&name.as_bytes()[..len]);
+        }
+
+        record
+    }
+
+    /// Get the name, if any
+    pub fn name(&self) -> Option<&str> {
+        let len = self
+            .name
+            .iter()
+            .position(|&byte| byte == 0)
+            .unwrap_or(Self::NAME_LEN);
+
+        if len == 0 {
+            None
+        } else {
+            str::from_utf8(&self.name[..len]).ok()
+        }
+    }
+}
+
+impl Default for AuditRecord {
+    fn default() -> Self {
+        Self::new(0, 0, AuditEventType::ProcessCreate, 0, 0, 0, None)
+    }
+}
@@ -0,0 +1,79 @@
+/// Device information, as discovered by the kernel
+#[derive(Debug, Clone, Copy)]
+#[repr(C)]
+pub struct DeviceInfo {
+    /// Bus on which the device is attached
+    pub bus: DeviceBus,
+
+    /// Address of the device on the bus
+    ///
+    /// For PCI: `bus << 16 | device << 8 | function`
+    pub address: u64,
+
+    pub vendor_id: u16,
+    pub device_id: u16,
+    pub class: u8,
+    pub subclass: u8,
+    pub prog_if: u8,
+
+    /// Resources used by the device
+    ///
+    /// Unused slots have type `DeviceResourceType::None`
+    pub resources: [DeviceResource; Self::RESOURCE_COUNT],
+
+    /// Pid of the process which drives the device, or 0 if no one does.
+    ///
+    /// `DeviceInfo::KERNEL_OWNER` if the kernel drives it.
+    pub claimed_by: u64,
+}
+
+impl DeviceInfo {
+    pub const RESOURCE_COUNT: usize = 6;
+
+    pub const KERNEL_OWNER: u64 = u64::MAX;
+}
+
+impl Default for DeviceInfo {
+    fn default() -> Self {
+        Self {
+            bus: DeviceBus::Platform,
+            address: 0,
+            vendor_id: 0,
+            device_id: 0,
+            class: 0,
+            subclass: 0,
+            prog_if: 0,
+            resources: [DeviceResource::default(); Self::RESOURCE_COUNT],
+            claimed_by: 0,
+        }
+    }
+}
+
+/// Bus of a device
+#[repr(u64)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DeviceBus {
+    /// Legacy device at well-known location (eg: PIT, serial port)
+    Platform = 1,
+    Pci,
+}
+
+/// Resource used by a device
+#[derive(Debug, Clone, Copy, Default)]
+#[repr(C)]
+pub struct DeviceResource {
+    pub r#type: DeviceResourceType,
+    pub start: u64,
+    pub len: u64,
+}
+
+/// Type of a device resource
+#[repr(u64)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum DeviceResourceType {
+    #[default]
+    None = 0,
+    IoPort,
+    Memory,
+    Irq,
+}
@@ -10,6 +10,23 @@ pub enum Error {
     ObjectNameDuplicate,
     ObjectClosed,
     ObjectNotReady,
+    /// The operation has been processed partially, to bound the time spent in the kernel: call it again for the rest
+    Partial,
+    /// The deadline of the request has passed: the work has been shed
+    DeadlineExceeded,
+    /// The process reached its limit on the number of kernel objects of this kind
+    QuotaExceeded,
+    /// Part of the address range is already used by a mapping or a reservation
+    AddressInUse,
+    /// Part of the address range is not mapped
+    AddressNotMapped,
+    /// Part of the address range is a reservation, which has no memory to access
+    AddressReserved,
+}
+
+impl Error {
+    /// Last error code: codes received from other processes (eg: in replies) must not be above it
+    pub const LAST: Self = Self::AddressReserved;
 }
 
 pub const SUCCESS: usize = 0;
@@ -10,4 +10,28 @@ pub enum HandleType {
     PortReceiver,
     ProcessListener,
     ThreadListener,
+    Timer,
+    PortListener,
+    Grant,
+    Trampoline,
+}
+
+/// Handle opened in a process
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+pub struct HandleInfo {
+    pub handle: u64,
+    pub r#type: HandleType,
+    /// Id of the object: pid for processes, tid for threads, port id for ports, 0 for the other types
+    pub object_id: u64,
+}
+
+impl Default for HandleInfo {
+    fn default() -> Self {
+        Self {
+            handle: 0,
+            r#type: HandleType::Invalid,
+            object_id: 0,
+        }
+    }
 }
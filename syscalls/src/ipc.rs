@@ -2,6 +2,8 @@ use core::fmt::{Debug, Formatter, Result};
 
 use core::str;
 
+use crate::HandleType;
+
 /// Structure of an IPC message
 #[derive(Debug, Clone)]
 #[repr(C)]
@@ -10,7 +12,11 @@ pub struct Message {
     ///
     /// May contain type, transaction id, whatever is relevant.
     ///
+    /// By convention, the first item is the message type: it is used by port filters.
+    ///
     /// If data are bigger than 8x8 bytes, you may use shared memory to pass buffer.
+    ///
+    /// Only the first `data_capacity` items of the destination port can be used: the others must be 0.
     pub data: [u64; Self::DATA_SIZE],
 
     /// Handles to transmit from one process to another
@@ -22,11 +28,98 @@ pub struct Message {
     /// Set to invalid if no handle
     ///
     pub handles: [u64; Self::HANDLE_COUNT],
+
+    /// Correlation id of the request the message belongs to
+    ///
+    /// From the sender perspective, 0 means that the kernel stamps the message with the correlation id of the sending thread,
+    /// or with a new one if the thread has none yet.
+    ///
+    /// From the receiver perspective, the receiving thread adopts it as its current correlation id,
+    /// so that the messages it sends while handling the request carry it too.
+    pub correlation: u64,
+
+    /// Deadline of the request the message belongs to, in nanoseconds since boot (0 if none)
+    ///
+    /// It is propagated like `correlation`: 0 means that the kernel stamps the message with the deadline of the sending thread,
+    /// and the receiving thread adopts it, so that servers can shed work that can no longer meet it.
+    pub deadline: u64,
+
+    /// Idempotency token of the request (0 if none)
+    ///
+    /// It is chosen by the client and kept across retries, so that the server does not process the same request twice.
+    /// The kernel passes it unchanged.
+    pub token: u64,
+
+    /// PID of the sending process, 0 if the message comes from the kernel (eg: listeners)
+    ///
+    /// Stamped by the kernel on receive (ignored on send): receivers can trust it to identify their peer.
+    pub sender_pid: u64,
 }
 
 impl Message {
     pub const DATA_SIZE: usize = 8;
     pub const HANDLE_COUNT: usize = 4;
+
+    /// Minimal data capacity of a port: the message type
+    pub const MIN_DATA_CAPACITY: usize = 1;
+}
+
+/// Header of the next message of a port, got without receiving it
+///
+/// It lets a server check a message before accepting its handles into its handle table.
+#[derive(Debug, Clone)]
+#[repr(C)]
+pub struct MessageHeader {
+    /// User data of the message
+    pub data: [u64; Message::DATA_SIZE],
+
+    /// Types of the attached handles (`Invalid` if no handle)
+    pub handle_types: [HandleType; Message::HANDLE_COUNT],
+
+    /// PID of the sending process, 0 if the message comes from the kernel (eg: listeners)
+    pub sender_pid: u64,
+
+    /// Correlation id of the message
+    pub correlation: u64,
+
+    /// Deadline of the message, in nanoseconds since boot (0 if none)
+    pub deadline: u64,
+
+    /// Idempotency token of the message (0 if none)
+    pub token: u64,
+}
+
+impl MessageHeader {
+    /// Get the message type (first data item, by convention)
+    pub fn r#type(&self) -> u64 {
+        self.data[0]
+    }
+
+    /// Get the number of attached handles
+    pub fn handle_count(&self) -> usize {
+        self.handle_types
+            .iter()
+            .filter(|&&handle_type| handle_type != HandleType::Invalid)
+            .count()
+    }
+}
+
+/// Range of message types, used to filter messages of a port
+///
+/// Messages whose type (first data item) is in none of the ranges are routed to the alternate port.
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+pub struct PortFilterRange {
+    /// First type of the range
+    pub start: u64,
+
+    /// End of the range (excluded)
+    pub end: u64,
+}
+
+impl PortFilterRange {
+    /// Maximum number of ranges in a port filter
+    pub const MAX_COUNT: usize = 8;
 }
 
 /// Process information
@@ -35,8 +128,19 @@ pub struct PortInfo {
     pub id: u64,
     pub name: [u8; Self::NAME_LEN],
     pub closed: bool,
+    /// Pid of the process which created the port
+    pub owner_pid: u64,
+    /// Messages sent to a broadcast port are delivered to its subscribers
+    pub broadcast: bool,
+    pub subscriber_count: usize,
+    /// Number of broadcast messages missed because the queue of this port was full
+    pub broadcast_dropped: usize,
+    /// Number of data items usable in messages of this port
+    pub data_capacity: usize,
     pub message_queue_count: usize,
     pub waiting_receiver_count: usize,
+    /// Id of the name in the kernel strings table (0 if no name), stable until the port is closed
+    pub name_id: u64,
 }
 
 impl PortInfo {
@@ -52,8 +156,14 @@ impl Debug for PortInfo {
                 &format_args!("{}", unsafe { str::from_utf8_unchecked(&self.name) }),
             )
             .field("closed", &self.closed)
+            .field("owner_pid", &self.owner_pid)
+            .field("broadcast", &self.broadcast)
+            .field("subscriber_count", &self.subscriber_count)
+            .field("broadcast_dropped", &self.broadcast_dropped)
+            .field("data_capacity", &self.data_capacity)
             .field("message_queue_count", &self.message_queue_count)
             .field("waiting_receiver_count", &self.waiting_receiver_count)
+            .field("name_id", &self.name_id)
             .finish()
     }
 }
@@ -1,28 +1,54 @@
 #![no_std]
 
+mod abi;
+mod audit;
+mod device;
 mod error;
 mod handle;
 mod ipc;
 mod listener;
+pub mod log_record;
+mod logging;
+mod measurement;
 mod memory;
+mod name;
 mod permissions;
+mod power;
 mod process;
+pub mod ramdisk;
+mod sched_trace;
+pub mod sha256;
+mod stats;
 mod thread;
+mod timer;
 
+pub use audit::*;
+pub use device::*;
 pub use error::*;
 pub use handle::*;
 pub use ipc::*;
 pub use listener::*;
+pub use logging::*;
+pub use measurement::*;
 pub use memory::*;
+pub use name::*;
 pub use permissions::*;
+pub use power::*;
 pub use process::*;
+pub use sched_trace::*;
+pub use stats::*;
 pub use thread::*;
+pub use timer::*;
 
 /// List of syscall numbers
+///
+/// Values are part of the ABI: they are pinned in `abi.rs`
 #[repr(usize)]
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
 pub enum SyscallNumber {
     Log = 1,
+    LogRead,
+    LogSetLevel,
 
     HandleClose,
     HandleDuplicate,
@@ -34,12 +60,19 @@ pub enum SyscallNumber {
     ProcessMMap,
     ProcessMUnmap,
     ProcessMProtect,
+    ProcessMName,
+    ProcessMappings,
     ProcessExit,
     ProcessKill,
+    ProcessSuspend,
+    ProcessResume,
     ProcessInfo,
     ProcessList,
     ProcessSetName,
     ProcessGetName,
+    ProcessNames,
+    ProcessThreads,
+    ProcessPorts,
 
     ThreadOpenSelf,
     ThreadOpen,
@@ -51,12 +84,18 @@ pub enum SyscallNumber {
     ThreadList,
     ThreadSetName,
     ThreadGetName,
+    ThreadNames,
     ThreadErrorInfo,
     ThreadContext,
     ThreadUpdateContext,
     ThreadResume,
+    ThreadSetWatchpoint,
+    ThreadClearWatchpoint,
+    ThreadSchedTraceEnable,
+    ThreadSchedTraceRead,
 
     MemoryObjectCreate,
+    MemoryObjectNotifyRelease,
 
     PortCreate,
     PortOpen,
@@ -65,11 +104,69 @@ pub enum SyscallNumber {
     PortWait,
     PortInfo,
     PortList,
+    PortSetFilter,
+    PortPeek,
+    PortDiscard,
+    PortSubscribe,
+    PortUnsubscribe,
 
     ListenerCreateProcess,
     ListenerCreateThread,
+    ListenerCreatePort,
+
+    TimerCreate,
+    TimerArm,
+    TimerCancel,
+    TimerStats,
 
     InitSetup,
 
     MemoryStats,
+    MemoryAuditFrames,
+
+    SyscallStats,
+    SystemInfo,
+
+    DeviceList,
+
+    GrantCreate,
+    GrantMap,
+    GrantRevoke,
+
+    ProcessQueryRange,
+    ThreadNewCorrelation,
+    ThreadSetDeadline,
+    ThreadCheckDeadline,
+    ProcessMMapStack,
+    ProcessMReserve,
+    ProcessMCommit,
+    AuditSetRules,
+    AuditRead,
+    AuditSubmit,
+    MeasurementExtend,
+    MeasurementRead,
+    IdleStats,
+    SystemSuspend,
+    ClockSetWall,
+    ProcessSetObjectLimits,
+    ProcessObjectUsage,
+    ReadyLatencyStats,
+    TrampolineCreate,
+    TrampolineSpawn,
+    ClockAdvance,
+    MemoryObjectRestrict,
+    StringLookup,
+    ThreadGetIds,
+    SystemPowerOff,
+    PortBlockingReceive,
+    ProcessGrantIoPorts,
+    ProcessSetSandbox,
+    DeviceClaim,
+    MemoryObjectPhysicalAddress,
+    ProcessMemoryRead,
+    ProcessMemoryWrite,
+    ProcessHandles,
+    ProcessInstallHandle,
+    LogRelay,
+    KernelReload,
 }
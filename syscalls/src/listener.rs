@@ -23,6 +23,25 @@ pub enum ProcessEventType {
     Deleted,
 }
 
+/// Memory object event
+#[repr(C)]
+#[derive(Debug, Clone)]
+pub struct MemoryObjectEvent {
+    /// Value given by the subscriber, to identify the object
+    pub cookie: u64,
+
+    /// Type of event
+    pub r#type: MemoryObjectEventType,
+}
+
+/// Memory object event type
+#[repr(u64)]
+#[derive(Debug, PartialEq, Eq, Hash, Clone, Copy)]
+pub enum MemoryObjectEventType {
+    /// Memory object has been released: all handles have been closed and it is not mapped anymore.
+    Released = 1,
+}
+
 /// Process event
 #[repr(C)]
 #[derive(Debug, Clone)]
@@ -53,3 +72,27 @@ pub enum ThreadEventType {
     /// Thread has been deleted: it does not exist anymore in the system
     Deleted,
 }
+
+/// Port event
+#[repr(C)]
+#[derive(Debug, Clone)]
+pub struct PortEvent {
+    /// Id of the named port this event occurs on
+    pub port_id: u64,
+
+    /// Type of event
+    pub r#type: PortEventType,
+}
+
+/// Port event type
+#[repr(u64)]
+#[derive(Debug, PartialEq, Eq, Hash, Clone, Copy)]
+pub enum PortEventType {
+    /// A port has been created with a matching name.
+    ///
+    /// Also sent on listener creation for each matching port that already exists.
+    Registered = 1,
+
+    /// A port with a matching name has been closed: its name is free again.
+    Unregistered,
+}
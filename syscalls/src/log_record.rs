@@ -0,0 +1,339 @@
+//! Binary format of the kernel log, written on the serial port with the kernel `binary-log` feature.
+//!
+//! Binary entries can be mixed with text: they start with a magic byte which never appears in UTF-8 text.
+//! Integers are LEB128 encoded.
+//!
+//! Targets are interned: a target definition is written before the first record which uses it.
+//! - target definition: `TARGET_MAGIC`, id, name length, name
+//! - record: `RECORD_MAGIC`, timestamp (ns), pid, tid, level, target id, message, `0`
+//! - formatted record: `FORMAT_RECORD_MAGIC`, timestamp (ns), pid, tid, level, target id, format id, args count,
+//!   then each arg, `0` terminated
+//!
+//! Format strings are interned too, but never defined in the stream: their ids (`format_id`) are resolved by the
+//! decoder with the table generated at build time (`cargo xtask build` writes `target/image/log-formats`).
+//! Only format strings with implicit positions (`{}`, `{:x}`, ..., see `is_internable`) are sent as formatted records,
+//! the others are sent as records.
+//!
+//! The message and the args are already formatted: they are written as they are formatted, without buffering.
+
+/// First byte of a record
+pub const RECORD_MAGIC: u8 = 0xFE;
+
+/// First byte of a target definition
+pub const TARGET_MAGIC: u8 = 0xFF;
+
+/// First byte of a formatted record
+pub const FORMAT_RECORD_MAGIC: u8 = 0xFD;
+
+/// Header of a record, followed by the message
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RecordHeader {
+    /// Nanoseconds since boot (tick granularity)
+    pub timestamp: u64,
+    /// Process executing when the record was emitted (0 if none)
+    pub pid: u64,
+    /// Thread executing when the record was emitted (0 if none)
+    pub tid: u64,
+    /// Same values as `log::Level`: Error = 1 to Trace = 5
+    pub level: u8,
+    /// Id of the target, defined by a previous target definition
+    pub target_id: u32,
+}
+
+impl RecordHeader {
+    /// Write the header. The message and the terminating `0` must follow.
+    pub fn encode(&self, out: &mut impl FnMut(u8)) {
+        self.encode_with(RECORD_MAGIC, out);
+    }
+
+    /// Write the header of a formatted record. The args, each `0` terminated, must follow.
+    pub fn encode_formatted(&self, format_id: u32, args_count: usize, out: &mut impl FnMut(u8)) {
+        self.encode_with(FORMAT_RECORD_MAGIC, out);
+        write_varint(format_id as u64, out);
+        write_varint(args_count as u64, out);
+    }
+
+    fn encode_with(&self, magic: u8, out: &mut impl FnMut(u8)) {
+        out(magic);
+        write_varint(self.timestamp, out);
+        write_varint(self.pid, out);
+        write_varint(self.tid, out);
+        out(self.level);
+        write_varint(self.target_id as u64, out);
+    }
+}
+
+/// Write a target definition
+pub fn encode_target(id: u32, name: &str, out: &mut impl FnMut(u8)) {
+    out(TARGET_MAGIC);
+    write_varint(id as u64, out);
+    write_varint(name.len() as u64, out);
+    for &byte in name.as_bytes() {
+        out(byte);
+    }
+}
+
+/// Get the id of a target (32 bits FNV-1a of its name)
+pub fn target_id(name: &str) -> u32 {
+    fnv1a(name)
+}
+
+/// Get the id of a format string (32 bits FNV-1a of its value, escapes resolved)
+pub const fn format_id(format: &str) -> u32 {
+    fnv1a(format)
+}
+
+/// Check if a format string can be sent as a formatted record: its placeholders have implicit positions
+/// (`{}`, `{:?}`, `{:#x}`, ...), without width or precision args, and match the number of args
+pub const fn is_internable(format: &str, args_count: usize) -> bool {
+    let bytes = format.as_bytes();
+    let mut index = 0;
+    let mut count = 0;
+
+    while index < bytes.len() {
+        match bytes[index] {
+            b'{' if index + 1 < bytes.len() && bytes[index + 1] == b'{' => index += 2,
+            b'}' if index + 1 < bytes.len() && bytes[index + 1] == b'}' => index += 2,
+            b'{' => {
+                index += 1;
+
+                // Inline (`{name}`) or explicit (`{0}`) positions
+                if index < bytes.len() && bytes[index] != b':' && bytes[index] != b'}' {
+                    return false;
+                }
+
+                while index < bytes.len() && bytes[index] != b'}' {
+                    // Width or precision taken from the args
+                    if bytes[index] == b'$' || bytes[index] == b'*' {
+                        return false;
+                    }
+                    index += 1;
+                }
+
+                index += 1;
+                count += 1;
+            }
+            _ => index += 1,
+        }
+    }
+
+    count == args_count
+}
+
+const fn fnv1a(value: &str) -> u32 {
+    let bytes = value.as_bytes();
+    let mut hash: u32 = 0x811c9dc5;
+    let mut index = 0;
+
+    while index < bytes.len() {
+        hash ^= bytes[index] as u32;
+        hash = hash.wrapping_mul(0x01000193);
+        index += 1;
+    }
+
+    hash
+}
+
+/// Get the name of a level
+pub fn level_name(level: u8) -> &'static str {
+    match level {
+        1 => "ERROR",
+        2 => "WARN",
+        3 => "INFO",
+        4 => "DEBUG",
+        5 => "TRACE",
+        _ => "?",
+    }
+}
+
+fn write_varint(mut value: u64, out: &mut impl FnMut(u8)) {
+    loop {
+        let byte = (value & 0x7F) as u8;
+        value >>= 7;
+
+        if value == 0 {
+            out(byte);
+            return;
+        }
+
+        out(byte | 0x80);
+    }
+}
+
+/// Entry of a log stream
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Entry<'a> {
+    /// Text between binary entries
+    Text(&'a [u8]),
+    /// Target definition
+    Target { id: u32, name: &'a [u8] },
+    /// Record
+    Record {
+        header: RecordHeader,
+        message: &'a [u8],
+    },
+    /// Formatted record
+    Formatted {
+        header: RecordHeader,
+        format_id: u32,
+        args: Args<'a>,
+    },
+}
+
+/// Args of a formatted record
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Args<'a> {
+    /// Args, each `0` terminated
+    data: &'a [u8],
+}
+
+impl<'a> Args<'a> {
+    pub fn iter(&self) -> impl Iterator<Item = &'a [u8]> {
+        let data = self.data;
+        data.split(|&byte| byte == 0).take(self.len())
+    }
+
+    pub fn len(&self) -> usize {
+        self.data.iter().filter(|&&byte| byte == 0).count()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.data.is_empty()
+    }
+}
+
+/// Decode a log stream
+///
+/// Malformed or truncated binary entries are returned as text.
+#[derive(Debug)]
+pub struct Decoder<'a> {
+    data: &'a [u8],
+}
+
+impl<'a> Decoder<'a> {
+    pub fn new(data: &'a [u8]) -> Self {
+        Self { data }
+    }
+
+    fn decode_binary(&self) -> Option<(Entry<'a>, usize)> {
+        let mut reader = Reader {
+            data: self.data,
+            offset: 1,
+        };
+
+        let entry = match self.data[0] {
+            TARGET_MAGIC => {
+                let id = reader.varint()? as u32;
+                let len = reader.varint()? as usize;
+                let name = reader.bytes(len)?;
+                Entry::Target { id, name }
+            }
+            RECORD_MAGIC => {
+                let header = reader.header()?;
+
+                let len = reader.data[reader.offset..]
+                    .iter()
+                    .position(|&byte| byte == 0)?;
+                let message = reader.bytes(len)?;
+                reader.byte()?;
+
+                Entry::Record { header, message }
+            }
+            FORMAT_RECORD_MAGIC => {
+                let header = reader.header()?;
+                let format_id = reader.varint()? as u32;
+                let count = reader.varint()?;
+
+                let start = reader.offset;
+                for _ in 0..count {
+                    let len = reader.data[reader.offset..]
+                        .iter()
+                        .position(|&byte| byte == 0)?;
+                    reader.bytes(len + 1)?;
+                }
+
+                Entry::Formatted {
+                    header,
+                    format_id,
+                    args: Args {
+                        data: &reader.data[start..reader.offset],
+                    },
+                }
+            }
+            _ => return None,
+        };
+
+        Some((entry, reader.offset))
+    }
+}
+
+impl<'a> Iterator for Decoder<'a> {
+    type Item = Entry<'a>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.data.is_empty() {
+            return None;
+        }
+
+        if let Some((entry, len)) = self.decode_binary() {
+            self.data = &self.data[len..];
+            return Some(entry);
+        }
+
+        // Text up to the next binary entry (at least one byte, to progress over malformed entries)
+        let len = self.data[1..]
+            .iter()
+            .position(|&byte| {
+                byte == RECORD_MAGIC || byte == TARGET_MAGIC || byte == FORMAT_RECORD_MAGIC
+            })
+            .map_or(self.data.len(), |index| index + 1);
+
+        let (text, remaining) = self.data.split_at(len);
+        self.data = remaining;
+        Some(Entry::Text(text))
+    }
+}
+
+struct Reader<'a> {
+    data: &'a [u8],
+    offset: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn byte(&mut self) -> Option<u8> {
+        let value = *self.data.get(self.offset)?;
+        self.offset += 1;
+        Some(value)
+    }
+
+    fn bytes(&mut self, len: usize) -> Option<&'a [u8]> {
+        let value = self.data.get(self.offset..self.offset.checked_add(len)?)?;
+        self.offset += len;
+        Some(value)
+    }
+
+    fn header(&mut self) -> Option<RecordHeader> {
+        Some(RecordHeader {
+            timestamp: self.varint()?,
+            pid: self.varint()?,
+            tid: self.varint()?,
+            level: self.byte()?,
+            target_id: self.varint()? as u32,
+        })
+    }
+
+    fn varint(&mut self) -> Option<u64> {
+        let mut value = 0u64;
+
+        for shift in (0..64).step_by(7) {
+            let byte = self.byte()?;
+            value |= ((byte & 0x7F) as u64) << shift;
+
+            if byte & 0x80 == 0 {
+                return Some(value);
+            }
+        }
+
+        None
+    }
+}
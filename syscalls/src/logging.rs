@@ -0,0 +1,11 @@
+/// Destination of the kernel log records
+#[repr(usize)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LogSink {
+    /// Serial port (COM1)
+    Serial = 1,
+    /// In-memory ring of the last records, read with `LogRead`
+    Ring,
+    /// Text console on the bootloader framebuffer
+    Console,
+}
@@ -0,0 +1,83 @@
+use core::str;
+
+use crate::sha256::{self, DIGEST_SIZE};
+
+/// Entry of the measurement log
+///
+/// The kernel computes the digest of each measured component (the ramdisk, then each loaded binary),
+/// and extends the chain with it: `chain = sha256(previous chain || digest)`, starting from zeroes.
+/// The last chain value summarizes everything that has been measured since boot.
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+pub struct Measurement {
+    /// Position in the log, starting at 0
+    pub index: u64,
+
+    /// Process which requested the measurement (0 for the kernel)
+    pub pid: u64,
+
+    /// Digest of the measured component
+    pub digest: [u8; DIGEST_SIZE],
+
+    /// Value of the chain after this measurement
+    pub chain: [u8; DIGEST_SIZE],
+
+    pub name: [u8; Self::NAME_LEN], // if name len == 0 then there is no name
+}
+
+impl Measurement {
+    pub const NAME_LEN: usize = 32;
+
+    /// Maximum number of measurements: the log is append-only, measurements fail once it is full
+    pub const MAX_COUNT: usize = 256;
+
+    /// Initial value of the chain
+    pub const INITIAL_CHAIN: [u8; DIGEST_SIZE] = [0; DIGEST_SIZE];
+
+    /// Build an entry, truncating the name if needed
+    pub fn new(
+        index: u64,
+        pid: u64,
+        digest: [u8; DIGEST_SIZE],
+        chain: [u8; DIGEST_SIZE],
+        name: &str,
+    ) -> Self {
+        let mut measurement = Self {
+            index,
+            pid,
+            digest,
+            chain,
+            name: [0; Self::NAME_LEN],
+        };
+
+        let len = name.len().min(Self::NAME_LEN);
+        measurement.name[..len].copy_from_slice(&name.as_bytes()[..len]);
+
+        measurement
+    }
+
+    /// Compute the chain value after measuring `digest`
+    pub fn extend(chain: &[u8; DIGEST_SIZE], digest: &[u8; DIGEST_SIZE]) -> [u8; DIGEST_SIZE] {
+        let mut hasher = sha256::Sha256::new();
+        hasher.update(chain);
+        hasher.update(digest);
+        hasher.finalize()
+    }
+
+    /// Get the name of the measured component
+    pub fn name(&self) -> &str {
+        let len = self
+            .name
+            .iter()
+            .position(|&byte| byte == 0)
+            .unwrap_or(Self::NAME_LEN);
+
+        str::from_utf8(&self.name[..len]).unwrap_or("")
+    }
+}
+
+impl Default for Measurement {
+    fn default() -> Self {
+        Self::new(0, 0, [0; DIGEST_SIZE], Self::INITIAL_CHAIN, "")
+    }
+}
@@ -1,8 +1,32 @@
+use bitflags::bitflags;
+
+bitflags! {
+  /// Options of memory object creation
+  #[derive(PartialEq, Eq, Debug, Clone, Copy)]
+  pub struct MemoryObjectFlags: u64 {
+      /// Default: the content is zeroed
+      const NONE = 0;
+
+      /// Do not zero the content, for objects that will be fully overwritten (eg: ELF segment staging)
+      ///
+      /// The content is unspecified: it may contain data of freed memory, including of other processes.
+      /// Reserved to privileged threads: others get `NotSupported`.
+      /// Frames zeroed at idle time are kept for zeroed objects.
+      const UNINITIALIZED = 1 << 0;
+  }
+}
+
 #[derive(Debug)]
 #[repr(C)]
 pub struct PhysStats {
     pub total: usize,
     pub free: usize,
+    /// Part of free memory which has already been zeroed at idle time
+    pub zeroed: usize,
+    /// Zeroed allocations served by a pre-zeroed frame
+    pub zeroed_hits: usize,
+    /// Zeroed allocations which had to zero the frame inline
+    pub zeroed_misses: usize,
 }
 
 #[derive(Debug)]
@@ -31,6 +55,39 @@ pub struct KallocStats {
     pub kvm_allocated: usize,
 }
 
+/// Result of the physical frames reference counts audit
+#[derive(Debug, Default)]
+#[repr(C)]
+pub struct FrameAudit {
+    /// Number of frames currently allocated
+    pub used_frames: usize,
+
+    /// Number of distinct frames mapped in user address spaces
+    pub mapped_frames: usize,
+
+    /// Frames in the wrong allocator list for their reference count
+    pub list_errors: usize,
+
+    /// Mapped frames which are free in the allocator (use after free)
+    pub free_mapped_frames: usize,
+
+    /// Mapped frames with less references than found holders (will be double freed)
+    pub underreferenced_frames: usize,
+
+    /// Mapped pages whose page table entry does not match the memory object frame
+    pub mismatched_pages: usize,
+}
+
+impl FrameAudit {
+    /// Check if the audit found no error
+    pub fn is_ok(&self) -> bool {
+        self.list_errors == 0
+            && self.free_mapped_frames == 0
+            && self.underreferenced_frames == 0
+            && self.mismatched_pages == 0
+    }
+}
+
 #[derive(Debug)]
 #[repr(C)]
 pub struct MemoryStats {
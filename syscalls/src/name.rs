@@ -0,0 +1,66 @@
+use core::fmt::{Debug, Formatter, Result};
+
+use core::str;
+
+/// Result of a batched name lookup (threads or processes)
+#[repr(C)]
+#[derive(Clone)]
+pub struct NameEntry {
+    pub id: u64,
+    pub found: bool,
+    pub name: [u8; Self::NAME_LEN], // if name len == 0 then there is no name
+}
+
+impl NameEntry {
+    pub const NAME_LEN: usize = 128;
+
+    /// Build an entry, truncating the name if needed
+    pub fn new(id: u64, name: Option<&str>) -> Self {
+        let mut entry = Self {
+            id,
+            found: true,
+            name: [0; Self::NAME_LEN],
+        };
+
+        if let Some(name) = name {
+            let len = name.len().min(Self::NAME_LEN);
+            entry.name[..len].copy_from_slice(&name.as_bytes()[..len]);
+        }
+
+        entry
+    }
+
+    /// Build an entry for an id which does not exist
+    pub fn not_found(id: u64) -> Self {
+        Self {
+            id,
+            found: false,
+            name: [0; Self::NAME_LEN],
+        }
+    }
+
+    /// Get the name, if any
+    pub fn name(&self) -> Option<&str> {
+        let len = self
+            .name
+            .iter()
+            .position(|&byte| byte == 0)
+            .unwrap_or(Self::NAME_LEN);
+
+        if len == 0 {
+            None
+        } else {
+            str::from_utf8(&self.name[..len]).ok()
+        }
+    }
+}
+
+impl Debug for NameEntry {
+    fn fmt(&self, f: &mut Formatter<'_>) -> Result {
+        f.debug_struct("NameEntry")
+            .field("id", &self.id)
+            .field("found", &self.found)
+            .field("name", &format_args!("{}", self.name().unwrap_or("<None>")))
+            .finish()
+    }
+}
@@ -0,0 +1,10 @@
+/// Sleep state requested by `SystemSuspend`
+#[repr(u64)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SleepMode {
+    /// Run the whole suspend/resume path (save and quiesce devices, restore them), without entering a sleep state
+    Test = 1,
+
+    /// ACPI S3 (suspend-to-RAM)
+    S3,
+}
@@ -2,6 +2,8 @@ use core::fmt::{Debug, Formatter, Result};
 
 use core::str;
 
+use crate::Permissions;
+
 /// Process information
 #[repr(C)]
 pub struct ProcessInfo {
@@ -11,6 +13,13 @@ pub struct ProcessInfo {
     pub mapping_count: usize,
     pub handle_count: usize,
     pub terminated: bool,
+    pub suspended: bool,
+    /// Id of the name in the kernel strings table: a rename gives a new id
+    pub name_id: u64,
+    /// Pid of the process which created it (0 for init)
+    pub creator_pid: u64,
+    /// Sandbox rights of the process
+    pub sandbox: SandboxFlags,
 }
 
 impl ProcessInfo {
@@ -29,6 +38,166 @@ impl Debug for ProcessInfo {
             .field("mapping_count", &self.mapping_count)
             .field("handle_count", &self.handle_count)
             .field("terminated", &self.terminated)
+            .field("suspended", &self.suspended)
+            .field("name_id", &self.name_id)
+            .field("creator_pid", &self.creator_pid)
+            .field("sandbox", &self.sandbox)
             .finish()
     }
 }
+
+/// Maximum size of the range processed by one call to munmap, mprotect or mname
+///
+/// Larger ranges are processed from their start up to this size, then the call fails with `Error::Partial`:
+/// the caller must call it again with the rest of the range.
+/// This bounds the time spent in the kernel with interrupts disabled.
+pub const MAPPING_BUDGET_SIZE: usize = 1024 * 4096;
+
+/// Maximum size of one read or write of the memory of another process
+pub const PROCESS_MEMORY_MAX_SIZE: usize = 64 * 4096;
+
+/// Mapping in a process address space
+#[repr(C)]
+#[derive(Clone, Copy)]
+pub struct MappingInfo {
+    pub address: usize,
+    pub size: usize,
+    pub perms: Permissions,
+    /// false if the mapping is a reservation only
+    pub has_memory_object: bool,
+    /// Reservation of a stack, committed by the kernel when the stack grows
+    pub grows_down: bool,
+    pub name: [u8; Self::NAME_LEN], // if name len == 0 then there is no name
+}
+
+impl MappingInfo {
+    pub const NAME_LEN: usize = 32;
+
+    /// Build a mapping info, truncating the name if needed
+    pub fn new(
+        address: usize,
+        size: usize,
+        perms: Permissions,
+        has_memory_object: bool,
+        grows_down: bool,
+        name: Option<&str>,
+    ) -> Self {
+        let mut info = Self {
+            address,
+            size,
+            perms,
+            has_memory_object,
+            grows_down,
+            name: [0; Self::NAME_LEN],
+        };
+
+        if let Some(name) = name {
+            let len = name.len().min(Self::NAME_LEN);
+            info.name[..len].copy_from_slice(&name.as_bytes()[..len]);
+        }
+
+        info
+    }
+
+    /// Get the name, if any
+    pub fn name(&self) -> Option<&str> {
+        let len = self
+            .name
+            .iter()
+            .position(|&byte| byte == 0)
+            .unwrap_or(Self::NAME_LEN);
+
+        if len == 0 {
+            None
+        } else {
+            str::from_utf8(&self.name[..len]).ok()
+        }
+    }
+}
+
+impl Default for MappingInfo {
+    fn default() -> Self {
+        Self::new(0, 0, Permissions::NONE, false, false, None)
+    }
+}
+
+impl Debug for MappingInfo {
+    fn fmt(&self, f: &mut Formatter<'_>) -> Result {
+        f.debug_struct("MappingInfo")
+            .field("address", &format_args!("0x{:016X}", self.address))
+            .field("size", &self.size)
+            .field("perms", &self.perms)
+            .field("has_memory_object", &self.has_memory_object)
+            .field("grows_down", &self.grows_down)
+            .field("name", &format_args!("{}", self.name().unwrap_or("<None>")))
+            .finish()
+    }
+}
+
+/// Sandbox rights of a process
+///
+/// Init has them all. Other processes get none when they are created: their spawner sets them,
+/// from the manifest of their binary, within its own rights.
+#[repr(transparent)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SandboxFlags(u32);
+
+impl SandboxFlags {
+    /// No special need
+    pub const NONE: Self = Self(0);
+    /// The process creates processes
+    pub const PROCESS_CREATE: Self = Self(1 << 0);
+    /// The process supervises threads of other processes (context, watchpoints, resume), suspends them, and accesses their memory
+    pub const DEBUG: Self = Self(1 << 1);
+    /// The process drives devices (claim, DMA)
+    pub const DEVICE: Self = Self(1 << 2);
+    /// All needs
+    pub const ALL: Self = Self(Self::PROCESS_CREATE.0 | Self::DEBUG.0 | Self::DEVICE.0);
+
+    /// Build flags from their raw value
+    pub const fn from_bits(bits: u32) -> Self {
+        Self(bits)
+    }
+
+    /// Get the raw value of the flags
+    pub const fn bits(&self) -> u32 {
+        self.0
+    }
+
+    /// Merge two sets of flags
+    pub const fn union(self, other: Self) -> Self {
+        Self(self.0 | other.0)
+    }
+
+    /// Check if all flags of `other` are set in `self`
+    pub const fn contains(&self, other: Self) -> bool {
+        (self.0 & other.0) == other.0
+    }
+
+    /// Get the flags of `self` which are not in `other`
+    pub const fn difference(self, other: Self) -> Self {
+        Self(self.0 & !other.0)
+    }
+}
+
+/// Number of kernel objects, per kind: used for the quota of a process and its usage
+///
+/// Ports are counted once per pair of receiver/sender. Listeners count process, thread and port listeners.
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ObjectCounts {
+    pub ports: usize,
+    pub timers: usize,
+    pub listeners: usize,
+}
+
+impl ObjectCounts {
+    /// Limits of a new process
+    ///
+    /// Generous enough so that no well-behaved process hits them: they only stop runaway loops.
+    pub const DEFAULT_LIMITS: Self = Self {
+        ports: 4096,
+        timers: 1024,
+        listeners: 256,
+    };
+}
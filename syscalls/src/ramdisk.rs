@@ -0,0 +1,116 @@
+//! Layout of the ramdisk passed by the bootloader.
+//!
+//! The ramdisk is the init binary, followed by the boot parameters, followed by a trailer which allows the kernel to check its integrity.
+//!
+//! The boot parameters are read by init (the kernel passes them along with the binary):
+//! a command line of space separated `key=value` pairs, followed by a `BootParamsFooter`.
+
+use core::mem::size_of;
+
+/// Trailer appended at the end of the ramdisk
+#[derive(Debug, Clone, Copy)]
+#[repr(C)]
+pub struct RamdiskTrailer {
+    /// Must be `RamdiskTrailer::MAGIC`
+    pub magic: u64,
+
+    /// Size of the data before the trailer
+    pub data_len: u64,
+
+    /// Checksum of the data before the trailer
+    pub checksum: u64,
+}
+
+impl RamdiskTrailer {
+    pub const MAGIC: u64 = u64::from_le_bytes(*b"MTIRDSK\0");
+
+    pub const SIZE: usize = size_of::<Self>();
+
+    /// Build the trailer of the given data
+    pub fn new(data: &[u8]) -> Self {
+        Self {
+            magic: Self::MAGIC,
+            data_len: data.len() as u64,
+            checksum: checksum(data),
+        }
+    }
+
+    /// Get the trailer bytes, to be appended after the data
+    pub fn to_bytes(&self) -> [u8; Self::SIZE] {
+        let mut bytes = [0u8; Self::SIZE];
+        bytes[0..8].copy_from_slice(&self.magic.to_le_bytes());
+        bytes[8..16].copy_from_slice(&self.data_len.to_le_bytes());
+        bytes[16..24].copy_from_slice(&self.checksum.to_le_bytes());
+        bytes
+    }
+
+    /// Read the trailer at the end of the ramdisk
+    ///
+    /// Returns None if the ramdisk is too small to contain it
+    pub fn from_ramdisk(ramdisk: &[u8]) -> Option<Self> {
+        let offset = ramdisk.len().checked_sub(Self::SIZE)?;
+        let bytes = &ramdisk[offset..];
+        let read = |index: usize| u64::from_le_bytes(bytes[index..index + 8].try_into().unwrap());
+
+        Some(Self {
+            magic: read(0),
+            data_len: read(8),
+            checksum: read(16),
+        })
+    }
+}
+
+/// Footer of the boot parameters, right before the ramdisk trailer
+#[derive(Debug, Clone, Copy)]
+#[repr(C)]
+pub struct BootParamsFooter {
+    /// Must be `BootParamsFooter::MAGIC`
+    pub magic: u64,
+
+    /// Size of the command line before the footer
+    pub len: u64,
+}
+
+impl BootParamsFooter {
+    pub const MAGIC: u64 = u64::from_le_bytes(*b"MTIPARM\0");
+
+    pub const SIZE: usize = size_of::<Self>();
+
+    /// Get the footer bytes of the command line, to be appended after it
+    pub fn to_bytes(cmdline: &[u8]) -> [u8; Self::SIZE] {
+        let mut bytes = [0u8; Self::SIZE];
+        bytes[0..8].copy_from_slice(&Self::MAGIC.to_le_bytes());
+        bytes[8..16].copy_from_slice(&(cmdline.len() as u64).to_le_bytes());
+        bytes
+    }
+
+    /// Split the ramdisk data (trailer excluded) into the init binary and the command line
+    ///
+    /// Returns None if the data does not end with valid boot parameters
+    pub fn split(data: &[u8]) -> Option<(&[u8], &[u8])> {
+        let offset = data.len().checked_sub(Self::SIZE)?;
+        let bytes = &data[offset..];
+        let read = |index: usize| u64::from_le_bytes(bytes[index..index + 8].try_into().unwrap());
+
+        if read(0) != Self::MAGIC {
+            return None;
+        }
+
+        let cmdline_start = offset.checked_sub(usize::try_from(read(8)).ok()?)?;
+        Some((&data[..cmdline_start], &data[cmdline_start..offset]))
+    }
+}
+
+/// Compute the checksum of the data (64 bits FNV-1a)
+pub fn checksum(data: &[u8]) -> u64 {
+    const OFFSET_BASIS: u64 = 0xcbf29ce484222325;
+    const PRIME: u64 = 0x100000001b3;
+
+    let mut hash = OFFSET_BASIS;
+    for &byte in data {
+        hash ^= byte as u64;
+        hash = hash.wrapping_mul(PRIME);
+    }
+
+    hash
+}
@@ -0,0 +1,58 @@
+/// Type of scheduler trace event
+#[repr(u64)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SchedEventType {
+    /// `tid` starts executing, in place of `other_tid`
+    Switch = 1,
+
+    /// `tid` goes to sleep on a wait queue
+    Sleep,
+
+    /// `tid` is woken up from a wait queue by `other_tid` (0 if woken by the kernel itself)
+    Wake,
+
+    /// `tid` runs next, in place of `other_tid` which blocks after sending it a message (directed yield)
+    DirectedYield,
+}
+
+/// What a wait queue is attached to
+#[repr(u64)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WaitCause {
+    /// Not a wait (eg: context switch)
+    None = 0,
+
+    /// Receive on port `cause_id`
+    Port,
+}
+
+/// Scheduler trace event
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+pub struct SchedEvent {
+    /// Time stamp counter value
+    pub timestamp: u64,
+    pub r#type: SchedEventType,
+    pub tid: u64,
+    pub other_tid: u64,
+    pub cause: WaitCause,
+    pub cause_id: u64,
+}
+
+impl SchedEvent {
+    /// Maximum number of events kept by the kernel: oldest events are dropped first
+    pub const BUFFER_SIZE: usize = 4096;
+}
+
+impl Default for SchedEvent {
+    fn default() -> Self {
+        Self {
+            timestamp: 0,
+            r#type: SchedEventType::Switch,
+            tid: 0,
+            other_tid: 0,
+            cause: WaitCause::None,
+            cause_id: 0,
+        }
+    }
+}
@@ -0,0 +1,215 @@
+use crate::ThreadPriority;
+
+/// Latency histogram of one syscall
+///
+/// Latencies are measured in TSC ticks, from syscall entry to syscall exit (including waits for blocking syscalls)
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+pub struct SyscallLatency {
+    /// SyscallNumber as usize
+    pub number: usize,
+
+    /// Number of completed calls
+    pub count: usize,
+
+    /// Sum of all latencies
+    pub total_ticks: u64,
+
+    /// Log-scale buckets: bucket `i` counts latencies in `[2^i, 2^(i+1))`, the last one counts all above
+    pub buckets: [u64; Self::BUCKET_COUNT],
+}
+
+impl SyscallLatency {
+    pub const BUCKET_COUNT: usize = 40;
+
+    /// Create an empty histogram
+    pub const fn new(number: usize) -> Self {
+        Self {
+            number,
+            count: 0,
+            total_ticks: 0,
+            buckets: [0; Self::BUCKET_COUNT],
+        }
+    }
+
+    /// Get the bucket index for the given latency
+    pub fn bucket_index(ticks: u64) -> usize {
+        let index = (u64::BITS - ticks.leading_zeros()).saturating_sub(1) as usize;
+        index.min(Self::BUCKET_COUNT - 1)
+    }
+
+    /// Add a latency to the histogram
+    pub fn add(&mut self, ticks: u64) {
+        self.count += 1;
+        self.total_ticks += ticks;
+        self.buckets[Self::bucket_index(ticks)] += 1;
+    }
+
+    /// Get the mean latency, in ticks
+    pub fn mean(&self) -> u64 {
+        if self.count == 0 {
+            0
+        } else {
+            self.total_ticks / self.count as u64
+        }
+    }
+}
+
+impl Default for SyscallLatency {
+    fn default() -> Self {
+        Self::new(0)
+    }
+}
+
+/// Time spent by threads in the ready list before being scheduled, for a priority
+///
+/// Latencies are measured in TSC ticks, from the time a thread becomes ready to the time it is picked by the scheduler.
+/// They tell scheduling delay apart from processing time.
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+pub struct ReadyLatency {
+    pub priority: ThreadPriority,
+
+    /// Number of times a thread of this priority has been scheduled
+    pub count: usize,
+
+    /// Sum of all latencies
+    pub total_ticks: u64,
+
+    /// Log-scale buckets, same as `SyscallLatency::buckets`
+    pub buckets: [u64; SyscallLatency::BUCKET_COUNT],
+}
+
+impl ReadyLatency {
+    /// Create an empty histogram
+    pub const fn new(priority: ThreadPriority) -> Self {
+        Self {
+            priority,
+            count: 0,
+            total_ticks: 0,
+            buckets: [0; SyscallLatency::BUCKET_COUNT],
+        }
+    }
+
+    /// Add a latency to the histogram
+    pub fn add(&mut self, ticks: u64) {
+        self.count += 1;
+        self.total_ticks += ticks;
+        self.buckets[SyscallLatency::bucket_index(ticks)] += 1;
+    }
+
+    /// Get the mean latency, in ticks
+    pub fn mean(&self) -> u64 {
+        if self.count == 0 {
+            0
+        } else {
+            self.total_ticks / self.count as u64
+        }
+    }
+
+    /// Get an upper bound of the given percentile (eg: 50, 99), in ticks
+    ///
+    /// This is the upper bound of the bucket which contains it, so it is at most twice the exact value.
+    pub fn percentile(&self, percent: u64) -> u64 {
+        let target = (self.count as u64 * percent).div_ceil(100).max(1);
+
+        let mut seen = 0;
+        for (index, &count) in self.buckets.iter().enumerate() {
+            seen += count;
+            if seen >= target {
+                return (1u64 << (index + 1)) - 1;
+            }
+        }
+
+        0
+    }
+}
+
+impl Default for ReadyLatency {
+    fn default() -> Self {
+        Self::new(ThreadPriority::Normal)
+    }
+}
+
+/// Aggregate snapshot of the system state, for status lines and monitoring tools
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SystemInfo {
+    /// Nanoseconds since boot (tick granularity)
+    pub uptime: u64,
+
+    /// Physical memory, in bytes
+    pub memory_total: usize,
+
+    /// Free physical memory, in bytes
+    pub memory_free: usize,
+
+    /// Number of processes
+    pub processes: usize,
+
+    /// Number of threads
+    pub threads: usize,
+
+    /// Number of threads ready or executing (idle threads excluded)
+    pub runnable: usize,
+
+    /// Average of `runnable` over the last minute, in `LOAD_SCALE` units
+    pub load: u64,
+
+    /// Wall clock at boot, in nanoseconds since the Unix epoch
+    ///
+    /// Wall clock adjustments move it, so that `boot_time + uptime` is always the wall clock.
+    pub boot_time: u64,
+
+    /// Wall clock, in nanoseconds since the Unix epoch (tick granularity)
+    pub wall_clock: u64,
+}
+
+impl SystemInfo {
+    /// Fixed point scale of `load`: a load of `LOAD_SCALE` means 1 runnable thread on average
+    pub const LOAD_SCALE: u64 = 1 << 11;
+}
+
+/// Instruction used by the idle thread to wait for interrupts
+#[repr(u64)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum IdleMethod {
+    /// `hlt`: C1, always available
+    #[default]
+    Hlt = 1,
+
+    /// `monitor`/`mwait` with `IdleStats::mwait_hint`: deeper C-states, if the CPU supports it
+    Mwait,
+}
+
+/// Idle policy and residency counters
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Default)]
+pub struct IdleStats {
+    /// Method chosen by the kernel at boot
+    pub method: IdleMethod,
+
+    /// `eax` hint given to `mwait` (target C-state and sub-state), if the method is `Mwait`
+    pub mwait_hint: u64,
+
+    /// Number of times the CPU went idle (switches to an idle thread)
+    pub entries: u64,
+
+    /// Time spent idle, in TSC ticks
+    pub residency_ticks: u64,
+
+    /// Time since CPU reset (TSC value), in TSC ticks: `residency_ticks / total_ticks` is the idle ratio
+    pub total_ticks: u64,
+}
+
+impl IdleStats {
+    /// Argument given to the idle thread: 0 to use `hlt`, else `MWAIT_ARG | mwait_hint`
+    pub fn thread_arg(&self) -> usize {
+        match self.method {
+            IdleMethod::Hlt => 0,
+            IdleMethod::Mwait => Self::MWAIT_ARG | self.mwait_hint as usize,
+        }
+    }
+
+    pub const MWAIT_ARG: usize = 1 << 32;
+}
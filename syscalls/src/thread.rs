@@ -14,6 +14,16 @@ pub struct ThreadCreationParameters {
     pub tls: usize,
 }
 
+/// Parameters of a trampoline: everything a thread created from it starts with, except its argument
+#[repr(C)]
+#[derive(Debug)]
+pub struct TrampolineParameters {
+    pub priority: ThreadPriority,
+    pub entry_point: usize,
+    pub stack_top: usize,
+    pub tls: usize,
+}
+
 /// Thread priority
 #[repr(u64)]
 #[derive(Debug, PartialEq, Eq, Hash, Clone, Copy, Default)]
@@ -50,6 +60,9 @@ pub enum ThreadState {
 
     /// This thread has been terminated
     Terminated,
+
+    /// This thread is ready, but its process is suspended
+    Suspended,
 }
 
 /// Thread information
@@ -62,6 +75,8 @@ pub struct ThreadInfo {
     pub privileged: bool,
     pub state: ThreadState,
     pub ticks: usize,
+    /// Id of the name in the kernel strings table (0 if no name): a rename gives a new id
+    pub name_id: u64,
 }
 
 impl ThreadInfo {
@@ -88,16 +103,26 @@ impl Debug for ThreadInfo {
             .field("privileged", &self.privileged)
             .field("state", &self.state)
             .field("ticks", &self.ticks)
+            .field("name_id", &self.name_id)
             .finish()
     }
 }
 
+/// Ids of the current thread and of its process, without opening handles
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Default)]
+pub struct CurrentIds {
+    pub pid: u64,
+    pub tid: u64,
+}
+
 #[repr(u64)]
 #[derive(Debug, Clone, Copy)]
 pub enum Exception {
     DivideError = 1,
 
-    Debug,
+    /// Parameter is value of DR6: debug status (bits 0-3 indicate which watchpoint triggered)
+    Debug(usize),
 
     /// Cannot happen in userland
     NonMaskableInterrupt,
@@ -209,8 +234,29 @@ pub enum ThreadContextRegister {
     InstructionPointer,
 
     /// CPU flags
+    ///
+    /// Only the status flags, the direction flag and the trap flag (single-step) can be updated.
     CpuFlags,
 
     // FS base value (used for TLS)
     TLS,
 }
+
+/// Number of hardware watchpoints available per thread
+pub const WATCHPOINT_COUNT: usize = 4;
+
+/// Kind of memory access that triggers a watchpoint
+#[repr(u64)]
+#[derive(Debug, PartialEq, Eq, Hash, Clone, Copy)]
+pub enum WatchpointKind {
+    /// Triggers on data writes
+    Write = 1,
+
+    /// Triggers on data reads or writes
+    ReadWrite,
+
+    /// Triggers before the instruction at the address executes (size must be 1)
+    ///
+    /// The thread stops on the instruction: to resume it, clear the watchpoint and single-step over it first.
+    Execute,
+}
@@ -0,0 +1,52 @@
+/// Period of the kernel tick, in nanoseconds
+///
+/// Timers fire on ticks: deadlines are rounded up to the next tick.
+pub const TICK_NS: u64 = 10_000_000;
+
+/// Longest delay the virtual clock can be advanced by in one call, in nanoseconds (1 hour)
+pub const MAX_ADVANCE_NS: u64 = 3_600_000_000_000;
+
+/// Timer event, sent to the port of the timer when it fires
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+pub struct TimerEvent {
+    /// Requested deadline, in nanoseconds since boot
+    pub deadline: u64,
+
+    /// Time at which the timer fired, in nanoseconds since boot
+    ///
+    /// It is in `[deadline, deadline + slack]`, rounded to ticks.
+    pub fired: u64,
+}
+
+/// Timers statistics
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Default)]
+pub struct TimerStats {
+    /// Number of ticks since boot
+    pub ticks: u64,
+
+    /// Number of ticks on which timers have been fired
+    pub wakeups: u64,
+
+    /// Number of timers fired
+    pub fired: u64,
+
+    /// Number of timers fired before the end of their slack, along with a timer that had to fire
+    pub coalesced: u64,
+
+    /// Number of currently armed timers
+    pub armed: u64,
+
+    /// Number of existing timers (armed or not)
+    pub timers: u64,
+
+    /// Time spent processing ticks, in TSC ticks
+    pub processing_ticks: u64,
+
+    /// Longest processing of a tick, in TSC ticks
+    pub processing_max_ticks: u64,
+
+    /// The kernel runs on a virtual clock (`virtual-clock` kernel feature)
+    pub virtual_clock: bool,
+}
@@ -0,0 +1,46 @@
+//! Invocation of cargo, and location of its outputs
+
+use std::{
+    env,
+    path::{Path, PathBuf},
+    process::Command,
+};
+
+use crate::Result;
+
+/// Userland target directory (servers and libraries, see `servers/.cargo/config.toml`)
+pub const USERLAND_TARGET_DIR: &str = "target/x86_64-mti_fun_os/debug";
+
+/// Run `cargo build` in `dir`, so that its `.cargo/config.toml` (target, build-std) applies
+pub fn build(dir: &Path, args: &[&str]) -> Result<()> {
+    let cargo = env::var_os("CARGO").unwrap_or_else(|| "cargo".into());
+
+    println!("xtask: building {}", dir.display());
+
+    let status = Command::new(cargo)
+        .arg("build")
+        .args(args)
+        .current_dir(dir)
+        .status()?;
+
+    if !status.success() {
+        return Err(format!("build failed in {} ({})", dir.display(), status).into());
+    }
+
+    Ok(())
+}
+
+/// Get the path of a userland binary
+pub fn userland_binary(root: &Path, name: &str) -> PathBuf {
+    root.join(USERLAND_TARGET_DIR).join(name)
+}
+
+/// Get the path of the init binary
+pub fn init_binary(root: &Path) -> PathBuf {
+    root.join("target/x86_64-mti_fun_os-init/debug/init")
+}
+
+/// Get the path of the kernel binary
+pub fn kernel_binary(root: &Path) -> PathBuf {
+    root.join("target/x86_64-unknown-none/debug/kernel")
+}
@@ -0,0 +1,63 @@
+//! Ramdisk and disk images
+
+use std::{fs, path::Path};
+
+use syscalls::{
+    ramdisk::{BootParamsFooter, RamdiskTrailer},
+    sha256,
+};
+
+use crate::{services::hex, Result};
+
+/// Write an output file, and log its digest
+pub fn write(path: &Path, data: &[u8]) -> Result<()> {
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+
+    fs::write(path, data)?;
+    log_digest(path, data);
+
+    Ok(())
+}
+
+/// Pack the ramdisk: the init binary (which embeds the boot servers), the boot parameters read by init,
+/// followed by the integrity trailer checked by the kernel
+pub fn pack_ramdisk(init: &Path, cmdline: &str, output: &Path) -> Result<()> {
+    let mut data =
+        fs::read(init).map_err(|err| format!("could not read {}: {}", init.display(), err))?;
+
+    data.extend_from_slice(cmdline.as_bytes());
+    data.extend_from_slice(&BootParamsFooter::to_bytes(cmdline.as_bytes()));
+
+    let trailer = RamdiskTrailer::new(&data);
+    data.extend_from_slice(&trailer.to_bytes());
+
+    write(output, &data)
+}
+
+/// Produce the UEFI and BIOS disk images
+pub fn disk_images(kernel: &Path, ramdisk: &Path, output: &Path) -> Result<()> {
+    let uefi = output.join("uefi.img");
+    bootloader::UefiBoot::new(kernel)
+        .set_ramdisk(ramdisk)
+        .create_disk_image(&uefi)?;
+    log_digest(&uefi, &fs::read(&uefi)?);
+
+    let bios = output.join("bios.img");
+    bootloader::BiosBoot::new(kernel)
+        .set_ramdisk(ramdisk)
+        .create_disk_image(&bios)?;
+    log_digest(&bios, &fs::read(&bios)?);
+
+    Ok(())
+}
+
+fn log_digest(path: &Path, data: &[u8]) {
+    println!(
+        "xtask: {} ({} bytes, sha256={})",
+        path.display(),
+        data.len(),
+        hex(&sha256::digest(data))
+    );
+}
@@ -0,0 +1,156 @@
+//! Table of the kernel log format strings, used by host-log-decoder to decode the binary log
+//!
+//! The format strings are found by scanning the kernel sources for the log macros (`info!("...", ...)`, ...).
+//! Table format: one line per format string, `<id in hex> <format string>`, with `\\`, `\n`, `\r` and `\t` escaped.
+
+use std::{collections::BTreeMap, fs, path::Path};
+
+use syscalls::log_record::format_id;
+
+use crate::Result;
+
+/// Macros whose first arg is the format string
+const MACROS: [&str; 5] = ["error", "warn", "info", "debug", "trace"];
+
+/// Macro whose second arg is the format string (the first one is the level)
+const LEVEL_MACRO: &str = "log_at";
+
+/// Build the table of the format strings used in the kernel sources
+pub fn table(kernel_src: &Path) -> Result<String> {
+    let mut formats = BTreeMap::new();
+    scan_dir(kernel_src, &mut formats)?;
+
+    let mut table = String::new();
+    for (id, format) in formats {
+        table.push_str(&format!("{:08x} {}\n", id, escape(&format)));
+    }
+
+    Ok(table)
+}
+
+fn scan_dir(dir: &Path, formats: &mut BTreeMap<u32, String>) -> Result<()> {
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+
+        if path.is_dir() {
+            scan_dir(&path, formats)?;
+        } else if path.extension().is_some_and(|ext| ext == "rs") {
+            for format in scan(&fs::read_to_string(&path)?) {
+                let id = format_id(&format);
+
+                match formats.get(&id) {
+                    Some(existing) if *existing != format => {
+                        return Err(format!(
+                            "format id collision ({:08x}): {:?} and {:?}",
+                            id, existing, format
+                        )
+                        .into());
+                    }
+                    _ => {
+                        formats.insert(id, format);
+                    }
+                }
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Find the format strings of the log macros invocations in a source file
+fn scan(source: &str) -> Vec<String> {
+    let bytes = source.as_bytes();
+    let mut formats = Vec::new();
+
+    for (index, _) in source.match_indices("!(") {
+        let start = bytes[..index]
+            .iter()
+            .rposition(|&byte| !(byte.is_ascii_alphanumeric() || byte == b'_'))
+            .map_or(0, |position| position + 1);
+        let name = &source[start..index];
+
+        let args = &source[index + 2..];
+        let format = if MACROS.contains(&name) {
+            parse_literal(args)
+        } else if name == LEVEL_MACRO {
+            skip_arg(args).and_then(parse_literal)
+        } else {
+            None
+        };
+
+        formats.extend(format);
+    }
+
+    formats
+}
+
+/// Skip the first arg of a macro, returns what follows its comma
+fn skip_arg(args: &str) -> Option<&str> {
+    let mut depth = 0;
+
+    for (index, char) in args.char_indices() {
+        match char {
+            '(' | '[' | '{' => depth += 1,
+            ')' | ']' | '}' if depth == 0 => return None,
+            ')' | ']' | '}' => depth -= 1,
+            ',' if depth == 0 => return Some(&args[index + 1..]),
+            _ => {}
+        }
+    }
+
+    None
+}
+
+/// Parse the string literal at the start of `source` (after whitespaces), returns its value
+fn parse_literal(source: &str) -> Option<String> {
+    let source = source.trim_start();
+
+    if let Some(raw) = source.strip_prefix('r') {
+        let hashes = raw.len() - raw.trim_start_matches('#').len();
+        let body = raw[hashes..].strip_prefix('"')?;
+        let end = body.find(&format!("\"{}", "#".repeat(hashes)))?;
+        return Some(body[..end].to_string());
+    }
+
+    let mut chars = source.strip_prefix('"')?.chars().peekable();
+    let mut value = String::new();
+
+    loop {
+        match chars.next()? {
+            '"' => return Some(value),
+            '\\' => match chars.next()? {
+                'n' => value.push('\n'),
+                'r' => value.push('\r'),
+                't' => value.push('\t'),
+                '0' => value.push('\0'),
+                '\\' => value.push('\\'),
+                '"' => value.push('"'),
+                '\'' => value.push('\''),
+                'x' => {
+                    let digits: String = [chars.next()?, chars.next()?].iter().collect();
+                    value.push(u8::from_str_radix(&digits, 16).ok()? as char);
+                }
+                'u' => {
+                    let digits: String = chars
+                        .by_ref()
+                        .skip(1)
+                        .take_while(|&char| char != '}')
+                        .collect();
+                    value.push(char::from_u32(u32::from_str_radix(&digits, 16).ok()?)?);
+                }
+                // Line continuation: the newline and the leading whitespaces of the next line are skipped
+                '\n' => while chars.next_if(|char| char.is_whitespace()).is_some() {},
+                _ => return None,
+            },
+            char => value.push(char),
+        }
+    }
+}
+
+fn escape(format: &str) -> String {
+    format
+        .replace('\\', "\\\\")
+        .replace('\n', "\\n")
+        .replace('\r', "\\r")
+        .replace('\t', "\\t")
+}
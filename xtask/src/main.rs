@@ -0,0 +1,130 @@
+//! System image builder
+//!
+//! Usage: `cargo xtask <command>` from anywhere in the repository
+//!
+//! Commands:
+//! - `build`: compile the userland binaries (servers, then init once they are signed) and the kernel,
+//!   then generate the table of the kernel log format strings
+//! - `sign`: sign the built services binaries (see `signing`), init embeds the signatures
+//! - `manifest`: generate the services manifest from the built binaries
+//! - `log-formats`: generate the table of the kernel log format strings (see `log_formats`)
+//! - `image [key=value...]`: all of the above, then pack the ramdisk and produce the bootable disk images
+//!
+//! The `key=value` arguments of `image` are the boot parameters, read by init at boot (eg: `profile=test`).
+//!
+//! Outputs go to `target/image`. Each step logs the SHA-256 of what it produces,
+//! so that two builds of the same sources can be compared.
+
+mod cargo;
+mod image;
+mod log_formats;
+mod services;
+mod signing;
+
+use std::{
+    env,
+    error::Error,
+    path::{Path, PathBuf},
+    process::ExitCode,
+};
+
+type Result<T> = std::result::Result<T, Box<dyn Error>>;
+
+fn main() -> ExitCode {
+    let command = env::args().nth(1);
+
+    let result = match command.as_deref() {
+        Some("build") => build(),
+        Some("sign") => sign(),
+        Some("manifest") => manifest().map(|_| ()),
+        Some("log-formats") => log_formats().map(|_| ()),
+        Some("image") => image(&env::args().skip(2).collect::<Vec<_>>().join(" ")),
+        _ => {
+            eprintln!("Usage: cargo xtask <build|sign|manifest|log-formats|image [key=value...]>");
+            return ExitCode::FAILURE;
+        }
+    };
+
+    match result {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(err) => {
+            eprintln!("xtask: {}", err);
+            ExitCode::FAILURE
+        }
+    }
+}
+
+/// Compile everything which goes into the image
+fn build() -> Result<()> {
+    let root = root_dir();
+
+    // Init embeds some of the servers (and libruntime, built as their dependency) with their signatures:
+    // they must be built and signed first
+    for service in services::SERVICES {
+        cargo::build(&root.join(service.crate_dir), &[])?;
+    }
+    sign()?;
+    cargo::build(&root.join("init"), &[])?;
+
+    cargo::build(
+        &root,
+        &["--package", "kernel", "--target", "x86_64-unknown-none"],
+    )?;
+    log_formats()?;
+
+    Ok(())
+}
+
+/// Sign the built services binaries
+fn sign() -> Result<()> {
+    signing::sign(&root_dir(), &output_dir())
+}
+
+/// Generate the services manifest
+fn manifest() -> Result<PathBuf> {
+    let root = root_dir();
+    let manifest = services::manifest(&root)?;
+
+    let path = output_dir().join("services.manifest");
+    image::write(&path, manifest.as_bytes())?;
+
+    Ok(path)
+}
+
+/// Generate the table of the kernel log format strings, read by host-log-decoder
+fn log_formats() -> Result<PathBuf> {
+    let table = log_formats::table(&root_dir().join("kernel/src"))?;
+
+    let path = output_dir().join("log-formats");
+    image::write(&path, table.as_bytes())?;
+
+    Ok(path)
+}
+
+/// Build the bootable disk images, with the given boot parameters
+fn image(cmdline: &str) -> Result<()> {
+    build()?;
+    manifest()?;
+
+    let root = root_dir();
+    let output = output_dir();
+
+    let ramdisk = output.join("ramdisk");
+    image::pack_ramdisk(&cargo::init_binary(&root), cmdline, &ramdisk)?;
+
+    image::disk_images(&cargo::kernel_binary(&root), &ramdisk, &output)?;
+
+    Ok(())
+}
+
+/// Get the root of the repository
+fn root_dir() -> PathBuf {
+    Path::new(env!("CARGO_MANIFEST_DIR"))
+        .parent()
+        .expect("xtask must be in the repository")
+        .to_path_buf()
+}
+
+fn output_dir() -> PathBuf {
+    root_dir().join("target/image")
+}
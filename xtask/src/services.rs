@@ -0,0 +1,145 @@
+//! Services of the system, and their manifest
+//!
+//! The manifest lists one service per line, in boot order:
+//!
+//! ```text
+//! # comment
+//! process-server binary=process-server start=boot size=123456 sha256=0123...
+//! ```
+//!
+//! - `start=boot`: started by init at boot
+//! - `start=manual`: built and shipped in the image, started on demand (eg: by lazy activation, or for debugging)
+
+use std::{fmt::Write, fs, path::Path};
+
+use syscalls::sha256;
+
+use crate::{cargo, Result};
+
+/// When a service is started
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Start {
+    Boot,
+    Manual,
+}
+
+impl Start {
+    fn name(&self) -> &'static str {
+        match self {
+            Start::Boot => "boot",
+            Start::Manual => "manual",
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct Service {
+    pub name: &'static str,
+    /// Crate directory, from the repository root
+    pub crate_dir: &'static str,
+    pub start: Start,
+}
+
+/// Services of the image, in boot order
+pub const SERVICES: &[Service] = &[
+    Service {
+        name: "process-server",
+        crate_dir: "servers/process-server",
+        start: Start::Boot,
+    },
+    Service {
+        name: "log-server",
+        crate_dir: "servers/log-server",
+        start: Start::Boot,
+    },
+    Service {
+        name: "memfs-server",
+        crate_dir: "servers/memfs-server",
+        start: Start::Boot,
+    },
+    Service {
+        name: "vfs-server",
+        crate_dir: "servers/vfs-server",
+        start: Start::Boot,
+    },
+    Service {
+        name: "event-bus",
+        crate_dir: "servers/event-bus",
+        start: Start::Manual,
+    },
+    Service {
+        name: "clipboard",
+        crate_dir: "servers/clipboard",
+        start: Start::Manual,
+    },
+    Service {
+        name: "display-server",
+        crate_dir: "servers/display-server",
+        start: Start::Manual,
+    },
+    Service {
+        name: "trace-proxy",
+        crate_dir: "servers/trace-proxy",
+        start: Start::Manual,
+    },
+    Service {
+        name: "terminal",
+        crate_dir: "servers/terminal",
+        start: Start::Manual,
+    },
+    Service {
+        name: "c-smoke",
+        crate_dir: "servers/c-smoke",
+        start: Start::Manual,
+    },
+    Service {
+        name: "ahci-server",
+        crate_dir: "servers/ahci-server",
+        start: Start::Manual,
+    },
+    Service {
+        name: "coredumpctl",
+        crate_dir: "servers/coredumpctl",
+        start: Start::Manual,
+    },
+    Service {
+        name: "gdbstub-server",
+        crate_dir: "servers/gdbstub-server",
+        start: Start::Manual,
+    },
+    Service {
+        name: "checkpoint-server",
+        crate_dir: "servers/checkpoint-server",
+        start: Start::Manual,
+    },
+];
+
+/// Generate the manifest of the built services
+pub fn manifest(root: &Path) -> Result<String> {
+    let mut manifest = String::from("# services manifest, generated by `cargo xtask manifest`\n");
+
+    for service in SERVICES {
+        let path = cargo::userland_binary(root, service.name);
+        let binary =
+            fs::read(&path).map_err(|err| format!("could not read {}: {}", path.display(), err))?;
+
+        writeln!(
+            manifest,
+            "{} binary={} start={} size={} sha256={}",
+            service.name,
+            service.name,
+            service.start.name(),
+            binary.len(),
+            hex(&sha256::digest(&binary))
+        )?;
+    }
+
+    Ok(manifest)
+}
+
+pub fn hex(bytes: &[u8]) -> String {
+    bytes.iter().fold(String::new(), |mut out, byte| {
+        let _ = write!(out, "{:02x}", byte);
+        out
+    })
+}
@@ -0,0 +1,82 @@
+//! Binary signatures
+//!
+//! Init embeds the public key, and checks the detached ed25519 signature of the binaries it loads
+//! (see `init/src/signature.rs`).
+//!
+//! The key pair is read from the file given by the `SIGNING_KEY` env variable (64 bytes: secret then public key).
+//! Without it, a development key pair is generated once in `target/image/keys`.
+//! Signatures are deterministic: with the same key, two builds of the same sources give the same image.
+
+use std::{
+    env, fs,
+    path::{Path, PathBuf},
+};
+
+use ed25519_compact::{KeyPair, Seed};
+
+use crate::{cargo, image, services::SERVICES, Result};
+
+/// Sign the services binaries, and write the public key embedded by init
+pub fn sign(root: &Path, output: &Path) -> Result<()> {
+    let key_pair = key_pair(output)?;
+    image::write(&public_key_path(output), key_pair.pk.as_ref())?;
+
+    for service in SERVICES {
+        let path = cargo::userland_binary(root, service.name);
+        let binary =
+            fs::read(&path).map_err(|err| format!("could not read {}: {}", path.display(), err))?;
+
+        let signature = key_pair.sk.sign(&binary, None);
+        image::write(&signature_path(output, service.name), signature.as_ref())?;
+    }
+
+    Ok(())
+}
+
+/// Get the path of the public key
+pub fn public_key_path(output: &Path) -> PathBuf {
+    output.join("keys/signing.pub")
+}
+
+/// Get the path of the detached signature of a service binary
+pub fn signature_path(output: &Path, name: &str) -> PathBuf {
+    output.join("signatures").join(format!("{}.sig", name))
+}
+
+/// Load the key pair given by `SIGNING_KEY`, or the development one (generated if missing)
+fn key_pair(output: &Path) -> Result<KeyPair> {
+    let path = match env::var_os("SIGNING_KEY") {
+        Some(path) => PathBuf::from(path),
+        None => {
+            let path = output.join("keys/signing.key");
+            if !path.exists() {
+                generate(&path)?;
+            }
+            path
+        }
+    };
+
+    let data =
+        fs::read(&path).map_err(|err| format!("could not read {}: {}", path.display(), err))?;
+
+    let key_pair = KeyPair::from_slice(&data)
+        .map_err(|err| format!("invalid key pair in {}: {}", path.display(), err))?;
+
+    Ok(key_pair)
+}
+
+fn generate(path: &Path) -> Result<()> {
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+
+    let mut seed = [0u8; Seed::BYTES];
+    getrandom::getrandom(&mut seed)?;
+    let key_pair = KeyPair::from_seed(Seed::new(seed));
+
+    // Not logged with its digest like the other outputs: it is a secret
+    fs::write(path, key_pair.as_ref())?;
+    println!("xtask: generated development signing key {}", path.display());
+
+    Ok(())
+}